@@ -144,9 +144,10 @@ func (s *Sender) splitIntoBatches(data []interface{}) [][]interface{} {
 // sendBatch sends a single batch of data
 func (s *Sender) sendBatch(ctx context.Context, batch []interface{}) error {
 	// Prepare payload
+	batchID := generateBatchID()
 	payload := map[string]interface{}{
 		"timestamp": time.Now().Unix(),
-		"batch_id":  generateBatchID(),
+		"batch_id":  batchID,
 		"data":      batch,
 	}
 
@@ -164,6 +165,8 @@ func (s *Sender) sendBatch(ctx context.Context, batch []interface{}) error {
 
 	req.Header.Set("Content-Type", "application/json")
 	req.Header.Set("User-Agent", "StreamForge-Collector/0.1.0")
+	// Retries carry the same key so the gateway ingests the batch once
+	req.Header.Set("Idempotency-Key", batchID)
 
 	// Send with retries
 	var lastErr error
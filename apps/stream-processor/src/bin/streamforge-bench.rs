@@ -0,0 +1,526 @@
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use futures::StreamExt;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::signal;
+use tokio::sync::Semaphore;
+use tokio_tungstenite::tungstenite::Message;
+
+use streamforge_core::config::{self as app_config, Config};
+use streamforge_core::dlq;
+
+/// Label carrying the run a generated metric belongs to, so a run only
+/// measures its own records when several share a processor.
+const RUN_LABEL: &str = "bench_run";
+
+/// Label carrying when a generated metric was sent, in microseconds since
+/// the epoch.
+const SENT_AT_LABEL: &str = "bench_sent_at";
+
+/// How often the generator catches up with the target rate.
+const TICK: Duration = Duration::from_millis(10);
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Generates synthetic metrics, logs and traces at a fixed rate and reports
+/// throughput and latency percentiles.
+///
+/// End-to-end latency is measured on metrics only: the run subscribes to the
+/// processor's WebSocket hub and times each of its metrics from send to
+/// publication. Logs and traces are stored but never published, so they only
+/// count towards throughput and produce latency.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Configuration file of the processor under test; supplies the Kafka
+    /// connection, input topic, tenant header and hub address
+    #[arg(short, long, default_value = "config/config.toml")]
+    config: String,
+
+    #[command(flatten)]
+    profile: app_config::ProfileArgs,
+
+    /// Where records are sent
+    #[arg(long, value_enum, default_value = "kafka")]
+    target: Target,
+
+    /// Topic to produce to (defaults to the first input topic)
+    #[arg(long)]
+    topic: Option<String>,
+
+    /// Base URL of the HTTP ingest API
+    #[arg(long, default_value = "http://localhost:8080")]
+    http_endpoint: String,
+
+    /// Bearer token for the HTTP ingest API
+    #[arg(long)]
+    http_token: Option<String>,
+
+    /// Records per HTTP request
+    #[arg(long, default_value_t = 100)]
+    batch_size: usize,
+
+    /// Tenant the records are sent as (defaults to the processor's default tenant)
+    #[arg(long)]
+    tenant: Option<String>,
+
+    /// Records per second, across all kinds
+    #[arg(short, long, default_value_t = 1000)]
+    rate: u64,
+
+    /// How long to generate load for, in seconds
+    #[arg(short, long, default_value_t = 60)]
+    duration: u64,
+
+    /// Distinct series (metric names and services) the records are spread over
+    #[arg(long, default_value_t = 100)]
+    cardinality: u64,
+
+    /// Approximate size of each encoded record in bytes
+    #[arg(long, default_value_t = 256)]
+    payload_size: usize,
+
+    /// Kinds of record to generate, in rotation
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "metrics,logs,traces")]
+    kinds: Vec<Kind>,
+
+    /// Sends not yet acknowledged before new ones wait
+    #[arg(long, default_value_t = 10_000)]
+    max_in_flight: usize,
+
+    /// WebSocket hub URL to measure end-to-end latency through (defaults to
+    /// the configured hub when it is enabled)
+    #[arg(long)]
+    hub_url: Option<String>,
+
+    /// Seconds to wait after sending for the last metrics to be published
+    #[arg(long, default_value_t = 10)]
+    drain: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Target {
+    Kafka,
+    Http,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Kind {
+    Metrics,
+    Logs,
+    Traces,
+}
+
+/// Counters and latency samples shared by the senders and the hub listener.
+#[derive(Default)]
+struct Stats {
+    sent: AtomicU64,
+    acked: AtomicU64,
+    failed: AtomicU64,
+    bytes: AtomicU64,
+    metrics_sent: AtomicU64,
+    /// Microseconds from send to acknowledgement by Kafka or the ingest API.
+    produce_latency: Mutex<Vec<u64>>,
+    /// Microseconds from send to publication by the hub.
+    end_to_end_latency: Mutex<Vec<u64>>,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Stats {
+    fn acked(&self, records: u64, bytes: u64, sent: Instant) {
+        self.acked.fetch_add(records, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.produce_latency.lock().unwrap().push(sent.elapsed().as_micros() as u64);
+    }
+
+    fn failed(&self, records: u64, error: anyhow::Error) {
+        self.failed.fetch_add(records, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some(error.to_string());
+    }
+}
+
+/// Sends encoded records to the target.
+enum Sink {
+    Kafka {
+        producer: FutureProducer,
+        topic: String,
+        tenant_header: String,
+        tenant: String,
+    },
+    Http {
+        client: reqwest::Client,
+        url: String,
+        token: Option<String>,
+        tenant: String,
+    },
+}
+
+impl Sink {
+    async fn send(&self, records: Vec<(String, Value)>) -> Result<()> {
+        match self {
+            Sink::Kafka {
+                producer,
+                topic,
+                tenant_header,
+                tenant,
+            } => {
+                // Batches from the generator hold a single record for Kafka
+                for (key, record) in records {
+                    let payload = serde_json::to_vec(&record)?;
+                    let headers = OwnedHeaders::new().insert(Header {
+                        key: tenant_header.as_str(),
+                        value: Some(tenant.as_bytes()),
+                    });
+                    let record = FutureRecord::to(topic).key(&key).payload(&payload).headers(headers);
+                    producer
+                        .send(record, SEND_TIMEOUT)
+                        .await
+                        .map_err(|(e, _)| anyhow::anyhow!("Failed to produce to {}: {}", topic, e))?;
+                }
+                Ok(())
+            }
+            Sink::Http {
+                client,
+                url,
+                token,
+                tenant,
+            } => {
+                // Same envelope and idempotency key the collector posts
+                let batch_id = uuid::Uuid::new_v4().to_string();
+                let body = json!({
+                    "timestamp": unix_micros() / 1_000_000,
+                    "batch_id": batch_id,
+                    "tenant_id": tenant,
+                    "data": records.into_iter().map(|(_, record)| record).collect::<Vec<_>>(),
+                });
+                let mut request = client.post(url).header("Idempotency-Key", &batch_id).json(&body);
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                let response = request
+                    .send()
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to post to {}: {}", url, e))?;
+                if !response.status().is_success() {
+                    anyhow::bail!("Ingest API responded with {}", response.status());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Builds the `seq`th record of a run.
+struct Generator {
+    run_id: String,
+    kinds: Vec<Kind>,
+    cardinality: u64,
+    payload_size: usize,
+}
+
+impl Generator {
+    fn record(&self, seq: u64) -> (Kind, String, Value) {
+        let kind = self.kinds[(seq % self.kinds.len() as u64) as usize];
+        let series = seq % self.cardinality;
+        let service = format!("bench-service-{}", series % 16);
+        let now = unix_micros();
+        let mut rng = rand::thread_rng();
+
+        let mut record = match kind {
+            Kind::Metrics => json!({
+                "name": format!("streamforge_bench_{}", series),
+                "value": rng.gen_range(0.0..100.0),
+                "unit": "count",
+                "labels": {
+                    "service": service,
+                    RUN_LABEL: self.run_id,
+                    SENT_AT_LABEL: now.to_string(),
+                },
+                "timestamp": now / 1000,
+            }),
+            Kind::Logs => json!({
+                "level": ["debug", "info", "warn", "error"][rng.gen_range(0..4)],
+                "message": format!("synthetic log line {}", seq),
+                "fields": {
+                    "service": service,
+                    "series": series,
+                    RUN_LABEL: self.run_id,
+                },
+                "timestamp": now / 1000,
+            }),
+            Kind::Traces => json!({
+                "trace_id": format!("{:032x}", rng.gen::<u128>()),
+                "span_id": format!("{:016x}", rng.gen::<u64>()),
+                "service": service,
+                "operation": format!("bench-operation-{}", series),
+                "start_time": now / 1000,
+                "duration_ms": rng.gen_range(1..500),
+                "attributes": {
+                    RUN_LABEL: self.run_id,
+                },
+            }),
+        };
+
+        // Pad up to the requested size; records already larger are sent as is
+        let size = record.to_string().len() + r#","padding":"""#.len();
+        if let Some(padding) = self.payload_size.checked_sub(size).filter(|n| *n > 0) {
+            let padding: String = rng.sample_iter(&Alphanumeric).take(padding).map(char::from).collect();
+            record["padding"] = Value::String(padding);
+        }
+
+        (kind, format!("{}-{}", self.run_id, series), record)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.rate == 0 || args.cardinality == 0 || args.batch_size == 0 || args.kinds.is_empty() {
+        anyhow::bail!("--rate, --cardinality, --batch-size and --kinds must be non-zero");
+    }
+    if args.batch_size > args.max_in_flight {
+        anyhow::bail!("--batch-size must not exceed --max-in-flight");
+    }
+
+    let profile = args.profile.active();
+    let config = Config::load(&args.config, profile.as_deref())?;
+    let tenant = args
+        .tenant
+        .clone()
+        .unwrap_or_else(|| config.processing.default_tenant_id.clone());
+
+    let sink = Arc::new(match args.target {
+        Target::Kafka => Sink::Kafka {
+            producer: dlq::producer(&config)?,
+            topic: match &args.topic {
+                Some(topic) => topic.clone(),
+                None => config
+                    .kafka
+                    .topics
+                    .first()
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("No input topic is configured; pass --topic"))?,
+            },
+            tenant_header: config.processing.tenant_header.clone(),
+            tenant,
+        },
+        Target::Http => Sink::Http {
+            client: reqwest::Client::builder()
+                .timeout(SEND_TIMEOUT)
+                .build()
+                .map_err(|e| anyhow::anyhow!("Failed to create HTTP client: {}", e))?,
+            url: format!("{}/api/v1/metrics", args.http_endpoint.trim_end_matches('/')),
+            token: args.http_token.clone(),
+            tenant,
+        },
+    });
+
+    let generator = Generator {
+        run_id: uuid::Uuid::new_v4().simple().to_string()[..12].to_string(),
+        kinds: args.kinds.clone(),
+        cardinality: args.cardinality,
+        payload_size: args.payload_size,
+    };
+    let stats = Arc::new(Stats::default());
+
+    // Time metrics through the hub when there is one to listen to
+    let hub_url = match &args.hub_url {
+        Some(url) => Some(url.clone()),
+        None if config.hub.enabled => {
+            let mut url = format!("ws://{}/?types=metrics", config.hub.address()?);
+            if let Some(api_key) = &config.hub.api_key {
+                url.push_str(&format!("&api_key={}", api_key));
+            }
+            Some(url)
+        }
+        None => None,
+    };
+    let listener = match &hub_url {
+        Some(url) if args.kinds.contains(&Kind::Metrics) => {
+            let (socket, _) = tokio_tungstenite::connect_async(url.as_str())
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to connect to hub {}: {}", url, e))?;
+            Some(tokio::spawn(listen(socket, generator.run_id.clone(), stats.clone())))
+        }
+        _ => None,
+    };
+
+    println!(
+        "Run {}: {} records/s of {:?} to {:?} for {}s",
+        generator.run_id, args.rate, args.kinds, args.target, args.duration
+    );
+
+    let in_flight = Arc::new(Semaphore::new(args.max_in_flight));
+    let batch_size = match args.target {
+        Target::Kafka => 1,
+        Target::Http => args.batch_size,
+    };
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(args.duration);
+    let mut ticks = tokio::time::interval(TICK);
+    let mut seq = 0u64;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut batch_metrics = 0u64;
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = signal::ctrl_c() => {
+                println!("Interrupted; stopping early");
+                break;
+            }
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+
+        // Catch up with the records due so far, so a slow tick does not lower the rate
+        let due = (now.duration_since(started).as_secs_f64() * args.rate as f64) as u64;
+        while seq < due {
+            let (kind, key, record) = generator.record(seq);
+            seq += 1;
+            if kind == Kind::Metrics {
+                batch_metrics += 1;
+            }
+            batch.push((key, record));
+            if batch.len() < batch_size && seq < due {
+                continue;
+            }
+
+            let records = std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+            let count = records.len() as u64;
+            let bytes: u64 = records.iter().map(|(_, record)| record.to_string().len() as u64).sum();
+            stats.sent.fetch_add(count, Ordering::Relaxed);
+            stats.metrics_sent.fetch_add(std::mem::take(&mut batch_metrics), Ordering::Relaxed);
+
+            let permit = in_flight.clone().acquire_many_owned(count as u32).await?;
+            let sink = sink.clone();
+            let stats = stats.clone();
+            tokio::spawn(async move {
+                let sent = Instant::now();
+                match sink.send(records).await {
+                    Ok(()) => stats.acked(count, bytes, sent),
+                    Err(e) => stats.failed(count, e),
+                }
+                drop(permit);
+            });
+        }
+    }
+    let sending = started.elapsed();
+
+    // Wait for outstanding sends, then for the hub to publish the last metrics
+    let _ = in_flight.acquire_many(args.max_in_flight as u32).await?;
+    let elapsed = started.elapsed();
+    if let Some(listener) = listener {
+        let drain = Instant::now() + Duration::from_secs(args.drain);
+        while Instant::now() < drain
+            && (stats.end_to_end_latency.lock().unwrap().len() as u64) < stats.metrics_sent.load(Ordering::Relaxed)
+        {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        listener.abort();
+    }
+
+    report(&stats, sending, elapsed, hub_url.is_some());
+    Ok(())
+}
+
+/// Records the end-to-end latency of every metric of the run the hub
+/// publishes.
+async fn listen<S>(mut socket: S, run_id: String, stats: Arc<Stats>)
+where
+    S: futures::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    while let Some(Ok(message)) = socket.next().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+        let Ok(message) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+        let received = unix_micros();
+        let metrics = message["metrics"].as_array().into_iter().flatten();
+        let mut latencies = stats.end_to_end_latency.lock().unwrap();
+        for metric in metrics {
+            let labels = &metric["labels"];
+            if labels[RUN_LABEL].as_str() != Some(run_id.as_str()) {
+                continue;
+            }
+            if let Some(sent) = labels[SENT_AT_LABEL].as_str().and_then(|sent| sent.parse::<u64>().ok()) {
+                latencies.push(received.saturating_sub(sent));
+            }
+        }
+    }
+}
+
+fn report(stats: &Stats, sending: Duration, elapsed: Duration, measured_end_to_end: bool) {
+    let sent = stats.sent.load(Ordering::Relaxed);
+    let acked = stats.acked.load(Ordering::Relaxed);
+    let failed = stats.failed.load(Ordering::Relaxed);
+    let bytes = stats.bytes.load(Ordering::Relaxed);
+    let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+
+    println!();
+    println!(
+        "Sent: {} records in {:.1}s ({:.0} records/s offered)",
+        sent,
+        sending.as_secs_f64(),
+        sent as f64 / sending.as_secs_f64().max(f64::EPSILON)
+    );
+    println!(
+        "Acknowledged: {} records ({:.0} records/s, {:.2} MiB/s)",
+        acked,
+        acked as f64 / seconds,
+        bytes as f64 / seconds / (1024.0 * 1024.0)
+    );
+    match stats.last_error.lock().unwrap().as_deref() {
+        Some(error) => println!("Failed: {} records (last error: {})", failed, error),
+        None => println!("Failed: {} records", failed),
+    }
+    print_latency("Produce latency", &mut stats.produce_latency.lock().unwrap());
+
+    if measured_end_to_end {
+        let mut latencies = stats.end_to_end_latency.lock().unwrap();
+        let metrics_sent = stats.metrics_sent.load(Ordering::Relaxed);
+        println!("Published: {} of {} metrics", latencies.len(), metrics_sent);
+        print_latency("End-to-end latency", &mut latencies);
+    }
+}
+
+fn print_latency(name: &str, samples: &mut [u64]) {
+    if samples.is_empty() {
+        println!("{}: no samples", name);
+        return;
+    }
+    samples.sort_unstable();
+    let ms = |micros: u64| micros as f64 / 1000.0;
+    println!(
+        "{}: p50 {:.2}ms, p90 {:.2}ms, p99 {:.2}ms, max {:.2}ms",
+        name,
+        ms(percentile(samples, 50.0)),
+        ms(percentile(samples, 90.0)),
+        ms(percentile(samples, 99.0)),
+        ms(samples[samples.len() - 1]),
+    );
+}
+
+/// Nearest-rank percentile of sorted, non-empty samples.
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn unix_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
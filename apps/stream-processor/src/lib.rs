@@ -1,9 +0,0 @@
-pub mod config;
-pub mod error;
-pub mod kafka;
-pub mod metrics;
-pub mod processor;
-pub mod telemetry;
-pub mod types;
-
-pub use error::{Error, Result}; 
\ No newline at end of file
@@ -1,13 +1,17 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::sync::Arc;
 use tokio::signal;
 use tracing::{error, info, warn};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use stream_processor::config::Config;
-use stream_processor::processor::StreamProcessor;
-use stream_processor::telemetry;
+use streamforge_core::audit::{self, Auditor};
+use streamforge_core::config::{self as app_config, Config};
+use streamforge_core::hub;
+use streamforge_core::metrics::{self, Metrics};
+use streamforge_core::processor::StreamProcessor;
+use streamforge_core::promql;
+use streamforge_core::storage::AuditEntry;
+use streamforge_core::telemetry;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -16,9 +20,23 @@ struct Args {
     #[arg(short, long, default_value = "config/config.toml")]
     config: String,
     
+    #[command(flatten)]
+    profile: app_config::ProfileArgs,
+    
     /// Log level
     #[arg(short, long, default_value = "info")]
     log_level: String,
+    
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Validate the configuration and exit
+    Check,
+    /// Print the effective configuration with secrets redacted
+    Print,
 }
 
 #[tokio::main]
@@ -26,21 +44,117 @@ async fn main() -> Result<()> {
     // Parse command line arguments
     let args = Args::parse();
     
+    // Load configuration
+    let profile = args.profile.active();
+    let config = Config::load(&args.config, profile.as_deref())?;
+    
+    match args.command {
+        Some(Command::Check) => {
+            println!("Configuration is valid");
+            return Ok(());
+        }
+        Some(Command::Print) => {
+            println!("{}", serde_json::to_string_pretty(&config.redacted()?)?);
+            return Ok(());
+        }
+        None => {}
+    }
+    
     // Initialize logging and tracing
-    telemetry::init(&args.log_level)?;
+    let telemetry_guard = telemetry::init(&config.telemetry, &args.log_level)?;
     
     info!("Starting StreamForge Stream Processor");
     info!("Version: {}", env!("CARGO_PKG_VERSION"));
-    info!("Configuration file: {}", args.config);
-    
-    // Load configuration
-    let config = Config::load(&args.config)?;
-    info!("Configuration loaded successfully");
+    match &profile {
+        Some(profile) => info!("Configuration loaded from {} with profile {}", args.config, profile),
+        None => info!("Configuration loaded from {}", args.config),
+    }
     
     // Create stream processor
-    let processor = Arc::new(StreamProcessor::new(config).await?);
+    let metrics = Arc::new(Metrics::new(&config.metrics)?);
+    let processor = Arc::new(StreamProcessor::new(config.clone(), metrics.clone()).await?);
     info!("Stream processor initialized");
     
+    // Start the metrics and health HTTP server
+    let (server_shutdown_tx, server_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let metrics_server_handle = if config.metrics.enabled {
+        let shutdown = async move {
+            let _ = server_shutdown_rx.await;
+        };
+        Some(tokio::spawn(metrics::start_server(
+            config.metrics.clone(),
+            metrics.clone(),
+            processor.health(),
+            processor.topologies(),
+            processor.poison(),
+            shutdown,
+        )))
+    } else {
+        None
+    };
+    
+    // Stream processed data to WebSocket clients
+    let (hub_shutdown_tx, hub_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let hub_server_handle = if config.hub.enabled {
+        let shutdown = async move {
+            let _ = hub_shutdown_rx.await;
+        };
+        Some(tokio::spawn(hub::start_server(config.hub.clone(), processor.hub(), shutdown)))
+    } else {
+        None
+    };
+    
+    // Answer PromQL queries from Prometheus datasources such as Grafana's
+    let (promql_shutdown_tx, promql_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let promql_server_handle = if config.promql.enabled {
+        let shutdown = async move {
+            let _ = promql_shutdown_rx.await;
+        };
+        Some(tokio::spawn(promql::start_server(config.promql.clone(), processor.storage(), shutdown)))
+    } else {
+        None
+    };
+    
+    // Export metrics to an OpenTelemetry collector
+    let meter_provider = match &config.metrics.otlp {
+        Some(otlp) => Some(metrics::init_otlp(otlp, &config.telemetry, metrics.clone())?),
+        None => None,
+    };
+    
+    // Push metrics for instances that cannot be scraped
+    let metrics_push_handle = if config.metrics.push.is_enabled() {
+        Some(tokio::spawn(metrics::run_push(metrics.clone(), config.metrics.push.clone())))
+    } else {
+        None
+    };
+    
+    // Apply safe configuration changes without a restart, recording each in the audit log
+    let config_watch_handle = {
+        let processor = Arc::clone(&processor);
+        let log_level = telemetry_guard.log_level();
+        let auditor = Arc::new(Auditor::new(processor.storage(), metrics.clone()));
+        let config_path = args.config.clone();
+        tokio::spawn(app_config::watch(args.config.clone(), profile.clone(), config.clone(), move |reloaded, changes| {
+            let entry = AuditEntry::new(audit::SYSTEM_ACTOR, audit::SYSTEM_ACTOR, "ConfigReload", config_path.clone())
+                .details(serde_json::json!({
+                    "applied": changes.applied,
+                    "restart_required": changes.restart_required,
+                }));
+            let auditor = Arc::clone(&auditor);
+            tokio::spawn(async move { auditor.record(entry).await });
+
+            if changes.applied.iter().any(|field| field.starts_with("processing.")) {
+                processor.update_processing(reloaded.processing.clone());
+            }
+            if changes.applied.iter().any(|field| field == "telemetry.log_level") {
+                let level = reloaded.telemetry.log_level.as_deref().unwrap_or(&args.log_level);
+                if let Err(e) = log_level.set(level) {
+                    warn!("{}", e);
+                }
+            }
+        }))
+    };
+    
     // Start the processor
     let processor_handle = {
         let processor = Arc::clone(&processor);
@@ -73,6 +187,49 @@ async fn main() -> Result<()> {
         warn!("Processor task error during shutdown: {}", e);
     }
     
+    config_watch_handle.abort();
+    
+    // Close WebSocket connections once the processor has stopped publishing
+    let _ = hub_shutdown_tx.send(());
+    if let Some(handle) = hub_server_handle {
+        match handle.await {
+            Ok(Err(e)) => warn!("WebSocket hub error during shutdown: {}", e),
+            Err(e) => warn!("WebSocket hub task error during shutdown: {}", e),
+            Ok(Ok(())) => {}
+        }
+    }
+    let _ = promql_shutdown_tx.send(());
+    if let Some(handle) = promql_server_handle {
+        match handle.await {
+            Ok(Err(e)) => warn!("PromQL API error during shutdown: {}", e),
+            Err(e) => warn!("PromQL API task error during shutdown: {}", e),
+            Ok(Ok(())) => {}
+        }
+    }
+    if let Some(handle) = metrics_push_handle {
+        handle.abort();
+    }
+    
+    // Flush the final OTLP export
+    if let Some(provider) = meter_provider {
+        if let Err(e) = provider.shutdown() {
+            warn!("Failed to shut down OTLP metrics exporter: {}", e);
+        }
+    }
+    
+    // Stop the metrics server once in-flight scrapes have completed
+    let _ = server_shutdown_tx.send(());
+    if let Some(handle) = metrics_server_handle {
+        match handle.await {
+            Ok(Err(e)) => warn!("Metrics server error during shutdown: {}", e),
+            Err(e) => warn!("Metrics server task error during shutdown: {}", e),
+            Ok(Ok(())) => {}
+        }
+    }
+    
     info!("StreamForge Stream Processor shutdown complete");
+    
+    // Flush buffered spans last so shutdown itself is traced
+    telemetry_guard.shutdown();
     Ok(())
 } 
\ No newline at end of file
@@ -0,0 +1,394 @@
+//! Approximate aggregations whose state stays bounded however many distinct
+//! values a window sees: HyperLogLog distinct counts, space-saving top-k
+//! and DDSketch quantiles. All serialize and merge, so partial results from
+//! several workers or windows combine into one.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+/// Registers are `2^precision`; 12 gives about 1.6% standard error in 4 KiB.
+pub const DEFAULT_HLL_PRECISION: u8 = 12;
+
+/// Counters kept per requested top entry, for accuracy past the `k`th.
+const TOP_K_CAPACITY_FACTOR: usize = 10;
+
+/// Quantiles come within 1% of a value actually observed.
+pub const DEFAULT_RELATIVE_ACCURACY: f64 = 0.01;
+
+/// Magnitudes below this count as zero, keeping bucket indexes bounded.
+const MIN_INDEXABLE: f64 = 1e-9;
+
+/// Distinct value count after Flajolet et al. (2007), with linear counting
+/// for small cardinalities.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_HLL_PRECISION)
+    }
+}
+
+impl HyperLogLog {
+    /// `precision` is clamped to 4..=16.
+    pub fn new(precision: u8) -> Self {
+        let precision = precision.clamp(4, 16);
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    pub fn insert(&mut self, value: &str) {
+        let hash = stable_hash(value.as_bytes());
+        let index = (hash >> (64 - self.precision)) as usize;
+        // Leading zeros of the remaining bits, plus one; the shifted-in
+        // sentinel bit bounds the count when they are all zero
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Folds `other` in, as if its values had been inserted here.
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), String> {
+        if other.precision != self.precision {
+            return Err(format!(
+                "cannot merge HyperLogLog of precision {} into one of precision {}",
+                other.precision, self.precision
+            ));
+        }
+        for (register, theirs) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*theirs);
+        }
+        Ok(())
+    }
+
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+}
+
+/// The most frequent values after Metwally et al. (2005): a fixed number of
+/// counters, where a new value takes over the smallest counter once all
+/// are in use. Counts are never under the true count, and over it by at
+/// most the entry's `error`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpaceSaving {
+    capacity: usize,
+    counters: HashMap<String, Counter>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counter {
+    pub count: u64,
+    pub error: u64,
+}
+
+impl SpaceSaving {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            counters: HashMap::new(),
+        }
+    }
+
+    /// Sized to report the top `k` values.
+    pub fn for_top(k: usize) -> Self {
+        Self::new(k.saturating_mul(TOP_K_CAPACITY_FACTOR))
+    }
+
+    pub fn insert(&mut self, value: &str, weight: u64) {
+        if let Some(counter) = self.counters.get_mut(value) {
+            counter.count += weight;
+            return;
+        }
+        if self.counters.len() < self.capacity {
+            self.counters.insert(value.to_string(), Counter { count: weight, error: 0 });
+            return;
+        }
+        let (smallest, min) = self
+            .counters
+            .iter()
+            .min_by(|a, b| a.1.count.cmp(&b.1.count).then_with(|| b.0.cmp(a.0)))
+            .map(|(value, counter)| (value.clone(), counter.count))
+            .expect("counters are full");
+        self.counters.remove(&smallest);
+        self.counters.insert(
+            value.to_string(),
+            Counter {
+                count: min + weight,
+                error: min,
+            },
+        );
+    }
+
+    /// Folds `other` in, keeping the largest counters of both.
+    pub fn merge(&mut self, other: &SpaceSaving) {
+        for (value, theirs) in &other.counters {
+            let counter = self.counters.entry(value.clone()).or_insert(Counter { count: 0, error: 0 });
+            counter.count += theirs.count;
+            counter.error += theirs.error;
+        }
+        if self.counters.len() > self.capacity {
+            let kept: HashMap<_, _> = self.ranked().into_iter().take(self.capacity).collect();
+            self.counters = kept;
+        }
+    }
+
+    /// The `k` values counted most, largest first, ties by value.
+    pub fn top(&self, k: usize) -> Vec<(String, Counter)> {
+        let mut ranked = self.ranked();
+        ranked.truncate(k);
+        ranked
+    }
+
+    fn ranked(&self) -> Vec<(String, Counter)> {
+        let mut ranked: Vec<_> = self.counters.iter().map(|(value, counter)| (value.clone(), *counter)).collect();
+        ranked.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(&b.0)));
+        ranked
+    }
+}
+
+/// Quantile sketch after Masson et al. (2019): values fall in buckets whose
+/// bounds grow geometrically, so any quantile is within the relative
+/// accuracy of a true value. Sketches with the same accuracy merge exactly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DDSketch {
+    relative_accuracy: f64,
+    count: u64,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+    zero_count: u64,
+    /// Counts by bucket index, for positive values and for the magnitudes
+    /// of negative ones.
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+}
+
+impl Default for DDSketch {
+    fn default() -> Self {
+        Self::new(DEFAULT_RELATIVE_ACCURACY)
+    }
+}
+
+impl DDSketch {
+    /// `relative_accuracy` is clamped to 0.0001..=0.5.
+    pub fn new(relative_accuracy: f64) -> Self {
+        Self {
+            relative_accuracy: relative_accuracy.clamp(0.0001, 0.5),
+            count: 0,
+            sum: 0.0,
+            min: None,
+            max: None,
+            zero_count: 0,
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+        }
+    }
+
+    /// Ignores values that are not finite.
+    pub fn insert(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        if value.abs() < MIN_INDEXABLE {
+            self.zero_count += 1;
+        } else {
+            let index = self.index(value.abs());
+            let buckets = if value > 0.0 { &mut self.positive } else { &mut self.negative };
+            *buckets.entry(index).or_insert(0) += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    /// Folds `other` in, as if its values had been inserted here.
+    pub fn merge(&mut self, other: &DDSketch) -> Result<(), String> {
+        if other.relative_accuracy != self.relative_accuracy {
+            return Err(format!(
+                "cannot merge a sketch of relative accuracy {} into one of {}",
+                other.relative_accuracy, self.relative_accuracy
+            ));
+        }
+        for (index, count) in &other.positive {
+            *self.positive.entry(*index).or_insert(0) += count;
+        }
+        for (index, count) in &other.negative {
+            *self.negative.entry(*index).or_insert(0) += count;
+        }
+        self.zero_count += other.zero_count;
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = [self.min, other.min].into_iter().flatten().reduce(f64::min);
+        self.max = [self.max, other.max].into_iter().flatten().reduce(f64::max);
+        Ok(())
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// The value at quantile `q` (0 to 1), or `None` for an empty sketch.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        let rank = (q * (self.count - 1) as f64).floor() as u64;
+        let mut seen = 0;
+        let mut value = None;
+        for (index, count) in self.negative.iter().rev() {
+            seen += count;
+            if seen > rank {
+                value = Some(-self.value(*index));
+                break;
+            }
+        }
+        if value.is_none() {
+            seen += self.zero_count;
+            if seen > rank {
+                value = Some(0.0);
+            }
+        }
+        if value.is_none() {
+            for (index, count) in &self.positive {
+                seen += count;
+                if seen > rank {
+                    value = Some(self.value(*index));
+                    break;
+                }
+            }
+        }
+        // Bucket values can stray past the extremes actually observed
+        let (min, max) = (self.min?, self.max?);
+        value.map(|value| value.clamp(min, max))
+    }
+
+    fn gamma(&self) -> f64 {
+        (1.0 + self.relative_accuracy) / (1.0 - self.relative_accuracy)
+    }
+
+    fn index(&self, magnitude: f64) -> i32 {
+        (magnitude.ln() / self.gamma().ln()).ceil() as i32
+    }
+
+    /// The value reported for a bucket, equally close to both its bounds.
+    fn value(&self, index: i32) -> f64 {
+        let gamma = self.gamma();
+        2.0 * gamma.powi(index) / (gamma + 1.0)
+    }
+}
+
+/// The first 8 bytes of the SHA-256 of `bytes`. Unlike the std hashers it
+/// is fixed by its specification, so state hashed by one build, such as
+/// stored registers, can be merged by any other.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    let digest = Sha256::digest(bytes);
+    u64::from_be_bytes(digest[..8].try_into().expect("a SHA-256 digest has 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hyperloglog_estimates_and_merges() {
+        let mut first = HyperLogLog::default();
+        let mut second = HyperLogLog::default();
+        for i in 0..20_000 {
+            first.insert(&format!("user-{}", i));
+            // Half of these were already seen by the first
+            second.insert(&format!("user-{}", i + 10_000));
+        }
+        let estimate = first.estimate();
+        assert!((estimate - 20_000.0).abs() < 20_000.0 * 0.05, "estimate {}", estimate);
+
+        first.merge(&second).unwrap();
+        let merged = first.estimate();
+        assert!((merged - 30_000.0).abs() < 30_000.0 * 0.05, "merged {}", merged);
+        assert!(first.merge(&HyperLogLog::new(10)).is_err());
+
+        let mut small = HyperLogLog::default();
+        for value in ["a", "b", "c", "a"] {
+            small.insert(value);
+        }
+        assert_eq!(small.estimate().round(), 3.0);
+
+        // Registers stored by any build hash like this one
+        assert_eq!(stable_hash(b"streamforge"), 16344724652345341219);
+    }
+
+    #[test]
+    fn test_space_saving_finds_the_heavy_hitters() {
+        let mut top = SpaceSaving::new(3);
+        for (value, times) in [("/orders", 50), ("/login", 30), ("/a", 1), ("/b", 1), ("/health", 20), ("/c", 1)] {
+            for _ in 0..times {
+                top.insert(value, 1);
+            }
+        }
+        let ranked: Vec<_> = top.top(2).into_iter().map(|(value, c)| (value, c.count)).collect();
+        assert_eq!(ranked, vec![("/orders".to_string(), 50), ("/login".to_string(), 30)]);
+
+        let mut other = SpaceSaving::new(3);
+        other.insert("/login", 40);
+        top.merge(&other);
+        assert_eq!(top.top(1)[0], ("/login".to_string(), Counter { count: 70, error: 0 }));
+        assert_eq!(top.top(10).len(), 3);
+    }
+
+    #[test]
+    fn test_ddsketch_quantiles_stay_within_the_relative_accuracy() {
+        let mut first = DDSketch::default();
+        let mut second = DDSketch::default();
+        for i in 1..=1000 {
+            first.insert(i as f64);
+            second.insert((i + 1000) as f64);
+        }
+        let p95 = first.quantile(0.95).unwrap();
+        assert!((p95 - 950.0).abs() <= 950.0 * DEFAULT_RELATIVE_ACCURACY, "p95 {}", p95);
+        assert_eq!(first.quantile(0.0), Some(1.0));
+        assert!(first.quantile(1.0).unwrap() <= 1000.0);
+
+        // Stored and read back, as another worker would
+        let stored: DDSketch = serde_json::from_value(serde_json::to_value(&second).unwrap()).unwrap();
+        first.merge(&stored).unwrap();
+        let median = first.quantile(0.5).unwrap();
+        assert!((median - 1000.0).abs() <= 1000.0 * DEFAULT_RELATIVE_ACCURACY, "median {}", median);
+        assert_eq!((first.count(), first.sum()), (2000, 2_001_000.0));
+        assert!(first.merge(&DDSketch::new(0.05)).is_err());
+
+        let mut mixed = DDSketch::default();
+        for value in [-10.0, 0.0, 5.0, f64::NAN] {
+            mixed.insert(value);
+        }
+        assert_eq!(mixed.quantile(0.5), Some(0.0));
+        assert!((mixed.quantile(0.0).unwrap() + 10.0).abs() <= 0.1);
+        assert_eq!(DDSketch::default().quantile(0.5), None);
+    }
+}
@@ -0,0 +1,41 @@
+//! Generators of untrusted input for property tests.
+
+use proptest::prelude::*;
+use serde_json::Value;
+
+/// Any JSON value, nested a few levels deep.
+pub fn json() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(Value::from),
+        any::<u64>().prop_map(Value::from),
+        // Eighths have short exact decimal forms, so they survive a round
+        // trip through serde_json's default float parser
+        any::<i32>().prop_map(|n| Value::from(n as f64 / 8.0)),
+        ".*".prop_map(Value::String),
+    ];
+    leaf.prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(Value::Array),
+            prop::collection::btree_map(".*", inner, 0..8).prop_map(|fields| Value::Object(fields.into_iter().collect())),
+        ]
+    })
+}
+
+/// Bytes a producer might send where a JSON document is expected: valid
+/// JSON, JSON cut off part way, nesting past the parser's recursion limit,
+/// invalid UTF-8 and plain noise.
+pub fn hostile_payload() -> impl Strategy<Value = Vec<u8>> {
+    let encoded = || json().prop_map(|value| serde_json::to_vec(&value).unwrap());
+    prop_oneof![
+        encoded(),
+        (encoded(), any::<prop::sample::Index>()).prop_map(|(bytes, cut)| bytes[..cut.index(bytes.len() + 1)].to_vec()),
+        (1usize..2048, any::<bool>()).prop_map(|(depth, objects)| {
+            let (open, close) = if objects { (r#"{"a":"#, "}") } else { ("[", "]") };
+            format!("{}1{}", open.repeat(depth), close.repeat(depth)).into_bytes()
+        }),
+        ".*".prop_map(|text| [&[0xff, 0xfe][..], text.as_bytes()].concat()),
+        prop::collection::vec(any::<u8>(), 0..512),
+    ]
+}
@@ -0,0 +1,133 @@
+use chrono::Utc;
+use std::fmt::Display;
+use std::sync::Arc;
+use tracing::error;
+
+use crate::metrics::Metrics;
+use crate::storage::{AuditEntry, StorageBackend};
+
+pub const OUTCOME_SUCCEEDED: &str = "succeeded";
+pub const OUTCOME_FAILED: &str = "failed";
+
+/// Actor and source of actions the process takes on its own, such as
+/// applying a changed config file.
+pub const SYSTEM_ACTOR: &str = "system";
+
+impl AuditEntry {
+    /// A successful action with no details, occurring now.
+    pub fn new(
+        actor: impl Into<String>,
+        source: impl Into<String>,
+        action: impl Into<String>,
+        target: impl Into<String>,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            occurred_at: Utc::now(),
+            actor: actor.into(),
+            source: source.into(),
+            action: action.into(),
+            tenant_id: None,
+            target: target.into(),
+            details: serde_json::Value::Null,
+            outcome: OUTCOME_SUCCEEDED.to_string(),
+            error: None,
+        }
+    }
+
+    pub fn tenant(mut self, tenant_id: impl Into<String>) -> Self {
+        self.tenant_id = Some(tenant_id.into());
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> Self {
+        self.details = details;
+        self
+    }
+
+    /// Records the outcome of the action from its result.
+    pub fn outcome<T, E: Display>(mut self, result: &Result<T, E>) -> Self {
+        if let Err(e) = result {
+            self.outcome = OUTCOME_FAILED.to_string();
+            self.error = Some(e.to_string());
+        }
+        self
+    }
+}
+
+/// Appends control-plane actions to the audit log in storage.
+pub struct Auditor {
+    storage: Arc<dyn StorageBackend>,
+    metrics: Arc<Metrics>,
+}
+
+impl Auditor {
+    pub fn new(storage: Arc<dyn StorageBackend>, metrics: Arc<Metrics>) -> Self {
+        Self { storage, metrics }
+    }
+
+    /// An entry that cannot be written does not undo or fail the action it
+    /// records; it is logged with its contents and counted in
+    /// `audit_write_failures_total` so the gap can be alerted on.
+    pub async fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.storage.append_audit_entry(&entry).await {
+            error!("Failed to write audit entry {:?}: {}", entry, e);
+            self.metrics.increment_audit_write_failures();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+    use crate::storage::{AuditFilter, MemoryStorage};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_record_and_query_audit_log() {
+        let storage = Arc::new(MemoryStorage::with_capacity(16));
+        let metrics = Arc::new(Metrics::new(&MetricsConfig::default()).unwrap());
+        let auditor = Auditor::new(storage.clone(), metrics);
+
+        let start = Utc::now();
+        for (i, actor) in ["alice", "bob", "alice"].into_iter().enumerate() {
+            let mut entry = AuditEntry::new(actor, "10.0.0.1:5000", "StopProcessing", format!("job-{}", i))
+                .details(json!({"job_id": format!("job-{}", i)}))
+                .outcome(&if i == 1 { Err("Job job-1 not found") } else { Ok(()) });
+            entry.occurred_at = start + chrono::Duration::seconds(i as i64);
+            auditor.record(entry).await;
+        }
+
+        let filter = AuditFilter {
+            actor: Some("alice".to_string()),
+            ..Default::default()
+        };
+        let page = storage.query_audit_log(&filter, Some(1), None).await.unwrap();
+        assert_eq!(page.items[0].target, "job-2");
+        let page = storage
+            .query_audit_log(&filter, Some(1), page.next_cursor.as_deref())
+            .await
+            .unwrap();
+        assert_eq!(page.items[0].target, "job-0");
+        assert!(page.next_cursor.is_none());
+
+        let failed = storage
+            .query_audit_log(&AuditFilter::default(), None, None)
+            .await
+            .unwrap()
+            .items
+            .into_iter()
+            .find(|entry| entry.actor == "bob")
+            .unwrap();
+        assert_eq!(failed.outcome, OUTCOME_FAILED);
+        assert_eq!(failed.error.as_deref(), Some("Job job-1 not found"));
+
+        let filter = AuditFilter {
+            end_time: Some(start + chrono::Duration::seconds(1)),
+            ..Default::default()
+        };
+        let page = storage.query_audit_log(&filter, None, None).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+    }
+}
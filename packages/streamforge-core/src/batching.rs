@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::config::{AdaptiveBatchingConfig, ProcessingConfig};
+use crate::metrics::Metrics;
+
+/// Factor a topic's batch size grows by after a window under the target.
+const GROWTH: f64 = 1.25;
+
+/// Factor it shrinks by after a window over the target.
+const BACKOFF: f64 = 0.5;
+
+/// Shortest wait for a batch to fill, however small the batch.
+const MIN_BATCH_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Chooses batch sizes per topic from the latency of the batches committed
+/// before them, when `processing.adaptive_batching` is enabled. Otherwise
+/// every batch uses the fixed `batch_size` and `batch_timeout`.
+pub struct AdaptiveBatching {
+    settings: watch::Receiver<ProcessingConfig>,
+    topics: Mutex<HashMap<String, TopicState>>,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Debug)]
+struct TopicState {
+    size: usize,
+    /// Latencies in seconds since the last adjustment.
+    samples: Vec<f64>,
+}
+
+impl AdaptiveBatching {
+    pub fn new(settings: watch::Receiver<ProcessingConfig>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            settings,
+            topics: Mutex::new(HashMap::new()),
+            metrics,
+        }
+    }
+
+    /// Size and fill timeout for a batch. Batches mix topics, so each is
+    /// sized for the topic of its first message.
+    pub fn limits(&self, topic: &str) -> (usize, Duration) {
+        let settings = self.settings.borrow();
+        let adaptive = &settings.adaptive_batching;
+        if !adaptive.enabled {
+            return (settings.batch_size, settings.batch_timeout);
+        }
+
+        let size = self
+            .topics
+            .lock()
+            .unwrap()
+            .get(topic)
+            .map_or_else(|| initial_size(&settings), |state| state.size)
+            .clamp(adaptive.min_batch_size, adaptive.max_batch_size.max(adaptive.min_batch_size));
+        let timeout = settings
+            .batch_timeout
+            .mul_f64(size as f64 / adaptive.max_batch_size.max(1) as f64)
+            .max(MIN_BATCH_TIMEOUT);
+        (size, timeout)
+    }
+
+    /// Records how long committed messages took from consumption to commit.
+    /// Consumer lag is left out because batching cannot make it shorter.
+    pub fn observe<'a>(&self, samples: impl IntoIterator<Item = (&'a str, Duration)>) {
+        let settings = self.settings.borrow();
+        let adaptive = &settings.adaptive_batching;
+        if !adaptive.enabled {
+            return;
+        }
+
+        let mut topics = self.topics.lock().unwrap();
+        for (topic, latency) in samples {
+            if !topics.contains_key(topic) {
+                let size = initial_size(&settings);
+                self.metrics.set_effective_batch_size(topic, size);
+                topics.insert(topic.to_string(), TopicState { size, samples: Vec::new() });
+            }
+            let state = topics.get_mut(topic).expect("topic was just inserted");
+            state.samples.push(latency.as_secs_f64());
+            if state.samples.len() >= adaptive.window {
+                state.size = adjust(state.size, p99(&mut state.samples), adaptive);
+                state.samples.clear();
+                self.metrics.set_effective_batch_size(topic, state.size);
+            }
+        }
+    }
+}
+
+fn initial_size(settings: &ProcessingConfig) -> usize {
+    let adaptive = &settings.adaptive_batching;
+    settings.batch_size.clamp(adaptive.min_batch_size, adaptive.max_batch_size.max(adaptive.min_batch_size))
+}
+
+fn adjust(size: usize, p99: f64, config: &AdaptiveBatchingConfig) -> usize {
+    let next = if p99 > config.target_latency.as_secs_f64() {
+        (size as f64 * BACKOFF) as usize
+    } else {
+        ((size as f64 * GROWTH) as usize).max(size + 1)
+    };
+    next.clamp(config.min_batch_size, config.max_batch_size.max(config.min_batch_size))
+}
+
+/// Nearest-rank 99th percentile; sorts `samples` in place.
+fn p99(samples: &mut [f64]) -> f64 {
+    samples.sort_by(f64::total_cmp);
+    let rank = ((samples.len() as f64 * 0.99).ceil() as usize).max(1);
+    samples[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+
+    #[test]
+    fn test_batches_grow_under_target_and_shrink_over_it() {
+        let mut settings = ProcessingConfig::default();
+        settings.batch_size = 100;
+        settings.batch_timeout = Duration::from_secs(10);
+        settings.adaptive_batching = AdaptiveBatchingConfig {
+            enabled: true,
+            target_latency: Duration::from_millis(500),
+            min_batch_size: 10,
+            max_batch_size: 1000,
+            window: 10,
+        };
+        let (tx, rx) = watch::channel(settings);
+        let batching = AdaptiveBatching::new(rx, Arc::new(Metrics::new(&MetricsConfig::default()).unwrap()));
+        assert_eq!(batching.limits("metrics"), (100, Duration::from_secs(1)));
+
+        let fast = [("metrics", Duration::from_millis(100)); 10];
+        batching.observe(fast.iter().copied());
+        assert_eq!(batching.limits("metrics").0, 125);
+        assert_eq!(batching.limits("logs").0, 100);
+
+        // One slow message in a window of ten is over the p99
+        let mut slow = fast;
+        slow[3].1 = Duration::from_secs(2);
+        batching.observe(slow.iter().copied());
+        assert_eq!(batching.limits("metrics").0, 62);
+        for _ in 0..5 {
+            batching.observe(slow.iter().copied());
+        }
+        assert_eq!(batching.limits("metrics"), (10, Duration::from_millis(100)));
+
+        tx.send_modify(|settings| settings.adaptive_batching.enabled = false);
+        assert_eq!(batching.limits("metrics"), (100, Duration::from_secs(10)));
+    }
+}
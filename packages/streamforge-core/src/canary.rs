@@ -0,0 +1,234 @@
+//! Versioned deployments: a candidate version of a pipeline runs as its own
+//! job beside the baseline job, on a share of the same input, writing its
+//! kafka output under a separate namespace until it is promoted or rolled
+//! back. The candidate consumes in its own consumer group so the baseline
+//! keeps every partition and offset it had.
+
+use crate::approx::stable_hash;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Key of the candidate's settings in its job configuration.
+pub const CANARY_KEY: &str = "canary";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CanaryMode {
+    /// A copy of all of the input.
+    Shadow,
+    /// `sample_percent` of the input.
+    Canary,
+}
+
+/// The `canary` section of a candidate job's configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CanarySettings {
+    pub deployment_id: String,
+    pub mode: CanaryMode,
+    /// Share of records the candidate takes in `canary` mode, in (0, 100].
+    pub sample_percent: f64,
+    /// Prefix of the candidate's output topics.
+    pub namespace: String,
+}
+
+impl CanarySettings {
+    /// The settings of a candidate job, or `None` for any other job.
+    pub fn from_job_config(config: &Value) -> Result<Option<Self>, String> {
+        match config.get(CANARY_KEY) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => {
+                let settings = Self::deserialize(value).map_err(|e| e.to_string())?;
+                settings.check()?;
+                Ok(Some(settings))
+            }
+        }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.mode == CanaryMode::Canary && !(self.sample_percent > 0.0 && self.sample_percent <= 100.0) {
+            return Err("sample_percent must be greater than 0 and at most 100".to_string());
+        }
+        let legal = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+        if self.namespace.is_empty() || !self.namespace.chars().all(legal) {
+            return Err(format!("{:?} is not a valid topic prefix", self.namespace));
+        }
+        Ok(())
+    }
+
+    /// Share of the input the candidate takes, from 0 to 1.
+    pub fn share(&self) -> f64 {
+        match self.mode {
+            CanaryMode::Shadow => 1.0,
+            CanaryMode::Canary => self.sample_percent / 100.0,
+        }
+    }
+
+    /// Whether the candidate takes a record. Decided from its key, or from
+    /// its position when it has none, so every instance and every restart
+    /// agrees on the sample, whatever build it runs.
+    pub fn selects(&self, key: Option<&[u8]>, partition: i32, offset: i64) -> bool {
+        if self.mode == CanaryMode::Shadow {
+            return true;
+        }
+        let mut bytes = self.deployment_id.as_bytes().to_vec();
+        bytes.push(0);
+        match key {
+            Some(key) => bytes.extend_from_slice(key),
+            None => {
+                bytes.extend_from_slice(&partition.to_be_bytes());
+                bytes.extend_from_slice(&offset.to_be_bytes());
+            }
+        }
+        (stable_hash(&bytes) % 10_000) as f64 < self.sample_percent * 100.0
+    }
+
+    /// `topic` under the candidate's namespace.
+    pub fn topic(&self, topic: &str) -> String {
+        format!("{}.{}", self.namespace, topic)
+    }
+}
+
+/// The candidate's job configuration: `config` of the new version with
+/// its kafka sinks moved under the namespace, its own consumer group next
+/// to `group`, and the `canary` section. Candidates may only write to kafka
+/// sinks, whose output can be kept apart from the baseline's.
+pub fn candidate_config(config: &Value, settings: &CanarySettings, group: &str) -> Result<Value, String> {
+    settings.check()?;
+    let mut config = match config {
+        Value::Null => Value::Object(Default::default()),
+        Value::Object(_) => config.clone(),
+        _ => return Err("the job configuration must be a JSON object".to_string()),
+    };
+
+    let sinks = config
+        .get_mut("sinks")
+        .and_then(Value::as_array_mut)
+        .filter(|sinks| !sinks.is_empty())
+        .ok_or("candidates must list their sinks, so their output can be moved")?;
+    for (i, sink) in sinks.iter_mut().enumerate() {
+        let kind = sink.get("type").and_then(Value::as_str).unwrap_or_default();
+        if kind != "kafka" {
+            return Err(format!("sinks[{}] is a {:?} sink; candidates can only write to kafka sinks", i, kind));
+        }
+        let topic = sink
+            .pointer("/config/topic")
+            .and_then(Value::as_str)
+            .map(|topic| settings.topic(topic))
+            .ok_or_else(|| format!("sinks[{}] has no topic", i))?;
+        sink["config"]["topic"] = Value::from(topic);
+    }
+    let group = format!(
+        "{}.{}",
+        config.get("group_id").and_then(Value::as_str).unwrap_or(group),
+        settings.namespace
+    );
+    config["group_id"] = Value::from(group);
+    config[CANARY_KEY] = serde_json::to_value(settings).map_err(|e| e.to_string())?;
+    Ok(config)
+}
+
+/// Processing counts of one version over its job's lifetime.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VersionStats {
+    pub messages_processed: u64,
+    pub errors: u64,
+    /// Messages per second.
+    pub throughput: f64,
+}
+
+impl VersionStats {
+    pub fn error_rate(&self) -> f64 {
+        if self.messages_processed == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.messages_processed as f64
+        }
+    }
+}
+
+/// How the candidate fares against the baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub baseline: VersionStats,
+    pub candidate: VersionStats,
+    /// Candidate error rate minus the baseline's; positive is worse.
+    pub error_rate_delta: f64,
+    /// Candidate throughput over the baseline's share of the same input;
+    /// below 1 the candidate keeps up worse. 0 while the baseline has no
+    /// throughput to compare against.
+    pub throughput_ratio: f64,
+}
+
+impl Comparison {
+    /// `share` is the part of the input the candidate takes.
+    pub fn new(baseline: VersionStats, candidate: VersionStats, share: f64) -> Self {
+        let expected = baseline.throughput * share;
+        Self {
+            baseline,
+            candidate,
+            error_rate_delta: candidate.error_rate() - baseline.error_rate(),
+            throughput_ratio: if expected > 0.0 { candidate.throughput / expected } else { 0.0 },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn settings(mode: CanaryMode, sample_percent: f64) -> CanarySettings {
+        CanarySettings {
+            deployment_id: "d-1".to_string(),
+            mode,
+            sample_percent,
+            namespace: "canary-d1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_candidate_config_moves_output_and_group() {
+        let config = json!({
+            "group_id": "processors",
+            "sinks": [{"name": "out", "type": "kafka", "config": {"topic": "metrics.enriched"}}],
+        });
+        let canary = settings(CanaryMode::Canary, 10.0);
+        let candidate = candidate_config(&config, &canary, "default").unwrap();
+
+        assert_eq!(candidate["sinks"][0]["config"]["topic"], "canary-d1.metrics.enriched");
+        assert_eq!(candidate["group_id"], "processors.canary-d1");
+        assert_eq!(CanarySettings::from_job_config(&candidate).unwrap(), Some(canary.clone()));
+        assert_eq!(CanarySettings::from_job_config(&config).unwrap(), None);
+
+        let storage = json!({"sinks": [{"name": "db", "type": "storage"}]});
+        assert!(candidate_config(&storage, &canary, "default").is_err());
+        assert!(candidate_config(&config, &settings(CanaryMode::Canary, 0.0), "default").is_err());
+    }
+
+    #[test]
+    fn test_sample_is_stable_and_close_to_the_share() {
+        let canary = settings(CanaryMode::Canary, 25.0);
+        let selected = (0..10_000).filter(|offset| canary.selects(None, 0, *offset)).count();
+        assert!((2_000..3_000).contains(&selected), "{} selected", selected);
+        assert_eq!(canary.selects(Some(b"host-1"), 0, 1), canary.selects(Some(b"host-1"), 3, 99));
+        assert!(settings(CanaryMode::Shadow, 0.0).selects(None, 0, 1));
+    }
+
+    #[test]
+    fn test_comparison_scales_baseline_throughput_by_the_share() {
+        let baseline = VersionStats {
+            messages_processed: 1_000,
+            errors: 10,
+            throughput: 100.0,
+        };
+        let candidate = VersionStats {
+            messages_processed: 100,
+            errors: 5,
+            throughput: 9.0,
+        };
+        let comparison = Comparison::new(baseline, candidate, 0.1);
+        assert!((comparison.error_rate_delta - 0.04).abs() < 1e-9);
+        assert!((comparison.throughput_ratio - 0.9).abs() < 1e-9);
+        assert_eq!(Comparison::new(VersionStats::default(), candidate, 0.1).throughput_ratio, 0.0);
+    }
+}
@@ -0,0 +1,214 @@
+//! Guard against metric label explosions: counts the distinct label sets
+//! of each metric name and limits new series past `processing.cardinality`.
+
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use streamforge_sdk::{Alert, Metric};
+use tokio::sync::watch;
+
+use crate::config::{CardinalityAction, CardinalityConfig, ProcessingConfig};
+use crate::metrics::Metrics;
+use crate::processor::ProcessedMessage;
+
+/// Label value given to the labels of series folded together past a limit.
+pub const OVERFLOW_LABEL_VALUE: &str = "__overflow__";
+
+pub struct CardinalityGuard {
+    settings: watch::Receiver<ProcessingConfig>,
+    state: Mutex<State>,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Default)]
+struct State {
+    window_start: Option<Instant>,
+    /// Label set hashes by `(tenant_id, metric name)`.
+    series: HashMap<(String, String), HashSet<u64>>,
+    /// Metrics already alerted on in this window.
+    alerted: HashSet<(String, String)>,
+}
+
+/// A metric that went over its series limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub tenant_id: String,
+    pub metric: String,
+    pub limit: usize,
+    pub action: CardinalityAction,
+}
+
+impl CardinalityGuard {
+    pub fn new(settings: watch::Receiver<ProcessingConfig>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            settings,
+            state: Mutex::new(State::default()),
+            metrics,
+        }
+    }
+
+    /// Returns whether to keep `message`, after folding its labels into the
+    /// overflow series if it is a new series past the limit, and the
+    /// violation when this is the first such series of its metric in the
+    /// window. Messages that are not metrics always pass.
+    pub fn admit(&self, message: &mut ProcessedMessage, now: Instant) -> (bool, Option<Violation>) {
+        let settings = self.settings.borrow();
+        let config = &settings.cardinality;
+        if !config.enabled {
+            return (true, None);
+        }
+        let Ok(metric) = Metric::deserialize(&message.processed_message) else {
+            return (true, None);
+        };
+        let labels: BTreeMap<&str, &str> = metric
+            .labels
+            .iter()
+            .flatten()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        if labels.values().any(|v| *v == OVERFLOW_LABEL_VALUE) {
+            return (true, None);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.window_start.map_or(true, |start| now.duration_since(start) >= config.window) {
+            state.window_start = Some(now);
+            state.series.clear();
+            state.alerted.clear();
+        }
+
+        let key = (message.processing_metadata.tenant_id.clone(), metric.name.clone());
+        let limit = config.overrides.get(&metric.name).copied().unwrap_or(config.max_series_per_metric);
+        let series = state.series.entry(key.clone()).or_default();
+        let hash = label_set_hash(&labels);
+        if series.contains(&hash) || series.len() < limit {
+            series.insert(hash);
+            return (true, None);
+        }
+
+        self.metrics.increment_cardinality_limited(match config.action {
+            CardinalityAction::Drop => "drop",
+            CardinalityAction::Aggregate => "aggregate",
+        });
+        let violation = state.alerted.insert(key).then(|| Violation {
+            tenant_id: message.processing_metadata.tenant_id.clone(),
+            metric: metric.name.clone(),
+            limit,
+            action: config.action,
+        });
+        match config.action {
+            CardinalityAction::Drop => (false, violation),
+            CardinalityAction::Aggregate => {
+                fold_labels(&mut message.processed_message, config);
+                (true, violation)
+            }
+        }
+    }
+}
+
+impl Violation {
+    /// Alert raised for the violation, for the notifier.
+    pub fn alert(&self) -> Alert {
+        let handling = match self.action {
+            CardinalityAction::Drop => "dropped",
+            CardinalityAction::Aggregate => "aggregated into an overflow series",
+        };
+        let metadata = [
+            ("metric".to_string(), Value::from(self.metric.clone())),
+            ("limit".to_string(), Value::from(self.limit)),
+        ];
+        Alert {
+            id: uuid::Uuid::new_v4().to_string(),
+            severity: "warning".to_string(),
+            message: format!(
+                "Metric {} exceeded {} distinct label sets; new series are {}",
+                self.metric, self.limit, handling
+            ),
+            timestamp: Utc::now().timestamp_millis() as u64,
+            service: "stream-processor".to_string(),
+            metadata: Some(metadata.into_iter().collect()),
+        }
+    }
+}
+
+fn label_set_hash(labels: &BTreeMap<&str, &str>) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    labels.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn fold_labels(message: &mut Value, config: &CardinalityConfig) {
+    if let Some(labels) = message.get_mut("labels").and_then(Value::as_object_mut) {
+        for (key, value) in labels.iter_mut() {
+            if !config.keep_labels.contains(key) {
+                *value = Value::from(OVERFLOW_LABEL_VALUE);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+    use crate::processor::ProcessingMetadata;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn message(tenant: &str, labels: Value) -> ProcessedMessage {
+        let metric = json!({"name": "http_requests", "value": 1.0, "unit": "count", "labels": labels});
+        ProcessedMessage {
+            id: "1".to_string(),
+            original_message: metric.clone(),
+            processed_message: metric,
+            processing_metadata: ProcessingMetadata {
+                tenant_id: tenant.to_string(),
+                processed_at: Utc::now(),
+                processor_version: "test".to_string(),
+                source_topic: "metrics".to_string(),
+                partition: 0,
+                offset: 0,
+                traceparent: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_series_past_the_limit_are_folded_and_alerted_once() {
+        let mut settings = ProcessingConfig::default();
+        settings.cardinality = CardinalityConfig {
+            enabled: true,
+            max_series_per_metric: 2,
+            window: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let (tx, rx) = watch::channel(settings);
+        let guard = CardinalityGuard::new(rx, Arc::new(Metrics::new(&MetricsConfig::default()).unwrap()));
+        let now = Instant::now();
+
+        for user in ["u1", "u2", "u1"] {
+            let mut m = message("acme", json!({"service": "api", "user": user}));
+            assert_eq!(guard.admit(&mut m, now), (true, None));
+        }
+        // Another tenant has its own count
+        assert_eq!(guard.admit(&mut message("globex", json!({"service": "api", "user": "u3"})), now), (true, None));
+
+        let mut third = message("acme", json!({"service": "api", "user": "u3"}));
+        let (kept, violation) = guard.admit(&mut third, now);
+        assert!(kept);
+        assert_eq!(violation.unwrap().metric, "http_requests");
+        assert_eq!(third.processed_message["labels"], json!({"service": "api", "user": OVERFLOW_LABEL_VALUE}));
+        assert_eq!(guard.admit(&mut message("acme", json!({"service": "api", "user": "u4"})), now).1, None);
+
+        tx.send_modify(|settings| settings.cardinality.action = CardinalityAction::Drop);
+        assert!(!guard.admit(&mut message("acme", json!({"service": "api", "user": "u5"})), now).0);
+
+        // A new window starts counting again
+        let later = now + Duration::from_secs(60);
+        assert!(guard.admit(&mut message("acme", json!({"service": "api", "user": "u5"})), later).0);
+    }
+}
@@ -0,0 +1,332 @@
+//! Records too large for the broker. A kafka sink's `oversize` option either
+//! splits them into chunks, produced in order to one partition with headers
+//! to reassemble them by, or writes the payload to object storage and
+//! produces a pointer record in its place. Consumers put records back
+//! together with `Reassembler` and `resolve`.
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use tracing::info;
+
+use crate::headers::MessageHeaders;
+use crate::partitioning::Route;
+use crate::storage::{read_object, write_object};
+
+/// Id shared by the chunks of one record.
+pub const HEADER_CHUNK_ID: &str = "x-chunk-id";
+
+/// Position of a chunk, from 0.
+pub const HEADER_CHUNK_INDEX: &str = "x-chunk-index";
+
+pub const HEADER_CHUNK_COUNT: &str = "x-chunk-count";
+
+/// Object holding the payload of a pointer record.
+pub const HEADER_PAYLOAD_LOCATION: &str = "x-payload-location";
+
+/// Room left for the record's framing in the broker's message size.
+const RECORD_OVERHEAD: usize = 512;
+
+/// The `oversize` option of a kafka sink. Without one, an oversized record
+/// fails to produce, as before the option existed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum Oversize {
+    #[default]
+    Fail,
+    Chunk,
+    /// Payloads are written under `location`, a local directory or an
+    /// `s3://bucket/prefix`, as `<topic>/<uuid>`.
+    Offload { location: String },
+}
+
+impl Oversize {
+    /// Reads the `oversize` option from a kafka sink's config.
+    pub fn from_sink_config(config: &Value) -> Result<Self, String> {
+        match config.get("oversize") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => {
+                let oversize = Self::deserialize(value).map_err(|e| e.to_string())?;
+                if matches!(&oversize, Self::Offload { location } if location.trim().is_empty()) {
+                    return Err("offloading oversized records requires a location".to_string());
+                }
+                Ok(oversize)
+            }
+        }
+    }
+}
+
+/// Fits the records of one kafka sink into `kafka.max_message_bytes`.
+#[derive(Debug)]
+pub struct Chunker {
+    oversize: Oversize,
+    max_bytes: usize,
+}
+
+impl Chunker {
+    pub fn new(oversize: Oversize, max_bytes: usize) -> Self {
+        Self { oversize, max_bytes }
+    }
+
+    /// The record as it is produced: unchanged when it fits, otherwise as
+    /// chunks or a pointer record.
+    pub async fn fit(
+        &self,
+        topic: &str,
+        route: Route,
+        headers: MessageHeaders,
+        payload: Vec<u8>,
+    ) -> Result<Vec<(Route, MessageHeaders, Vec<u8>)>> {
+        let size = record_size(&route, &headers, payload.len());
+        if size <= self.max_bytes {
+            return Ok(vec![(route, headers, payload)]);
+        }
+        match &self.oversize {
+            Oversize::Fail => Err(anyhow::anyhow!(
+                "Record of {} bytes for {} exceeds kafka.max_message_bytes ({})",
+                size,
+                topic,
+                self.max_bytes
+            )),
+            Oversize::Chunk => split(route, headers, payload, self.max_bytes),
+            Oversize::Offload { location } => {
+                let object = format!("{}/{}/{}", location.trim_end_matches('/'), topic, uuid::Uuid::new_v4());
+                let bytes = payload.len();
+                write_object(&object, payload).await?;
+                info!("Offloaded {} byte record for {} to {}", bytes, topic, object);
+
+                let mut headers = headers;
+                headers.insert(HEADER_PAYLOAD_LOCATION, object.as_bytes());
+                let pointer = serde_json::to_vec(&json!({"location": object, "bytes": bytes}))?;
+                Ok(vec![(route, headers, pointer)])
+            }
+        }
+    }
+}
+
+/// Bytes a record takes against the broker's limit.
+fn record_size(route: &Route, headers: &MessageHeaders, payload: usize) -> usize {
+    let headers: usize = headers.iter().map(|(key, value)| key.len() + value.len()).sum();
+    RECORD_OVERHEAD + route.key.as_ref().map_or(0, Vec::len) + headers + payload
+}
+
+/// Splits `payload` into chunks that fit `max_bytes`. Chunks keep the route
+/// of the record so they land on one partition in order; an unrouted
+/// record is keyed by its chunk id for that.
+fn split(
+    mut route: Route,
+    headers: MessageHeaders,
+    payload: Vec<u8>,
+    max_bytes: usize,
+) -> Result<Vec<(Route, MessageHeaders, Vec<u8>)>> {
+    let id = uuid::Uuid::new_v4().to_string();
+    if route.key.is_none() && route.partition.is_none() {
+        route.key = Some(id.clone().into_bytes());
+    }
+
+    let mut chunk_headers = headers;
+    chunk_headers.insert(HEADER_CHUNK_ID, id.as_bytes());
+    // Widest values the index and count can take
+    chunk_headers.insert(HEADER_CHUNK_INDEX, usize::MAX.to_string());
+    chunk_headers.insert(HEADER_CHUNK_COUNT, usize::MAX.to_string());
+    let chunk_bytes = max_bytes.saturating_sub(record_size(&route, &chunk_headers, 0));
+    if chunk_bytes == 0 {
+        return Err(anyhow::anyhow!(
+            "kafka.max_message_bytes ({}) leaves no room for a chunk next to the record's headers",
+            max_bytes
+        ));
+    }
+
+    let count = payload.len().div_ceil(chunk_bytes);
+    chunk_headers.insert(HEADER_CHUNK_COUNT, count.to_string());
+    Ok(payload
+        .chunks(chunk_bytes)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut headers = chunk_headers.clone();
+            headers.insert(HEADER_CHUNK_INDEX, index.to_string());
+            (route.clone(), headers, chunk.to_vec())
+        })
+        .collect())
+}
+
+/// The payload of a consumed record, read from object storage if the
+/// record is a pointer.
+pub async fn resolve(headers: &MessageHeaders, payload: Vec<u8>) -> Result<Vec<u8>> {
+    match headers.get(HEADER_PAYLOAD_LOCATION) {
+        Some(location) => {
+            let location = std::str::from_utf8(location)
+                .map_err(|e| anyhow::anyhow!("Failed to read {} header: {}", HEADER_PAYLOAD_LOCATION, e))?;
+            read_object(location).await
+        }
+        None => Ok(payload),
+    }
+}
+
+/// Puts chunked records back together on the consuming side. Chunks may
+/// arrive more than once; records left incomplete are dropped once
+/// `max_pending` others are in progress.
+#[derive(Debug)]
+pub struct Reassembler {
+    pending: HashMap<String, Vec<Option<Vec<u8>>>>,
+    /// Ids of pending records, oldest first.
+    order: VecDeque<String>,
+    max_pending: usize,
+    max_chunks: usize,
+}
+
+impl Reassembler {
+    /// Records split into more than `max_chunks` chunks are rejected before
+    /// room is made for them, as the count comes from the record's headers.
+    pub fn new(max_pending: usize, max_chunks: usize) -> Self {
+        Self {
+            pending: HashMap::new(),
+            order: VecDeque::new(),
+            max_pending: max_pending.max(1),
+            max_chunks: max_chunks.max(1),
+        }
+    }
+
+    /// Takes a consumed record, returning the whole payload once every
+    /// chunk of it has arrived. Records that were not chunked are returned
+    /// as they are.
+    pub fn push(&mut self, headers: &MessageHeaders, payload: Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+        let Some(id) = headers.get(HEADER_CHUNK_ID) else {
+            return Ok(Some(payload));
+        };
+        let id = String::from_utf8_lossy(id).into_owned();
+        let index = number(headers, HEADER_CHUNK_INDEX)?;
+        let count = number(headers, HEADER_CHUNK_COUNT)?;
+        if index >= count {
+            return Err(format!("chunk {} of {} is out of range", index, count));
+        }
+        if count > self.max_chunks {
+            return Err(format!("record of {} chunks exceeds the limit of {}", count, self.max_chunks));
+        }
+
+        if !self.pending.contains_key(&id) {
+            if self.pending.len() >= self.max_pending {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.pending.remove(&oldest);
+                }
+            }
+            self.pending.insert(id.clone(), vec![None; count]);
+            self.order.push_back(id.clone());
+        }
+        let chunks = self.pending.get_mut(&id).expect("pending record was just added");
+        if chunks.len() != count {
+            return Err(format!("chunk count {} differs from earlier chunks of {}", count, id));
+        }
+        chunks[index] = Some(payload);
+        if chunks.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+
+        let chunks = self.pending.remove(&id).unwrap_or_default();
+        self.order.retain(|pending| *pending != id);
+        Ok(Some(chunks.into_iter().flatten().flatten().collect()))
+    }
+
+    /// Records with chunks still missing.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+fn number(headers: &MessageHeaders, key: &str) -> Result<usize, String> {
+    headers
+        .get(key)
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("missing or invalid {} header", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_chunks_reassemble_in_any_order() {
+        let chunker = Chunker::new(Oversize::Chunk, RECORD_OVERHEAD + 200);
+        let payload: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut headers = MessageHeaders::new();
+        headers.insert("x-team", "core");
+
+        let mut chunks = chunker.fit("out", Route::default(), headers, payload.clone()).await.unwrap();
+        assert!(chunks.len() > 1);
+        let key = chunks[0].0.key.clone();
+        assert!(key.is_some());
+        for (route, headers, chunk) in &chunks {
+            assert_eq!(route.key, key);
+            assert_eq!(headers.get("x-team"), Some(&b"core"[..]));
+            assert!(record_size(route, headers, chunk.len()) <= RECORD_OVERHEAD + 200);
+        }
+
+        chunks.reverse();
+        let mut reassembler = Reassembler::new(10, 100);
+        let (_, first_headers, first) = chunks[0].clone();
+        let mut whole = None;
+        for (_, headers, chunk) in chunks {
+            assert!(whole.is_none());
+            whole = reassembler.push(&headers, chunk).unwrap();
+        }
+        assert_eq!(whole, Some(payload));
+        assert_eq!(reassembler.pending(), 0);
+
+        // A redelivered chunk starts a record that never completes
+        assert_eq!(reassembler.push(&first_headers, first).unwrap(), None);
+        assert_eq!(reassembler.pending(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_records_that_fit_are_produced_unchanged() {
+        let chunker = Chunker::new(Oversize::Fail, 1_000_000);
+        let fitted = chunker.fit("out", Route::default(), MessageHeaders::new(), b"{}".to_vec()).await.unwrap();
+        assert_eq!(fitted, vec![(Route::default(), MessageHeaders::new(), b"{}".to_vec())]);
+
+        let oversized = vec![0; 1_000_000];
+        assert!(chunker.fit("out", Route::default(), MessageHeaders::new(), oversized).await.is_err());
+        assert_eq!(Reassembler::new(1, 1).push(&MessageHeaders::new(), b"{}".to_vec()), Ok(Some(b"{}".to_vec())));
+    }
+
+    #[test]
+    fn test_chunk_counts_past_the_limit_are_rejected() {
+        let mut headers = MessageHeaders::new();
+        headers.insert(HEADER_CHUNK_ID, "hostile");
+        headers.insert(HEADER_CHUNK_INDEX, "0");
+        headers.insert(HEADER_CHUNK_COUNT, "1000000000000000");
+
+        let mut reassembler = Reassembler::new(10, 64);
+        assert!(reassembler.push(&headers, vec![0]).is_err());
+        assert_eq!(reassembler.pending(), 0);
+
+        headers.insert(HEADER_CHUNK_COUNT, "64");
+        assert_eq!(reassembler.push(&headers, vec![0]), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_offloaded_records_resolve_to_the_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = dir.path().display().to_string();
+        let chunker = Chunker::new(Oversize::Offload { location }, 1024);
+        let payload = vec![7; 4096];
+
+        let mut fitted = chunker.fit("out", Route::default(), MessageHeaders::new(), payload.clone()).await.unwrap();
+        let (_, headers, pointer) = fitted.pop().unwrap();
+        assert!(fitted.is_empty());
+        let pointer: Value = serde_json::from_slice(&pointer).unwrap();
+        assert_eq!(pointer["bytes"], 4096);
+        assert_eq!(resolve(&headers, Vec::new()).await.unwrap(), payload);
+    }
+
+    #[test]
+    fn test_sink_oversize_option() {
+        assert_eq!(Oversize::from_sink_config(&json!({"topic": "out"})), Ok(Oversize::Fail));
+        assert_eq!(
+            Oversize::from_sink_config(&json!({"oversize": {"strategy": "chunk"}})),
+            Ok(Oversize::Chunk)
+        );
+        assert!(Oversize::from_sink_config(&json!({"oversize": {"strategy": "offload", "location": ""}})).is_err());
+    }
+}
@@ -0,0 +1,663 @@
+//! Columnar execution of pipeline transforms. A batch of messages is
+//! converted into one Arrow `RecordBatch` holding only the fields the
+//! transforms read, filtered with Arrow's comparison kernels and grouped and
+//! aggregated with its `take` and aggregate kernels, instead of walking each
+//! message's JSON.
+
+use anyhow::Result;
+use arrow::array::{Array, ArrayRef, AsArray, BooleanArray, Datum, Float64Array, StringArray, UInt32Array};
+use arrow::compute::kernels::cmp;
+use arrow::compute::{self, filter_record_batch};
+use arrow::datatypes::{DataType, Field, Float64Type, Schema, UInt32Type};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use crate::approx::{DDSketch, HyperLogLog, SpaceSaving};
+use crate::pipeline::TransformSpec;
+use crate::reshape::{Reshape, RESHAPE_KINDS};
+
+/// Transform types columnar pipelines can run, besides the reshaping ones.
+pub const COLUMNAR_TRANSFORM_KINDS: &[&str] = &["filter", "aggregate"];
+
+/// Column holding each row's index into the input batch.
+const ROW_COLUMN: &str = "row";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+/// `filter` config: keeps messages whose `field` (a dotted path into the
+/// processed message) compares to `value` as `op` says. Numeric values
+/// compare numerically, anything else as text. Messages without the field
+/// are dropped.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FilterSpec {
+    pub field: String,
+    pub op: CompareOp,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateFunction {
+    /// Messages in the group; takes no field.
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    /// Approximate number of distinct values of the field (HyperLogLog).
+    DistinctCount,
+    /// The `k` most frequent values of the field with their approximate
+    /// counts (space-saving), as `[{"value": .., "count": ..}]`.
+    TopK,
+    /// The value at `quantile` of the field (DDSketch).
+    Quantile,
+    /// A DDSketch of the field, to store and compute any quantile from
+    /// later. Fields that already hold sketches, such as those of other
+    /// workers or shorter windows, are merged into it.
+    Sketch,
+}
+
+impl AggregateFunction {
+    fn name(self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::DistinctCount => "distinct_count",
+            AggregateFunction::TopK => "top_k",
+            AggregateFunction::Quantile => "quantile",
+            AggregateFunction::Sketch => "sketch",
+        }
+    }
+
+    /// Whether the function reads its field as text rather than numbers.
+    fn reads_text(self) -> bool {
+        matches!(
+            self,
+            AggregateFunction::DistinctCount
+                | AggregateFunction::TopK
+                | AggregateFunction::Quantile
+                | AggregateFunction::Sketch
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Aggregation {
+    #[serde(default)]
+    pub field: Option<String>,
+    pub function: AggregateFunction,
+    /// Output field; defaults to e.g. `sum_value` or `count`.
+    #[serde(default)]
+    pub alias: Option<String>,
+    /// Values reported by `top_k`; 10 if unset.
+    #[serde(default)]
+    pub k: Option<usize>,
+    /// Quantile computed by `quantile`, from 0 to 1.
+    #[serde(default)]
+    pub quantile: Option<f64>,
+}
+
+impl Aggregation {
+    fn output_name(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return alias.clone();
+        }
+        let function = match (self.function, self.quantile) {
+            // e.g. `p95_latency_ms`
+            (AggregateFunction::Quantile, Some(q)) => format!("p{}", (q * 1e6).round() / 1e4),
+            (function, _) => function.name().to_string(),
+        };
+        match &self.field {
+            Some(field) if self.function != AggregateFunction::Count => format!("{}_{}", function, field),
+            _ => function,
+        }
+    }
+}
+
+/// `aggregate` config: one output row per distinct combination of the
+/// `group_by` fields, holding those fields and each aggregation. Numeric
+/// aggregations skip messages where the field is missing or not a number.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AggregateSpec {
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    pub aggregations: Vec<Aggregation>,
+}
+
+/// The transforms of a columnar pipeline: any number of reshaping
+/// transforms, applied to each message before the batch is built, then any
+/// number of filters, then at most one aggregate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnarPlan {
+    reshapes: Vec<Reshape>,
+    filters: Vec<FilterSpec>,
+    aggregate: Option<AggregateSpec>,
+}
+
+impl ColumnarPlan {
+    /// Fails with `(field, message)` pairs locating every transform that
+    /// columnar mode cannot run.
+    pub fn new(transforms: &[TransformSpec]) -> Result<Self, Vec<(String, String)>> {
+        let mut reshapes = Vec::new();
+        let mut filters = Vec::new();
+        let mut aggregate = None;
+        let mut problems = Vec::new();
+
+        for (i, transform) in transforms.iter().enumerate() {
+            let config = format!("transforms[{}].config", i);
+            if aggregate.is_some() {
+                problems.push((
+                    format!("transforms[{}]", i),
+                    "columnar pipelines must end with their aggregate transform".to_string(),
+                ));
+                continue;
+            }
+            match transform.kind.as_str() {
+                kind if RESHAPE_KINDS.contains(&kind) => {
+                    if !filters.is_empty() {
+                        problems.push((
+                            format!("transforms[{}]", i),
+                            "columnar pipelines must reshape messages before filtering them".to_string(),
+                        ));
+                    }
+                    match Reshape::from_config(kind, &transform.config) {
+                        Ok(reshape) => reshapes.push(reshape),
+                        Err(e) => problems.push((config, format!("invalid {}: {}", kind, e))),
+                    }
+                }
+                "filter" => match serde_json::from_value::<FilterSpec>(transform.config.clone()) {
+                    Ok(filter) => filters.push(filter),
+                    Err(e) => problems.push((config, format!("invalid filter: {}", e))),
+                },
+                "aggregate" => match serde_json::from_value::<AggregateSpec>(transform.config.clone()) {
+                    Ok(spec) => {
+                        if spec.aggregations.is_empty() {
+                            problems.push((
+                                format!("{}.aggregations", config),
+                                "at least one aggregation is required".to_string(),
+                            ));
+                        }
+                        for (j, aggregation) in spec.aggregations.iter().enumerate() {
+                            if aggregation.function != AggregateFunction::Count && aggregation.field.is_none() {
+                                problems.push((
+                                    format!("{}.aggregations[{}].field", config, j),
+                                    format!("{} requires a field", aggregation.function.name()),
+                                ));
+                            }
+                            if aggregation.k == Some(0) {
+                                problems.push((
+                                    format!("{}.aggregations[{}].k", config, j),
+                                    "must be greater than 0".to_string(),
+                                ));
+                            }
+                            let quantile = aggregation.quantile.filter(|q| (0.0..=1.0).contains(q));
+                            if aggregation.function == AggregateFunction::Quantile && quantile.is_none() {
+                                problems.push((
+                                    format!("{}.aggregations[{}].quantile", config, j),
+                                    "quantile requires a quantile between 0 and 1".to_string(),
+                                ));
+                            }
+                        }
+                        aggregate = Some(spec);
+                    }
+                    Err(e) => problems.push((config, format!("invalid aggregate: {}", e))),
+                },
+                other => problems.push((
+                    format!("transforms[{}].type", i),
+                    format!(
+                        "{:?} cannot run in columnar mode; expected one of {}",
+                        other,
+                        [COLUMNAR_TRANSFORM_KINDS, RESHAPE_KINDS].concat().join(", ")
+                    ),
+                )),
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(Self {
+                reshapes,
+                filters,
+                aggregate,
+            })
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Runs the plan over a batch of processed messages. Without an
+    /// aggregate the reshaped messages that pass every filter are returned;
+    /// with one, its output rows. A message that fails to reshape fails the
+    /// batch.
+    pub fn run(&self, messages: &[Value]) -> Result<Vec<Value>> {
+        let reshaped;
+        let messages = if self.reshapes.is_empty() {
+            messages
+        } else {
+            let mut owned = messages.to_vec();
+            for message in &mut owned {
+                for reshape in &self.reshapes {
+                    reshape
+                        .apply(message)
+                        .map_err(|e| anyhow::anyhow!("Failed to reshape message: {}", e))?;
+                }
+            }
+            reshaped = owned;
+            &reshaped[..]
+        };
+        let mut batch = self.record_batch(messages)?;
+        for filter in &self.filters {
+            let mask = compare(&batch, filter)?;
+            batch = filter_record_batch(&batch, &mask).map_err(|e| anyhow::anyhow!("Failed to filter batch: {}", e))?;
+        }
+
+        match &self.aggregate {
+            Some(spec) => aggregate(&batch, spec),
+            None => {
+                let rows = batch.column(0).as_primitive::<UInt32Type>();
+                Ok(rows.values().iter().map(|&row| messages[row as usize].clone()).collect())
+            }
+        }
+    }
+
+    /// The fields read as numbers and as text, each becoming one column.
+    fn columns(&self) -> (BTreeSet<&str>, BTreeSet<&str>) {
+        let mut numeric = BTreeSet::new();
+        let mut text = BTreeSet::new();
+        for filter in &self.filters {
+            if filter.value.is_number() {
+                numeric.insert(filter.field.as_str());
+            } else {
+                text.insert(filter.field.as_str());
+            }
+        }
+        if let Some(spec) = &self.aggregate {
+            text.extend(spec.group_by.iter().map(String::as_str));
+            for aggregation in &spec.aggregations {
+                match (aggregation.function, aggregation.field.as_deref()) {
+                    (AggregateFunction::Count, _) | (_, None) => {}
+                    (function, Some(field)) if function.reads_text() => {
+                        text.insert(field);
+                    }
+                    (_, Some(field)) => {
+                        numeric.insert(field);
+                    }
+                }
+            }
+        }
+        (numeric, text)
+    }
+
+    fn record_batch(&self, messages: &[Value]) -> Result<RecordBatch> {
+        let (numeric, text) = self.columns();
+        let mut fields = vec![Field::new(ROW_COLUMN, DataType::UInt32, false)];
+        let mut arrays: Vec<ArrayRef> = vec![Arc::new(UInt32Array::from_iter_values(0..messages.len() as u32))];
+
+        for name in numeric {
+            fields.push(Field::new(numeric_column(name), DataType::Float64, true));
+            let values = messages.iter().map(|m| lookup(m, name).and_then(Value::as_f64));
+            arrays.push(Arc::new(Float64Array::from_iter(values)));
+        }
+        for name in text {
+            fields.push(Field::new(text_column(name), DataType::Utf8, true));
+            let values = messages.iter().map(|m| lookup(m, name).and_then(as_text));
+            arrays.push(Arc::new(StringArray::from_iter(values)));
+        }
+
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+            .map_err(|e| anyhow::anyhow!("Failed to build record batch: {}", e))
+    }
+}
+
+fn numeric_column(field: &str) -> String {
+    format!("f64:{}", field)
+}
+
+fn text_column(field: &str) -> String {
+    format!("utf8:{}", field)
+}
+
+/// Follows a dotted path such as `labels.service` into a message.
+pub(crate) fn lookup<'a>(message: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(message, |value, key| value.get(key))
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a ArrayRef> {
+    batch
+        .column_by_name(name)
+        .ok_or_else(|| anyhow::anyhow!("Column {} missing from batch", name))
+}
+
+fn compare(batch: &RecordBatch, filter: &FilterSpec) -> Result<BooleanArray> {
+    let compared = match filter.value.as_f64() {
+        Some(value) => {
+            let values = column(batch, &numeric_column(&filter.field))?;
+            apply(filter.op, values, &Float64Array::new_scalar(value))
+        }
+        None => {
+            let value = as_text(&filter.value).unwrap_or_default();
+            let values = column(batch, &text_column(&filter.field))?;
+            apply(filter.op, values, &StringArray::new_scalar(value))
+        }
+    };
+    compared.map_err(|e| anyhow::anyhow!("Failed to compare {}: {}", filter.field, e))
+}
+
+fn apply(op: CompareOp, left: &dyn Datum, right: &dyn Datum) -> Result<BooleanArray, ArrowError> {
+    match op {
+        CompareOp::Eq => cmp::eq(left, right),
+        CompareOp::Ne => cmp::neq(left, right),
+        CompareOp::Gt => cmp::gt(left, right),
+        CompareOp::Gte => cmp::gt_eq(left, right),
+        CompareOp::Lt => cmp::lt(left, right),
+        CompareOp::Lte => cmp::lt_eq(left, right),
+    }
+}
+
+fn aggregate(batch: &RecordBatch, spec: &AggregateSpec) -> Result<Vec<Value>> {
+    let keys = spec
+        .group_by
+        .iter()
+        .map(|field| Ok(column(batch, &text_column(field))?.as_string::<i32>()))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Groups in order of first appearance, so output is deterministic
+    let mut groups: Vec<(Vec<Option<&str>>, Vec<u32>)> = Vec::new();
+    let mut index: HashMap<Vec<Option<&str>>, usize> = HashMap::new();
+    for row in 0..batch.num_rows() {
+        let key: Vec<Option<&str>> = keys.iter().map(|k| k.is_valid(row).then(|| k.value(row))).collect();
+        let group = *index.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[group].1.push(row as u32);
+    }
+
+    let mut output = Vec::with_capacity(groups.len());
+    for (key, rows) in groups {
+        let indices = UInt32Array::from(rows);
+        let mut fields = Map::new();
+        for (field, value) in spec.group_by.iter().zip(key) {
+            fields.insert(field.clone(), value.map_or(Value::Null, Value::from));
+        }
+        for aggregation in &spec.aggregations {
+            let value = match (aggregation.function, &aggregation.field) {
+                (AggregateFunction::Count, _) => Value::from(indices.len()),
+                (AggregateFunction::DistinctCount, Some(field)) => {
+                    let values = column(batch, &text_column(field))?.as_string::<i32>();
+                    let mut distinct = HyperLogLog::default();
+                    for value in text_values(values, &indices) {
+                        distinct.insert(value);
+                    }
+                    Value::from(distinct.estimate().round() as u64)
+                }
+                (AggregateFunction::TopK, Some(field)) => {
+                    let values = column(batch, &text_column(field))?.as_string::<i32>();
+                    let k = aggregation.k.unwrap_or(10);
+                    let mut top = SpaceSaving::for_top(k);
+                    for value in text_values(values, &indices) {
+                        top.insert(value, 1);
+                    }
+                    top.top(k)
+                        .into_iter()
+                        .map(|(value, counter)| serde_json::json!({"value": value, "count": counter.count}))
+                        .collect()
+                }
+                (AggregateFunction::Quantile, Some(field)) => {
+                    let values = column(batch, &text_column(field))?.as_string::<i32>();
+                    let sketch = sketch(text_values(values, &indices));
+                    number(aggregation.quantile.and_then(|q| sketch.quantile(q)))
+                }
+                (AggregateFunction::Sketch, Some(field)) => {
+                    let values = column(batch, &text_column(field))?.as_string::<i32>();
+                    serde_json::to_value(sketch(text_values(values, &indices)))
+                        .map_err(|e| anyhow::anyhow!("Failed to encode sketch of {}: {}", field, e))?
+                }
+                (function, Some(field)) => {
+                    let values = column(batch, &numeric_column(field))?;
+                    let taken = compute::take(values.as_ref(), &indices, None)
+                        .map_err(|e| anyhow::anyhow!("Failed to gather {}: {}", field, e))?;
+                    number(reduce(function, taken.as_primitive::<Float64Type>()))
+                }
+                (_, None) => Value::Null,
+            };
+            fields.insert(aggregation.output_name(), value);
+        }
+        output.push(Value::Object(fields));
+    }
+    Ok(output)
+}
+
+fn text_values<'a>(values: &'a StringArray, rows: &'a UInt32Array) -> impl Iterator<Item = &'a str> + 'a {
+    rows.values()
+        .iter()
+        .map(|&row| row as usize)
+        .filter(move |&row| values.is_valid(row))
+        .map(move |row| values.value(row))
+}
+
+/// Sketch of numbers and merged sketches, skipping anything else.
+fn sketch<'a>(values: impl Iterator<Item = &'a str>) -> DDSketch {
+    let mut sketch = DDSketch::default();
+    for value in values {
+        if let Ok(number) = value.parse::<f64>() {
+            sketch.insert(number);
+        } else if let Ok(other) = serde_json::from_str::<DDSketch>(value) {
+            let _ = sketch.merge(&other);
+        }
+    }
+    sketch
+}
+
+fn reduce(function: AggregateFunction, values: &Float64Array) -> Option<f64> {
+    match function {
+        AggregateFunction::Count => Some((values.len() - values.null_count()) as f64),
+        AggregateFunction::Sum => compute::sum(values),
+        AggregateFunction::Min => compute::min(values),
+        AggregateFunction::Max => compute::max(values),
+        AggregateFunction::Avg => {
+            let count = values.len() - values.null_count();
+            compute::sum(values).map(|sum| sum / count as f64)
+        }
+        // Read as text, in `aggregate`
+        AggregateFunction::DistinctCount
+        | AggregateFunction::TopK
+        | AggregateFunction::Quantile
+        | AggregateFunction::Sketch => None,
+    }
+}
+
+fn number(value: Option<f64>) -> Value {
+    value
+        .and_then(serde_json::Number::from_f64)
+        .map_or(Value::Null, Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn transform(kind: &str, config: Value) -> TransformSpec {
+        TransformSpec {
+            name: kind.to_string(),
+            kind: kind.to_string(),
+            config,
+            inputs: Vec::new(),
+            buffer: None,
+        }
+    }
+
+    #[test]
+    fn test_filter_then_aggregate() {
+        let plan = ColumnarPlan::new(&[
+            transform("filter", json!({"field": "name", "op": "eq", "value": "cpu_usage"})),
+            transform("filter", json!({"field": "value", "op": "gte", "value": 10})),
+            transform(
+                "aggregate",
+                json!({
+                    "group_by": ["labels.service"],
+                    "aggregations": [
+                        {"function": "count"},
+                        {"field": "value", "function": "avg"},
+                        {"field": "value", "function": "max", "alias": "peak"},
+                    ],
+                }),
+            ),
+        ])
+        .unwrap();
+
+        let messages = vec![
+            json!({"name": "cpu_usage", "value": 10.0, "labels": {"service": "api"}}),
+            json!({"name": "cpu_usage", "value": 30.0, "labels": {"service": "api"}}),
+            json!({"name": "cpu_usage", "value": 5.0, "labels": {"service": "api"}}),
+            json!({"name": "cpu_usage", "value": 50.0, "labels": {"service": "db"}}),
+            json!({"name": "cpu_usage", "value": "high", "labels": {"service": "db"}}),
+            json!({"name": "memory", "value": 99.0, "labels": {"service": "api"}}),
+        ];
+        assert_eq!(
+            plan.run(&messages).unwrap(),
+            vec![
+                json!({"labels.service": "api", "count": 2, "avg_value": 20.0, "peak": 30.0}),
+                json!({"labels.service": "db", "count": 1, "avg_value": 50.0, "peak": 50.0}),
+            ]
+        );
+
+        let filter_only =
+            ColumnarPlan::new(&[transform("filter", json!({"field": "value", "op": "lt", "value": 10}))]).unwrap();
+        assert_eq!(filter_only.run(&messages).unwrap(), vec![messages[2].clone()]);
+
+        let problems = ColumnarPlan::new(&[
+            transform("aggregate", json!({"aggregations": [{"function": "sum"}]})),
+            transform("map", json!({})),
+        ])
+        .unwrap_err();
+        let fields: Vec<&str> = problems.iter().map(|(field, _)| field.as_str()).collect();
+        assert_eq!(fields, vec!["transforms[0].config.aggregations[0].field", "transforms[1]"]);
+    }
+
+    #[test]
+    fn test_reshaping_before_filters() {
+        let plan = ColumnarPlan::new(&[
+            transform("rename", json!({"fields": {"attrs.svc": "service"}})),
+            transform("coerce", json!({"fields": {"latency": "float"}})),
+            transform("filter", json!({"field": "latency", "op": "gt", "value": 100})),
+        ])
+        .unwrap();
+        let messages = vec![
+            json!({"latency": "250", "attrs": {"svc": "api"}}),
+            json!({"latency": "20", "attrs": {"svc": "db"}}),
+        ];
+        assert_eq!(
+            plan.run(&messages).unwrap(),
+            vec![json!({"latency": 250.0, "service": "api", "attrs": {}})]
+        );
+        assert!(plan.run(&[json!({"latency": "slow"})]).is_err());
+
+        let problems = ColumnarPlan::new(&[
+            transform("filter", json!({"field": "latency", "op": "gt", "value": 100})),
+            transform("defaults", json!({"fields": {"service": "unknown"}})),
+        ])
+        .unwrap_err();
+        assert_eq!(problems[0].0, "transforms[1]");
+    }
+
+    #[test]
+    fn test_approximate_aggregations() {
+        let plan = ColumnarPlan::new(&[transform(
+            "aggregate",
+            json!({
+                "group_by": ["service"],
+                "aggregations": [
+                    {"field": "user", "function": "distinct_count"},
+                    {"field": "endpoint", "function": "top_k", "k": 2, "alias": "top_endpoints"},
+                ],
+            }),
+        )])
+        .unwrap();
+
+        let visits = [("u1", "/orders"), ("u2", "/orders"), ("u1", "/login"), ("u3", "/orders"), ("u2", "/cart")];
+        let messages: Vec<Value> = visits
+            .iter()
+            .map(|(user, endpoint)| json!({"service": "api", "user": user, "endpoint": endpoint}))
+            .chain([json!({"service": "api", "user": 7})])
+            .collect();
+        assert_eq!(
+            plan.run(&messages).unwrap(),
+            vec![json!({
+                "service": "api",
+                "distinct_count_user": 4,
+                "top_endpoints": [{"value": "/orders", "count": 3}, {"value": "/cart", "count": 1}],
+            })]
+        );
+
+        let problems = ColumnarPlan::new(&[transform(
+            "aggregate",
+            json!({"aggregations": [{"field": "endpoint", "function": "top_k", "k": 0}]}),
+        )])
+        .unwrap_err();
+        assert_eq!(problems[0].0, "transforms[0].config.aggregations[0].k");
+    }
+
+    #[test]
+    fn test_sketches_are_stored_and_merged() {
+        let window = ColumnarPlan::new(&[transform(
+            "aggregate",
+            json!({
+                "group_by": ["worker"],
+                "aggregations": [
+                    {"field": "latency_ms", "function": "quantile", "quantile": 0.5},
+                    {"field": "latency_ms", "function": "sketch"},
+                ],
+            }),
+        )])
+        .unwrap();
+        let messages: Vec<Value> = (1..=200)
+            .map(|i| json!({"worker": if i % 2 == 0 { "a" } else { "b" }, "latency_ms": i}))
+            .collect();
+        let partials = window.run(&messages).unwrap();
+        assert_eq!(partials.len(), 2);
+        assert!(partials[0]["p50_latency_ms"].as_f64().is_some());
+
+        // A later query merges the workers' sketches for any quantile
+        let rollup = ColumnarPlan::new(&[transform(
+            "aggregate",
+            json!({"aggregations": [
+                {"field": "sketch_latency_ms", "function": "quantile", "quantile": 0.9, "alias": "p90"},
+            ]}),
+        )])
+        .unwrap();
+        let p90 = rollup.run(&partials).unwrap()[0]["p90"].as_f64().unwrap();
+        assert!((p90 - 180.0).abs() <= 180.0 * crate::approx::DEFAULT_RELATIVE_ACCURACY, "p90 {}", p90);
+
+        let problems = ColumnarPlan::new(&[transform(
+            "aggregate",
+            json!({"aggregations": [{"field": "latency_ms", "function": "quantile", "quantile": 95}]}),
+        )])
+        .unwrap_err();
+        assert_eq!(problems[0].0, "transforms[0].config.aggregations[0].quantile");
+    }
+}
@@ -0,0 +1,104 @@
+use rdkafka::config::ClientConfig;
+
+use super::{Config, DeliveryGuarantee, KafkaConfig};
+
+impl KafkaConfig {
+    pub fn effective_acks(&self) -> &str {
+        self.acks.as_deref().unwrap_or("all")
+    }
+
+    pub fn effective_idempotence(&self) -> bool {
+        self.enable_idempotence
+            .unwrap_or(self.delivery == DeliveryGuarantee::ExactlyOnce)
+    }
+
+    /// Five is the most the broker keeps in order for an idempotent producer.
+    pub fn effective_max_in_flight(&self) -> u32 {
+        self.max_in_flight.unwrap_or(5)
+    }
+}
+
+impl Config {
+    /// rdkafka settings for the pipeline's consumers.
+    pub fn kafka_consumer_config(&self) -> ClientConfig {
+        let kafka = &self.kafka;
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &kafka.bootstrap_servers)
+            .set("group.id", &kafka.group_id)
+            .set("auto.offset.reset", &kafka.auto_offset_reset)
+            .set("enable.auto.commit", kafka.enable_auto_commit.to_string())
+            .set("session.timeout.ms", kafka.session_timeout_ms.to_string())
+            .set("heartbeat.interval.ms", kafka.heartbeat_interval_ms.to_string())
+            .set("fetch.wait.max.ms", kafka.fetch_max_wait_ms.to_string())
+            .set("fetch.min.bytes", kafka.fetch_min_bytes.to_string())
+            .set("fetch.max.bytes", kafka.fetch_max_bytes.to_string());
+        if let Some(id) = &kafka.group_instance_id {
+            config.set("group.instance.id", id);
+        }
+        if !kafka.partition_assignment_strategy.is_empty() {
+            let strategies: Vec<_> = kafka.partition_assignment_strategy.iter().map(|s| s.as_str()).collect();
+            config.set("partition.assignment.strategy", strategies.join(","));
+        }
+        config
+    }
+
+    /// rdkafka settings for producers. With `exactly_once` the producer is
+    /// transactional and has to be initialised with `init_transactions`
+    /// before its first send.
+    pub fn kafka_producer_config(&self) -> ClientConfig {
+        let kafka = &self.kafka;
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &kafka.bootstrap_servers)
+            .set("acks", kafka.effective_acks())
+            .set("enable.idempotence", kafka.effective_idempotence().to_string())
+            .set(
+                "max.in.flight.requests.per.connection",
+                kafka.effective_max_in_flight().to_string(),
+            )
+            .set("linger.ms", kafka.linger_ms.to_string())
+            .set("compression.type", &kafka.compression_type)
+            .set("message.max.bytes", kafka.max_message_bytes.to_string());
+        if let Some(id) = &kafka.transactional_id {
+            config.set("transactional.id", id);
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AssignmentStrategy;
+
+    #[test]
+    fn test_consumer_config_sets_membership_and_assignor() {
+        let mut config = Config::default();
+        assert_eq!(config.kafka_consumer_config().get("group.instance.id"), None);
+
+        config.kafka.group_instance_id = Some("stream-processor-0".to_string());
+        config.kafka.partition_assignment_strategy = vec![AssignmentStrategy::CooperativeSticky];
+        let consumer = config.kafka_consumer_config();
+        assert_eq!(consumer.get("group.instance.id"), Some("stream-processor-0"));
+        assert_eq!(consumer.get("partition.assignment.strategy"), Some("cooperative-sticky"));
+    }
+
+    #[test]
+    fn test_producer_config_follows_delivery_preset() {
+        let mut config = Config::default();
+        let producer = config.kafka_producer_config();
+        assert_eq!(producer.get("acks"), Some("all"));
+        assert_eq!(producer.get("enable.idempotence"), Some("false"));
+        assert_eq!(producer.get("transactional.id"), None);
+
+        config.kafka.delivery = DeliveryGuarantee::ExactlyOnce;
+        config.kafka.transactional_id = Some("stream-processor-0".to_string());
+        config.kafka.max_in_flight = Some(1);
+        let producer = config.kafka_producer_config();
+        assert_eq!(producer.get("enable.idempotence"), Some("true"));
+        assert_eq!(producer.get("max.in.flight.requests.per.connection"), Some("1"));
+        assert_eq!(producer.get("transactional.id"), Some("stream-processor-0"));
+        assert_eq!(producer.get("compression.type"), Some("lz4"));
+    }
+}
@@ -0,0 +1,201 @@
+use super::Config;
+use anyhow::Result;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+/// Settings a running processor picks up when the config file changes.
+/// Everything else is read once at startup.
+pub const RELOADABLE_FIELDS: &[&str] = &[
+    "processing.batch_size",
+    "processing.batch_timeout",
+    "processing.retry_attempts",
+    "processing.retry_delay",
+    "processing.poison",
+    "processing.transform_timeout",
+    "processing.on_transform_timeout",
+    "telemetry.log_level",
+];
+
+/// Editors and config-map updates touch the file several times per save.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Field paths that differ between two configurations.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ConfigDiff {
+    /// Changed fields that take effect immediately.
+    pub applied: Vec<String>,
+    /// Changed fields that only take effect after a restart.
+    pub restart_required: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.restart_required.is_empty()
+    }
+}
+
+/// Compares two configurations field by field.
+pub fn diff(old: &Config, new: &Config) -> Result<ConfigDiff> {
+    let old = leaves(old)?;
+    let new = leaves(new)?;
+
+    let mut applied = BTreeSet::new();
+    let mut restart_required = BTreeSet::new();
+    for path in old.keys().chain(new.keys()) {
+        if old.get(path) == new.get(path) {
+            continue;
+        }
+        match reloadable_field(path) {
+            Some(field) => applied.insert(field.to_string()),
+            None => restart_required.insert(path.clone()),
+        };
+    }
+
+    Ok(ConfigDiff {
+        applied: applied.into_iter().collect(),
+        restart_required: restart_required.into_iter().collect(),
+    })
+}
+
+/// Maps a leaf path such as `processing.batch_timeout.secs` to the
+/// reloadable field it belongs to.
+fn reloadable_field(path: &str) -> Option<&'static str> {
+    RELOADABLE_FIELDS.iter().copied().find(|field| {
+        path.strip_prefix(field)
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+fn leaves(config: &Config) -> Result<BTreeMap<String, Value>> {
+    fn flatten(prefix: String, value: Value, out: &mut BTreeMap<String, Value>) {
+        match value {
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    let path = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+                    flatten(path, value, out);
+                }
+            }
+            leaf => {
+                out.insert(prefix, leaf);
+            }
+        }
+    }
+
+    let value = serde_json::to_value(config)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize configuration: {}", e))?;
+    let mut out = BTreeMap::new();
+    flatten(String::new(), value, &mut out);
+    Ok(out)
+}
+
+/// Watches `path` and its `profile` overlay and calls `apply` with the reloaded configuration whenever
+/// a change touches one of [`RELOADABLE_FIELDS`]. Changes to other fields are
+/// logged as requiring a restart; a file that fails to load is ignored and
+/// the running configuration is kept.
+pub async fn watch<F>(path: String, profile: Option<String>, mut current: Config, mut apply: F) -> Result<()>
+where
+    F: FnMut(&Config, &ConfigDiff) + Send,
+{
+    let file = PathBuf::from(&path);
+    let file_names: Vec<_> = super::source::layers(&file, profile.as_deref())
+        .iter()
+        .filter_map(|layer| layer.file_name().map(|name| name.to_os_string()))
+        .collect();
+    let directory = file
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
+        .to_path_buf();
+
+    let (tx, mut rx) = mpsc::channel(16);
+    let mut watcher = RecommendedWatcher::new(
+        move |event: notify::Result<Event>| {
+            let _ = tx.blocking_send(event);
+        },
+        notify::Config::default(),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to create config watcher: {}", e))?;
+
+    // Watch the directory rather than the file: editors and Kubernetes
+    // config maps replace the file instead of writing to it in place.
+    watcher
+        .watch(&directory, RecursiveMode::NonRecursive)
+        .map_err(|e| anyhow::anyhow!("Failed to watch {}: {}", directory.display(), e))?;
+    info!("Watching {} for configuration changes", path);
+
+    while let Some(event) = rx.recv().await {
+        match event {
+            Ok(event)
+                if event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name().map_or(false, |name| file_names.iter().any(|f| f == name))) => {}
+            Ok(_) => continue,
+            Err(e) => {
+                warn!("Config watcher error: {}", e);
+                continue;
+            }
+        }
+
+        tokio::time::sleep(DEBOUNCE).await;
+        while rx.try_recv().is_ok() {}
+
+        let reloaded = match Config::load(&path, profile.as_deref()) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Ignoring invalid configuration in {}: {}", path, e);
+                continue;
+            }
+        };
+
+        let changes = diff(&current, &reloaded)?;
+        if changes.is_empty() {
+            continue;
+        }
+        if !changes.restart_required.is_empty() {
+            warn!(
+                "Configuration changes require a restart and were not applied: {}",
+                changes.restart_required.join(", ")
+            );
+        }
+        if !changes.applied.is_empty() {
+            info!("Applying configuration changes: {}", changes.applied.join(", "));
+            apply(&reloaded, &changes);
+        }
+        current = reloaded;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_separates_reloadable_fields() {
+        let old = Config::default();
+        let mut new = Config::default();
+        new.processing.batch_size += 1;
+        new.processing.batch_timeout += Duration::from_millis(250);
+        new.telemetry.log_level = Some("debug".to_string());
+        new.kafka.group_id = "another-group".to_string();
+
+        let changes = diff(&old, &new).unwrap();
+        assert_eq!(
+            changes.applied,
+            vec![
+                "processing.batch_size".to_string(),
+                "processing.batch_timeout".to_string(),
+                "telemetry.log_level".to_string(),
+            ]
+        );
+        assert_eq!(changes.restart_required, vec!["kafka.group_id".to_string()]);
+        assert!(diff(&old, &Config::default()).unwrap().is_empty());
+    }
+}
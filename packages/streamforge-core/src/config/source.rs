@@ -0,0 +1,208 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Environment variable selecting the profile when `--profile` is not given.
+pub const PROFILE_ENV: &str = "APP_ENV";
+
+/// Suffix marking a key whose value is read from the named file, e.g.
+/// `database.password_file = "/run/secrets/db_password"` sets
+/// `database.password`.
+const FILE_SUFFIX: &str = "_file";
+
+/// The profile named on the command line, else the one in [`PROFILE_ENV`].
+pub fn active_profile(explicit: Option<&str>) -> Option<String> {
+    explicit
+        .map(str::to_string)
+        .or_else(|| std::env::var(PROFILE_ENV).ok())
+        .filter(|profile| !profile.is_empty())
+}
+
+/// The `--profile` option of every binary, flattened into its arguments.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ProfileArgs {
+    /// Configuration profile overlaid on the base file (defaults to APP_ENV)
+    #[arg(short, long)]
+    pub profile: Option<String>,
+}
+
+impl ProfileArgs {
+    pub fn active(&self) -> Option<String> {
+        active_profile(self.profile.as_deref())
+    }
+}
+
+/// Config files making up `path` under `profile`, lowest precedence first:
+/// the base file, then the `<stem>.<profile>.<ext>` overlay next to it, so
+/// `config/config.toml` with profile `prod` adds `config/config.prod.toml`.
+pub fn layers(path: &Path, profile: Option<&str>) -> Vec<PathBuf> {
+    let mut layers = vec![path.to_path_buf()];
+    if let Some(profile) = profile {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(ext) => format!("{}.{}.{}", stem, profile, ext.to_string_lossy()),
+            None => format!("{}.{}", stem, profile),
+        };
+        layers.push(path.with_file_name(name));
+    }
+    layers
+}
+
+/// Reads one config file with `${VAR}` references expanded, picking the
+/// format from its extension.
+pub fn file(path: &Path) -> Result<::config::File<::config::FileSourceString, ::config::FileFormat>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read config file {}: {}", path.display(), e))?;
+    let text = interpolate(&text, |name| std::env::var(name).ok())
+        .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    let format = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => ::config::FileFormat::Yaml,
+        Some("json") => ::config::FileFormat::Json,
+        _ => ::config::FileFormat::Toml,
+    };
+    Ok(::config::File::from_str(&text, format))
+}
+
+/// Expands `${VAR}` and `${VAR:-default}` references in raw config text.
+/// `$${` produces a literal `${`. Every unset variable without a default is
+/// reported in one error.
+pub fn interpolate(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut missing = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start..];
+
+        if let Some(escaped) = after.strip_prefix("$${") {
+            out.push_str("${");
+            rest = escaped;
+            continue;
+        }
+
+        let Some(body) = after.strip_prefix("${") else {
+            out.push('$');
+            rest = &after[1..];
+            continue;
+        };
+        let Some(end) = body.find('}') else {
+            return Err(anyhow::anyhow!("Unterminated ${{...}} in configuration"));
+        };
+
+        let expression = &body[..end];
+        let (name, default) = match expression.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expression, None),
+        };
+        match lookup(name).or_else(|| default.map(str::to_string)) {
+            Some(value) => out.push_str(&value),
+            None => missing.push(name.to_string()),
+        }
+        rest = &body[end + 1..];
+    }
+    out.push_str(rest);
+
+    if !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Configuration references unset environment variables: {}",
+            missing.join(", ")
+        ));
+    }
+    Ok(out)
+}
+
+/// Replaces every `<key>_file` entry with `<key>` set to the file's contents,
+/// trailing newline removed, so secrets mounted by Docker or Kubernetes never
+/// have to be inlined. Setting both `<key>` and `<key>_file` is an error.
+pub fn resolve_secret_files(value: &mut Value) -> Result<()> {
+    resolve(value, "")
+}
+
+fn resolve(value: &mut Value, path: &str) -> Result<()> {
+    let Value::Object(fields) = value else { return Ok(()) };
+
+    let secret_keys: Vec<String> = fields
+        .iter()
+        .filter(|(key, value)| key.ends_with(FILE_SUFFIX) && value.is_string())
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    for file_key in secret_keys {
+        let key = file_key.trim_end_matches(FILE_SUFFIX).to_string();
+        let field_path = join(path, &key);
+        if fields.contains_key(&key) {
+            return Err(anyhow::anyhow!(
+                "Both {} and {} are set; use only one",
+                field_path,
+                join(path, &file_key)
+            ));
+        }
+
+        let Some(Value::String(file)) = fields.remove(&file_key) else { continue };
+        let contents = std::fs::read_to_string(&file)
+            .map_err(|e| anyhow::anyhow!("Failed to read {} from {}: {}", field_path, file, e))?;
+        fields.insert(key, Value::String(contents.trim_end_matches(['\n', '\r']).to_string()));
+    }
+
+    for (key, child) in fields.iter_mut() {
+        resolve(child, &join(path, key))?;
+    }
+    Ok(())
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Write;
+
+    #[test]
+    fn test_layers() {
+        let base = Path::new("config/config.toml");
+        assert_eq!(layers(base, None), vec![PathBuf::from("config/config.toml")]);
+        assert_eq!(
+            layers(base, Some("prod")),
+            vec![PathBuf::from("config/config.toml"), PathBuf::from("config/config.prod.toml")]
+        );
+        assert_eq!(layers(Path::new("settings"), Some("dev"))[1], PathBuf::from("settings.dev"));
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let lookup = |name: &str| (name == "DB_HOST").then(|| "db.internal".to_string());
+
+        assert_eq!(
+            interpolate("url = \"postgres://${DB_HOST}:${DB_PORT:-5432}/app\"", lookup).unwrap(),
+            "url = \"postgres://db.internal:5432/app\""
+        );
+        assert_eq!(interpolate("a = \"$${LITERAL} $5\"", lookup).unwrap(), "a = \"${LITERAL} $5\"");
+
+        let err = interpolate("${MISSING_A} ${MISSING_B}", lookup).unwrap_err().to_string();
+        assert!(err.contains("MISSING_A, MISSING_B"));
+    }
+
+    #[test]
+    fn test_resolve_secret_files() {
+        let mut secret = tempfile::NamedTempFile::new().unwrap();
+        writeln!(secret, "s3cret").unwrap();
+        let file = secret.path().to_string_lossy().to_string();
+
+        let mut value = json!({"database": {"url": "postgres://db", "password_file": file}});
+        resolve_secret_files(&mut value).unwrap();
+        assert_eq!(value["database"]["password"], "s3cret");
+        assert!(value["database"].get("password_file").is_none());
+
+        let mut both = json!({"database": {"password": "inline", "password_file": file}});
+        let err = resolve_secret_files(&mut both).unwrap_err().to_string();
+        assert!(err.contains("database.password"));
+    }
+}
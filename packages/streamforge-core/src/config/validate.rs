@@ -0,0 +1,691 @@
+use super::{
+    AssignmentStrategy, Config, DeliveryGuarantee, LogMetricKind, NotificationChannelKind, StartPosition, StorageBackendKind,
+    TraceExporterKind,
+};
+use anyhow::Result;
+use reqwest::Url;
+
+impl Config {
+    /// Cross-checks settings that deserialize fine on their own but would
+    /// otherwise fail later inside rdkafka or sqlx. Every violation is
+    /// reported in one error, each prefixed with its field path.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Violations::default();
+
+        let kafka = &self.kafka;
+        if kafka.bootstrap_servers.split(',').any(|server| !is_host_port(server.trim())) {
+            errors.push("kafka.bootstrap_servers", "must be a comma-separated list of host:port");
+        }
+        if kafka.group_id.trim().is_empty() {
+            errors.push("kafka.group_id", "must not be empty");
+        }
+        if kafka.topics.is_empty() {
+            errors.push("kafka.topics", "must list at least one topic");
+        }
+        if kafka.heartbeat_interval_ms <= 0 {
+            errors.push("kafka.heartbeat_interval_ms", "must be greater than 0");
+        }
+        if kafka.heartbeat_interval_ms >= kafka.session_timeout_ms {
+            errors.push(
+                "kafka.heartbeat_interval_ms",
+                format!(
+                    "must be lower than kafka.session_timeout_ms ({} >= {})",
+                    kafka.heartbeat_interval_ms, kafka.session_timeout_ms
+                ),
+            );
+        }
+        if !matches!(kafka.auto_offset_reset.as_str(), "earliest" | "latest" | "none") {
+            errors.push("kafka.auto_offset_reset", "must be one of earliest, latest, none");
+        }
+        if kafka.group_instance_id.as_deref().map_or(false, |id| id.trim().is_empty()) {
+            errors.push("kafka.group_instance_id", "must not be empty when set");
+        }
+        let strategies = &kafka.partition_assignment_strategy;
+        if strategies.contains(&AssignmentStrategy::CooperativeSticky) && strategies.len() > 1 {
+            errors.push(
+                "kafka.partition_assignment_strategy",
+                "cooperative-sticky cannot be combined with eager assignors",
+            );
+        }
+        if !matches!(kafka.effective_acks(), "0" | "1" | "all" | "-1") {
+            errors.push("kafka.acks", "must be one of 0, 1, all, -1");
+        }
+        if !matches!(kafka.compression_type.as_str(), "none" | "gzip" | "snappy" | "lz4" | "zstd") {
+            errors.push("kafka.compression_type", "must be one of none, gzip, snappy, lz4, zstd");
+        }
+        if kafka.effective_max_in_flight() == 0 {
+            errors.push("kafka.max_in_flight", "must be greater than 0");
+        }
+        if kafka.effective_idempotence() {
+            if !matches!(kafka.effective_acks(), "all" | "-1") {
+                errors.push("kafka.acks", "must be all when the producer is idempotent");
+            }
+            if kafka.effective_max_in_flight() > 5 {
+                errors.push("kafka.max_in_flight", "must be at most 5 when the producer is idempotent");
+            }
+        }
+        for (topic, start) in &kafka.start_positions {
+            let field = format!("kafka.start_positions.{}", topic);
+            if !kafka.topics.contains(topic) {
+                errors.push(&field, "is not one of kafka.topics");
+            }
+            match &start.position {
+                StartPosition::Offsets { offsets } => {
+                    let valid = |(partition, offset): (&String, &i64)| {
+                        partition.parse::<i32>().map_or(false, |p| p >= 0) && *offset >= 0
+                    };
+                    if !offsets.iter().all(valid) {
+                        errors.push(&format!("{}.offsets", field), "partitions and offsets must not be negative");
+                    }
+                }
+                StartPosition::File { path } if path.trim().is_empty() => {
+                    errors.push(&format!("{}.path", field), "must not be empty");
+                }
+                _ => {}
+            }
+        }
+        for (name, table) in &kafka.tables {
+            let field = format!("kafka.tables.{}.topic", name);
+            if table.topic.trim().is_empty() {
+                errors.push(&field, "must not be empty");
+            } else if kafka.topics.contains(&table.topic) {
+                errors.push(&field, "is also an input topic in kafka.topics");
+            }
+            if table.max_entries == Some(0) {
+                errors.push(&format!("kafka.tables.{}.max_entries", name), "must be greater than 0 when set");
+            }
+        }
+        if kafka.max_message_bytes < 1000 {
+            errors.push("kafka.max_message_bytes", "must be at least 1000");
+        }
+        if let Some(registry) = &kafka.schema_registry {
+            if !has_scheme(&registry.url, &["http", "https"]) {
+                errors.push("kafka.schema_registry.url", "must be an http:// or https:// URL");
+            }
+            if registry.password.is_some() && registry.username.is_none() {
+                errors.push("kafka.schema_registry.username", "is required when a password is set");
+            }
+        }
+        if kafka.delivery == DeliveryGuarantee::ExactlyOnce {
+            if kafka.enable_idempotence == Some(false) {
+                errors.push("kafka.enable_idempotence", "must not be false with exactly_once delivery");
+            }
+            if kafka.transactional_id.as_deref().map_or(true, |id| id.trim().is_empty()) {
+                errors.push("kafka.transactional_id", "is required with exactly_once delivery");
+            }
+        }
+
+        let processing = &self.processing;
+        if processing.batch_size == 0 {
+            errors.push("processing.batch_size", "must be greater than 0");
+        }
+        if processing.max_concurrent_tasks == 0 {
+            errors.push("processing.max_concurrent_tasks", "must be greater than 0");
+        }
+        let limits = &processing.limits;
+        for (field, value) in [
+            ("processing.limits.max_workers", limits.max_workers.map(|v| v as u64)),
+            ("processing.limits.max_memory_bytes", limits.max_memory_bytes),
+            ("processing.limits.max_inflight_bytes", limits.max_inflight_bytes),
+            ("processing.limits.max_messages_per_second", limits.max_messages_per_second.map(u64::from)),
+            ("processing.memory_budget.max_bytes", processing.memory_budget.max_bytes),
+            (
+                "processing.record_limits.max_payload_bytes",
+                processing.record_limits.max_payload_bytes.map(|v| v as u64),
+            ),
+            ("processing.record_limits.max_depth", processing.record_limits.max_depth.map(|v| v as u64)),
+            ("processing.record_limits.max_fields", processing.record_limits.max_fields.map(|v| v as u64)),
+        ] {
+            if value == Some(0) {
+                errors.push(field, "must be greater than 0 when set");
+            }
+        }
+        let budget = &processing.memory_budget;
+        if budget.max_bytes.is_some() {
+            if budget.max_queued_batches == 0 {
+                errors.push("processing.memory_budget.max_queued_batches", "must be greater than 0");
+            }
+            if budget.spill_directory.is_empty() {
+                errors.push("processing.memory_budget.spill_directory", "must be set when max_bytes is set");
+            }
+        }
+        let adaptive = &processing.adaptive_batching;
+        if adaptive.enabled {
+            if adaptive.min_batch_size == 0 {
+                errors.push("processing.adaptive_batching.min_batch_size", "must be greater than 0");
+            }
+            if adaptive.max_batch_size < adaptive.min_batch_size {
+                errors.push("processing.adaptive_batching.max_batch_size", "must be at least min_batch_size");
+            }
+            if adaptive.target_latency.is_zero() {
+                errors.push("processing.adaptive_batching.target_latency", "must be greater than 0");
+            }
+            if adaptive.window == 0 {
+                errors.push("processing.adaptive_batching.window", "must be greater than 0");
+            }
+        }
+        let cardinality = &processing.cardinality;
+        if cardinality.enabled {
+            if cardinality.max_series_per_metric == 0 {
+                errors.push("processing.cardinality.max_series_per_metric", "must be greater than 0");
+            }
+            for (name, limit) in &cardinality.overrides {
+                if *limit == 0 {
+                    errors.push(&format!("processing.cardinality.overrides.{}", name), "must be greater than 0");
+                }
+            }
+            if cardinality.window.is_zero() {
+                errors.push("processing.cardinality.window", "must be greater than 0");
+            }
+        }
+        let patterns = &processing.log_patterns;
+        if patterns.enabled {
+            if !(patterns.similarity_threshold > 0.0 && patterns.similarity_threshold <= 1.0) {
+                errors.push("processing.log_patterns.similarity_threshold", "must be greater than 0 and at most 1");
+            }
+            if patterns.max_templates == 0 {
+                errors.push("processing.log_patterns.max_templates", "must be greater than 0");
+            }
+        }
+        let schemas = &processing.schemas;
+        if schemas.enabled {
+            if schemas.max_depth == 0 {
+                errors.push("processing.schemas.max_depth", "must be greater than 0");
+            }
+            if schemas.max_fields == 0 {
+                errors.push("processing.schemas.max_fields", "must be greater than 0");
+            }
+        }
+        if processing.transform_timeout.map_or(false, |timeout| timeout.is_zero()) {
+            errors.push("processing.transform_timeout", "must be greater than 0 when set");
+        }
+        if processing.offload.enabled && processing.offload.threads == 0 {
+            errors.push("processing.offload.threads", "must be greater than 0");
+        }
+        if processing.poison.max_attempts == 0 {
+            errors.push("processing.poison.max_attempts", "must be greater than 0");
+        }
+        let log_metrics = &processing.log_metrics;
+        if !log_metrics.rules.is_empty() && log_metrics.interval.is_zero() {
+            errors.push("processing.log_metrics.interval", "must be greater than 0");
+        }
+        let mut rule_names = std::collections::HashSet::new();
+        for (i, rule) in log_metrics.rules.iter().enumerate() {
+            let field = format!("processing.log_metrics.rules[{}]", i);
+            if rule.name.is_empty() {
+                errors.push(&format!("{}.name", field), "must not be empty");
+            } else if !rule_names.insert(rule.name.as_str()) {
+                errors.push(&format!("{}.name", field), format!("{} is already the name of another rule", rule.name));
+            }
+            match &rule.kind {
+                LogMetricKind::Count => {}
+                LogMetricKind::Histogram { field: value_field, buckets, .. } => {
+                    if value_field.is_empty() {
+                        errors.push(&format!("{}.field", field), "must not be empty");
+                    }
+                    if buckets.is_empty() || buckets.windows(2).any(|w| w[0] >= w[1]) {
+                        errors.push(&format!("{}.buckets", field), "must be a non-empty, strictly ascending list");
+                    }
+                }
+                LogMetricKind::DistinctCount { field: value_field } => {
+                    if value_field.is_empty() {
+                        errors.push(&format!("{}.field", field), "must not be empty");
+                    }
+                }
+                LogMetricKind::TopK { field: value_field, k, .. } => {
+                    if value_field.is_empty() {
+                        errors.push(&format!("{}.field", field), "must not be empty");
+                    }
+                    if *k == 0 {
+                        errors.push(&format!("{}.k", field), "must be greater than 0");
+                    }
+                }
+            }
+        }
+        let recording = &processing.recording_rules;
+        if !recording.rules.is_empty() && recording.interval.is_zero() {
+            errors.push("processing.recording_rules.interval", "must be greater than 0");
+        }
+        for (i, rule) in recording.rules.iter().enumerate() {
+            let field = format!("processing.recording_rules.rules[{}]", i);
+            if rule.record.is_empty() {
+                errors.push(&format!("{}.record", field), "must not be empty");
+            }
+            if let Err(e) = crate::recording::Expr::parse(&rule.expr) {
+                errors.push(&format!("{}.expr", field), e);
+            }
+        }
+        if kafka.topics.contains(&processing.dead_letter_queue_topic) {
+            errors.push(
+                "processing.dead_letter_queue_topic",
+                format!(
+                    "{} is also an input topic in kafka.topics; failed messages would be consumed again",
+                    processing.dead_letter_queue_topic
+                ),
+            );
+        }
+        if let Some(topic) = &processing.error_topic {
+            if topic.is_empty() {
+                errors.push("processing.error_topic", "must not be empty");
+            } else if kafka.topics.contains(topic) || *topic == processing.dead_letter_queue_topic {
+                errors.push(
+                    "processing.error_topic",
+                    format!("{} is also an input or dead letter topic", topic),
+                );
+            }
+        }
+
+        let database = &self.database;
+        match database.backend {
+            StorageBackendKind::Postgres => {
+                if !has_scheme(&database.url, &["postgres", "postgresql"]) {
+                    errors.push("database.url", "must be a postgres:// or postgresql:// URL");
+                }
+                if let Some(replica_url) = &database.replica_url {
+                    if !has_scheme(replica_url, &["postgres", "postgresql"]) {
+                        errors.push("database.replica_url", "must be a postgres:// or postgresql:// URL");
+                    }
+                }
+            }
+            StorageBackendKind::Sqlite => {
+                if !database.url.starts_with("sqlite:") {
+                    errors.push("database.url", "must start with sqlite:");
+                }
+            }
+            StorageBackendKind::Memory => {}
+        }
+        if database.max_connections == 0 {
+            errors.push("database.max_connections", "must be greater than 0");
+        }
+        if database.write_concurrency == 0 {
+            errors.push("database.write_concurrency", "must be greater than 0");
+        } else if database.write_concurrency > database.max_connections as usize {
+            errors.push("database.write_concurrency", "must not exceed database.max_connections");
+        }
+        if database.min_connections > database.max_connections {
+            errors.push("database.min_connections", "must not exceed database.max_connections");
+        }
+        let lifecycle = &database.lifecycle;
+        if lifecycle.enabled {
+            let s3_prefix = |rest: &str| {
+                rest.split_once('/')
+                    .map_or(false, |(bucket, prefix)| !bucket.is_empty() && !prefix.is_empty())
+            };
+            match lifecycle.destination.strip_prefix("s3://") {
+                _ if lifecycle.destination.is_empty() => {
+                    errors.push("database.lifecycle.destination", "is required when the lifecycle is enabled");
+                }
+                Some(rest) if !s3_prefix(rest) => {
+                    errors.push("database.lifecycle.destination", "must be a local directory or s3://bucket/prefix");
+                }
+                _ => {}
+            }
+            if lifecycle.segment_hours == 0 {
+                errors.push("database.lifecycle.segment_hours", "must be greater than 0");
+            }
+            if lifecycle.interval.is_zero() {
+                errors.push("database.lifecycle.interval", "must be greater than 0");
+            }
+            for (i, rule) in lifecycle.rules.iter().enumerate() {
+                let field = format!("database.lifecycle.rules[{}]", i);
+                if rule.archive_after_days == 0 {
+                    errors.push(&format!("{}.archive_after_days", field), "must be greater than 0");
+                }
+                if lifecycle.rules[..i].iter().any(|other| other.dataset == rule.dataset) {
+                    errors.push(&format!("{}.dataset", field), format!("{} already has a rule", rule.dataset.as_str()));
+                }
+            }
+        }
+        if database.sql.max_rows == 0 {
+            errors.push("database.sql.max_rows", "must be greater than 0");
+        }
+        if database.sql.memory_limit_bytes == 0 {
+            errors.push("database.sql.memory_limit_bytes", "must be greater than 0");
+        }
+        let encryption = &database.encryption;
+        if encryption.enabled {
+            if encryption.key_id.is_empty() || encryption.key_id.contains(':') {
+                errors.push("database.encryption.key_id", "must be non-empty and must not contain ':'");
+            }
+            match &encryption.key {
+                None => errors.push("database.encryption.key", "is required when encryption is enabled"),
+                Some(key) if crate::storage::decode_key(key).is_none() => {
+                    errors.push("database.encryption.key", "must be a base64-encoded 32-byte key");
+                }
+                Some(_) => {}
+            }
+            for (id, key) in &encryption.previous_keys {
+                if id == &encryption.key_id {
+                    errors.push("database.encryption.previous_keys", format!("{} is the current key_id", id));
+                } else if crate::storage::decode_key(key).is_none() {
+                    errors.push(
+                        &format!("database.encryption.previous_keys.{}", id),
+                        "must be a base64-encoded 32-byte key",
+                    );
+                }
+            }
+            if encryption.fields.is_empty() {
+                errors.push("database.encryption.fields", "must not be empty when encryption is enabled");
+            }
+            if encryption.fields.iter().any(|field| field.split('.').any(str::is_empty)) {
+                errors.push("database.encryption.fields", "must be dotted paths without empty segments");
+            }
+        }
+
+        let telemetry = &self.telemetry;
+        match telemetry.exporter {
+            TraceExporterKind::Jaeger if !has_scheme(&telemetry.jaeger_endpoint, &["http", "https"]) => {
+                errors.push("telemetry.jaeger_endpoint", "must be an http:// or https:// URL");
+            }
+            TraceExporterKind::Otlp if !has_scheme(&telemetry.otlp_endpoint, &["http", "https"]) => {
+                errors.push("telemetry.otlp_endpoint", "must be an http:// or https:// URL");
+            }
+            _ => {}
+        }
+        if !(0.0..=1.0).contains(&telemetry.sampling.ratio) {
+            errors.push("telemetry.sampling.ratio", "must be between 0.0 and 1.0");
+        }
+
+        if self.grpc.unix_socket.is_none() && self.grpc.address().is_err() {
+            errors.push("grpc.host", "must be an IP address");
+        }
+        for (field, size) in [
+            ("grpc.max_receive_message_size", self.grpc.max_receive_message_size),
+            ("grpc.max_send_message_size", self.grpc.max_send_message_size),
+        ] {
+            if size == 0 {
+                errors.push(field, "must be greater than 0");
+            }
+        }
+        if self.grpc.stream_credits == 0 {
+            errors.push("grpc.stream_credits", "must be greater than 0");
+        }
+        if self.grpc.stream_ack_batch_size == 0 || self.grpc.stream_ack_batch_size > self.grpc.stream_credits {
+            errors.push("grpc.stream_ack_batch_size", "must be between 1 and grpc.stream_credits");
+        }
+        if self.grpc.stream_ack_interval.is_zero() {
+            errors.push("grpc.stream_ack_interval", "must be greater than 0");
+        }
+
+        let rbac = &self.grpc.rbac;
+        let mut roles = std::collections::HashSet::new();
+        for (i, role) in rbac.roles.iter().enumerate() {
+            if !roles.insert(role.name.as_str()) {
+                errors.push(&format!("grpc.rbac.roles[{}].name", i), format!("{} is defined twice", role.name));
+            }
+            if role.rpcs.is_empty() {
+                errors.push(&format!("grpc.rbac.roles[{}].rpcs", i), "must not be empty");
+            }
+        }
+        let mut keys = std::collections::HashSet::new();
+        for (i, key) in rbac.api_keys.iter().enumerate() {
+            if key.subject.trim().is_empty() {
+                errors.push(&format!("grpc.rbac.api_keys[{}].subject", i), "must not be empty");
+            }
+            if key.api_key.is_empty() {
+                errors.push(&format!("grpc.rbac.api_keys[{}].api_key", i), "must not be empty");
+            } else if !keys.insert(key.api_key.as_str()) {
+                errors.push(&format!("grpc.rbac.api_keys[{}].api_key", i), "is used by another key");
+            }
+        }
+        for (i, binding) in rbac.bindings.iter().enumerate() {
+            if !roles.contains(binding.role.as_str()) {
+                errors.push(&format!("grpc.rbac.bindings[{}].role", i), format!("no role is named {}", binding.role));
+            }
+            if binding.tenants.is_empty() {
+                errors.push(&format!("grpc.rbac.bindings[{}].tenants", i), "must not be empty");
+            }
+        }
+        if rbac.enabled && rbac.api_keys.is_empty() {
+            errors.push("grpc.rbac.api_keys", "must not be empty when rbac is enabled");
+        }
+        if rbac.refresh_interval.is_zero() {
+            errors.push("grpc.rbac.refresh_interval", "must be greater than 0");
+        }
+
+        let coordination = &self.coordination;
+        if coordination.enabled {
+            if coordination.instance_id.trim().is_empty() {
+                errors.push("coordination.instance_id", "must not be empty");
+            }
+            match &coordination.advertise_url {
+                Some(url) if !has_scheme(url, &["http", "https"]) => {
+                    errors.push("coordination.advertise_url", "must be an http:// or https:// URL");
+                }
+                Some(_) => {}
+                None => errors.push("coordination.advertise_url", "is required when coordination is enabled"),
+            }
+            if matches!(self.database.backend, StorageBackendKind::Memory) {
+                errors.push("coordination.enabled", "requires a database shared by every instance, not memory");
+            }
+        }
+        if coordination.renew_interval.is_zero() || coordination.renew_interval >= coordination.lease_ttl {
+            errors.push("coordination.renew_interval", "must be greater than 0 and lower than coordination.lease_ttl");
+        }
+
+        if self.hub.enabled {
+            if self.hub.address().is_err() {
+                errors.push("hub.host", "must be an IP address");
+            }
+            if self.hub.buffer == 0 {
+                errors.push("hub.buffer", "must be greater than 0");
+            }
+        }
+
+        let promql = &self.promql;
+        if promql.enabled {
+            if promql.address().is_err() {
+                errors.push("promql.host", "must be an IP address");
+            }
+            if promql.default_tenant.is_empty() {
+                errors.push("promql.default_tenant", "must not be empty");
+            }
+            if promql.lookback_delta.is_zero() {
+                errors.push("promql.lookback_delta", "must be greater than 0");
+            }
+            if promql.max_samples == 0 {
+                errors.push("promql.max_samples", "must be greater than 0");
+            }
+            if promql.max_points == 0 {
+                errors.push("promql.max_points", "must be greater than 0");
+            }
+        }
+
+        let status = &self.service_status;
+        if status.enabled {
+            if status.window.is_zero() {
+                errors.push("service_status.window", "must be greater than 0");
+            }
+            if status.interval.is_zero() || status.interval > status.window {
+                errors.push("service_status.interval", "must be greater than 0 and at most service_status.window");
+            }
+            if status.degraded_response_time_ms.is_nan() || status.degraded_response_time_ms <= 0.0 {
+                errors.push("service_status.degraded_response_time_ms", "must be greater than 0");
+            }
+        }
+
+        let sessions = &self.sessions;
+        if sessions.enabled {
+            if sessions.key_field.is_empty() {
+                errors.push("sessions.key_field", "must not be empty");
+            }
+            if sessions.gap.is_zero() {
+                errors.push("sessions.gap", "must be greater than 0");
+            }
+            if sessions.max_duration < sessions.gap {
+                errors.push("sessions.max_duration", "must be at least sessions.gap");
+            }
+            if sessions.output_topic.is_empty() {
+                errors.push("sessions.output_topic", "must not be empty");
+            } else if kafka.topics.contains(&sessions.output_topic) {
+                errors.push(
+                    "sessions.output_topic",
+                    format!("{} is also an input topic in kafka.topics", sessions.output_topic),
+                );
+            }
+            if sessions.max_open_sessions == 0 {
+                errors.push("sessions.max_open_sessions", "must be greater than 0");
+            }
+        }
+
+        let notifier = &self.notifier;
+        if notifier.enabled {
+            if notifier.max_attempts == 0 {
+                errors.push("notifier.max_attempts", "must be greater than 0");
+            }
+            if notifier.queue_size == 0 {
+                errors.push("notifier.queue_size", "must be greater than 0");
+            }
+            if notifier.silence_refresh_interval.is_zero() {
+                errors.push("notifier.silence_refresh_interval", "must be greater than 0");
+            }
+            let mut names = std::collections::HashSet::new();
+            for (i, channel) in notifier.channels.iter().enumerate() {
+                let field = |name: &str| format!("notifier.channels[{}].{}", i, name);
+                if channel.name.is_empty() {
+                    errors.push(&field("name"), "must not be empty");
+                } else if !names.insert(channel.name.as_str()) {
+                    errors.push(&field("name"), "is used by another channel");
+                }
+                if channel.rate_limit_per_minute == Some(0) {
+                    errors.push(&field("rate_limit_per_minute"), "must be greater than 0 when set");
+                }
+                match &channel.kind {
+                    NotificationChannelKind::Webhook { url, .. } if !has_scheme(url, &["http", "https"]) => {
+                        errors.push(&field("url"), "must be an http:// or https:// URL");
+                    }
+                    NotificationChannelKind::Slack { webhook_url } if !has_scheme(webhook_url, &["https"]) => {
+                        errors.push(&field("webhook_url"), "must be an https:// URL");
+                    }
+                    NotificationChannelKind::Email { to, .. } if to.is_empty() => {
+                        errors.push(&field("to"), "must list at least one recipient");
+                    }
+                    NotificationChannelKind::PagerDuty { routing_key, .. } if routing_key.is_empty() => {
+                        errors.push(&field("routing_key"), "must not be empty");
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if self.faults.enabled {
+            for (name, rates) in [("storage", &self.faults.storage), ("kafka", &self.faults.kafka)] {
+                let field = |rate: &str| format!("faults.{}.{}", name, rate);
+                let named = [
+                    ("error_rate", rates.error_rate),
+                    ("delay_rate", rates.delay_rate),
+                    ("duplicate_rate", rates.duplicate_rate),
+                ];
+                for (rate, value) in named {
+                    if !(0.0..=1.0).contains(&value) {
+                        errors.push(&field(rate), "must be between 0.0 and 1.0");
+                    }
+                }
+                if rates.error_rate + rates.delay_rate + rates.duplicate_rate > 1.0 {
+                    errors.push(&format!("faults.{}", name), "rates must add up to at most 1.0");
+                }
+            }
+        }
+
+        let metrics = &self.metrics;
+        if let Some(otlp) = &metrics.otlp {
+            if !has_scheme(&otlp.endpoint, &["http", "https"]) {
+                errors.push("metrics.otlp.endpoint", "must be an http:// or https:// URL");
+            }
+        }
+        if let Some(pushgateway) = &metrics.push.pushgateway {
+            if !has_scheme(&pushgateway.url, &["http", "https"]) {
+                errors.push("metrics.push.pushgateway.url", "must be an http:// or https:// URL");
+            }
+        }
+        if let Some(streamforge) = &metrics.push.streamforge {
+            if !has_scheme(&streamforge.api_url, &["http", "https"]) {
+                errors.push("metrics.push.streamforge.api_url", "must be an http:// or https:// URL");
+            }
+        }
+        if let Some(max_consumer_lag) = metrics.readiness.max_consumer_lag {
+            if max_consumer_lag < 0 {
+                errors.push("metrics.readiness.max_consumer_lag", "must not be negative");
+            }
+            if metrics.readiness.lag_interval.is_zero() {
+                errors.push("metrics.readiness.lag_interval", "must be greater than 0");
+            }
+        }
+
+        errors.into_result()
+    }
+}
+
+#[derive(Default)]
+struct Violations(Vec<String>);
+
+impl Violations {
+    fn push(&mut self, field: &str, message: impl std::fmt::Display) {
+        self.0.push(format!("{}: {}", field, message));
+    }
+
+    fn into_result(self) -> Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow::anyhow!("Invalid configuration:\n  - {}", self.0.join("\n  - ")))
+    }
+}
+
+fn has_scheme(url: &str, schemes: &[&str]) -> bool {
+    Url::parse(url).map_or(false, |url| schemes.contains(&url.scheme()) && url.has_host())
+}
+
+fn is_host_port(server: &str) -> bool {
+    match server.rsplit_once(':') {
+        Some((host, port)) => !host.is_empty() && port.parse::<u16>().is_ok(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_reports_every_violation() {
+        assert!(Config::default().validate().is_ok());
+
+        let mut config = Config::default();
+        config.processing.batch_size = 0;
+        config.kafka.heartbeat_interval_ms = config.kafka.session_timeout_ms;
+        config.processing.dead_letter_queue_topic = config.kafka.topics[0].clone();
+        config.processing.error_topic = Some(String::new());
+        config.database.url = "localhost:5432/streamforge".to_string();
+        config.kafka.bootstrap_servers = "localhost".to_string();
+        config.coordination.enabled = true;
+        config.faults.enabled = true;
+        config.faults.kafka.error_rate = 1.5;
+        config.kafka.delivery = DeliveryGuarantee::ExactlyOnce;
+        config.grpc.rbac.enabled = true;
+        config.database.encryption.enabled = true;
+        config.database.lifecycle.enabled = true;
+        config.grpc.stream_ack_interval = std::time::Duration::ZERO;
+        config.database.lifecycle.interval = std::time::Duration::ZERO;
+
+        let err = config.validate().unwrap_err().to_string();
+        for field in [
+            "processing.batch_size",
+            "kafka.heartbeat_interval_ms",
+            "processing.dead_letter_queue_topic",
+            "processing.error_topic",
+            "database.url",
+            "kafka.bootstrap_servers",
+            "coordination.advertise_url",
+            "faults.kafka.error_rate",
+            "kafka.transactional_id",
+            "grpc.rbac.api_keys",
+            "database.encryption.key",
+            "database.lifecycle.destination",
+            "grpc.stream_ack_interval",
+            "database.lifecycle.interval",
+        ] {
+            assert!(err.contains(field), "{} missing from {}", field, err);
+        }
+    }
+}
@@ -0,0 +1,849 @@
+//! Pipelines as a graph of stages. Each transform and sink may name the
+//! stages it reads in `inputs`: a stage read by several others fans out to
+//! all of them, and a stage with several inputs merges them. A spec without
+//! `inputs` is the linear chain it always was, sources into the first
+//! transform, each transform into the next and the last into every sink.
+//!
+//! [`PipelineGraph`] resolves and checks the graph. [`DagPlan`] runs it,
+//! either one batch at a time or as a task per stage joined by bounded
+//! channels, one per edge, so a slow branch holds back what feeds it.
+//! A running graph keeps counters per stage; [`DagMonitor`] reads them as
+//! a [`Topology`] and [`Topologies`] keeps those of every running pipeline
+//! for the admin endpoint.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::columnar::ColumnarPlan;
+use crate::pipeline::PipelineSpec;
+
+/// Batches each input edge of a stage holds before its upstream waits.
+pub const DEFAULT_EDGE_BUFFER: usize = 16;
+
+/// Errors each running stage keeps, newest last.
+pub const ERROR_SAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageKind {
+    Source,
+    Transform,
+    Sink,
+}
+
+impl StageKind {
+    /// The spec's list holding stages of this kind.
+    pub fn field(&self) -> &'static str {
+        match self {
+            StageKind::Source => "sources",
+            StageKind::Transform => "transforms",
+            StageKind::Sink => "sinks",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    pub name: String,
+    pub kind: StageKind,
+    /// Position in the spec's list of stages of its kind.
+    pub index: usize,
+    /// Stages read, as positions in [`PipelineGraph::stages`].
+    pub inputs: Vec<usize>,
+    /// Capacity of each input edge, in batches.
+    pub buffer: usize,
+}
+
+/// The stages of a pipeline and the edges between them, checked to be
+/// acyclic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineGraph {
+    /// Sources, then transforms, then sinks, in spec order.
+    stages: Vec<Stage>,
+    /// Positions in `stages`, every stage after those it reads.
+    order: Vec<usize>,
+    linear: bool,
+}
+
+impl PipelineGraph {
+    /// Fails with `(field, message)` pairs locating inputs that name no
+    /// readable stage and stages that read themselves, directly or not.
+    pub fn new(spec: &PipelineSpec) -> Result<Self, Vec<(String, String)>> {
+        let mut stages = Vec::new();
+        let mut ids = HashMap::new();
+        let names = spec
+            .sources
+            .iter()
+            .map(|s| (StageKind::Source, &s.name, None))
+            .chain(
+                spec.transforms
+                    .iter()
+                    .map(|t| (StageKind::Transform, &t.name, t.buffer)),
+            )
+            .chain(spec.sinks.iter().map(|s| (StageKind::Sink, &s.name, s.buffer)));
+        let mut problems = Vec::new();
+        let mut counts = BTreeMap::new();
+        for (kind, name, buffer) in names {
+            let index = counts.entry(kind.field()).or_insert(0);
+            if buffer == Some(0) {
+                let field = format!("{}[{}].buffer", kind.field(), index);
+                problems.push((field, "must be greater than 0 when set".to_string()));
+            }
+            // Duplicate names are reported by validation; the first one wins
+            ids.entry(name.as_str()).or_insert(stages.len());
+            stages.push(Stage {
+                name: name.clone(),
+                kind,
+                index: *index,
+                inputs: Vec::new(),
+                buffer: buffer.unwrap_or(DEFAULT_EDGE_BUFFER),
+            });
+            *index += 1;
+        }
+
+        let sources: Vec<usize> = (0..spec.sources.len()).collect();
+        let transform = |i: usize| spec.sources.len() + i;
+        let explicit = spec
+            .transforms
+            .iter()
+            .map(|t| &t.inputs)
+            .chain(spec.sinks.iter().map(|s| &s.inputs));
+        for (id, inputs) in (spec.sources.len()..stages.len()).zip(explicit) {
+            let stage = &stages[id];
+            let field = format!("{}[{}].inputs", stage.kind.field(), stage.index);
+            let resolved = if !inputs.is_empty() {
+                let mut resolved = Vec::new();
+                for (j, input) in inputs.iter().enumerate() {
+                    let field = format!("{}[{}]", field, j);
+                    match ids.get(input.as_str()).map(|&input| (input, stages[input].kind)) {
+                        None => problems.push((field, format!("no source or transform is named {}", input))),
+                        Some((_, StageKind::Sink)) => problems.push((field, format!("{} is a sink", input))),
+                        Some((input, _)) if input == id => {
+                            problems.push((field, "a stage cannot read itself".to_string()))
+                        }
+                        Some((input, _)) if resolved.contains(&input) => {
+                            problems.push((field, format!("{} is listed twice", stages[input].name)))
+                        }
+                        Some((input, _)) => resolved.push(input),
+                    }
+                }
+                resolved
+            } else {
+                match (stage.kind, stage.index) {
+                    (StageKind::Transform, 0) => sources.clone(),
+                    (StageKind::Transform, i) => vec![transform(i - 1)],
+                    _ if spec.transforms.is_empty() => sources.clone(),
+                    _ => vec![transform(spec.transforms.len() - 1)],
+                }
+            };
+            stages[id].inputs = resolved;
+        }
+
+        // Place each stage once everything it reads is placed; what is left
+        // over reads itself through a cycle of transforms
+        let mut order = Vec::with_capacity(stages.len());
+        let mut placed = vec![false; stages.len()];
+        while let Some(id) =
+            (0..stages.len()).find(|&id| !placed[id] && stages[id].inputs.iter().all(|&input| placed[input]))
+        {
+            placed[id] = true;
+            order.push(id);
+        }
+        for (id, stage) in stages.iter().enumerate().filter(|(id, _)| !placed[*id]) {
+            // Sinks downstream of a cycle are not part of it
+            if stage.kind == StageKind::Transform && reads(&stages, id, id) {
+                let field = format!("transforms[{}].inputs", stage.index);
+                problems.push((field, format!("{} reads its own output", stage.name)));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(Self {
+                stages,
+                order,
+                linear: spec.transforms.iter().all(|t| t.inputs.is_empty())
+                    && spec.sinks.iter().all(|s| s.inputs.is_empty()),
+            })
+        } else {
+            Err(problems)
+        }
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Stages in the order they can run, each after those it reads.
+    pub fn ordered(&self) -> impl Iterator<Item = &Stage> {
+        self.order.iter().map(|&id| &self.stages[id])
+    }
+
+    /// Whether no stage names its inputs, i.e. the pipeline is the chain of
+    /// its transforms.
+    pub fn is_linear(&self) -> bool {
+        self.linear
+    }
+
+    /// Sources and transforms whose output no stage reads.
+    pub fn unread(&self) -> impl Iterator<Item = &Stage> {
+        self.stages.iter().enumerate().filter_map(|(id, stage)| {
+            let read = self.stages.iter().any(|other| other.inputs.contains(&id));
+            (stage.kind != StageKind::Sink && !read).then_some(stage)
+        })
+    }
+}
+
+/// Whether stage `reader` reads the output of stage `from` at any distance.
+fn reads(stages: &[Stage], reader: usize, from: usize) -> bool {
+    let mut seen = vec![false; stages.len()];
+    let mut pending = stages[reader].inputs.clone();
+    while let Some(id) = pending.pop() {
+        if id == from {
+            return true;
+        }
+        if !std::mem::replace(&mut seen[id], true) {
+            pending.extend(&stages[id].inputs);
+        }
+    }
+    false
+}
+
+type Batch = Arc<Vec<Value>>;
+
+/// Where a sink stage's batches go when a [`DagPlan`] runs as tasks.
+#[async_trait]
+pub trait StageSink: Send + Sync {
+    async fn write(&self, records: &[Value]) -> Result<()>;
+}
+
+/// A pipeline graph whose transform stages each run as a [`ColumnarPlan`],
+/// so only transforms columnar execution supports can be used.
+pub struct DagPlan {
+    graph: PipelineGraph,
+    /// Plans of the transform stages, by position in the graph.
+    plans: HashMap<usize, ColumnarPlan>,
+}
+
+impl DagPlan {
+    /// Fails with `(field, message)` pairs, as [`PipelineGraph::new`] and
+    /// [`ColumnarPlan::new`] do.
+    pub fn new(spec: &PipelineSpec) -> Result<Self, Vec<(String, String)>> {
+        let graph = PipelineGraph::new(spec)?;
+        let mut plans = HashMap::new();
+        let mut problems = Vec::new();
+        for (id, stage) in graph.stages.iter().enumerate() {
+            if stage.kind != StageKind::Transform {
+                continue;
+            }
+            match ColumnarPlan::new(std::slice::from_ref(&spec.transforms[stage.index])) {
+                Ok(plan) => {
+                    plans.insert(id, plan);
+                }
+                Err(found) => {
+                    // Each plan sees its transform as the first and only one
+                    let located = format!("transforms[{}]", stage.index);
+                    problems.extend(
+                        found
+                            .into_iter()
+                            .map(|(field, message)| (field.replacen("transforms[0]", &located, 1), message)),
+                    );
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(Self { graph, plans })
+        } else {
+            Err(problems)
+        }
+    }
+
+    pub fn graph(&self) -> &PipelineGraph {
+        &self.graph
+    }
+
+    /// Runs one batch through every stage, with `records` read by every
+    /// source. Returns what each sink receives, in the order of the spec's
+    /// sinks; a sink with several inputs gets them one after another.
+    pub fn run(&self, records: Vec<Value>) -> Result<Vec<Vec<Value>>> {
+        let records = Arc::new(records);
+        let mut outputs: HashMap<usize, Batch> = HashMap::new();
+        let mut received = Vec::new();
+        for (id, stage) in self.graph.order.iter().map(|&id| (id, &self.graph.stages[id])) {
+            match stage.kind {
+                StageKind::Source => {
+                    outputs.insert(id, records.clone());
+                }
+                StageKind::Transform => {
+                    let input = merge(&outputs, &stage.inputs);
+                    let output = self.plans[&id]
+                        .run(&input)
+                        .map_err(|e| anyhow::anyhow!("Stage {} failed: {}", stage.name, e))?;
+                    outputs.insert(id, Arc::new(output));
+                }
+                StageKind::Sink => {
+                    let input = merge(&outputs, &stage.inputs);
+                    received.push((
+                        stage.index,
+                        Arc::try_unwrap(input).unwrap_or_else(|input| (*input).clone()),
+                    ));
+                }
+            }
+        }
+        received.sort_by_key(|(index, _)| *index);
+        Ok(received.into_iter().map(|(_, records)| records).collect())
+    }
+
+    /// Starts a task per transform and sink stage, joined by a bounded
+    /// channel per edge. A stage that falls behind fills its input edges,
+    /// which holds back the stages feeding it and finally
+    /// [`RunningDag::send`]. `sinks` are in the order of the spec's sinks.
+    pub fn spawn(self: Arc<Self>, sinks: Vec<Arc<dyn StageSink>>) -> Result<RunningDag> {
+        let sink_count = self.graph.stages.iter().filter(|s| s.kind == StageKind::Sink).count();
+        if sinks.len() != sink_count {
+            return Err(anyhow::anyhow!(
+                "Pipeline has {} sink(s) but {} were given",
+                sink_count,
+                sinks.len()
+            ));
+        }
+
+        let stats: Vec<Arc<StageStats>> = self.graph.stages.iter().map(|_| Arc::default()).collect();
+        let mut senders: HashMap<usize, Vec<Edge>> = HashMap::new();
+        let mut receivers: HashMap<usize, Vec<mpsc::Receiver<Batch>>> = HashMap::new();
+        for (id, stage) in self.graph.stages.iter().enumerate() {
+            for &input in &stage.inputs {
+                let (tx, rx) = mpsc::channel(stage.buffer);
+                let edge = Edge {
+                    tx,
+                    to: stats[id].clone(),
+                };
+                senders.entry(input).or_default().push(edge);
+                receivers.entry(id).or_default().push(rx);
+            }
+        }
+
+        let mut sources = BTreeMap::new();
+        let mut tasks = JoinSet::new();
+        for (id, stage) in self.graph.stages.iter().enumerate() {
+            let outputs = senders.remove(&id).unwrap_or_default();
+            let mut inputs = merged(receivers.remove(&id).unwrap_or_default());
+            let name = stage.name.clone();
+            let stats = stats[id].clone();
+            match stage.kind {
+                StageKind::Source => {
+                    sources.insert(name, (stats, outputs));
+                }
+                StageKind::Transform => {
+                    let plan = self.clone();
+                    tasks.spawn(async move {
+                        let result: Result<()> = async {
+                            while let Some(batch) = inputs.next().await {
+                                stats.taken(&batch);
+                                let output = plan.plans[&id]
+                                    .run(&batch)
+                                    .map_err(|e| anyhow::anyhow!("Stage {} failed: {}", name, e))?;
+                                stats.records_out.fetch_add(output.len() as u64, Ordering::Relaxed);
+                                if !output.is_empty() {
+                                    forward(&outputs, Arc::new(output), &name).await?;
+                                }
+                            }
+                            Ok(())
+                        }
+                        .await;
+                        stats.ended(&result);
+                        result
+                    });
+                }
+                StageKind::Sink => {
+                    let sink = sinks[stage.index].clone();
+                    tasks.spawn(async move {
+                        let result: Result<()> = async {
+                            while let Some(batch) = inputs.next().await {
+                                stats.taken(&batch);
+                                sink.write(&batch)
+                                    .await
+                                    .map_err(|e| anyhow::anyhow!("Sink {} failed: {}", name, e))?;
+                                stats.records_out.fetch_add(batch.len() as u64, Ordering::Relaxed);
+                            }
+                            Ok(())
+                        }
+                        .await;
+                        stats.ended(&result);
+                        result
+                    });
+                }
+            }
+        }
+        let monitor = Arc::new(DagMonitor {
+            graph: self.graph.clone(),
+            stats,
+            started: Instant::now(),
+            started_at: Utc::now(),
+        });
+        Ok(RunningDag {
+            sources,
+            tasks,
+            monitor,
+        })
+    }
+}
+
+/// An edge into a stage, counting the batches it holds against the stage.
+struct Edge {
+    tx: mpsc::Sender<Batch>,
+    to: Arc<StageStats>,
+}
+
+/// Counters of one running stage, updated by its task or, for a source, by
+/// [`RunningDag::send`].
+#[derive(Default)]
+struct StageStats {
+    batches: AtomicU64,
+    records_in: AtomicU64,
+    records_out: AtomicU64,
+    errors: AtomicU64,
+    /// Batches sent to the stage it has not taken yet, including those
+    /// waiting for room on a full edge.
+    queued: AtomicU64,
+    finished: AtomicBool,
+    last_errors: Mutex<VecDeque<ErrorSample>>,
+}
+
+impl StageStats {
+    fn taken(&self, batch: &[Value]) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        self.records_in.fetch_add(batch.len() as u64, Ordering::Relaxed);
+    }
+
+    fn failed(&self, error: &anyhow::Error) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        let mut samples = self.last_errors.lock().unwrap_or_else(|e| e.into_inner());
+        if samples.len() == ERROR_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(ErrorSample {
+            at: Utc::now(),
+            message: error.to_string(),
+        });
+    }
+
+    fn ended(&self, result: &Result<()>) {
+        if let Err(e) = result {
+            self.failed(e);
+        }
+        self.finished.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The output of `inputs`, one after another.
+fn merge(outputs: &HashMap<usize, Batch>, inputs: &[usize]) -> Batch {
+    match inputs {
+        [input] => outputs[input].clone(),
+        inputs => Arc::new(inputs.iter().flat_map(|input| outputs[input].iter().cloned()).collect()),
+    }
+}
+
+/// Batches from every input edge, as they arrive.
+fn merged(inputs: Vec<mpsc::Receiver<Batch>>) -> impl Stream<Item = Batch> + Unpin {
+    stream::select_all(inputs.into_iter().map(ReceiverStream::new))
+}
+
+async fn forward(outputs: &[Edge], batch: Batch, stage: &str) -> Result<()> {
+    for output in outputs {
+        output.to.queued.fetch_add(1, Ordering::Relaxed);
+        if output.tx.send(batch.clone()).await.is_err() {
+            output.to.queued.fetch_sub(1, Ordering::Relaxed);
+            return Err(anyhow::anyhow!("A stage reading {} has stopped", stage));
+        }
+    }
+    Ok(())
+}
+
+/// A [`DagPlan`] running as tasks.
+pub struct RunningDag {
+    sources: BTreeMap<String, (Arc<StageStats>, Vec<Edge>)>,
+    tasks: JoinSet<Result<()>>,
+    monitor: Arc<DagMonitor>,
+}
+
+impl RunningDag {
+    /// Feeds `records` to the source named `source`, waiting while any edge
+    /// it feeds is full. Fails once a stage downstream has failed; see
+    /// [`RunningDag::finish`] for why.
+    pub async fn send(&self, source: &str, records: Vec<Value>) -> Result<()> {
+        let (stats, outputs) = self
+            .sources
+            .get(source)
+            .ok_or_else(|| anyhow::anyhow!("Pipeline has no source {}", source))?;
+        let count = records.len() as u64;
+        stats.batches.fetch_add(1, Ordering::Relaxed);
+        stats.records_in.fetch_add(count, Ordering::Relaxed);
+        let result = forward(outputs, Arc::new(records), source).await;
+        match &result {
+            Ok(()) => {
+                stats.records_out.fetch_add(count, Ordering::Relaxed);
+            }
+            Err(e) => stats.failed(e),
+        }
+        result
+    }
+
+    /// Counters of the running stages, which stay readable after
+    /// [`RunningDag::finish`].
+    pub fn monitor(&self) -> Arc<DagMonitor> {
+        self.monitor.clone()
+    }
+
+    /// Closes the sources and waits for every stage to drain what it was
+    /// sent. Fails with the first stage error.
+    pub async fn finish(mut self) -> Result<()> {
+        for (stats, _) in std::mem::take(&mut self.sources).into_values() {
+            stats.finished.store(true, Ordering::Relaxed);
+        }
+        let mut result = Ok(());
+        while let Some(joined) = self.tasks.join_next().await {
+            let joined = joined
+                .map_err(|e| anyhow::anyhow!("Pipeline stage panicked: {}", e))
+                .and_then(|stage| stage);
+            if result.is_ok() {
+                result = joined;
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageState {
+    Running,
+    Finished,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorSample {
+    pub at: DateTime<Utc>,
+    pub message: String,
+}
+
+/// One stage of a running pipeline as [`DagMonitor::topology`] saw it.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageStatus {
+    pub name: String,
+    pub kind: StageKind,
+    /// Names of the stages read.
+    pub inputs: Vec<String>,
+    pub state: StageState,
+    pub batches: u64,
+    pub records_in: u64,
+    pub records_out: u64,
+    /// Records passed on per second since the pipeline started.
+    pub throughput: f64,
+    pub errors: u64,
+    /// Errors per batch taken.
+    pub error_rate: f64,
+    /// Batches sent to the stage and not taken yet; at `queue_capacity` or
+    /// beyond, the stages feeding it are waiting on it.
+    pub queue_depth: u64,
+    pub queue_capacity: usize,
+    pub last_errors: Vec<ErrorSample>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Topology {
+    pub started_at: DateTime<Utc>,
+    /// Stages in the order they can run, each after those it reads.
+    pub stages: Vec<StageStatus>,
+}
+
+/// Reads the counters of a [`RunningDag`].
+pub struct DagMonitor {
+    graph: PipelineGraph,
+    /// By position in the graph.
+    stats: Vec<Arc<StageStats>>,
+    started: Instant,
+    started_at: DateTime<Utc>,
+}
+
+impl DagMonitor {
+    pub fn topology(&self) -> Topology {
+        let elapsed = self.started.elapsed().as_secs_f64().max(f64::EPSILON);
+        let stages = self
+            .graph
+            .order
+            .iter()
+            .map(|&id| {
+                let stage = &self.graph.stages[id];
+                let stats = &self.stats[id];
+                let batches = stats.batches.load(Ordering::Relaxed);
+                let records_out = stats.records_out.load(Ordering::Relaxed);
+                let errors = stats.errors.load(Ordering::Relaxed);
+                let state = match (stats.finished.load(Ordering::Relaxed), errors) {
+                    (false, _) => StageState::Running,
+                    (true, 0) => StageState::Finished,
+                    (true, _) => StageState::Failed,
+                };
+                StageStatus {
+                    name: stage.name.clone(),
+                    kind: stage.kind,
+                    inputs: stage.inputs.iter().map(|&input| self.graph.stages[input].name.clone()).collect(),
+                    state,
+                    batches,
+                    records_in: stats.records_in.load(Ordering::Relaxed),
+                    records_out,
+                    throughput: records_out as f64 / elapsed,
+                    errors,
+                    error_rate: if batches == 0 { 0.0 } else { errors as f64 / batches as f64 },
+                    queue_depth: stats.queued.load(Ordering::Relaxed),
+                    queue_capacity: stage.buffer * stage.inputs.len(),
+                    last_errors: stats
+                        .last_errors
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .iter()
+                        .cloned()
+                        .collect(),
+                }
+            })
+            .collect();
+        Topology {
+            started_at: self.started_at,
+            stages,
+        }
+    }
+}
+
+/// Running pipelines by id, as served by the admin endpoint. A pipeline
+/// stays until removed, so one that failed can still be looked at.
+#[derive(Default)]
+pub struct Topologies {
+    running: RwLock<BTreeMap<String, Arc<DagMonitor>>>,
+}
+
+impl Topologies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, pipeline: impl Into<String>, monitor: Arc<DagMonitor>) {
+        let mut running = self.running.write().unwrap_or_else(|e| e.into_inner());
+        running.insert(pipeline.into(), monitor);
+    }
+
+    pub fn remove(&self, pipeline: &str) {
+        let mut running = self.running.write().unwrap_or_else(|e| e.into_inner());
+        running.remove(pipeline);
+    }
+
+    pub fn get(&self, pipeline: &str) -> Option<Topology> {
+        let running = self.running.read().unwrap_or_else(|e| e.into_inner());
+        running.get(pipeline).map(|monitor| monitor.topology())
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, Topology> {
+        let running = self.running.read().unwrap_or_else(|e| e.into_inner());
+        running
+            .iter()
+            .map(|(pipeline, monitor)| (pipeline.clone(), monitor.topology()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{SinkSpec, SourceSpec, TransformSpec};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    fn transform(name: &str, inputs: &[&str], config: Value) -> TransformSpec {
+        TransformSpec {
+            name: name.to_string(),
+            kind: "filter".to_string(),
+            config,
+            inputs: inputs.iter().map(|i| i.to_string()).collect(),
+            buffer: None,
+        }
+    }
+
+    fn sink(name: &str, inputs: &[&str]) -> SinkSpec {
+        SinkSpec {
+            name: name.to_string(),
+            kind: "storage".to_string(),
+            config: Value::Null,
+            inputs: inputs.iter().map(|i| i.to_string()).collect(),
+            buffer: Some(1),
+        }
+    }
+
+    /// `events` fans out to an error and a warning branch, which merge into
+    /// `alerts`; `archive` reads the raw events.
+    fn branching() -> PipelineSpec {
+        PipelineSpec {
+            sources: vec![SourceSpec {
+                name: "events".to_string(),
+                topics: vec!["events".to_string()],
+            }],
+            transforms: vec![
+                transform(
+                    "errors",
+                    &["events"],
+                    json!({"field": "level", "op": "eq", "value": "error"}),
+                ),
+                transform(
+                    "warnings",
+                    &["events"],
+                    json!({"field": "level", "op": "eq", "value": "warn"}),
+                ),
+            ],
+            sinks: vec![sink("archive", &["events"]), sink("alerts", &["errors", "warnings"])],
+            ..Default::default()
+        }
+    }
+
+    fn records() -> Vec<Value> {
+        ["info", "error", "warn", "error"]
+            .iter()
+            .map(|level| json!({ "level": level }))
+            .collect()
+    }
+
+    #[test]
+    fn test_graph_resolves_defaults_and_rejects_cycles() {
+        let mut linear = branching();
+        for transform in &mut linear.transforms {
+            transform.inputs.clear();
+        }
+        for sink in &mut linear.sinks {
+            sink.inputs.clear();
+        }
+        let graph = PipelineGraph::new(&linear).unwrap();
+        assert!(graph.is_linear());
+        let inputs: Vec<Vec<usize>> = graph.stages().iter().map(|s| s.inputs.clone()).collect();
+        assert_eq!(inputs, vec![vec![], vec![0], vec![1], vec![2], vec![2]]);
+
+        let graph = PipelineGraph::new(&branching()).unwrap();
+        assert!(!graph.is_linear());
+        assert_eq!(graph.unread().count(), 0);
+        let order: Vec<&str> = graph.ordered().map(|s| s.name.as_str()).collect();
+        assert_eq!(order, vec!["events", "errors", "warnings", "archive", "alerts"]);
+
+        let mut cyclic = branching();
+        cyclic.transforms[0].inputs = vec!["warnings".to_string()];
+        cyclic.transforms[1].inputs = vec!["errors".to_string(), "archive".to_string(), "missing".to_string()];
+        let problems = PipelineGraph::new(&cyclic).unwrap_err();
+        let fields: Vec<&str> = problems.iter().map(|(field, _)| field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transforms[1].inputs[1]",
+                "transforms[1].inputs[2]",
+                "transforms[0].inputs",
+                "transforms[1].inputs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_fans_out_and_merges_branches() {
+        let plan = DagPlan::new(&branching()).unwrap();
+        let received = plan.run(records()).unwrap();
+        assert_eq!(received[0].len(), 4);
+        assert_eq!(
+            received[1],
+            vec![
+                json!({"level": "error"}),
+                json!({"level": "error"}),
+                json!({"level": "warn"})
+            ]
+        );
+
+        let mut unsupported = branching();
+        unsupported.transforms[1].kind = "map".to_string();
+        let problems = DagPlan::new(&unsupported).unwrap_err();
+        assert_eq!(problems[0].0, "transforms[1].type");
+    }
+
+    #[derive(Default)]
+    struct Collect(Mutex<Vec<Value>>);
+
+    #[async_trait]
+    impl StageSink for Collect {
+        async fn write(&self, records: &[Value]) -> Result<()> {
+            self.0.lock().unwrap().extend_from_slice(records);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spawned_stages_drain_every_batch() {
+        let plan = Arc::new(DagPlan::new(&branching()).unwrap());
+        let (archive, alerts) = (Arc::new(Collect::default()), Arc::new(Collect::default()));
+        let running = plan.spawn(vec![archive.clone(), alerts.clone()]).unwrap();
+        let monitor = running.monitor();
+        // Edges into the sinks hold one batch, so sends wait on the sinks
+        for _ in 0..10 {
+            running.send("events", records()).await.unwrap();
+        }
+        assert!(running.send("missing", records()).await.is_err());
+        running.finish().await.unwrap();
+
+        assert_eq!(archive.0.lock().unwrap().len(), 40);
+        let alerts = alerts.0.lock().unwrap();
+        assert_eq!(alerts.len(), 30);
+        assert_eq!(alerts.iter().filter(|r| r["level"] == "warn").count(), 10);
+
+        let topology = monitor.topology();
+        let stage = |name: &str| topology.stages.iter().find(|s| s.name == name).unwrap().clone();
+        let (events, alerting) = (stage("events"), stage("alerts"));
+        assert_eq!((events.batches, events.records_out), (10, 40));
+        assert_eq!(alerting.inputs, vec!["errors", "warnings"]);
+        assert_eq!((alerting.records_in, alerting.records_out, alerting.queue_depth), (30, 30, 0));
+        assert_eq!(alerting.queue_capacity, 2);
+        assert!(topology.stages.iter().all(|s| s.state == StageState::Finished));
+    }
+
+    struct Reject;
+
+    #[async_trait]
+    impl StageSink for Reject {
+        async fn write(&self, _records: &[Value]) -> Result<()> {
+            Err(anyhow::anyhow!("disk full"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_topology_samples_stage_errors() {
+        let plan = Arc::new(DagPlan::new(&branching()).unwrap());
+        let running = plan.spawn(vec![Arc::new(Collect::default()), Arc::new(Reject)]).unwrap();
+        let topologies = Topologies::new();
+        topologies.register("alerting", running.monitor());
+        running.send("events", records()).await.unwrap();
+        assert!(running.finish().await.is_err());
+
+        let topology = topologies.get("alerting").unwrap();
+        let alerts = topology.stages.iter().find(|s| s.name == "alerts").unwrap();
+        assert_eq!(alerts.state, StageState::Failed);
+        assert_eq!((alerts.errors, alerts.error_rate), (1, 1.0));
+        assert_eq!(alerts.last_errors[0].message, "Sink alerts failed: disk full");
+        let archive = topology.stages.iter().find(|s| s.name == "archive").unwrap();
+        assert_eq!(archive.state, StageState::Finished);
+
+        topologies.remove("alerting");
+        assert!(topologies.snapshot().is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+/// Largest payload whose copy is kept between calls; a rare huge message
+/// should not pin its size in every worker.
+const MAX_RETAINED_SCRATCH: usize = 4 * 1024 * 1024;
+
+/// Decodes JSON message payloads with simd-json, reusing its scratch
+/// buffers from one message to the next. Each processing worker owns one.
+pub struct Decoder {
+    /// simd-json parses in place, so payloads are copied here first.
+    scratch: Vec<u8>,
+    buffers: simd_json::Buffers,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            scratch: Vec::new(),
+            buffers: simd_json::Buffers::default(),
+        }
+    }
+
+    /// Payloads simd-json rejects are decoded again with serde_json, so
+    /// what is accepted and the errors reported stay the same as before.
+    pub fn decode<T: DeserializeOwned>(&mut self, payload: &[u8]) -> Result<T> {
+        self.scratch.clear();
+        self.scratch.extend_from_slice(payload);
+        let decoded = simd_json::serde::from_slice_with_buffers(&mut self.scratch, &mut self.buffers);
+        if self.scratch.capacity() > MAX_RETAINED_SCRATCH {
+            self.scratch = Vec::new();
+        }
+
+        match decoded {
+            Ok(value) => Ok(value),
+            Err(_) => serde_json::from_slice(payload).map_err(|e| anyhow::anyhow!("Invalid JSON payload: {}", e)),
+        }
+    }
+
+    /// Decodes types that borrow from the payload. simd-json rewrites its
+    /// input while parsing and cannot lend from it, so these always go
+    /// through serde_json.
+    pub fn decode_borrowed<'a, T: Deserialize<'a>>(payload: &'a [u8]) -> Result<T> {
+        serde_json::from_slice(payload).map_err(|e| anyhow::anyhow!("Invalid JSON payload: {}", e))
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_decoder_matches_serde_json() {
+        let mut decoder = Decoder::new();
+        let big = json!({"name": "cpu_usage", "value": 42.5, "labels": {"service": "api"}, "padding": "x".repeat(5000)});
+
+        for payload in [
+            big.to_string(),
+            r#"{"escaped": "line\nbreak é", "nested": [1, [2, [3]]], "n": -1.5e3}"#.to_string(),
+            "[]".to_string(),
+        ] {
+            let decoded: Value = decoder.decode(payload.as_bytes()).unwrap();
+            assert_eq!(decoded, serde_json::from_str::<Value>(&payload).unwrap());
+        }
+
+        for garbage in [&b"not json"[..], b"{\"a\":", b"", &[0xff, 0xfe]] {
+            let err = decoder.decode::<Value>(garbage).unwrap_err().to_string();
+            assert!(err.starts_with("Invalid JSON payload"), "{}", err);
+        }
+
+        #[derive(Deserialize)]
+        struct Borrowed<'a> {
+            name: &'a str,
+        }
+        let payload = br#"{"name": "cpu_usage"}"#;
+        let borrowed: Borrowed = Decoder::decode_borrowed(payload).unwrap();
+        assert_eq!(borrowed.name, "cpu_usage");
+    }
+}
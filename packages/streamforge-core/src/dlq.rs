@@ -0,0 +1,754 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{Message, Offset, TopicPartitionList};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::processor::KafkaMessage;
+
+/// Version of the envelope written by this build. Envelopes without a
+/// version header predate it and read as version 0.
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// Headers forming the error envelope of a dead letter. The payload is the
+/// original message, unchanged, so it can be republished as is.
+pub const HEADER_ENVELOPE_VERSION: &str = "x-envelope-version";
+pub const HEADER_ORIGINAL_TOPIC: &str = "x-original-topic";
+pub const HEADER_ORIGINAL_PARTITION: &str = "x-original-partition";
+pub const HEADER_ORIGINAL_OFFSET: &str = "x-original-offset";
+pub const HEADER_PIPELINE: &str = "x-error-pipeline-id";
+pub const HEADER_STAGE: &str = "x-error-stage";
+pub const HEADER_ERROR_CLASS: &str = "x-error-class";
+pub const HEADER_ERROR: &str = "x-error";
+/// JSON array of the error's causes.
+pub const HEADER_ERROR_CAUSES: &str = "x-error-causes";
+/// JSON object of string values.
+pub const HEADER_ERROR_CONTEXT: &str = "x-error-context";
+/// Also set on redriven messages, so a message failing again counts on from
+/// its earlier attempts.
+pub const HEADER_ATTEMPTS: &str = "x-attempts";
+pub const HEADER_RECEIVED_AT: &str = "x-received-at";
+pub const HEADER_FAILED_AT: &str = "x-failed-at";
+
+pub(crate) const ENVELOPE_HEADERS: &[&str] = &[
+    HEADER_ENVELOPE_VERSION,
+    HEADER_ORIGINAL_TOPIC,
+    HEADER_ORIGINAL_PARTITION,
+    HEADER_ORIGINAL_OFFSET,
+    HEADER_PIPELINE,
+    HEADER_STAGE,
+    HEADER_ERROR_CLASS,
+    HEADER_ERROR,
+    HEADER_ERROR_CAUSES,
+    HEADER_ERROR_CONTEXT,
+    HEADER_ATTEMPTS,
+    HEADER_RECEIVED_AT,
+    HEADER_FAILED_AT,
+];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What kind of error failed a message, for tooling that routes failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    /// The message could not be decoded or transformed.
+    Processing,
+    /// The message broke its topic's schema; see [`crate::schemas`].
+    Schema,
+    /// The record broke `processing.record_limits` and never reached the
+    /// transform stage; see [`crate::limits::check_record`].
+    Admission,
+    /// The transform ran past `processing.transform_timeout` and was
+    /// cancelled.
+    Timeout,
+    /// Written without a class, or with one this build does not know.
+    #[default]
+    Unknown,
+}
+
+impl ErrorClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorClass::Processing => "processing",
+            ErrorClass::Schema => "schema",
+            ErrorClass::Admission => "admission",
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Unknown => "unknown",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "processing" => ErrorClass::Processing,
+            "schema" => ErrorClass::Schema,
+            "admission" => ErrorClass::Admission,
+            "timeout" => ErrorClass::Timeout,
+            _ => ErrorClass::Unknown,
+        }
+    }
+}
+
+/// Why a message failed, as reported by the stage that failed it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub pipeline_id: Option<String>,
+    pub stage: String,
+    pub class: ErrorClass,
+    pub error: String,
+    pub causes: Vec<String>,
+    pub context: BTreeMap<String, String>,
+}
+
+impl Failure {
+    pub fn new(stage: &str, class: ErrorClass, error: impl ToString) -> Self {
+        Self {
+            pipeline_id: None,
+            stage: stage.to_string(),
+            class,
+            error: error.to_string(),
+            causes: Vec::new(),
+            context: BTreeMap::new(),
+        }
+    }
+
+    /// A failure from `error`, keeping its chain of causes.
+    pub fn from_error(stage: &str, class: ErrorClass, error: &anyhow::Error) -> Self {
+        Self {
+            causes: error.chain().skip(1).map(|cause| cause.to_string()).collect(),
+            ..Self::new(stage, class, error)
+        }
+    }
+
+    pub fn pipeline(mut self, pipeline_id: &str) -> Self {
+        self.pipeline_id = Some(pipeline_id.to_string());
+        self
+    }
+
+    pub fn context(mut self, key: &str, value: impl ToString) -> Self {
+        self.context.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+/// Where a message came from and why it failed. Dead letters carry it as
+/// headers and the error topic as [`ErrorRecord`] JSON; both hold the same
+/// fields so tooling can parse failures from either.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub version: u32,
+    pub original_topic: String,
+    pub original_partition: i32,
+    pub original_offset: i64,
+    /// `None` for messages failed by the processor's own processing.
+    pub pipeline_id: Option<String>,
+    /// One of the `processor::STAGE_*` names.
+    pub stage: String,
+    pub error_class: ErrorClass,
+    pub error: String,
+    /// Causes of `error`, outermost first.
+    pub causes: Vec<String>,
+    /// Details from the failing stage, such as the worker.
+    pub context: BTreeMap<String, String>,
+    /// Times the message has failed, counting those before earlier redrives.
+    pub attempts: u32,
+    /// When the message was consumed before failing.
+    pub received_at: Option<DateTime<Utc>>,
+    pub failed_at: DateTime<Utc>,
+}
+
+impl Envelope {
+    /// The envelope of `message` failing now, one attempt after any its
+    /// headers record from an earlier redrive.
+    pub fn new(message: &KafkaMessage, failure: Failure) -> Self {
+        let attempts = message
+            .headers
+            .as_ref()
+            .and_then(|headers| headers.iter().find(|h| h.key == HEADER_ATTEMPTS))
+            .and_then(|h| h.value)
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(0);
+        Self {
+            version: ENVELOPE_VERSION,
+            original_topic: message.topic.clone(),
+            original_partition: message.partition,
+            original_offset: message.offset,
+            pipeline_id: failure.pipeline_id,
+            stage: failure.stage,
+            error_class: failure.class,
+            error: failure.error,
+            causes: failure.causes,
+            context: failure.context,
+            attempts: attempts.saturating_add(1),
+            received_at: Some(message.received_at),
+            failed_at: Utc::now(),
+        }
+    }
+
+    /// `headers` are the original message's headers, kept so the tenant and
+    /// trace context survive a redrive.
+    pub fn to_headers(&self, headers: OwnedHeaders) -> OwnedHeaders {
+        let version = self.version.to_string();
+        let partition = self.original_partition.to_string();
+        let offset = self.original_offset.to_string();
+        let causes = serde_json::to_string(&self.causes).unwrap_or_default();
+        let context = serde_json::to_string(&self.context).unwrap_or_default();
+        let attempts = self.attempts.to_string();
+        let received_at = self.received_at.map(|t| t.to_rfc3339());
+        let failed_at = self.failed_at.to_rfc3339();
+        let mut headers = headers
+            .insert(header(HEADER_ENVELOPE_VERSION, &version))
+            .insert(header(HEADER_ORIGINAL_TOPIC, &self.original_topic))
+            .insert(header(HEADER_ORIGINAL_PARTITION, &partition))
+            .insert(header(HEADER_ORIGINAL_OFFSET, &offset))
+            .insert(header(HEADER_STAGE, &self.stage))
+            .insert(header(HEADER_ERROR_CLASS, self.error_class.as_str()))
+            .insert(header(HEADER_ERROR, &self.error))
+            .insert(header(HEADER_ERROR_CAUSES, &causes))
+            .insert(header(HEADER_ERROR_CONTEXT, &context))
+            .insert(header(HEADER_ATTEMPTS, &attempts))
+            .insert(header(HEADER_FAILED_AT, &failed_at));
+        if let Some(pipeline_id) = &self.pipeline_id {
+            headers = headers.insert(header(HEADER_PIPELINE, pipeline_id));
+        }
+        if let Some(received_at) = &received_at {
+            headers = headers.insert(header(HEADER_RECEIVED_AT, received_at));
+        }
+        headers
+    }
+
+    /// `None` when the headers lack the original topic, i.e. the message was
+    /// not written by the processor.
+    pub fn from_headers(headers: Option<&impl Headers>) -> Option<Self> {
+        let headers = headers?;
+        let value = |key: &str| {
+            headers
+                .iter()
+                .find(|h| h.key == key)
+                .and_then(|h| h.value)
+                .and_then(|v| std::str::from_utf8(v).ok())
+        };
+        let time = |key: &str| {
+            value(key)
+                .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+                .map(|t| t.with_timezone(&Utc))
+        };
+        Some(Self {
+            version: value(HEADER_ENVELOPE_VERSION).and_then(|v| v.parse().ok()).unwrap_or(0),
+            original_topic: value(HEADER_ORIGINAL_TOPIC)?.to_string(),
+            original_partition: value(HEADER_ORIGINAL_PARTITION).and_then(|v| v.parse().ok()).unwrap_or(-1),
+            original_offset: value(HEADER_ORIGINAL_OFFSET).and_then(|v| v.parse().ok()).unwrap_or(-1),
+            pipeline_id: value(HEADER_PIPELINE).map(str::to_string),
+            stage: value(HEADER_STAGE).unwrap_or_default().to_string(),
+            error_class: value(HEADER_ERROR_CLASS).map_or(ErrorClass::Unknown, ErrorClass::parse),
+            error: value(HEADER_ERROR).unwrap_or_default().to_string(),
+            causes: value(HEADER_ERROR_CAUSES)
+                .and_then(|v| serde_json::from_str(v).ok())
+                .unwrap_or_default(),
+            context: value(HEADER_ERROR_CONTEXT)
+                .and_then(|v| serde_json::from_str(v).ok())
+                .unwrap_or_default(),
+            attempts: value(HEADER_ATTEMPTS).and_then(|v| v.parse().ok()).unwrap_or(1),
+            received_at: time(HEADER_RECEIVED_AT),
+            failed_at: time(HEADER_FAILED_AT).unwrap_or(DateTime::UNIX_EPOCH),
+        })
+    }
+}
+
+/// A failed message as one JSON document, as written to the error topic:
+/// the envelope with the original key and payload, base64 encoded, so it
+/// can be read without Kafka headers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorRecord {
+    #[serde(flatten)]
+    pub envelope: Envelope,
+    pub key: Option<String>,
+    pub payload: String,
+}
+
+impl ErrorRecord {
+    pub fn new(envelope: Envelope, message: &KafkaMessage) -> Self {
+        use base64::Engine;
+        let engine = base64::engine::general_purpose::STANDARD;
+        Self {
+            envelope,
+            key: message.key.as_ref().map(|key| engine.encode(key)),
+            payload: engine.encode(&message.payload),
+        }
+    }
+
+    /// The original payload.
+    pub fn payload(&self) -> Result<Vec<u8>> {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD
+            .decode(&self.payload)
+            .map_err(|e| anyhow::anyhow!("Invalid error record payload: {}", e))
+    }
+}
+
+fn header<'a>(key: &'a str, value: &'a str) -> Header<'a, &'a str> {
+    Header {
+        key,
+        value: Some(value),
+    }
+}
+
+/// A message read back from the dead letter topic.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<Vec<u8>>,
+    pub payload: Vec<u8>,
+    /// Headers of the original message, without the envelope.
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub envelope: Envelope,
+}
+
+/// Which dead letters to read. Bounds are on the DLQ message timestamp,
+/// i.e. when the message failed.
+#[derive(Debug, Clone, Default)]
+pub struct DeadLetterQuery {
+    pub topic: String,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    /// Only letters that failed on this topic.
+    pub original_topic: Option<String>,
+    /// Offset to resume each partition from, overriding `start`. When set,
+    /// only these partitions are read.
+    pub positions: BTreeMap<i32, i64>,
+    pub limit: usize,
+}
+
+/// Dead letters read by [`read`], and where each partition left off.
+/// `positions` is empty once every partition has been read to the end.
+#[derive(Debug, Clone, Default)]
+pub struct DeadLetterPage {
+    pub letters: Vec<DeadLetter>,
+    pub positions: BTreeMap<i32, i64>,
+}
+
+impl DeadLetterPage {
+    /// Opaque token resuming the read at `positions`; empty on the last page.
+    pub fn next_page_token(&self) -> String {
+        use base64::Engine;
+        if self.positions.is_empty() {
+            return String::new();
+        }
+        let raw: Vec<String> = self
+            .positions
+            .iter()
+            .map(|(partition, offset)| format!("{}:{}", partition, offset))
+            .collect();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw.join(","))
+    }
+}
+
+/// Positions encoded by [`DeadLetterPage::next_page_token`].
+pub fn parse_page_token(token: &str) -> Result<BTreeMap<i32, i64>> {
+    use base64::Engine;
+    if token.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(token)
+        .map_err(|e| anyhow::anyhow!("Invalid page token: {}", e))?;
+    let raw = String::from_utf8(raw).map_err(|e| anyhow::anyhow!("Invalid page token: {}", e))?;
+
+    raw.split(',')
+        .map(|position| {
+            let (partition, offset) = position
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Invalid page token: missing separator"))?;
+            let partition = partition.parse().map_err(|e| anyhow::anyhow!("Invalid page token: {}", e))?;
+            let offset = offset.parse().map_err(|e| anyhow::anyhow!("Invalid page token: {}", e))?;
+            Ok((partition, offset))
+        })
+        .collect()
+}
+
+/// Reads up to `query.limit` dead letters without joining the consumer
+/// group or committing offsets. Blocks for up to `timeout`, so call it from
+/// a blocking task.
+pub fn read(config: &Config, query: &DeadLetterQuery, timeout: Duration) -> Result<DeadLetterPage> {
+    let consumer: BaseConsumer = config
+        .kafka_consumer_config()
+        .set("enable.auto.commit", "false")
+        .create()
+        .map_err(|e| anyhow::anyhow!("Failed to create Kafka consumer: {}", e))?;
+    let deadline = Instant::now() + timeout;
+
+    let metadata = consumer
+        .fetch_metadata(Some(&query.topic), timeout)
+        .map_err(|e| anyhow::anyhow!("Failed to fetch metadata for {}: {}", query.topic, e))?;
+    let partitions: Vec<i32> = metadata
+        .topics()
+        .iter()
+        .flat_map(|t| t.partitions().iter().map(|p| p.id()))
+        .collect();
+    if partitions.is_empty() {
+        return Err(anyhow::anyhow!("Topic {} does not exist", query.topic));
+    }
+
+    // Resolve where each partition starts from
+    let mut starts = BTreeMap::new();
+    let mut by_time = TopicPartitionList::new();
+    for &partition in &partitions {
+        if !query.positions.is_empty() && !query.positions.contains_key(&partition) {
+            continue;
+        }
+        match (query.positions.get(&partition), query.start) {
+            (Some(&offset), _) => {
+                starts.insert(partition, offset);
+            }
+            (None, Some(start)) => {
+                by_time
+                    .add_partition_offset(&query.topic, partition, Offset::Offset(start.timestamp_millis()))
+                    .map_err(|e| anyhow::anyhow!("Failed to resolve start offsets: {}", e))?;
+            }
+            (None, None) => {
+                starts.insert(partition, 0);
+            }
+        }
+    }
+    if by_time.count() > 0 {
+        let resolved = consumer
+            .offsets_for_times(by_time, timeout)
+            .map_err(|e| anyhow::anyhow!("Failed to resolve start offsets: {}", e))?;
+        for element in resolved.elements() {
+            // No message at or after the start time: nothing to read
+            let offset = match element.offset() {
+                Offset::Offset(offset) => offset,
+                _ => i64::MAX,
+            };
+            starts.insert(element.partition(), offset);
+        }
+    }
+
+    // Read each partition up to the end it had when the read started
+    let mut ends = BTreeMap::new();
+    let mut positions = BTreeMap::new();
+    let mut assignment = TopicPartitionList::new();
+    for (&partition, &start) in &starts {
+        let (low, high) = consumer
+            .fetch_watermarks(&query.topic, partition, timeout)
+            .map_err(|e| anyhow::anyhow!("Failed to fetch watermarks for {}/{}: {}", query.topic, partition, e))?;
+        let start = start.max(low);
+        if start < high {
+            assignment
+                .add_partition_offset(&query.topic, partition, Offset::Offset(start))
+                .map_err(|e| anyhow::anyhow!("Failed to assign {}/{}: {}", query.topic, partition, e))?;
+            ends.insert(partition, high);
+            positions.insert(partition, start);
+        }
+    }
+    if ends.is_empty() {
+        return Ok(DeadLetterPage::default());
+    }
+    consumer
+        .assign(&assignment)
+        .map_err(|e| anyhow::anyhow!("Failed to assign {}: {}", query.topic, e))?;
+
+    let mut letters = Vec::new();
+    while !ends.is_empty() && letters.len() < query.limit {
+        if Instant::now() >= deadline {
+            warn!("Timed out reading dead letters from {}", query.topic);
+            break;
+        }
+        let message = match consumer.poll(POLL_INTERVAL) {
+            Some(Ok(message)) => message,
+            Some(Err(e)) => return Err(anyhow::anyhow!("Failed to read dead letters: {}", e)),
+            None => continue,
+        };
+        let partition = message.partition();
+        positions.insert(partition, message.offset() + 1);
+
+        let past_end = query
+            .end
+            .zip(message.timestamp().to_millis())
+            .map_or(false, |(end, millis)| millis >= end.timestamp_millis());
+        if past_end || message.offset() + 1 >= ends[&partition] {
+            ends.remove(&partition);
+            consumer
+                .pause(&single(&query.topic, partition))
+                .map_err(|e| anyhow::anyhow!("Failed to pause {}/{}: {}", query.topic, partition, e))?;
+            if past_end {
+                continue;
+            }
+        }
+
+        let Some(envelope) = Envelope::from_headers(message.headers()) else {
+            continue;
+        };
+        if query.original_topic.as_ref().map_or(false, |t| *t != envelope.original_topic) {
+            continue;
+        }
+        let headers = message
+            .headers()
+            .map(|headers| {
+                headers
+                    .iter()
+                    .filter(|h| !ENVELOPE_HEADERS.contains(&h.key))
+                    .map(|h| (h.key.to_string(), h.value.unwrap_or_default().to_vec()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        letters.push(DeadLetter {
+            partition,
+            offset: message.offset(),
+            key: message.key().map(<[u8]>::to_vec),
+            payload: message.payload().unwrap_or_default().to_vec(),
+            headers,
+            envelope,
+        });
+    }
+
+    // Partitions that were read to the end need no resume position
+    positions.retain(|partition, _| ends.contains_key(partition));
+    Ok(DeadLetterPage { letters, positions })
+}
+
+fn single(topic: &str, partition: i32) -> TopicPartitionList {
+    let mut list = TopicPartitionList::new();
+    list.add_partition(topic, partition);
+    list
+}
+
+/// Writes a message that failed processing to `topic`, wrapped in its
+/// error envelope. Any envelope headers it arrived with, as a redriven
+/// message does, are replaced.
+pub async fn publish(
+    producer: &FutureProducer,
+    topic: &str,
+    message: &KafkaMessage,
+    envelope: &Envelope,
+) -> Result<()> {
+    let original = message
+        .headers
+        .iter()
+        .flat_map(|headers| headers.iter())
+        .filter(|h| !ENVELOPE_HEADERS.contains(&h.key))
+        .fold(OwnedHeaders::new(), |headers, h| {
+            headers.insert(Header {
+                key: h.key,
+                value: h.value,
+            })
+        });
+    let headers = envelope.to_headers(original);
+    let mut record = FutureRecord::to(topic).payload(&message.payload).headers(headers);
+    if let Some(key) = &message.key {
+        record = record.key(key);
+    }
+
+    producer
+        .send(record, SEND_TIMEOUT)
+        .await
+        .map_err(|(e, _)| anyhow::anyhow!("Failed to send to dead letter topic {}: {}", topic, e))?;
+    Ok(())
+}
+
+/// Writes `record` to the error topic as JSON, keyed like the original.
+pub async fn publish_record(producer: &FutureProducer, topic: &str, record: &ErrorRecord) -> Result<()> {
+    let payload =
+        serde_json::to_vec(record).map_err(|e| anyhow::anyhow!("Failed to serialize error record: {}", e))?;
+    let key = format!(
+        "{}/{}@{}",
+        record.envelope.original_topic, record.envelope.original_partition, record.envelope.original_offset
+    );
+    producer
+        .send(FutureRecord::to(topic).payload(&payload).key(&key), SEND_TIMEOUT)
+        .await
+        .map_err(|(e, _)| anyhow::anyhow!("Failed to send to error topic {}: {}", topic, e))?;
+    Ok(())
+}
+
+/// Producer for [`redrive`].
+pub fn producer(config: &Config) -> Result<FutureProducer> {
+    config
+        .kafka_producer_config()
+        .create()
+        .map_err(|e| anyhow::anyhow!("Failed to create Kafka producer: {}", e))
+}
+
+/// Republishes a dead letter to the topic it originally failed on, with its
+/// original key and headers and its attempt count. The letter itself stays
+/// in the DLQ.
+pub async fn redrive(producer: &FutureProducer, letter: &DeadLetter) -> Result<()> {
+    let attempts = letter.envelope.attempts.to_string();
+    let headers = letter
+        .headers
+        .iter()
+        .fold(OwnedHeaders::new(), |headers, (key, value)| {
+            headers.insert(Header {
+                key: key.as_str(),
+                value: Some(value.as_slice()),
+            })
+        })
+        .insert(header(HEADER_ATTEMPTS, &attempts));
+    let mut record = FutureRecord::to(&letter.envelope.original_topic)
+        .payload(&letter.payload)
+        .headers(headers);
+    if let Some(key) = &letter.key {
+        record = record.key(key);
+    }
+
+    producer
+        .send(record, SEND_TIMEOUT)
+        .await
+        .map_err(|(e, _)| anyhow::anyhow!("Failed to republish to {}: {}", letter.envelope.original_topic, e))?;
+    info!(
+        "Redrove dead letter {}@{} to {}",
+        letter.partition, letter.offset, letter.envelope.original_topic
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rdkafka::message::BorrowedHeaders;
+
+    fn message(headers: OwnedHeaders) -> KafkaMessage {
+        KafkaMessage {
+            tenant_id: "acme".to_string(),
+            topic: "events".to_string(),
+            partition: 3,
+            offset: 42,
+            payload: b"not json".to_vec(),
+            key: Some(b"poison".to_vec()),
+            headers: Some(headers),
+            timestamp: 0,
+            received_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            span: tracing::Span::none(),
+            inflight: None,
+        }
+    }
+
+    #[test]
+    fn test_envelope_round_trips_through_headers() {
+        let failure = Failure::from_error(
+            "transform",
+            ErrorClass::Processing,
+            &anyhow::anyhow!("expected value").context("invalid JSON"),
+        )
+        .pipeline("pipeline-1")
+        .context("worker_id", 2);
+        let envelope = Envelope::new(&message(OwnedHeaders::new()), failure);
+        assert_eq!(envelope.version, ENVELOPE_VERSION);
+        assert_eq!(envelope.error, "invalid JSON");
+        assert_eq!(envelope.causes, vec!["expected value"]);
+        assert_eq!(envelope.attempts, 1);
+        let original = OwnedHeaders::new().insert(header("x-tenant-id", "acme"));
+
+        let headers = envelope.to_headers(original);
+        assert_eq!(Envelope::from_headers(Some(&headers)), Some(envelope));
+        assert!(headers.iter().any(|h| h.key == "x-tenant-id"));
+        assert_eq!(Envelope::from_headers(None::<&BorrowedHeaders>), None);
+        assert_eq!(Envelope::from_headers(Some(&OwnedHeaders::new())), None);
+
+        // Envelopes written before versioning still read
+        let legacy = OwnedHeaders::new()
+            .insert(header(HEADER_ORIGINAL_TOPIC, "events"))
+            .insert(header(HEADER_ERROR, "invalid JSON"));
+        let legacy = Envelope::from_headers(Some(&legacy)).unwrap();
+        assert_eq!((legacy.version, legacy.error_class, legacy.attempts), (0, ErrorClass::Unknown, 1));
+    }
+
+    #[test]
+    fn test_redriven_message_counts_attempts_and_replaces_envelope() {
+        let redriven = OwnedHeaders::new()
+            .insert(header("x-tenant-id", "acme"))
+            .insert(header(HEADER_ATTEMPTS, "2"));
+        let envelope = Envelope::new(
+            &message(redriven),
+            Failure::new("transform", ErrorClass::Schema, "field value changed type"),
+        );
+        assert_eq!(envelope.attempts, 3);
+
+        let record = ErrorRecord::new(envelope.clone(), &message(OwnedHeaders::new()));
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["version"], ENVELOPE_VERSION);
+        assert_eq!(json["error_class"], "schema");
+        assert_eq!(json["attempts"], 3);
+        let parsed: ErrorRecord = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.payload().unwrap(), b"not json");
+    }
+
+    #[test]
+    fn test_page_token_round_trips_positions() {
+        let page = DeadLetterPage {
+            letters: Vec::new(),
+            positions: BTreeMap::from([(0, 17), (2, 5)]),
+        };
+
+        let token = page.next_page_token();
+        assert_eq!(parse_page_token(&token).unwrap(), page.positions);
+        assert_eq!(DeadLetterPage::default().next_page_token(), "");
+        assert!(parse_page_token("").unwrap().is_empty());
+        assert!(parse_page_token("not a token").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_page_token_round_trips_any_positions(positions in prop::collection::btree_map(any::<i32>(), any::<i64>(), 1..16)) {
+            let page = DeadLetterPage {
+                letters: Vec::new(),
+                positions,
+            };
+            prop_assert_eq!(parse_page_token(&page.next_page_token()).unwrap(), page.positions);
+        }
+
+        #[test]
+        fn prop_parse_page_token_never_panics(token in ".*", bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            use base64::Engine;
+            let _ = parse_page_token(&token);
+            let _ = parse_page_token(&base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes));
+        }
+
+        /// Anyone can write to the DLQ topic, so envelope headers are
+        /// untrusted; missing or garbled fields fall back to defaults.
+        #[test]
+        fn prop_envelope_from_garbled_headers(
+            fields in prop::collection::vec(
+                (
+                    prop_oneof![
+                        Just(HEADER_ORIGINAL_TOPIC.to_string()),
+                        Just(HEADER_ORIGINAL_PARTITION.to_string()),
+                        Just(HEADER_ORIGINAL_OFFSET.to_string()),
+                        Just(HEADER_ERROR.to_string()),
+                        Just(HEADER_ERROR_CAUSES.to_string()),
+                        Just(HEADER_ERROR_CONTEXT.to_string()),
+                        Just(HEADER_ATTEMPTS.to_string()),
+                        Just(HEADER_FAILED_AT.to_string()),
+                        ".*",
+                    ],
+                    prop::option::of(prop::collection::vec(any::<u8>(), 0..32)),
+                ),
+                0..12,
+            ),
+        ) {
+            let headers = fields.iter().fold(OwnedHeaders::new(), |headers, (key, value)| {
+                headers.insert(Header {
+                    key: key.as_str(),
+                    value: value.as_deref(),
+                })
+            });
+            let has_topic = fields
+                .iter()
+                .find(|(key, _)| key == HEADER_ORIGINAL_TOPIC)
+                .and_then(|(_, value)| value.as_deref())
+                .map_or(false, |value| std::str::from_utf8(value).is_ok());
+            prop_assert_eq!(Envelope::from_headers(Some(&headers)).is_some(), has_topic);
+        }
+    }
+}
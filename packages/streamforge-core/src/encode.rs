@@ -0,0 +1,307 @@
+//! How a Kafka sink encodes the records it produces, independently of the
+//! format its sources were decoded from.
+
+use anyhow::Result;
+use prost::Message;
+use prost_reflect::{DescriptorPool, DeserializeOptions, DynamicMessage, MessageDescriptor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use tokio::sync::OnceCell;
+use tracing::info;
+
+use crate::config::SchemaRegistryConfig;
+
+/// First byte of a record in the Confluent wire format.
+const MAGIC_BYTE: u8 = 0;
+
+/// The `format` option of a kafka sink. Without one, records are produced
+/// as JSON, as before the option existed.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    /// MessagePack, with maps keyed by field name.
+    Msgpack,
+    /// Avro binary in the Confluent wire format: the magic byte and the
+    /// big-endian schema id, then the datum. `schema` is registered with
+    /// `kafka.schema_registry` under `subject`, `<topic>-value` by default,
+    /// before the first record is produced.
+    Avro {
+        schema: Value,
+        #[serde(default)]
+        subject: Option<String>,
+    },
+    /// Protobuf, each record encoded as `message` from a descriptor set
+    /// compiled with `protoc --include_imports --descriptor_set_out`.
+    /// Fields are matched by their JSON names; record fields the message
+    /// does not have are left out.
+    Protobuf { descriptor_set: PathBuf, message: String },
+}
+
+impl OutputFormat {
+    /// Reads the `format` option from a kafka sink's config.
+    pub fn from_sink_config(config: &Value) -> Result<Self, String> {
+        match config.get("format") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => {
+                let format = Self::deserialize(value).map_err(|e| e.to_string())?;
+                match &format {
+                    Self::Avro { schema, .. } => {
+                        apache_avro::Schema::parse(schema).map_err(|e| format!("invalid avro schema: {}", e))?;
+                    }
+                    Self::Protobuf { message, .. } if message.is_empty() => {
+                        return Err("protobuf output requires a message".to_string());
+                    }
+                    _ => {}
+                }
+                Ok(format)
+            }
+        }
+    }
+}
+
+/// Encodes the output of one kafka sink. Shared by every worker producing
+/// to the sink, so an Avro schema is registered once.
+pub struct Encoder {
+    kind: Kind,
+}
+
+enum Kind {
+    Json,
+    Msgpack,
+    Avro(Box<AvroEncoder>),
+    Protobuf(MessageDescriptor),
+}
+
+struct AvroEncoder {
+    schema: apache_avro::Schema,
+    /// The schema as written in the sink, as it is registered.
+    definition: String,
+    subject: String,
+    registry: SchemaRegistry,
+    id: OnceCell<u32>,
+}
+
+impl Encoder {
+    /// `registry` is required for Avro output.
+    pub fn new(format: &OutputFormat, topic: &str, registry: Option<&SchemaRegistryConfig>) -> Result<Self> {
+        let kind = match format {
+            OutputFormat::Json => Kind::Json,
+            OutputFormat::Msgpack => Kind::Msgpack,
+            OutputFormat::Avro { schema, subject } => {
+                let registry = registry
+                    .ok_or_else(|| anyhow::anyhow!("Avro output to {} requires kafka.schema_registry", topic))?;
+                Kind::Avro(Box::new(AvroEncoder {
+                    schema: apache_avro::Schema::parse(schema)
+                        .map_err(|e| anyhow::anyhow!("Failed to parse Avro schema for {}: {}", topic, e))?,
+                    definition: schema.to_string(),
+                    subject: subject.clone().unwrap_or_else(|| format!("{}-value", topic)),
+                    registry: SchemaRegistry::new(registry)?,
+                    id: OnceCell::new(),
+                }))
+            }
+            OutputFormat::Protobuf { descriptor_set, message } => {
+                let bytes = std::fs::read(descriptor_set).map_err(|e| {
+                    anyhow::anyhow!("Failed to read descriptor set {}: {}", descriptor_set.display(), e)
+                })?;
+                let pool = DescriptorPool::decode(bytes.as_slice()).map_err(|e| {
+                    anyhow::anyhow!("Failed to parse descriptor set {}: {}", descriptor_set.display(), e)
+                })?;
+                let descriptor = pool.get_message_by_name(message).ok_or_else(|| {
+                    anyhow::anyhow!("Descriptor set {} has no message {}", descriptor_set.display(), message)
+                })?;
+                Kind::Protobuf(descriptor)
+            }
+        };
+        Ok(Self { kind })
+    }
+
+    pub async fn encode(&self, record: &Value) -> Result<Vec<u8>> {
+        match &self.kind {
+            Kind::Json => Ok(serde_json::to_vec(record)?),
+            Kind::Msgpack => {
+                rmp_serde::to_vec_named(record).map_err(|e| anyhow::anyhow!("Failed to encode MessagePack: {}", e))
+            }
+            Kind::Avro(avro) => {
+                let id = avro
+                    .id
+                    .get_or_try_init(|| async {
+                        let id = avro.registry.register(&avro.subject, &avro.definition).await?;
+                        info!("Registered Avro schema {} under subject {}", id, avro.subject);
+                        Ok::<_, anyhow::Error>(id)
+                    })
+                    .await?;
+                avro.encode(*id, record)
+            }
+            Kind::Protobuf(descriptor) => {
+                let options = DeserializeOptions::new().deny_unknown_fields(false);
+                let message = DynamicMessage::deserialize_with_options(descriptor.clone(), record, &options)
+                    .map_err(|e| anyhow::anyhow!("Failed to encode {} as protobuf: {}", descriptor.full_name(), e))?;
+                Ok(message.encode_to_vec())
+            }
+        }
+    }
+}
+
+impl AvroEncoder {
+    fn encode(&self, id: u32, record: &Value) -> Result<Vec<u8>> {
+        let value = apache_avro::to_value(record)
+            .and_then(|value| value.resolve(&self.schema))
+            .map_err(|e| anyhow::anyhow!("Record does not match the Avro schema of {}: {}", self.subject, e))?;
+        let datum = apache_avro::to_avro_datum(&self.schema, value)
+            .map_err(|e| anyhow::anyhow!("Failed to encode Avro: {}", e))?;
+
+        let mut payload = Vec::with_capacity(5 + datum.len());
+        payload.push(MAGIC_BYTE);
+        payload.extend_from_slice(&id.to_be_bytes());
+        payload.extend_from_slice(&datum);
+        Ok(payload)
+    }
+}
+
+/// Client of a Confluent-compatible schema registry.
+pub struct SchemaRegistry {
+    client: reqwest::Client,
+    config: SchemaRegistryConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct Registered {
+    id: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Registration<'a> {
+    schema: &'a str,
+    schema_type: &'a str,
+}
+
+impl SchemaRegistry {
+    pub fn new(config: &SchemaRegistryConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to create schema registry client: {}", e))?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+        })
+    }
+
+    /// Registers an Avro `schema` under `subject` and returns its id. A
+    /// schema the subject already has keeps its id, so registering again on
+    /// every start is harmless.
+    pub async fn register(&self, subject: &str, schema: &str) -> Result<u32> {
+        let url = format!("{}/subjects/{}/versions", self.config.url.trim_end_matches('/'), subject);
+        let mut request = self
+            .client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/vnd.schemaregistry.v1+json")
+            .json(&Registration {
+                schema,
+                schema_type: "AVRO",
+            });
+        if let Some(username) = &self.config.username {
+            request = request.basic_auth(username, self.config.password.as_ref());
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to register schema under {}: {}", subject, e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Failed to register schema under {}: {} {}", subject, status, body));
+        }
+        let registered: Registered = response
+            .json()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to parse schema registry response: {}", e))?;
+        Ok(registered.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apache_avro::types::Value as AvroValue;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn avro_encoder(schema: Value) -> AvroEncoder {
+        AvroEncoder {
+            schema: apache_avro::Schema::parse(&schema).unwrap(),
+            definition: schema.to_string(),
+            subject: "metrics-value".to_string(),
+            registry: SchemaRegistry::new(&SchemaRegistryConfig {
+                url: "http://localhost:8081".to_string(),
+                username: None,
+                password: None,
+                timeout: Duration::from_secs(1),
+            })
+            .unwrap(),
+            id: OnceCell::new(),
+        }
+    }
+
+    #[test]
+    fn test_sink_format_option() {
+        assert_eq!(OutputFormat::from_sink_config(&json!({"topic": "out"})), Ok(OutputFormat::Json));
+        assert_eq!(
+            OutputFormat::from_sink_config(&json!({"format": {"type": "msgpack"}})),
+            Ok(OutputFormat::Msgpack)
+        );
+        let avro = json!({"format": {"type": "avro", "schema": {"type": "nope"}}});
+        assert!(OutputFormat::from_sink_config(&avro).is_err());
+        assert!(OutputFormat::from_sink_config(&json!({"format": {"type": "protobuf"}})).is_err());
+        assert!(OutputFormat::from_sink_config(&json!({"format": {"type": "xml"}})).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_msgpack_round_trips() {
+        let encoder = Encoder::new(&OutputFormat::Msgpack, "out", None).unwrap();
+        let record = json!({"name": "cpu", "value": 0.5, "labels": {"host": "web-1"}});
+        let payload = encoder.encode(&record).await.unwrap();
+        assert_eq!(rmp_serde::from_slice::<Value>(&payload).unwrap(), record);
+    }
+
+    #[test]
+    fn test_avro_uses_the_confluent_wire_format() {
+        let schema = json!({
+            "type": "record",
+            "name": "Metric",
+            "fields": [
+                {"name": "name", "type": "string"},
+                {"name": "value", "type": "double"},
+                {"name": "host", "type": ["null", "string"], "default": null},
+            ],
+        });
+        let avro = avro_encoder(schema);
+
+        let payload = avro.encode(42, &json!({"name": "cpu", "value": 0.5, "extra": true})).unwrap();
+        assert_eq!(&payload[..5], &[0, 0, 0, 0, 42]);
+        assert_eq!(
+            apache_avro::from_avro_datum(&avro.schema, &mut &payload[5..], None).unwrap(),
+            AvroValue::Record(vec![
+                ("name".to_string(), AvroValue::String("cpu".to_string())),
+                ("value".to_string(), AvroValue::Double(0.5)),
+                ("host".to_string(), AvroValue::Union(0, Box::new(AvroValue::Null))),
+            ])
+        );
+
+        assert!(avro.encode(42, &json!({"name": "cpu"})).is_err());
+    }
+
+    #[test]
+    fn test_avro_output_requires_a_registry() {
+        let format = OutputFormat::Avro {
+            schema: json!("string"),
+            subject: None,
+        };
+        assert!(Encoder::new(&format, "out", None).is_err());
+    }
+}
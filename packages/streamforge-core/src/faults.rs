@@ -0,0 +1,136 @@
+use anyhow::Result;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::config::{Config, FaultRates};
+
+/// What is done to one operation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    Error,
+    Delay(Duration),
+    Duplicate,
+}
+
+/// Injects the faults configured under `faults` into the operations it
+/// wraps. Without fault injection enabled it runs them untouched.
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector {
+    rates: Option<FaultRates>,
+}
+
+impl FaultInjector {
+    pub fn new(rates: FaultRates) -> Self {
+        Self { rates: Some(rates) }
+    }
+
+    /// Injector for storage writes under `config`.
+    pub fn storage(config: &Config) -> Self {
+        if config.faults.enabled {
+            Self::new(config.faults.storage.clone())
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Injector for Kafka produces under `config`.
+    pub fn kafka(config: &Config) -> Self {
+        if config.faults.enabled {
+            Self::new(config.faults.kafka.clone())
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Draws the fault, if any, for the next operation.
+    pub fn pick(&self) -> Option<Fault> {
+        let rates = self.rates.as_ref()?;
+        let mut rng = rand::thread_rng();
+        let roll: f64 = rng.gen();
+
+        if roll < rates.error_rate {
+            Some(Fault::Error)
+        } else if roll < rates.error_rate + rates.delay_rate {
+            Some(Fault::Delay(rates.max_delay.mul_f64(rng.gen())))
+        } else if roll < rates.error_rate + rates.delay_rate + rates.duplicate_rate {
+            Some(Fault::Duplicate)
+        } else {
+            None
+        }
+    }
+
+    /// Runs `operation`, named for the error and log of an injected fault.
+    /// A duplicated operation returns the result of its second run.
+    pub async fn run<T, F, Fut>(&self, name: &str, operation: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        match self.pick() {
+            None => operation().await,
+            Some(Fault::Error) => {
+                debug!("Injecting failure into {}", name);
+                Err(anyhow::anyhow!("Injected fault: {} failed", name))
+            }
+            Some(Fault::Delay(delay)) => {
+                debug!("Injecting {:?} delay into {}", delay, name);
+                tokio::time::sleep(delay).await;
+                operation().await
+            }
+            Some(Fault::Duplicate) => {
+                debug!("Injecting duplicate of {}", name);
+                operation().await?;
+                operation().await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_injected_faults() {
+        let calls = &AtomicU32::new(0);
+        let operation = move || async move {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, anyhow::Error>(())
+        };
+
+        FaultInjector::default().run("write", operation).await.unwrap();
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 1);
+
+        let err = FaultInjector::new(FaultRates {
+            error_rate: 1.0,
+            ..Default::default()
+        })
+        .run("write", operation)
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Injected fault: write failed");
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 0);
+
+        FaultInjector::new(FaultRates {
+            duplicate_rate: 1.0,
+            ..Default::default()
+        })
+        .run("write", operation)
+        .await
+        .unwrap();
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 2);
+
+        let delayed = FaultInjector::new(FaultRates {
+            delay_rate: 1.0,
+            max_delay: Duration::from_millis(10),
+            ..Default::default()
+        });
+        match delayed.pick() {
+            Some(Fault::Delay(delay)) => assert!(delay <= Duration::from_millis(10)),
+            fault => panic!("expected a delay, got {:?}", fault),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Headers carried from consumed messages to the records a pipeline produces.
+
+use opentelemetry::Context;
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::dlq::ENVELOPE_HEADERS;
+use crate::telemetry;
+
+/// Id of the pipeline that produced a record, added by every kafka sink.
+pub const HEADER_PIPELINE_ID: &str = "x-pipeline-id";
+
+/// Schema of the produced payload, for transforms that know it.
+pub const HEADER_SCHEMA_ID: &str = "x-schema-id";
+
+/// Headers of one record, in order and with unique keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageHeaders(Vec<(String, Vec<u8>)>);
+
+impl MessageHeaders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The consumed message's headers, to carry through the pipeline. Trace
+    /// context is left out because produced records carry the context they
+    /// were produced under, and so is any dead letter envelope, which only
+    /// describes the copy in the DLQ.
+    pub fn from_kafka(headers: Option<&impl Headers>) -> Self {
+        let mut carried = Self::new();
+        for header in headers.into_iter().flat_map(|headers| headers.iter()) {
+            if telemetry::is_propagation_header(header.key) || ENVELOPE_HEADERS.contains(&header.key) {
+                continue;
+            }
+            carried.insert(header.key, header.value.unwrap_or_default());
+        }
+        carried
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_slice())
+    }
+
+    /// Sets `key`, replacing any value it already had in place.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Vec<u8>>) {
+        let (key, value) = (key.into(), value.into());
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.0.push((key, value)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Vec<u8>> {
+        let index = self.0.iter().position(|(k, _)| k == key)?;
+        Some(self.0.remove(index).1)
+    }
+
+    /// Sets every header of `other`, which wins on conflicts.
+    pub fn extend(&mut self, other: &MessageHeaders) {
+        for (key, value) in other.iter() {
+            self.insert(key, value);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Kafka headers for a record produced under `context`, with its trace
+    /// context appended.
+    pub fn to_kafka(&self, context: &Context) -> OwnedHeaders {
+        let headers = self.0.iter().fold(OwnedHeaders::new(), |headers, (key, value)| {
+            headers.insert(Header {
+                key,
+                value: Some(value),
+            })
+        });
+        telemetry::inject_context(context, headers)
+    }
+}
+
+/// Headers a kafka sink sets on every record: the pipeline id and the
+/// string values of the sink's `headers` config.
+pub fn sink_headers(pipeline_id: &str, config: &Value) -> Result<MessageHeaders, String> {
+    let mut headers = MessageHeaders::new();
+    headers.insert(HEADER_PIPELINE_ID, pipeline_id);
+    match config.get("headers") {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(fields)) => {
+            for (key, value) in fields {
+                let value = value
+                    .as_str()
+                    .ok_or_else(|| format!("header {} must be a string", key))?;
+                let reserved = telemetry::is_propagation_header(key) || ENVELOPE_HEADERS.contains(&key.as_str());
+                if key.is_empty() || reserved {
+                    return Err(format!("header {:?} is reserved", key));
+                }
+                headers.insert(key.as_str(), value);
+            }
+        }
+        Some(_) => return Err("headers must be an object of string values".to_string()),
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dlq::HEADER_ERROR;
+    use serde_json::json;
+
+    #[test]
+    fn test_headers_carry_through_and_sink_headers_win() {
+        let consumed = OwnedHeaders::new()
+            .insert(Header { key: "x-tenant-id", value: Some("acme") })
+            .insert(Header { key: HEADER_SCHEMA_ID, value: Some("7") })
+            .insert(Header { key: "traceparent", value: Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01") })
+            .insert(Header { key: HEADER_ERROR, value: Some("boom") });
+        let mut headers = MessageHeaders::from_kafka(Some(&consumed));
+        assert_eq!(
+            headers.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            vec!["x-tenant-id", HEADER_SCHEMA_ID]
+        );
+
+        // A transform that re-encodes the payload replaces its schema id
+        headers.insert(HEADER_SCHEMA_ID, "8");
+        let sink = sink_headers("pipeline-1", &json!({"topic": "out", "headers": {"x-team": "core"}})).unwrap();
+        headers.extend(&sink);
+        assert_eq!(headers.get(HEADER_SCHEMA_ID), Some(&b"8"[..]));
+        assert_eq!(headers.get(HEADER_PIPELINE_ID), Some(&b"pipeline-1"[..]));
+        assert_eq!(headers.get("x-team"), Some(&b"core"[..]));
+
+        let produced = headers.to_kafka(&Context::new());
+        assert_eq!(produced.count(), 4);
+
+        assert!(sink_headers("p", &json!({"headers": {"x-team": 1}})).is_err());
+        assert!(sink_headers("p", &json!({"headers": {"traceparent": "x"}})).is_err());
+        assert!(sink_headers("p", &json!({"headers": ["x-team"]})).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::config::ReadinessConfig;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+/// Component name under which consumer lag reports readiness.
+pub const CONSUMER_LAG_COMPONENT: &str = "consumer_lag";
+
+/// Component name under which the spill buffer reports readiness.
+pub const SPILL_COMPONENT: &str = "spill";
+
+/// Health of a single subsystem as reported to readiness probes.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub ready: bool,
+    pub detail: String,
+}
+
+/// Shared readiness registry. Subsystems report their own state under a
+/// stable component name; the instance is ready only when every registered
+/// component is.
+#[derive(Debug, Default)]
+pub struct HealthState {
+    components: RwLock<BTreeMap<&'static str, ComponentHealth>>,
+}
+
+impl HealthState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, component: &'static str, ready: bool, detail: impl Into<String>) {
+        let mut components = self.components.write().unwrap_or_else(|e| e.into_inner());
+        components.insert(
+            component,
+            ComponentHealth {
+                ready,
+                detail: detail.into(),
+            },
+        );
+    }
+
+    pub fn component(&self, component: &str) -> Option<ComponentHealth> {
+        let components = self.components.read().unwrap_or_else(|e| e.into_inner());
+        components.get(component).cloned()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        let components = self.components.read().unwrap_or_else(|e| e.into_inner());
+        components.values().all(|c| c.ready)
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<&'static str, ComponentHealth> {
+        self.components.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Holds readiness back while consumer lag is above
+/// `metrics.readiness.max_consumer_lag`, or with `startup_only` only until
+/// the lag first drops to it.
+#[derive(Debug, Clone)]
+pub struct LagGate {
+    max_lag: i64,
+    startup_only: bool,
+    caught_up: bool,
+}
+
+impl LagGate {
+    /// `None` when lag is left out of readiness.
+    pub fn new(config: &ReadinessConfig) -> Option<Self> {
+        Some(Self {
+            max_lag: config.max_consumer_lag?,
+            startup_only: config.startup_only,
+            caught_up: false,
+        })
+    }
+
+    /// Readiness and its detail for the lag summed over the assigned
+    /// partitions, `None` while none are assigned. An instance without
+    /// partitions has nothing to catch up on and is ready, but that does not
+    /// count as having caught up.
+    pub fn observe(&mut self, lag: Option<i64>) -> (bool, String) {
+        match lag {
+            _ if self.startup_only && self.caught_up => (true, "caught up".to_string()),
+            None => (true, "no partitions assigned".to_string()),
+            Some(lag) if lag <= self.max_lag => {
+                self.caught_up = true;
+                (true, format!("lag {}", lag))
+            }
+            Some(lag) => (false, format!("lag {} exceeds {}", lag, self.max_lag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ready_only_when_all_components_ready() {
+        let health = HealthState::new();
+        assert!(health.is_ready());
+
+        health.set("storage", false, "connecting");
+        health.set("kafka", true, "assigned");
+        assert!(!health.is_ready());
+
+        health.set("storage", true, "ok");
+        assert!(health.is_ready());
+        assert_eq!(health.snapshot().len(), 2);
+    }
+
+    #[test]
+    fn test_lag_gate_holds_readiness_until_caught_up() {
+        let mut config = ReadinessConfig::default();
+        assert!(LagGate::new(&config).is_none());
+
+        config.max_consumer_lag = Some(100);
+        let mut gate = LagGate::new(&config).unwrap();
+        assert!(gate.observe(None).0);
+        assert!(!gate.observe(Some(5_000)).0);
+        assert!(gate.observe(Some(100)).0);
+        assert!(gate.observe(Some(5_000)).0);
+
+        config.startup_only = false;
+        let mut gate = LagGate::new(&config).unwrap();
+        assert!(gate.observe(Some(50)).0);
+        assert_eq!(gate.observe(Some(5_000)), (false, "lag 5000 exceeds 100".to_string()));
+    }
+}
@@ -0,0 +1,420 @@
+use anyhow::Result;
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use streamforge_sdk::{Alert, Metric, ServiceStatus, WebSocketMessage};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info, warn};
+
+use crate::config::{HubConfig, SlowConsumerPolicy};
+use crate::metrics::{self, Metrics};
+use crate::processor::ProcessedMessage;
+
+/// Close code for connections dropped for falling behind (RFC 6455 "Try
+/// Again Later").
+const CLOSE_TRY_AGAIN_LATER: u16 = 1013;
+
+/// Fans processed metrics, alerts and service status out to connected
+/// WebSocket clients as the SDK's `WebSocketMessage`s. Publishing never waits
+/// on clients: every connection reads from a buffer of `hub.buffer` messages
+/// and is handled per `hub.slow_consumer` once it falls further behind.
+pub struct Hub {
+    tx: broadcast::Sender<Arc<WebSocketMessage>>,
+    slow_consumer: SlowConsumerPolicy,
+    metrics: Arc<Metrics>,
+}
+
+impl Hub {
+    pub fn new(config: &HubConfig, metrics: Arc<Metrics>) -> Self {
+        let (tx, _) = broadcast::channel(config.buffer.max(1));
+        Self {
+            tx,
+            slow_consumer: config.slow_consumer,
+            metrics,
+        }
+    }
+
+    pub fn publish_metrics(&self, metrics: Vec<Metric>) {
+        if !metrics.is_empty() {
+            self.publish(WebSocketMessage::Metrics { metrics });
+        }
+    }
+
+    pub fn publish_alerts(&self, alerts: Vec<Alert>) {
+        if !alerts.is_empty() {
+            self.publish(WebSocketMessage::Alerts { alerts });
+        }
+    }
+
+    pub fn publish_service_status(&self, services: Vec<ServiceStatus>) {
+        if !services.is_empty() {
+            self.publish(WebSocketMessage::ServiceStatus { services });
+        }
+    }
+
+    /// Publishes the processed messages shaped like an SDK `Alert` or
+    /// `Metric`. Other messages are only stored.
+    pub fn publish_processed(&self, messages: &[ProcessedMessage]) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+
+        let mut metrics = Vec::new();
+        let mut alerts = Vec::new();
+        for message in messages {
+            let value = &message.processed_message;
+            if let Ok(alert) = Alert::deserialize(value) {
+                alerts.push(alert);
+            } else if let Ok(metric) = Metric::deserialize(value) {
+                metrics.push(metric);
+            }
+        }
+        self.publish_metrics(metrics);
+        self.publish_alerts(alerts);
+    }
+
+    fn publish(&self, message: WebSocketMessage) {
+        // Only fails when no client is connected
+        let _ = self.tx.send(Arc::new(message));
+    }
+
+    async fn serve_connection(self: Arc<Self>, mut socket: WebSocket, mut subscription: Subscription) {
+        let mut rx = self.tx.subscribe();
+        self.metrics.hub_connections.inc();
+
+        loop {
+            tokio::select! {
+                received = rx.recv() => match received {
+                    Ok(message) => {
+                        let Some(message) = subscription.select(&message) else {
+                            continue;
+                        };
+                        let text = match serde_json::to_string(&message) {
+                            Ok(text) => text,
+                            Err(e) => {
+                                error!("Failed to encode hub message: {}", e);
+                                continue;
+                            }
+                        };
+                        // A client that stops reading blocks here until the
+                        // buffer overflows and the slow consumer policy applies
+                        if socket.send(Message::Text(text)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => match self.slow_consumer {
+                        SlowConsumerPolicy::Drop => {
+                            debug!("WebSocket client fell behind; skipped {} messages", missed);
+                            self.metrics.increment_hub_messages_dropped(missed);
+                        }
+                        SlowConsumerPolicy::Disconnect => {
+                            warn!("Disconnecting WebSocket client that fell {} messages behind", missed);
+                            self.metrics.increment_hub_slow_consumer_disconnects();
+                            let close = CloseFrame {
+                                code: CLOSE_TRY_AGAIN_LATER,
+                                reason: "client fell behind".into(),
+                            };
+                            let _ = socket.send(Message::Close(Some(close))).await;
+                            break;
+                        }
+                    },
+                    Err(RecvError::Closed) => break,
+                },
+                incoming = socket.recv() => match incoming {
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str::<Control>(&text) {
+                        Ok(Control::Subscribe(updated)) => subscription = updated,
+                        Err(e) => debug!("Ignoring WebSocket client message: {}", e),
+                    },
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // Pings are answered by axum
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+
+        self.metrics.hub_connections.dec();
+    }
+}
+
+/// Kinds of `WebSocketMessage`, by their `type` tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    Metrics,
+    Alerts,
+    ServiceStatus,
+}
+
+impl FromStr for MessageKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "metrics" => Ok(Self::Metrics),
+            "alerts" => Ok(Self::Alerts),
+            "service_status" => Ok(Self::ServiceStatus),
+            other => Err(anyhow::anyhow!(
+                "unknown message type {:?}; expected metrics, alerts or service_status",
+                other
+            )),
+        }
+    }
+}
+
+/// What one connection receives. Empty sets match everything.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Subscription {
+    #[serde(default)]
+    pub types: BTreeSet<MessageKind>,
+    /// Matches alerts by `service`, service status by `name` and metrics by
+    /// their `service` label.
+    #[serde(default)]
+    pub services: BTreeSet<String>,
+    /// Metric names.
+    #[serde(default)]
+    pub metrics: BTreeSet<String>,
+}
+
+impl Subscription {
+    /// The part of `message` this subscription wants, if any.
+    pub fn select(&self, message: &WebSocketMessage) -> Option<WebSocketMessage> {
+        let wants = |kind| self.types.is_empty() || self.types.contains(&kind);
+        let service = |name: Option<&String>| self.services.is_empty() || name.map_or(false, |n| self.services.contains(n));
+
+        let selected = match message {
+            WebSocketMessage::Metrics { metrics } if wants(MessageKind::Metrics) => WebSocketMessage::Metrics {
+                metrics: metrics
+                    .iter()
+                    .filter(|m| self.metrics.is_empty() || self.metrics.contains(&m.name))
+                    .filter(|m| service(m.labels.as_ref().and_then(|labels| labels.get("service"))))
+                    .cloned()
+                    .collect(),
+            },
+            WebSocketMessage::Alerts { alerts } if wants(MessageKind::Alerts) => WebSocketMessage::Alerts {
+                alerts: alerts.iter().filter(|a| service(Some(&a.service))).cloned().collect(),
+            },
+            WebSocketMessage::ServiceStatus { services } if wants(MessageKind::ServiceStatus) => {
+                WebSocketMessage::ServiceStatus {
+                    services: services.iter().filter(|s| service(Some(&s.name))).cloned().collect(),
+                }
+            }
+            _ => return None,
+        };
+
+        let empty = match &selected {
+            WebSocketMessage::Metrics { metrics } => metrics.is_empty(),
+            WebSocketMessage::Alerts { alerts } => alerts.is_empty(),
+            WebSocketMessage::ServiceStatus { services } => services.is_empty(),
+        };
+        (!empty).then_some(selected)
+    }
+}
+
+/// Messages clients may send; `{"type": "subscribe", ...}` replaces the
+/// connection's subscription.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Control {
+    Subscribe(Subscription),
+}
+
+/// Connection query parameters. The initial subscription is given as
+/// comma-separated lists, e.g. `?types=alerts&services=api,web`.
+#[derive(Debug, Default, Deserialize)]
+struct ConnectParams {
+    api_key: Option<String>,
+    types: Option<String>,
+    services: Option<String>,
+    metrics: Option<String>,
+}
+
+impl ConnectParams {
+    fn subscription(&self) -> Result<Subscription> {
+        let list = |value: &Option<String>| -> BTreeSet<String> {
+            value
+                .iter()
+                .flat_map(|v| v.split(','))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        Ok(Subscription {
+            types: list(&self.types).iter().map(|t| t.parse()).collect::<Result<_>>()?,
+            services: list(&self.services),
+            metrics: list(&self.metrics),
+        })
+    }
+}
+
+#[derive(Clone)]
+struct ServerState {
+    hub: Arc<Hub>,
+    api_key: Option<String>,
+}
+
+/// Accepts WebSocket clients on `/` until `shutdown` resolves.
+pub async fn start_server<F>(config: HubConfig, hub: Arc<Hub>, shutdown: F) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let addr = config.address()?;
+    let state = ServerState {
+        hub,
+        api_key: config.api_key,
+    };
+    let app = Router::new().route("/", get(connect_handler)).with_state(state);
+
+    info!("WebSocket hub listening on {}", addr);
+
+    axum::Server::try_bind(&addr)
+        .map_err(|e| anyhow::anyhow!("Failed to bind WebSocket hub to {}: {}", addr, e))?
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(|e| anyhow::anyhow!("WebSocket hub error: {}", e))?;
+
+    info!("WebSocket hub stopped");
+    Ok(())
+}
+
+async fn connect_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<ConnectParams>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    if let Some(expected) = &state.api_key {
+        let given = params.api_key.as_deref().unwrap_or_default();
+        if !metrics::constant_time_eq(given.as_bytes(), expected.as_bytes()) {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+
+    let subscription = match params.subscription() {
+        Ok(subscription) => subscription,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let hub = state.hub;
+    upgrade.on_upgrade(move |socket| hub.serve_connection(socket, subscription))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbitrary;
+    use crate::config::MetricsConfig;
+    use crate::processor::ProcessingMetadata;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+
+    fn metric(name: &str, service: &str) -> Metric {
+        Metric {
+            name: name.to_string(),
+            value: 1.0,
+            unit: "ms".to_string(),
+            labels: Some(HashMap::from([("service".to_string(), service.to_string())])),
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_subscription_filters_messages() {
+        let params = ConnectParams {
+            types: Some("metrics, service_status".to_string()),
+            services: Some("api".to_string()),
+            ..Default::default()
+        };
+        let subscription = params.subscription().unwrap();
+
+        let message = WebSocketMessage::Metrics {
+            metrics: vec![metric("latency", "api"), metric("latency", "web")],
+        };
+        match subscription.select(&message) {
+            Some(WebSocketMessage::Metrics { metrics }) => {
+                assert_eq!(metrics.len(), 1);
+                assert_eq!(metrics[0].labels.as_ref().unwrap()["service"], "api");
+            }
+            other => panic!("unexpected selection: {:?}", other),
+        }
+
+        let alerts = WebSocketMessage::Alerts {
+            alerts: vec![Alert {
+                id: "a1".to_string(),
+                severity: "critical".to_string(),
+                message: "down".to_string(),
+                timestamp: 0,
+                service: "api".to_string(),
+                metadata: None,
+            }],
+        };
+        assert!(subscription.select(&alerts).is_none());
+        assert!(Subscription::default().select(&alerts).is_some());
+
+        let web_only = WebSocketMessage::Metrics {
+            metrics: vec![metric("latency", "web")],
+        };
+        assert!(subscription.select(&web_only).is_none());
+
+        let control: Control = serde_json::from_str(r#"{"type": "subscribe", "types": ["alerts"]}"#).unwrap();
+        let Control::Subscribe(updated) = control;
+        assert!(updated.select(&alerts).is_some());
+        assert!(ConnectParams {
+            types: Some("logs".to_string()),
+            ..Default::default()
+        }
+        .subscription()
+        .is_err());
+    }
+
+    proptest! {
+        /// Processed messages can hold anything a producer sent; only those
+        /// shaped like an alert or metric reach clients, each exactly once.
+        #[test]
+        fn prop_publish_processed_classifies_arbitrary_values(values in prop::collection::vec(arbitrary::json(), 0..16)) {
+            let metrics = Arc::new(Metrics::new(&MetricsConfig::default()).unwrap());
+            let hub = Hub::new(&HubConfig::default(), metrics);
+            let mut rx = hub.tx.subscribe();
+
+            let messages: Vec<ProcessedMessage> = values
+                .iter()
+                .map(|value| ProcessedMessage {
+                    id: "id".to_string(),
+                    original_message: value.clone(),
+                    processed_message: value.clone(),
+                    processing_metadata: ProcessingMetadata {
+                        tenant_id: "tenant-a".to_string(),
+                        processed_at: chrono::Utc::now(),
+                        processor_version: "1.0.0".to_string(),
+                        source_topic: "metrics".to_string(),
+                        partition: 0,
+                        offset: 0,
+                        traceparent: None,
+                    },
+                })
+                .collect();
+            hub.publish_processed(&messages);
+
+            let mut published = 0;
+            while let Ok(message) = rx.try_recv() {
+                published += match message.as_ref() {
+                    WebSocketMessage::Metrics { metrics } => metrics.len(),
+                    WebSocketMessage::Alerts { alerts } => alerts.len(),
+                    WebSocketMessage::ServiceStatus { .. } => 0,
+                };
+            }
+            let expected = values
+                .iter()
+                .filter(|value| Alert::deserialize(*value).is_ok() || Metric::deserialize(*value).is_ok())
+                .count();
+            prop_assert_eq!(published, expected);
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! `join` transforms: windowed stream-stream joins of two sources on a key,
+//! such as orders with the payments made within ten minutes of them.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::columnar::lookup;
+use crate::pipeline::SourceSpec;
+use crate::table::set_path;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinType {
+    /// Passes left records once per matching right record.
+    #[default]
+    Inner,
+    /// As inner, and also passes left records that found no match by the
+    /// end of their window, with `into` set to null.
+    Left,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct JoinSpec {
+    /// Names of the two sources joined.
+    pub left: String,
+    pub right: String,
+    /// Dotted path to the join key in left records.
+    pub left_key: String,
+    /// Dotted path to the join key in right records; `left_key` if unset.
+    #[serde(default)]
+    pub right_key: Option<String>,
+    /// Most event time between two records that join.
+    pub within_seconds: u64,
+    #[serde(default)]
+    pub join_type: JoinType,
+    /// Dotted path the right record is written to in the left one; the
+    /// right source's name if unset.
+    #[serde(default)]
+    pub into: Option<String>,
+    /// Dotted path to the event time, as RFC 3339 or epoch milliseconds.
+    /// Records without it are timed by when they are processed.
+    #[serde(default = "default_time_field")]
+    pub time_field: String,
+    /// Records held per side; past it the oldest leave the window early.
+    #[serde(default = "default_max_buffered")]
+    pub max_buffered: usize,
+}
+
+fn default_time_field() -> String {
+    "timestamp".to_string()
+}
+
+fn default_max_buffered() -> usize {
+    100_000
+}
+
+impl JoinSpec {
+    pub fn from_config(config: &Value) -> Result<Self, String> {
+        let spec = Self::deserialize(config).map_err(|e| e.to_string())?;
+        if spec.left == spec.right {
+            return Err("left and right must be different sources".to_string());
+        }
+        if spec.left_key.is_empty() || spec.right_key.as_deref() == Some("") || spec.into.as_deref() == Some("") {
+            return Err("left_key, right_key and into must not be empty".to_string());
+        }
+        if spec.within_seconds == 0 || spec.max_buffered == 0 {
+            return Err("within_seconds and max_buffered must be greater than 0".to_string());
+        }
+        Ok(spec)
+    }
+
+    fn right_key(&self) -> &str {
+        self.right_key.as_deref().unwrap_or(&self.left_key)
+    }
+
+    fn into(&self) -> &str {
+        self.into.as_deref().unwrap_or(&self.right)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Runs one `join` transform. Each side's records wait in a buffer for
+/// matches from the other side until they fall out of the window, which
+/// moves with the latest event time seen on either side. The buffers can
+/// be saved with a job snapshot and restored from it.
+pub struct StreamJoin {
+    spec: JoinSpec,
+    left_topics: HashSet<String>,
+    right_topics: HashSet<String>,
+    state: Mutex<State>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    stream_time: Option<DateTime<Utc>>,
+    left: Buffer,
+    right: Buffer,
+}
+
+/// Records of one side by join key.
+#[derive(Default, Serialize, Deserialize)]
+struct Buffer {
+    records: HashMap<String, Vec<Buffered>>,
+    len: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Buffered {
+    record: Value,
+    at: DateTime<Utc>,
+    matched: bool,
+}
+
+impl StreamJoin {
+    /// `sources` are the pipeline's, which the spec's sides are named from.
+    pub fn new(spec: JoinSpec, sources: &[SourceSpec]) -> Result<Self, String> {
+        let topics = |name: &str| {
+            sources
+                .iter()
+                .find(|source| source.name == name)
+                .map(|source| source.topics.iter().cloned().collect::<HashSet<_>>())
+                .ok_or_else(|| format!("source {} is not defined", name))
+        };
+        let left_topics = topics(&spec.left)?;
+        let right_topics = topics(&spec.right)?;
+        if let Some(shared) = left_topics.intersection(&right_topics).next() {
+            return Err(format!("sources {} and {} both read topic {}", spec.left, spec.right, shared));
+        }
+        Ok(Self {
+            spec,
+            left_topics,
+            right_topics,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Returns the records to pass on now for `record` read from `topic`:
+    /// its joins with the other side's buffered records, and for left joins
+    /// the left records that have left the window unmatched. Records of
+    /// other sources pass through unchanged.
+    pub fn offer(&self, topic: &str, record: Value, now: DateTime<Utc>) -> Vec<Value> {
+        let side = if self.left_topics.contains(topic) {
+            Side::Left
+        } else if self.right_topics.contains(topic) {
+            Side::Right
+        } else {
+            return vec![record];
+        };
+        let key_path = match side {
+            Side::Left => self.spec.left_key.as_str(),
+            Side::Right => self.spec.right_key(),
+        };
+        let Some(key) = lookup(&record, key_path).and_then(key_text) else {
+            // Unkeyed records match nothing
+            return match (side, self.spec.join_type) {
+                (Side::Left, JoinType::Left) => vec![self.joined(record, Value::Null)],
+                _ => Vec::new(),
+            };
+        };
+        let at = lookup(&record, &self.spec.time_field).and_then(event_time).unwrap_or(now);
+        let within = self.within();
+
+        let mut state = self.state.lock().unwrap();
+        let stream_time = state.stream_time.map_or(at, |time| time.max(at));
+        state.stream_time = Some(stream_time);
+
+        let State { left, right, .. } = &mut *state;
+        let (own, other) = match side {
+            Side::Left => (left, right),
+            Side::Right => (right, left),
+        };
+        let mut passed = Vec::new();
+        let mut matched = false;
+        for candidate in other.records.get_mut(&key).into_iter().flatten() {
+            if (candidate.at - at).abs() > within {
+                continue;
+            }
+            candidate.matched = true;
+            matched = true;
+            passed.push(match side {
+                Side::Left => self.joined(record.clone(), candidate.record.clone()),
+                Side::Right => self.joined(candidate.record.clone(), record.clone()),
+            });
+        }
+
+        if at < stream_time - within {
+            // Too late to wait for matches
+            if side == Side::Left && !matched && self.spec.join_type == JoinType::Left {
+                passed.push(self.joined(record, Value::Null));
+            }
+        } else {
+            own.push(key, Buffered { record, at, matched });
+            while own.len > self.spec.max_buffered {
+                let Some(oldest) = own.remove_oldest() else {
+                    break;
+                };
+                if side == Side::Left {
+                    passed.extend(self.unmatched(vec![oldest]));
+                }
+            }
+        }
+        passed.extend(self.expire(&mut state, stream_time));
+        passed
+    }
+
+    /// Left joins: moves the window up to `now` and returns the left records
+    /// that left it unmatched. Call it on a timer for live streams, so they
+    /// are not held back while no newer records arrive.
+    pub fn flush(&self, now: DateTime<Utc>) -> Vec<Value> {
+        let mut state = self.state.lock().unwrap();
+        let stream_time = state.stream_time.map_or(now, |time| time.max(now));
+        state.stream_time = Some(stream_time);
+        self.expire(&mut state, stream_time)
+    }
+
+    /// The buffered records, for the job snapshot's keyed state.
+    pub fn snapshot(&self) -> Value {
+        serde_json::to_value(&*self.state.lock().unwrap()).unwrap_or_default()
+    }
+
+    /// Replaces the buffered records with those of a [`StreamJoin::snapshot`].
+    pub fn restore(&self, snapshot: &Value) -> Result<(), String> {
+        let restored = State::deserialize(snapshot).map_err(|e| e.to_string())?;
+        *self.state.lock().unwrap() = restored;
+        Ok(())
+    }
+
+    fn within(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.spec.within_seconds as i64)
+    }
+
+    fn expire(&self, state: &mut State, stream_time: DateTime<Utc>) -> Vec<Value> {
+        let cutoff = stream_time - self.within();
+        state.right.remove_before(cutoff);
+        let expired = state.left.remove_before(cutoff);
+        self.unmatched(expired)
+    }
+
+    fn unmatched(&self, expired: Vec<Buffered>) -> Vec<Value> {
+        if self.spec.join_type != JoinType::Left {
+            return Vec::new();
+        }
+        expired
+            .into_iter()
+            .filter(|buffered| !buffered.matched)
+            .map(|buffered| self.joined(buffered.record, Value::Null))
+            .collect()
+    }
+
+    fn joined(&self, mut left: Value, right: Value) -> Value {
+        set_path(&mut left, self.spec.into(), right);
+        left
+    }
+}
+
+impl Buffer {
+    fn push(&mut self, key: String, buffered: Buffered) {
+        self.records.entry(key).or_default().push(buffered);
+        self.len += 1;
+    }
+
+    /// Removes the records timed before `cutoff`, oldest first.
+    fn remove_before(&mut self, cutoff: DateTime<Utc>) -> Vec<Buffered> {
+        let mut removed = Vec::new();
+        for records in self.records.values_mut() {
+            if records.iter().any(|buffered| buffered.at < cutoff) {
+                let (expired, kept): (Vec<_>, Vec<_>) =
+                    std::mem::take(records).into_iter().partition(|buffered| buffered.at < cutoff);
+                *records = kept;
+                removed.extend(expired);
+            }
+        }
+        self.records.retain(|_, records| !records.is_empty());
+        self.len -= removed.len();
+        removed.sort_by_key(|buffered| buffered.at);
+        removed
+    }
+
+    fn remove_oldest(&mut self) -> Option<Buffered> {
+        let (key, index) = self
+            .records
+            .iter()
+            .flat_map(|(key, records)| records.iter().enumerate().map(move |(i, b)| ((key, i), b.at)))
+            .min_by_key(|(_, at)| *at)
+            .map(|((key, i), _)| (key.clone(), i))?;
+        let records = self.records.get_mut(&key).expect("key was just found");
+        let oldest = records.remove(index);
+        if records.is_empty() {
+            self.records.remove(&key);
+        }
+        self.len -= 1;
+        Some(oldest)
+    }
+}
+
+fn key_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn event_time(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|t| t.with_timezone(&Utc)),
+        Value::Number(n) => n.as_i64().and_then(DateTime::from_timestamp_millis),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sources() -> Vec<SourceSpec> {
+        ["orders", "payments"]
+            .iter()
+            .map(|name| SourceSpec {
+                name: name.to_string(),
+                topics: vec![name.to_string()],
+            })
+            .collect()
+    }
+
+    fn stream_join(join_type: &str) -> StreamJoin {
+        let config = json!({
+            "left": "orders", "right": "payments", "left_key": "order_id",
+            "within_seconds": 600, "join_type": join_type, "into": "payment",
+        });
+        StreamJoin::new(JoinSpec::from_config(&config).unwrap(), &sources()).unwrap()
+    }
+
+    fn at(start: DateTime<Utc>, minutes: i64) -> String {
+        (start + chrono::Duration::minutes(minutes)).to_rfc3339()
+    }
+
+    #[test]
+    fn test_records_join_within_the_window() {
+        let join = stream_join("inner");
+        let start = Utc::now();
+        let order = |id: u64, minute| json!({"order_id": id, "timestamp": at(start, minute)});
+        let payment = |id: u64, minute| json!({"order_id": id, "amount": 5, "timestamp": at(start, minute)});
+
+        assert!(join.offer("orders", order(1, 0), start).is_empty());
+        assert!(join.offer("orders", order(2, 0), start).is_empty());
+        let joined = join.offer("payments", payment(1, 5), start);
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0]["payment"]["amount"], json!(5));
+        assert_eq!(joined[0]["order_id"], json!(1));
+        // Order 2's payment comes too late
+        assert!(join.offer("payments", payment(2, 11), start).is_empty());
+        // Either side can arrive first
+        assert!(join.offer("payments", payment(3, 11), start).is_empty());
+        assert_eq!(join.offer("orders", order(3, 12), start).len(), 1);
+        assert_eq!(join.offer("other", json!({"x": 1}), start), vec![json!({"x": 1})]);
+
+        let restored = stream_join("inner");
+        restored.restore(&join.snapshot()).unwrap();
+        assert_eq!(restored.offer("orders", order(3, 13), start).len(), 1);
+    }
+
+    #[test]
+    fn test_left_join_passes_unmatched_records_when_they_expire() {
+        let join = stream_join("left");
+        let start = Utc::now();
+        join.offer("orders", json!({"order_id": "a", "timestamp": at(start, 0)}), start);
+        join.offer("orders", json!({"order_id": "b", "timestamp": at(start, 1)}), start);
+        assert_eq!(
+            join.offer("payments", json!({"order_id": "b", "timestamp": at(start, 2)}), start).len(),
+            1
+        );
+
+        let expired = join.offer("payments", json!({"order_id": "c", "timestamp": at(start, 20)}), start);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0]["order_id"], json!("a"));
+        assert_eq!(expired[0]["payment"], Value::Null);
+
+        join.offer("orders", json!({"order_id": "d", "timestamp": at(start, 20)}), start);
+        assert_eq!(join.flush(start + chrono::Duration::minutes(40)).len(), 1);
+
+        let config = json!({"left": "orders", "right": "orders", "left_key": "id", "within_seconds": 1});
+        assert!(JoinSpec::from_config(&config).is_err());
+        let config = json!({"left": "orders", "right": "refunds", "left_key": "id", "within_seconds": 1});
+        assert!(StreamJoin::new(JoinSpec::from_config(&config).unwrap(), &sources()).is_err());
+    }
+}
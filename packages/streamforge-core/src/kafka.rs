@@ -2,18 +2,49 @@ use crate::config::Config;
 use anyhow::Result;
 use rdkafka::client::ClientContext;
 use rdkafka::config::ClientConfig;
-use rdkafka::consumer::{Consumer, ConsumerContext, Rebalance, StreamConsumer};
+use rdkafka::consumer::{BaseConsumer, Consumer, ConsumerContext, Rebalance, StreamConsumer};
 use rdkafka::error::KafkaResult;
-use rdkafka::message::{Headers, OwnedHeaders};
-use rdkafka::producer::{FutureProducer, FutureRecord, ProducerContext};
+use rdkafka::message::Headers;
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer, ProducerContext};
 use rdkafka::topic_partition_list::TopicPartitionList;
 use rdkafka::{Offset, TopicPartitionList};
+use std::collections::BTreeSet;
 use std::sync::Arc;
 use std::time::Duration;
-use tracing::{error, info, warn};
+use tracing::{error, info, warn, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::config::Config;
+use crate::headers::MessageHeaders;
 use crate::metrics::Metrics;
+use crate::partitioning::Route;
+
+/// Consumer used by the pipeline.
+pub type PipelineConsumer = StreamConsumer<PipelineConsumerContext>;
+
+/// Tracks rebalances. Assignment itself is left to rdkafka, which applies
+/// cooperative rebalances incrementally; this only drops the state kept for
+/// the partitions actually revoked, so the rest are consumed without a gap.
+pub struct PipelineConsumerContext {
+    metrics: Arc<Metrics>,
+}
+
+impl ClientContext for PipelineConsumerContext {}
+
+impl ConsumerContext for PipelineConsumerContext {
+    fn pre_rebalance(&self, _consumer: &BaseConsumer<Self>, rebalance: &Rebalance<'_>) {
+        match rebalance {
+            Rebalance::Revoke(partitions) => {
+                info!("Revoking {} partition(s)", partitions.count());
+                for partition in partitions.elements() {
+                    self.metrics.remove_consumer_lag(partition.topic(), partition.partition());
+                }
+            }
+            Rebalance::Assign(partitions) => info!("Assigned {} partition(s)", partitions.count()),
+            Rebalance::Error(e) => warn!("Rebalance failed: {}", e),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct KafkaManager {
@@ -34,13 +65,16 @@ impl KafkaManager {
         })
     }
 
-    pub async fn create_consumer(&self) -> Result<StreamConsumer> {
+    pub async fn create_consumer(&self) -> Result<PipelineConsumer> {
         let consumer_config = self.config.kafka_consumer_config();
         
         info!("Creating Kafka consumer with group: {}", self.config.kafka.group_id);
         
-        let consumer: StreamConsumer = consumer_config
-            .create()
+        let context = PipelineConsumerContext {
+            metrics: self.metrics.clone(),
+        };
+        let consumer: PipelineConsumer = consumer_config
+            .create_with_context(context)
             .map_err(|e| anyhow::anyhow!("Failed to create Kafka consumer: {}", e))?;
 
         info!("Kafka consumer created successfully");
@@ -66,11 +100,13 @@ impl KafkaManager {
         topic: &str,
         key: Option<&str>,
         payload: &[u8],
+        headers: &MessageHeaders,
     ) -> Result<()> {
+        let headers = headers.to_kafka(&Span::current().context());
         let record = if let Some(key) = key {
-            FutureRecord::to(topic).key(key).payload(payload)
+            FutureRecord::to(topic).key(key).payload(payload).headers(headers)
         } else {
-            FutureRecord::to(topic).payload(payload)
+            FutureRecord::to(topic).payload(payload).headers(headers)
         };
 
         match producer.send(record, std::time::Duration::from_secs(5)).await {
@@ -89,17 +125,19 @@ impl KafkaManager {
         &self,
         producer: &FutureProducer,
         topic: &str,
-        messages: Vec<(Option<String>, Vec<u8>)>,
+        messages: Vec<(Option<String>, MessageHeaders, Vec<u8>)>,
     ) -> Result<()> {
         info!("Sending batch of {} messages to topic: {}", messages.len(), topic);
 
         let mut futures = Vec::new();
+        let context = Span::current().context();
 
-        for (key, payload) in messages {
+        for (key, headers, payload) in messages {
+            let headers = headers.to_kafka(&context);
             let record = if let Some(key) = key {
-                FutureRecord::to(topic).key(&key).payload(&payload)
+                FutureRecord::to(topic).key(&key).payload(&payload).headers(headers)
             } else {
-                FutureRecord::to(topic).payload(&payload)
+                FutureRecord::to(topic).payload(&payload).headers(headers)
             };
 
             futures.push(producer.send(record, std::time::Duration::from_secs(5)));
@@ -133,7 +171,55 @@ impl KafkaManager {
         Ok(())
     }
 
-    pub async fn get_consumer_lag(&self, consumer: &StreamConsumer) -> Result<Vec<(i32, i64)>> {
+    /// Like `send_batch_messages`, with each record keyed and partitioned by
+    /// its route from the sink's `Partitioner`.
+    pub async fn send_routed_messages(
+        &self,
+        producer: &FutureProducer,
+        topic: &str,
+        messages: Vec<(Route, MessageHeaders, Vec<u8>)>,
+    ) -> Result<()> {
+        let context = Span::current().context();
+        let futures = messages.iter().map(|(route, headers, payload)| {
+            let headers = headers.to_kafka(&context);
+            let mut record = FutureRecord::<[u8], [u8]>::to(topic).payload(payload).headers(headers);
+            if let Some(key) = &route.key {
+                record = record.key(key);
+            }
+            if let Some(partition) = route.partition {
+                record = record.partition(partition);
+            }
+            producer.send(record, std::time::Duration::from_secs(5))
+        });
+
+        let failed = futures::future::join_all(futures)
+            .await
+            .into_iter()
+            .filter_map(|result| result.err())
+            .inspect(|(e, _)| error!("Failed to send message to topic {}: {}", topic, e))
+            .count();
+        if failed > 0 {
+            return Err(anyhow::anyhow!("Failed to send {} of {} messages to {}", failed, messages.len(), topic));
+        }
+        Ok(())
+    }
+
+    /// Partition count of `topic`, which round-robin and preserve-source
+    /// routing need. Blocks on a metadata request.
+    pub fn partition_count(&self, producer: &FutureProducer, topic: &str) -> Result<i32> {
+        let metadata = producer
+            .client()
+            .fetch_metadata(Some(topic), Duration::from_secs(5))
+            .map_err(|e| anyhow::anyhow!("Failed to fetch metadata for topic {}: {}", topic, e))?;
+        metadata
+            .topics()
+            .first()
+            .map(|t| t.partitions().len() as i32)
+            .filter(|count| *count > 0)
+            .ok_or_else(|| anyhow::anyhow!("Topic not found: {}", topic))
+    }
+
+    pub async fn get_consumer_lag(&self, consumer: &PipelineConsumer) -> Result<Vec<(i32, i64)>> {
         let mut lag_info = Vec::new();
 
         for topic in &self.config.kafka.input_topics {
@@ -153,7 +239,7 @@ impl KafkaManager {
                     lag_info.push((partition.partition(), lag));
 
                     // Update metrics
-                    self.metrics.set_consumer_lag(partition.partition(), lag);
+                    self.metrics.set_consumer_lag(partition.topic(), partition.partition(), lag);
                 }
             }
         }
@@ -207,6 +293,20 @@ impl KafkaManager {
     }
 }
 
+/// Names of every topic on the cluster. Blocks for up to `timeout`, so call
+/// it from a blocking task.
+pub fn fetch_topic_names(config: &Config, timeout: Duration) -> Result<BTreeSet<String>> {
+    let consumer: BaseConsumer = config
+        .kafka_consumer_config()
+        .create()
+        .map_err(|e| anyhow::anyhow!("Failed to create Kafka consumer: {}", e))?;
+    let metadata = consumer
+        .fetch_metadata(None, timeout)
+        .map_err(|e| anyhow::anyhow!("Failed to fetch Kafka metadata: {}", e))?;
+
+    Ok(metadata.topics().iter().map(|t| t.name().to_string()).collect())
+}
+
 // Helper struct for managing Kafka message metadata
 #[derive(Debug, Clone)]
 pub struct KafkaMessageMetadata {
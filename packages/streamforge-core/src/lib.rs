@@ -0,0 +1,49 @@
+pub mod approx;
+#[cfg(test)]
+mod arbitrary;
+pub mod audit;
+pub mod backfill;
+pub mod batching;
+pub mod canary;
+pub mod cardinality;
+pub mod chunking;
+pub mod columnar;
+pub mod config;
+pub mod dag;
+pub mod decode;
+pub mod dlq;
+pub mod encode;
+pub mod error;
+pub mod faults;
+pub mod headers;
+pub mod health;
+pub mod hub;
+pub mod join;
+pub mod kafka;
+pub mod limits;
+pub mod log_metrics;
+pub mod memory;
+pub mod metrics;
+pub mod notify;
+pub mod offload;
+pub mod partitioning;
+pub mod patterns;
+pub mod pipeline;
+pub mod poison;
+pub mod processor;
+pub mod promql;
+pub mod rbac;
+pub mod recording;
+pub mod reshape;
+pub mod sampling;
+pub mod scheduler;
+pub mod schemas;
+pub mod seek;
+pub mod sessions;
+pub mod status;
+pub mod storage;
+pub mod table;
+pub mod telemetry;
+pub mod types;
+
+pub use error::{Error, Result}; 
\ No newline at end of file
@@ -0,0 +1,275 @@
+use crate::config::{RecordLimits, ResourceLimits};
+use anyhow::Result;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, ProcessRefreshKind, System};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+/// How often the memory limit re-reads process memory, and how long the
+/// consumer waits between checks while over it.
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Holds the consumer back until the next message fits within the job's
+/// [`ResourceLimits`]. Waiting here is the same backpressure as a full
+/// processing channel.
+pub struct Admission {
+    rate: Option<RateLimiter>,
+    inflight: Option<(Arc<Semaphore>, u32)>,
+    memory: Option<MemoryLimit>,
+}
+
+impl Admission {
+    pub fn new(limits: &ResourceLimits) -> Result<Self> {
+        let memory = limits.max_memory_bytes.map(MemoryLimit::new).transpose()?;
+        let inflight = limits.max_inflight_bytes.map(|max| {
+            let max = max.min(Semaphore::MAX_PERMITS as u64).min(u32::MAX as u64) as u32;
+            (Arc::new(Semaphore::new(max as usize)), max)
+        });
+
+        Ok(Self {
+            rate: limits.max_messages_per_second.map(RateLimiter::new),
+            inflight,
+            memory,
+        })
+    }
+
+    /// Waits until a message of `bytes` may be consumed. The returned permit
+    /// counts it against the in-flight limit until dropped; a message larger
+    /// than the whole limit is admitted alone.
+    pub async fn admit(&mut self, bytes: usize) -> Option<OwnedSemaphorePermit> {
+        if let Some(memory) = &mut self.memory {
+            memory.wait().await;
+        }
+        if let Some(rate) = &mut self.rate {
+            rate.acquire().await;
+        }
+        match &self.inflight {
+            Some((semaphore, max)) => {
+                let permits = (bytes.min(*max as usize) as u32).max(1);
+                semaphore.clone().acquire_many_owned(permits).await.ok()
+            }
+            None => None,
+        }
+    }
+}
+
+/// Token bucket allowing `per_second` messages a second, with bursts of up
+/// to one second's worth.
+pub struct RateLimiter {
+    per_second: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u32) -> Self {
+        let per_second = per_second.max(1) as f64;
+        Self {
+            per_second,
+            tokens: per_second,
+            updated: Instant::now(),
+        }
+    }
+
+    pub async fn acquire(&mut self) {
+        let delay = self.take(Instant::now());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Takes one token at `now`, returning how long to wait before using it.
+    fn take(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.per_second) - 1.0;
+        self.updated = now;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.per_second)
+        }
+    }
+}
+
+struct MemoryLimit {
+    max_bytes: u64,
+    system: System,
+    pid: Pid,
+}
+
+impl MemoryLimit {
+    fn new(max_bytes: u64) -> Result<Self> {
+        let pid = sysinfo::get_current_pid()
+            .map_err(|e| anyhow::anyhow!("Failed to determine current process id: {}", e))?;
+        Ok(Self {
+            max_bytes,
+            system: System::new(),
+            pid,
+        })
+    }
+
+    fn usage(&mut self) -> Option<u64> {
+        self.system
+            .refresh_process_specifics(self.pid, ProcessRefreshKind::new().with_memory())
+            .then(|| self.system.process(self.pid).map(|p| p.memory()))
+            .flatten()
+    }
+
+    async fn wait(&mut self) {
+        let mut warned = false;
+        while let Some(usage) = self.usage().filter(|usage| *usage > self.max_bytes) {
+            if !warned {
+                warn!(
+                    "Process memory {} bytes exceeds limit of {} bytes; holding consumption",
+                    usage, self.max_bytes
+                );
+                warned = true;
+            }
+            tokio::time::sleep(MEMORY_CHECK_INTERVAL).await;
+        }
+    }
+}
+
+/// Why [`check_record`] turned a record away.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordViolation {
+    Oversized { bytes: usize, max: usize },
+    TooDeep { max: usize },
+    TooManyFields { max: usize },
+    /// JSON whose brackets or strings do not close.
+    Malformed(&'static str),
+}
+
+impl RecordViolation {
+    /// Short name, used as the `reason` label and in the dead letter context.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            RecordViolation::Oversized { .. } => "oversized",
+            RecordViolation::TooDeep { .. } => "too_deep",
+            RecordViolation::TooManyFields { .. } => "too_many_fields",
+            RecordViolation::Malformed(_) => "malformed",
+        }
+    }
+}
+
+impl fmt::Display for RecordViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordViolation::Oversized { bytes, max } => {
+                write!(f, "payload of {} bytes exceeds the limit of {} bytes", bytes, max)
+            }
+            RecordViolation::TooDeep { max } => write!(f, "payload nests deeper than {} levels", max),
+            RecordViolation::TooManyFields { max } => write!(f, "payload has more than {} fields", max),
+            RecordViolation::Malformed(reason) => write!(f, "malformed JSON payload: {}", reason),
+        }
+    }
+}
+
+/// Checks `payload` against `limits` without parsing it. A payload starting
+/// with `{` or `[` is scanned once for its nesting and field count, stopping
+/// at the first limit it passes, so a pathological record costs no more
+/// than reading it.
+pub fn check_record(limits: &RecordLimits, payload: &[u8]) -> Result<(), RecordViolation> {
+    if let Some(max) = limits.max_payload_bytes.filter(|max| payload.len() > *max) {
+        return Err(RecordViolation::Oversized {
+            bytes: payload.len(),
+            max,
+        });
+    }
+    if limits.max_depth.is_none() && limits.max_fields.is_none() {
+        return Ok(());
+    }
+    if !matches!(payload.iter().find(|b| !b.is_ascii_whitespace()), Some(b'{') | Some(b'[')) {
+        return Ok(());
+    }
+
+    let max_depth = limits.max_depth.unwrap_or(usize::MAX);
+    let max_fields = limits.max_fields.unwrap_or(usize::MAX);
+    let (mut depth, mut fields) = (0usize, 0usize);
+    let (mut in_string, mut escaped) = (false, false);
+    for &byte in payload {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth > max_depth {
+                    return Err(RecordViolation::TooDeep { max: max_depth });
+                }
+            }
+            b'}' | b']' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or(RecordViolation::Malformed("unbalanced brackets"))?;
+            }
+            // Outside strings a colon only ever follows an object key
+            b':' => {
+                fields += 1;
+                if fields > max_fields {
+                    return Err(RecordViolation::TooManyFields { max: max_fields });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if in_string {
+        Err(RecordViolation::Malformed("unterminated string"))
+    } else if depth > 0 {
+        Err(RecordViolation::Malformed("unbalanced brackets"))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_record_enforces_each_limit() {
+        let limits = RecordLimits {
+            max_payload_bytes: Some(64),
+            max_depth: Some(2),
+            max_fields: Some(3),
+        };
+        assert_eq!(check_record(&limits, br#"{"a": [1, 2], "b": {"c": "x:{[\""}}"#), Ok(()));
+        assert_eq!(check_record(&limits, b"not json, so not scanned [[[["), Ok(()));
+        assert_eq!(
+            check_record(&limits, &[b' '; 65]),
+            Err(RecordViolation::Oversized { bytes: 65, max: 64 })
+        );
+        assert_eq!(check_record(&limits, b"[[[1]]]"), Err(RecordViolation::TooDeep { max: 2 }));
+        assert_eq!(
+            check_record(&limits, br#"{"a": 1, "b": 2, "c": 3, "d": 4}"#),
+            Err(RecordViolation::TooManyFields { max: 3 })
+        );
+        assert_eq!(check_record(&limits, br#"{"a": "open}"#).unwrap_err().reason(), "malformed");
+        assert_eq!(check_record(&limits, b"[1]]").unwrap_err().reason(), "malformed");
+        assert_eq!(check_record(&RecordLimits::default(), &[b'['; 1000]), Ok(()));
+    }
+
+    #[test]
+    fn test_rate_limiter_spaces_out_after_burst() {
+        let mut limiter = RateLimiter::new(2);
+        let start = limiter.updated;
+
+        assert_eq!(limiter.take(start), Duration::ZERO);
+        assert_eq!(limiter.take(start), Duration::ZERO);
+        assert_eq!(limiter.take(start), Duration::from_millis(500));
+        // The wait above is paid off; half a second later one more is due
+        assert_eq!(limiter.take(start + Duration::from_millis(500)), Duration::from_millis(500));
+        assert_eq!(limiter.take(start + Duration::from_secs(5)), Duration::ZERO);
+    }
+}
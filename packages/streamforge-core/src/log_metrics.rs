@@ -0,0 +1,347 @@
+//! Metrics derived from logs under `processing.log_metrics`: counts of
+//! matching logs and histograms of a numeric field, per label set.
+
+use chrono::Utc;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Instant;
+use streamforge_sdk::Metric;
+use tokio::sync::watch;
+
+use crate::approx::{HyperLogLog, SpaceSaving};
+use crate::columnar::lookup;
+use crate::config::{LogMetricKind, LogMetricRule, ProcessingConfig};
+use crate::processor::{ProcessedMessage, ProcessingMetadata};
+
+pub struct LogMetrics {
+    settings: watch::Receiver<ProcessingConfig>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    window_start: Option<Instant>,
+    series: HashMap<SeriesKey, Series>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    tenant_id: String,
+    rule: String,
+    labels: BTreeMap<String, String>,
+}
+
+struct Series {
+    /// The latest log counted, which the derived metrics are attributed to.
+    source: ProcessingMetadata,
+    value: Aggregate,
+}
+
+enum Aggregate {
+    Count(u64),
+    /// Logs per bucket, not cumulative; the last bucket is above every bound.
+    Histogram {
+        bounds: Vec<f64>,
+        unit: String,
+        buckets: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+    Distinct(HyperLogLog),
+    TopK {
+        k: usize,
+        label: String,
+        top: SpaceSaving,
+    },
+}
+
+/// What a rule takes from one log.
+enum Observation {
+    Log,
+    Number(f64),
+    Text(String),
+}
+
+impl LogMetrics {
+    pub fn new(settings: watch::Receiver<ProcessingConfig>) -> Self {
+        Self {
+            settings,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Counts `message` into every rule it matches. Logs are records with a
+    /// string `message` and `level`.
+    pub fn observe(&self, message: &ProcessedMessage) {
+        let settings = self.settings.borrow();
+        let rules = &settings.log_metrics.rules;
+        let record = &message.processed_message;
+        if rules.is_empty() || !is_log(record) {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        for rule in rules.iter().filter(|rule| matches(rule, record)) {
+            let observation = match &rule.kind {
+                LogMetricKind::Count => Observation::Log,
+                LogMetricKind::Histogram { field, .. } => match field_value(record, field).and_then(as_number) {
+                    Some(value) => Observation::Number(value),
+                    None => continue,
+                },
+                LogMetricKind::DistinctCount { field } | LogMetricKind::TopK { field, .. } => {
+                    match field_value(record, field).and_then(as_text) {
+                        Some(value) => Observation::Text(value),
+                        None => continue,
+                    }
+                }
+            };
+            let key = SeriesKey {
+                tenant_id: message.processing_metadata.tenant_id.clone(),
+                rule: rule.name.clone(),
+                labels: rule
+                    .labels
+                    .iter()
+                    .filter_map(|(label, field)| field_value(record, field).and_then(as_text).map(|v| (label.clone(), v)))
+                    .collect(),
+            };
+            let series = state.series.entry(key).or_insert_with(|| Series {
+                source: message.processing_metadata.clone(),
+                value: match &rule.kind {
+                    LogMetricKind::Count => Aggregate::Count(0),
+                    LogMetricKind::Histogram { buckets, unit, .. } => Aggregate::Histogram {
+                        bounds: buckets.clone(),
+                        unit: unit.clone(),
+                        buckets: vec![0; buckets.len() + 1],
+                        sum: 0.0,
+                        count: 0,
+                    },
+                    LogMetricKind::DistinctCount { .. } => Aggregate::Distinct(HyperLogLog::default()),
+                    LogMetricKind::TopK { field, k, label } => Aggregate::TopK {
+                        k: *k,
+                        label: label.clone().unwrap_or_else(|| field.rsplit('.').next().unwrap_or(field).to_string()),
+                        top: SpaceSaving::for_top(*k),
+                    },
+                },
+            });
+            series.source.clone_from(&message.processing_metadata);
+            match (&mut series.value, observation) {
+                (Aggregate::Count(count), _) => *count += 1,
+                (Aggregate::Histogram { bounds, buckets, sum, count, .. }, Observation::Number(value)) => {
+                    let bucket = bounds.iter().position(|bound| value <= *bound).unwrap_or(bounds.len());
+                    buckets[bucket] += 1;
+                    *sum += value;
+                    *count += 1;
+                }
+                (Aggregate::Distinct(distinct), Observation::Text(value)) => distinct.insert(&value),
+                (Aggregate::TopK { top, .. }, Observation::Text(value)) => top.insert(&value, 1),
+                // The rule changed kind since the series started
+                _ => {}
+            }
+        }
+    }
+
+    /// The derived metrics of the interval, once it has ended, as processed
+    /// messages to store with the current batch.
+    pub fn flush(&self, now: Instant) -> Vec<ProcessedMessage> {
+        let interval = self.settings.borrow().log_metrics.interval;
+        let mut state = self.state.lock().unwrap();
+        let start = *state.window_start.get_or_insert(now);
+        if now.duration_since(start) < interval {
+            return Vec::new();
+        }
+        state.window_start = Some(now);
+
+        let timestamp = Utc::now().timestamp_millis() as u64;
+        let mut derived = Vec::new();
+        for (key, series) in state.series.drain() {
+            let labels: HashMap<String, String> = key.labels.into_iter().collect();
+            let metrics = match series.value {
+                Aggregate::Count(count) => vec![metric(&key.rule, count as f64, "count", labels)],
+                Aggregate::Histogram { bounds, unit, buckets, sum, count } => {
+                    let mut metrics = Vec::with_capacity(buckets.len() + 2);
+                    let mut cumulative = 0;
+                    for (i, logs) in buckets.iter().enumerate() {
+                        cumulative += logs;
+                        let le = bounds.get(i).map_or_else(|| "+Inf".to_string(), |bound| bound.to_string());
+                        let mut labels = labels.clone();
+                        labels.insert("le".to_string(), le);
+                        metrics.push(metric(&format!("{}_bucket", key.rule), cumulative as f64, "count", labels));
+                    }
+                    metrics.push(metric(&format!("{}_sum", key.rule), sum, &unit, labels.clone()));
+                    metrics.push(metric(&format!("{}_count", key.rule), count as f64, "count", labels));
+                    metrics
+                }
+                Aggregate::Distinct(distinct) => vec![metric(&key.rule, distinct.estimate().round(), "count", labels)],
+                Aggregate::TopK { k, label, top } => top
+                    .top(k)
+                    .into_iter()
+                    .map(|(value, counter)| {
+                        let mut labels = labels.clone();
+                        labels.insert(label.clone(), value);
+                        metric(&key.rule, counter.count as f64, "count", labels)
+                    })
+                    .collect(),
+            };
+            for mut metric in metrics {
+                metric.timestamp = Some(timestamp);
+                let record = serde_json::to_value(&metric).unwrap_or_default();
+                derived.push(ProcessedMessage::derived(record, &series.source));
+            }
+        }
+        derived
+    }
+}
+
+fn metric(name: &str, value: f64, unit: &str, labels: HashMap<String, String>) -> Metric {
+    Metric {
+        name: name.to_string(),
+        value,
+        unit: unit.to_string(),
+        labels: (!labels.is_empty()).then_some(labels),
+        timestamp: None,
+    }
+}
+
+fn is_log(record: &Value) -> bool {
+    record.get("message").map_or(false, Value::is_string) && record.get("level").map_or(false, Value::is_string)
+}
+
+fn matches(rule: &LogMetricRule, record: &Value) -> bool {
+    rule.filter
+        .iter()
+        .all(|(field, expected)| field_value(record, field).and_then(as_text).as_deref() == Some(expected.as_str()))
+}
+
+/// `path` in the log, or under its `fields` as in SDK log entries.
+fn field_value<'a>(record: &'a Value, path: &str) -> Option<&'a Value> {
+    lookup(record, path).or_else(|| record.get("fields").and_then(|fields| lookup(fields, path)))
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn log(record: Value) -> ProcessedMessage {
+        ProcessedMessage {
+            id: "1".to_string(),
+            original_message: record.clone(),
+            processed_message: record,
+            processing_metadata: ProcessingMetadata {
+                tenant_id: "acme".to_string(),
+                processed_at: Utc::now(),
+                processor_version: "test".to_string(),
+                source_topic: "logs".to_string(),
+                partition: 2,
+                offset: 10,
+                traceparent: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_counts_and_histograms_are_derived_per_interval() {
+        let mut settings = ProcessingConfig::default();
+        settings.log_metrics.interval = Duration::from_secs(60);
+        settings.log_metrics.rules = serde_json::from_value(json!([
+            {"name": "log_lines", "type": "count"},
+            {"name": "request_latency", "type": "histogram", "field": "latency_ms", "buckets": [100, 500],
+             "filter": {"service": "api"}, "labels": {"service": "service"}},
+        ]))
+        .unwrap();
+        let (_tx, rx) = watch::channel(settings);
+        let derived = LogMetrics::new(rx);
+        let start = Instant::now();
+        assert!(derived.flush(start).is_empty());
+
+        derived.observe(&log(json!({"level": "info", "message": "ok", "service": "api", "latency_ms": 40})));
+        derived.observe(&log(json!({"level": "info", "message": "ok", "fields": {"service": "api", "latency_ms": "250"}})));
+        derived.observe(&log(json!({"level": "error", "message": "slow", "service": "api", "latency_ms": 900})));
+        derived.observe(&log(json!({"level": "info", "message": "ok", "service": "web", "latency_ms": 5})));
+        derived.observe(&log(json!({"name": "cpu", "value": 1.0})));
+        assert!(derived.flush(start + Duration::from_secs(30)).is_empty());
+
+        let mut values: Vec<(String, Value, f64)> = derived
+            .flush(start + Duration::from_secs(60))
+            .into_iter()
+            .map(|m| {
+                let metric: Metric = serde_json::from_value(m.processed_message).unwrap();
+                (metric.name, serde_json::to_value(metric.labels).unwrap(), metric.value)
+            })
+            .collect();
+        values.sort_by(|a, b| (&a.0, a.1.to_string()).cmp(&(&b.0, b.1.to_string())));
+        assert_eq!(
+            values,
+            vec![
+                ("log_lines".to_string(), json!({"level": "error", "service": "api"}), 1.0),
+                ("log_lines".to_string(), json!({"level": "info", "service": "api"}), 2.0),
+                ("log_lines".to_string(), json!({"level": "info", "service": "web"}), 1.0),
+                ("request_latency_bucket".to_string(), json!({"le": "+Inf", "service": "api"}), 3.0),
+                ("request_latency_bucket".to_string(), json!({"le": "100", "service": "api"}), 1.0),
+                ("request_latency_bucket".to_string(), json!({"le": "500", "service": "api"}), 2.0),
+                ("request_latency_count".to_string(), json!({"service": "api"}), 3.0),
+                ("request_latency_sum".to_string(), json!({"service": "api"}), 1190.0),
+            ]
+        );
+        assert!(derived.flush(start + Duration::from_secs(120)).is_empty());
+    }
+
+    #[test]
+    fn test_top_k_and_distinct_counts_are_derived() {
+        let mut settings = ProcessingConfig::default();
+        settings.log_metrics.rules = serde_json::from_value(json!([
+            {"name": "top_error_endpoints", "type": "top_k", "field": "http.route", "k": 2,
+             "filter": {"level": "error"}, "labels": {}},
+            {"name": "users_with_errors", "type": "distinct_count", "field": "user_id",
+             "filter": {"level": "error"}, "labels": {}},
+        ]))
+        .unwrap();
+        let (_tx, rx) = watch::channel(settings);
+        let derived = LogMetrics::new(rx);
+        let start = Instant::now();
+        derived.flush(start);
+
+        let errors = [("/orders", "u1"), ("/orders", "u2"), ("/login", "u1"), ("/orders", "u1"), ("/cart", "u3")];
+        for (route, user) in errors {
+            let record = json!({"level": "error", "message": "failed", "http": {"route": route}, "user_id": user});
+            derived.observe(&log(record));
+        }
+        derived.observe(&log(json!({"level": "info", "message": "ok", "http": {"route": "/login"}, "user_id": "u4"})));
+
+        let mut values: Vec<(String, Value, f64)> = derived
+            .flush(start + Duration::from_secs(60))
+            .into_iter()
+            .map(|m| {
+                let metric: Metric = serde_json::from_value(m.processed_message).unwrap();
+                (metric.name, serde_json::to_value(metric.labels).unwrap(), metric.value)
+            })
+            .collect();
+        values.sort_by(|a, b| (&a.0, a.1.to_string()).cmp(&(&b.0, b.1.to_string())));
+        assert_eq!(
+            values,
+            vec![
+                ("top_error_endpoints".to_string(), json!({"route": "/cart"}), 1.0),
+                ("top_error_endpoints".to_string(), json!({"route": "/orders"}), 3.0),
+                ("users_with_errors".to_string(), Value::Null, 3.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Accounting for the memory held by batches between consumption and the
+//! database writer, and overflow of queued batches to disk once the budget
+//! is used up.
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::config::MemoryBudgetConfig;
+use crate::metrics::Metrics;
+
+const SEGMENT_EXTENSION: &str = "batch";
+
+/// Allowance per JSON value for its tag, allocation and container slot.
+const VALUE_OVERHEAD: u64 = 32;
+
+/// Estimated bytes held by batches across every worker and the writer queue.
+pub struct MemoryBudget {
+    max_bytes: u64,
+    used: AtomicU64,
+    metrics: Arc<Metrics>,
+}
+
+impl MemoryBudget {
+    pub fn new(max_bytes: u64, metrics: Arc<Metrics>) -> Arc<Self> {
+        Arc::new(Self {
+            max_bytes,
+            used: AtomicU64::new(0),
+            metrics,
+        })
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Acquire)
+    }
+
+    /// Counts `bytes` whether or not they fit, for batches that are already
+    /// being processed and cannot be set aside. Going over the budget makes
+    /// the batches queued after them spill.
+    pub fn reserve(self: &Arc<Self>, bytes: u64) -> Reservation {
+        self.used.fetch_add(bytes, Ordering::AcqRel);
+        self.publish();
+        Reservation {
+            budget: self.clone(),
+            bytes,
+        }
+    }
+
+    /// Counts `bytes` only if they fit within the budget.
+    pub fn try_reserve(self: &Arc<Self>, bytes: u64) -> Option<Reservation> {
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                Some(used + bytes).filter(|total| *total <= self.max_bytes)
+            })
+            .ok()?;
+        self.publish();
+        Some(Reservation {
+            budget: self.clone(),
+            bytes,
+        })
+    }
+
+    fn publish(&self) {
+        self.metrics.set_processing_memory_used(self.used() as i64);
+    }
+}
+
+/// Bytes counted against a [`MemoryBudget`] until dropped.
+pub struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: u64,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.used.fetch_sub(self.bytes, Ordering::AcqRel);
+        self.budget.publish();
+    }
+}
+
+/// A queued value: in memory under its reservation, or spilled to disk.
+pub enum Held<T> {
+    Memory(T, Option<Reservation>),
+    Spilled(Segment),
+}
+
+impl<T: DeserializeOwned> Held<T> {
+    /// Returns the value, reading a spilled one back and deleting its
+    /// segment. The reservation comes with it and should be dropped once
+    /// the value has been dealt with.
+    pub async fn take(self) -> Result<(T, Option<Reservation>)> {
+        match self {
+            Held::Memory(value, reservation) => Ok((value, reservation)),
+            Held::Spilled(segment) => segment.read().await,
+        }
+    }
+}
+
+/// One spilled value. The file is only removed once it has been read back,
+/// so a segment dropped unread is picked up again by the next run.
+pub struct Segment {
+    overflow: Arc<Overflow>,
+    path: PathBuf,
+    bytes: u64,
+}
+
+impl Segment {
+    async fn read<T: DeserializeOwned>(self) -> Result<(T, Option<Reservation>)> {
+        let data = tokio::fs::read(&self.path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read overflow segment {}: {}", self.path.display(), e))?;
+        let value = serde_json::from_slice(&data)
+            .map_err(|e| anyhow::anyhow!("Failed to decode overflow segment {}: {}", self.path.display(), e))?;
+
+        // Back in memory until it has been written, like any batch in hand
+        let reservation = self.overflow.budget.reserve(self.bytes);
+        if let Err(e) = tokio::fs::remove_file(&self.path).await {
+            warn!("Failed to remove overflow segment {}: {}", self.path.display(), e);
+        }
+        self.overflow.release(self.bytes);
+        Ok((value, Some(reservation)))
+    }
+}
+
+/// Keeps queued values in memory while the budget allows and spills the
+/// rest to a directory of temporary segments, one file per value.
+pub struct Overflow {
+    budget: Arc<MemoryBudget>,
+    directory: PathBuf,
+    max_spill_bytes: u64,
+    spilled_bytes: AtomicU64,
+    next_seq: AtomicU64,
+    metrics: Arc<Metrics>,
+}
+
+impl Overflow {
+    /// Opens the spill directory. Segments left behind by a previous run are
+    /// returned, oldest first, so they can be queued again.
+    pub async fn open(
+        config: &MemoryBudgetConfig,
+        budget: Arc<MemoryBudget>,
+        metrics: Arc<Metrics>,
+    ) -> Result<(Arc<Self>, Vec<Segment>)> {
+        let directory = PathBuf::from(&config.spill_directory);
+        tokio::fs::create_dir_all(&directory)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create overflow directory {}: {}", directory.display(), e))?;
+
+        let mut leftovers = Vec::new();
+        let mut entries = tokio::fs::read_dir(&directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            let seq = match path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u64>().ok()) {
+                Some(seq) => seq,
+                None => continue,
+            };
+            leftovers.push((seq, path, entry.metadata().await?.len()));
+        }
+        leftovers.sort_by_key(|(seq, _, _)| *seq);
+
+        let spilled_bytes = leftovers.iter().map(|(_, _, bytes)| bytes).sum();
+        if !leftovers.is_empty() {
+            info!(
+                "Found {} overflow segment(s) ({} bytes) in {}",
+                leftovers.len(),
+                spilled_bytes,
+                directory.display()
+            );
+        }
+        metrics.set_processing_overflow_bytes(spilled_bytes as i64);
+
+        let overflow = Arc::new(Self {
+            budget,
+            directory,
+            max_spill_bytes: config.max_spill_bytes,
+            spilled_bytes: AtomicU64::new(spilled_bytes),
+            next_seq: AtomicU64::new(leftovers.last().map_or(0, |(seq, _, _)| seq + 1)),
+            metrics,
+        });
+        let leftovers = leftovers
+            .into_iter()
+            .map(|(_, path, bytes)| Segment {
+                overflow: overflow.clone(),
+                path,
+                bytes,
+            })
+            .collect();
+        Ok((overflow, leftovers))
+    }
+
+    pub fn budget(&self) -> &Arc<MemoryBudget> {
+        &self.budget
+    }
+
+    /// Keeps `value` in memory if its estimated `bytes` fit the budget and
+    /// spills it otherwise. It stays in memory over budget when the disk
+    /// allowance is used up or the segment cannot be written.
+    pub async fn hold<T: Serialize>(self: &Arc<Self>, value: T, bytes: u64) -> Held<T> {
+        if let Some(reservation) = self.budget.try_reserve(bytes) {
+            return Held::Memory(value, Some(reservation));
+        }
+        match self.spill(&value).await {
+            Ok(Some(segment)) => Held::Spilled(segment),
+            Ok(None) => Held::Memory(value, Some(self.budget.reserve(bytes))),
+            Err(e) => {
+                warn!("Keeping queued batch in memory over budget: {}", e);
+                Held::Memory(value, Some(self.budget.reserve(bytes)))
+            }
+        }
+    }
+
+    async fn spill<T: Serialize>(self: &Arc<Self>, value: &T) -> Result<Option<Segment>> {
+        let payload = serde_json::to_vec(value)?;
+        let bytes = payload.len() as u64;
+        let reserved = self
+            .spilled_bytes
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |spilled| {
+                Some(spilled + bytes).filter(|total| *total <= self.max_spill_bytes)
+            });
+        if reserved.is_err() {
+            return Ok(None);
+        }
+
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let path = self.directory.join(format!("{:020}.{}", seq, SEGMENT_EXTENSION));
+        if let Err(e) = tokio::fs::write(&path, &payload).await {
+            self.release(bytes);
+            return Err(anyhow::anyhow!("Failed to write overflow segment {}: {}", path.display(), e));
+        }
+
+        self.metrics.increment_processing_overflow_batches();
+        self.metrics
+            .set_processing_overflow_bytes(self.spilled_bytes.load(Ordering::Acquire) as i64);
+        Ok(Some(Segment {
+            overflow: self.clone(),
+            path,
+            bytes,
+        }))
+    }
+
+    fn release(&self, bytes: u64) {
+        let spilled = self.spilled_bytes.fetch_sub(bytes, Ordering::AcqRel) - bytes;
+        self.metrics.set_processing_overflow_bytes(spilled as i64);
+    }
+}
+
+/// Rough heap footprint of a JSON value, for budgeting without serializing it.
+pub fn approximate_size(value: &Value) -> u64 {
+    VALUE_OVERHEAD
+        + match value {
+            Value::String(s) => s.len() as u64,
+            Value::Array(items) => items.iter().map(approximate_size).sum(),
+            Value::Object(fields) => fields.iter().map(|(k, v)| k.len() as u64 + approximate_size(v)).sum(),
+            _ => 0,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+
+    #[tokio::test]
+    async fn test_queued_values_spill_over_budget_and_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = MemoryBudgetConfig {
+            max_bytes: Some(100),
+            spill_directory: dir.path().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let metrics = Arc::new(Metrics::new(&MetricsConfig::default()).unwrap());
+        let budget = MemoryBudget::new(100, metrics.clone());
+        let (overflow, leftovers) = Overflow::open(&config, budget.clone(), metrics.clone()).await.unwrap();
+        assert!(leftovers.is_empty());
+
+        let processing = budget.reserve(60);
+        let first = overflow.hold(vec![1, 2, 3], 30).await;
+        assert!(matches!(first, Held::Memory(..)));
+        let second = overflow.hold(vec![4, 5, 6], 30).await;
+        assert!(matches!(second, Held::Spilled(..)));
+        assert_eq!(budget.used(), 90);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        drop(processing);
+        let (value, reservation) = second.take().await.unwrap();
+        assert_eq!(value, vec![4, 5, 6]);
+        assert!(reservation.is_some());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        drop((first, reservation));
+        assert_eq!(budget.used(), 0);
+
+        // A segment never read back is found again by the next run
+        let _full = budget.reserve(100);
+        drop(overflow.hold(vec![7], 10).await);
+        let (_, leftovers) = Overflow::open(&config, budget.clone(), metrics).await.unwrap();
+        let (value, _) = Held::<Vec<i32>>::Spilled(leftovers.into_iter().next().unwrap()).take().await.unwrap();
+        assert_eq!(value, vec![7]);
+    }
+}
@@ -0,0 +1,1084 @@
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use prometheus::{
+    Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry,
+};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use sysinfo::{Pid, ProcessRefreshKind, System};
+use tracing::{error, info, warn};
+
+use crate::config::{HistogramBuckets, MetricsAuth, MetricsConfig};
+use crate::dag::Topologies;
+use crate::health::HealthState;
+use crate::poison::PoisonGuard;
+
+mod otlp;
+mod push;
+
+pub use otlp::init as init_otlp;
+pub use push::run as run_push;
+
+pub struct Metrics {
+    registry: Registry,
+    
+    // Kafka metrics
+    pub kafka_messages_received: IntCounterVec,
+    pub kafka_messages_processed: IntCounterVec,
+    pub kafka_messages_failed: IntCounterVec,
+    pub kafka_consumer_lag: IntGaugeVec,
+    
+    // Processing metrics
+    pub processing_duration: HistogramVec,
+    pub processing_batch_size: HistogramVec,
+    pub processing_effective_batch_size: IntGaugeVec,
+    pub processing_errors: IntCounterVec,
+    pub pipeline_end_to_end_latency: HistogramVec,
+    pub pipeline_stage_latency: HistogramVec,
+    pub processing_retries: IntCounter,
+    pub processing_memory_used_bytes: IntGauge,
+    pub processing_overflow_bytes: IntGauge,
+    pub processing_overflow_batches: IntCounter,
+    pub table_entries: IntGaugeVec,
+    pub table_updates_dropped: IntCounterVec,
+    pub processing_cardinality_limited: IntCounterVec,
+    pub log_templates: IntGauge,
+    pub log_templates_created: IntCounter,
+    pub sessions_open: IntGauge,
+    pub sessions_closed: IntCounterVec,
+    pub schema_changes: IntCounterVec,
+    pub records_rejected: IntCounterVec,
+    pub transform_timeouts: IntCounterVec,
+    
+    // Database metrics
+    pub database_operations: IntCounter,
+    pub database_errors: IntCounter,
+    pub database_connection_pool_size: IntGauge,
+    pub database_connection_pool_available: IntGauge,
+    pub database_healthy: IntGauge,
+    pub database_spill_bytes: IntGauge,
+    pub database_spilled_batches: IntCounter,
+    pub database_replayed_batches: IntCounter,
+    pub database_duplicate_writes: IntCounter,
+    
+    // Stream processing metrics
+    pub stream_watermark: IntGauge,
+    pub stream_window_count: IntCounter,
+    pub stream_late_records: IntCounter,
+    
+    // WebSocket hub metrics
+    pub hub_connections: IntGauge,
+    pub hub_messages_dropped: IntCounter,
+    pub hub_slow_consumer_disconnects: IntCounter,
+    
+    // Alert notification metrics
+    pub notifications: IntCounterVec,
+    pub alerts_suppressed: IntCounterVec,
+    
+    // Audit log metrics
+    pub audit_write_failures: IntCounter,
+    
+    // System metrics
+    pub memory_usage_bytes: IntGauge,
+    pub cpu_usage_percent: Gauge,
+    pub open_fds: IntGauge,
+    pub active_tasks: IntGauge,
+    
+    // Tokio runtime metrics
+    pub runtime_workers: IntGauge,
+    pub runtime_global_queue_depth: IntGauge,
+    pub runtime_blocking_threads: IntGauge,
+}
+
+impl Metrics {
+    pub fn new(config: &MetricsConfig) -> Result<Self> {
+        let registry = Registry::new();
+        
+        // Kafka metrics
+        let kafka_messages_received = IntCounterVec::new(
+            Opts::new(
+                "kafka_messages_received_total",
+                "Total number of Kafka messages received",
+            ),
+            &["topic", "partition"],
+        )?;
+        
+        let kafka_messages_processed = IntCounterVec::new(
+            Opts::new(
+                "kafka_messages_processed_total",
+                "Total number of Kafka messages processed successfully",
+            ),
+            &["topic", "partition", "worker_id"],
+        )?;
+        
+        let kafka_messages_failed = IntCounterVec::new(
+            Opts::new(
+                "kafka_messages_failed_total",
+                "Total number of Kafka messages that failed processing",
+            ),
+            &["topic", "partition", "worker_id"],
+        )?;
+        
+        let kafka_consumer_lag = IntGaugeVec::new(
+            Opts::new(
+                "kafka_consumer_lag",
+                "Current consumer lag for each partition",
+            ),
+            &["topic", "partition"],
+        )?;
+        
+        // Processing metrics
+        let processing_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "processing_duration_seconds",
+                "Time spent processing messages",
+            )
+            .buckets(bucket_bounds(&config.histograms.processing_duration)?),
+            &["worker_id"],
+        )?;
+        
+        let processing_batch_size = HistogramVec::new(
+            HistogramOpts::new(
+                "processing_batch_size",
+                "Size of processing batches",
+            )
+            .buckets(bucket_bounds(&config.histograms.processing_batch_size)?),
+            &["worker_id"],
+        )?;
+        
+        let processing_errors = IntCounterVec::new(
+            Opts::new(
+                "processing_errors_total",
+                "Total number of processing errors",
+            ),
+            &["worker_id"],
+        )?;
+        
+        let pipeline_end_to_end_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "pipeline_end_to_end_latency_seconds",
+                "Time from the Kafka message timestamp to the storage commit",
+            )
+            .buckets(bucket_bounds(&config.histograms.pipeline_latency)?),
+            &["topic"],
+        )?;
+        
+        let pipeline_stage_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "pipeline_stage_latency_seconds",
+                "Time spent in each pipeline stage (consume, transform, write)",
+            )
+            .buckets(bucket_bounds(&config.histograms.pipeline_latency)?),
+            &["topic", "stage"],
+        )?;
+        
+        let processing_effective_batch_size = IntGaugeVec::new(
+            Opts::new(
+                "processing_effective_batch_size",
+                "Batch size currently chosen by adaptive batching for each topic",
+            ),
+            &["topic"],
+        )?;
+        
+        let processing_retries = IntCounter::new(
+            "processing_retries_total",
+            "Total number of processing retries",
+        )?;
+        
+        let processing_memory_used_bytes = IntGauge::new(
+            "processing_memory_used_bytes",
+            "Estimated bytes of batches being processed or waiting for the database writer",
+        )?;
+        
+        let processing_overflow_bytes = IntGauge::new(
+            "processing_overflow_bytes",
+            "Bytes of queued batches currently spilled to disk over the memory budget",
+        )?;
+        
+        let processing_overflow_batches = IntCounter::new(
+            "processing_overflow_batches_total",
+            "Total number of queued batches spilled to disk over the memory budget",
+        )?;
+        
+        let table_entries = IntGaugeVec::new(
+            Opts::new(
+                "table_entries",
+                "Keys held by each table materialized from a compacted topic",
+            ),
+            &["table"],
+        )?;
+        
+        let table_updates_dropped = IntCounterVec::new(
+            Opts::new(
+                "table_updates_dropped_total",
+                "Total number of table updates dropped for an undecodable payload or a full table",
+            ),
+            &["table"],
+        )?;
+        
+        let processing_cardinality_limited = IntCounterVec::new(
+            Opts::new(
+                "processing_cardinality_limited_total",
+                "Total number of metric samples dropped or aggregated past a series limit",
+            ),
+            &["action"],
+        )?;
+        
+        let log_templates = IntGauge::new(
+            "log_templates",
+            "Log templates currently held across tenants",
+        )?;
+        
+        let log_templates_created = IntCounter::new(
+            "log_templates_created_total",
+            "Total number of log templates started by a log unlike any before",
+        )?;
+        
+        let sessions_open = IntGauge::new(
+            "sessions_open",
+            "Session windows currently open",
+        )?;
+        
+        let sessions_closed = IntCounterVec::new(
+            Opts::new(
+                "sessions_closed_total",
+                "Total number of session windows ended, by why they ended",
+            ),
+            &["reason"],
+        )?;
+        
+        let schema_changes = IntCounterVec::new(
+            Opts::new(
+                "schema_changes_total",
+                "Total number of changes to the shape of a topic's messages, by their compatibility",
+            ),
+            &["topic", "outcome"],
+        )?;
+        
+        let records_rejected = IntCounterVec::new(
+            Opts::new(
+                "records_rejected_total",
+                "Total number of consumed records dead-lettered for breaking processing.record_limits",
+            ),
+            &["topic", "reason"],
+        )?;
+
+        let transform_timeouts = IntCounterVec::new(
+            Opts::new(
+                "transform_timeouts_total",
+                "Total number of transforms cancelled for running past processing.transform_timeout",
+            ),
+            &["topic"],
+        )?;
+        
+        // Database metrics
+        let database_operations = IntCounter::new(
+            "database_operations_total",
+            "Total number of database operations",
+        )?;
+        
+        let database_errors = IntCounter::new(
+            "database_errors_total",
+            "Total number of database errors",
+        )?;
+        
+        let database_connection_pool_size = IntGauge::new(
+            "database_connection_pool_size",
+            "Total number of database connections in pool",
+        )?;
+        
+        let database_connection_pool_available = IntGauge::new(
+            "database_connection_pool_available",
+            "Number of available database connections in pool",
+        )?;
+        
+        let database_healthy = IntGauge::new(
+            "database_healthy",
+            "Whether the last database health check succeeded (1) or failed (0)",
+        )?;
+        
+        let database_spill_bytes = IntGauge::new(
+            "database_spill_bytes",
+            "Bytes of unwritten batches currently spilled to disk",
+        )?;
+        
+        let database_spilled_batches = IntCounter::new(
+            "database_spilled_batches_total",
+            "Total number of batches spilled to disk while the database was unavailable",
+        )?;
+        
+        let database_replayed_batches = IntCounter::new(
+            "database_replayed_batches_total",
+            "Total number of spilled batches replayed into the database",
+        )?;
+        
+        let database_duplicate_writes = IntCounter::new(
+            "database_duplicate_writes_total",
+            "Total number of redelivered messages that updated an already stored row",
+        )?;
+        
+        // Stream processing metrics
+        let stream_watermark = IntGauge::new(
+            "stream_watermark_timestamp",
+            "Current watermark timestamp for stream processing",
+        )?;
+        
+        let stream_window_count = IntCounter::new(
+            "stream_window_count_total",
+            "Total number of stream processing windows",
+        )?;
+        
+        let stream_late_records = IntCounter::new(
+            "stream_late_records_total",
+            "Total number of late records in stream processing",
+        )?;
+        
+        // WebSocket hub metrics
+        let hub_connections = IntGauge::new(
+            "hub_connections",
+            "Number of WebSocket clients connected to the hub",
+        )?;
+        
+        let hub_messages_dropped = IntCounter::new(
+            "hub_messages_dropped_total",
+            "Total number of hub messages skipped for connections that fell behind",
+        )?;
+        
+        let hub_slow_consumer_disconnects = IntCounter::new(
+            "hub_slow_consumer_disconnects_total",
+            "Total number of WebSocket connections closed for falling behind",
+        )?;
+        
+        // Alert notification metrics
+        let notifications = IntCounterVec::new(
+            Opts::new(
+                "notifications_total",
+                "Total number of alert notifications by channel and delivery status",
+            ),
+            &["channel", "status"],
+        )?;
+        let alerts_suppressed = IntCounterVec::new(
+            Opts::new(
+                "alerts_suppressed_total",
+                "Total number of alerts not notified because a silence matched them",
+            ),
+            &["tenant_id"],
+        )?;
+        
+        // Audit log metrics
+        let audit_write_failures = IntCounter::new(
+            "audit_write_failures_total",
+            "Total number of control-plane actions that could not be written to the audit log",
+        )?;
+        
+        // System metrics
+        let memory_usage_bytes = IntGauge::new(
+            "memory_usage_bytes",
+            "Resident set size of the process in bytes",
+        )?;
+        
+        let cpu_usage_percent = Gauge::new(
+            "cpu_usage_percent",
+            "CPU usage of the process since the previous sample, in percent of one core",
+        )?;
+        
+        let open_fds = IntGauge::new(
+            "process_open_fds",
+            "Number of file descriptors held open by the process",
+        )?;
+        
+        let active_tasks = IntGauge::new(
+            "active_tasks",
+            "Number of tasks alive on the tokio runtime",
+        )?;
+        
+        // Tokio runtime metrics
+        let runtime_workers = IntGauge::new(
+            "tokio_runtime_workers",
+            "Number of worker threads used by the tokio runtime",
+        )?;
+        
+        let runtime_global_queue_depth = IntGauge::new(
+            "tokio_runtime_global_queue_depth",
+            "Number of tasks waiting in the tokio runtime's global queue",
+        )?;
+        
+        let runtime_blocking_threads = IntGauge::new(
+            "tokio_runtime_blocking_threads",
+            "Number of threads spawned for blocking work by the tokio runtime",
+        )?;
+        
+        // Register all metrics
+        registry.register(Box::new(kafka_messages_received.clone()))?;
+        registry.register(Box::new(kafka_messages_processed.clone()))?;
+        registry.register(Box::new(kafka_messages_failed.clone()))?;
+        registry.register(Box::new(kafka_consumer_lag.clone()))?;
+        registry.register(Box::new(processing_duration.clone()))?;
+        registry.register(Box::new(processing_batch_size.clone()))?;
+        registry.register(Box::new(processing_effective_batch_size.clone()))?;
+        registry.register(Box::new(processing_errors.clone()))?;
+        registry.register(Box::new(pipeline_end_to_end_latency.clone()))?;
+        registry.register(Box::new(pipeline_stage_latency.clone()))?;
+        registry.register(Box::new(processing_retries.clone()))?;
+        registry.register(Box::new(processing_memory_used_bytes.clone()))?;
+        registry.register(Box::new(processing_overflow_bytes.clone()))?;
+        registry.register(Box::new(processing_overflow_batches.clone()))?;
+        registry.register(Box::new(table_entries.clone()))?;
+        registry.register(Box::new(table_updates_dropped.clone()))?;
+        registry.register(Box::new(processing_cardinality_limited.clone()))?;
+        registry.register(Box::new(log_templates.clone()))?;
+        registry.register(Box::new(log_templates_created.clone()))?;
+        registry.register(Box::new(sessions_open.clone()))?;
+        registry.register(Box::new(sessions_closed.clone()))?;
+        registry.register(Box::new(schema_changes.clone()))?;
+        registry.register(Box::new(records_rejected.clone()))?;
+        registry.register(Box::new(transform_timeouts.clone()))?;
+        registry.register(Box::new(database_operations.clone()))?;
+        registry.register(Box::new(database_errors.clone()))?;
+        registry.register(Box::new(database_connection_pool_size.clone()))?;
+        registry.register(Box::new(database_connection_pool_available.clone()))?;
+        registry.register(Box::new(database_healthy.clone()))?;
+        registry.register(Box::new(database_spill_bytes.clone()))?;
+        registry.register(Box::new(database_spilled_batches.clone()))?;
+        registry.register(Box::new(database_replayed_batches.clone()))?;
+        registry.register(Box::new(database_duplicate_writes.clone()))?;
+        registry.register(Box::new(stream_watermark.clone()))?;
+        registry.register(Box::new(stream_window_count.clone()))?;
+        registry.register(Box::new(stream_late_records.clone()))?;
+        registry.register(Box::new(hub_connections.clone()))?;
+        registry.register(Box::new(hub_messages_dropped.clone()))?;
+        registry.register(Box::new(hub_slow_consumer_disconnects.clone()))?;
+        registry.register(Box::new(notifications.clone()))?;
+        registry.register(Box::new(alerts_suppressed.clone()))?;
+        registry.register(Box::new(audit_write_failures.clone()))?;
+        registry.register(Box::new(memory_usage_bytes.clone()))?;
+        registry.register(Box::new(cpu_usage_percent.clone()))?;
+        registry.register(Box::new(open_fds.clone()))?;
+        registry.register(Box::new(active_tasks.clone()))?;
+        registry.register(Box::new(runtime_workers.clone()))?;
+        registry.register(Box::new(runtime_global_queue_depth.clone()))?;
+        registry.register(Box::new(runtime_blocking_threads.clone()))?;
+        
+        Ok(Self {
+            registry,
+            kafka_messages_received,
+            kafka_messages_processed,
+            kafka_messages_failed,
+            kafka_consumer_lag,
+            processing_duration,
+            processing_batch_size,
+            processing_effective_batch_size,
+            processing_errors,
+            pipeline_end_to_end_latency,
+            pipeline_stage_latency,
+            processing_retries,
+            processing_memory_used_bytes,
+            processing_overflow_bytes,
+            processing_overflow_batches,
+            table_entries,
+            table_updates_dropped,
+            processing_cardinality_limited,
+            log_templates,
+            log_templates_created,
+            sessions_open,
+            sessions_closed,
+            schema_changes,
+            records_rejected,
+            transform_timeouts,
+            database_operations,
+            database_errors,
+            database_connection_pool_size,
+            database_connection_pool_available,
+            database_healthy,
+            database_spill_bytes,
+            database_spilled_batches,
+            database_replayed_batches,
+            database_duplicate_writes,
+            stream_watermark,
+            stream_window_count,
+            stream_late_records,
+            hub_connections,
+            hub_messages_dropped,
+            hub_slow_consumer_disconnects,
+            notifications,
+            alerts_suppressed,
+            audit_write_failures,
+            memory_usage_bytes,
+            cpu_usage_percent,
+            open_fds,
+            active_tasks,
+            runtime_workers,
+            runtime_global_queue_depth,
+            runtime_blocking_threads,
+        })
+    }
+    
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+    
+    // Helper methods for common metric operations
+    pub fn increment_messages_received(&self, topic: &str, partition: i32, count: u64) {
+        self.kafka_messages_received
+            .with_label_values(&[topic, &partition.to_string()])
+            .inc_by(count);
+    }
+    
+    pub fn increment_messages_processed(&self, topic: &str, partition: i32, worker_id: usize, count: u64) {
+        self.kafka_messages_processed
+            .with_label_values(&[topic, &partition.to_string(), &worker_id.to_string()])
+            .inc_by(count);
+    }
+    
+    /// Failures before a message reaches a worker pass `None` for the worker;
+    /// consumer errors that carry no message pass `None` for the position too.
+    /// Missing labels are exported as empty values.
+    pub fn increment_messages_failed(
+        &self,
+        position: Option<(&str, i32)>,
+        worker_id: Option<usize>,
+        count: u64,
+    ) {
+        let (topic, partition) = position
+            .map(|(topic, partition)| (topic, partition.to_string()))
+            .unwrap_or_default();
+        let worker_id = worker_id.map(|id| id.to_string()).unwrap_or_default();
+        self.kafka_messages_failed
+            .with_label_values(&[topic, &partition, &worker_id])
+            .inc_by(count);
+    }
+    
+    pub fn set_consumer_lag(&self, topic: &str, partition: i32, lag: i64) {
+        self.kafka_consumer_lag
+            .with_label_values(&[topic, &partition.to_string()])
+            .set(lag);
+    }
+    
+    /// Drops the lag series of a partition this consumer no longer owns.
+    pub fn remove_consumer_lag(&self, topic: &str, partition: i32) {
+        let _ = self
+            .kafka_consumer_lag
+            .remove_label_values(&[topic, &partition.to_string()]);
+    }
+    
+    pub fn observe_processing_duration(&self, worker_id: usize, duration: f64) {
+        self.processing_duration
+            .with_label_values(&[&worker_id.to_string()])
+            .observe(duration);
+    }
+    
+    pub fn observe_batch_size(&self, worker_id: usize, size: f64) {
+        self.processing_batch_size
+            .with_label_values(&[&worker_id.to_string()])
+            .observe(size);
+    }
+    
+    pub fn set_effective_batch_size(&self, topic: &str, size: usize) {
+        self.processing_effective_batch_size
+            .with_label_values(&[topic])
+            .set(size as i64);
+    }
+    
+    pub fn set_table_entries(&self, table: &str, entries: usize) {
+        self.table_entries
+            .with_label_values(&[table])
+            .set(entries as i64);
+    }
+    
+    pub fn increment_table_updates_dropped(&self, table: &str) {
+        self.table_updates_dropped
+            .with_label_values(&[table])
+            .inc();
+    }
+    
+    pub fn increment_cardinality_limited(&self, action: &str) {
+        self.processing_cardinality_limited
+            .with_label_values(&[action])
+            .inc();
+    }
+    
+    pub fn set_log_templates(&self, templates: usize) {
+        self.log_templates.set(templates as i64);
+    }
+    
+    pub fn increment_log_templates_created(&self) {
+        self.log_templates_created.inc();
+    }
+    
+    pub fn set_sessions_open(&self, sessions: usize) {
+        self.sessions_open.set(sessions as i64);
+    }
+    
+    pub fn increment_sessions_closed(&self, reason: &str) {
+        self.sessions_closed
+            .with_label_values(&[reason])
+            .inc();
+    }
+    
+    pub fn increment_schema_changes(&self, topic: &str, outcome: &str) {
+        self.schema_changes
+            .with_label_values(&[topic, outcome])
+            .inc();
+    }
+    
+    pub fn increment_records_rejected(&self, topic: &str, reason: &str) {
+        self.records_rejected
+            .with_label_values(&[topic, reason])
+            .inc();
+    }
+    
+    pub fn increment_transform_timeouts(&self, topic: &str) {
+        self.transform_timeouts
+            .with_label_values(&[topic])
+            .inc();
+    }
+    
+    pub fn increment_processing_errors(&self, worker_id: usize) {
+        self.processing_errors
+            .with_label_values(&[&worker_id.to_string()])
+            .inc();
+    }
+    
+    pub fn observe_end_to_end_latency(&self, topic: &str, seconds: f64) {
+        self.pipeline_end_to_end_latency
+            .with_label_values(&[topic])
+            .observe(seconds);
+    }
+    
+    pub fn observe_stage_latency(&self, topic: &str, stage: &str, seconds: f64) {
+        self.pipeline_stage_latency
+            .with_label_values(&[topic, stage])
+            .observe(seconds);
+    }
+    
+    pub fn increment_processing_retries(&self) {
+        self.processing_retries.inc();
+    }
+    
+    pub fn set_processing_memory_used(&self, bytes: i64) {
+        self.processing_memory_used_bytes.set(bytes);
+    }
+    
+    pub fn set_processing_overflow_bytes(&self, bytes: i64) {
+        self.processing_overflow_bytes.set(bytes);
+    }
+    
+    pub fn increment_processing_overflow_batches(&self) {
+        self.processing_overflow_batches.inc();
+    }
+    
+    pub fn increment_database_operations(&self) {
+        self.database_operations.inc();
+    }
+    
+    pub fn increment_database_errors(&self) {
+        self.database_errors.inc();
+    }
+    
+    pub fn set_connection_pool_size(&self, size: i64) {
+        self.database_connection_pool_size.set(size);
+    }
+    
+    pub fn set_connection_pool_available(&self, available: i64) {
+        self.database_connection_pool_available.set(available);
+    }
+    
+    pub fn set_database_healthy(&self, healthy: bool) {
+        self.database_healthy.set(healthy as i64);
+    }
+    
+    pub fn set_spill_bytes(&self, bytes: i64) {
+        self.database_spill_bytes.set(bytes);
+    }
+    
+    pub fn increment_spilled_batches(&self) {
+        self.database_spilled_batches.inc();
+    }
+    
+    pub fn increment_replayed_batches(&self, count: u64) {
+        self.database_replayed_batches.inc_by(count);
+    }
+    
+    pub fn increment_duplicate_writes(&self, count: u64) {
+        self.database_duplicate_writes.inc_by(count);
+    }
+    
+    pub fn set_watermark(&self, timestamp: i64) {
+        self.stream_watermark.set(timestamp);
+    }
+    
+    pub fn increment_window_count(&self) {
+        self.stream_window_count.inc();
+    }
+    
+    pub fn increment_late_records(&self) {
+        self.stream_late_records.inc();
+    }
+    
+    pub fn increment_hub_messages_dropped(&self, count: u64) {
+        self.hub_messages_dropped.inc_by(count);
+    }
+    
+    pub fn increment_hub_slow_consumer_disconnects(&self) {
+        self.hub_slow_consumer_disconnects.inc();
+    }
+    
+    pub fn increment_notifications(&self, channel: &str, status: &str) {
+        self.notifications.with_label_values(&[channel, status]).inc();
+    }
+    
+    pub fn increment_alerts_suppressed(&self, tenant_id: &str) {
+        self.alerts_suppressed.with_label_values(&[tenant_id]).inc();
+    }
+    
+    pub fn increment_audit_write_failures(&self) {
+        self.audit_write_failures.inc();
+    }
+    
+    pub fn set_memory_usage(&self, bytes: i64) {
+        self.memory_usage_bytes.set(bytes);
+    }
+    
+    pub fn set_cpu_usage(&self, percent: f64) {
+        self.cpu_usage_percent.set(percent);
+    }
+    
+    pub fn set_open_fds(&self, count: i64) {
+        self.open_fds.set(count);
+    }
+    
+    pub fn set_active_tasks(&self, count: i64) {
+        self.active_tasks.set(count);
+    }
+}
+
+/// Resolves a configured bucket layout into strictly increasing upper bounds.
+fn bucket_bounds(buckets: &HistogramBuckets) -> Result<Vec<f64>> {
+    let bounds = match buckets {
+        HistogramBuckets::Explicit { bounds } => bounds.clone(),
+        HistogramBuckets::Exponential { start, factor, count } => {
+            prometheus::exponential_buckets(*start, *factor, *count)
+                .map_err(|e| anyhow::anyhow!("Invalid exponential histogram buckets: {}", e))?
+        }
+    };
+
+    if bounds.is_empty() {
+        return Err(anyhow::anyhow!("Histogram buckets must not be empty"));
+    }
+    if bounds.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(anyhow::anyhow!("Histogram buckets must be strictly increasing: {:?}", bounds));
+    }
+    Ok(bounds)
+}
+
+/// Samples process and tokio runtime statistics into [`Metrics`].
+///
+/// CPU usage is measured between consecutive calls to [`collect`], so the
+/// first sample after construction reads as zero.
+///
+/// [`collect`]: SystemCollector::collect
+pub struct SystemCollector {
+    system: System,
+    pid: Pid,
+}
+
+impl SystemCollector {
+    pub fn new() -> Result<Self> {
+        let pid = sysinfo::get_current_pid()
+            .map_err(|e| anyhow::anyhow!("Failed to determine current process id: {}", e))?;
+        Ok(Self {
+            system: System::new(),
+            pid,
+        })
+    }
+
+    /// Must be called from within a tokio runtime.
+    pub fn collect(&mut self, metrics: &Metrics) {
+        let refresh = ProcessRefreshKind::new().with_cpu().with_memory();
+        if self.system.refresh_process_specifics(self.pid, refresh) {
+            if let Some(process) = self.system.process(self.pid) {
+                metrics.set_memory_usage(process.memory() as i64);
+                metrics.set_cpu_usage(process.cpu_usage() as f64);
+            }
+        } else {
+            warn!("Failed to refresh statistics for process {}", self.pid);
+        }
+
+        if let Some(count) = open_fd_count() {
+            metrics.set_open_fds(count);
+        }
+
+        let runtime = tokio::runtime::Handle::current().metrics();
+        metrics.runtime_workers.set(runtime.num_workers() as i64);
+        metrics.set_active_tasks(runtime.num_alive_tasks() as i64);
+        metrics.runtime_global_queue_depth.set(runtime.global_queue_depth() as i64);
+        // Blocking pool statistics are only exposed by tokio under
+        // `--cfg tokio_unstable`; the gauge stays at zero otherwise.
+        #[cfg(tokio_unstable)]
+        metrics.runtime_blocking_threads.set(runtime.num_blocking_threads() as i64);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_fd_count() -> Option<i64> {
+    std::fs::read_dir("/proc/self/fd").ok().map(|entries| entries.count() as i64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_fd_count() -> Option<i64> {
+    None
+}
+
+#[derive(Clone)]
+struct ServerState {
+    metrics: Arc<Metrics>,
+    health: Arc<HealthState>,
+    topologies: Arc<Topologies>,
+    poison: Arc<PoisonGuard>,
+    auth: Option<MetricsAuth>,
+}
+
+/// Serves `/metrics`, `/healthz` and `/readyz` until `shutdown` resolves,
+/// along with `/pipelines` and `/pipelines/:id`, the stage graphs of running
+/// pipelines with per-stage counters, and `/partitions/held`, the partitions
+/// paused at poison messages, each released by a `DELETE` of
+/// `/partitions/held/:topic/:partition`. When `config.auth` is set, all but
+/// the probe endpoints require matching credentials, so orchestrators can
+/// still reach those.
+pub async fn start_server<F>(
+    config: MetricsConfig,
+    metrics: Arc<Metrics>,
+    health: Arc<HealthState>,
+    topologies: Arc<Topologies>,
+    poison: Arc<PoisonGuard>,
+    shutdown: F,
+) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let addr: SocketAddr = format!("{}:{}", config.host, config.port)
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid metrics server address: {}", e))?;
+
+    let state = ServerState {
+        metrics,
+        health,
+        topologies,
+        poison,
+        auth: config.auth,
+    };
+
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/pipelines", get(pipelines_handler))
+        .route("/pipelines/:id", get(pipeline_handler))
+        .route("/partitions/held", get(held_partitions_handler))
+        .route("/partitions/held/:topic/:partition", delete(release_partition_handler))
+        .with_state(state);
+
+    info!("Metrics server listening on {}", addr);
+
+    axum::Server::try_bind(&addr)
+        .map_err(|e| anyhow::anyhow!("Failed to bind metrics server to {}: {}", addr, e))?
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(|e| anyhow::anyhow!("Metrics server error: {}", e))?;
+
+    info!("Metrics server stopped");
+    Ok(())
+}
+
+async fn metrics_handler(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    if let Some(challenge) = challenge(&state, &headers) {
+        return challenge;
+    }
+
+    match prometheus::TextEncoder::new().encode_to_string(state.metrics.registry()) {
+        Ok(body) => ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response(),
+        Err(e) => {
+            error!("Failed to encode metrics: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn pipelines_handler(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    if let Some(challenge) = challenge(&state, &headers) {
+        return challenge;
+    }
+    Json(state.topologies.snapshot()).into_response()
+}
+
+async fn pipeline_handler(State(state): State<ServerState>, Path(id): Path<String>, headers: HeaderMap) -> Response {
+    if let Some(challenge) = challenge(&state, &headers) {
+        return challenge;
+    }
+    match state.topologies.get(&id) {
+        Some(topology) => Json(topology).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("No running pipeline {}", id)).into_response(),
+    }
+}
+
+async fn held_partitions_handler(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    if let Some(challenge) = challenge(&state, &headers) {
+        return challenge;
+    }
+    Json(state.poison.holds()).into_response()
+}
+
+async fn release_partition_handler(
+    State(state): State<ServerState>,
+    Path((topic, partition)): Path<(String, i32)>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(challenge) = challenge(&state, &headers) {
+        return challenge;
+    }
+    match state.poison.release(&topic, partition) {
+        Some(hold) => Json(hold).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("{}/{} is not held", topic, partition)).into_response(),
+    }
+}
+
+/// The response asking for credentials, when auth is configured and
+/// `headers` do not carry matching ones.
+fn challenge(state: &ServerState, headers: &HeaderMap) -> Option<Response> {
+    let auth = state.auth.as_ref()?;
+    if is_authorized(auth, headers) {
+        return None;
+    }
+    let challenge = match auth {
+        MetricsAuth::Basic { .. } => "Basic realm=\"metrics\"",
+        MetricsAuth::Bearer { .. } => "Bearer",
+    };
+    Some((StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, challenge)]).into_response())
+}
+
+async fn healthz_handler() -> &'static str {
+    "ok"
+}
+
+async fn readyz_handler(State(state): State<ServerState>) -> Response {
+    let status = if state.health.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(state.health.snapshot())).into_response()
+}
+
+fn is_authorized(auth: &MetricsAuth, headers: &HeaderMap) -> bool {
+    let value = match headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        Some(value) => value,
+        None => return false,
+    };
+
+    match auth {
+        MetricsAuth::Basic { username, password } => {
+            use base64::Engine;
+            let expected = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+            value
+                .strip_prefix("Basic ")
+                .map_or(false, |given| constant_time_eq(given.as_bytes(), expected.as_bytes()))
+        }
+        MetricsAuth::Bearer { token } => value
+            .strip_prefix("Bearer ")
+            .map_or(false, |given| constant_time_eq(given.as_bytes(), token.as_bytes())),
+    }
+}
+
+/// Compares credentials without short-circuiting on the first mismatch.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_metrics_auth() {
+        let basic = MetricsAuth::Basic {
+            username: "prom".to_string(),
+            password: "secret".to_string(),
+        };
+        // base64("prom:secret")
+        assert!(is_authorized(&basic, &headers("Basic cHJvbTpzZWNyZXQ=")));
+        assert!(!is_authorized(&basic, &headers("Basic cHJvbTp3cm9uZw==")));
+        assert!(!is_authorized(&basic, &HeaderMap::new()));
+
+        let bearer = MetricsAuth::Bearer {
+            token: "t0ken".to_string(),
+        };
+        assert!(is_authorized(&bearer, &headers("Bearer t0ken")));
+        assert!(!is_authorized(&bearer, &headers("Bearer t0ke")));
+        assert!(!is_authorized(&bearer, &headers("Basic t0ken")));
+    }
+
+    #[test]
+    fn test_labeled_kafka_metrics() {
+        let metrics = Metrics::new(&MetricsConfig::default()).unwrap();
+        metrics.increment_messages_received("events", 3, 2);
+        metrics.increment_messages_processed("events", 3, 1, 2);
+        metrics.increment_messages_failed(None, None, 1);
+        metrics.set_consumer_lag("events", 3, 42);
+
+        let body = prometheus::TextEncoder::new()
+            .encode_to_string(metrics.registry())
+            .unwrap();
+        assert!(body.contains(r#"kafka_messages_received_total{partition="3",topic="events"} 2"#));
+        assert!(body.contains(r#"kafka_messages_processed_total{partition="3",topic="events",worker_id="1"} 2"#));
+        assert!(body.contains(r#"kafka_consumer_lag{partition="3",topic="events"} 42"#));
+        assert!(body.contains("kafka_messages_failed_total"));
+    }
+
+    #[test]
+    fn test_bucket_bounds() {
+        let exponential = HistogramBuckets::Exponential {
+            start: 0.001,
+            factor: 10.0,
+            count: 3,
+        };
+        let bounds = bucket_bounds(&exponential).unwrap();
+        assert_eq!(bounds.len(), 3);
+        assert!((bounds[2] - 0.1).abs() < 1e-12);
+
+        let explicit = HistogramBuckets::Explicit {
+            bounds: vec![0.5, 0.1],
+        };
+        assert!(bucket_bounds(&explicit).is_err());
+        assert!(bucket_bounds(&HistogramBuckets::Explicit { bounds: vec![] }).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_system_collector_reports_process_stats() {
+        let metrics = Metrics::new(&MetricsConfig::default()).unwrap();
+        let mut collector = SystemCollector::new().unwrap();
+        collector.collect(&metrics);
+
+        assert!(metrics.memory_usage_bytes.get() > 0);
+        assert!(metrics.runtime_workers.get() >= 1);
+        if cfg!(target_os = "linux") {
+            assert!(metrics.open_fds.get() > 0);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use super::Metrics;
+use crate::config::{OtlpMetricsConfig, TelemetryConfig};
+use anyhow::Result;
+use crate::telemetry;
+use opentelemetry::metrics::Unit;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::metrics::data::{
+    self, DataPoint, Gauge, Histogram, HistogramDataPoint, ScopeMetrics, Sum, Temporality,
+};
+use opentelemetry_sdk::metrics::reader::{DefaultAggregationSelector, DefaultTemporalitySelector, MetricProducer};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::{AttributeSet, InstrumentationLibrary};
+use prometheus::proto::{MetricFamily, MetricType};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::info;
+
+/// Starts a periodic OTLP export of the Prometheus registry.
+///
+/// The registry stays the single source of truth: every collection cycle
+/// converts the gathered families into OTel data, so both export paths
+/// report identical values. The returned provider must be shut down on exit
+/// to flush the final export.
+pub fn init(
+    config: &OtlpMetricsConfig,
+    telemetry: &TelemetryConfig,
+    metrics: Arc<Metrics>,
+) -> Result<SdkMeterProvider> {
+    let exporter = opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(&config.endpoint)
+        .with_timeout(config.timeout)
+        .build_metrics_exporter(
+            Box::new(DefaultAggregationSelector::new()),
+            Box::new(DefaultTemporalitySelector::new()),
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to create OTLP metrics exporter: {}", e))?;
+
+    let reader = PeriodicReader::builder(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_interval(config.interval)
+        .with_producer(RegistryProducer::new(metrics))
+        .build();
+
+    let provider = SdkMeterProvider::builder()
+        .with_reader(reader)
+        .with_resource(telemetry::resource(telemetry))
+        .build();
+
+    info!("Exporting metrics over OTLP to {} every {:?}", config.endpoint, config.interval);
+    Ok(provider)
+}
+
+/// Feeds the Prometheus registry into the OTel reader on every collection.
+struct RegistryProducer {
+    metrics: Arc<Metrics>,
+    start_time: SystemTime,
+}
+
+impl RegistryProducer {
+    fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            metrics,
+            start_time: SystemTime::now(),
+        }
+    }
+}
+
+impl std::fmt::Debug for RegistryProducer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryProducer")
+            .field("start_time", &self.start_time)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MetricProducer for RegistryProducer {
+    fn produce(&self) -> opentelemetry::metrics::Result<ScopeMetrics> {
+        let now = SystemTime::now();
+        let metrics = self
+            .metrics
+            .registry()
+            .gather()
+            .iter()
+            .filter_map(|family| convert_family(family, self.start_time, now))
+            .collect();
+
+        Ok(ScopeMetrics {
+            scope: InstrumentationLibrary::new("stream-processor", Some(env!("CARGO_PKG_VERSION")), None, None),
+            metrics,
+        })
+    }
+}
+
+/// Maps one Prometheus family onto the equivalent OTel aggregation.
+/// Prometheus counters and histograms are cumulative since process start.
+/// Summaries have no OTel counterpart and are skipped; the registry does
+/// not define any.
+fn convert_family(family: &MetricFamily, start_time: SystemTime, now: SystemTime) -> Option<data::Metric> {
+    let attributes = |metric: &prometheus::proto::Metric| {
+        let pairs: Vec<KeyValue> = metric
+            .get_label()
+            .iter()
+            .map(|label| KeyValue::new(label.get_name().to_string(), label.get_value().to_string()))
+            .collect();
+        AttributeSet::from(pairs.as_slice())
+    };
+    let point = |metric: &prometheus::proto::Metric, value: f64| DataPoint {
+        attributes: attributes(metric),
+        start_time: Some(start_time),
+        time: Some(now),
+        value,
+        exemplars: Vec::new(),
+    };
+
+    let data: Box<dyn data::Aggregation> = match family.get_field_type() {
+        MetricType::COUNTER => Box::new(Sum {
+            data_points: family
+                .get_metric()
+                .iter()
+                .map(|m| point(m, m.get_counter().get_value()))
+                .collect(),
+            temporality: Temporality::Cumulative,
+            is_monotonic: true,
+        }),
+        MetricType::GAUGE => Box::new(Gauge {
+            data_points: family
+                .get_metric()
+                .iter()
+                .map(|m| point(m, m.get_gauge().get_value()))
+                .collect(),
+        }),
+        MetricType::UNTYPED => Box::new(Gauge {
+            data_points: family
+                .get_metric()
+                .iter()
+                .map(|m| point(m, m.get_untyped().get_value()))
+                .collect(),
+        }),
+        MetricType::HISTOGRAM => Box::new(Histogram {
+            data_points: family
+                .get_metric()
+                .iter()
+                .map(|m| {
+                    let histogram = m.get_histogram();
+                    // Prometheus buckets are cumulative and exclude +Inf;
+                    // OTel wants per-bucket counts plus an overflow bucket.
+                    let bounds: Vec<f64> = histogram.get_bucket().iter().map(|b| b.get_upper_bound()).collect();
+                    let mut bucket_counts = Vec::with_capacity(bounds.len() + 1);
+                    let mut previous = 0;
+                    for bucket in histogram.get_bucket() {
+                        bucket_counts.push(bucket.get_cumulative_count() - previous);
+                        previous = bucket.get_cumulative_count();
+                    }
+                    bucket_counts.push(histogram.get_sample_count() - previous);
+
+                    HistogramDataPoint {
+                        attributes: attributes(m),
+                        start_time,
+                        time: now,
+                        count: histogram.get_sample_count(),
+                        bounds,
+                        bucket_counts,
+                        min: None,
+                        max: None,
+                        sum: histogram.get_sample_sum(),
+                        exemplars: Vec::new(),
+                    }
+                })
+                .collect(),
+            temporality: Temporality::Cumulative,
+        }),
+        MetricType::SUMMARY => return None,
+    };
+
+    Some(data::Metric {
+        name: family.get_name().to_string().into(),
+        description: family.get_help().to_string().into(),
+        unit: Unit::new(""),
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+
+    #[test]
+    fn test_histogram_buckets_are_decumulated() {
+        let metrics = Metrics::new(&MetricsConfig::default()).unwrap();
+        for size in [1.0, 1.0, 3.0, 5000.0] {
+            metrics.observe_batch_size(0, size);
+        }
+
+        let family = metrics
+            .registry()
+            .gather()
+            .into_iter()
+            .find(|f| f.get_name() == "processing_batch_size")
+            .unwrap();
+        let now = SystemTime::now();
+        let metric = convert_family(&family, now, now).unwrap();
+        let histogram = metric.data.as_any().downcast_ref::<Histogram<f64>>().unwrap();
+        let point = &histogram.data_points[0];
+
+        assert_eq!(point.count, 4);
+        assert_eq!(point.bucket_counts.len(), point.bounds.len() + 1);
+        assert_eq!(point.bucket_counts.iter().sum::<u64>(), 4);
+        // 1.0 falls in the first bucket, 5000 overflows the 2048 upper bound.
+        assert_eq!(point.bucket_counts[0], 2);
+        assert_eq!(*point.bucket_counts.last().unwrap(), 1);
+    }
+}
@@ -0,0 +1,158 @@
+use super::Metrics;
+use crate::config::{MetricsPushConfig, PushgatewayConfig};
+use anyhow::Result;
+use prometheus::proto::{MetricFamily, MetricType};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Pushes the registry to every configured target on `config.interval`.
+/// A failing target is logged and retried on the next tick; it never stops
+/// the other target or the processor.
+pub async fn run(metrics: Arc<Metrics>, config: MetricsPushConfig) {
+    let http = reqwest::Client::new();
+    let instance = config
+        .pushgateway
+        .as_ref()
+        .and_then(|gateway| gateway.instance.clone())
+        .or_else(sysinfo::System::host_name)
+        .unwrap_or_else(|| "unknown".to_string());
+    let sdk = config.streamforge.as_ref().map(|target| {
+        streamforge_sdk::Client::new(streamforge_sdk::Config {
+            api_url: target.api_url.clone(),
+            api_key: target.api_key.clone(),
+            ..Default::default()
+        })
+    });
+
+    info!("Pushing metrics every {:?}", config.interval);
+    let mut ticker = tokio::time::interval(config.interval);
+
+    loop {
+        ticker.tick().await;
+
+        if let Some(gateway) = &config.pushgateway {
+            if let Err(e) = push_to_gateway(&http, gateway, &instance, &metrics).await {
+                warn!("Failed to push metrics to Pushgateway: {}", e);
+            }
+        }
+
+        if let Some(sdk) = &sdk {
+            let samples = to_sdk_metrics(&metrics.registry().gather(), &instance);
+            if let Err(e) = sdk.send_metrics(samples).await {
+                warn!("Failed to report metrics to StreamForge: {}", e);
+            }
+        }
+    }
+}
+
+/// Replaces this instance's group on the gateway with the current registry.
+async fn push_to_gateway(
+    http: &reqwest::Client,
+    gateway: &PushgatewayConfig,
+    instance: &str,
+    metrics: &Metrics,
+) -> Result<()> {
+    let body = prometheus::TextEncoder::new()
+        .encode_to_string(metrics.registry())
+        .map_err(|e| anyhow::anyhow!("Failed to encode metrics: {}", e))?;
+    let url = format!(
+        "{}/metrics/job/{}/instance/{}",
+        gateway.url.trim_end_matches('/'),
+        gateway.job,
+        instance
+    );
+
+    http.put(&url)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| anyhow::anyhow!("Failed to push to {}: {}", url, e))?;
+    Ok(())
+}
+
+/// Flattens gathered families into SDK samples. Counters and gauges map
+/// one-to-one; histograms and summaries are reported as their `_sum` and
+/// `_count` series.
+fn to_sdk_metrics(families: &[MetricFamily], instance: &str) -> Vec<streamforge_sdk::Metric> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let mut samples = Vec::new();
+    for family in families {
+        for metric in family.get_metric() {
+            let mut labels: HashMap<String, String> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+                .collect();
+            labels.insert("instance".to_string(), instance.to_string());
+
+            let values = match family.get_field_type() {
+                MetricType::COUNTER => vec![(family.get_name().to_string(), metric.get_counter().get_value())],
+                MetricType::GAUGE => vec![(family.get_name().to_string(), metric.get_gauge().get_value())],
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    vec![
+                        (format!("{}_sum", family.get_name()), histogram.get_sample_sum()),
+                        (format!("{}_count", family.get_name()), histogram.get_sample_count() as f64),
+                    ]
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    vec![
+                        (format!("{}_sum", family.get_name()), summary.get_sample_sum()),
+                        (format!("{}_count", family.get_name()), summary.get_sample_count() as f64),
+                    ]
+                }
+                MetricType::UNTYPED => vec![(family.get_name().to_string(), metric.get_untyped().get_value())],
+            };
+
+            for (name, value) in values {
+                samples.push(streamforge_sdk::Metric {
+                    name,
+                    value,
+                    unit: String::new(),
+                    labels: Some(labels.clone()),
+                    timestamp: Some(timestamp),
+                });
+            }
+        }
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+
+    #[test]
+    fn test_to_sdk_metrics_flattens_families() {
+        let metrics = Metrics::new(&MetricsConfig::default()).unwrap();
+        metrics.increment_messages_received("events", 0, 3);
+        metrics.observe_batch_size(1, 8.0);
+
+        let samples = to_sdk_metrics(&metrics.registry().gather(), "host-1");
+
+        let received = samples
+            .iter()
+            .find(|s| s.name == "kafka_messages_received_total")
+            .unwrap();
+        assert_eq!(received.value, 3.0);
+        let labels = received.labels.as_ref().unwrap();
+        assert_eq!(labels["topic"], "events");
+        assert_eq!(labels["instance"], "host-1");
+
+        let count = samples
+            .iter()
+            .find(|s| s.name == "processing_batch_size_count")
+            .unwrap();
+        assert_eq!(count.value, 1.0);
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde_json::json;
+use std::time::Duration;
+
+use super::{alert_time, render, Notification, DEFAULT_TEMPLATE};
+use crate::config::{NotificationChannelConfig, NotificationChannelKind, NotifierConfig};
+
+/// Why an attempt failed, and whether trying again may help.
+pub(super) struct DeliveryError {
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl DeliveryError {
+    fn retryable(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            retryable: true,
+        }
+    }
+
+    fn permanent(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            retryable: false,
+        }
+    }
+}
+
+pub(super) struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+/// Sends notifications to one configured channel.
+pub(super) struct ChannelSender {
+    config: NotificationChannelConfig,
+    retry: RetryPolicy,
+    http: reqwest::Client,
+    smtp: Option<Smtp>,
+}
+
+struct Smtp {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl ChannelSender {
+    pub fn new(config: &NotificationChannelConfig, notifier: &NotifierConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(notifier.timeout)
+            .build()
+            .map_err(|e| anyhow::anyhow!("Failed to create HTTP client for channel {}: {}", config.name, e))?;
+
+        let smtp = match &config.kind {
+            NotificationChannelKind::Email {
+                smtp_host,
+                smtp_port,
+                username,
+                password,
+                from,
+                to,
+            } => {
+                let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(smtp_host)
+                    .map_err(|e| anyhow::anyhow!("Invalid SMTP host {}: {}", smtp_host, e))?
+                    .port(*smtp_port)
+                    .timeout(Some(notifier.timeout));
+                if let (Some(username), Some(password)) = (username, password) {
+                    transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+                }
+                let mailbox = |address: &String| {
+                    address
+                        .parse::<Mailbox>()
+                        .map_err(|e| anyhow::anyhow!("Invalid email address {} for channel {}: {}", address, config.name, e))
+                };
+                Some(Smtp {
+                    transport: transport.build(),
+                    from: mailbox(from)?,
+                    to: to.iter().map(mailbox).collect::<Result<_>>()?,
+                })
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            retry: RetryPolicy {
+                max_attempts: notifier.max_attempts,
+                initial_backoff: notifier.initial_backoff,
+                max_backoff: notifier.max_backoff,
+            },
+            http,
+            smtp,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    pub async fn send(&self, notification: &Notification) -> Result<(), DeliveryError> {
+        let text = render(self.config.template.as_deref().unwrap_or(DEFAULT_TEMPLATE), notification);
+        match &self.config.kind {
+            NotificationChannelKind::Webhook { url, headers } => {
+                let mut request = self.http.post(url);
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+                // Without a template the receiver gets the alert itself
+                request = match &self.config.template {
+                    Some(_) if serde_json::from_str::<serde_json::Value>(&text).is_ok() => {
+                        request.header(reqwest::header::CONTENT_TYPE, "application/json").body(text)
+                    }
+                    Some(_) => request.header(reqwest::header::CONTENT_TYPE, "text/plain").body(text),
+                    None => request.json(&json!({
+                        "tenant_id": notification.tenant_id,
+                        "alert": notification.alert,
+                    })),
+                };
+                post(request).await
+            }
+            NotificationChannelKind::Slack { webhook_url } => {
+                post(self.http.post(webhook_url).json(&json!({ "text": text }))).await
+            }
+            NotificationChannelKind::PagerDuty {
+                routing_key,
+                events_url,
+            } => {
+                let alert = &notification.alert;
+                let event = json!({
+                    "routing_key": routing_key,
+                    "event_action": "trigger",
+                    // Repeats of an alert update its incident instead of opening more
+                    "dedup_key": alert.id,
+                    "payload": {
+                        "summary": text,
+                        "source": alert.service,
+                        "severity": pagerduty_severity(&alert.severity),
+                        "timestamp": alert_time(alert).map(|time| time.to_rfc3339()),
+                        "custom_details": alert.metadata,
+                    },
+                });
+                post(self.http.post(events_url).json(&event)).await
+            }
+            NotificationChannelKind::Email { .. } => {
+                let smtp = self
+                    .smtp
+                    .as_ref()
+                    .ok_or_else(|| DeliveryError::permanent("SMTP transport is not configured"))?;
+                let mut email = Message::builder()
+                    .from(smtp.from.clone())
+                    .subject(render(DEFAULT_TEMPLATE, notification));
+                for to in &smtp.to {
+                    email = email.to(to.clone());
+                }
+                let email = email
+                    .header(ContentType::TEXT_PLAIN)
+                    .body(text)
+                    .map_err(|e| DeliveryError::permanent(format!("Failed to build email: {}", e)))?;
+
+                smtp.transport.send(email).await.map(|_| ()).map_err(|e| {
+                    let message = format!("SMTP delivery failed: {}", e);
+                    if e.is_permanent() {
+                        DeliveryError::permanent(message)
+                    } else {
+                        DeliveryError::retryable(message)
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Sends an HTTP notification. Rate limiting and server errors are worth
+/// retrying; other rejections are not.
+async fn post(request: reqwest::RequestBuilder) -> Result<(), DeliveryError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|e| DeliveryError::retryable(format!("Request failed: {}", e)))?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = format!("Channel responded with {}: {}", status, body.chars().take(200).collect::<String>());
+    if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        Err(DeliveryError::retryable(message))
+    } else {
+        Err(DeliveryError::permanent(message))
+    }
+}
+
+/// Maps alert severities onto the four PagerDuty accepts.
+fn pagerduty_severity(severity: &str) -> &'static str {
+    match severity.to_ascii_lowercase().as_str() {
+        "critical" | "fatal" => "critical",
+        "error" | "high" => "error",
+        "warning" | "warn" | "medium" => "warning",
+        _ => "info",
+    }
+}
@@ -0,0 +1,423 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use streamforge_sdk::Alert;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::config::{NotificationChannelConfig, NotifierConfig};
+use crate::metrics::Metrics;
+use crate::processor::ProcessedMessage;
+use crate::storage::{AlertDelivery, Silence, StorageBackend};
+
+mod channel;
+
+use channel::ChannelSender;
+
+pub const DELIVERY_PENDING: &str = "pending";
+pub const DELIVERY_DELIVERED: &str = "delivered";
+pub const DELIVERY_FAILED: &str = "failed";
+pub const DELIVERY_RATE_LIMITED: &str = "rate_limited";
+
+/// Message text of channels without a template of their own.
+pub const DEFAULT_TEMPLATE: &str = "[{{severity}}] {{service}}: {{message}}";
+
+/// An alert on its way to the channels, with the tenant it belongs to.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub tenant_id: String,
+    pub alert: Alert,
+}
+
+/// Delivers processed alerts to the configured channels. Each channel has
+/// its own queue and worker, so a slow or failing channel only holds up its
+/// own notifications. Every delivery is recorded in storage as it
+/// progresses. Alerts matching an active silence are not sent at all.
+pub struct Notifier {
+    channels: Vec<ChannelQueue>,
+    /// Taken by [`Notifier::run`].
+    workers: Mutex<Vec<ChannelWorker>>,
+    /// Unexpired silences, reloaded every `silence_refresh_interval`.
+    silences: RwLock<Vec<Silence>>,
+    silence_refresh_interval: Duration,
+    storage: Arc<dyn StorageBackend>,
+    metrics: Arc<Metrics>,
+}
+
+struct ChannelQueue {
+    config: NotificationChannelConfig,
+    tx: mpsc::Sender<Notification>,
+}
+
+struct ChannelWorker {
+    sender: ChannelSender,
+    rx: mpsc::Receiver<Notification>,
+    limiter: Option<RateLimiter>,
+}
+
+impl Notifier {
+    /// Sets up a queue per channel. Nothing is delivered until
+    /// [`Notifier::run`] is started, and nothing is queued when the notifier
+    /// is disabled.
+    pub fn new(config: &NotifierConfig, storage: Arc<dyn StorageBackend>, metrics: Arc<Metrics>) -> Result<Self> {
+        let mut channels = Vec::new();
+        let mut workers = Vec::new();
+        if config.enabled {
+            for channel in &config.channels {
+                let (tx, rx) = mpsc::channel(config.queue_size.max(1));
+                workers.push(ChannelWorker {
+                    sender: ChannelSender::new(channel, config)?,
+                    rx,
+                    limiter: channel.rate_limit_per_minute.map(RateLimiter::per_minute),
+                });
+                channels.push(ChannelQueue {
+                    config: channel.clone(),
+                    tx,
+                });
+            }
+        }
+
+        Ok(Self {
+            channels,
+            workers: Mutex::new(workers),
+            silences: RwLock::new(Vec::new()),
+            silence_refresh_interval: config.silence_refresh_interval,
+            storage,
+            metrics,
+        })
+    }
+
+    /// Queues the processed messages shaped like an SDK `Alert`.
+    pub fn notify(&self, messages: &[ProcessedMessage]) {
+        if self.channels.is_empty() {
+            return;
+        }
+        for message in messages {
+            if let Ok(alert) = Alert::deserialize(&message.processed_message) {
+                self.send(Notification {
+                    tenant_id: message.processing_metadata.tenant_id.clone(),
+                    alert,
+                });
+            }
+        }
+    }
+
+    /// Queues `notification` on every channel that routes its severity,
+    /// unless a silence suppresses it. A full queue fails the delivery
+    /// rather than holding up processing.
+    pub fn send(&self, notification: Notification) {
+        if let Some(silence) = self.silenced_by(&notification, Utc::now()) {
+            debug!(
+                "Alert {} of tenant {} suppressed by silence {}",
+                notification.alert.id, notification.tenant_id, silence
+            );
+            self.metrics.increment_alerts_suppressed(&notification.tenant_id);
+            return;
+        }
+
+        for channel in &self.channels {
+            if !routes(&channel.config, &notification.alert) {
+                continue;
+            }
+            if let Err(e) = channel.tx.try_send(notification.clone()) {
+                warn!(
+                    "Notification queue of channel {} is unavailable; alert {} not sent: {}",
+                    channel.config.name, notification.alert.id, e
+                );
+                let mut delivery = new_delivery(&channel.config.name, &notification);
+                delivery.status = DELIVERY_FAILED.to_string();
+                delivery.last_error = Some(format!("Notification queue unavailable: {}", e));
+                self.metrics.increment_notifications(&channel.config.name, DELIVERY_FAILED);
+
+                let storage = self.storage.clone();
+                tokio::spawn(async move { record(storage.as_ref(), &delivery).await });
+            }
+        }
+    }
+
+    /// Delivers queued notifications and keeps the silences current until
+    /// the task is aborted.
+    pub async fn run(&self) {
+        let workers = std::mem::take(&mut *self.workers.lock().unwrap_or_else(|e| e.into_inner()));
+        info!("Delivering alert notifications to {} channel(s)", workers.len());
+
+        let deliveries = futures::future::join_all(workers.into_iter().map(|worker| self.run_channel(worker)));
+        tokio::join!(self.refresh_silences(), deliveries);
+    }
+
+    /// The id of the first silence suppressing `notification` at `at`.
+    fn silenced_by(&self, notification: &Notification, at: DateTime<Utc>) -> Option<String> {
+        let silences = self.silences.read().unwrap_or_else(|e| e.into_inner());
+        silences
+            .iter()
+            .find(|silence| matches(silence, notification, at))
+            .map(|silence| silence.id.clone())
+    }
+
+    async fn refresh_silences(&self) {
+        let mut interval = tokio::time::interval(self.silence_refresh_interval);
+        loop {
+            interval.tick().await;
+            // Keeps the previous silences while storage is unavailable
+            match self.storage.list_unexpired_silences(Utc::now()).await {
+                Ok(silences) => *self.silences.write().unwrap_or_else(|e| e.into_inner()) = silences,
+                Err(e) => warn!("Failed to refresh silences: {}", e),
+            }
+        }
+    }
+
+    async fn run_channel(&self, mut worker: ChannelWorker) {
+        while let Some(notification) = worker.rx.recv().await {
+            self.deliver(&worker.sender, worker.limiter.as_mut(), &notification).await;
+        }
+    }
+
+    async fn deliver(&self, sender: &ChannelSender, limiter: Option<&mut RateLimiter>, notification: &Notification) {
+        let channel = sender.name();
+        let mut delivery = new_delivery(channel, notification);
+
+        if let Some(limiter) = limiter {
+            if !limiter.allow(Instant::now()) {
+                delivery.status = DELIVERY_RATE_LIMITED.to_string();
+                self.metrics.increment_notifications(channel, DELIVERY_RATE_LIMITED);
+                record(self.storage.as_ref(), &delivery).await;
+                return;
+            }
+        }
+        record(self.storage.as_ref(), &delivery).await;
+
+        let mut backoff = sender.retry().initial_backoff;
+        loop {
+            delivery.attempts += 1;
+            let result = sender.send(notification).await;
+            delivery.updated_at = Utc::now();
+
+            match result {
+                Ok(()) => {
+                    delivery.status = DELIVERY_DELIVERED.to_string();
+                    delivery.last_error = None;
+                }
+                Err(e) if e.retryable && (delivery.attempts as u32) < sender.retry().max_attempts => {
+                    warn!(
+                        "Failed to deliver alert {} to {} (attempt {}), retrying in {:?}: {}",
+                        notification.alert.id, channel, delivery.attempts, backoff, e.message
+                    );
+                    delivery.last_error = Some(e.message);
+                    record(self.storage.as_ref(), &delivery).await;
+
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(sender.retry().max_backoff);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to deliver alert {} to {}: {}", notification.alert.id, channel, e.message);
+                    delivery.status = DELIVERY_FAILED.to_string();
+                    delivery.last_error = Some(e.message);
+                }
+            }
+            break;
+        }
+
+        self.metrics.increment_notifications(channel, &delivery.status);
+        record(self.storage.as_ref(), &delivery).await;
+    }
+}
+
+/// Whether `silence` suppresses `notification` at `at`: it belongs to the
+/// same tenant, is in effect, and every matcher holds.
+pub fn matches(silence: &Silence, notification: &Notification, at: DateTime<Utc>) -> bool {
+    silence.tenant_id == notification.tenant_id
+        && silence.is_active(at)
+        && silence
+            .matchers
+            .iter()
+            .all(|matcher| (field(&matcher.field, notification) == matcher.value) != matcher.negate)
+}
+
+fn routes(channel: &NotificationChannelConfig, alert: &Alert) -> bool {
+    channel.severities.is_empty() || channel.severities.iter().any(|s| s.eq_ignore_ascii_case(&alert.severity))
+}
+
+fn new_delivery(channel: &str, notification: &Notification) -> AlertDelivery {
+    let now = Utc::now();
+    AlertDelivery {
+        id: uuid::Uuid::new_v4().to_string(),
+        tenant_id: notification.tenant_id.clone(),
+        alert_id: notification.alert.id.clone(),
+        channel: channel.to_string(),
+        status: DELIVERY_PENDING.to_string(),
+        attempts: 0,
+        last_error: None,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+/// Delivery tracking is best effort; a storage outage does not stop alerts.
+async fn record(storage: &dyn StorageBackend, delivery: &AlertDelivery) {
+    if let Err(e) = storage.record_alert_delivery(delivery).await {
+        warn!("Failed to record delivery of alert {} to {}: {}", delivery.alert_id, delivery.channel, e);
+    }
+}
+
+/// Allows a number of notifications per sliding minute.
+struct RateLimiter {
+    limit: usize,
+    sent: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    fn per_minute(limit: u32) -> Self {
+        Self {
+            limit: limit as usize,
+            sent: VecDeque::new(),
+        }
+    }
+
+    fn allow(&mut self, now: Instant) -> bool {
+        while self
+            .sent
+            .front()
+            .map_or(false, |sent| now.duration_since(*sent) >= Duration::from_secs(60))
+        {
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= self.limit {
+            return false;
+        }
+        self.sent.push_back(now);
+        true
+    }
+}
+
+/// Fills the `{{field}}` placeholders of `template` from `notification`.
+/// Unknown fields render as nothing.
+pub fn render(template: &str, notification: &Notification) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let placeholder = &rest[start + 2..];
+        match placeholder.find("}}") {
+            Some(end) => {
+                rendered.push_str(&field(placeholder[..end].trim(), notification));
+                rest = &placeholder[end + 2..];
+            }
+            None => {
+                rendered.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn field(name: &str, notification: &Notification) -> String {
+    let alert = &notification.alert;
+    match name {
+        "id" => alert.id.clone(),
+        "severity" => alert.severity.clone(),
+        "message" => alert.message.clone(),
+        "service" => alert.service.clone(),
+        "tenant_id" => notification.tenant_id.clone(),
+        "timestamp" => alert_time(alert).map_or_else(|| alert.timestamp.to_string(), |time| time.to_rfc3339()),
+        _ => name
+            .strip_prefix("metadata.")
+            .and_then(|key| alert.metadata.as_ref()?.get(key))
+            .map(|value| match value {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// Alert timestamps are milliseconds since the epoch.
+fn alert_time(alert: &Alert) -> Option<DateTime<Utc>> {
+    i64::try_from(alert.timestamp).ok().and_then(DateTime::from_timestamp_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SilenceMatcher;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_render_and_rate_limit() {
+        let notification = Notification {
+            tenant_id: "acme".to_string(),
+            alert: Alert {
+                id: "a1".to_string(),
+                severity: "critical".to_string(),
+                message: "disk full".to_string(),
+                timestamp: 0,
+                service: "db".to_string(),
+                metadata: Some(HashMap::from([
+                    ("host".to_string(), json!("db-1")),
+                    ("usage".to_string(), json!(0.98)),
+                ])),
+            },
+        };
+
+        assert_eq!(render(DEFAULT_TEMPLATE, &notification), "[critical] db: disk full");
+        assert_eq!(
+            render("{{ metadata.host }} at {{metadata.usage}} {{missing}}for {{tenant_id}} {{timestamp}}", &notification),
+            "db-1 at 0.98 for acme 1970-01-01T00:00:00+00:00"
+        );
+        assert_eq!(render("unterminated {{id", &notification), "unterminated {{id");
+
+        let mut limiter = RateLimiter::per_minute(2);
+        let start = Instant::now();
+        assert!(limiter.allow(start));
+        assert!(limiter.allow(start + Duration::from_secs(10)));
+        assert!(!limiter.allow(start + Duration::from_secs(20)));
+        assert!(limiter.allow(start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_silence_matches() {
+        let notification = Notification {
+            tenant_id: "acme".to_string(),
+            alert: Alert {
+                id: "a1".to_string(),
+                severity: "warning".to_string(),
+                message: "deploy in progress".to_string(),
+                timestamp: 0,
+                service: "api".to_string(),
+                metadata: Some(HashMap::from([("env".to_string(), json!("staging"))])),
+            },
+        };
+        let now = Utc::now();
+        let matcher = |field: &str, value: &str, negate: bool| SilenceMatcher {
+            field: field.to_string(),
+            value: value.to_string(),
+            negate,
+        };
+        let mut silence = Silence {
+            id: "s1".to_string(),
+            tenant_id: "acme".to_string(),
+            matchers: vec![matcher("service", "api", false), matcher("severity", "critical", true)],
+            starts_at: now - chrono::Duration::minutes(5),
+            ends_at: now + chrono::Duration::minutes(5),
+            comment: "deploy".to_string(),
+            created_by: "ops".to_string(),
+            created_at: now,
+        };
+        assert!(matches(&silence, &notification, now));
+        assert!(!matches(&silence, &notification, now + chrono::Duration::minutes(5)));
+        assert!(!matches(&silence, &notification, now - chrono::Duration::minutes(6)));
+
+        silence.matchers.push(matcher("metadata.env", "production", false));
+        assert!(!matches(&silence, &notification, now));
+        silence.matchers.pop();
+
+        silence.tenant_id = "other".to_string();
+        assert!(!matches(&silence, &notification, now));
+    }
+}
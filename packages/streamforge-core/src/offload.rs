@@ -0,0 +1,86 @@
+//! CPU-heavy stages run on tokio's blocking pool instead of the async
+//! workers, so consuming, lookups and writes are not held up behind them.
+//! At most `processing.offload.threads` jobs run at once; the rest wait for
+//! a thread without blocking the reactor.
+
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use crate::config::OffloadConfig;
+
+#[derive(Clone)]
+pub struct Offload {
+    /// `None` runs jobs in place.
+    threads: Option<Arc<Semaphore>>,
+}
+
+impl Offload {
+    pub fn new(config: &OffloadConfig) -> Self {
+        Self {
+            threads: config.enabled.then(|| Arc::new(Semaphore::new(config.threads.max(1)))),
+        }
+    }
+
+    /// Runs `job` on a blocking thread once one is free, or in place when
+    /// offloading is off.
+    pub async fn run<F, R>(&self, job: F) -> Result<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let Some(threads) = &self.threads else {
+            return Ok(job());
+        };
+        let permit = threads
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to wait for an offload thread: {}", e))?;
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            job()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Offloaded job failed: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_jobs_never_exceed_the_thread_limit() {
+        let offload = Offload::new(&OffloadConfig {
+            enabled: true,
+            threads: 2,
+        });
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let jobs = (0..8).map(|i| {
+            let (running, peak) = (running.clone(), peak.clone());
+            offload.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                i * 2
+            })
+        });
+        let results: Vec<_> = futures::future::join_all(jobs).await.into_iter().map(Result::unwrap).collect();
+
+        assert_eq!(results, (0..8).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_job_fails_instead_of_the_caller() {
+        let offload = Offload::new(&OffloadConfig::default());
+        assert!(offload.run(|| panic!("bad regex")).await.is_err());
+        assert_eq!(offload.run(|| 1).await.unwrap(), 1);
+    }
+}
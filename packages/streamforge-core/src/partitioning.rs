@@ -0,0 +1,145 @@
+//! How a Kafka sink keys and partitions the records it produces.
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::columnar::lookup;
+
+/// The `partitioning` option of a kafka sink. Without one, records are
+/// produced unkeyed to sticky partitions, as before the option existed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum Partitioning {
+    /// Keyed by the value at a dotted field path of the output record, so
+    /// records with equal values stay in order on one partition.
+    Hash { field: String },
+    /// Unkeyed, spread over the partitions one record at a time.
+    RoundRobin,
+    /// Unkeyed, filling one partition per producer batch.
+    #[default]
+    Sticky,
+    /// The key and partition of the consumed record, keeping output
+    /// co-partitioned with the source topic.
+    PreserveSource,
+}
+
+impl Partitioning {
+    /// Reads the `partitioning` option from a kafka sink's config.
+    pub fn from_sink_config(config: &Value) -> Result<Self, String> {
+        match config.get("partitioning") {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => {
+                let partitioning = Self::deserialize(value).map_err(|e| e.to_string())?;
+                if matches!(&partitioning, Self::Hash { field } if field.is_empty()) {
+                    return Err("hash partitioning requires a field".to_string());
+                }
+                Ok(partitioning)
+            }
+        }
+    }
+}
+
+/// Where one produced record goes. `None` leaves the choice to the
+/// producer: an unset partition is picked from the key, or sticky when the
+/// record is unkeyed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Route {
+    pub key: Option<Vec<u8>>,
+    pub partition: Option<i32>,
+}
+
+/// The consumed record an output record was derived from.
+#[derive(Debug, Clone, Copy)]
+pub struct SourceRecord<'a> {
+    pub partition: i32,
+    pub key: Option<&'a [u8]>,
+}
+
+/// Routes the output of one kafka sink. Shared by every worker producing
+/// to the sink, so round-robin turns are taken across all of them.
+#[derive(Debug)]
+pub struct Partitioner {
+    partitioning: Partitioning,
+    next: AtomicU32,
+}
+
+impl Partitioner {
+    pub fn new(partitioning: Partitioning) -> Self {
+        Self {
+            partitioning,
+            next: AtomicU32::new(0),
+        }
+    }
+
+    /// `partitions` is the partition count of the output topic.
+    pub fn route(&self, source: SourceRecord<'_>, output: &Value, partitions: i32) -> Route {
+        match &self.partitioning {
+            // Records without the field are produced unkeyed rather than
+            // all hashed together under one empty key
+            Partitioning::Hash { field } => Route {
+                key: lookup(output, field).filter(|v| !v.is_null()).map(key_bytes),
+                partition: None,
+            },
+            Partitioning::RoundRobin if partitions > 0 => Route {
+                key: None,
+                partition: Some((self.next.fetch_add(1, Ordering::Relaxed) % partitions as u32) as i32),
+            },
+            Partitioning::RoundRobin | Partitioning::Sticky => Route::default(),
+            // An output topic with fewer partitions cannot take the source
+            // partition, so the source key decides instead
+            Partitioning::PreserveSource => Route {
+                key: source.key.map(<[u8]>::to_vec),
+                partition: Some(source.partition).filter(|p| (0..partitions).contains(p)),
+            },
+        }
+    }
+}
+
+/// Strings are keyed by their text so keys match what other producers
+/// write; anything else by its JSON encoding.
+fn key_bytes(value: &Value) -> Vec<u8> {
+    match value {
+        Value::String(s) => s.as_bytes().to_vec(),
+        other => other.to_string().into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_routes_follow_the_sink_strategy() {
+        let source = SourceRecord {
+            partition: 5,
+            key: Some(&b"host-1"[..]),
+        };
+        let output = json!({"labels": {"service": "api"}, "value": 1});
+
+        let parse = |config: Value| Partitioning::from_sink_config(&config);
+        assert_eq!(parse(json!({"topic": "out"})), Ok(Partitioning::Sticky));
+        assert!(parse(json!({"partitioning": {"strategy": "hash"}})).is_err());
+        assert!(parse(json!({"partitioning": {"strategy": "hash", "field": ""}})).is_err());
+        assert!(parse(json!({"partitioning": {"strategy": "random"}})).is_err());
+
+        let hash = Partitioner::new(parse(json!({"partitioning": {"strategy": "hash", "field": "labels.service"}})).unwrap());
+        assert_eq!(hash.route(source, &output, 8).key.as_deref(), Some(&b"api"[..]));
+        assert_eq!(hash.route(source, &json!({"value": 1}), 8), Route::default());
+        let numeric = Partitioner::new(Partitioning::Hash { field: "value".to_string() });
+        assert_eq!(numeric.route(source, &output, 8).key.as_deref(), Some(&b"1"[..]));
+
+        let round_robin = Partitioner::new(Partitioning::RoundRobin);
+        let partitions: Vec<_> = (0..4).map(|_| round_robin.route(source, &output, 3).partition).collect();
+        assert_eq!(partitions, vec![Some(0), Some(1), Some(2), Some(0)]);
+
+        let sticky = Partitioner::new(Partitioning::Sticky);
+        assert_eq!(sticky.route(source, &output, 8), Route::default());
+
+        let preserve = Partitioner::new(Partitioning::PreserveSource);
+        let route = preserve.route(source, &output, 8);
+        assert_eq!((route.key.as_deref(), route.partition), (Some(&b"host-1"[..]), Some(5)));
+        assert_eq!(preserve.route(source, &output, 4).partition, None);
+    }
+}
@@ -0,0 +1,365 @@
+//! Log template mining after Drain (He et al., 2017): logs are routed by
+//! token count and leading tokens to a few candidate templates, joined to
+//! the most similar one or started as a new template, and stored as a
+//! template id and the parameters that filled its `<*>` slots.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use streamforge_sdk::Alert;
+use tokio::sync::watch;
+
+use crate::approx::stable_hash;
+use crate::config::{LogPatternConfig, ProcessingConfig};
+use crate::metrics::Metrics;
+use crate::processor::ProcessedMessage;
+
+/// Template token standing for a parameter.
+pub const WILDCARD: &str = "<*>";
+
+/// A template and how often logs have matched it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TemplateSummary {
+    pub id: String,
+    pub template: String,
+    pub count: u64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// A log's template, as written to the stored record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extracted {
+    pub template_id: String,
+    pub params: Vec<String>,
+    /// Whether this log started the template.
+    pub new: bool,
+}
+
+/// A template seen for the first time in a log at one of the alerted levels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewPattern {
+    pub tenant_id: String,
+    pub template_id: String,
+    pub template: String,
+    pub level: String,
+    pub service: Option<String>,
+}
+
+/// Templates of each tenant's logs, following `processing.log_patterns`.
+pub struct LogPatterns {
+    settings: watch::Receiver<ProcessingConfig>,
+    miners: Mutex<HashMap<String, Miner>>,
+    metrics: Arc<Metrics>,
+}
+
+impl LogPatterns {
+    pub fn new(settings: watch::Receiver<ProcessingConfig>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            settings,
+            miners: Mutex::new(HashMap::new()),
+            metrics,
+        }
+    }
+
+    /// Replaces the raw message of a log with `template_id` and
+    /// `template_params` fields. Logs are records with a string `message`
+    /// and `level`; anything else passes through. Returns the new pattern
+    /// to alert on, if the log started one at an alerted level.
+    pub fn apply(&self, message: &mut ProcessedMessage) -> Option<NewPattern> {
+        let settings = self.settings.borrow();
+        let config = &settings.log_patterns;
+        if !config.enabled {
+            return None;
+        }
+        let record = message.processed_message.as_object_mut()?;
+        let text = record.get("message")?.as_str()?;
+        let level = record.get("level")?.as_str()?.to_string();
+
+        let tenant_id = &message.processing_metadata.tenant_id;
+        let (extracted, template, total) = {
+            let mut miners = self.miners.lock().unwrap();
+            let miner = miners.entry(tenant_id.clone()).or_insert_with(|| Miner::new(tenant_id));
+            let extracted = miner.add(text, config, message.processing_metadata.processed_at)?;
+            let template = miner.templates[&extracted.template_id].tokens.join(" ");
+            (extracted, template, miners.values().map(|m| m.templates.len()).sum::<usize>())
+        };
+        self.metrics.set_log_templates(total);
+        if extracted.new {
+            self.metrics.increment_log_templates_created();
+        }
+
+        record.insert("template_id".to_string(), Value::from(extracted.template_id.clone()));
+        record.insert("template_params".to_string(), Value::from(extracted.params.clone()));
+        if config.drop_message {
+            record.remove("message");
+        }
+        let alerted = config.alert_levels.iter().any(|l| l.eq_ignore_ascii_case(&level));
+        (extracted.new && alerted).then(|| NewPattern {
+            tenant_id: tenant_id.clone(),
+            template_id: extracted.template_id,
+            template,
+            level,
+            service: record.get("service").and_then(Value::as_str).map(str::to_string),
+        })
+    }
+
+    /// The tenant's templates, most matched first.
+    pub fn templates(&self, tenant_id: &str) -> Vec<TemplateSummary> {
+        let miners = self.miners.lock().unwrap();
+        let mut templates: Vec<_> = miners
+            .get(tenant_id)
+            .map(|miner| miner.templates.values().map(Template::summary).collect())
+            .unwrap_or_default();
+        templates.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.id.cmp(&b.id)));
+        templates
+    }
+}
+
+impl NewPattern {
+    /// Alert raised for the new pattern, for the notifier.
+    pub fn alert(&self) -> Alert {
+        let metadata = [
+            ("template_id".to_string(), Value::from(self.template_id.clone())),
+            ("template".to_string(), Value::from(self.template.clone())),
+            ("level".to_string(), Value::from(self.level.clone())),
+        ];
+        Alert {
+            id: uuid::Uuid::new_v4().to_string(),
+            severity: "warning".to_string(),
+            message: format!("New {} log pattern: {}", self.level.to_lowercase(), self.template),
+            timestamp: Utc::now().timestamp_millis() as u64,
+            service: self.service.clone().unwrap_or_else(|| "stream-processor".to_string()),
+            metadata: Some(metadata.into_iter().collect()),
+        }
+    }
+}
+
+struct Template {
+    id: String,
+    tokens: Vec<String>,
+    count: u64,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+}
+
+impl Template {
+    fn summary(&self) -> TemplateSummary {
+        TemplateSummary {
+            id: self.id.clone(),
+            template: self.tokens.join(" "),
+            count: self.count,
+            first_seen: self.first_seen,
+            last_seen: self.last_seen,
+        }
+    }
+}
+
+/// One tenant's templates.
+struct Miner {
+    tenant_id: String,
+    /// Template ids by token count and leading tokens.
+    groups: HashMap<(usize, Vec<String>), Vec<String>>,
+    templates: HashMap<String, Template>,
+}
+
+impl Miner {
+    fn new(tenant_id: &str) -> Self {
+        Self {
+            tenant_id: tenant_id.to_string(),
+            groups: HashMap::new(),
+            templates: HashMap::new(),
+        }
+    }
+
+    /// `None` for a message without tokens.
+    fn add(&mut self, text: &str, config: &LogPatternConfig, now: DateTime<Utc>) -> Option<Extracted> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        if tokens.is_empty() {
+            return None;
+        }
+        let masked: Vec<String> = tokens.iter().map(|t| mask(t).to_string()).collect();
+        let group = (tokens.len(), masked.iter().take(config.prefix_depth).cloned().collect::<Vec<_>>());
+
+        let candidates = self.groups.get(&group).map(Vec::as_slice).unwrap_or_default();
+        let best = candidates
+            .iter()
+            .map(|id| (id, similarity(&self.templates[id].tokens, &masked)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .filter(|(_, similarity)| *similarity >= config.similarity_threshold)
+            .map(|(id, _)| id.clone());
+
+        let new = best.is_none();
+        let id = match best {
+            Some(id) => {
+                let template = self.templates.get_mut(&id).expect("grouped templates exist");
+                for (slot, token) in template.tokens.iter_mut().zip(&masked) {
+                    if *slot != *token {
+                        *slot = WILDCARD.to_string();
+                    }
+                }
+                template.count += 1;
+                template.last_seen = now;
+                id
+            }
+            None => {
+                if self.templates.len() >= config.max_templates {
+                    self.evict();
+                }
+                let id = template_id(&self.tenant_id, &masked);
+                self.templates.insert(
+                    id.clone(),
+                    Template {
+                        id: id.clone(),
+                        tokens: masked,
+                        count: 1,
+                        first_seen: now,
+                        last_seen: now,
+                    },
+                );
+                self.groups.entry(group).or_default().push(id.clone());
+                id
+            }
+        };
+
+        let params = self.templates[&id]
+            .tokens
+            .iter()
+            .zip(&tokens)
+            .filter(|(slot, _)| *slot == WILDCARD)
+            .map(|(_, token)| token.to_string())
+            .collect();
+        Some(Extracted {
+            template_id: id,
+            params,
+            new,
+        })
+    }
+
+    /// Forgets the least recently matched template.
+    fn evict(&mut self) {
+        let Some(oldest) = self.templates.values().min_by_key(|t| t.last_seen).map(|t| t.id.clone()) else {
+            return;
+        };
+        self.templates.remove(&oldest);
+        for ids in self.groups.values_mut() {
+            ids.retain(|id| *id != oldest);
+        }
+        self.groups.retain(|_, ids| !ids.is_empty());
+    }
+}
+
+/// Tokens holding a digit are taken for parameters from the start.
+fn mask(token: &str) -> &str {
+    if token.bytes().any(|b| b.is_ascii_digit()) {
+        WILDCARD
+    } else {
+        token
+    }
+}
+
+/// Share of positions where the template has exactly the message's token.
+fn similarity(template: &[String], tokens: &[String]) -> f64 {
+    let same = template
+        .iter()
+        .zip(tokens)
+        .filter(|(slot, token)| *slot != WILDCARD && slot == token)
+        .count();
+    let constant = template.iter().filter(|slot| *slot != WILDCARD).count();
+    if constant == 0 {
+        return 1.0;
+    }
+    same as f64 / constant as f64
+}
+
+/// Ids come from the tenant and the template's first form, so every
+/// instance mining the same logs names their templates alike, whatever
+/// build it runs.
+fn template_id(tenant_id: &str, tokens: &[String]) -> String {
+    let mut bytes = tenant_id.as_bytes().to_vec();
+    bytes.push(0);
+    for token in tokens {
+        bytes.extend_from_slice(token.as_bytes());
+        bytes.push(0);
+    }
+    format!("{:016x}", stable_hash(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+    use crate::processor::ProcessingMetadata;
+    use serde_json::json;
+
+    fn log(level: &str, text: &str) -> ProcessedMessage {
+        let record = json!({"level": level, "message": text, "service": "api"});
+        ProcessedMessage {
+            id: "1".to_string(),
+            original_message: record.clone(),
+            processed_message: record,
+            processing_metadata: ProcessingMetadata {
+                tenant_id: "acme".to_string(),
+                processed_at: Utc::now(),
+                processor_version: "test".to_string(),
+                source_topic: "logs".to_string(),
+                partition: 0,
+                offset: 0,
+                traceparent: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_logs_are_clustered_into_templates() {
+        let mut settings = ProcessingConfig::default();
+        settings.log_patterns.enabled = true;
+        settings.log_patterns.drop_message = true;
+        let (_tx, rx) = watch::channel(settings);
+        let patterns = LogPatterns::new(rx, Arc::new(Metrics::new(&MetricsConfig::default()).unwrap()));
+
+        let mut first = log("info", "user alice logged in from 10.0.0.1");
+        assert_eq!(patterns.apply(&mut first), None);
+        let mut second = log("info", "user bob logged in from 10.0.0.2");
+        assert_eq!(patterns.apply(&mut second), None);
+        assert_eq!(second.processed_message["template_id"], first.processed_message["template_id"]);
+        assert_eq!(second.processed_message["template_params"], json!(["bob", "10.0.0.2"]));
+        assert!(second.processed_message.get("message").is_none());
+
+        let mut error = log("ERROR", "connection to db-7 refused");
+        let new = patterns.apply(&mut error).unwrap();
+        assert_eq!(new.template, "connection to <*> refused");
+        assert_eq!(new.alert().service, "api");
+        assert_eq!(patterns.apply(&mut log("error", "connection to db-9 refused")), None);
+
+        let mut templates: Vec<_> = patterns
+            .templates("acme")
+            .into_iter()
+            .map(|t| (t.template, t.count))
+            .collect();
+        templates.sort();
+        assert_eq!(
+            templates,
+            vec![
+                ("connection to <*> refused".to_string(), 2),
+                ("user <*> logged in from <*>".to_string(), 2),
+            ]
+        );
+        assert!(patterns.templates("globex").is_empty());
+
+        let mut metric = log("info", "ignored");
+        metric.processed_message = json!({"name": "cpu", "value": 1.0});
+        assert_eq!(patterns.apply(&mut metric), None);
+        assert_eq!(metric.processed_message, json!({"name": "cpu", "value": 1.0}));
+    }
+
+    #[test]
+    fn test_template_ids_are_stable() {
+        let tokens: Vec<String> = ["user", "alice", "logged"].iter().map(|t| t.to_string()).collect();
+        // Ids are stored with the templates, so this value must never change
+        assert_eq!(template_id("acme", &tokens), "5d0e6a2f82a297c9");
+        assert_ne!(template_id("globex", &tokens), template_id("acme", &tokens));
+    }
+}
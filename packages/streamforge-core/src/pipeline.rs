@@ -0,0 +1,500 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashSet};
+
+use crate::chunking::Oversize;
+use crate::encode::OutputFormat;
+use crate::headers::sink_headers;
+use crate::partitioning::Partitioning;
+
+/// Transform types the processor knows how to run.
+pub const TRANSFORM_KINDS: &[&str] = &[
+    "filter",
+    "map",
+    "enrich",
+    "aggregate",
+    "sample",
+    "join",
+    "rename",
+    "project",
+    "flatten",
+    "coerce",
+    "defaults",
+];
+
+/// Sink types the processor knows how to write to.
+pub const SINK_KINDS: &[&str] = &["storage", "kafka"];
+
+/// Kafka's limit on topic name length.
+const MAX_TOPIC_LENGTH: usize = 249;
+
+/// A stored pipeline definition, scoped to a tenant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineDefinition {
+    pub id: String,
+    pub tenant_id: String,
+    pub name: String,
+    pub description: String,
+    pub spec: PipelineSpec,
+    /// Starts at 1 and is incremented by every update, so concurrent editors
+    /// cannot overwrite each other's changes.
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What a pipeline reads, how it transforms each message and where the
+/// results are written.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PipelineSpec {
+    #[serde(default)]
+    pub sources: Vec<SourceSpec>,
+    /// Applied in order to every consumed message, unless they name their
+    /// `inputs`; see [`crate::dag`].
+    #[serde(default)]
+    pub transforms: Vec<TransformSpec>,
+    #[serde(default)]
+    pub sinks: Vec<SinkSpec>,
+    /// Runs the pipeline automatically as bounded jobs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<ScheduleSpec>,
+    #[serde(default)]
+    pub execution: ExecutionMode,
+}
+
+/// How a pipeline's transforms are run over each batch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionMode {
+    /// Every transform is applied to each message's JSON in turn.
+    #[default]
+    Row,
+    /// The batch is converted to Arrow columns and filtered and aggregated
+    /// with vectorized kernels (see `columnar`). Suits aggregate-heavy
+    /// pipelines; only `filter` and `aggregate` transforms are supported.
+    Columnar,
+}
+
+/// When a pipeline runs on its own. Each run replays the `window_seconds` of
+/// input before its trigger time and is stopped after `max_runtime_seconds`
+/// if it has not finished by then. Exactly one of `cron` and
+/// `interval_seconds` is set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleSpec {
+    /// Cron expression with a leading seconds field, e.g. `0 0 * * * *` for
+    /// the top of every hour (UTC).
+    #[serde(default)]
+    pub cron: Option<String>,
+    /// Runs at multiples of this many seconds since the epoch.
+    #[serde(default)]
+    pub interval_seconds: Option<u64>,
+    pub window_seconds: u64,
+    #[serde(default)]
+    pub max_runtime_seconds: Option<u64>,
+}
+
+/// Kafka topics consumed by a pipeline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceSpec {
+    pub name: String,
+    pub topics: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransformSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Settings specific to `kind`.
+    #[serde(default)]
+    pub config: serde_json::Value,
+    /// Sources and transforms this one reads, by name. When empty, the
+    /// previous transform, or every source for the first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    /// Batches each input edge holds before its upstream waits;
+    /// [`crate::dag::DEFAULT_EDGE_BUFFER`] when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SinkSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Settings specific to `kind`.
+    #[serde(default)]
+    pub config: serde_json::Value,
+    /// Sources and transforms this sink writes, by name. When empty, the
+    /// last transform, or every source if there are none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The pipeline cannot run as defined.
+    Error,
+    /// The pipeline runs but probably not as intended.
+    Warning,
+}
+
+/// One validation finding, located by its field path in the definition
+/// (e.g. `transforms[1].type`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub field: String,
+    pub message: String,
+}
+
+impl PipelineSpec {
+    /// Checks the definition without running it. `topics` is the set of
+    /// topics on the cluster when it could be fetched; referenced topics are
+    /// only checked for existence then.
+    pub fn validate(&self, topics: Option<&BTreeSet<String>>) -> Vec<Diagnostic> {
+        let mut diagnostics = Diagnostics::default();
+
+        if self.sources.is_empty() {
+            diagnostics.error("sources", "at least one source is required");
+        }
+        if self.sinks.is_empty() {
+            diagnostics.warning("sinks", "no sinks are defined; results are discarded");
+        }
+
+        let mut names = HashSet::new();
+        let stages = [
+            (
+                "sources",
+                self.sources.iter().map(|s| &s.name).collect::<Vec<_>>(),
+            ),
+            (
+                "transforms",
+                self.transforms.iter().map(|t| &t.name).collect(),
+            ),
+            ("sinks", self.sinks.iter().map(|s| &s.name).collect()),
+        ];
+        let stage_names = stages.iter().flat_map(|(stage, names)| {
+            names
+                .iter()
+                .enumerate()
+                .map(move |(i, name)| (format!("{}[{}].name", stage, i), *name))
+        });
+        for (field, name) in stage_names {
+            if name.is_empty() {
+                diagnostics.error(field, "name is required");
+            } else if !names.insert(name.as_str()) {
+                diagnostics.error(field, format!("{} is used by another stage", name));
+            }
+        }
+
+        let mut input_topics = HashSet::new();
+        for (i, source) in self.sources.iter().enumerate() {
+            if source.topics.is_empty() {
+                diagnostics.error(
+                    format!("sources[{}].topics", i),
+                    "at least one topic is required",
+                );
+            }
+            for (j, topic) in source.topics.iter().enumerate() {
+                let field = format!("sources[{}].topics[{}]", i, j);
+                check_topic(&mut diagnostics, &field, topic, topics);
+                input_topics.insert(topic.as_str());
+            }
+        }
+
+        for (i, transform) in self.transforms.iter().enumerate() {
+            if !TRANSFORM_KINDS.contains(&transform.kind.as_str()) {
+                diagnostics.error(
+                    format!("transforms[{}].type", i),
+                    format!(
+                        "unknown transform {:?}; expected one of {}",
+                        transform.kind,
+                        TRANSFORM_KINDS.join(", ")
+                    ),
+                );
+            }
+            check_config(
+                &mut diagnostics,
+                &format!("transforms[{}].config", i),
+                &transform.config,
+            );
+            let checked = match transform.kind.as_str() {
+                "enrich" => crate::table::TableJoin::from_config(&transform.config).map(|_| ()),
+                "sample" => crate::sampling::SamplingSpec::from_config(&transform.config).map(|_| ()),
+                "join" => crate::join::JoinSpec::from_config(&transform.config)
+                    .and_then(|spec| crate::join::StreamJoin::new(spec, &self.sources))
+                    .map(|_| ()),
+                kind if crate::reshape::RESHAPE_KINDS.contains(&kind) => {
+                    crate::reshape::Reshape::from_config(kind, &transform.config).map(|_| ())
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = checked {
+                diagnostics.error(format!("transforms[{}].config", i), e);
+            }
+        }
+
+        let graph = crate::dag::PipelineGraph::new(self);
+        match &graph {
+            Ok(graph) if !self.sinks.is_empty() => {
+                for stage in graph.unread() {
+                    diagnostics.warning(
+                        format!("{}[{}]", stage.kind.field(), stage.index),
+                        format!("no stage reads the output of {}", stage.name),
+                    );
+                }
+            }
+            Ok(_) => {}
+            Err(problems) => {
+                for (field, message) in problems {
+                    diagnostics.error(field.as_str(), message.as_str());
+                }
+            }
+        }
+
+        if self.execution == ExecutionMode::Columnar {
+            // Branching pipelines run each transform as its own plan
+            let checked = match &graph {
+                Ok(graph) if !graph.is_linear() => crate::dag::DagPlan::new(self).map(|_| ()),
+                _ => crate::columnar::ColumnarPlan::new(&self.transforms).map(|_| ()),
+            };
+            if let Err(problems) = checked {
+                for (field, message) in problems {
+                    diagnostics.error(field, message);
+                }
+            }
+        }
+
+        for (i, sink) in self.sinks.iter().enumerate() {
+            let field = format!("sinks[{}]", i);
+            check_config(&mut diagnostics, &format!("{}.config", field), &sink.config);
+            match sink.kind.as_str() {
+                "storage" => {}
+                "kafka" => match sink.config.get("topic").and_then(|t| t.as_str()) {
+                    Some(topic) => {
+                        if let Err(e) = Partitioning::from_sink_config(&sink.config) {
+                            diagnostics.error(format!("{}.config.partitioning", field), e);
+                        }
+                        if let Err(e) = sink_headers("", &sink.config) {
+                            diagnostics.error(format!("{}.config.headers", field), e);
+                        }
+                        if let Err(e) = OutputFormat::from_sink_config(&sink.config) {
+                            diagnostics.error(format!("{}.config.format", field), e);
+                        }
+                        if let Err(e) = Oversize::from_sink_config(&sink.config) {
+                            diagnostics.error(format!("{}.config.oversize", field), e);
+                        }
+                        let field = format!("{}.config.topic", field);
+                        check_topic(&mut diagnostics, &field, topic, topics);
+                        if input_topics.contains(topic) {
+                            let message = format!("{} is also a source topic", topic);
+                            diagnostics.error(field, message);
+                        }
+                    }
+                    None => diagnostics.error(
+                        format!("{}.config.topic", field),
+                        "kafka sinks require a topic",
+                    ),
+                },
+                other => diagnostics.error(
+                    format!("{}.type", field),
+                    format!(
+                        "unknown sink {:?}; expected one of {}",
+                        other,
+                        SINK_KINDS.join(", ")
+                    ),
+                ),
+            }
+        }
+
+        if let Some(schedule) = &self.schedule {
+            if let Err(e) = crate::scheduler::Trigger::parse(schedule) {
+                diagnostics.error("schedule", e.to_string());
+            }
+            if schedule.window_seconds == 0 {
+                diagnostics.error("schedule.window_seconds", "must be greater than 0");
+            }
+            if schedule.max_runtime_seconds == Some(0) {
+                diagnostics.error("schedule.max_runtime_seconds", "must be greater than 0 when set");
+            }
+        }
+
+        diagnostics.0
+    }
+
+    /// Content hash of the definition; see [`fingerprint`].
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&serde_json::to_value(self).unwrap_or_default())
+    }
+}
+
+/// `sha256:` and the hex SHA-256 of `config` as JSON with object keys
+/// sorted, so equal definitions share a fingerprint whatever order their
+/// fields were written in.
+pub fn fingerprint(config: &serde_json::Value) -> String {
+    let mut canonical = String::new();
+    write_canonical(config, &mut canonical);
+    format!("sha256:{}", hex::encode(Sha256::digest(canonical.as_bytes())))
+}
+
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by_key(|(key, _)| *key);
+            out.push('{');
+            for (i, (key, value)) in fields.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+#[derive(Default)]
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn push(&mut self, severity: Severity, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(Diagnostic {
+            severity,
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    fn error(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Error, field, message);
+    }
+
+    fn warning(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Warning, field, message);
+    }
+}
+
+fn check_topic(
+    diagnostics: &mut Diagnostics,
+    field: &str,
+    topic: &str,
+    topics: Option<&BTreeSet<String>>,
+) {
+    let legal = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+    if topic.is_empty()
+        || topic.len() > MAX_TOPIC_LENGTH
+        || !topic.chars().all(legal)
+        || topic == "."
+        || topic == ".."
+    {
+        diagnostics.error(
+            field,
+            format!("{:?} is not a valid Kafka topic name", topic),
+        );
+    } else if topics.map_or(false, |topics| !topics.contains(topic)) {
+        diagnostics.warning(
+            field,
+            format!("topic {} does not exist on the cluster", topic),
+        );
+    }
+}
+
+fn check_config(diagnostics: &mut Diagnostics, field: &str, config: &serde_json::Value) {
+    if !config.is_null() && !config.is_object() {
+        diagnostics.error(field, "config must be a JSON object");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_reports_field_paths() {
+        let spec = PipelineSpec {
+            sources: vec![SourceSpec {
+                name: "events".to_string(),
+                topics: vec!["events".to_string(), "bad topic".to_string()],
+            }],
+            transforms: vec![TransformSpec {
+                name: "events".to_string(),
+                kind: "explode".to_string(),
+                config: json!(null),
+                inputs: Vec::new(),
+                buffer: None,
+            }],
+            sinks: vec![SinkSpec {
+                name: "out".to_string(),
+                kind: "kafka".to_string(),
+                config: json!({"topic": "missing"}),
+                inputs: vec!["archive".to_string()],
+                buffer: None,
+            }],
+            schedule: None,
+            execution: ExecutionMode::Row,
+        };
+        let topics: BTreeSet<String> = ["events".to_string()].into();
+
+        let diagnostics = spec.validate(Some(&topics));
+        let fields: Vec<(&str, Severity)> = diagnostics
+            .iter()
+            .map(|d| (d.field.as_str(), d.severity))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("transforms[0].name", Severity::Error),
+                ("sources[0].topics[1]", Severity::Error),
+                ("transforms[0].type", Severity::Error),
+                ("sinks[0].inputs[0]", Severity::Error),
+                ("sinks[0].config.topic", Severity::Warning),
+            ]
+        );
+        assert!(PipelineSpec::default()
+            .validate(None)
+            .iter()
+            .any(|d| d.field == "sources"));
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_key_order_only() {
+        let a: serde_json::Value = serde_json::from_str(r#"{"topic": "out", "retries": [1, {"a": 2}]}"#).unwrap();
+        let b: serde_json::Value = serde_json::from_str(r#"{"retries": [1, {"a": 2}], "topic": "out"}"#).unwrap();
+        assert_eq!(fingerprint(&a), fingerprint(&b));
+        assert!(fingerprint(&a).starts_with("sha256:"));
+        assert_ne!(fingerprint(&a), fingerprint(&json!({"topic": "out"})));
+
+        let spec = PipelineSpec {
+            sources: vec![SourceSpec {
+                name: "events".to_string(),
+                topics: vec!["events".to_string()],
+            }],
+            ..PipelineSpec::default()
+        };
+        let mut changed = spec.clone();
+        changed.sources[0].topics.push("more-events".to_string());
+        assert_eq!(spec.fingerprint(), spec.clone().fingerprint());
+        assert_ne!(spec.fingerprint(), changed.fingerprint());
+    }
+}
@@ -0,0 +1,251 @@
+//! Poison messages: a message whose processing keeps failing is attempted up
+//! to `processing.poison.max_attempts` times, then either dead-lettered so
+//! its partition moves on, or held, which pauses the partition at the
+//! message until it is released. Holds live in memory; a restarted processor
+//! consumes from the committed offsets again. An attempt running past
+//! `processing.transform_timeout` is cancelled and dealt with per
+//! `processing.on_transform_timeout`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use streamforge_sdk::Alert;
+use tokio::sync::watch;
+
+use crate::config::{PoisonPolicy, ProcessingConfig, TimeoutAction};
+use crate::processor::KafkaMessage;
+
+/// Held partitions by `(topic, partition)`.
+pub type Holds = BTreeMap<(String, i32), HeldPartition>;
+
+/// A transform cancelled for running past `processing.transform_timeout`.
+#[derive(Debug, thiserror::Error)]
+#[error("transform timed out after {0:?}")]
+pub struct TransformTimeout(pub Duration);
+
+/// A partition paused at the message that kept failing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeldPartition {
+    pub topic: String,
+    pub partition: i32,
+    /// Offset of the message; consumption resumes from it on release.
+    pub offset: i64,
+    pub attempts: u32,
+    pub error: String,
+    pub held_at: DateTime<Utc>,
+}
+
+impl HeldPartition {
+    /// Alert raised for the hold, for the notifier.
+    pub fn alert(&self) -> Alert {
+        let metadata = [
+            ("topic".to_string(), Value::from(self.topic.clone())),
+            ("partition".to_string(), Value::from(self.partition)),
+            ("offset".to_string(), Value::from(self.offset)),
+            ("attempts".to_string(), Value::from(self.attempts)),
+        ];
+        Alert {
+            id: uuid::Uuid::new_v4().to_string(),
+            severity: "critical".to_string(),
+            message: format!(
+                "Paused {}/{} at offset {} after {} failed attempt(s): {}",
+                self.topic, self.partition, self.offset, self.attempts, self.error
+            ),
+            timestamp: self.held_at.timestamp_millis() as u64,
+            service: "stream-processor".to_string(),
+            metadata: Some(metadata.into_iter().collect()),
+        }
+    }
+}
+
+/// What to do with a message after a failed attempt at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Attempt it again after the delay.
+    Retry(Duration),
+    /// Dead-letter it and move on.
+    Skip,
+    /// Hold its partition at it.
+    Pause,
+    /// Drop it without dead-lettering it.
+    Drop,
+}
+
+pub struct PoisonGuard {
+    settings: watch::Receiver<ProcessingConfig>,
+    holds: watch::Sender<Holds>,
+}
+
+impl PoisonGuard {
+    pub fn new(settings: watch::Receiver<ProcessingConfig>) -> Self {
+        let (holds, _) = watch::channel(Holds::new());
+        Self { settings, holds }
+    }
+
+    /// The verdict on a message of `topic` that has failed `attempts` times.
+    pub fn verdict(&self, topic: &str, attempts: u32) -> Verdict {
+        let settings = self.settings.borrow();
+        let poison = &settings.poison;
+        if attempts < poison.max_attempts {
+            return Verdict::Retry(settings.retry_delay);
+        }
+        match poison.overrides.get(topic).copied().unwrap_or(poison.policy) {
+            PoisonPolicy::Skip => Verdict::Skip,
+            PoisonPolicy::Pause => Verdict::Pause,
+        }
+    }
+
+    /// The verdict on a message of `topic` whose `attempts`-th attempt
+    /// timed out.
+    pub fn timeout_verdict(&self, topic: &str, attempts: u32) -> Verdict {
+        let action = self.settings.borrow().on_transform_timeout;
+        match action {
+            TimeoutAction::Retry => self.verdict(topic, attempts),
+            TimeoutAction::DeadLetter => Verdict::Skip,
+            TimeoutAction::Skip => Verdict::Drop,
+        }
+    }
+
+    pub fn transform_timeout(&self) -> Option<Duration> {
+        self.settings.borrow().transform_timeout
+    }
+
+    /// Holds `message`'s partition at it, returning the hold, or `None` if
+    /// the partition is held already.
+    pub fn hold(&self, message: &KafkaMessage, attempts: u32, error: impl ToString) -> Option<HeldPartition> {
+        let mut added = None;
+        self.holds.send_if_modified(|holds| {
+            let key = (message.topic.clone(), message.partition);
+            if holds.contains_key(&key) {
+                return false;
+            }
+            let hold = HeldPartition {
+                topic: message.topic.clone(),
+                partition: message.partition,
+                offset: message.offset,
+                attempts,
+                error: error.to_string(),
+                held_at: Utc::now(),
+            };
+            holds.insert(key, hold.clone());
+            added = Some(hold);
+            true
+        });
+        added
+    }
+
+    /// Whether a message is at or past the hold of its partition, and so is
+    /// left to be consumed again after the release.
+    pub fn is_held(&self, topic: &str, partition: i32, offset: i64) -> bool {
+        self.holds
+            .borrow()
+            .get(&(topic.to_string(), partition))
+            .map_or(false, |hold| offset >= hold.offset)
+    }
+
+    /// Resumes a held partition from its poison message, which is attempted
+    /// afresh.
+    pub fn release(&self, topic: &str, partition: i32) -> Option<HeldPartition> {
+        let mut released = None;
+        self.holds.send_if_modified(|holds| {
+            released = holds.remove(&(topic.to_string(), partition));
+            released.is_some()
+        });
+        released
+    }
+
+    pub fn holds(&self) -> Vec<HeldPartition> {
+        self.holds.borrow().values().cloned().collect()
+    }
+
+    /// Follows holds as they are added and released, for the consumer.
+    pub fn subscribe(&self) -> watch::Receiver<Holds> {
+        self.holds.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PoisonConfig;
+    use tracing::Span;
+
+    fn message(topic: &str, partition: i32, offset: i64) -> KafkaMessage {
+        KafkaMessage {
+            tenant_id: "default".to_string(),
+            topic: topic.to_string(),
+            partition,
+            offset,
+            payload: b"{".to_vec(),
+            key: None,
+            headers: None,
+            timestamp: 0,
+            received_at: Utc::now(),
+            span: Span::none(),
+            inflight: None,
+        }
+    }
+
+    #[test]
+    fn test_verdict_retries_then_applies_the_topic_policy() {
+        let (_, settings) = watch::channel(ProcessingConfig {
+            retry_delay: Duration::from_millis(10),
+            poison: PoisonConfig {
+                max_attempts: 3,
+                policy: PoisonPolicy::Skip,
+                overrides: [("orders".to_string(), PoisonPolicy::Pause)].into_iter().collect(),
+            },
+            ..ProcessingConfig::default()
+        });
+        let guard = PoisonGuard::new(settings);
+
+        assert_eq!(guard.verdict("logs", 1), Verdict::Retry(Duration::from_millis(10)));
+        assert_eq!(guard.verdict("logs", 2), Verdict::Retry(Duration::from_millis(10)));
+        assert_eq!(guard.verdict("logs", 3), Verdict::Skip);
+        assert_eq!(guard.verdict("orders", 3), Verdict::Pause);
+    }
+
+    #[test]
+    fn test_timeouts_follow_on_transform_timeout() {
+        let (settings_tx, settings) = watch::channel(ProcessingConfig {
+            poison: PoisonConfig {
+                max_attempts: 2,
+                ..PoisonConfig::default()
+            },
+            ..ProcessingConfig::default()
+        });
+        let guard = PoisonGuard::new(settings);
+
+        assert!(matches!(guard.timeout_verdict("logs", 1), Verdict::Retry(_)));
+        assert_eq!(guard.timeout_verdict("logs", 2), Verdict::Skip);
+        settings_tx.send_modify(|settings| settings.on_transform_timeout = TimeoutAction::DeadLetter);
+        assert_eq!(guard.timeout_verdict("logs", 1), Verdict::Skip);
+        settings_tx.send_modify(|settings| settings.on_transform_timeout = TimeoutAction::Skip);
+        assert_eq!(guard.timeout_verdict("logs", 1), Verdict::Drop);
+    }
+
+    #[test]
+    fn test_holds_cover_the_rest_of_the_partition_until_released() {
+        let (_, settings) = watch::channel(ProcessingConfig::default());
+        let guard = PoisonGuard::new(settings);
+        let mut holds = guard.subscribe();
+
+        let hold = guard.hold(&message("orders", 2, 40), 3, "invalid JSON").unwrap();
+        assert_eq!((hold.offset, hold.attempts), (40, 3));
+        assert!(guard.hold(&message("orders", 2, 41), 1, "invalid JSON").is_none());
+        assert!(holds.has_changed().unwrap());
+        assert_eq!(holds.borrow_and_update().len(), 1);
+
+        assert!(!guard.is_held("orders", 2, 39));
+        assert!(guard.is_held("orders", 2, 40));
+        assert!(guard.is_held("orders", 2, 41));
+        assert!(!guard.is_held("orders", 1, 41));
+
+        assert_eq!(guard.release("orders", 2), Some(hold));
+        assert!(guard.release("orders", 2).is_none());
+        assert!(!guard.is_held("orders", 2, 40));
+        assert!(holds.has_changed().unwrap());
+    }
+}
@@ -0,0 +1,1535 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use rdkafka::consumer::Consumer;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::message::{Headers, OwnedHeaders};
+use rdkafka::{Message, Offset, TopicPartitionList};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch, OwnedSemaphorePermit};
+use tokio::time::timeout;
+use tracing::{error, info, info_span, warn, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::batching::AdaptiveBatching;
+use crate::canary::CanarySettings;
+use crate::cardinality::CardinalityGuard;
+use crate::config::{Config, ProcessingConfig};
+use crate::dag::Topologies;
+use crate::decode::Decoder;
+use crate::dlq::{self, Envelope, ErrorClass, ErrorRecord, Failure};
+use crate::faults::FaultInjector;
+use crate::health::{HealthState, LagGate, CONSUMER_LAG_COMPONENT, SPILL_COMPONENT};
+use crate::hub::Hub;
+use crate::notify::{Notification, Notifier};
+use crate::offload::Offload;
+use crate::patterns::LogPatterns;
+use crate::poison::{HeldPartition, Holds, PoisonGuard, TransformTimeout, Verdict};
+use crate::kafka::{KafkaManager, PipelineConsumer};
+use crate::limits::{check_record, Admission};
+use crate::log_metrics::LogMetrics;
+use crate::memory::{approximate_size, Held, MemoryBudget, Overflow, Reservation, Segment};
+use crate::metrics::{Metrics, SystemCollector};
+use crate::processing::MessageProcessor;
+use crate::recording::RecordingRules;
+use crate::schemas::{SchemaDrift, SchemaRegistry};
+use crate::seek::StartPositions;
+use crate::sessions::{self, SessionTracker};
+use crate::status::{self, StatusTracker};
+use crate::storage::{self, BufferedWriter, FieldCipher, StorageBackend};
+use crate::table::Tables;
+use crate::telemetry;
+
+pub struct StreamProcessor {
+    config: Config,
+    metrics: Arc<Metrics>,
+    kafka_manager: KafkaManager,
+    storage: Arc<dyn StorageBackend>,
+    message_processor: MessageProcessor,
+    health: Arc<HealthState>,
+    observers: BatchObservers,
+    /// Live processing settings; updated on config reload.
+    processing: watch::Sender<ProcessingConfig>,
+    batching: Arc<AdaptiveBatching>,
+    stages: BatchStages,
+    /// Compacted topics materialized for enrichment joins.
+    tables: Arc<Tables>,
+    /// Whether consumption is on hold; see [`StreamProcessor::pause`].
+    paused: watch::Sender<bool>,
+    /// Pipelines running as stage graphs, for the admin endpoint.
+    topologies: Arc<Topologies>,
+    /// Sample taken when running as a deployment's candidate.
+    canary: Option<CanarySettings>,
+}
+
+impl StreamProcessor {
+    pub async fn new(config: Config, metrics: Arc<Metrics>) -> Result<Self> {
+        info!("Initializing Stream Processor...");
+
+        // Initialize Kafka manager
+        let kafka_manager = KafkaManager::new(&config, metrics.clone()).await?;
+        info!("Kafka manager initialized");
+        if config.faults.enabled {
+            warn!("Fault injection is enabled for Kafka produces");
+        }
+
+        // Initialize storage backend
+        let health = Arc::new(HealthState::new());
+        health.set(storage::STORAGE_COMPONENT, false, "connecting");
+        let storage = storage::connect(&config).await?;
+        health.set(storage::STORAGE_COMPONENT, true, "ok");
+        info!("Storage backend initialized");
+        if LagGate::new(&config.metrics.readiness).is_some() {
+            health.set(CONSUMER_LAG_COMPONENT, false, "consumer lag not measured yet");
+        }
+
+        // Initialize message processor
+        let message_processor = MessageProcessor::new(&config, metrics.clone());
+        info!("Message processor initialized");
+
+        let observers = BatchObservers {
+            hub: Arc::new(Hub::new(&config.hub, metrics.clone())),
+            status: Arc::new(StatusTracker::new(config.service_status.clone())),
+            notifier: Arc::new(Notifier::new(&config.notifier, storage.clone(), metrics.clone())?),
+            sessions: Arc::new(SessionTracker::new(config.sessions.clone(), metrics.clone())),
+        };
+        let (processing, _) = watch::channel(config.processing.clone());
+        let batching = Arc::new(AdaptiveBatching::new(processing.subscribe(), metrics.clone()));
+        let stages = BatchStages {
+            cardinality: Arc::new(CardinalityGuard::new(processing.subscribe(), metrics.clone())),
+            patterns: Arc::new(LogPatterns::new(processing.subscribe(), metrics.clone())),
+            log_metrics: Arc::new(LogMetrics::new(processing.subscribe())),
+            recording: Arc::new(RecordingRules::new(processing.subscribe())),
+            schemas: Arc::new(SchemaRegistry::new(processing.subscribe(), storage.clone(), metrics.clone())),
+            poison: Arc::new(PoisonGuard::new(processing.subscribe())),
+            offload: Offload::new(&config.processing.offload),
+        };
+        if config.processing.schemas.enabled {
+            match stages.schemas.load().await {
+                Ok(loaded) => info!("Loaded the schemas of {} topic(s)", loaded),
+                Err(e) => warn!("Failed to load topic schemas, starting from none: {}", e),
+            }
+        }
+        let (paused, _) = watch::channel(false);
+        let tables = Arc::new(Tables::new(&config, metrics.clone()));
+
+        Ok(Self {
+            config,
+            metrics,
+            kafka_manager,
+            storage,
+            message_processor,
+            health,
+            observers,
+            processing,
+            batching,
+            stages,
+            tables,
+            paused,
+            topologies: Arc::new(Topologies::new()),
+            canary: None,
+        })
+    }
+
+    /// Applies the `canary` section of the job configuration this processor
+    /// runs, so a deployment's candidate consumes only its sample.
+    pub fn for_job(mut self, job_config: &serde_json::Value) -> Result<Self> {
+        self.canary = CanarySettings::from_job_config(job_config)
+            .map_err(|e| anyhow::anyhow!("Failed to read canary settings: {}", e))?;
+        if let Some(canary) = &self.canary {
+            info!(
+                "Running as candidate of deployment {} on {:.1}% of the input",
+                canary.deployment_id,
+                canary.share() * 100.0
+            );
+        }
+        Ok(self)
+    }
+
+    /// Stops fetching from Kafka without leaving the consumer group, so the
+    /// assignment and committed offsets are kept. Messages already fetched
+    /// are still processed and written.
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// Continues consumption from where [`StreamProcessor::pause`] held it.
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Applies reloaded processing settings; workers pick them up before
+    /// their next batch.
+    pub fn update_processing(&self, processing: ProcessingConfig) {
+        self.processing.send_replace(processing);
+    }
+
+    /// Readiness registry shared with the metrics/health HTTP server.
+    pub fn health(&self) -> Arc<HealthState> {
+        self.health.clone()
+    }
+
+    /// Storage backend processed data is written to.
+    pub fn storage(&self) -> Arc<dyn StorageBackend> {
+        self.storage.clone()
+    }
+
+    /// Partitions held at poison messages under `processing.poison`.
+    pub fn poison(&self) -> Arc<PoisonGuard> {
+        self.stages.poison.clone()
+    }
+
+    /// Log templates mined under `processing.log_patterns`.
+    pub fn log_patterns(&self) -> Arc<LogPatterns> {
+        self.stages.patterns.clone()
+    }
+
+    /// Tables from `kafka.tables`, for enrichment joins.
+    pub fn tables(&self) -> Arc<Tables> {
+        self.tables.clone()
+    }
+
+    /// Stage graphs of running pipelines, shared with the metrics/health
+    /// HTTP server.
+    pub fn topologies(&self) -> Arc<Topologies> {
+        self.topologies.clone()
+    }
+
+    /// Broadcast hub fed with every processed batch, for the WebSocket server.
+    pub fn hub(&self) -> Arc<Hub> {
+        self.observers.hub.clone()
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        info!("Starting stream processor...");
+
+        // Create channels for communication between components
+        let (tx, rx) = mpsc::channel(1000);
+
+        // Follow the compacted topics behind enrichment tables
+        let table_handles = self.tables.spawn(&self.config);
+
+        // Start Kafka consumer
+        let consumer_handle = self.start_kafka_consumer(tx.clone()).await?;
+
+        // Processed batches are handed to the database writer, spilling to
+        // disk past the memory budget
+        let (queue, db_rx, leftovers) = self.open_writer_queue().await?;
+
+        // Start message processing workers
+        let worker_handles = self.start_processing_workers(rx, queue.clone()).await?;
+
+        // Start database writer
+        let db_writer_handle = self.start_database_writer(db_rx).await?;
+
+        // Batches spilled by a previous run are written alongside new ones
+        if !leftovers.is_empty() {
+            let tx = queue.tx.clone();
+            tokio::spawn(async move {
+                for segment in leftovers {
+                    if tx.send(Held::Spilled(segment)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(queue);
+
+        // Start metrics collection
+        let metrics_handle = self.start_metrics_collection().await?;
+
+        // Keep storage readiness up to date
+        let storage_health_handle = tokio::spawn(storage::monitor_health(
+            self.storage.clone(),
+            self.config.database.retry.clone(),
+            self.health.clone(),
+            self.metrics.clone(),
+        ));
+
+        // Downsample aged metrics per the configured retention rules
+        let compaction_handle = if self.config.database.compaction.enabled {
+            tokio::spawn(storage::run_compaction(
+                self.storage.clone(),
+                self.config.database.compaction.clone(),
+            ))
+        } else {
+            tokio::spawn(futures::future::pending::<()>())
+        };
+
+        // Move aged rows to the warm tier per the lifecycle rules
+        let lifecycle_handle = if self.config.database.lifecycle.enabled {
+            let encryption = &self.config.database.encryption;
+            let cipher = encryption.enabled.then(|| FieldCipher::new(encryption)).transpose()?;
+            tokio::spawn(storage::run_lifecycle(
+                self.storage.clone(),
+                self.config.database.lifecycle.clone(),
+                cipher,
+            ))
+        } else {
+            tokio::spawn(futures::future::pending::<()>())
+        };
+
+        // Derive service status from the processed metrics
+        let status_handle = if self.config.service_status.enabled {
+            tokio::spawn(status::run(
+                self.observers.status.clone(),
+                self.storage.clone(),
+                self.observers.hub.clone(),
+            ))
+        } else {
+            tokio::spawn(futures::future::pending::<()>())
+        };
+
+        // Deliver processed alerts to the notification channels
+        let notifier_handle = if self.config.notifier.enabled {
+            let notifier = self.observers.notifier.clone();
+            tokio::spawn(async move { notifier.run().await })
+        } else {
+            tokio::spawn(futures::future::pending::<()>())
+        };
+
+        // Produce summaries of ended sessions to the sessions output topic
+        let sessions_handle = if self.config.sessions.enabled {
+            tokio::spawn(sessions::run(
+                self.observers.sessions.clone(),
+                self.kafka_manager.create_producer().await?,
+            ))
+        } else {
+            tokio::spawn(futures::future::pending::<()>())
+        };
+
+        // Wait for all components to complete
+        tokio::select! {
+            _ = consumer_handle => {
+                info!("Kafka consumer stopped");
+            }
+            _ = futures::future::join_all(worker_handles) => {
+                info!("All processing workers stopped");
+            }
+            _ = db_writer_handle => {
+                info!("Database writer stopped");
+            }
+            _ = metrics_handle => {
+                info!("Metrics collection stopped");
+            }
+            _ = storage_health_handle => {
+                info!("Storage health monitor stopped");
+            }
+            _ = compaction_handle => {
+                info!("Metric compaction stopped");
+            }
+            _ = lifecycle_handle => {
+                info!("Data lifecycle stopped");
+            }
+            _ = status_handle => {
+                info!("Service status tracking stopped");
+            }
+            _ = notifier_handle => {
+                info!("Alert notifier stopped");
+            }
+            _ = sessions_handle => {
+                info!("Session tracking stopped");
+            }
+        }
+        for handle in table_handles {
+            handle.abort();
+        }
+
+        info!("Stream processor stopped");
+        Ok(())
+    }
+
+    async fn start_kafka_consumer(&self, tx: mpsc::Sender<KafkaMessage>) -> Result<tokio::task::JoinHandle<()>> {
+        let config = self.config.clone();
+        let metrics = self.metrics.clone();
+        let kafka_manager = self.kafka_manager.clone();
+        let paused = self.paused.subscribe();
+        let holds = self.stages.poison.subscribe();
+        let health = self.health.clone();
+        let canary = self.canary.clone();
+
+        let handle = tokio::spawn(async move {
+            let consumed = Self::run_kafka_consumer(config, metrics, kafka_manager, tx, paused, holds, health, canary);
+            if let Err(e) = consumed.await {
+                error!("Kafka consumer error: {}", e);
+            }
+        });
+
+        Ok(handle)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_kafka_consumer(
+        config: Config,
+        metrics: Arc<Metrics>,
+        kafka_manager: KafkaManager,
+        tx: mpsc::Sender<KafkaMessage>,
+        mut paused: watch::Receiver<bool>,
+        mut holds: watch::Receiver<Holds>,
+        health: Arc<HealthState>,
+        canary: Option<CanarySettings>,
+    ) -> Result<()> {
+        let consumer: Arc<PipelineConsumer> = Arc::new(kafka_manager.create_consumer().await?);
+        let mut admission = Admission::new(&config.processing.limits)?;
+        let dead_letters = DeadLetterSink::new(&config, &kafka_manager).await?;
+        let mut start_positions = StartPositions::load(&config.kafka)?;
+        
+        // Subscribe to topics
+        consumer.subscribe(&config.kafka.input_topics)?;
+        info!("Subscribed to topics: {:?}", config.kafka.input_topics);
+
+        // Hold readiness back while lagging, per `metrics.readiness`
+        let lag_handle = LagGate::new(&config.metrics.readiness).map(|gate| {
+            tokio::spawn(Self::run_lag_gate(
+                gate,
+                config.metrics.readiness.lag_interval,
+                kafka_manager.clone(),
+                consumer.clone(),
+                health,
+            ))
+        });
+
+        let mut message_stream = consumer.stream();
+        // Holds applied to the consumer so far
+        let mut held = Holds::new();
+
+        loop {
+            // Keep polling while paused so the consumer stays in the group
+            let message_result = tokio::select! {
+                changed = paused.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let pause = *paused.borrow_and_update();
+                    Self::set_consumption_paused(&consumer, pause);
+                    if !pause {
+                        held.values().for_each(|hold| Self::hold_partition(&consumer, hold));
+                    }
+                    continue;
+                }
+                changed = holds.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let current = holds.borrow_and_update().clone();
+                    for (key, hold) in &current {
+                        if !held.contains_key(key) {
+                            Self::hold_partition(&consumer, hold);
+                        }
+                    }
+                    if !*paused.borrow() {
+                        for (topic, partition) in held.keys().filter(|key| !current.contains_key(*key)) {
+                            Self::release_partition(&consumer, topic, *partition);
+                        }
+                    }
+                    held = current;
+                    continue;
+                }
+                message_result = message_stream.next() => match message_result {
+                    Some(message_result) => message_result,
+                    None => break,
+                },
+            };
+
+            if *paused.borrow() {
+                // Partitions assigned after the pause start out unpaused;
+                // rewind to this message and hold them too
+                if let Ok(message) = &message_result {
+                    if let Err(e) = consumer.seek(
+                        message.topic(),
+                        message.partition(),
+                        Offset::Offset(message.offset()),
+                        Duration::from_secs(1),
+                    ) {
+                        warn!("Failed to rewind paused partition {}/{}: {}", message.topic(), message.partition(), e);
+                    }
+                }
+                Self::set_consumption_paused(&consumer, true);
+                continue;
+            }
+
+            // Held partitions reassigned by a rebalance start out unpaused,
+            // and messages fetched before a hold still arrive; rewind those
+            if let Ok(message) = &message_result {
+                if let Some(hold) = held.get(&(message.topic().to_string(), message.partition())) {
+                    if message.offset() >= hold.offset {
+                        Self::hold_partition(&consumer, hold);
+                        continue;
+                    }
+                }
+            }
+
+            match message_result {
+                Ok(message) => {
+                    // Backfills start the first time a partition is consumed
+                    match start_positions.target(&*consumer, message.topic(), message.partition()) {
+                        Ok(Some(offset)) => {
+                            match consumer.seek(message.topic(), message.partition(), offset, Duration::from_secs(1)) {
+                                Ok(()) => {
+                                    info!("Moved {}/{} to start position {:?}", message.topic(), message.partition(), offset);
+                                    continue;
+                                }
+                                Err(e) => warn!(
+                                    "Failed to seek {}/{} to its start position: {}",
+                                    message.topic(),
+                                    message.partition(),
+                                    e
+                                ),
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!("Keeping {}/{} at its current offset: {}", message.topic(), message.partition(), e),
+                    }
+
+                    // A canary candidate leaves records outside its sample
+                    // to the baseline job
+                    if let Some(canary) = &canary {
+                        if !canary.selects(message.key(), message.partition(), message.offset()) {
+                            continue;
+                        }
+                    }
+
+                    let topic = message.topic().to_string();
+                    let partition = message.partition();
+                    let offset = message.offset();
+                    let tenant_id = message
+                        .headers()
+                        .and_then(|headers| {
+                            headers
+                                .iter()
+                                .find(|h| h.key == config.processing.tenant_header)
+                                .and_then(|h| h.value)
+                                .and_then(|v| std::str::from_utf8(v).ok())
+                                .filter(|v| !v.is_empty())
+                                .map(str::to_string)
+                        })
+                        .unwrap_or_else(|| config.processing.default_tenant_id.clone());
+                    
+                    info!("Received message from topic: {}, partition: {}, offset: {}", 
+                          topic, partition, offset);
+
+                    // Continue the producer's trace when it sent one
+                    let span = info_span!(
+                        "kafka.consume",
+                        otel.kind = "consumer",
+                        messaging.system = "kafka",
+                        messaging.destination.name = %topic,
+                        messaging.kafka.partition = partition,
+                        messaging.kafka.offset = offset,
+                    );
+                    span.set_parent(telemetry::extract_context(message.headers()));
+
+                    // Update metrics
+                    metrics.increment_messages_received(&topic, partition, 1);
+
+                    // Wait for room under the job's resource limits, unless
+                    // the record is turned away anyway
+                    let payload = message.payload().unwrap_or_default().to_vec();
+                    let violation = check_record(&config.processing.record_limits, &payload).err();
+                    let inflight = match violation {
+                        None => admission.admit(payload.len()).await.map(Arc::new),
+                        Some(_) => None,
+                    };
+
+                    // Create Kafka message
+                    let kafka_message = KafkaMessage {
+                        tenant_id,
+                        topic,
+                        partition,
+                        offset,
+                        payload,
+                        key: message.key().map(<[u8]>::to_vec),
+                        headers: message.headers().map(|headers| headers.detach()),
+                        timestamp: message.timestamp().to_millis().unwrap_or_default(),
+                        received_at: Utc::now(),
+                        span,
+                        inflight,
+                    };
+
+                    if let Some(violation) = violation {
+                        warn!("Rejected message {}/{}@{}: {}", kafka_message.topic, partition, offset, violation);
+                        metrics.increment_records_rejected(&kafka_message.topic, violation.reason());
+                        metrics.increment_messages_failed(Some((&kafka_message.topic, partition)), None, 1);
+                        let failure = Failure::new(STAGE_CONSUME, ErrorClass::Admission, &violation)
+                            .context("reason", violation.reason());
+                        dead_letters.send(&kafka_message, failure).await;
+                        continue;
+                    }
+
+                    // Send to processing channel
+                    if let Err(e) = tx.send(kafka_message).await {
+                        error!("Failed to send message to processing channel: {}", e);
+                        let message = e.0;
+                        metrics.increment_messages_failed(Some((&message.topic, message.partition)), None, 1);
+                    }
+                }
+                Err(e) => {
+                    error!("Error receiving Kafka message: {}", e);
+                    metrics.increment_messages_failed(None, None, 1);
+                }
+            }
+        }
+
+        if let Some(handle) = lag_handle {
+            handle.abort();
+        }
+        Ok(())
+    }
+
+    /// Measures consumer lag every `interval` and reports it to readiness.
+    async fn run_lag_gate(
+        mut gate: LagGate,
+        interval: Duration,
+        kafka_manager: KafkaManager,
+        consumer: Arc<PipelineConsumer>,
+        health: Arc<HealthState>,
+    ) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match kafka_manager.get_consumer_lag(&consumer).await {
+                Ok(lags) => {
+                    let lag = (!lags.is_empty()).then(|| lags.iter().map(|&(_, lag)| lag.max(0)).sum());
+                    let (ready, detail) = gate.observe(lag);
+                    health.set(CONSUMER_LAG_COMPONENT, ready, detail);
+                }
+                Err(e) => warn!("Failed to measure consumer lag: {}", e),
+            }
+        }
+    }
+
+    /// Rewinds a held partition to its poison message and pauses it there.
+    fn hold_partition(consumer: &PipelineConsumer, hold: &HeldPartition) {
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition(&hold.topic, hold.partition);
+        let result = consumer
+            .seek(&hold.topic, hold.partition, Offset::Offset(hold.offset), Duration::from_secs(1))
+            .and_then(|()| consumer.pause(&partitions));
+        if let Err(e) = result {
+            warn!("Failed to hold {}/{} at offset {}: {}", hold.topic, hold.partition, hold.offset, e);
+        }
+    }
+
+    fn release_partition(consumer: &PipelineConsumer, topic: &str, partition: i32) {
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition(topic, partition);
+        match consumer.resume(&partitions) {
+            Ok(()) => info!("Released {}/{}", topic, partition),
+            Err(e) => warn!("Failed to resume {}/{}: {}", topic, partition, e),
+        }
+    }
+
+    fn set_consumption_paused(consumer: &PipelineConsumer, paused: bool) {
+        let result = consumer.assignment().and_then(|assignment| {
+            if paused {
+                consumer.pause(&assignment)
+            } else {
+                consumer.resume(&assignment)
+            }
+        });
+        match result {
+            Ok(()) if paused => info!("Kafka consumption paused"),
+            Ok(()) => info!("Kafka consumption resumed"),
+            Err(e) => error!("Failed to {} Kafka consumption: {}", if paused { "pause" } else { "resume" }, e),
+        }
+    }
+
+    async fn open_writer_queue(&self) -> Result<(WriterQueue, mpsc::Receiver<Held<ProcessedBatch>>, Vec<Segment>)> {
+        let budget = &self.config.processing.memory_budget;
+        let (overflow, leftovers, capacity) = match budget.max_bytes {
+            Some(max_bytes) => {
+                let memory = MemoryBudget::new(max_bytes, self.metrics.clone());
+                let (overflow, leftovers) = Overflow::open(budget, memory, self.metrics.clone()).await?;
+                info!("Memory budget of {} bytes enabled for in-flight batches", max_bytes);
+                (Some(overflow), leftovers, budget.max_queued_batches)
+            }
+            None => (None, Vec::new(), self.config.processing.max_concurrent_tasks.max(1) * 2),
+        };
+
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        Ok((WriterQueue { tx, overflow }, rx, leftovers))
+    }
+
+    async fn start_processing_workers(
+        &self,
+        rx: mpsc::Receiver<KafkaMessage>,
+        queue: WriterQueue,
+    ) -> Result<Vec<tokio::task::JoinHandle<()>>> {
+        let mut handles = Vec::new();
+        let processing = &self.config.processing;
+        let worker_count = processing
+            .limits
+            .max_workers
+            .map_or(processing.max_concurrent_tasks, |max| max.min(processing.max_concurrent_tasks));
+        let dead_letters = DeadLetterSink::new(&self.config, &self.kafka_manager).await?;
+
+        for worker_id in 0..worker_count {
+            let rx = rx.clone();
+            let queue = queue.clone();
+            let message_processor = self.message_processor.clone();
+            let metrics = self.metrics.clone();
+            let batching = self.batching.clone();
+            let stages = self.stages.clone();
+            let dead_letters = dead_letters.clone();
+            let observers = self.observers.clone();
+
+            let handle = tokio::spawn(async move {
+                if let Err(e) = Self::run_processing_worker(
+                    worker_id,
+                    rx,
+                    queue,
+                    message_processor,
+                    stages,
+                    dead_letters,
+                    observers,
+                    metrics,
+                    batching,
+                ).await {
+                    error!("Processing worker {} error: {}", worker_id, e);
+                }
+            });
+
+            handles.push(handle);
+        }
+
+        info!("Started {} processing workers", worker_count);
+        Ok(handles)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_processing_worker(
+        worker_id: usize,
+        mut rx: mpsc::Receiver<KafkaMessage>,
+        queue: WriterQueue,
+        message_processor: MessageProcessor,
+        stages: BatchStages,
+        dead_letters: DeadLetterSink,
+        observers: BatchObservers,
+        metrics: Arc<Metrics>,
+        batching: Arc<AdaptiveBatching>,
+    ) -> Result<()> {
+        info!("Processing worker {} started", worker_id);
+
+        let mut batch = Vec::new();
+        let mut decoder = Decoder::new();
+
+        while let Some(message) = rx.recv().await {
+            batch.push(message);
+
+            let (batch_size, batch_timeout) = batching.limits(&batch[0].topic);
+
+            // Process batch if it's full or timeout reached
+            if batch.len() >= batch_size {
+                if let Err(e) = Self::process_batch(worker_id, &batch, &mut decoder, &message_processor, &stages, &dead_letters, &observers, &metrics, &queue).await {
+                    error!("Worker {} failed to process batch: {}", worker_id, e);
+                }
+                batch.clear();
+            } else {
+                // Wait for more messages or timeout
+                match timeout(batch_timeout, rx.recv()).await {
+                    Ok(Some(message)) => {
+                        batch.push(message);
+                    }
+                    Ok(None) => break, // Channel closed
+                    Err(_) => {
+                        // Timeout reached, process current batch
+                        if !batch.is_empty() {
+                            if let Err(e) = Self::process_batch(worker_id, &batch, &mut decoder, &message_processor, &stages, &dead_letters, &observers, &metrics, &queue).await {
+                                error!("Worker {} failed to process batch: {}", worker_id, e);
+                            }
+                            batch.clear();
+                        }
+                    }
+                }
+            }
+        }
+
+        // Process remaining messages
+        if !batch.is_empty() {
+            if let Err(e) = Self::process_batch(worker_id, &batch, &mut decoder, &message_processor, &stages, &dead_letters, &observers, &metrics, &queue).await {
+                error!("Worker {} failed to process final batch: {}", worker_id, e);
+            }
+        }
+
+        info!("Processing worker {} stopped", worker_id);
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn process_batch(
+        worker_id: usize,
+        batch: &[KafkaMessage],
+        decoder: &mut Decoder,
+        message_processor: &MessageProcessor,
+        stages: &BatchStages,
+        dead_letters: &DeadLetterSink,
+        observers: &BatchObservers,
+        metrics: &Arc<Metrics>,
+        queue: &WriterQueue,
+    ) -> Result<()> {
+        let start_time = Instant::now();
+        let _processing = queue.reserve(batch.iter().map(|message| message.payload.len() as u64).sum());
+        
+        info!("Processing batch of {} messages", batch.len());
+        metrics.observe_batch_size(worker_id, batch.len() as f64);
+
+        let mut transformed = Vec::with_capacity(batch.len());
+        for (index, message) in batch.iter().enumerate() {
+            // Left for after the release of a partition held at an earlier message
+            if stages.poison.is_held(&message.topic, message.partition, message.offset) {
+                continue;
+            }
+            // Decoded once, with this worker's buffers, for every attempt
+            let record: serde_json::Value = match decoder.decode(&message.payload) {
+                Ok(record) => record,
+                Err(e) => {
+                    warn!("Failed to decode message {}/{}@{}: {}", message.topic, message.partition, message.offset, e);
+                    metrics.increment_messages_failed(Some((&message.topic, message.partition)), Some(worker_id), 1);
+                    let failure = Failure::from_error(STAGE_TRANSFORM, ErrorClass::Processing, &e)
+                        .context("worker_id", worker_id);
+                    dead_letters.send(message, failure).await;
+                    continue;
+                }
+            };
+            let span = info_span!(parent: &message.span, "transform", worker_id);
+            match Self::attempt(message_processor, message, &record, &span, &stages.poison, metrics).await {
+                Ok(mut result) => {
+                    result.processing_metadata.tenant_id = message.tenant_id.clone();
+                    result.processing_metadata.traceparent = telemetry::traceparent(&span.context());
+                    transformed.push((index, result));
+                }
+                Err((e, attempts, verdict)) => {
+                    error!("Failed to process message: {}", e);
+                    metrics.increment_messages_failed(Some((&message.topic, message.partition)), Some(worker_id), 1);
+                    metrics.increment_processing_errors(worker_id);
+                    if verdict == Verdict::Pause {
+                        if let Some(hold) = stages.poison.hold(message, attempts, &e) {
+                            error!("Holding {}/{} at offset {}", hold.topic, hold.partition, hold.offset);
+                            observers.notifier.send(Notification {
+                                tenant_id: message.tenant_id.clone(),
+                                alert: hold.alert(),
+                            });
+                        }
+                        continue;
+                    }
+                    if verdict == Verdict::Drop {
+                        warn!("Dropping message {}/{}@{}", message.topic, message.partition, message.offset);
+                        continue;
+                    }
+                    let class = if e.is::<TransformTimeout>() {
+                        ErrorClass::Timeout
+                    } else {
+                        ErrorClass::Processing
+                    };
+                    let failure = Failure::from_error(STAGE_TRANSFORM, class, &e)
+                        .context("worker_id", worker_id)
+                        .context("attempts", attempts);
+                    dead_letters.send(message, failure).await;
+                }
+            }
+        }
+
+        // The stages after the transform are CPU-bound; keep them off the
+        // async workers
+        let staged = {
+            let (stages, notifier) = (stages.clone(), observers.notifier.clone());
+            stages.offload.clone().run(move || stages.run(transformed, &notifier)).await?
+        };
+        for drift in &staged.drifts {
+            stages.schemas.record(drift).await;
+        }
+        for (index, error) in staged.rejected {
+            let message = &batch[index];
+            metrics.increment_messages_failed(Some((&message.topic, message.partition)), Some(worker_id), 1);
+            let failure = Failure::new(STAGE_TRANSFORM, ErrorClass::Schema, error).context("worker_id", worker_id);
+            dead_letters.send(message, failure).await;
+        }
+        for &index in &staged.checked {
+            let message = &batch[index];
+            metrics.increment_messages_processed(&message.topic, message.partition, worker_id, 1);
+        }
+        let (processed, timings): (Vec<_>, Vec<_>) = staged
+            .kept
+            .into_iter()
+            .map(|(message, index)| {
+                let timing = match index {
+                    Some(index) => MessageTiming::from(&batch[index]),
+                    None => MessageTiming::derived(&message),
+                };
+                (message, timing)
+            })
+            .unzip();
+
+        let duration = start_time.elapsed();
+        metrics.observe_processing_duration(worker_id, duration.as_secs_f64());
+        
+        info!("Batch processed in {:?}", duration);
+
+        if !processed.is_empty() {
+            observers.observe(&processed);
+            let batch = ProcessedBatch {
+                messages: processed,
+                timings,
+                transformed_at: Utc::now(),
+            };
+            queue.send(batch).await?;
+        }
+        Ok(())
+    }
+
+    /// Processes `message`, decoded as `record`, attempting it again while
+    /// `processing.poison` allows, and fails with the last error, the
+    /// attempts made and what to do with the message. Attempts past
+    /// `processing.transform_timeout` are dropped, which cancels whatever
+    /// lookup they were waiting on.
+    async fn attempt(
+        message_processor: &MessageProcessor,
+        message: &KafkaMessage,
+        record: &serde_json::Value,
+        span: &Span,
+        poison: &PoisonGuard,
+        metrics: &Metrics,
+    ) -> Result<ProcessedMessage, (anyhow::Error, u32, Verdict)> {
+        let mut attempts = 1;
+        loop {
+            let process = message_processor.process_message(message, record).instrument(span.clone());
+            let result = match poison.transform_timeout() {
+                Some(limit) => timeout(limit, process)
+                    .await
+                    .unwrap_or_else(|_| Err(TransformTimeout(limit).into())),
+                None => process.await,
+            };
+            let error = match result {
+                Ok(result) => return Ok(result),
+                Err(e) => e,
+            };
+            let verdict = if error.is::<TransformTimeout>() {
+                metrics.increment_transform_timeouts(&message.topic);
+                poison.timeout_verdict(&message.topic, attempts)
+            } else {
+                poison.verdict(&message.topic, attempts)
+            };
+            match verdict {
+                Verdict::Retry(delay) => {
+                    warn!(
+                        "Attempt {} at {}/{}@{} failed, retrying in {:?}: {}",
+                        attempts, message.topic, message.partition, message.offset, delay, error
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempts += 1;
+                }
+                verdict => return Err((error, attempts, verdict)),
+            }
+        }
+    }
+
+    async fn start_database_writer(
+        &self,
+        rx: mpsc::Receiver<Held<ProcessedBatch>>,
+    ) -> Result<tokio::task::JoinHandle<()>> {
+        let storage = self.storage.clone();
+        let metrics = self.metrics.clone();
+        let batching = self.batching.clone();
+        let writer = BufferedWriter::new(storage.clone(), &self.config.database.spill, metrics.clone()).await?;
+        let lanes = self.config.database.write_concurrency.max(1);
+        let spill_health = self.config.metrics.readiness.gate_on_spill.then(|| self.health.clone());
+
+        let handle = tokio::spawn(async move {
+            let writer = Arc::new(writer);
+            let result = Self::run_database_writer(storage, writer, lanes, rx, metrics, batching, spill_health).await;
+            if let Err(e) = result {
+                error!("Database writer error: {}", e);
+            }
+        });
+
+        Ok(handle)
+    }
+
+    /// Splits each batch by topic partition across `lanes` concurrent write
+    /// lanes. A partition always maps to the same lane and each lane writes
+    /// in arrival order, so per-partition commit order is preserved. With
+    /// `spill_health`, the instance is not ready while writes are spilled.
+    async fn run_database_writer(
+        storage: Arc<dyn StorageBackend>,
+        writer: Arc<BufferedWriter>,
+        lanes: usize,
+        mut rx: mpsc::Receiver<Held<ProcessedBatch>>,
+        metrics: Arc<Metrics>,
+        batching: Arc<AdaptiveBatching>,
+        spill_health: Option<Arc<HealthState>>,
+    ) -> Result<()> {
+        info!("Database writer started with {} write lane(s)", lanes);
+
+        let (lane_txs, lane_handles): (Vec<_>, Vec<_>) = (0..lanes)
+            .map(|_| {
+                // One batch in hand and one waiting bounds each lane
+                let (tx, rx) = mpsc::channel(1);
+                let handle = tokio::spawn(Self::run_write_lane(writer.clone(), rx, metrics.clone(), batching.clone()));
+                (tx, handle)
+            })
+            .unzip();
+
+        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            tokio::select! {
+                batch = rx.recv() => {
+                    let held = match batch {
+                        Some(held) => held,
+                        None => break, // All workers stopped
+                    };
+                    let (batch, reservation) = match held.take().await {
+                        Ok(taken) => taken,
+                        Err(e) => {
+                            error!("Dropping queued batch: {}", e);
+                            metrics.increment_database_errors();
+                            continue;
+                        }
+                    };
+
+                    // Released once every lane has written its part
+                    let reservation = Arc::new(reservation);
+                    for (lane, part) in batch.split(lanes) {
+                        if lane_txs[lane].send((part, reservation.clone())).await.is_err() {
+                            anyhow::bail!("Database write lane {} stopped", lane);
+                        }
+                    }
+                }
+                _ = ticker.tick() => {
+                    // Drain anything spilled while the database was down
+                    writer.replay().await?;
+                    if let Some(health) = &spill_health {
+                        match writer.spilled_bytes() {
+                            0 => health.set(SPILL_COMPONENT, true, "ok"),
+                            bytes => {
+                                let detail = format!("{} bytes spilled, writes diverted to disk", bytes);
+                                health.set(SPILL_COMPONENT, false, detail);
+                            }
+                        }
+                    }
+
+                    // Update connection pool metrics
+                    let pool = storage.pool_status();
+
+                    metrics.set_connection_pool_size(pool.size as i64);
+                    metrics.set_connection_pool_available(pool.idle as i64);
+                }
+            }
+        }
+
+        drop(lane_txs);
+        futures::future::join_all(lane_handles).await;
+        writer.replay().await?;
+        info!("Database writer stopped");
+        Ok(())
+    }
+
+    async fn run_write_lane(
+        writer: Arc<BufferedWriter>,
+        mut rx: mpsc::Receiver<(ProcessedBatch, Arc<Option<Reservation>>)>,
+        metrics: Arc<Metrics>,
+        batching: Arc<AdaptiveBatching>,
+    ) {
+        while let Some((batch, _reservation)) = rx.recv().await {
+            metrics.increment_database_operations();
+            let span = info_span!("storage.write", messages = batch.messages.len());
+            match writer.write(&batch.messages).instrument(span).await {
+                Ok(true) => {
+                    let committed_at = Utc::now();
+                    batch.observe_latency(&metrics, committed_at);
+                    batching.observe(batch.pipeline_latencies(committed_at));
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    error!("Dropping batch of {} processed messages: {}", batch.messages.len(), e);
+                    metrics.increment_database_errors();
+                }
+            }
+        }
+    }
+
+    async fn start_metrics_collection(&self) -> Result<tokio::task::JoinHandle<()>> {
+        let metrics = self.metrics.clone();
+
+        let handle = tokio::spawn(async move {
+            if let Err(e) = Self::run_metrics_collection(metrics).await {
+                error!("Metrics collection error: {}", e);
+            }
+        });
+
+        Ok(handle)
+    }
+
+    async fn run_metrics_collection(metrics: Arc<Metrics>) -> Result<()> {
+        info!("Metrics collection started");
+
+        let mut collector = SystemCollector::new()?;
+        loop {
+            collector.collect(&metrics);
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+    }
+}
+
+/// Consumers of every processed batch besides storage.
+#[derive(Clone)]
+struct BatchObservers {
+    hub: Arc<Hub>,
+    status: Arc<StatusTracker>,
+    notifier: Arc<Notifier>,
+    sessions: Arc<SessionTracker>,
+}
+
+impl BatchObservers {
+    fn observe(&self, processed: &[ProcessedMessage]) {
+        self.hub.publish_processed(processed);
+        self.status.observe(processed);
+        self.notifier.notify(processed);
+        self.sessions.observe(processed);
+    }
+}
+
+/// Stages every processed message goes through before storage.
+#[derive(Clone)]
+struct BatchStages {
+    cardinality: Arc<CardinalityGuard>,
+    patterns: Arc<LogPatterns>,
+    log_metrics: Arc<LogMetrics>,
+    recording: Arc<RecordingRules>,
+    schemas: Arc<SchemaRegistry>,
+    poison: Arc<PoisonGuard>,
+    offload: Offload,
+}
+
+/// What the stages after the transform made of a batch. Messages are
+/// referred to by their position in the batch.
+#[derive(Default)]
+struct StagedBatch {
+    /// Messages to store, with the position of the message each came from,
+    /// or `None` for those derived from others.
+    kept: Vec<(ProcessedMessage, Option<usize>)>,
+    /// Messages rejected by their topic's schema, and why.
+    rejected: Vec<(usize, String)>,
+    /// Messages that passed the schema check, kept or not.
+    checked: Vec<usize>,
+    /// Schema changes to store.
+    drifts: Vec<SchemaDrift>,
+}
+
+impl BatchStages {
+    /// Runs the cardinality guard, alerting on violations, and returns
+    /// whether to keep `message`.
+    fn admit(&self, message: &mut ProcessedMessage, notifier: &Notifier) -> bool {
+        let (keep, violation) = self.cardinality.admit(message, Instant::now());
+        if let Some(violation) = violation {
+            warn!("Metric {} is over its limit of {} series", violation.metric, violation.limit);
+            notifier.send(Notification {
+                tenant_id: violation.tenant_id.clone(),
+                alert: violation.alert(),
+            });
+        }
+        keep
+    }
+
+    /// Runs the transformed messages of a batch, by position, through the
+    /// schema checks, the cardinality guard, log patterns and metrics and
+    /// recording rules, then adds the metrics those derived.
+    fn run(&self, transformed: Vec<(usize, ProcessedMessage)>, notifier: &Notifier) -> StagedBatch {
+        let mut staged = StagedBatch::default();
+        for (index, mut message) in transformed {
+            if let Some(drift) = self.schemas.observe(&message) {
+                self.alert_schema(&drift, notifier);
+                let rejected = drift.rejected.then(|| drift.error());
+                staged.drifts.push(drift);
+                if let Some(error) = rejected {
+                    staged.rejected.push((index, error));
+                    continue;
+                }
+            }
+            staged.checked.push(index);
+            if !self.admit(&mut message, notifier) {
+                continue;
+            }
+            self.log_metrics.observe(&message);
+            self.recording.observe(&message);
+            if let Some(pattern) = self.patterns.apply(&mut message) {
+                info!("New {} log pattern {}: {}", pattern.level, pattern.template_id, pattern.template);
+                notifier.send(Notification {
+                    tenant_id: pattern.tenant_id.clone(),
+                    alert: pattern.alert(),
+                });
+            }
+            staged.kept.push((message, Some(index)));
+        }
+
+        // Rules can record from metrics derived from logs, so those go first
+        let now = Instant::now();
+        for mut derived in self.log_metrics.flush(now) {
+            if self.admit(&mut derived, notifier) {
+                self.recording.observe(&derived);
+                staged.kept.push((derived, None));
+            }
+        }
+        staged.kept.extend(self.recording.flush(now).into_iter().map(|recorded| (recorded, None)));
+        staged
+    }
+
+    /// Alerts on a schema change that calls for it.
+    fn alert_schema(&self, drift: &SchemaDrift, notifier: &Notifier) {
+        if drift.alerted {
+            warn!(
+                "Schema of topic {} changed incompatibly: {}",
+                drift.version.topic,
+                drift.version.violations.join("; ")
+            );
+            notifier.send(Notification {
+                tenant_id: drift.version.tenant_id.clone(),
+                alert: drift.alert(),
+            });
+        }
+    }
+}
+
+/// Sending side of the queue to the database writer. Under a memory budget,
+/// batches that do not fit are spilled to disk on their way through.
+#[derive(Clone)]
+struct WriterQueue {
+    tx: mpsc::Sender<Held<ProcessedBatch>>,
+    overflow: Option<Arc<Overflow>>,
+}
+
+impl WriterQueue {
+    /// Counts a batch being processed against the budget while the
+    /// reservation is held.
+    fn reserve(&self, bytes: u64) -> Option<Reservation> {
+        self.overflow.as_ref().map(|overflow| overflow.budget().reserve(bytes))
+    }
+
+    async fn send(&self, batch: ProcessedBatch) -> Result<()> {
+        let held = match &self.overflow {
+            Some(overflow) => {
+                let bytes = batch.approximate_bytes();
+                overflow.hold(batch, bytes).await
+            }
+            None => Held::Memory(batch, None),
+        };
+        self.tx
+            .send(held)
+            .await
+            .map_err(|_| anyhow::anyhow!("Database writer channel closed"))
+    }
+}
+
+/// Producer for messages that failed processing.
+#[derive(Clone)]
+struct DeadLetterSink {
+    producer: FutureProducer,
+    topic: String,
+    /// Also receives every failure as an [`ErrorRecord`] when set.
+    error_topic: Option<String>,
+    faults: FaultInjector,
+}
+
+impl DeadLetterSink {
+    async fn new(config: &Config, kafka_manager: &KafkaManager) -> Result<Self> {
+        Ok(Self {
+            producer: kafka_manager.create_producer().await?,
+            topic: config.processing.dead_letter_queue_topic.clone(),
+            error_topic: config.processing.error_topic.clone(),
+            faults: FaultInjector::kafka(config),
+        })
+    }
+
+    async fn send(&self, message: &KafkaMessage, failure: Failure) {
+        let envelope = Envelope::new(message, failure);
+        let publish = || dlq::publish(&self.producer, &self.topic, message, &envelope);
+        if let Err(e) = self.faults.run("dlq.publish", publish).await {
+            error!("Failed to dead-letter message {}/{}@{}: {}", message.topic, message.partition, message.offset, e);
+        }
+
+        if let Some(error_topic) = &self.error_topic {
+            let record = ErrorRecord::new(envelope, message);
+            let publish = || dlq::publish_record(&self.producer, error_topic, &record);
+            if let Err(e) = self.faults.run("errors.publish", publish).await {
+                error!(
+                    "Failed to publish error record for {}/{}@{}: {}",
+                    message.topic, message.partition, message.offset, e
+                );
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KafkaMessage {
+    pub tenant_id: String,
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub payload: Vec<u8>,
+    pub key: Option<Vec<u8>>,
+    /// Original headers, carried into the dead letter envelope on failure.
+    pub headers: Option<OwnedHeaders>,
+    /// Kafka message timestamp in milliseconds since the epoch; 0 when the
+    /// broker supplied none.
+    pub timestamp: i64,
+    /// When the consumer received the message.
+    pub received_at: DateTime<Utc>,
+    /// Consumer span, parented to the producer's trace context when the
+    /// message carried one.
+    pub span: Span,
+    /// Counts the payload against `limits.max_inflight_bytes` until the
+    /// message is processed.
+    pub inflight: Option<Arc<OwnedSemaphorePermit>>,
+}
+
+/// Pipeline stage names used as the `stage` label on latency metrics.
+pub const STAGE_CONSUME: &str = "consume";
+pub const STAGE_TRANSFORM: &str = "transform";
+pub const STAGE_WRITE: &str = "write";
+
+fn lane_for(topic: &str, partition: i32, lanes: usize) -> usize {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (topic, partition).hash(&mut hasher);
+    (hasher.finish() % lanes as u64) as usize
+}
+
+/// Budgeted bytes per message on top of its JSON, for ids, metadata and timing.
+const MESSAGE_OVERHEAD: u64 = 256;
+
+/// Processed messages on their way to the database writer, along with the
+/// timestamps needed to attribute latency once they are committed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessedBatch {
+    pub messages: Vec<ProcessedMessage>,
+    timings: Vec<MessageTiming>,
+    transformed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MessageTiming {
+    topic: String,
+    event_time: Option<DateTime<Utc>>,
+    received_at: DateTime<Utc>,
+}
+
+impl From<&KafkaMessage> for MessageTiming {
+    fn from(message: &KafkaMessage) -> Self {
+        Self {
+            topic: message.topic.clone(),
+            event_time: (message.timestamp > 0)
+                .then(|| DateTime::from_timestamp_millis(message.timestamp))
+                .flatten(),
+            received_at: message.received_at,
+        }
+    }
+}
+
+impl MessageTiming {
+    /// Timing of a message the pipeline made itself, such as a metric
+    /// derived from logs, which starts when it is made.
+    fn derived(message: &ProcessedMessage) -> Self {
+        Self {
+            topic: message.processing_metadata.source_topic.clone(),
+            event_time: None,
+            received_at: message.processing_metadata.processed_at,
+        }
+    }
+}
+
+impl ProcessedBatch {
+    /// Splits the batch into one part per lane its topic partitions hash
+    /// to, keeping message order within each part.
+    fn split(self, lanes: usize) -> Vec<(usize, ProcessedBatch)> {
+        if lanes <= 1 {
+            return vec![(0, self)];
+        }
+
+        let mut parts: Vec<Option<ProcessedBatch>> = (0..lanes).map(|_| None).collect();
+        for (message, timing) in self.messages.into_iter().zip(self.timings) {
+            let metadata = &message.processing_metadata;
+            let lane = lane_for(&metadata.source_topic, metadata.partition, lanes);
+            let part = parts[lane].get_or_insert_with(|| ProcessedBatch {
+                messages: Vec::new(),
+                timings: Vec::new(),
+                transformed_at: self.transformed_at,
+            });
+            part.messages.push(message);
+            part.timings.push(timing);
+        }
+        parts
+            .into_iter()
+            .enumerate()
+            .filter_map(|(lane, part)| part.map(|part| (lane, part)))
+            .collect()
+    }
+
+    /// Time each message spent between consumption and `committed_at`, by topic.
+    fn pipeline_latencies(&self, committed_at: DateTime<Utc>) -> impl Iterator<Item = (&str, Duration)> + '_ {
+        self.timings.iter().map(move |timing| {
+            let latency = (committed_at - timing.received_at).to_std().unwrap_or_default();
+            (timing.topic.as_str(), latency)
+        })
+    }
+
+    /// Estimated memory held by the batch, for the memory budget.
+    fn approximate_bytes(&self) -> u64 {
+        self.messages
+            .iter()
+            .map(|m| approximate_size(&m.original_message) + approximate_size(&m.processed_message) + MESSAGE_OVERHEAD)
+            .sum()
+    }
+
+    /// Records per-stage and end-to-end latency for a batch committed at
+    /// `committed_at`. Messages without a Kafka timestamp only contribute to
+    /// the stages that do not depend on it. Clock skew between producer and
+    /// consumer can make event-time spans negative; those are clamped to zero.
+    fn observe_latency(&self, metrics: &Metrics, committed_at: DateTime<Utc>) {
+        let seconds = |from: DateTime<Utc>, to: DateTime<Utc>| {
+            (to - from).to_std().map(|d| d.as_secs_f64()).unwrap_or(0.0)
+        };
+
+        for timing in &self.timings {
+            if let Some(event_time) = timing.event_time {
+                metrics.observe_stage_latency(&timing.topic, STAGE_CONSUME, seconds(event_time, timing.received_at));
+                metrics.observe_end_to_end_latency(&timing.topic, seconds(event_time, committed_at));
+            }
+            metrics.observe_stage_latency(&timing.topic, STAGE_TRANSFORM, seconds(timing.received_at, self.transformed_at));
+            metrics.observe_stage_latency(&timing.topic, STAGE_WRITE, seconds(self.transformed_at, committed_at));
+        }
+    }
+}
+
+/// Result of running a message through the pipeline, as persisted by storage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessedMessage {
+    pub id: String,
+    pub original_message: serde_json::Value,
+    pub processed_message: serde_json::Value,
+    pub processing_metadata: ProcessingMetadata,
+}
+
+/// Tenant used for messages that arrive without a tenant header.
+pub const DEFAULT_TENANT_ID: &str = "default";
+
+fn default_tenant_id() -> String {
+    DEFAULT_TENANT_ID.to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessingMetadata {
+    /// Owning tenant; batches spilled before tenants existed decode as the default tenant.
+    #[serde(default = "default_tenant_id")]
+    pub tenant_id: String,
+    pub processed_at: DateTime<Utc>,
+    pub processor_version: String,
+    pub source_topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// W3C `traceparent` of the span that processed the message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
+}
+
+impl ProcessedMessage {
+    /// A record the pipeline made itself, such as a derived metric,
+    /// attributed to the consumed message at `source`.
+    pub fn derived(record: serde_json::Value, source: &ProcessingMetadata) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            original_message: record.clone(),
+            processed_message: record,
+            processing_metadata: ProcessingMetadata {
+                processed_at: Utc::now(),
+                processor_version: env!("CARGO_PKG_VERSION").to_string(),
+                traceparent: None,
+                ..source.clone()
+            },
+        }
+    }
+}
+
+// Re-export modules for easier access
+pub mod kafka;
+pub mod processing; 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(topic: &str, partition: i32, offset: i64) -> ProcessedMessage {
+        ProcessedMessage {
+            id: format!("{}-{}-{}", topic, partition, offset),
+            original_message: json!({"offset": offset}),
+            processed_message: json!({"offset": offset}),
+            processing_metadata: ProcessingMetadata {
+                tenant_id: DEFAULT_TENANT_ID.to_string(),
+                processed_at: Utc::now(),
+                processor_version: "test".to_string(),
+                source_topic: topic.to_string(),
+                partition,
+                offset,
+                traceparent: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_partition_always_maps_to_the_same_lane() {
+        for lanes in [1, 3, 8] {
+            for partition in 0..32 {
+                let lane = lane_for("metrics", partition, lanes);
+                assert!(lane < lanes);
+                assert_eq!(lane_for("metrics", partition, lanes), lane);
+            }
+        }
+        let lanes: std::collections::HashSet<_> = (0..32).map(|partition| lane_for("metrics", partition, 4)).collect();
+        assert!(lanes.len() > 1);
+    }
+
+    #[test]
+    fn test_split_keeps_message_order_within_each_part() {
+        let messages: Vec<_> = (0..40)
+            .map(|offset| message(&format!("topic-{}", offset % 2), (offset % 5) as i32, offset))
+            .collect();
+        let batch = ProcessedBatch {
+            timings: messages.iter().map(MessageTiming::derived).collect(),
+            messages,
+            transformed_at: Utc::now(),
+        };
+
+        let parts = batch.split(4);
+        assert_eq!(parts.iter().map(|(_, part)| part.messages.len()).sum::<usize>(), 40);
+        let mut lanes = std::collections::HashSet::new();
+        for (lane, part) in &parts {
+            assert!(lanes.insert(*lane));
+            assert_eq!(part.messages.len(), part.timings.len());
+            for pair in part.messages.windows(2) {
+                assert!(pair[0].processing_metadata.offset < pair[1].processing_metadata.offset);
+            }
+            for message in &part.messages {
+                let metadata = &message.processing_metadata;
+                assert_eq!(lane_for(&metadata.source_topic, metadata.partition, 4), *lane);
+            }
+            for (message, timing) in part.messages.iter().zip(&part.timings) {
+                assert_eq!(message.processing_metadata.source_topic, timing.topic);
+            }
+        }
+
+        let whole = ProcessedBatch {
+            messages: vec![message("metrics", 0, 1)],
+            timings: Vec::new(),
+            transformed_at: Utc::now(),
+        };
+        assert_eq!(whole.split(1)[0].0, 0);
+    }
+}
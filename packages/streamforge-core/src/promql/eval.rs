@@ -0,0 +1,795 @@
+use super::parser::{
+    Aggregate, AggregateOp, Binary, BinaryOp, Expr, Function, Grouping, Selector, ValueKind, VectorMatching,
+    METRIC_NAME,
+};
+use crate::config::PromqlConfig;
+use crate::storage::{self, MetricRecord, StorageBackend};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+
+pub type Labels = BTreeMap<String, String>;
+
+/// Why a query failed, which decides the error type and status code of
+/// the HTTP response.
+#[derive(Debug, thiserror::Error)]
+pub enum QueryError {
+    /// The query or its parameters are invalid.
+    #[error("{0}")]
+    BadData(String),
+    /// The query is valid but cannot be answered, e.g. it selects too much.
+    #[error("{0}")]
+    Execution(String),
+    #[error("a valid bearer token is required")]
+    Unauthorized,
+    #[error("{0}")]
+    Internal(anyhow::Error),
+}
+
+/// A series' samples as `(timestamp in ms, value)`, oldest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub labels: Labels,
+    pub samples: Vec<(i64, f64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub labels: Labels,
+    pub value: f64,
+}
+
+/// The result of a query, with timestamps in ms.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryValue {
+    Scalar(i64, f64),
+    Vector(i64, Vec<Sample>),
+    Matrix(Vec<Series>),
+}
+
+enum Value {
+    Scalar(f64),
+    Vector(Vec<Sample>),
+}
+
+/// Evaluates parsed queries over one tenant's stored metrics, archived
+/// segments included. Every selector's series are loaded once for the
+/// whole query, within `promql.max_samples`.
+pub struct Engine<'a> {
+    storage: &'a dyn StorageBackend,
+    tenant_id: &'a str,
+    config: &'a PromqlConfig,
+}
+
+impl<'a> Engine<'a> {
+    pub fn new(storage: &'a dyn StorageBackend, tenant_id: &'a str, config: &'a PromqlConfig) -> Self {
+        Self {
+            storage,
+            tenant_id,
+            config,
+        }
+    }
+
+    /// Evaluates `expr` at `time`. A range selector on its own returns its
+    /// samples, as in Prometheus.
+    pub async fn instant(&self, expr: &Expr, time: i64) -> Result<QueryValue, QueryError> {
+        let loaded = self.load(expr, time, time).await?;
+        let evaluation = Evaluation {
+            series: &loaded,
+            lookback: self.config.lookback_delta.as_millis() as i64,
+        };
+        if let Expr::Matrix(selector, range) = expr {
+            return Ok(QueryValue::Matrix(evaluation.matrix(selector, *range, time).0));
+        }
+        Ok(match evaluation.eval(expr, time)? {
+            Value::Scalar(value) => QueryValue::Scalar(time, value),
+            Value::Vector(samples) => QueryValue::Vector(time, unique(samples)?),
+        })
+    }
+
+    /// Evaluates `expr` at every `step` from `start` to `end`, both included,
+    /// collecting each series' values into a matrix.
+    pub async fn range(&self, expr: &Expr, start: i64, end: i64, step: i64) -> Result<QueryValue, QueryError> {
+        if expr.kind() == ValueKind::Matrix {
+            return Err(QueryError::BadData(format!(
+                "invalid expression type {:?} for range query, must be scalar or instant vector",
+                expr.kind().as_str()
+            )));
+        }
+        if end < start {
+            return Err(QueryError::BadData("end timestamp must not be before start time".to_string()));
+        }
+        if step <= 0 {
+            return Err(QueryError::BadData("step must be greater than 0".to_string()));
+        }
+        // Stepping past `end` must not overflow either
+        let points = match (end.checked_sub(start), end.checked_add(step)) {
+            (Some(span), Some(_)) => span / step,
+            _ => return Err(QueryError::BadData("query time range is too large".to_string())),
+        };
+        if points >= self.config.max_points as i64 {
+            return Err(QueryError::BadData(format!(
+                "exceeded maximum resolution of {} points per timeseries; try a larger step",
+                self.config.max_points
+            )));
+        }
+
+        let loaded = self.load(expr, start, end).await?;
+        let evaluation = Evaluation {
+            series: &loaded,
+            lookback: self.config.lookback_delta.as_millis() as i64,
+        };
+        let mut series: BTreeMap<Labels, Vec<(i64, f64)>> = BTreeMap::new();
+        let mut time = start;
+        while time <= end {
+            match evaluation.eval(expr, time)? {
+                Value::Scalar(value) => series.entry(Labels::new()).or_default().push((time, value)),
+                Value::Vector(samples) => {
+                    for sample in unique(samples)? {
+                        series.entry(sample.labels).or_default().push((time, sample.value));
+                    }
+                }
+            }
+            time += step;
+        }
+        Ok(QueryValue::Matrix(
+            series
+                .into_iter()
+                .map(|(labels, samples)| Series { labels, samples })
+                .collect(),
+        ))
+    }
+
+    /// Series of every selector in `expr`, covering what evaluating it from
+    /// `start` to `end` looks at.
+    async fn load(&self, expr: &Expr, start: i64, end: i64) -> Result<HashMap<usize, Vec<Series>>, QueryError> {
+        let mut selectors = Vec::new();
+        selectors_of(expr, &mut selectors);
+
+        let mut loaded = HashMap::new();
+        let mut samples = 0;
+        for (selector, range) in selectors {
+            let window = range.unwrap_or(self.config.lookback_delta).as_millis() as i64;
+            let offset = selector.offset.as_millis() as i64;
+            let (from, to) = (start - offset - window, end - offset);
+            let remaining = self.config.max_samples - samples;
+            let metrics = storage::get_tiered_metrics(
+                self.storage,
+                self.tenant_id,
+                selector.metric_name(),
+                timestamp(from)?,
+                timestamp(to + 1)?,
+                Some(remaining as i64 + 1),
+            )
+            .await
+            .map_err(QueryError::Internal)?;
+            if metrics.len() > remaining {
+                return Err(QueryError::Execution(format!(
+                    "query would load more than {} samples; narrow the selectors or the time range",
+                    self.config.max_samples
+                )));
+            }
+            samples += metrics.len();
+            loaded.insert(selector.id, series_of(selector, metrics));
+        }
+        Ok(loaded)
+    }
+}
+
+fn timestamp(ms: i64) -> Result<DateTime<Utc>, QueryError> {
+    DateTime::from_timestamp_millis(ms).ok_or_else(|| QueryError::BadData(format!("timestamp {} is out of range", ms)))
+}
+
+fn selectors_of<'e>(expr: &'e Expr, selectors: &mut Vec<(&'e Selector, Option<std::time::Duration>)>) {
+    match expr {
+        Expr::Number(_) => {}
+        Expr::Vector(selector) => selectors.push((selector, None)),
+        Expr::Matrix(selector, range) => selectors.push((selector, Some(*range))),
+        Expr::Call(_, args) => args.iter().for_each(|arg| selectors_of(arg, selectors)),
+        Expr::Aggregate(aggregate) => {
+            if let Some(parameter) = &aggregate.parameter {
+                selectors_of(parameter, selectors);
+            }
+            selectors_of(&aggregate.expr, selectors);
+        }
+        Expr::Binary(binary) => {
+            selectors_of(&binary.lhs, selectors);
+            selectors_of(&binary.rhs, selectors);
+        }
+        Expr::Negate(expr) => selectors_of(expr, selectors),
+    }
+}
+
+/// Labels of a stored metric: its tags, with non-string values as JSON
+/// text, and its name as `__name__`.
+fn labels(metric: &MetricRecord) -> Labels {
+    let mut labels = Labels::new();
+    if let Some(serde_json::Value::Object(tags)) = &metric.tags {
+        for (key, value) in tags {
+            match value {
+                serde_json::Value::Null => {}
+                serde_json::Value::String(value) => {
+                    labels.insert(key.clone(), value.clone());
+                }
+                value => {
+                    labels.insert(key.clone(), value.to_string());
+                }
+            }
+        }
+    }
+    labels.insert(METRIC_NAME.to_string(), metric.name.clone());
+    labels
+}
+
+fn series_of(selector: &Selector, metrics: Vec<MetricRecord>) -> Vec<Series> {
+    let mut series: BTreeMap<Labels, Vec<(i64, f64)>> = BTreeMap::new();
+    for metric in metrics {
+        let labels = labels(&metric);
+        if selector.matchers.iter().all(|m| m.matches(labels.get(&m.label).map(String::as_str))) {
+            series
+                .entry(labels)
+                .or_default()
+                .push((metric.timestamp.timestamp_millis(), metric.value));
+        }
+    }
+    series
+        .into_iter()
+        .map(|(labels, samples)| Series { labels, samples })
+        .collect()
+}
+
+fn unique(samples: Vec<Sample>) -> Result<Vec<Sample>, QueryError> {
+    let mut seen = std::collections::BTreeSet::new();
+    for sample in &samples {
+        if !seen.insert(&sample.labels) {
+            return Err(QueryError::Execution(
+                "vector cannot contain metrics with the same labelset".to_string(),
+            ));
+        }
+    }
+    Ok(samples)
+}
+
+struct Evaluation<'l> {
+    series: &'l HashMap<usize, Vec<Series>>,
+    lookback: i64,
+}
+
+impl Evaluation<'_> {
+    fn loaded(&self, selector: &Selector) -> &[Series] {
+        self.series.get(&selector.id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    fn eval(&self, expr: &Expr, time: i64) -> Result<Value, QueryError> {
+        Ok(match expr {
+            Expr::Number(value) => Value::Scalar(*value),
+            Expr::Vector(selector) => Value::Vector(self.vector(selector, time)),
+            Expr::Matrix(..) => {
+                return Err(QueryError::BadData("range vectors are only valid as function arguments".to_string()))
+            }
+            Expr::Call(function, args) => self.call(*function, args, time)?,
+            Expr::Aggregate(aggregate) => Value::Vector(self.aggregate(aggregate, time)?),
+            Expr::Binary(binary) => self.binary(binary, time)?,
+            Expr::Negate(expr) => match self.eval(expr, time)? {
+                Value::Scalar(value) => Value::Scalar(-value),
+                Value::Vector(samples) => Value::Vector(
+                    samples
+                        .into_iter()
+                        .map(|sample| Sample {
+                            labels: without_name(sample.labels),
+                            value: -sample.value,
+                        })
+                        .collect(),
+                ),
+            },
+        })
+    }
+
+    /// The latest sample of each series within the lookback delta.
+    fn vector(&self, selector: &Selector, time: i64) -> Vec<Sample> {
+        let at = time - selector.offset.as_millis() as i64;
+        self.loaded(selector)
+            .iter()
+            .filter_map(|series| {
+                let end = series.samples.partition_point(|&(t, _)| t <= at);
+                let &(t, value) = series.samples[..end].last()?;
+                (t > at - self.lookback).then(|| Sample {
+                    labels: series.labels.clone(),
+                    value,
+                })
+            })
+            .collect()
+    }
+
+    /// Samples of each series in `(time - range, time]`, with that window.
+    fn matrix(&self, selector: &Selector, range: std::time::Duration, time: i64) -> (Vec<Series>, i64, i64) {
+        let end = time - selector.offset.as_millis() as i64;
+        let start = end - range.as_millis() as i64;
+        let series = self
+            .loaded(selector)
+            .iter()
+            .filter_map(|series| {
+                let from = series.samples.partition_point(|&(t, _)| t <= start);
+                let to = series.samples.partition_point(|&(t, _)| t <= end);
+                (from < to).then(|| Series {
+                    labels: series.labels.clone(),
+                    samples: series.samples[from..to].to_vec(),
+                })
+            })
+            .collect();
+        (series, start, end)
+    }
+
+    fn scalar(&self, expr: &Expr, time: i64) -> Result<f64, QueryError> {
+        match self.eval(expr, time)? {
+            Value::Scalar(value) => Ok(value),
+            Value::Vector(_) => Err(QueryError::BadData("expected a scalar".to_string())),
+        }
+    }
+
+    fn samples(&self, expr: &Expr, time: i64) -> Result<Vec<Sample>, QueryError> {
+        match self.eval(expr, time)? {
+            Value::Vector(samples) => Ok(samples),
+            Value::Scalar(_) => Err(QueryError::BadData("expected an instant vector".to_string())),
+        }
+    }
+
+    fn call(&self, function: Function, args: &[Expr], time: i64) -> Result<Value, QueryError> {
+        let map = |f: &dyn Fn(f64) -> f64| -> Result<Value, QueryError> {
+            let samples = self.samples(&args[0], time)?;
+            Ok(Value::Vector(
+                samples
+                    .into_iter()
+                    .map(|sample| Sample {
+                        labels: without_name(sample.labels),
+                        value: f(sample.value),
+                    })
+                    .collect(),
+            ))
+        };
+
+        Ok(match function {
+            Function::Time => Value::Scalar(time as f64 / 1000.0),
+            Function::Vector => Value::Vector(vec![Sample {
+                labels: Labels::new(),
+                value: self.scalar(&args[0], time)?,
+            }]),
+            Function::Scalar => match self.samples(&args[0], time)?.as_slice() {
+                [sample] => Value::Scalar(sample.value),
+                _ => Value::Scalar(f64::NAN),
+            },
+            Function::Abs => map(&f64::abs)?,
+            Function::Ceil => map(&f64::ceil)?,
+            Function::Floor => map(&f64::floor)?,
+            // Halves round up, as in Prometheus
+            Function::Round => map(&|v| (v + 0.5).floor())?,
+            Function::Sqrt => map(&f64::sqrt)?,
+            Function::ClampMin => {
+                let min = self.scalar(&args[1], time)?;
+                map(&|v| v.max(min))?
+            }
+            Function::ClampMax => {
+                let max = self.scalar(&args[1], time)?;
+                map(&|v| v.min(max))?
+            }
+            _ => {
+                let Expr::Matrix(selector, range) = &args[0] else {
+                    return Err(QueryError::BadData("expected a range vector".to_string()));
+                };
+                let (series, start, end) = self.matrix(selector, *range, time);
+                Value::Vector(
+                    series
+                        .into_iter()
+                        .filter_map(|series| {
+                            let value = over_time(function, &series.samples, start, end)?;
+                            let labels = if function == Function::LastOverTime {
+                                series.labels
+                            } else {
+                                without_name(series.labels)
+                            };
+                            Some(Sample { labels, value })
+                        })
+                        .collect(),
+                )
+            }
+        })
+    }
+
+    fn aggregate(&self, aggregate: &Aggregate, time: i64) -> Result<Vec<Sample>, QueryError> {
+        let samples = self.samples(&aggregate.expr, time)?;
+        let mut groups: BTreeMap<Labels, Vec<Sample>> = BTreeMap::new();
+        for sample in samples {
+            let key = match &aggregate.grouping {
+                Grouping::By(labels) => sample
+                    .labels
+                    .iter()
+                    .filter(|(name, _)| labels.contains(name))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+                Grouping::Without(labels) => sample
+                    .labels
+                    .iter()
+                    .filter(|(name, _)| *name != METRIC_NAME && !labels.contains(name))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+            };
+            groups.entry(key).or_default().push(sample);
+        }
+
+        if let Some(parameter) = &aggregate.parameter {
+            let k = self.scalar(parameter, time)?;
+            let k = if k.is_nan() || k < 1.0 { 0 } else { k as usize };
+            let mut selected = Vec::new();
+            for (_, mut group) in groups {
+                group.sort_by(|a, b| {
+                    let order = a.value.partial_cmp(&b.value).unwrap_or(std::cmp::Ordering::Equal);
+                    if aggregate.op == AggregateOp::Topk {
+                        order.reverse()
+                    } else {
+                        order
+                    }
+                });
+                selected.extend(group.into_iter().take(k));
+            }
+            return Ok(selected);
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|(labels, group)| {
+                let values = group.iter().map(|sample| sample.value);
+                let value = match aggregate.op {
+                    AggregateOp::Sum => values.sum(),
+                    AggregateOp::Avg => values.sum::<f64>() / group.len() as f64,
+                    AggregateOp::Min => values.fold(f64::NAN, f64::min),
+                    AggregateOp::Max => values.fold(f64::NAN, f64::max),
+                    AggregateOp::Count => group.len() as f64,
+                    AggregateOp::Topk | AggregateOp::Bottomk => unreachable!("topk and bottomk take a parameter"),
+                };
+                Sample { labels, value }
+            })
+            .collect())
+    }
+
+    fn binary(&self, binary: &Binary, time: i64) -> Result<Value, QueryError> {
+        let op = binary.op;
+        let lhs = self.eval(&binary.lhs, time)?;
+        let rhs = self.eval(&binary.rhs, time)?;
+        // Filtering comparisons keep the samples as they are
+        let keeps_name = op.is_comparison() && !binary.return_bool;
+        let result_labels = |labels: Labels| if keeps_name { labels } else { without_name(labels) };
+
+        Ok(match (lhs, rhs) {
+            (Value::Scalar(lhs), Value::Scalar(rhs)) => Value::Scalar(apply(op, lhs, rhs, true).unwrap_or(0.0)),
+            (Value::Vector(samples), Value::Scalar(scalar)) => Value::Vector(
+                samples
+                    .into_iter()
+                    .filter_map(|sample| {
+                        let value = apply(op, sample.value, scalar, binary.return_bool)?;
+                        Some(Sample {
+                            labels: result_labels(sample.labels),
+                            value,
+                        })
+                    })
+                    .collect(),
+            ),
+            (Value::Scalar(scalar), Value::Vector(samples)) => Value::Vector(
+                samples
+                    .into_iter()
+                    .filter_map(|sample| {
+                        let value = apply(op, scalar, sample.value, binary.return_bool)?;
+                        // A filtering comparison keeps the vector's value
+                        let value = if keeps_name { sample.value } else { value };
+                        Some(Sample {
+                            labels: result_labels(sample.labels),
+                            value,
+                        })
+                    })
+                    .collect(),
+            ),
+            (Value::Vector(lhs), Value::Vector(rhs)) if op.is_set() => {
+                Value::Vector(set_operation(op, &binary.matching, lhs, rhs))
+            }
+            (Value::Vector(lhs), Value::Vector(rhs)) => {
+                let mut matches = HashMap::new();
+                for sample in rhs {
+                    let signature = signature(&binary.matching, &sample.labels);
+                    if matches.insert(signature, sample).is_some() {
+                        return Err(QueryError::Execution(
+                            "found duplicate series for the match group on the right hand-side of the operation"
+                                .to_string(),
+                        ));
+                    }
+                }
+                let mut matched = std::collections::HashSet::new();
+                let mut samples = Vec::new();
+                for sample in lhs {
+                    let signature = signature(&binary.matching, &sample.labels);
+                    let Some(other) = matches.get(&signature) else {
+                        continue;
+                    };
+                    if !matched.insert(signature) {
+                        return Err(QueryError::Execution(
+                            "many-to-many matching not allowed: matching labels must be unique on one side"
+                                .to_string(),
+                        ));
+                    }
+                    let Some(value) = apply(op, sample.value, other.value, binary.return_bool) else {
+                        continue;
+                    };
+                    samples.push(Sample {
+                        labels: matched_labels(&binary.matching, result_labels(sample.labels)),
+                        value,
+                    });
+                }
+                Value::Vector(samples)
+            }
+        })
+    }
+}
+
+/// `lhs op rhs`, or `None` when a comparison without `bool` filters the
+/// sample out.
+fn apply(op: BinaryOp, lhs: f64, rhs: f64, return_bool: bool) -> Option<f64> {
+    let comparison = match op {
+        BinaryOp::Add => return Some(lhs + rhs),
+        BinaryOp::Sub => return Some(lhs - rhs),
+        BinaryOp::Mul => return Some(lhs * rhs),
+        BinaryOp::Div => return Some(lhs / rhs),
+        BinaryOp::Mod => return Some(lhs % rhs),
+        BinaryOp::Pow => return Some(lhs.powf(rhs)),
+        BinaryOp::Eq => lhs == rhs,
+        BinaryOp::Ne => lhs != rhs,
+        BinaryOp::Gt => lhs > rhs,
+        BinaryOp::Lt => lhs < rhs,
+        BinaryOp::Ge => lhs >= rhs,
+        BinaryOp::Le => lhs <= rhs,
+        BinaryOp::And | BinaryOp::Or | BinaryOp::Unless => return None,
+    };
+    if return_bool {
+        Some(if comparison { 1.0 } else { 0.0 })
+    } else {
+        comparison.then_some(lhs)
+    }
+}
+
+fn without_name(mut labels: Labels) -> Labels {
+    labels.remove(METRIC_NAME);
+    labels
+}
+
+/// The labels two samples must share to match.
+fn signature(matching: &VectorMatching, labels: &Labels) -> Labels {
+    labels
+        .iter()
+        .filter(|(name, _)| {
+            if matching.on {
+                matching.labels.contains(name)
+            } else {
+                *name != METRIC_NAME && !matching.labels.contains(name)
+            }
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Labels of a one-to-one match: only the `on` labels, or all but the
+/// `ignoring` ones.
+fn matched_labels(matching: &VectorMatching, labels: Labels) -> Labels {
+    if matching.on {
+        signature(matching, &labels)
+    } else {
+        labels
+            .into_iter()
+            .filter(|(name, _)| !matching.labels.contains(name))
+            .collect()
+    }
+}
+
+fn set_operation(op: BinaryOp, matching: &VectorMatching, lhs: Vec<Sample>, rhs: Vec<Sample>) -> Vec<Sample> {
+    let rhs_signatures: std::collections::HashSet<_> = rhs.iter().map(|s| signature(matching, &s.labels)).collect();
+    match op {
+        BinaryOp::And => lhs
+            .into_iter()
+            .filter(|s| rhs_signatures.contains(&signature(matching, &s.labels)))
+            .collect(),
+        BinaryOp::Unless => lhs
+            .into_iter()
+            .filter(|s| !rhs_signatures.contains(&signature(matching, &s.labels)))
+            .collect(),
+        _ => {
+            let lhs_signatures: std::collections::HashSet<_> =
+                lhs.iter().map(|s| signature(matching, &s.labels)).collect();
+            let mut samples = lhs;
+            samples.extend(
+                rhs.into_iter()
+                    .filter(|s| !lhs_signatures.contains(&signature(matching, &s.labels))),
+            );
+            samples
+        }
+    }
+}
+
+/// A range function over one series' samples in `(start, end]`, or `None`
+/// when there are too few samples for it.
+fn over_time(function: Function, samples: &[(i64, f64)], start: i64, end: i64) -> Option<f64> {
+    let values = samples.iter().map(|&(_, value)| value);
+    Some(match function {
+        Function::Rate => extrapolated_rate(samples, start, end, true, true)?,
+        Function::Increase => extrapolated_rate(samples, start, end, true, false)?,
+        Function::Delta => extrapolated_rate(samples, start, end, false, false)?,
+        Function::Irate => {
+            let [.., (previous_at, previous), (last_at, last)] = samples else {
+                return None;
+            };
+            // A counter reset starts again from zero
+            let increase = if last < previous { *last } else { last - previous };
+            increase / ((last_at - previous_at) as f64 / 1000.0)
+        }
+        Function::AvgOverTime => values.sum::<f64>() / samples.len() as f64,
+        Function::MinOverTime => values.fold(f64::NAN, f64::min),
+        Function::MaxOverTime => values.fold(f64::NAN, f64::max),
+        Function::SumOverTime => values.sum(),
+        Function::CountOverTime => samples.len() as f64,
+        Function::LastOverTime => samples.last()?.1,
+        _ => return None,
+    })
+}
+
+/// Prometheus' `rate`, `increase` and `delta`: the change between the first
+/// and last sample, adjusted for counter resets, extrapolated towards the
+/// window's edges by up to half a sample interval when the series does not
+/// start or end close to them.
+fn extrapolated_rate(samples: &[(i64, f64)], start: i64, end: i64, is_counter: bool, is_rate: bool) -> Option<f64> {
+    let (&(first_at, first), &(last_at, last)) = (samples.first()?, samples.last()?);
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let mut result = last - first;
+    if is_counter {
+        for pair in samples.windows(2) {
+            if pair[1].1 < pair[0].1 {
+                result += pair[0].1;
+            }
+        }
+    }
+
+    let seconds = |ms: i64| ms as f64 / 1000.0;
+    let mut to_start = seconds(first_at - start);
+    let to_end = seconds(end - last_at);
+    let sampled = seconds(last_at - first_at);
+    let average_interval = sampled / (samples.len() - 1) as f64;
+
+    // Counters cannot go below zero, so do not extrapolate past that point
+    if is_counter && result > 0.0 && first >= 0.0 {
+        to_start = to_start.min(sampled * (first / result));
+    }
+
+    let threshold = average_interval * 1.1;
+    let mut interval = sampled;
+    interval += if to_start < threshold { to_start } else { average_interval / 2.0 };
+    interval += if to_end < threshold { to_end } else { average_interval / 2.0 };
+
+    result *= interval / sampled;
+    if is_rate {
+        result /= seconds(end - start);
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parser::parse;
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use serde_json::json;
+
+    const MINUTE: i64 = 60_000;
+
+    async fn storage() -> MemoryStorage {
+        let storage = MemoryStorage::with_capacity(100);
+        // A counter per job, sampled every minute, that resets at 5m for web
+        for minute in 0..10 {
+            let at = DateTime::from_timestamp_millis(minute * MINUTE).unwrap();
+            let web = if minute < 5 { minute * 10 } else { (minute - 5) * 10 };
+            for (job, value) in [("api", minute * 60), ("web", web)] {
+                storage
+                    .store_metric("acme", "requests_total", value as f64, "counter", Some(json!({"job": job})), at)
+                    .await
+                    .unwrap();
+            }
+            storage
+                .store_metric("globex", "requests_total", 1e6, "counter", Some(json!({"job": "api"})), at)
+                .await
+                .unwrap();
+        }
+        storage
+    }
+
+    fn vector(value: QueryValue) -> Vec<(String, f64)> {
+        let QueryValue::Vector(_, samples) = value else {
+            panic!("expected a vector, got {:?}", value);
+        };
+        samples
+            .into_iter()
+            .map(|s| (s.labels.get("job").cloned().unwrap_or_default(), s.value))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_instant_queries() {
+        let storage = storage().await;
+        let config = PromqlConfig::default();
+        let engine = Engine::new(&storage, "acme", &config);
+        let at = 9 * MINUTE;
+
+        let query = |text: &str| parse(text).unwrap();
+        let latest = engine.instant(&query("requests_total"), at).await.unwrap();
+        assert_eq!(vector(latest), vec![("api".to_string(), 540.0), ("web".to_string(), 40.0)]);
+
+        let rate = engine.instant(&query("rate(requests_total{job=\"api\"}[5m])"), at).await.unwrap();
+        assert_eq!(vector(rate), vec![("api".to_string(), 1.0)]);
+
+        // The reset at 5m counts as a restart from zero, not a drop of 40;
+        // the 40 seen is then extrapolated over the minute before the window
+        let increase = engine.instant(&query("increase(requests_total{job=\"web\"}[6m])"), at).await.unwrap();
+        let increase = vector(increase);
+        assert_eq!(increase.len(), 1);
+        assert!((increase[0].1 - 48.0).abs() < 1e-9);
+
+        let total = engine.instant(&query("sum(requests_total) / 2"), at).await.unwrap();
+        assert_eq!(vector(total), vec![(String::new(), 290.0)]);
+
+        let filtered = engine.instant(&query("requests_total > 100"), at).await.unwrap();
+        assert_eq!(vector(filtered), vec![("api".to_string(), 540.0)]);
+
+        let ratio = engine
+            .instant(&query("requests_total{job=\"web\"} / ignoring(job) requests_total{job=\"api\"}"), at)
+            .await
+            .unwrap();
+        assert_eq!(vector(ratio), vec![(String::new(), 40.0 / 540.0)]);
+
+        assert_eq!(
+            engine.instant(&query("1 + 2 * 3"), at).await.unwrap(),
+            QueryValue::Scalar(at, 7.0)
+        );
+
+        // Older than the lookback delta, so nothing is current
+        let stale = engine.instant(&query("requests_total"), at + 10 * MINUTE).await.unwrap();
+        assert!(vector(stale).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_range_queries() {
+        let storage = storage().await;
+        let config = PromqlConfig::default();
+        let engine = Engine::new(&storage, "acme", &config);
+
+        let expr = parse("max by (job) (requests_total)").unwrap();
+        let QueryValue::Matrix(series) = engine.range(&expr, 0, 4 * MINUTE, 2 * MINUTE).await.unwrap() else {
+            panic!("expected a matrix");
+        };
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].samples, vec![(0, 0.0), (2 * MINUTE, 120.0), (4 * MINUTE, 240.0)]);
+
+        let matrix = parse("requests_total[5m]").unwrap();
+        assert!(matches!(
+            engine.range(&matrix, 0, MINUTE, MINUTE).await,
+            Err(QueryError::BadData(_))
+        ));
+        assert!(matches!(
+            engine.range(&expr, i64::MIN, i64::MAX, MINUTE).await,
+            Err(QueryError::BadData(_))
+        ));
+
+        let limited = PromqlConfig {
+            max_samples: 5,
+            ..PromqlConfig::default()
+        };
+        let engine = Engine::new(&storage, "acme", &limited);
+        assert!(matches!(
+            engine.range(&expr, 0, 9 * MINUTE, MINUTE).await,
+            Err(QueryError::Execution(_))
+        ));
+    }
+}
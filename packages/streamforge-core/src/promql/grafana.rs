@@ -0,0 +1,302 @@
+use super::parser::METRIC_NAME;
+use super::{authorize, error_response, parse, Engine, Labels, QueryError, QueryValue, Series, ServerState};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Metric names `/search` returns at most.
+const MAX_SEARCH_RESULTS: i64 = 1000;
+
+/// Log lines `/annotations` returns at most.
+const MAX_ANNOTATIONS: i64 = 1000;
+
+/// Grafana's JSON datasource API: `/search` lists metric names, `/query`
+/// evaluates each target as PromQL, and `/annotations` turns logs into
+/// annotations.
+pub(super) fn router() -> Router<ServerState> {
+    Router::new()
+        .route("/", get(|| async { StatusCode::OK }))
+        .route("/search", post(search))
+        .route("/query", post(query))
+        .route("/annotations", post(annotations))
+}
+
+#[derive(Debug, Deserialize)]
+struct TimeRange {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SearchRequest {
+    #[serde(default)]
+    target: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryRequest {
+    range: TimeRange,
+    #[serde(default)]
+    interval_ms: Option<i64>,
+    #[serde(default)]
+    max_data_points: Option<i64>,
+    #[serde(default)]
+    targets: Vec<Target>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Target {
+    #[serde(default)]
+    target: String,
+    /// `timeserie` (the default) or `table`.
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    hide: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnnotationRequest {
+    range: TimeRange,
+    /// Echoed back with every annotation, as Grafana expects.
+    annotation: Value,
+}
+
+/// Metric names containing the search text.
+async fn search(State(state): State<ServerState>, headers: HeaderMap, Json(request): Json<SearchRequest>) -> Response {
+    let tenant_id = match authorize(&state, &headers) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return error_response(e),
+    };
+    match state.storage.list_metric_names(tenant_id, MAX_SEARCH_RESULTS).await {
+        Ok(names) => {
+            let names: Vec<_> = names.into_iter().filter(|name| name.contains(&request.target)).collect();
+            Json(names).into_response()
+        }
+        Err(e) => error_response(QueryError::Internal(e)),
+    }
+}
+
+async fn query(State(state): State<ServerState>, headers: HeaderMap, Json(request): Json<QueryRequest>) -> Response {
+    match run_query(&state, &headers, request).await {
+        Ok(results) => Json(results).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn run_query(state: &ServerState, headers: &HeaderMap, request: QueryRequest) -> Result<Vec<Value>, QueryError> {
+    let tenant_id = authorize(state, headers)?;
+    let engine = Engine::new(state.storage.as_ref(), tenant_id, &state.config);
+    let (start, end) = (request.range.from.timestamp_millis(), request.range.to.timestamp_millis());
+    let step = step(start, end, request.interval_ms, request.max_data_points, state.config.max_points);
+
+    let mut results = Vec::new();
+    for target in request.targets.iter().filter(|t| !t.hide && !t.target.trim().is_empty()) {
+        let expr = parse(&target.target)
+            .map_err(|e| QueryError::BadData(format!("invalid target {:?}: {}", target.target, e)))?;
+        match target.kind.as_deref().unwrap_or("timeserie") {
+            "timeserie" => results.extend(timeseries(&target.target, engine.range(&expr, start, end, step).await?)),
+            "table" => results.push(table(&target.target, engine.instant(&expr, end).await?)),
+            other => return Err(QueryError::BadData(format!("unknown target type {:?}", other))),
+        }
+    }
+    Ok(results)
+}
+
+/// Grafana's interval, widened so that neither `max_data_points` nor
+/// `max_points` steps are exceeded.
+fn step(start: i64, end: i64, interval_ms: Option<i64>, max_data_points: Option<i64>, max_points: usize) -> i64 {
+    let range = (end - start).max(0);
+    let points = max_data_points.filter(|&points| points > 0).unwrap_or(max_points as i64).min(max_points as i64);
+    let fewest = range / points + 1;
+    interval_ms.unwrap_or(0).max(fewest).max(1)
+}
+
+/// A series in the JSON datasource's time series format, with values
+/// first and NaN as null.
+fn timeseries(target: &str, value: QueryValue) -> Vec<Value> {
+    let series = match value {
+        QueryValue::Matrix(series) => series,
+        QueryValue::Vector(time, samples) => samples
+            .into_iter()
+            .map(|sample| Series {
+                labels: sample.labels,
+                samples: vec![(time, sample.value)],
+            })
+            .collect(),
+        QueryValue::Scalar(time, value) => vec![Series {
+            labels: Labels::new(),
+            samples: vec![(time, value)],
+        }],
+    };
+    series
+        .into_iter()
+        .map(|series| {
+            let datapoints: Vec<_> = series
+                .samples
+                .iter()
+                .map(|&(time, value)| json!([value.is_finite().then_some(value), time]))
+                .collect();
+            json!({"target": series_name(target, &series.labels), "datapoints": datapoints})
+        })
+        .collect()
+}
+
+/// A target's latest values as a table, one row per series.
+fn table(target: &str, value: QueryValue) -> Value {
+    let rows: Vec<_> = match value {
+        QueryValue::Scalar(time, value) => vec![json!([time, target, value.is_finite().then_some(value)])],
+        QueryValue::Vector(time, samples) => samples
+            .iter()
+            .map(|s| json!([time, series_name(target, &s.labels), s.value.is_finite().then_some(s.value)]))
+            .collect(),
+        QueryValue::Matrix(series) => series
+            .iter()
+            .filter_map(|s| {
+                let &(time, value) = s.samples.last()?;
+                Some(json!([time, series_name(target, &s.labels), value.is_finite().then_some(value)]))
+            })
+            .collect(),
+    };
+    json!({
+        "type": "table",
+        "columns": [
+            {"text": "Time", "type": "time"},
+            {"text": "Metric", "type": "string"},
+            {"text": "Value", "type": "number"},
+        ],
+        "rows": rows,
+    })
+}
+
+/// `name{label="value", ...}` as Prometheus prints series, or the target
+/// itself for a series without labels.
+fn series_name(target: &str, labels: &Labels) -> String {
+    let name = labels.get(METRIC_NAME).map(String::as_str).unwrap_or_default();
+    let pairs: Vec<_> = labels
+        .iter()
+        .filter(|(label, _)| *label != METRIC_NAME)
+        .map(|(label, value)| format!("{}={:?}", label, value))
+        .collect();
+    match (name, pairs.is_empty()) {
+        ("", true) => target.to_string(),
+        (name, true) => name.to_string(),
+        (name, false) => format!("{}{{{}}}", name, pairs.join(", ")),
+    }
+}
+
+/// Logs of the range as annotations. The annotation query narrows them
+/// with space-separated `level=...` and `service=...` filters.
+async fn annotations(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(request): Json<AnnotationRequest>,
+) -> Response {
+    match run_annotations(&state, &headers, request).await {
+        Ok(annotations) => Json(annotations).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn run_annotations(
+    state: &ServerState,
+    headers: &HeaderMap,
+    request: AnnotationRequest,
+) -> Result<Vec<Value>, QueryError> {
+    let tenant_id = authorize(state, headers)?;
+    let query = request.annotation.get("query").and_then(Value::as_str).unwrap_or_default();
+    let (level, service) = log_filters(query)?;
+
+    let logs = state
+        .storage
+        .get_logs(
+            tenant_id,
+            level.as_deref(),
+            service.as_deref(),
+            request.range.from,
+            request.range.to,
+            Some(MAX_ANNOTATIONS),
+        )
+        .await
+        .map_err(QueryError::Internal)?;
+    Ok(logs
+        .into_iter()
+        .map(|log| {
+            let mut tags = vec![log.level.clone()];
+            tags.extend(log.service_name.clone());
+            json!({
+                "annotation": request.annotation,
+                "time": log.timestamp.timestamp_millis(),
+                "title": log.service_name.as_deref().unwrap_or(&log.level),
+                "text": log.message,
+                "tags": tags,
+            })
+        })
+        .collect())
+}
+
+/// The level and service an annotation query filters logs by.
+fn log_filters(query: &str) -> Result<(Option<String>, Option<String>), QueryError> {
+    let (mut level, mut service) = (None, None);
+    for filter in query.split_whitespace() {
+        match filter.split_once('=') {
+            Some(("level", value)) => level = Some(value.to_string()),
+            Some(("service", value)) => service = Some(value.to_string()),
+            _ => {
+                return Err(QueryError::BadData(format!(
+                    "invalid annotation filter {:?}; expected level=... or service=...",
+                    filter
+                )))
+            }
+        }
+    }
+    Ok((level, service))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeseries_use_prometheus_series_names() {
+        let labels = Labels::from([
+            (METRIC_NAME.to_string(), "requests_total".to_string()),
+            ("job".to_string(), "api".to_string()),
+        ]);
+        let matrix = QueryValue::Matrix(vec![Series {
+            labels,
+            samples: vec![(1_000, 2.0), (2_000, f64::NAN)],
+        }]);
+        assert_eq!(
+            timeseries("requests_total", matrix),
+            vec![json!({
+                "target": "requests_total{job=\"api\"}",
+                "datapoints": [[2.0, 1_000], [null, 2_000]],
+            })]
+        );
+        assert_eq!(series_name("1 + 1", &Labels::new()), "1 + 1");
+    }
+
+    #[test]
+    fn test_step_respects_the_point_limits() {
+        assert_eq!(step(0, 60_000, Some(1_000), Some(1_000), 11_000), 1_000);
+        assert_eq!(step(0, 60_000, Some(1_000), Some(10), 11_000), 6_001);
+        assert_eq!(step(0, 3_600_000, None, None, 100), 36_001);
+    }
+
+    #[test]
+    fn test_log_filters() {
+        assert_eq!(
+            log_filters("level=error service=api").unwrap(),
+            (Some("error".to_string()), Some("api".to_string()))
+        );
+        assert_eq!(log_filters("").unwrap(), (None, None));
+        assert!(log_filters("host=web-1").is_err());
+    }
+}
@@ -0,0 +1,288 @@
+mod eval;
+mod grafana;
+mod parser;
+
+pub use eval::{Engine, Labels, QueryError, QueryValue, Sample, Series};
+pub use parser::{parse, parse_duration, Expr};
+
+use anyhow::Result;
+use axum::extract::{Form, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use std::future::Future;
+use std::sync::Arc;
+use tracing::{debug, error, info};
+
+use crate::config::PromqlConfig;
+use crate::metrics;
+use crate::storage::StorageBackend;
+
+/// Header naming the tenant to query, as in Cortex and Mimir.
+pub const TENANT_HEADER: &str = "x-scope-orgid";
+
+/// Parameters of `/api/v1/query` and `/api/v1/query_range`, from the URL
+/// or a form body. Times are RFC 3339 or Unix seconds; `step` is seconds or
+/// a duration such as `30s`.
+#[derive(Debug, Default, Deserialize)]
+struct QueryParams {
+    query: Option<String>,
+    time: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    step: Option<String>,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    config: Arc<PromqlConfig>,
+    storage: Arc<dyn StorageBackend>,
+}
+
+/// Serves `/api/v1/query` and `/api/v1/query_range` until `shutdown`
+/// resolves, answering both GET and form-encoded POST requests like the
+/// Prometheus HTTP API, and Grafana's JSON datasource API under `/grafana`.
+pub async fn start_server<F>(config: PromqlConfig, storage: Arc<dyn StorageBackend>, shutdown: F) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let addr = config.address()?;
+    let state = ServerState {
+        config: Arc::new(config),
+        storage,
+    };
+    let app = Router::new()
+        .route("/api/v1/query", get(instant_get).post(instant_post))
+        .route("/api/v1/query_range", get(range_get).post(range_post))
+        .nest("/grafana", grafana::router())
+        .with_state(state);
+
+    info!("PromQL API listening on {}", addr);
+
+    axum::Server::try_bind(&addr)
+        .map_err(|e| anyhow::anyhow!("Failed to bind PromQL API to {}: {}", addr, e))?
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(|e| anyhow::anyhow!("PromQL API error: {}", e))?;
+
+    info!("PromQL API stopped");
+    Ok(())
+}
+
+async fn instant_get(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Query(params): Query<QueryParams>,
+) -> Response {
+    respond(evaluate(&state, &headers, params, false).await)
+}
+
+async fn instant_post(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Form(params): Form<QueryParams>,
+) -> Response {
+    respond(evaluate(&state, &headers, params, false).await)
+}
+
+async fn range_get(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Query(params): Query<QueryParams>,
+) -> Response {
+    respond(evaluate(&state, &headers, params, true).await)
+}
+
+async fn range_post(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Form(params): Form<QueryParams>,
+) -> Response {
+    respond(evaluate(&state, &headers, params, true).await)
+}
+
+/// Checks the bearer token and returns the tenant the request is for.
+fn authorize<'a>(state: &'a ServerState, headers: &'a HeaderMap) -> Result<&'a str, QueryError> {
+    if let Some(expected) = &state.config.api_key {
+        let given = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !metrics::constant_time_eq(given.as_bytes(), expected.as_bytes()) {
+            return Err(QueryError::Unauthorized);
+        }
+    }
+    Ok(headers
+        .get(TENANT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .unwrap_or(&state.config.default_tenant))
+}
+
+async fn evaluate(
+    state: &ServerState,
+    headers: &HeaderMap,
+    params: QueryParams,
+    range: bool,
+) -> Result<QueryValue, QueryError> {
+    let tenant_id = authorize(state, headers)?;
+    let query = params
+        .query
+        .ok_or_else(|| QueryError::BadData("query parameter is required".to_string()))?;
+    let expr = parse(&query).map_err(|e| QueryError::BadData(format!("invalid parameter \"query\": {}", e)))?;
+    debug!("Evaluating PromQL for tenant {}: {}", tenant_id, query);
+
+    let engine = Engine::new(state.storage.as_ref(), tenant_id, &state.config);
+    if range {
+        let start = parse_time(params.start.as_deref(), "start")?;
+        let end = parse_time(params.end.as_deref(), "end")?;
+        let step = parse_step(params.step.as_deref())?;
+        engine.range(&expr, start, end, step).await
+    } else {
+        let time = match params.time {
+            Some(time) => parse_time(Some(&time), "time")?,
+            None => chrono::Utc::now().timestamp_millis(),
+        };
+        engine.instant(&expr, time).await
+    }
+}
+
+/// A time parameter in ms, within the range chrono can represent.
+fn parse_time(value: Option<&str>, name: &str) -> Result<i64, QueryError> {
+    let value = value.ok_or_else(|| QueryError::BadData(format!("{} parameter is required", name)))?;
+    if let Ok(seconds) = value.parse::<f64>() {
+        if seconds.is_finite() {
+            // The cast saturates at the i64 bounds, which are out of chrono's range too
+            let ms = (seconds * 1000.0).round() as i64;
+            return match chrono::DateTime::from_timestamp_millis(ms) {
+                Some(_) => Ok(ms),
+                None => Err(QueryError::BadData(format!(
+                    "invalid parameter {:?}: {:?} is out of the representable time range",
+                    name, value
+                ))),
+            };
+        }
+    }
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|time| time.timestamp_millis())
+        .map_err(|_| QueryError::BadData(format!("invalid parameter {:?}: cannot parse {:?} to a time", name, value)))
+}
+
+/// The `step` parameter in ms.
+fn parse_step(value: Option<&str>) -> Result<i64, QueryError> {
+    let value = value.ok_or_else(|| QueryError::BadData("step parameter is required".to_string()))?;
+    let step = match value.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() => (seconds * 1000.0).round() as i64,
+        Ok(_) => 0,
+        Err(_) => parse_duration(value)
+            .map(|step| step.as_millis() as i64)
+            .map_err(|_| QueryError::BadData(format!("invalid parameter \"step\": cannot parse {:?}", value)))?,
+    };
+    if step <= 0 {
+        return Err(QueryError::BadData(
+            "zero or negative query resolution step widths are not accepted".to_string(),
+        ));
+    }
+    Ok(step)
+}
+
+fn respond(result: Result<QueryValue, QueryError>) -> Response {
+    match result {
+        Ok(value) => Json(json!({"status": "success", "data": data(value)})).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+fn error_response(e: QueryError) -> Response {
+    let (status, error_type) = match &e {
+        QueryError::BadData(_) => (StatusCode::BAD_REQUEST, "bad_data"),
+        QueryError::Execution(_) => (StatusCode::UNPROCESSABLE_ENTITY, "execution"),
+        QueryError::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized"),
+        QueryError::Internal(_) => {
+            error!("Query failed: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal")
+        }
+    };
+    let body = json!({"status": "error", "errorType": error_type, "error": e.to_string()});
+    (status, Json(body)).into_response()
+}
+
+/// The `data` of a successful response, with timestamps in seconds and
+/// values as strings.
+fn data(value: QueryValue) -> serde_json::Value {
+    let point = |time: i64, value: f64| json!([time as f64 / 1000.0, format_value(value)]);
+    match value {
+        QueryValue::Scalar(time, value) => json!({"resultType": "scalar", "result": point(time, value)}),
+        QueryValue::Vector(time, samples) => json!({
+            "resultType": "vector",
+            "result": samples
+                .into_iter()
+                .map(|sample| json!({"metric": sample.labels, "value": point(time, sample.value)}))
+                .collect::<Vec<_>>(),
+        }),
+        QueryValue::Matrix(series) => json!({
+            "resultType": "matrix",
+            "result": series
+                .into_iter()
+                .map(|series| {
+                    let values: Vec<_> = series.samples.iter().map(|&(time, value)| point(time, value)).collect();
+                    json!({"metric": series.labels, "values": values})
+                })
+                .collect::<Vec<_>>(),
+        }),
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        (if value > 0.0 { "+Inf" } else { "-Inf" }).to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parameters() {
+        assert_eq!(parse_time(Some("1700000000.5"), "time").unwrap(), 1_700_000_000_500);
+        assert_eq!(parse_time(Some("2023-11-14T22:13:20Z"), "time").unwrap(), 1_700_000_000_000);
+        assert!(parse_time(None, "start").is_err());
+        // start=-1e300&end=1e300
+        assert!(matches!(parse_time(Some("-1e300"), "start"), Err(QueryError::BadData(_))));
+        assert!(matches!(parse_time(Some("1e300"), "end"), Err(QueryError::BadData(_))));
+        assert_eq!(parse_step(Some("15")).unwrap(), 15_000);
+        assert_eq!(parse_step(Some("1m")).unwrap(), 60_000);
+        assert!(parse_step(Some("0")).is_err());
+    }
+
+    #[test]
+    fn test_response_data() {
+        let labels = Labels::from([("__name__".to_string(), "up".to_string())]);
+        let vector = QueryValue::Vector(
+            1_500,
+            vec![Sample {
+                labels,
+                value: f64::INFINITY,
+            }],
+        );
+        assert_eq!(
+            data(vector),
+            json!({"resultType": "vector", "result": [{"metric": {"__name__": "up"}, "value": [1.5, "+Inf"]}]})
+        );
+        assert_eq!(
+            data(QueryValue::Scalar(0, 0.25)),
+            json!({"resultType": "scalar", "result": [0.0, "0.25"]})
+        );
+    }
+}
@@ -0,0 +1,867 @@
+use anyhow::Result;
+use regex::Regex;
+use std::time::Duration;
+
+/// Label holding a series' metric name.
+pub const METRIC_NAME: &str = "__name__";
+
+/// A parsed PromQL expression.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Number(f64),
+    /// Instant vector selector: the latest sample of every matching series.
+    Vector(Selector),
+    /// Range vector selector: every sample of the matching series within
+    /// the range; only valid as a function argument or a whole query.
+    Matrix(Selector, Duration),
+    Call(Function, Vec<Expr>),
+    Aggregate(Aggregate),
+    Binary(Box<Binary>),
+    Negate(Box<Expr>),
+}
+
+/// What an expression evaluates to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Scalar,
+    Vector,
+    Matrix,
+}
+
+impl ValueKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValueKind::Scalar => "scalar",
+            ValueKind::Vector => "instant vector",
+            ValueKind::Matrix => "range vector",
+        }
+    }
+}
+
+impl Expr {
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            Expr::Number(_) => ValueKind::Scalar,
+            Expr::Vector(_) | Expr::Aggregate(_) => ValueKind::Vector,
+            Expr::Matrix(..) => ValueKind::Matrix,
+            Expr::Call(function, _) => function.returns(),
+            Expr::Binary(binary) => {
+                if binary.lhs.kind() == ValueKind::Scalar && binary.rhs.kind() == ValueKind::Scalar {
+                    ValueKind::Scalar
+                } else {
+                    ValueKind::Vector
+                }
+            }
+            Expr::Negate(expr) => expr.kind(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Selector {
+    /// Numbers selectors in the order they appear, so an evaluation can
+    /// load each one's series once.
+    pub id: usize,
+    pub matchers: Vec<Matcher>,
+    pub offset: Duration,
+}
+
+impl Selector {
+    /// The metric name when the selector picks exactly one.
+    pub fn metric_name(&self) -> Option<&str> {
+        self.matchers
+            .iter()
+            .find(|m| m.label == METRIC_NAME && m.op == MatchOp::Equal)
+            .map(|m| m.value.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchOp {
+    Equal,
+    NotEqual,
+    Regex,
+    NotRegex,
+}
+
+/// Matches a label against a value or a fully anchored regex. A missing
+/// label matches as the empty string, as in Prometheus.
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    pub label: String,
+    pub op: MatchOp,
+    pub value: String,
+    regex: Option<Regex>,
+}
+
+impl Matcher {
+    pub fn new(label: impl Into<String>, op: MatchOp, value: impl Into<String>) -> Result<Self> {
+        let value = value.into();
+        let regex = match op {
+            MatchOp::Regex | MatchOp::NotRegex => Some(
+                Regex::new(&format!("^(?:{})$", value))
+                    .map_err(|e| anyhow::anyhow!("Invalid regex {:?}: {}", value, e))?,
+            ),
+            MatchOp::Equal | MatchOp::NotEqual => None,
+        };
+        Ok(Self {
+            label: label.into(),
+            op,
+            value,
+            regex,
+        })
+    }
+
+    pub fn matches(&self, value: Option<&str>) -> bool {
+        let value = value.unwrap_or_default();
+        match (self.op, &self.regex) {
+            (MatchOp::Equal, _) => value == self.value,
+            (MatchOp::NotEqual, _) => value != self.value,
+            (MatchOp::Regex, Some(regex)) => regex.is_match(value),
+            (MatchOp::NotRegex, Some(regex)) => !regex.is_match(value),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Rate,
+    Irate,
+    Increase,
+    Delta,
+    AvgOverTime,
+    MinOverTime,
+    MaxOverTime,
+    SumOverTime,
+    CountOverTime,
+    LastOverTime,
+    Abs,
+    Ceil,
+    Floor,
+    Round,
+    Sqrt,
+    ClampMin,
+    ClampMax,
+    Time,
+    Vector,
+    Scalar,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "rate" => Function::Rate,
+            "irate" => Function::Irate,
+            "increase" => Function::Increase,
+            "delta" => Function::Delta,
+            "avg_over_time" => Function::AvgOverTime,
+            "min_over_time" => Function::MinOverTime,
+            "max_over_time" => Function::MaxOverTime,
+            "sum_over_time" => Function::SumOverTime,
+            "count_over_time" => Function::CountOverTime,
+            "last_over_time" => Function::LastOverTime,
+            "abs" => Function::Abs,
+            "ceil" => Function::Ceil,
+            "floor" => Function::Floor,
+            "round" => Function::Round,
+            "sqrt" => Function::Sqrt,
+            "clamp_min" => Function::ClampMin,
+            "clamp_max" => Function::ClampMax,
+            "time" => Function::Time,
+            "vector" => Function::Vector,
+            "scalar" => Function::Scalar,
+            _ => return None,
+        })
+    }
+
+    /// Argument kinds the function takes.
+    fn arguments(self) -> &'static [ValueKind] {
+        use ValueKind::*;
+        match self {
+            Function::Rate
+            | Function::Irate
+            | Function::Increase
+            | Function::Delta
+            | Function::AvgOverTime
+            | Function::MinOverTime
+            | Function::MaxOverTime
+            | Function::SumOverTime
+            | Function::CountOverTime
+            | Function::LastOverTime => &[Matrix],
+            Function::Abs | Function::Ceil | Function::Floor | Function::Round | Function::Sqrt => &[Vector],
+            Function::ClampMin | Function::ClampMax => &[Vector, Scalar],
+            Function::Time => &[],
+            Function::Vector => &[Scalar],
+            Function::Scalar => &[Vector],
+        }
+    }
+
+    fn returns(self) -> ValueKind {
+        match self {
+            Function::Time | Function::Scalar => ValueKind::Scalar,
+            _ => ValueKind::Vector,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateOp {
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Count,
+    Topk,
+    Bottomk,
+}
+
+impl AggregateOp {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "sum" => AggregateOp::Sum,
+            "avg" => AggregateOp::Avg,
+            "min" => AggregateOp::Min,
+            "max" => AggregateOp::Max,
+            "count" => AggregateOp::Count,
+            "topk" => AggregateOp::Topk,
+            "bottomk" => AggregateOp::Bottomk,
+            _ => return None,
+        })
+    }
+
+    fn takes_parameter(self) -> bool {
+        matches!(self, AggregateOp::Topk | AggregateOp::Bottomk)
+    }
+}
+
+/// Labels an aggregation groups by. `sum(x)` is `sum by () (x)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Grouping {
+    By(Vec<String>),
+    Without(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Aggregate {
+    pub op: AggregateOp,
+    pub parameter: Option<Box<Expr>>,
+    pub grouping: Grouping,
+    pub expr: Box<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    And,
+    Or,
+    Unless,
+}
+
+impl BinaryOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And | BinaryOp::Unless => 2,
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Gt | BinaryOp::Lt | BinaryOp::Ge | BinaryOp::Le => 3,
+            BinaryOp::Add | BinaryOp::Sub => 4,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 5,
+            BinaryOp::Pow => 6,
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        self.precedence() == 3
+    }
+
+    pub fn is_set(self) -> bool {
+        matches!(self, BinaryOp::And | BinaryOp::Or | BinaryOp::Unless)
+    }
+}
+
+/// Labels two vectors' samples are matched on: only `labels` with `on`,
+/// or all but `labels` with `ignoring`. The metric name never counts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VectorMatching {
+    pub on: bool,
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Binary {
+    pub op: BinaryOp,
+    pub lhs: Expr,
+    pub rhs: Expr,
+    /// `bool` modifier: comparisons return 0 or 1 instead of filtering.
+    pub return_bool: bool,
+    pub matching: VectorMatching,
+}
+
+/// Parses the PromQL subset StreamForge evaluates: selectors with label
+/// matchers and offsets, number literals, arithmetic, comparison and set
+/// operators with `on`/`ignoring`, the aggregations of [`AggregateOp`] and
+/// the functions of [`Function`]. Subqueries, `group_left`/`group_right`
+/// and `@` modifiers are rejected. Metric names may contain dots.
+pub fn parse(input: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        input,
+        pos: 0,
+        selectors: 0,
+    };
+    let expr = parser.expr(0)?;
+    parser.skip_whitespace();
+    if parser.pos < input.len() {
+        return Err(parser.error("unexpected input"));
+    }
+    Ok(expr)
+}
+
+/// Parses a Prometheus duration such as `5m` or `1h30m`.
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let mut parser = Parser {
+        input: text,
+        pos: 0,
+        selectors: 0,
+    };
+    let duration = parser.duration()?;
+    if parser.pos < text.len() {
+        return Err(anyhow::anyhow!("Invalid duration {:?}", text));
+    }
+    Ok(duration)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    selectors: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow::anyhow!("{} at position {}", message, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if !trimmed.starts_with('#') {
+                return;
+            }
+            self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {:?}", token)))
+        }
+    }
+
+    /// The identifier at the cursor, without consuming it.
+    fn peek_identifier(&mut self) -> Option<&'a str> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let mut chars = rest.char_indices();
+        match chars.next() {
+            Some((_, c)) if c.is_ascii_alphabetic() || c == '_' || c == ':' => {}
+            _ => return None,
+        }
+        let end = chars
+            .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_' || c == ':' || c == '.'))
+            .map_or(rest.len(), |(i, _)| i);
+        Some(&rest[..end])
+    }
+
+    fn identifier(&mut self) -> Result<&'a str> {
+        let identifier = self.peek_identifier().ok_or_else(|| self.error("expected an identifier"))?;
+        self.pos += identifier.len();
+        Ok(identifier)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_identifier() == Some(keyword) {
+            self.pos += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        while let Some((op, len)) = self.peek_binary_op() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.pos += len;
+            let return_bool = self.keyword("bool");
+            let matching = self.vector_matching()?;
+            // `^` is right-associative, the others left-associative
+            let next = if op == BinaryOp::Pow { op.precedence() } else { op.precedence() + 1 };
+            let rhs = self.expr(next)?;
+            lhs = self.binary(op, lhs, rhs, return_bool, matching)?;
+        }
+        Ok(lhs)
+    }
+
+    fn peek_binary_op(&mut self) -> Option<(BinaryOp, usize)> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let symbols = [
+            ("==", BinaryOp::Eq),
+            ("!=", BinaryOp::Ne),
+            (">=", BinaryOp::Ge),
+            ("<=", BinaryOp::Le),
+            ("+", BinaryOp::Add),
+            ("-", BinaryOp::Sub),
+            ("*", BinaryOp::Mul),
+            ("/", BinaryOp::Div),
+            ("%", BinaryOp::Mod),
+            ("^", BinaryOp::Pow),
+            (">", BinaryOp::Gt),
+            ("<", BinaryOp::Lt),
+        ];
+        if let Some(&(symbol, op)) = symbols.iter().find(|(symbol, _)| rest.starts_with(symbol)) {
+            return Some((op, symbol.len()));
+        }
+        match self.peek_identifier()? {
+            "and" => Some((BinaryOp::And, 3)),
+            "or" => Some((BinaryOp::Or, 2)),
+            "unless" => Some((BinaryOp::Unless, 6)),
+            _ => None,
+        }
+    }
+
+    fn vector_matching(&mut self) -> Result<VectorMatching> {
+        let on = if self.keyword("on") {
+            true
+        } else if self.keyword("ignoring") {
+            false
+        } else {
+            return Ok(VectorMatching::default());
+        };
+        let labels = self.label_list()?;
+        if self.keyword("group_left") || self.keyword("group_right") {
+            return Err(self.error("group_left and group_right are not supported"));
+        }
+        Ok(VectorMatching { on, labels })
+    }
+
+    fn binary(
+        &self,
+        op: BinaryOp,
+        lhs: Expr,
+        rhs: Expr,
+        return_bool: bool,
+        matching: VectorMatching,
+    ) -> Result<Expr> {
+        let (lhs_kind, rhs_kind) = (lhs.kind(), rhs.kind());
+        if lhs_kind == ValueKind::Matrix || rhs_kind == ValueKind::Matrix {
+            return Err(self.error("binary operators need scalar or instant vector operands"));
+        }
+        let scalars = lhs_kind == ValueKind::Scalar && rhs_kind == ValueKind::Scalar;
+        if return_bool && !op.is_comparison() {
+            return Err(self.error("bool modifier can only be used on comparison operators"));
+        }
+        if op.is_comparison() && scalars && !return_bool {
+            return Err(self.error("comparisons between scalars must use the bool modifier"));
+        }
+        if op.is_set() && (lhs_kind != ValueKind::Vector || rhs_kind != ValueKind::Vector) {
+            return Err(self.error("set operators need instant vectors on both sides"));
+        }
+        let has_matching = matching != VectorMatching::default();
+        if has_matching && (lhs_kind != ValueKind::Vector || rhs_kind != ValueKind::Vector) {
+            return Err(self.error("vector matching only applies between two instant vectors"));
+        }
+        Ok(Expr::Binary(Box::new(Binary {
+            op,
+            lhs,
+            rhs,
+            return_bool,
+            matching,
+        })))
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("-") {
+            // Binds looser than `^`, so `-2 ^ 2` is -4
+            let expr = self.expr(BinaryOp::Pow.precedence())?;
+            if expr.kind() == ValueKind::Matrix {
+                return Err(self.error("unary minus needs a scalar or instant vector"));
+            }
+            return Ok(match expr {
+                Expr::Number(value) => Expr::Number(-value),
+                expr => Expr::Negate(Box::new(expr)),
+            });
+        }
+        if self.eat("+") {
+            return self.expr(BinaryOp::Pow.precedence());
+        }
+        let expr = self.primary()?;
+        self.postfix(expr)
+    }
+
+    /// A range and an offset after a selector.
+    fn postfix(&mut self, expr: Expr) -> Result<Expr> {
+        let mut expr = expr;
+        if self.peek() == Some('[') {
+            let Expr::Vector(selector) = expr else {
+                return Err(self.error("ranges only apply to vector selectors; subqueries are not supported"));
+            };
+            self.expect("[")?;
+            self.skip_whitespace();
+            let range = self.duration()?;
+            if self.peek() == Some(':') {
+                return Err(self.error("subqueries are not supported"));
+            }
+            self.expect("]")?;
+            expr = Expr::Matrix(selector, range);
+        }
+        if self.keyword("offset") {
+            self.skip_whitespace();
+            let offset = self.duration()?;
+            match &mut expr {
+                Expr::Vector(selector) | Expr::Matrix(selector, _) => selector.offset = offset,
+                _ => return Err(self.error("offset only applies to selectors")),
+            }
+        }
+        if self.peek() == Some('@') {
+            return Err(self.error("@ modifiers are not supported"));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some('(') => {
+                self.expect("(")?;
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some('{') => self.selector(None),
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number().map(Expr::Number),
+            Some(_) => {
+                let name = self.identifier()?;
+                match name.to_ascii_lowercase().as_str() {
+                    "inf" => return Ok(Expr::Number(f64::INFINITY)),
+                    "nan" => return Ok(Expr::Number(f64::NAN)),
+                    _ => {}
+                }
+                if let Some(op) = AggregateOp::parse(name) {
+                    if matches!(self.peek(), Some('(')) || matches!(self.peek_identifier(), Some("by" | "without")) {
+                        return self.aggregate(op);
+                    }
+                }
+                if self.peek() == Some('(') {
+                    let function =
+                        Function::parse(name).ok_or_else(|| self.error(&format!("unknown function {:?}", name)))?;
+                    return self.call(function, name);
+                }
+                self.selector(Some(name))
+            }
+            None => Err(self.error("unexpected end of query")),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let bytes = rest.as_bytes();
+        let mut end = 0;
+        while end < bytes.len() && (bytes[end].is_ascii_digit() || bytes[end] == b'.') {
+            end += 1;
+        }
+        if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+            let mut exponent = end + 1;
+            if exponent < bytes.len() && (bytes[exponent] == b'+' || bytes[exponent] == b'-') {
+                exponent += 1;
+            }
+            if exponent < bytes.len() && bytes[exponent].is_ascii_digit() {
+                end = exponent;
+                while end < bytes.len() && bytes[end].is_ascii_digit() {
+                    end += 1;
+                }
+            }
+        }
+        let value = rest[..end]
+            .parse::<f64>()
+            .map_err(|_| self.error(&format!("invalid number {:?}", &rest[..end])))?;
+        self.pos += end;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        let mut chars = self.rest().char_indices();
+        let quote = match chars.next() {
+            Some((_, quote @ ('"' | '\'' | '`'))) => quote,
+            _ => return Err(self.error("expected a string")),
+        };
+        let mut value = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => {
+                    self.pos += i + c.len_utf8();
+                    return Ok(value);
+                }
+                // Backquoted strings are raw
+                '\\' if quote != '`' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn duration(&mut self) -> Result<Duration> {
+        let start = self.pos;
+        let mut total = Duration::ZERO;
+        loop {
+            let rest = self.rest();
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            if digits == 0 {
+                break;
+            }
+            let amount: u64 = rest[..digits]
+                .parse()
+                .map_err(|_| self.error("duration is too long"))?;
+            let unit = &rest[digits..];
+            let (seconds, unit_len) = if unit.starts_with("ms") {
+                (0.001, 2)
+            } else {
+                match unit.chars().next() {
+                    Some('s') => (1.0, 1),
+                    Some('m') => (60.0, 1),
+                    Some('h') => (3600.0, 1),
+                    Some('d') => (86_400.0, 1),
+                    Some('w') => (604_800.0, 1),
+                    Some('y') => (31_536_000.0, 1),
+                    _ => return Err(self.error("expected a duration unit (ms, s, m, h, d, w or y)")),
+                }
+            };
+            total += Duration::from_secs_f64(amount as f64 * seconds);
+            self.pos += digits + unit_len;
+        }
+        if self.pos == start || total.is_zero() {
+            return Err(self.error("expected a positive duration such as 5m"));
+        }
+        Ok(total)
+    }
+
+    fn label_list(&mut self) -> Result<Vec<String>> {
+        self.expect("(")?;
+        let mut labels = Vec::new();
+        while !self.eat(")") {
+            labels.push(self.identifier()?.to_string());
+            if !self.eat(",") {
+                self.expect(")")?;
+                break;
+            }
+        }
+        Ok(labels)
+    }
+
+    fn grouping(&mut self) -> Result<Option<Grouping>> {
+        if self.keyword("by") {
+            Ok(Some(Grouping::By(self.label_list()?)))
+        } else if self.keyword("without") {
+            Ok(Some(Grouping::Without(self.label_list()?)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn aggregate(&mut self, op: AggregateOp) -> Result<Expr> {
+        let before = self.grouping()?;
+        self.expect("(")?;
+        let parameter = if op.takes_parameter() {
+            let parameter = self.expr(0)?;
+            if parameter.kind() != ValueKind::Scalar {
+                return Err(self.error("the parameter of topk and bottomk must be a scalar"));
+            }
+            self.expect(",")?;
+            Some(Box::new(parameter))
+        } else {
+            None
+        };
+        let expr = self.expr(0)?;
+        self.expect(")")?;
+        if expr.kind() != ValueKind::Vector {
+            return Err(self.error("aggregations need an instant vector"));
+        }
+        let grouping = match before {
+            Some(grouping) => grouping,
+            None => self.grouping()?.unwrap_or(Grouping::By(Vec::new())),
+        };
+        Ok(Expr::Aggregate(Aggregate {
+            op,
+            parameter,
+            grouping,
+            expr: Box::new(expr),
+        }))
+    }
+
+    fn call(&mut self, function: Function, name: &str) -> Result<Expr> {
+        self.expect("(")?;
+        let mut args = Vec::new();
+        while !self.eat(")") {
+            args.push(self.expr(0)?);
+            if !self.eat(",") {
+                self.expect(")")?;
+                break;
+            }
+        }
+
+        let expected = function.arguments();
+        let kinds: Vec<_> = args.iter().map(Expr::kind).collect();
+        if kinds != expected {
+            let expected: Vec<_> = expected.iter().map(|kind| kind.as_str()).collect();
+            return Err(self.error(&format!("{} expects arguments ({})", name, expected.join(", "))));
+        }
+        Ok(Expr::Call(function, args))
+    }
+
+    fn selector(&mut self, name: Option<&str>) -> Result<Expr> {
+        let mut matchers = Vec::new();
+        if let Some(name) = name {
+            matchers.push(Matcher::new(METRIC_NAME, MatchOp::Equal, name)?);
+        }
+        if self.eat("{") {
+            while !self.eat("}") {
+                let label = self.identifier()?;
+                let op = if self.eat("=~") {
+                    MatchOp::Regex
+                } else if self.eat("!~") {
+                    MatchOp::NotRegex
+                } else if self.eat("!=") {
+                    MatchOp::NotEqual
+                } else {
+                    self.expect("=")?;
+                    MatchOp::Equal
+                };
+                let value = self.string()?;
+                matchers.push(Matcher::new(label, op, value)?);
+                if !self.eat(",") {
+                    self.expect("}")?;
+                    break;
+                }
+            }
+        }
+        if matchers.iter().all(|m| m.matches(None)) {
+            return Err(self.error("vector selectors need at least one matcher that does not match the empty string"));
+        }
+
+        let id = self.selectors;
+        self.selectors += 1;
+        Ok(Expr::Vector(Selector {
+            id,
+            matchers,
+            offset: Duration::ZERO,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_selectors() {
+        let Expr::Matrix(selector, range) = parse(r#"http_requests_total{job=~"api|web", code!="200"}[5m] offset 1h"#)
+            .unwrap()
+        else {
+            panic!("expected a range selector");
+        };
+        assert_eq!(range, Duration::from_secs(300));
+        assert_eq!(selector.offset, Duration::from_secs(3600));
+        assert_eq!(selector.metric_name(), Some("http_requests_total"));
+        assert!(selector.matchers[1].matches(Some("web")));
+        assert!(!selector.matchers[1].matches(Some("webserver")));
+        assert!(selector.matchers[2].matches(None));
+
+        assert!(matches!(parse("cpu.usage").unwrap(), Expr::Vector(s) if s.metric_name() == Some("cpu.usage")));
+        assert!(parse(r#"{job=""}"#).is_err());
+        assert!(parse("x[5m:1m]").is_err());
+    }
+
+    #[test]
+    fn test_parse_precedence_and_aggregations() {
+        let Expr::Binary(binary) = parse("1 + 2 * 3 ^ 2 ^ 0.5").unwrap() else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(binary.op, BinaryOp::Add);
+        assert!(matches!(&binary.rhs, Expr::Binary(rhs) if rhs.op == BinaryOp::Mul));
+
+        assert!(matches!(parse("-2 ^ 2").unwrap(), Expr::Negate(_)));
+
+        let Expr::Aggregate(aggregate) = parse("sum by (job) (rate(requests[1m]))").unwrap() else {
+            panic!("expected an aggregation");
+        };
+        assert_eq!(aggregate.grouping, Grouping::By(vec!["job".to_string()]));
+        let Expr::Aggregate(aggregate) = parse("topk(3, requests) without (instance)").unwrap() else {
+            panic!("expected an aggregation");
+        };
+        assert_eq!(aggregate.op, AggregateOp::Topk);
+        assert_eq!(aggregate.grouping, Grouping::Without(vec!["instance".to_string()]));
+    }
+
+    #[test]
+    fn test_parse_rejects_mistyped_expressions() {
+        assert!(parse("rate(requests)").is_err());
+        assert!(parse("requests[5m] + 1").is_err());
+        assert!(parse("1 > 2").is_err());
+        assert!(parse("1 > bool 2").is_ok());
+        assert!(parse("a and 1").is_err());
+        assert!(parse("a / on(job) group_left b").is_err());
+        assert!(parse("histogram_quantile(0.9, a)").is_err());
+        assert!(parse("sum(a) b").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert!(parse_duration("5").is_err());
+        assert!(parse_duration("5m ").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Role-based access control for the gRPC API. A role, from
+//! `grpc.rbac.roles`, permits RPCs by name; a [`RoleBinding`] grants a
+//! subject a role in some tenants. [`Policy`] answers whether a subject may
+//! call an RPC for a tenant, and denies whatever no binding grants.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::config::{ApiKeyConfig, RbacConfig, RoleBindingConfig, RoleConfig};
+use crate::metrics::constant_time_eq;
+
+/// Tenant of a binding that applies in every tenant.
+pub const ALL_TENANTS: &str = "*";
+
+/// Grants `subject` the RPCs `role` permits, for requests about `tenants`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoleBinding {
+    pub subject: String,
+    pub role: String,
+    pub tenants: Vec<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl RoleBinding {
+    /// A binding from `grpc.rbac.bindings`.
+    pub fn configured(binding: &RoleBindingConfig) -> Self {
+        Self {
+            subject: binding.subject.clone(),
+            role: binding.role.clone(),
+            tenants: binding.tenants.clone(),
+            created_by: "config".to_string(),
+            created_at: DateTime::<Utc>::default(),
+        }
+    }
+}
+
+/// Tenants a subject may call an RPC for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    All,
+    Tenants(BTreeSet<String>),
+}
+
+impl Scope {
+    pub fn permits(&self, tenant: &str) -> bool {
+        match self {
+            Scope::All => true,
+            Scope::Tenants(tenants) => tenants.contains(tenant),
+        }
+    }
+}
+
+/// Roles and the bindings in effect.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    roles: HashMap<String, Vec<String>>,
+    bindings: Vec<RoleBinding>,
+}
+
+impl Policy {
+    /// Bindings naming no role in `roles` grant nothing.
+    pub fn new(roles: &[RoleConfig], bindings: Vec<RoleBinding>) -> Self {
+        Self {
+            roles: roles.iter().map(|role| (role.name.clone(), role.rpcs.clone())).collect(),
+            bindings,
+        }
+    }
+
+    pub fn is_role(&self, role: &str) -> bool {
+        self.roles.contains_key(role)
+    }
+
+    /// Where `subject` may call `rpc`, or `None` when no binding of theirs
+    /// permits it at all.
+    pub fn scope(&self, subject: &str, rpc: &str) -> Option<Scope> {
+        let mut tenants = BTreeSet::new();
+        let mut granted = false;
+        for binding in self.bindings.iter().filter(|b| b.subject == subject) {
+            let permitted = self
+                .roles
+                .get(&binding.role)
+                .map_or(false, |rpcs| rpcs.iter().any(|pattern| matches(pattern, rpc)));
+            if !permitted {
+                continue;
+            }
+            granted = true;
+            if binding.tenants.iter().any(|tenant| tenant == ALL_TENANTS) {
+                return Some(Scope::All);
+            }
+            tenants.extend(binding.tenants.iter().cloned());
+        }
+        granted.then_some(Scope::Tenants(tenants))
+    }
+
+    /// Whether `subject` may call `rpc` for `tenant`. RPCs about no tenant
+    /// in particular need a binding in every tenant.
+    pub fn permits(&self, subject: &str, rpc: &str, tenant: Option<&str>) -> bool {
+        match (self.scope(subject, rpc), tenant) {
+            (Some(scope), Some(tenant)) => scope.permits(tenant),
+            (Some(scope), None) => scope == Scope::All,
+            (None, _) => false,
+        }
+    }
+}
+
+/// The key in `grpc.rbac.api_keys` a caller presented, compared in constant
+/// time.
+pub fn api_key<'a>(config: &'a RbacConfig, presented: &str) -> Option<&'a ApiKeyConfig> {
+    config
+        .api_keys
+        .iter()
+        .find(|key| constant_time_eq(key.api_key.as_bytes(), presented.as_bytes()))
+}
+
+/// Whether `pattern` names `rpc`: exactly, by a prefix or suffix marked by
+/// `*`, or `*` for any RPC.
+fn matches(pattern: &str, rpc: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    if let Some(prefix) = pattern.strip_suffix('*') {
+        return rpc.starts_with(prefix);
+    }
+    if let Some(suffix) = pattern.strip_prefix('*') {
+        return rpc.ends_with(suffix);
+    }
+    pattern == rpc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(subject: &str, role: &str, tenants: &[&str]) -> RoleBinding {
+        RoleBinding::configured(&RoleBindingConfig {
+            subject: subject.to_string(),
+            role: role.to_string(),
+            tenants: tenants.iter().map(|t| t.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn test_policy_denies_what_no_binding_grants() {
+        let policy = Policy::new(
+            &RbacConfig::default().roles,
+            vec![
+                binding("alice", "admin", &["*"]),
+                binding("ci", "operator", &["acme"]),
+                binding("ci", "viewer", &["globex"]),
+                binding("bob", "auditor", &["*"]),
+            ],
+        );
+
+        assert!(policy.permits("alice", "CreateRoleBinding", None));
+        assert!(policy.permits("ci", "CreatePipeline", Some("acme")));
+        assert!(!policy.permits("ci", "CreatePipeline", Some("globex")));
+        assert!(policy.permits("ci", "ListPipelines", Some("globex")));
+        // Tenant-scoped bindings do not reach RPCs about every tenant
+        assert!(!policy.permits("ci", "RestoreJob", None));
+        assert!(!policy.permits("ci", "ListRoleBindings", None));
+        assert!(!policy.permits("ci", "QueryAuditLog", Some("acme")));
+        // Unknown roles and subjects get nothing
+        assert!(!policy.permits("bob", "ListPipelines", Some("acme")));
+        assert!(!policy.permits("mallory", "ListPipelines", Some("acme")));
+
+        let tenants = BTreeSet::from(["acme".to_string(), "globex".to_string()]);
+        assert_eq!(policy.scope("ci", "GetPipeline"), Some(Scope::Tenants(tenants)));
+        assert_eq!(policy.scope("alice", "GetPipeline"), Some(Scope::All));
+    }
+
+    #[test]
+    fn test_api_key_lookup() {
+        let mut config = RbacConfig::default();
+        config.api_keys.push(ApiKeyConfig {
+            subject: "gateway".to_string(),
+            api_key: "s3cret".to_string(),
+            forwards_identity: true,
+        });
+
+        assert_eq!(api_key(&config, "s3cret").map(|key| key.subject.as_str()), Some("gateway"));
+        assert!(api_key(&config, "s3cre").is_none());
+        assert!(api_key(&config, "").is_none());
+    }
+
+    #[test]
+    fn test_rpc_patterns() {
+        assert!(matches("*", "DeletePipeline"));
+        assert!(matches("List*", "ListJobs"));
+        assert!(matches("*Pipeline", "DeletePipeline"));
+        assert!(!matches("*Pipeline", "ListPipelines"));
+        assert!(matches("QueryLogs", "QueryLogs"));
+        assert!(!matches("QueryLogs", "QueryLogsAll"));
+    }
+}
@@ -0,0 +1,599 @@
+//! Recording rules under `processing.recording_rules`: expressions over the
+//! latest value of each incoming metric series, evaluated every interval
+//! and stored as new series.
+
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Instant;
+use streamforge_sdk::Metric;
+use tokio::sync::watch;
+use tracing::warn;
+
+use crate::config::{ProcessingConfig, RecordingRule};
+use crate::processor::{ProcessedMessage, ProcessingMetadata};
+
+type Labels = BTreeMap<String, String>;
+
+/// A parsed rule expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// Every series of `name` whose labels satisfy all matchers.
+    Selector { name: String, matchers: Vec<Matcher> },
+    /// Combines the series of `expr` into one per distinct value of the
+    /// `by` labels.
+    Aggregate { op: AggregateOp, by: Vec<String>, expr: Box<Expr> },
+    /// Series on both sides are paired by identical label sets.
+    Binary { op: BinaryOp, lhs: Box<Expr>, rhs: Box<Expr> },
+    Negate(Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Matcher {
+    pub label: String,
+    pub value: String,
+    /// `=` rather than `!=`.
+    pub equal: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateOp {
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Count,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Str(String),
+    Symbol(char),
+    NotEqual,
+}
+
+/// Result of evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Sample {
+    Scalar(f64),
+    Vector(Vec<(Labels, f64)>),
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+        let expr = parser.expr()?;
+        match parser.next() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {} after the expression", describe(&token))),
+        }
+    }
+
+    fn eval(&self, series: &HashMap<String, HashMap<Labels, f64>>) -> Sample {
+        match self {
+            Expr::Number(n) => Sample::Scalar(*n),
+            Expr::Selector { name, matchers } => Sample::Vector(
+                series
+                    .get(name)
+                    .into_iter()
+                    .flatten()
+                    .filter(|(labels, _)| {
+                        matchers.iter().all(|m| {
+                            (labels.get(&m.label).map_or("", String::as_str) == m.value) == m.equal
+                        })
+                    })
+                    .map(|(labels, value)| (labels.clone(), *value))
+                    .collect(),
+            ),
+            Expr::Aggregate { op, by, expr } => {
+                let mut groups: BTreeMap<Labels, Vec<f64>> = BTreeMap::new();
+                for (labels, value) in expr.eval(series).into_vector() {
+                    let key = labels.into_iter().filter(|(label, _)| by.contains(label)).collect();
+                    groups.entry(key).or_default().push(value);
+                }
+                Sample::Vector(
+                    groups
+                        .into_iter()
+                        .map(|(labels, values)| {
+                            let value = match op {
+                                AggregateOp::Sum => values.iter().sum(),
+                                AggregateOp::Avg => values.iter().sum::<f64>() / values.len() as f64,
+                                AggregateOp::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+                                AggregateOp::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                                AggregateOp::Count => values.len() as f64,
+                            };
+                            (labels, value)
+                        })
+                        .collect(),
+                )
+            }
+            Expr::Binary { op, lhs, rhs } => {
+                let apply = |a: f64, b: f64| match op {
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div => a / b,
+                };
+                match (lhs.eval(series), rhs.eval(series)) {
+                    (Sample::Scalar(a), Sample::Scalar(b)) => Sample::Scalar(apply(a, b)),
+                    (Sample::Vector(a), Sample::Scalar(b)) => {
+                        Sample::Vector(a.into_iter().map(|(labels, a)| (labels, apply(a, b))).collect())
+                    }
+                    (Sample::Scalar(a), Sample::Vector(b)) => {
+                        Sample::Vector(b.into_iter().map(|(labels, b)| (labels, apply(a, b))).collect())
+                    }
+                    (Sample::Vector(a), Sample::Vector(b)) => {
+                        let b: HashMap<Labels, f64> = b.into_iter().collect();
+                        Sample::Vector(
+                            a.into_iter()
+                                .filter_map(|(labels, a)| b.get(&labels).map(|b| (labels, apply(a, *b))))
+                                .collect(),
+                        )
+                    }
+                }
+            }
+            Expr::Negate(expr) => match expr.eval(series) {
+                Sample::Scalar(n) => Sample::Scalar(-n),
+                Sample::Vector(v) => Sample::Vector(v.into_iter().map(|(labels, n)| (labels, -n)).collect()),
+            },
+        }
+    }
+}
+
+impl Sample {
+    fn into_vector(self) -> Vec<(Labels, f64)> {
+        match self {
+            Sample::Scalar(n) => vec![(Labels::new(), n)],
+            Sample::Vector(v) => v,
+        }
+    }
+}
+
+impl AggregateOp {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sum" => Some(AggregateOp::Sum),
+            "avg" => Some(AggregateOp::Avg),
+            "min" => Some(AggregateOp::Min),
+            "max" => Some(AggregateOp::Max),
+            "count" => Some(AggregateOp::Count),
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '0'..='9' | '.' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let number = &input[start..end];
+                tokens.push(Token::Number(
+                    number.parse().map_err(|_| format!("invalid number {:?}", number))?,
+                ));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' || c == ':' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_' || c == ':' || c == '.') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Ident(input[start..end].to_string()));
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => value.push(c),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some((_, c)) => value.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            '!' => {
+                chars.next();
+                match chars.next() {
+                    Some((_, '=')) => tokens.push(Token::NotEqual),
+                    _ => return Err(format!("expected != at {}", start)),
+                }
+            }
+            '+' | '-' | '*' | '/' | '(' | ')' | '{' | '}' | ',' | '=' => {
+                chars.next();
+                tokens.push(Token::Symbol(c));
+            }
+            other => return Err(format!("unexpected character {:?} at {}", other, start)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(n) => n.to_string(),
+        Token::Ident(name) => name.clone(),
+        Token::Str(value) => format!("{:?}", value),
+        Token::Symbol(c) => format!("{:?}", c),
+        Token::NotEqual => "!=".to_string(),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(c)) if c == symbol => Ok(()),
+            Some(token) => Err(format!("expected {:?}, found {}", symbol, describe(&token))),
+            None => Err(format!("expected {:?} at the end", symbol)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                BinaryOp::Add
+            } else if self.eat('-') {
+                BinaryOp::Sub
+            } else {
+                return Ok(lhs);
+            };
+            let rhs = self.term()?;
+            lhs = Expr::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.factor()?;
+        loop {
+            let op = if self.eat('*') {
+                BinaryOp::Mul
+            } else if self.eat('/') {
+                BinaryOp::Div
+            } else {
+                return Ok(lhs);
+            };
+            let rhs = self.factor()?;
+            lhs = Expr::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+        }
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Symbol('-')) => Ok(Expr::Negate(Box::new(self.factor()?))),
+            Some(Token::Symbol('(')) => {
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                let aggregate = AggregateOp::from_name(&name).filter(|_| {
+                    matches!(self.peek(), Some(Token::Symbol('('))) || self.peek() == Some(&Token::Ident("by".to_string()))
+                });
+                match aggregate {
+                    Some(op) => self.aggregate(op),
+                    None => self.selector(name),
+                }
+            }
+            Some(token) => Err(format!("unexpected {}", describe(&token))),
+            None => Err("unexpected end of the expression".to_string()),
+        }
+    }
+
+    /// `op [by (labels)] (expr) [by (labels)]`
+    fn aggregate(&mut self, op: AggregateOp) -> Result<Expr, String> {
+        let mut by = self.by()?;
+        self.expect('(')?;
+        let expr = self.expr()?;
+        self.expect(')')?;
+        if by.is_empty() {
+            by = self.by()?;
+        }
+        Ok(Expr::Aggregate { op, by, expr: Box::new(expr) })
+    }
+
+    fn by(&mut self) -> Result<Vec<String>, String> {
+        if self.peek() != Some(&Token::Ident("by".to_string())) {
+            return Ok(Vec::new());
+        }
+        self.pos += 1;
+        self.expect('(')?;
+        let mut labels = Vec::new();
+        while !self.eat(')') {
+            match self.next() {
+                Some(Token::Ident(label)) => labels.push(label),
+                Some(token) => return Err(format!("expected a label in by (...), found {}", describe(&token))),
+                None => return Err("unterminated by (...)".to_string()),
+            }
+            if !self.eat(',') && self.peek() != Some(&Token::Symbol(')')) {
+                return Err("expected , or ) in by (...)".to_string());
+            }
+        }
+        Ok(labels)
+    }
+
+    /// `name [{label="value", label!="value"}]`
+    fn selector(&mut self, name: String) -> Result<Expr, String> {
+        let mut matchers = Vec::new();
+        if self.eat('{') {
+            while !self.eat('}') {
+                let label = match self.next() {
+                    Some(Token::Ident(label)) => label,
+                    Some(token) => return Err(format!("expected a label in {}{{...}}, found {}", name, describe(&token))),
+                    None => return Err(format!("unterminated {}{{...}}", name)),
+                };
+                let equal = match self.next() {
+                    Some(Token::Symbol('=')) => true,
+                    Some(Token::NotEqual) => false,
+                    _ => return Err(format!("expected = or != after label {}", label)),
+                };
+                let value = match self.next() {
+                    Some(Token::Str(value)) => value,
+                    _ => return Err(format!("expected a quoted value for label {}", label)),
+                };
+                matchers.push(Matcher { label, value, equal });
+                if !self.eat(',') && self.peek() != Some(&Token::Symbol('}')) {
+                    return Err(format!("expected , or }} in {}{{...}}", name));
+                }
+            }
+        }
+        Ok(Expr::Selector { name, matchers })
+    }
+}
+
+/// Evaluates the configured rules over the metrics passing through.
+pub struct RecordingRules {
+    settings: watch::Receiver<ProcessingConfig>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    window_start: Option<Instant>,
+    tenants: HashMap<String, TenantSeries>,
+}
+
+struct TenantSeries {
+    /// Latest value by metric name and label set.
+    series: HashMap<String, HashMap<Labels, f64>>,
+    /// The latest metric seen, which recorded series are attributed to.
+    source: ProcessingMetadata,
+}
+
+impl RecordingRules {
+    pub fn new(settings: watch::Receiver<ProcessingConfig>) -> Self {
+        Self {
+            settings,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Keeps the value of `message` if it is a metric.
+    pub fn observe(&self, message: &ProcessedMessage) {
+        if self.settings.borrow().recording_rules.rules.is_empty() {
+            return;
+        }
+        let Ok(metric) = Metric::deserialize(&message.processed_message) else {
+            return;
+        };
+        let metadata = &message.processing_metadata;
+        let mut state = self.state.lock().unwrap();
+        let tenant = state
+            .tenants
+            .entry(metadata.tenant_id.clone())
+            .or_insert_with(|| TenantSeries {
+                series: HashMap::new(),
+                source: metadata.clone(),
+            });
+        tenant.source.clone_from(metadata);
+        tenant
+            .series
+            .entry(metric.name)
+            .or_default()
+            .insert(metric.labels.unwrap_or_default().into_iter().collect(), metric.value);
+    }
+
+    /// Series recorded from the interval, once it has ended, as processed
+    /// messages to store with the current batch.
+    pub fn flush(&self, now: Instant) -> Vec<ProcessedMessage> {
+        let settings = self.settings.borrow();
+        let config = &settings.recording_rules;
+        let mut state = self.state.lock().unwrap();
+        let start = *state.window_start.get_or_insert(now);
+        if now.duration_since(start) < config.interval {
+            return Vec::new();
+        }
+        state.window_start = Some(now);
+
+        let rules: Vec<(&RecordingRule, Expr)> = config
+            .rules
+            .iter()
+            .filter_map(|rule| match Expr::parse(&rule.expr) {
+                Ok(expr) => Some((rule, expr)),
+                Err(e) => {
+                    warn!("Skipping recording rule {}: {}", rule.record, e);
+                    None
+                }
+            })
+            .collect();
+        let timestamp = chrono::Utc::now().timestamp_millis() as u64;
+        let mut recorded = Vec::new();
+        for (_, mut tenant) in state.tenants.drain() {
+            for (rule, expr) in &rules {
+                let mut values = HashMap::new();
+                for (mut labels, value) in expr.eval(&tenant.series).into_vector() {
+                    if !value.is_finite() {
+                        continue;
+                    }
+                    labels.extend(rule.labels.clone());
+                    let metric = Metric {
+                        name: rule.record.clone(),
+                        value,
+                        unit: rule.unit.clone(),
+                        labels: (!labels.is_empty()).then(|| labels.clone().into_iter().collect()),
+                        timestamp: Some(timestamp),
+                    };
+                    let record = serde_json::to_value(&metric).unwrap_or_default();
+                    recorded.push(ProcessedMessage::derived(record, &tenant.source));
+                    values.insert(labels, value);
+                }
+                tenant.series.insert(rule.record.clone(), values);
+            }
+        }
+        recorded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    fn metric(name: &str, labels: Value, value: f64) -> ProcessedMessage {
+        let record = json!({"name": name, "value": value, "unit": "count", "labels": labels});
+        ProcessedMessage {
+            id: "1".to_string(),
+            original_message: record.clone(),
+            processed_message: record,
+            processing_metadata: ProcessingMetadata {
+                tenant_id: "acme".to_string(),
+                processed_at: Utc::now(),
+                processor_version: "test".to_string(),
+                source_topic: "metrics".to_string(),
+                partition: 0,
+                offset: 0,
+                traceparent: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Expr::parse(r#"sum by (service) (errors{env="prod"}) / 2"#),
+            Ok(Expr::Binary {
+                op: BinaryOp::Div,
+                lhs: Box::new(Expr::Aggregate {
+                    op: AggregateOp::Sum,
+                    by: vec!["service".to_string()],
+                    expr: Box::new(Expr::Selector {
+                        name: "errors".to_string(),
+                        matchers: vec![Matcher {
+                            label: "env".to_string(),
+                            value: "prod".to_string(),
+                            equal: true,
+                        }],
+                    }),
+                }),
+                rhs: Box::new(Expr::Number(2.0)),
+            })
+        );
+        assert_eq!(Expr::parse("sum(errors) by (service)"), Expr::parse("sum by (service) (errors)"));
+        // Aggregation names are plain metrics unless followed by ( or by
+        assert!(matches!(Expr::parse("count"), Ok(Expr::Selector { .. })));
+        assert!(Expr::parse("errors{env=prod}").is_err());
+        assert!(Expr::parse("sum(errors").is_err());
+        assert!(Expr::parse("errors +").is_err());
+        assert!(Expr::parse("errors @ 1").is_err());
+    }
+
+    #[test]
+    fn test_rules_are_recorded_per_interval() {
+        let mut settings = ProcessingConfig::default();
+        settings.recording_rules.interval = Duration::from_secs(60);
+        settings.recording_rules.rules = serde_json::from_value(json!([
+            {"record": "service:errors:sum", "expr": "sum by (service) (http_errors)"},
+            {"record": "service:error_ratio", "expr": "service:errors:sum / sum by (service) (http_requests)",
+             "labels": {"source": "rule"}, "unit": "ratio"},
+        ]))
+        .unwrap();
+        let (_tx, rx) = watch::channel(settings);
+        let rules = RecordingRules::new(rx);
+        let start = Instant::now();
+        assert!(rules.flush(start).is_empty());
+
+        rules.observe(&metric("http_errors", json!({"service": "api", "pod": "a"}), 1.0));
+        rules.observe(&metric("http_errors", json!({"service": "api", "pod": "a"}), 3.0));
+        rules.observe(&metric("http_errors", json!({"service": "api", "pod": "b"}), 2.0));
+        rules.observe(&metric("http_requests", json!({"service": "api"}), 50.0));
+        rules.observe(&metric("http_requests", json!({"service": "web"}), 10.0));
+
+        let mut recorded: Vec<(String, Value, f64)> = rules
+            .flush(start + Duration::from_secs(60))
+            .into_iter()
+            .map(|m| {
+                let metric: Metric = serde_json::from_value(m.processed_message).unwrap();
+                (metric.name, serde_json::to_value(metric.labels).unwrap(), metric.value)
+            })
+            .collect();
+        recorded.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            recorded,
+            vec![
+                ("service:error_ratio".to_string(), json!({"service": "api", "source": "rule"}), 0.1),
+                ("service:errors:sum".to_string(), json!({"service": "api"}), 5.0),
+            ]
+        );
+        assert!(rules.flush(start + Duration::from_secs(120)).is_empty());
+    }
+}
@@ -0,0 +1,462 @@
+//! Declarative reshaping transforms: `rename`, `project`, `flatten`,
+//! `coerce` and `defaults`. Each rewrites one record in place from its
+//! config alone, so the common field shuffles a pipeline needs do not take
+//! a code change. Fields are addressed by dotted paths such as
+//! `labels.service`.
+
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use crate::columnar::lookup;
+use crate::table::set_path;
+
+/// Transform types that reshape records.
+pub const RESHAPE_KINDS: &[&str] = &["rename", "project", "flatten", "coerce", "defaults"];
+
+/// The type a `coerce` transform converts a field to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    /// Epoch milliseconds, from a number or an RFC 3339 string.
+    Timestamp,
+}
+
+impl FieldType {
+    fn name(self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Integer => "integer",
+            FieldType::Float => "float",
+            FieldType::Boolean => "boolean",
+            FieldType::Timestamp => "timestamp",
+        }
+    }
+}
+
+/// What a `coerce` transform does with a value it cannot convert.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Fails the record, sending it to the dead letter queue.
+    #[default]
+    Fail,
+    /// Replaces the value with null.
+    Null,
+    /// Leaves the value as it was.
+    Keep,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reshape {
+    /// Moves each field to its new path. All fields are taken before any is
+    /// written, so renames may swap fields.
+    Rename(BTreeMap<String, String>),
+    /// Keeps only `fields`, or drops `exclude`.
+    Project { fields: Vec<String>, exclude: Vec<String> },
+    /// Lifts the nested objects under `fields`, or the whole record when
+    /// empty, into their parent, joining keys with `separator`.
+    Flatten {
+        fields: Vec<String>,
+        separator: String,
+        max_depth: Option<usize>,
+    },
+    Coerce {
+        fields: BTreeMap<String, FieldType>,
+        on_error: OnError,
+    },
+    /// Sets each field that is missing or null.
+    Defaults(BTreeMap<String, Value>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenameConfig {
+    fields: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProjectConfig {
+    #[serde(default)]
+    fields: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FlattenConfig {
+    #[serde(default)]
+    fields: Vec<String>,
+    #[serde(default = "default_separator")]
+    separator: String,
+    #[serde(default)]
+    max_depth: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CoerceConfig {
+    fields: BTreeMap<String, FieldType>,
+    #[serde(default)]
+    on_error: OnError,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DefaultsConfig {
+    fields: BTreeMap<String, Value>,
+}
+
+fn default_separator() -> String {
+    "_".to_string()
+}
+
+impl Reshape {
+    /// Parses the config of a transform whose type is one of
+    /// [`RESHAPE_KINDS`].
+    pub fn from_config(kind: &str, config: &Value) -> Result<Self, String> {
+        let reshape = match kind {
+            "rename" => Reshape::Rename(RenameConfig::deserialize(config).map_err(|e| e.to_string())?.fields),
+            "project" => {
+                let config = ProjectConfig::deserialize(config).map_err(|e| e.to_string())?;
+                if config.fields.is_empty() == config.exclude.is_empty() {
+                    return Err("project requires exactly one of fields and exclude".to_string());
+                }
+                Reshape::Project {
+                    fields: config.fields,
+                    exclude: config.exclude,
+                }
+            }
+            "flatten" => {
+                let config = FlattenConfig::deserialize(config).map_err(|e| e.to_string())?;
+                if config.separator.is_empty() {
+                    return Err("separator must not be empty".to_string());
+                }
+                if config.max_depth == Some(0) {
+                    return Err("max_depth must be greater than 0 when set".to_string());
+                }
+                Reshape::Flatten {
+                    fields: config.fields,
+                    separator: config.separator,
+                    max_depth: config.max_depth,
+                }
+            }
+            "coerce" => {
+                let config = CoerceConfig::deserialize(config).map_err(|e| e.to_string())?;
+                Reshape::Coerce {
+                    fields: config.fields,
+                    on_error: config.on_error,
+                }
+            }
+            "defaults" => Reshape::Defaults(DefaultsConfig::deserialize(config).map_err(|e| e.to_string())?.fields),
+            other => return Err(format!("{:?} is not a reshaping transform", other)),
+        };
+
+        let paths: Vec<&str> = match &reshape {
+            Reshape::Rename(fields) => fields
+                .iter()
+                .flat_map(|(from, to)| [from.as_str(), to.as_str()])
+                .collect(),
+            Reshape::Project { fields, exclude } => fields.iter().chain(exclude).map(String::as_str).collect(),
+            Reshape::Flatten { fields, .. } => fields.iter().map(String::as_str).collect(),
+            Reshape::Coerce { fields, .. } => fields.keys().map(String::as_str).collect(),
+            Reshape::Defaults(fields) => fields.keys().map(String::as_str).collect(),
+        };
+        let requires_fields = !matches!(reshape, Reshape::Flatten { .. } | Reshape::Project { .. });
+        if requires_fields && paths.is_empty() {
+            return Err(format!("{} requires at least one field", kind));
+        }
+        if let Some(path) = paths.iter().find(|path| path.split('.').any(str::is_empty)) {
+            return Err(format!("invalid field path {:?}", path));
+        }
+        Ok(reshape)
+    }
+
+    /// Rewrites `record`. Fails only for a `coerce` whose `on_error` is
+    /// `fail`, naming the field that could not be converted.
+    pub fn apply(&self, record: &mut Value) -> Result<(), String> {
+        match self {
+            Reshape::Rename(fields) => {
+                let taken: Vec<(&String, Value)> = fields
+                    .iter()
+                    .filter_map(|(from, to)| remove_path(record, from).map(|value| (to, value)))
+                    .collect();
+                for (to, value) in taken {
+                    set_path(record, to, value);
+                }
+            }
+            Reshape::Project { fields, exclude } => {
+                if fields.is_empty() {
+                    for path in exclude {
+                        remove_path(record, path);
+                    }
+                } else {
+                    let mut projected = Value::Object(Map::new());
+                    for path in fields {
+                        if let Some(value) = lookup(record, path) {
+                            set_path(&mut projected, path, value.clone());
+                        }
+                    }
+                    *record = projected;
+                }
+            }
+            Reshape::Flatten {
+                fields,
+                separator,
+                max_depth,
+            } => {
+                let depth = max_depth.unwrap_or(usize::MAX);
+                if fields.is_empty() {
+                    if let Value::Object(object) = record {
+                        let mut flat = Map::new();
+                        for (key, value) in std::mem::take(object) {
+                            flatten_into(&mut flat, key, value, separator, depth);
+                        }
+                        *object = flat;
+                    }
+                }
+                for path in fields {
+                    if !lookup(record, path).map_or(false, Value::is_object) {
+                        continue;
+                    }
+                    let Some(value) = remove_path(record, path) else {
+                        continue;
+                    };
+                    let (parent, key) = match path.rsplit_once('.') {
+                        Some((parent, key)) => (lookup_object(record, parent), key),
+                        None => (record.as_object_mut(), path.as_str()),
+                    };
+                    if let Some(parent) = parent {
+                        flatten_into(parent, key.to_string(), value, separator, depth);
+                    }
+                }
+            }
+            Reshape::Coerce { fields, on_error } => {
+                for (path, to) in fields {
+                    let Some(value) = lookup(record, path).filter(|value| !value.is_null()) else {
+                        continue;
+                    };
+                    let coerced = match (coerce(value, *to), on_error) {
+                        (Some(coerced), _) => coerced,
+                        (None, OnError::Fail) => {
+                            return Err(format!("cannot coerce {} ({}) to {}", path, value, to.name()))
+                        }
+                        (None, OnError::Null) => Value::Null,
+                        (None, OnError::Keep) => continue,
+                    };
+                    set_path(record, path, coerced);
+                }
+            }
+            Reshape::Defaults(fields) => {
+                for (path, default) in fields {
+                    if lookup(record, path).map_or(true, Value::is_null) {
+                        set_path(record, path, default.clone());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Removes the value at the dotted `path`, returning it.
+fn remove_path(record: &mut Value, path: &str) -> Option<Value> {
+    match path.rsplit_once('.') {
+        Some((parent, key)) => lookup_object(record, parent)?.remove(key),
+        None => record.as_object_mut()?.remove(path),
+    }
+}
+
+fn lookup_object<'a>(record: &'a mut Value, path: &str) -> Option<&'a mut Map<String, Value>> {
+    path.split('.')
+        .try_fold(record, |value, key| value.get_mut(key))?
+        .as_object_mut()
+}
+
+/// Inserts `value` under `key`, or when it is a non-empty object and
+/// `depth` allows, each of its fields under `key`, `separator` and the
+/// field's name.
+fn flatten_into(target: &mut Map<String, Value>, key: String, value: Value, separator: &str, depth: usize) {
+    match value {
+        Value::Object(object) if depth > 0 && !object.is_empty() => {
+            for (field, value) in object {
+                flatten_into(
+                    target,
+                    format!("{}{}{}", key, separator, field),
+                    value,
+                    separator,
+                    depth - 1,
+                );
+            }
+        }
+        value => {
+            target.insert(key, value);
+        }
+    }
+}
+
+fn coerce(value: &Value, to: FieldType) -> Option<Value> {
+    match (to, value) {
+        (FieldType::String, Value::String(_)) => Some(value.clone()),
+        (FieldType::String, value) => Some(Value::from(value.to_string())),
+        (FieldType::Integer, Value::Number(n)) => match n.as_i64() {
+            Some(i) => Some(Value::from(i)),
+            None => n.as_f64().filter(|f| f.fract() == 0.0).map(|f| Value::from(f as i64)),
+        },
+        (FieldType::Integer, Value::String(s)) => s.trim().parse::<i64>().ok().map(Value::from),
+        (FieldType::Integer, Value::Bool(b)) => Some(Value::from(*b as i64)),
+        (FieldType::Float, Value::Number(n)) => n.as_f64().map(Value::from),
+        (FieldType::Float, Value::String(s)) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number),
+        (FieldType::Float, Value::Bool(b)) => Some(Value::from(if *b { 1.0 } else { 0.0 })),
+        (FieldType::Boolean, Value::Bool(_)) => Some(value.clone()),
+        (FieldType::Boolean, Value::Number(n)) => match n.as_f64() {
+            Some(f) if f == 0.0 => Some(Value::Bool(false)),
+            Some(f) if f == 1.0 => Some(Value::Bool(true)),
+            _ => None,
+        },
+        (FieldType::Boolean, Value::String(s)) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(Value::Bool(true)),
+            "false" | "no" | "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        (FieldType::Timestamp, Value::Number(_)) => coerce(value, FieldType::Integer),
+        (FieldType::Timestamp, Value::String(s)) => DateTime::parse_from_rfc3339(s.trim())
+            .ok()
+            .map(|t| Value::from(t.timestamp_millis()))
+            .or_else(|| coerce(value, FieldType::Integer)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn reshape(kind: &str, config: Value, mut record: Value) -> Result<Value, String> {
+        Reshape::from_config(kind, &config)?.apply(&mut record)?;
+        Ok(record)
+    }
+
+    #[test]
+    fn test_reshaping_transforms() {
+        let record = json!({
+            "msg": "started",
+            "lvl": "INFO",
+            "http": {"status": "200", "latency_ms": "12.5", "headers": {"host": "api"}},
+            "labels": {"pod": "api-0", "debug": "true"},
+        });
+
+        assert_eq!(
+            reshape(
+                "rename",
+                json!({"fields": {"msg": "message", "labels.pod": "pod", "lvl": "msg"}}),
+                record.clone()
+            )
+            .unwrap(),
+            json!({
+                "message": "started",
+                "msg": "INFO",
+                "pod": "api-0",
+                "http": {"status": "200", "latency_ms": "12.5", "headers": {"host": "api"}},
+                "labels": {"debug": "true"},
+            })
+        );
+        assert_eq!(
+            reshape(
+                "project",
+                json!({"fields": ["msg", "http.status", "missing"]}),
+                record.clone()
+            )
+            .unwrap(),
+            json!({"msg": "started", "http": {"status": "200"}})
+        );
+        assert_eq!(
+            reshape(
+                "project",
+                json!({"exclude": ["labels", "http.headers"]}),
+                record.clone()
+            )
+            .unwrap(),
+            json!({"msg": "started", "lvl": "INFO", "http": {"status": "200", "latency_ms": "12.5"}})
+        );
+        assert_eq!(
+            reshape("flatten", json!({"fields": ["http"], "max_depth": 1}), record.clone()).unwrap(),
+            json!({
+                "msg": "started",
+                "lvl": "INFO",
+                "http_status": "200",
+                "http_latency_ms": "12.5",
+                "http_headers": {"host": "api"},
+                "labels": {"pod": "api-0", "debug": "true"},
+            })
+        );
+        assert_eq!(
+            reshape(
+                "flatten",
+                json!({"separator": "."}),
+                json!({"a": {"b": {"c": 1}}, "d": {}})
+            )
+            .unwrap(),
+            json!({"a.b.c": 1, "d": {}})
+        );
+        assert_eq!(
+            reshape(
+                "coerce",
+                json!({"fields": {"http.status": "integer", "http.latency_ms": "float", "labels.debug": "boolean"}}),
+                record.clone()
+            )
+            .unwrap()["http"],
+            json!({"status": 200, "latency_ms": 12.5, "headers": {"host": "api"}})
+        );
+        assert_eq!(
+            reshape(
+                "coerce",
+                json!({"fields": {"at": "timestamp"}}),
+                json!({"at": "2024-01-01T00:00:01Z"})
+            )
+            .unwrap(),
+            json!({"at": 1_704_067_201_000i64})
+        );
+        assert_eq!(
+            reshape("coerce", json!({"fields": {"lvl": "integer"}}), record.clone()).unwrap_err(),
+            "cannot coerce lvl (\"INFO\") to integer"
+        );
+        assert_eq!(
+            reshape(
+                "coerce",
+                json!({"fields": {"lvl": "integer"}, "on_error": "keep"}),
+                record.clone()
+            )
+            .unwrap(),
+            record
+        );
+        assert_eq!(
+            reshape(
+                "defaults",
+                json!({"fields": {"lvl": "WARN", "labels.env": "prod"}}),
+                record
+            )
+            .unwrap()["labels"],
+            json!({"pod": "api-0", "debug": "true", "env": "prod"})
+        );
+
+        assert!(Reshape::from_config("project", &json!({})).is_err());
+        assert!(Reshape::from_config("rename", &json!({"fields": {}})).is_err());
+        assert!(Reshape::from_config("defaults", &json!({"fields": {"a..b": 1}})).is_err());
+        assert!(Reshape::from_config("coerce", &json!({"fields": {"a": "date"}})).is_err());
+        assert!(Reshape::from_config("flatten", &json!({"separator": ""})).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+//! `sample` transforms: head and tail sampling of trace spans and logs, so
+//! a pipeline can cut the trace volume it stores without losing the traces
+//! worth looking at.
+
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::approx::stable_hash;
+
+/// How long a head sampling decision is remembered, so later spans and
+/// logs of a trace follow its first record.
+const DECISION_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum SamplingSpec {
+    /// Decides on each trace as its first record arrives.
+    Head {
+        /// Share of traces kept. Decided from the trace id, so every
+        /// instance consuming a trace's spans agrees.
+        #[serde(default = "default_probability")]
+        probability: f64,
+        /// Most traces kept per second on top of `probability`, for a
+        /// storage budget that holds through bursts.
+        #[serde(default)]
+        max_per_second: Option<u32>,
+    },
+    /// Holds each trace back until `decision_wait_seconds` after its first
+    /// record, then keeps all of it if any record is an error or the trace
+    /// is slow, and a `probability` share of the rest.
+    Tail {
+        #[serde(default = "default_decision_wait_seconds")]
+        decision_wait_seconds: u64,
+        #[serde(default = "default_keep_errors")]
+        keep_errors: bool,
+        /// Keeps traces with a span at least this long.
+        #[serde(default)]
+        latency_threshold_ms: Option<i64>,
+        #[serde(default)]
+        probability: f64,
+        /// Traces held at once; past it the oldest is decided early.
+        #[serde(default = "default_max_pending_traces")]
+        max_pending_traces: usize,
+    },
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+fn default_decision_wait_seconds() -> u64 {
+    10
+}
+
+fn default_keep_errors() -> bool {
+    true
+}
+
+fn default_max_pending_traces() -> usize {
+    10_000
+}
+
+impl SamplingSpec {
+    pub fn from_config(config: &Value) -> Result<Self, String> {
+        let spec = Self::deserialize(config).map_err(|e| e.to_string())?;
+        let probability = match &spec {
+            SamplingSpec::Head { probability, max_per_second } => {
+                if *max_per_second == Some(0) {
+                    return Err("max_per_second must be greater than 0 when set".to_string());
+                }
+                *probability
+            }
+            SamplingSpec::Tail {
+                decision_wait_seconds,
+                probability,
+                max_pending_traces,
+                ..
+            } => {
+                if *decision_wait_seconds == 0 || *max_pending_traces == 0 {
+                    return Err("decision_wait_seconds and max_pending_traces must be greater than 0".to_string());
+                }
+                *probability
+            }
+        };
+        if !(0.0..=1.0).contains(&probability) {
+            return Err("probability must be between 0 and 1".to_string());
+        }
+        Ok(spec)
+    }
+}
+
+/// Runs one `sample` transform. Records are stored trace spans and logs as
+/// JSON; those sharing a `trace_id` are kept or dropped together.
+pub struct Sampler {
+    spec: SamplingSpec,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Head: recent decisions by trace id, and when they were made.
+    decisions: HashMap<String, (bool, Instant)>,
+    /// Head: start of the current second and traces kept in it.
+    window: Option<(Instant, u32)>,
+    /// Tail: traces waiting for a decision, oldest first.
+    pending: VecDeque<(String, Instant)>,
+    held: HashMap<String, Vec<Value>>,
+    /// Tail: traces already decided, for stragglers that arrive later.
+    decided: HashMap<String, (bool, Instant)>,
+}
+
+impl Sampler {
+    pub fn new(spec: SamplingSpec) -> Self {
+        Self {
+            spec,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Returns the records to pass on now. Tail sampling holds records
+    /// back and returns them from a later `offer` or [`Sampler::flush`].
+    pub fn offer(&self, records: Vec<Value>, now: Instant) -> Vec<Value> {
+        let mut state = self.state.lock().unwrap();
+        match &self.spec {
+            SamplingSpec::Head {
+                probability,
+                max_per_second,
+            } => {
+                state.decisions.retain(|_, (_, at)| now.duration_since(*at) < DECISION_TTL);
+                records
+                    .into_iter()
+                    .filter(|record| match trace_id(record) {
+                        Some(id) => match state.decisions.get(id) {
+                            Some((keep, _)) => *keep,
+                            None => {
+                                let keep = keep_share(Some(id), *probability) && state.admit(*max_per_second, now);
+                                state.decisions.insert(id.to_string(), (keep, now));
+                                keep
+                            }
+                        },
+                        None => keep_share(None, *probability) && state.admit(*max_per_second, now),
+                    })
+                    .collect()
+            }
+            SamplingSpec::Tail { .. } => {
+                let mut passed = Vec::new();
+                for record in records {
+                    let Some(id) = trace_id(&record).map(str::to_string) else {
+                        // Untraced records have nothing to wait for
+                        if self.keep_trace(std::slice::from_ref(&record)) {
+                            passed.push(record);
+                        }
+                        continue;
+                    };
+                    if let Some((keep, _)) = state.decided.get(&id) {
+                        if *keep {
+                            passed.push(record);
+                        }
+                        continue;
+                    }
+                    if !state.held.contains_key(&id) {
+                        state.pending.push_back((id.clone(), now));
+                    }
+                    state.held.entry(id).or_default().push(record);
+                }
+                passed.extend(self.decide(&mut state, now));
+                passed
+            }
+        }
+    }
+
+    /// Tail: records of traces whose decision wait is up. Call it on a
+    /// timer so quiet traces are not held indefinitely.
+    pub fn flush(&self, now: Instant) -> Vec<Value> {
+        match &self.spec {
+            SamplingSpec::Head { .. } => Vec::new(),
+            SamplingSpec::Tail { .. } => self.decide(&mut self.state.lock().unwrap(), now),
+        }
+    }
+
+    fn decide(&self, state: &mut State, now: Instant) -> Vec<Value> {
+        let SamplingSpec::Tail {
+            decision_wait_seconds,
+            max_pending_traces,
+            ..
+        } = &self.spec
+        else {
+            return Vec::new();
+        };
+        let wait = Duration::from_secs(*decision_wait_seconds);
+        state.decided.retain(|_, (_, at)| now.duration_since(*at) < wait + DECISION_TTL);
+
+        let mut kept = Vec::new();
+        while let Some((_, first_seen)) = state.pending.front() {
+            if now.duration_since(*first_seen) < wait && state.pending.len() <= *max_pending_traces {
+                break;
+            }
+            let (id, _) = state.pending.pop_front().expect("front was just checked");
+            let records = state.held.remove(&id).unwrap_or_default();
+            let keep = self.keep_trace(&records);
+            if keep {
+                kept.extend(records);
+            }
+            state.decided.insert(id, (keep, now));
+        }
+        kept
+    }
+
+    fn keep_trace(&self, records: &[Value]) -> bool {
+        let SamplingSpec::Tail {
+            keep_errors,
+            latency_threshold_ms,
+            probability,
+            ..
+        } = &self.spec
+        else {
+            return true;
+        };
+        if *keep_errors && records.iter().any(is_error) {
+            return true;
+        }
+        let slowest = records.iter().filter_map(|r| r.get("duration_ms").and_then(Value::as_i64)).max();
+        if matches!((latency_threshold_ms, slowest), (Some(threshold), Some(slowest)) if slowest >= *threshold) {
+            return true;
+        }
+        keep_share(records.first().and_then(trace_id), *probability)
+    }
+}
+
+impl State {
+    /// Counts one more kept trace against `max_per_second`, if there is room.
+    fn admit(&mut self, max_per_second: Option<u32>, now: Instant) -> bool {
+        let Some(max) = max_per_second else {
+            return true;
+        };
+        let (start, kept) = self.window.get_or_insert((now, 0));
+        if now.duration_since(*start) >= Duration::from_secs(1) {
+            *start = now;
+            *kept = 0;
+        }
+        if *kept >= max {
+            return false;
+        }
+        *kept += 1;
+        true
+    }
+}
+
+fn trace_id(record: &Value) -> Option<&str> {
+    record.get("trace_id").and_then(Value::as_str).filter(|id| !id.is_empty())
+}
+
+/// Error spans carry an error status, error logs an error level.
+fn is_error(record: &Value) -> bool {
+    ["status", "level"].iter().any(|field| {
+        record
+            .get(*field)
+            .and_then(Value::as_str)
+            .map_or(false, |value| value.eq_ignore_ascii_case("error"))
+    })
+}
+
+/// Whether a trace falls in the kept `probability` share. Hex trace ids
+/// are decided from their low 64 bits, like OpenTelemetry's ratio sampler,
+/// and other ids from their stable hash, so every instance agrees.
+fn keep_share(trace_id: Option<&str>, probability: f64) -> bool {
+    if probability >= 1.0 {
+        return true;
+    }
+    if probability <= 0.0 {
+        return false;
+    }
+    let draw = match trace_id {
+        Some(id) => id
+            .get(id.len().saturating_sub(16)..)
+            .and_then(|low| u64::from_str_radix(low, 16).ok())
+            .unwrap_or_else(|| stable_hash(id.as_bytes())),
+        None => rand::thread_rng().gen(),
+    };
+    (draw as f64) < probability * u64::MAX as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn span(trace: &str, status: &str, duration_ms: i64) -> Value {
+        json!({"trace_id": trace, "span_id": "1", "status": status, "duration_ms": duration_ms})
+    }
+
+    #[test]
+    fn test_head_sampling_keeps_traces_whole_and_respects_the_rate() {
+        let sampler = Sampler::new(
+            SamplingSpec::from_config(&json!({"mode": "head", "probability": 0.5, "max_per_second": 2})).unwrap(),
+        );
+        let now = Instant::now();
+        // Low 64 bits of zero is always under the cut, all ones never is
+        let kept = sampler.offer(
+            vec![
+                span("00000000000000000000000000000000", "ok", 1),
+                span("0000000000000000ffffffffffffffff", "ok", 1),
+                span("00000000000000000000000000000000", "ok", 2),
+                span("00000000000000010000000000000001", "ok", 1),
+                span("00000000000000020000000000000002", "ok", 1),
+            ],
+            now,
+        );
+        let ids: Vec<_> = kept.iter().map(|r| r["trace_id"].as_str().unwrap()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "00000000000000000000000000000000",
+                "00000000000000000000000000000000",
+                "00000000000000010000000000000001",
+            ]
+        );
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(sampler.offer(vec![span("00000000000000030000000000000003", "ok", 1)], later).len(), 1);
+    }
+
+    #[test]
+    fn test_ids_that_are_not_hex_are_sampled_by_their_stable_hash() {
+        // These hash to about 0.23 and 0.77 of the range
+        assert!(keep_share(Some("order-42"), 0.5));
+        assert!(!keep_share(Some("order-44"), 0.5));
+    }
+
+    #[test]
+    fn test_tail_sampling_keeps_error_and_slow_traces() {
+        let config = json!({"mode": "tail", "decision_wait_seconds": 5, "latency_threshold_ms": 1000});
+        let sampler = Sampler::new(SamplingSpec::from_config(&config).unwrap());
+        let now = Instant::now();
+
+        let passed = sampler.offer(
+            vec![
+                span("a", "ok", 10),
+                span("a", "error", 20),
+                span("b", "ok", 10),
+                span("c", "ok", 1500),
+                json!({"trace_id": "d", "level": "ERROR", "message": "boom"}),
+            ],
+            now,
+        );
+        assert!(passed.is_empty());
+        assert!(sampler.flush(now + Duration::from_secs(4)).is_empty());
+
+        let kept = sampler.flush(now + Duration::from_secs(5));
+        let ids: Vec<_> = kept.iter().map(|r| r["trace_id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["a", "a", "c", "d"]);
+
+        // Stragglers follow the decision already made for their trace
+        let late = now + Duration::from_secs(6);
+        assert_eq!(sampler.offer(vec![span("a", "ok", 1), span("b", "ok", 1)], late).len(), 1);
+
+        assert!(SamplingSpec::from_config(&json!({"mode": "head", "probability": 1.5})).is_err());
+        assert!(SamplingSpec::from_config(&json!({"mode": "tail", "decision_wait_seconds": 0})).is_err());
+        assert!(SamplingSpec::from_config(&json!({"mode": "random"})).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use crate::pipeline::ScheduleSpec;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
+
+/// Parsed form of a [`ScheduleSpec`]'s cron expression or interval.
+#[derive(Debug, Clone)]
+pub enum Trigger {
+    Cron(Box<cron::Schedule>),
+    Interval(u64),
+}
+
+impl Trigger {
+    pub fn parse(spec: &ScheduleSpec) -> Result<Self> {
+        match (&spec.cron, spec.interval_seconds) {
+            (Some(expression), None) => cron::Schedule::from_str(expression)
+                .map(|schedule| Trigger::Cron(Box::new(schedule)))
+                .map_err(|e| anyhow::anyhow!("Invalid cron expression {:?}: {}", expression, e)),
+            (None, Some(0)) => Err(anyhow::anyhow!("Schedule interval must be greater than 0")),
+            (None, Some(seconds)) => Ok(Trigger::Interval(seconds)),
+            (Some(_), Some(_)) => Err(anyhow::anyhow!("Schedule sets both cron and interval_seconds")),
+            (None, None) => Err(anyhow::anyhow!("Schedule requires cron or interval_seconds")),
+        }
+    }
+
+    /// First trigger time strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Trigger::Cron(schedule) => schedule.after(&after).next(),
+            Trigger::Interval(seconds) => {
+                let seconds = *seconds as i64;
+                let next = (after.timestamp().div_euclid(seconds) + 1) * seconds;
+                DateTime::from_timestamp(next, 0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_trigger_next_after() {
+        let hourly = Trigger::parse(&ScheduleSpec {
+            cron: Some("0 0 * * * *".to_string()),
+            window_seconds: 3600,
+            ..ScheduleSpec::default()
+        })
+        .unwrap();
+        assert_eq!(hourly.next_after(at("2024-01-01T10:00:00Z")), Some(at("2024-01-01T11:00:00Z")));
+
+        let every_five_minutes = Trigger::Interval(300);
+        assert_eq!(
+            every_five_minutes.next_after(at("2024-01-01T10:07:30Z")),
+            Some(at("2024-01-01T10:10:00Z"))
+        );
+
+        assert!(Trigger::parse(&ScheduleSpec::default()).is_err());
+        assert!(Trigger::parse(&ScheduleSpec {
+            cron: Some("every hour".to_string()),
+            ..ScheduleSpec::default()
+        })
+        .is_err());
+    }
+}
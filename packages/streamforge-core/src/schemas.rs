@@ -0,0 +1,527 @@
+//! Schema tracking for the messages of each topic, following
+//! `processing.schemas`. The shape of every processed message (the kind of
+//! each field, by dotted path) is compared with the current version of its
+//! topic's schema; a message that adds a field, lacks a field every earlier
+//! message had, or sends a field as another kind starts a new version,
+//! which is stored and checked against the topic's compatibility mode.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use streamforge_sdk::Alert;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::config::{ProcessingConfig, SchemaCompatibility, SchemaConfig, SchemaViolationAction};
+use crate::metrics::Metrics;
+use crate::processor::ProcessedMessage;
+use crate::storage::StorageBackend;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    String,
+    Number,
+    Boolean,
+    Array,
+    /// An object tracked as a whole: opaque, nested too deep or empty.
+    Object,
+}
+
+impl FieldKind {
+    fn of(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => None,
+            Value::String(_) => Some(FieldKind::String),
+            Value::Number(_) => Some(FieldKind::Number),
+            Value::Bool(_) => Some(FieldKind::Boolean),
+            Value::Array(_) => Some(FieldKind::Array),
+            Value::Object(_) => Some(FieldKind::Object),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FieldKind::String => "string",
+            FieldKind::Number => "number",
+            FieldKind::Boolean => "boolean",
+            FieldKind::Array => "array",
+            FieldKind::Object => "object",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    /// `None` while the field has only been null.
+    pub kind: Option<FieldKind>,
+    /// Whether every message since the first has had the field.
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchemaChanges {
+    #[serde(default)]
+    pub added: Vec<String>,
+    /// Required fields a message was sent without; they become optional.
+    #[serde(default)]
+    pub removed: Vec<String>,
+    #[serde(default)]
+    pub retyped: Vec<Retyped>,
+}
+
+impl SchemaChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.retyped.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Retyped {
+    pub field: String,
+    pub from: FieldKind,
+    pub to: FieldKind,
+}
+
+/// One version of the schema of a tenant's topic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaVersion {
+    pub tenant_id: String,
+    pub topic: String,
+    /// Starts at 1 and is incremented by every change.
+    pub version: i64,
+    pub fields: BTreeMap<String, FieldSchema>,
+    /// How the version differs from the one before; empty for the first.
+    pub changes: SchemaChanges,
+    /// Ways the changes break the topic's compatibility mode.
+    pub violations: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A message that changed its topic's schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaDrift {
+    /// The new version; not adopted when `rejected`.
+    pub version: SchemaVersion,
+    pub compatibility: SchemaCompatibility,
+    /// Whether the message must be dead-lettered.
+    pub rejected: bool,
+    /// Whether to alert: on violations, and while rejecting, only the first
+    /// time each set of violations is seen.
+    pub alerted: bool,
+}
+
+impl SchemaDrift {
+    /// Alert raised for the violations, for the notifier.
+    pub fn alert(&self) -> Alert {
+        let version = &self.version;
+        let metadata = [
+            ("topic".to_string(), Value::from(version.topic.clone())),
+            ("version".to_string(), Value::from(version.version)),
+            ("compatibility".to_string(), Value::from(self.compatibility_name())),
+            ("violations".to_string(), Value::from(version.violations.clone())),
+            ("rejected".to_string(), Value::from(self.rejected)),
+        ];
+        Alert {
+            id: uuid::Uuid::new_v4().to_string(),
+            severity: if self.rejected { "critical" } else { "warning" }.to_string(),
+            message: format!(
+                "Schema of topic {} changed incompatibly: {}",
+                version.topic,
+                version.violations.join("; ")
+            ),
+            timestamp: Utc::now().timestamp_millis() as u64,
+            service: "stream-processor".to_string(),
+            metadata: Some(metadata.into_iter().collect()),
+        }
+    }
+
+    /// Why the message was rejected, for its dead letter.
+    pub fn error(&self) -> String {
+        format!(
+            "Message breaks {} compatibility of the schema of topic {}: {}",
+            self.compatibility_name(),
+            self.version.topic,
+            self.version.violations.join("; ")
+        )
+    }
+
+    fn compatibility_name(&self) -> &'static str {
+        match self.compatibility {
+            SchemaCompatibility::None => "none",
+            SchemaCompatibility::Backward => "backward",
+            SchemaCompatibility::Forward => "forward",
+            SchemaCompatibility::Full => "full",
+        }
+    }
+}
+
+struct Topic {
+    current: SchemaVersion,
+    /// Violation sets already alerted on while rejecting.
+    rejected: HashSet<Vec<String>>,
+}
+
+/// Schemas of each tenant's topics, following `processing.schemas`.
+pub struct SchemaRegistry {
+    settings: watch::Receiver<ProcessingConfig>,
+    storage: Arc<dyn StorageBackend>,
+    topics: Mutex<HashMap<(String, String), Topic>>,
+    metrics: Arc<Metrics>,
+}
+
+impl SchemaRegistry {
+    pub fn new(
+        settings: watch::Receiver<ProcessingConfig>,
+        storage: Arc<dyn StorageBackend>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            settings,
+            storage,
+            topics: Mutex::new(HashMap::new()),
+            metrics,
+        }
+    }
+
+    /// Picks up the latest stored version of every topic's schema.
+    pub async fn load(&self) -> anyhow::Result<usize> {
+        let versions = self.storage.list_latest_schema_versions().await?;
+        let loaded = versions.len();
+        let mut topics = self.topics.lock().unwrap();
+        for version in versions {
+            let key = (version.tenant_id.clone(), version.topic.clone());
+            topics.insert(
+                key,
+                Topic {
+                    current: version,
+                    rejected: HashSet::new(),
+                },
+            );
+        }
+        Ok(loaded)
+    }
+
+    /// Compares the processed record of `message` with its topic's schema.
+    /// Records that are not objects are not tracked.
+    pub fn observe(&self, message: &ProcessedMessage) -> Option<SchemaDrift> {
+        let settings = self.settings.borrow();
+        let config = &settings.schemas;
+        if !config.enabled || !message.processed_message.is_object() {
+            return None;
+        }
+        let metadata = &message.processing_metadata;
+        let shape = shape(&message.processed_message, config);
+        let compatibility = config
+            .overrides
+            .get(&metadata.source_topic)
+            .copied()
+            .unwrap_or(config.compatibility);
+
+        let mut topics = self.topics.lock().unwrap();
+        let key = (metadata.tenant_id.clone(), metadata.source_topic.clone());
+        let Some(topic) = topics.get_mut(&key) else {
+            let fields = shape
+                .into_iter()
+                .take(config.max_fields)
+                .map(|(field, kind)| (field, FieldSchema { kind, required: true }))
+                .collect();
+            let current = SchemaVersion {
+                tenant_id: metadata.tenant_id.clone(),
+                topic: metadata.source_topic.clone(),
+                version: 1,
+                fields,
+                changes: SchemaChanges::default(),
+                violations: Vec::new(),
+                created_at: Utc::now(),
+            };
+            topics.insert(
+                key,
+                Topic {
+                    current: current.clone(),
+                    rejected: HashSet::new(),
+                },
+            );
+            self.metrics.increment_schema_changes(&metadata.source_topic, "registered");
+            return Some(SchemaDrift {
+                version: current,
+                compatibility,
+                rejected: false,
+                alerted: false,
+            });
+        };
+
+        let (changes, fields) = diff(&topic.current.fields, &shape, config.max_fields);
+        if changes.is_empty() {
+            // Fields seen only as null so far may have a kind now
+            topic.current.fields = fields;
+            return None;
+        }
+        let violations = violations(&changes, compatibility);
+        let version = SchemaVersion {
+            tenant_id: metadata.tenant_id.clone(),
+            topic: metadata.source_topic.clone(),
+            version: topic.current.version + 1,
+            fields,
+            changes,
+            violations,
+            created_at: Utc::now(),
+        };
+
+        let compatible = version.violations.is_empty();
+        if !compatible && config.on_violation == SchemaViolationAction::Reject {
+            self.metrics.increment_schema_changes(&metadata.source_topic, "rejected");
+            let alerted = topic.rejected.insert(version.violations.clone());
+            return Some(SchemaDrift {
+                version,
+                compatibility,
+                rejected: true,
+                alerted,
+            });
+        }
+        let outcome = if compatible { "compatible" } else { "incompatible" };
+        self.metrics.increment_schema_changes(&metadata.source_topic, outcome);
+        topic.current = version.clone();
+        topic.rejected.clear();
+        Some(SchemaDrift {
+            version,
+            compatibility,
+            rejected: false,
+            alerted: !compatible,
+        })
+    }
+
+    /// Stores the version a drift adopted. When another instance stored a
+    /// version of that number first, its latest version is adopted instead.
+    pub async fn record(&self, drift: &SchemaDrift) {
+        if drift.rejected {
+            return;
+        }
+        let version = &drift.version;
+        match self.storage.create_schema_version(version).await {
+            Ok(true) => info!(
+                "Schema of topic {} for tenant {} is now version {}",
+                version.topic, version.tenant_id, version.version
+            ),
+            Ok(false) => match self.storage.list_schema_versions(&version.tenant_id, &version.topic).await {
+                Ok(mut stored) => {
+                    if let Some(latest) = stored.pop() {
+                        let mut topics = self.topics.lock().unwrap();
+                        let key = (version.tenant_id.clone(), version.topic.clone());
+                        if let Some(topic) = topics.get_mut(&key).filter(|t| t.current.version <= latest.version) {
+                            topic.current = latest;
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to reload schema of topic {}: {}", version.topic, e),
+            },
+            Err(e) => warn!(
+                "Failed to store version {} of the schema of topic {}: {}",
+                version.version, version.topic, e
+            ),
+        }
+    }
+}
+
+/// The kind of every field of `record`, by dotted path. Objects are walked
+/// down to `max_depth` unless opaque or empty.
+fn shape(record: &Value, config: &SchemaConfig) -> BTreeMap<String, Option<FieldKind>> {
+    fn walk(
+        path: String,
+        value: &Value,
+        depth: usize,
+        config: &SchemaConfig,
+        out: &mut BTreeMap<String, Option<FieldKind>>,
+    ) {
+        match value {
+            Value::Object(object)
+                if depth < config.max_depth && !object.is_empty() && !config.opaque_fields.contains(&path) =>
+            {
+                for (key, value) in object {
+                    walk(format!("{}.{}", path, key), value, depth + 1, config, out);
+                }
+            }
+            value => {
+                out.insert(path, FieldKind::of(value));
+            }
+        }
+    }
+
+    let mut out = BTreeMap::new();
+    if let Value::Object(object) = record {
+        for (key, value) in object {
+            walk(key.clone(), value, 1, config, &mut out);
+        }
+    }
+    out
+}
+
+/// How a message of `shape` changes `fields`, and the fields it leaves.
+/// Fields past `max_fields` are not added.
+fn diff(
+    fields: &BTreeMap<String, FieldSchema>,
+    shape: &BTreeMap<String, Option<FieldKind>>,
+    max_fields: usize,
+) -> (SchemaChanges, BTreeMap<String, FieldSchema>) {
+    let mut changes = SchemaChanges::default();
+    let mut next = fields.clone();
+    for (field, kind) in shape {
+        match next.get_mut(field) {
+            Some(schema) => match (schema.kind, *kind) {
+                (Some(from), Some(to)) if from != to => {
+                    changes.retyped.push(Retyped {
+                        field: field.clone(),
+                        from,
+                        to,
+                    });
+                    schema.kind = Some(to);
+                }
+                (None, Some(to)) => schema.kind = Some(to),
+                _ => {}
+            },
+            None if next.len() < max_fields => {
+                changes.added.push(field.clone());
+                next.insert(field.clone(), FieldSchema { kind: *kind, required: false });
+            }
+            None => {}
+        }
+    }
+    for (field, schema) in next.iter_mut() {
+        if schema.required && !shape.contains_key(field) {
+            changes.removed.push(field.clone());
+            schema.required = false;
+        }
+    }
+    (changes, next)
+}
+
+fn violations(changes: &SchemaChanges, compatibility: SchemaCompatibility) -> Vec<String> {
+    let (added, removed) = match compatibility {
+        SchemaCompatibility::None => return Vec::new(),
+        SchemaCompatibility::Backward => (true, false),
+        SchemaCompatibility::Forward => (false, true),
+        SchemaCompatibility::Full => (true, true),
+    };
+    let mut violations = Vec::new();
+    if added {
+        violations.extend(changes.added.iter().map(|field| format!("{} was added", field)));
+    }
+    if removed {
+        violations.extend(changes.removed.iter().map(|field| format!("{} is missing", field)));
+    }
+    violations.extend(
+        changes
+            .retyped
+            .iter()
+            .map(|r| format!("{} changed from {} to {}", r.field, r.from.name(), r.to.name())),
+    );
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+    use crate::processor::ProcessingMetadata;
+    use crate::storage::MemoryStorage;
+    use serde_json::json;
+
+    fn message(topic: &str, record: Value) -> ProcessedMessage {
+        ProcessedMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            original_message: record.clone(),
+            processed_message: record,
+            processing_metadata: ProcessingMetadata {
+                tenant_id: "acme".to_string(),
+                processed_at: Utc::now(),
+                processor_version: "test".to_string(),
+                source_topic: topic.to_string(),
+                partition: 0,
+                offset: 0,
+                traceparent: None,
+            },
+        }
+    }
+
+    fn registry(schemas: SchemaConfig, storage: Arc<dyn StorageBackend>) -> SchemaRegistry {
+        let (_, settings) = watch::channel(ProcessingConfig {
+            schemas,
+            ..ProcessingConfig::default()
+        });
+        let metrics = Arc::new(Metrics::new(&MetricsConfig::default()).unwrap());
+        SchemaRegistry::new(settings, storage, metrics)
+    }
+
+    #[tokio::test]
+    async fn test_schema_changes_are_versioned_and_checked() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(MemoryStorage::with_capacity(100));
+        let config = SchemaConfig {
+            enabled: true,
+            overrides: [("audit".to_string(), SchemaCompatibility::Full)].into_iter().collect(),
+            ..SchemaConfig::default()
+        };
+        let schemas = registry(config.clone(), storage.clone());
+
+        let first = json!({"level": "info", "http": {"status": 200}, "labels": {"pod": "api-0"}, "user": null});
+        let drift = schemas.observe(&message("logs", first.clone())).unwrap();
+        assert_eq!(drift.version.version, 1);
+        assert!(!drift.alerted);
+        assert_eq!(
+            drift.version.fields.keys().collect::<Vec<_>>(),
+            vec!["http.status", "labels", "level", "user"]
+        );
+        schemas.record(&drift).await;
+
+        // Same shape, new label keys and a first value for a null field
+        let same = json!({"level": "warn", "http": {"status": 500}, "labels": {"node": "n1"}, "user": "bob"});
+        assert_eq!(schemas.observe(&message("logs", same)), None);
+
+        // Forward compatibility allows added fields
+        let added = json!({"level": "info", "http": {"status": 200}, "labels": {}, "user": "bob", "region": "eu"});
+        let drift = schemas.observe(&message("logs", added)).unwrap();
+        assert_eq!(drift.version.version, 2);
+        assert_eq!(drift.version.changes.added, vec!["region"]);
+        assert!(drift.version.violations.is_empty() && !drift.alerted);
+        schemas.record(&drift).await;
+
+        // but not missing or retyped ones
+        let broken = json!({"level": 3, "labels": {}, "user": "bob"});
+        let drift = schemas.observe(&message("logs", broken.clone())).unwrap();
+        assert_eq!(drift.version.version, 3);
+        assert_eq!(
+            drift.version.violations,
+            vec!["http.status is missing", "level changed from string to number"]
+        );
+        assert!(drift.alerted && !drift.rejected);
+        schemas.record(&drift).await;
+        assert_eq!(drift.alert().severity, "warning");
+
+        let stored = storage.list_schema_versions("acme", "logs").await.unwrap();
+        assert_eq!(stored.iter().map(|v| v.version).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(!stored[2].fields["http.status"].required);
+
+        // Rejecting keeps the version and alerts once per set of violations
+        let rejecting = registry(
+            SchemaConfig {
+                on_violation: SchemaViolationAction::Reject,
+                ..config
+            },
+            storage.clone(),
+        );
+        assert_eq!(rejecting.load().await.unwrap(), 1);
+        let drift = rejecting.observe(&message("logs", json!({"level": "info"}))).unwrap();
+        assert!(drift.rejected && drift.alerted);
+        assert!(drift.error().contains("forward compatibility"));
+        let again = rejecting.observe(&message("logs", json!({"level": "info"}))).unwrap();
+        assert!(again.rejected && !again.alerted);
+        assert_eq!(again.version.version, 4);
+
+        // Overrides apply per topic
+        rejecting.observe(&message("audit", json!({"actor": "ops"})));
+        let drift = rejecting.observe(&message("audit", json!({"actor": "ops", "target": "job"}))).unwrap();
+        assert_eq!(drift.version.violations, vec!["target was added"]);
+        assert!(drift.rejected);
+    }
+}
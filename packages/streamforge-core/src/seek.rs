@@ -0,0 +1,162 @@
+//! Per-topic start positions from `kafka.start_positions`, applied the first
+//! time this process consumes each partition.
+
+use anyhow::Result;
+use rdkafka::consumer::{Consumer, ConsumerContext};
+use rdkafka::{Offset, TopicPartitionList};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+use crate::config::{KafkaConfig, StartPosition};
+
+const BROKER_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct StartPositions {
+    topics: HashMap<String, Start>,
+    /// Partitions already positioned, or found not to need it.
+    settled: HashSet<(String, i32)>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Start {
+    position: Position,
+    override_committed: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Position {
+    Earliest,
+    Latest,
+    /// Milliseconds since the epoch.
+    Timestamp(i64),
+    Offsets(BTreeMap<i32, i64>),
+}
+
+impl StartPositions {
+    /// Offset files are read here, so a missing or malformed one stops the
+    /// consumer before it joins the group.
+    pub fn load(config: &KafkaConfig) -> Result<Self> {
+        let mut topics = HashMap::new();
+        for (topic, start) in &config.start_positions {
+            let position = match &start.position {
+                StartPosition::Earliest => Position::Earliest,
+                StartPosition::Latest => Position::Latest,
+                StartPosition::Timestamp { timestamp } => Position::Timestamp(timestamp.timestamp_millis()),
+                StartPosition::Offsets { offsets } => Position::Offsets(
+                    partition_offsets(offsets)
+                        .map_err(|e| anyhow::anyhow!("Invalid start offsets for {}: {}", topic, e))?,
+                ),
+                StartPosition::File { path } => {
+                    let data = std::fs::read(path)
+                        .map_err(|e| anyhow::anyhow!("Failed to read start offsets for {} from {}: {}", topic, path, e))?;
+                    let offsets = serde_json::from_slice(&data)
+                        .map_err(|e| e.to_string())
+                        .and_then(|offsets| partition_offsets(&offsets))
+                        .map_err(|e| anyhow::anyhow!("Failed to parse start offsets for {} in {}: {}", topic, path, e))?;
+                    Position::Offsets(offsets)
+                }
+            };
+            topics.insert(
+                topic.clone(),
+                Start {
+                    position,
+                    override_committed: start.override_committed,
+                },
+            );
+        }
+        Ok(Self {
+            topics,
+            settled: HashSet::new(),
+        })
+    }
+
+    /// Where to seek `partition` of `topic` when its first message arrives,
+    /// or `None` to keep reading from where the consumer is. Blocks on
+    /// broker requests for committed offsets and timestamps.
+    pub fn target<C: ConsumerContext>(
+        &mut self,
+        consumer: &impl Consumer<C>,
+        topic: &str,
+        partition: i32,
+    ) -> Result<Option<Offset>> {
+        let Some(start) = self.topics.get(topic) else {
+            return Ok(None);
+        };
+        if !self.settled.insert((topic.to_string(), partition)) {
+            return Ok(None);
+        }
+
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition(topic, partition);
+        if !start.override_committed {
+            let committed = consumer
+                .committed_offsets(tpl.clone(), BROKER_TIMEOUT)
+                .map_err(|e| anyhow::anyhow!("Failed to fetch committed offset for {}/{}: {}", topic, partition, e))?;
+            if committed.elements().iter().any(|e| matches!(e.offset(), Offset::Offset(_))) {
+                return Ok(None);
+            }
+        }
+
+        Ok(match &start.position {
+            Position::Earliest => Some(Offset::Beginning),
+            Position::Latest => Some(Offset::End),
+            Position::Timestamp(millis) => {
+                tpl.set_partition_offset(topic, partition, Offset::Offset(*millis))?;
+                let found = consumer
+                    .offsets_for_times(tpl, BROKER_TIMEOUT)
+                    .map_err(|e| anyhow::anyhow!("Failed to look up offset by time for {}/{}: {}", topic, partition, e))?;
+                // No message at or after the timestamp comes back as the end
+                found.elements().first().map(|e| e.offset())
+            }
+            Position::Offsets(offsets) => offsets.get(&partition).map(|offset| Offset::Offset(*offset)),
+        })
+    }
+}
+
+fn partition_offsets(offsets: &BTreeMap<String, i64>) -> Result<BTreeMap<i32, i64>, String> {
+    offsets
+        .iter()
+        .map(|(partition, offset)| match partition.parse::<i32>() {
+            Ok(p) if p >= 0 && *offset >= 0 => Ok((p, *offset)),
+            _ => Err(format!("{:?}: {} is not a partition and offset", partition, offset)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StartPositionConfig;
+
+    #[test]
+    fn test_load_reads_offset_files() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), r#"{"0": 1200, "3": 40}"#).unwrap();
+
+        let mut config = KafkaConfig::default();
+        config.start_positions.insert(
+            "metrics".to_string(),
+            StartPositionConfig {
+                position: StartPosition::File {
+                    path: file.path().to_string_lossy().to_string(),
+                },
+                override_committed: false,
+            },
+        );
+        let positions = StartPositions::load(&config).unwrap();
+        assert_eq!(
+            positions.topics["metrics"].position,
+            Position::Offsets([(0, 1200), (3, 40)].into())
+        );
+
+        std::fs::write(file.path(), "[1200, 40]").unwrap();
+        let err = StartPositions::load(&config).err().unwrap().to_string();
+        assert!(err.starts_with("Failed to parse start offsets for metrics"), "{}", err);
+
+        let parsed: StartPositionConfig =
+            serde_json::from_str(r#"{"from": "timestamp", "timestamp": "2026-01-01T00:00:00Z", "override_committed": true}"#)
+                .unwrap();
+        assert!(parsed.override_committed);
+        assert!(matches!(parsed.position, StartPosition::Timestamp { .. }));
+    }
+}
@@ -0,0 +1,306 @@
+//! Session windows over processed records, configured in `sessions`, with
+//! a summary of each ended session produced to the output topic.
+
+use chrono::{DateTime, Utc};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::columnar::lookup;
+use crate::config::SessionWindowConfig;
+use crate::metrics::Metrics;
+use crate::processor::ProcessedMessage;
+
+/// How often sessions are checked for an expired gap.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    /// No event arrived for `gap`.
+    Gap,
+    /// The session spanned `max_duration`.
+    MaxDuration,
+    /// Ended early to stay within `max_open_sessions`.
+    Capacity,
+}
+
+impl CloseReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            CloseReason::Gap => "gap",
+            CloseReason::MaxDuration => "max_duration",
+            CloseReason::Capacity => "capacity",
+        }
+    }
+}
+
+/// What is produced for each ended session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub tenant_id: String,
+    /// The field sessions are keyed by and this session's value of it.
+    pub key_field: String,
+    pub key: String,
+    pub event_count: u64,
+    pub first_event_at: DateTime<Utc>,
+    pub last_event_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    pub closed_by: CloseReason,
+}
+
+pub struct SessionTracker {
+    config: SessionWindowConfig,
+    state: Mutex<State>,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Open sessions keyed by `(tenant_id, key)`.
+    open: HashMap<(String, String), OpenSession>,
+    /// Sessions ended by a later event, waiting to be produced.
+    closed: Vec<SessionSummary>,
+}
+
+struct OpenSession {
+    id: String,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+    events: u64,
+    /// Processing time of the latest event; gaps are timed from it, so
+    /// sessions of late-arriving events are not ended as soon as they open.
+    last_arrival: DateTime<Utc>,
+}
+
+impl SessionTracker {
+    pub fn new(config: SessionWindowConfig, metrics: Arc<Metrics>) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+            metrics,
+        }
+    }
+
+    /// Adds the records among `messages` that carry the key field to their
+    /// sessions.
+    pub fn observe(&self, messages: &[ProcessedMessage]) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        for message in messages {
+            let metadata = &message.processing_metadata;
+            if !self.config.topics.is_empty() && !self.config.topics.contains(&metadata.source_topic) {
+                continue;
+            }
+            let record = &message.processed_message;
+            let key = match lookup(record, &self.config.key_field) {
+                Some(Value::String(s)) if !s.is_empty() => s.clone(),
+                Some(Value::Number(n)) => n.to_string(),
+                _ => continue,
+            };
+            let at = lookup(record, &self.config.time_field)
+                .and_then(event_time)
+                .unwrap_or(metadata.processed_at);
+            let id = (metadata.tenant_id.clone(), key);
+
+            if let Some(session) = state.open.get_mut(&id) {
+                let reason = if longer(at - session.last, self.config.gap) {
+                    Some(CloseReason::Gap)
+                } else if longer(at.max(session.last) - session.first.min(at), self.config.max_duration) {
+                    Some(CloseReason::MaxDuration)
+                } else {
+                    None
+                };
+                match reason {
+                    None => {
+                        session.first = session.first.min(at);
+                        session.last = session.last.max(at);
+                        session.events += 1;
+                        session.last_arrival = metadata.processed_at;
+                        continue;
+                    }
+                    Some(reason) => {
+                        let session = state.open.remove(&id).expect("session was just found");
+                        let summary = self.summary(&id, session, reason);
+                        state.closed.push(summary);
+                    }
+                }
+            } else if state.open.len() >= self.config.max_open_sessions {
+                let idle = state
+                    .open
+                    .iter()
+                    .min_by_key(|(_, session)| session.last_arrival)
+                    .map(|(id, _)| id.clone());
+                if let Some(idle) = idle {
+                    let session = state.open.remove(&idle).expect("session was just found");
+                    let summary = self.summary(&idle, session, CloseReason::Capacity);
+                    state.closed.push(summary);
+                }
+            }
+
+            state.open.insert(
+                id,
+                OpenSession {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    first: at,
+                    last: at,
+                    events: 1,
+                    last_arrival: metadata.processed_at,
+                },
+            );
+        }
+        self.metrics.set_sessions_open(state.open.len());
+    }
+
+    /// Ends the sessions that have had no event for `gap` by `now`, and
+    /// returns them along with those ended since the last call.
+    pub fn expire(&self, now: DateTime<Utc>) -> Vec<SessionSummary> {
+        let mut state = self.state.lock().unwrap();
+        let expired: Vec<_> = state
+            .open
+            .iter()
+            .filter(|(_, session)| {
+                (now - session.last_arrival)
+                    .to_std()
+                    .map_or(false, |idle| idle >= self.config.gap)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired {
+            let session = state.open.remove(&id).expect("session was just found");
+            let summary = self.summary(&id, session, CloseReason::Gap);
+            state.closed.push(summary);
+        }
+        self.metrics.set_sessions_open(state.open.len());
+        std::mem::take(&mut state.closed)
+    }
+
+    fn summary(&self, (tenant_id, key): &(String, String), session: OpenSession, reason: CloseReason) -> SessionSummary {
+        self.metrics.increment_sessions_closed(reason.as_str());
+        SessionSummary {
+            session_id: session.id,
+            tenant_id: tenant_id.clone(),
+            key_field: self.config.key_field.clone(),
+            key: key.clone(),
+            event_count: session.events,
+            first_event_at: session.first,
+            last_event_at: session.last,
+            duration_ms: (session.last - session.first).num_milliseconds(),
+            closed_by: reason,
+        }
+    }
+}
+
+/// Whether `elapsed` is over `limit`; never for negative `elapsed`, such as
+/// events arriving out of order.
+fn longer(elapsed: chrono::Duration, limit: Duration) -> bool {
+    elapsed.to_std().map_or(false, |elapsed| elapsed > limit)
+}
+
+fn event_time(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|t| t.with_timezone(&Utc)),
+        Value::Number(n) => n.as_i64().and_then(DateTime::from_timestamp_millis),
+        _ => None,
+    }
+}
+
+/// Produces ended sessions to the output topic, keyed by the session key
+/// so each key's sessions stay in order.
+pub async fn run(tracker: Arc<SessionTracker>, producer: FutureProducer) {
+    info!(
+        "Tracking sessions by {} with a {:?} gap into {}",
+        tracker.config.key_field, tracker.config.gap, tracker.config.output_topic
+    );
+
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        for summary in tracker.expire(Utc::now()) {
+            let payload = match serde_json::to_vec(&summary) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("Failed to encode session {}: {}", summary.session_id, e);
+                    continue;
+                }
+            };
+            let record = FutureRecord::to(&tracker.config.output_topic)
+                .key(&summary.key)
+                .payload(&payload);
+            if let Err((e, _)) = producer.send(record, Duration::from_secs(5)).await {
+                warn!("Failed to produce session {} to {}: {}", summary.session_id, tracker.config.output_topic, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+    use crate::processor::ProcessingMetadata;
+    use serde_json::json;
+
+    fn event(user: &str, at: DateTime<Utc>) -> ProcessedMessage {
+        let record = json!({"user_id": user, "event": "click", "timestamp": at.to_rfc3339()});
+        ProcessedMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            original_message: record.clone(),
+            processed_message: record,
+            processing_metadata: ProcessingMetadata {
+                tenant_id: "acme".to_string(),
+                processed_at: at,
+                processor_version: "test".to_string(),
+                source_topic: "events".to_string(),
+                partition: 0,
+                offset: 0,
+                traceparent: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_sessions_end_on_gap_and_max_duration() {
+        let config = SessionWindowConfig {
+            enabled: true,
+            gap: Duration::from_secs(60),
+            max_duration: Duration::from_secs(300),
+            ..Default::default()
+        };
+        let tracker = SessionTracker::new(config, Arc::new(Metrics::new(&MetricsConfig::default()).unwrap()));
+        let start = Utc::now();
+        let secs = chrono::Duration::seconds;
+
+        tracker.observe(&[event("alice", start), event("alice", start + secs(30)), event("bob", start)]);
+        assert!(tracker.expire(start + secs(59)).is_empty());
+        // Every 50s keeps alice's session open until it would span over 300s
+        tracker.observe(&(1..=7).map(|i| event("alice", start + secs(30 + 50 * i))).collect::<Vec<_>>());
+
+        let mut ended = tracker.expire(start + secs(430));
+        ended.sort_by_key(|s| s.key.clone());
+        assert_eq!(ended.len(), 2);
+        let (alice, bob) = (&ended[0], &ended[1]);
+        assert_eq!((alice.key.as_str(), alice.event_count, alice.duration_ms), ("alice", 7, 280_000));
+        assert_eq!(alice.closed_by, CloseReason::MaxDuration);
+        assert_eq!((bob.key.as_str(), bob.event_count, bob.closed_by), ("bob", 1, CloseReason::Gap));
+
+        // The event over max_duration opened a new session
+        let rest = tracker.expire(start + secs(1000));
+        assert_eq!(rest.len(), 1);
+        assert_eq!((rest[0].event_count, rest[0].first_event_at), (2, start + secs(330)));
+        assert_ne!(rest[0].session_id, alice.session_id);
+
+        tracker.observe(&[ProcessedMessage {
+            processed_message: json!({"event": "click"}),
+            ..event("carol", start)
+        }]);
+        assert!(tracker.expire(start + secs(2000)).is_empty());
+    }
+}
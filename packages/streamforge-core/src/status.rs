@@ -0,0 +1,223 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use streamforge_sdk::{Metric, ServiceStatus};
+use tracing::{info, warn};
+
+use crate::config::ServiceStatusConfig;
+use crate::hub::Hub;
+use crate::processor::ProcessedMessage;
+use crate::storage::{ServiceStatusRecord, StorageBackend};
+
+pub const STATUS_HEALTHY: &str = "healthy";
+pub const STATUS_DEGRADED: &str = "degraded";
+pub const STATUS_DOWN: &str = "down";
+
+/// Derives per-service status, uptime, request rate and response time from
+/// processed metrics labelled with a `service`, over the sliding window
+/// configured in `service_status`.
+pub struct StatusTracker {
+    config: ServiceStatusConfig,
+    /// Windows keyed by `(tenant_id, service)`.
+    services: Mutex<HashMap<(String, String), ServiceWindow>>,
+}
+
+struct ServiceWindow {
+    last_seen: DateTime<Utc>,
+    /// Response time samples in milliseconds, oldest first.
+    samples: VecDeque<(DateTime<Utc>, f64)>,
+    /// Whether each evaluation in the window found the service up.
+    checks: VecDeque<(DateTime<Utc>, bool)>,
+}
+
+impl StatusTracker {
+    pub fn new(config: ServiceStatusConfig) -> Self {
+        Self {
+            config,
+            services: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records the metrics among `messages` that name their service.
+    pub fn observe(&self, messages: &[ProcessedMessage]) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut services = self.services.lock().unwrap_or_else(|e| e.into_inner());
+        for message in messages {
+            let Ok(metric) = Metric::deserialize(&message.processed_message) else {
+                continue;
+            };
+            let Some(service) = metric.labels.as_ref().and_then(|labels| labels.get("service")) else {
+                continue;
+            };
+
+            let seen_at = message.processing_metadata.processed_at;
+            let key = (message.processing_metadata.tenant_id.clone(), service.clone());
+            let window = services.entry(key).or_insert_with(|| ServiceWindow {
+                last_seen: seen_at,
+                samples: VecDeque::new(),
+                checks: VecDeque::new(),
+            });
+            window.last_seen = window.last_seen.max(seen_at);
+            if metric.name == self.config.response_time_metric && metric.value.is_finite() {
+                window.samples.push_back((seen_at, metric.value));
+            }
+        }
+    }
+
+    /// Computes the status of every service seen so far as of `now`, and
+    /// records the check towards uptime.
+    pub fn evaluate(&self, now: DateTime<Utc>) -> Vec<ServiceStatusRecord> {
+        let window = chrono::Duration::from_std(self.config.window).unwrap_or_else(|_| chrono::Duration::minutes(5));
+        let stale_after =
+            chrono::Duration::from_std(self.config.stale_after).unwrap_or_else(|_| chrono::Duration::minutes(1));
+        let since = now - window;
+
+        let mut services = self.services.lock().unwrap_or_else(|e| e.into_inner());
+        let mut statuses: Vec<ServiceStatusRecord> = services
+            .iter_mut()
+            .map(|((tenant_id, name), service)| {
+                while service.samples.front().map_or(false, |(at, _)| *at < since) {
+                    service.samples.pop_front();
+                }
+                let up = now - service.last_seen <= stale_after;
+                service.checks.push_back((now, up));
+                while service.checks.front().map_or(false, |(at, _)| *at < since) {
+                    service.checks.pop_front();
+                }
+
+                let response_time = if service.samples.is_empty() {
+                    0.0
+                } else {
+                    service.samples.iter().map(|(_, ms)| ms).sum::<f64>() / service.samples.len() as f64
+                };
+                let status = if !up {
+                    STATUS_DOWN
+                } else if response_time > self.config.degraded_response_time_ms {
+                    STATUS_DEGRADED
+                } else {
+                    STATUS_HEALTHY
+                };
+                let up_checks = service.checks.iter().filter(|(_, up)| *up).count();
+
+                ServiceStatusRecord {
+                    tenant_id: tenant_id.clone(),
+                    name: name.clone(),
+                    status: status.to_string(),
+                    uptime: up_checks as f64 * 100.0 / service.checks.len() as f64,
+                    response_time,
+                    requests_per_second: service.samples.len() as f64 / window.num_milliseconds() as f64 * 1000.0,
+                    last_seen: service.last_seen,
+                    last_check: now,
+                }
+            })
+            .collect();
+        statuses.sort_by(|a, b| (&a.tenant_id, &a.name).cmp(&(&b.tenant_id, &b.name)));
+        statuses
+    }
+}
+
+pub fn to_sdk(status: &ServiceStatusRecord) -> ServiceStatus {
+    ServiceStatus {
+        name: status.name.clone(),
+        status: status.status.clone(),
+        uptime: status.uptime,
+        response_time: status.response_time,
+        requests_per_second: status.requests_per_second,
+        last_check: status.last_check.timestamp_millis().max(0) as u64,
+    }
+}
+
+/// Recomputes service status every `service_status.interval`, storing it and
+/// publishing it to the hub.
+pub async fn run(tracker: Arc<StatusTracker>, storage: Arc<dyn StorageBackend>, hub: Arc<Hub>) {
+    info!(
+        "Tracking service status over a {:?} window every {:?}",
+        tracker.config.window, tracker.config.interval
+    );
+
+    let mut interval = tokio::time::interval(tracker.config.interval);
+    loop {
+        interval.tick().await;
+        let statuses = tracker.evaluate(Utc::now());
+        if statuses.is_empty() {
+            continue;
+        }
+
+        // Clients still get the update when storage is briefly unavailable
+        if let Err(e) = storage.store_service_statuses(&statuses).await {
+            warn!("Failed to store service statuses: {}", e);
+        }
+        hub.publish_service_status(statuses.iter().map(to_sdk).collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::ProcessingMetadata;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn message(service: &str, name: &str, value: f64, at: DateTime<Utc>) -> ProcessedMessage {
+        let metric = json!({"name": name, "value": value, "unit": "ms", "labels": {"service": service}});
+        ProcessedMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            original_message: metric.clone(),
+            processed_message: metric,
+            processing_metadata: ProcessingMetadata {
+                tenant_id: "default".to_string(),
+                processed_at: at,
+                processor_version: "test".to_string(),
+                source_topic: "metrics".to_string(),
+                partition: 0,
+                offset: 0,
+                traceparent: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_evaluate_derives_status_over_window() {
+        let tracker = StatusTracker::new(ServiceStatusConfig {
+            enabled: true,
+            window: Duration::from_secs(60),
+            interval: Duration::from_secs(10),
+            stale_after: Duration::from_secs(30),
+            degraded_response_time_ms: 500.0,
+            response_time_metric: "response_time".to_string(),
+        });
+        let start = Utc::now();
+        tracker.observe(&[
+            message("api", "response_time", 100.0, start),
+            message("api", "response_time", 300.0, start),
+            message("web", "response_time", 900.0, start),
+            message("worker", "heartbeat", 1.0, start),
+        ]);
+
+        let statuses = tracker.evaluate(start);
+        let summary: Vec<(&str, &str)> = statuses.iter().map(|s| (s.name.as_str(), s.status.as_str())).collect();
+        assert_eq!(
+            summary,
+            vec![("api", STATUS_HEALTHY), ("web", STATUS_DEGRADED), ("worker", STATUS_HEALTHY)]
+        );
+        assert_eq!(statuses[0].response_time, 200.0);
+        assert!((statuses[0].requests_per_second - 2.0 / 60.0).abs() < 1e-9);
+        assert_eq!(statuses[2].requests_per_second, 0.0);
+
+        // Only the worker keeps reporting; the others go stale
+        let later = start + chrono::Duration::seconds(45);
+        tracker.observe(&[message("worker", "heartbeat", 1.0, later)]);
+        let statuses = tracker.evaluate(later);
+        assert_eq!(statuses[0].status, STATUS_DOWN);
+        assert_eq!(statuses[0].uptime, 50.0);
+        assert_eq!(statuses[2].uptime, 100.0);
+
+        // Response time samples age out of the window
+        let statuses = tracker.evaluate(start + chrono::Duration::seconds(61));
+        assert_eq!(statuses[0].response_time, 0.0);
+        assert_eq!(statuses[0].uptime, 0.0);
+    }
+}
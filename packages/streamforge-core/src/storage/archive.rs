@@ -0,0 +1,168 @@
+use super::export::{s3_store, write_object, Destination};
+use anyhow::Result;
+use arrow::array::{Array, AsArray};
+use arrow::datatypes::{DataType, Float64Type, Int64Type, SchemaRef, TimeUnit, TimestampMicrosecondType};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ArrowWriter;
+use serde_json::{Map, Value};
+
+/// Columns of exported metrics and logs and the record fields they are read
+/// back into, matching what the SDK sends. Other columns, such as those of
+/// exported spans, keep their names.
+const METRIC_FIELDS: &[(&str, &str)] = &[
+    ("metric_name", "name"),
+    ("metric_value", "value"),
+    ("metric_type", "unit"),
+    ("tags", "labels"),
+];
+
+const LOG_FIELDS: &[(&str, &str)] = &[
+    ("log_level", "level"),
+    ("service_name", "service"),
+    ("host_name", "host"),
+    ("attributes", "fields"),
+];
+
+/// The Parquet segments at `location`, in name order: a local file, every
+/// `.parquet` file of a local directory, or of an `s3://bucket/prefix`.
+pub async fn archive_segments(location: &str) -> Result<Vec<String>> {
+    let mut segments = match Destination::parse(location)? {
+        Destination::Local(path) if path.is_dir() => {
+            let mut entries = tokio::fs::read_dir(&path)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to list archive {}: {}", location, e))?;
+            let mut segments = Vec::new();
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().map_or(false, |ext| ext == "parquet") {
+                    segments.push(path.display().to_string());
+                }
+            }
+            segments
+        }
+        Destination::Local(path) => vec![path.display().to_string()],
+        Destination::S3 { bucket, key } => {
+            use futures::TryStreamExt;
+            use object_store::ObjectStore;
+
+            let prefix = object_store::path::Path::from(key);
+            s3_store(&bucket)?
+                .list(Some(&prefix))
+                .try_filter(|object| futures::future::ready(object.location.as_ref().ends_with(".parquet")))
+                .map_ok(|object| format!("s3://{}/{}", bucket, object.location))
+                .try_collect()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to list archive {}: {}", location, e))?
+        }
+    };
+    segments.sort();
+    Ok(segments)
+}
+
+/// Reads one Parquet segment as JSON records, `batch_size` rows at a time.
+/// The segment is loaded into memory whole.
+pub struct SegmentReader {
+    reader: ParquetRecordBatchReader,
+}
+
+impl SegmentReader {
+    pub async fn open(location: &str, batch_size: usize) -> Result<Self> {
+        let body = match Destination::parse(location)? {
+            Destination::Local(path) => tokio::fs::read(&path)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to read segment {}: {}", location, e))?
+                .into(),
+            Destination::S3 { bucket, key } => {
+                use object_store::ObjectStore;
+
+                s3_store(&bucket)?
+                    .get(&object_store::path::Path::from(key))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to download segment {}: {}", location, e))?
+                    .bytes()
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to download segment {}: {}", location, e))?
+            }
+        };
+        let reader = ParquetRecordBatchReaderBuilder::try_new(body)
+            .and_then(|builder| builder.with_batch_size(batch_size.max(1)).build())
+            .map_err(|e| anyhow::anyhow!("Failed to open segment {}: {}", location, e))?;
+        Ok(Self { reader })
+    }
+
+    /// The next rows, or `None` past the end.
+    pub fn next_batch(&mut self) -> Option<Result<Vec<Value>>> {
+        Some(self.next_record_batch()?.map(|batch| records(&batch)))
+    }
+
+    /// The next rows as written, or `None` past the end.
+    pub(super) fn next_record_batch(&mut self) -> Option<Result<RecordBatch>> {
+        let batch = self.reader.next()?;
+        Some(batch.map_err(|e| anyhow::anyhow!("Failed to decode segment rows: {}", e)))
+    }
+}
+
+/// Overwrites the segment at `location` with `batches` of `schema`.
+pub(super) async fn replace_segment(location: &str, schema: SchemaRef, batches: &[RecordBatch]) -> Result<()> {
+    let mut body = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut body, schema, None)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.close()?;
+    write_object(location, body)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to rewrite segment {}: {}", location, e))
+}
+
+fn records(batch: &RecordBatch) -> Vec<Value> {
+    let schema = batch.schema();
+    let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    let renames = if names.contains(&"metric_name") {
+        METRIC_FIELDS
+    } else if names.contains(&"log_level") {
+        LOG_FIELDS
+    } else {
+        &[]
+    };
+
+    (0..batch.num_rows())
+        .map(|row| {
+            let mut record = Map::new();
+            for (name, column) in names.iter().zip(batch.columns()) {
+                let field = renames
+                    .iter()
+                    .find(|(from, _)| from == name)
+                    .map_or(*name, |(_, to)| *to);
+                record.insert(field.to_string(), cell(column.as_ref(), row));
+            }
+            Value::Object(record)
+        })
+        .collect()
+}
+
+/// One cell as JSON. Timestamps become epoch milliseconds, and text holding
+/// a JSON object or array, as exports write tags and attributes, is parsed.
+fn cell(column: &dyn Array, row: usize) -> Value {
+    if column.is_null(row) {
+        return Value::Null;
+    }
+    match column.data_type() {
+        DataType::Utf8 => {
+            let text = column.as_string::<i32>().value(row);
+            match text.as_bytes().first() {
+                Some(b'{') | Some(b'[') => serde_json::from_str(text).unwrap_or_else(|_| Value::from(text)),
+                _ => Value::from(text),
+            }
+        }
+        DataType::Float64 => serde_json::Number::from_f64(column.as_primitive::<Float64Type>().value(row))
+            .map_or(Value::Null, Value::Number),
+        DataType::Int64 => Value::from(column.as_primitive::<Int64Type>().value(row)),
+        DataType::Boolean => Value::from(column.as_boolean().value(row)),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            Value::from(column.as_primitive::<TimestampMicrosecondType>().value(row) / 1000)
+        }
+        _ => Value::Null,
+    }
+}
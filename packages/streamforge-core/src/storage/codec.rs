@@ -0,0 +1,109 @@
+use crate::config::{CompressionConfig, PayloadCodec};
+use anyhow::Result;
+
+/// First byte of every encoded payload, identifying how the rest is stored.
+/// Markers are persisted, so existing values must never be renumbered.
+const MARKER_JSON: u8 = 0;
+const MARKER_ZSTD: u8 = 1;
+
+/// Encodes a payload for a binary column, or returns `None` when compression
+/// is disabled and the payload belongs in the plain JSON column instead.
+pub(crate) fn encode(value: &serde_json::Value, config: &CompressionConfig) -> Result<Option<Vec<u8>>> {
+    match config.codec {
+        PayloadCodec::None => Ok(None),
+        PayloadCodec::Zstd => {
+            let json = serde_json::to_vec(value)?;
+            let mut encoded = Vec::with_capacity(json.len() / 4 + 1);
+            encoded.push(MARKER_ZSTD);
+            zstd::stream::copy_encode(json.as_slice(), &mut encoded, config.level)
+                .map_err(|e| anyhow::anyhow!("Failed to compress payload: {}", e))?;
+            Ok(Some(encoded))
+        }
+    }
+}
+
+/// Decodes a payload written by [`encode`], whatever codec was configured at
+/// the time it was stored.
+pub(crate) fn decode(bytes: &[u8]) -> Result<serde_json::Value> {
+    let (marker, body) = bytes
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty encoded payload"))?;
+
+    match *marker {
+        MARKER_JSON => Ok(serde_json::from_slice(body)?),
+        MARKER_ZSTD => {
+            let json = zstd::stream::decode_all(body)
+                .map_err(|e| anyhow::anyhow!("Failed to decompress payload: {}", e))?;
+            Ok(serde_json::from_slice(&json)?)
+        }
+        other => Err(anyhow::anyhow!("Unknown payload codec marker: {}", other)),
+    }
+}
+
+/// Reads a payload from a row that stores it either as JSON in `json` or
+/// encoded in `blob`; rows written before compression was enabled only have
+/// the former.
+pub(crate) fn from_columns(json: Option<serde_json::Value>, blob: Option<Vec<u8>>) -> Result<serde_json::Value> {
+    match (blob, json) {
+        (Some(blob), _) => decode(&blob),
+        (None, Some(json)) => Ok(json),
+        (None, None) => Err(anyhow::anyhow!("Stored message has no payload")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbitrary;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_zstd_round_trip_and_plain_fallback() {
+        let config = CompressionConfig {
+            codec: PayloadCodec::Zstd,
+            level: 3,
+        };
+        let value = json!({"message": "x".repeat(1000), "count": 3});
+
+        let encoded = encode(&value, &config).unwrap().unwrap();
+        assert_eq!(encoded[0], MARKER_ZSTD);
+        assert!(encoded.len() < 200);
+        assert_eq!(from_columns(None, Some(encoded)).unwrap(), value);
+
+        let disabled = CompressionConfig::default();
+        assert!(encode(&value, &disabled).unwrap().is_none());
+        assert_eq!(from_columns(Some(value.clone()), None).unwrap(), value);
+
+        let mut plain = vec![MARKER_JSON];
+        plain.extend(serde_json::to_vec(&value).unwrap());
+        assert_eq!(decode(&plain).unwrap(), value);
+        assert!(decode(&[9, 1, 2]).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_zstd_round_trips_any_json(value in arbitrary::json(), level in 1..10i32) {
+            let config = CompressionConfig {
+                codec: PayloadCodec::Zstd,
+                level,
+            };
+            let encoded = encode(&value, &config).unwrap().unwrap();
+            prop_assert_eq!(decode(&encoded).unwrap(), value);
+        }
+
+        #[test]
+        fn prop_decode_rejects_hostile_payloads_without_panicking(
+            payload in arbitrary::hostile_payload(),
+            marker in prop_oneof![Just(MARKER_JSON), Just(MARKER_ZSTD), any::<u8>()],
+        ) {
+            let bytes = [&[marker][..], &payload].concat();
+            let decoded = decode(&bytes);
+            if marker == MARKER_JSON {
+                prop_assert_eq!(decoded.is_ok(), serde_json::from_slice::<serde_json::Value>(&payload).is_ok());
+            } else if marker != MARKER_ZSTD {
+                prop_assert!(decoded.is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use super::StorageBackend;
+use crate::config::{CompactionConfig, MetricRetentionRule};
+use chrono::Utc;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Outcome of compacting one retention rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub rows_deleted: u64,
+    pub rollups_written: u64,
+}
+
+/// Runs every retention rule once per `compaction.interval`. A failing rule is
+/// logged and retried on the next tick; it does not stop the others.
+pub async fn run(storage: Arc<dyn StorageBackend>, config: CompactionConfig) {
+    info!("Metric compaction started with {} rule(s)", config.rules.len());
+    let mut interval = tokio::time::interval(config.interval);
+
+    loop {
+        interval.tick().await;
+
+        for rule in &config.rules {
+            let cutoff = Utc::now() - chrono::Duration::days(i64::from(rule.older_than_days));
+            match storage.compact_metrics(rule, cutoff).await {
+                Ok(stats) if stats.rows_deleted > 0 => info!(
+                    "Compacted {} row(s) of {} into {} rollup(s) at {}s resolution",
+                    stats.rows_deleted, rule.metric, stats.rollups_written, rule.resolution_secs
+                ),
+                Ok(_) => {}
+                Err(e) => warn!("Metric compaction for {} failed: {}", rule.metric, e),
+            }
+        }
+    }
+}
+
+/// Translates a rule's metric pattern into a SQL `LIKE` pattern using `\` as
+/// the escape character.
+pub(crate) fn like_pattern(rule: &MetricRetentionRule) -> String {
+    let (name, prefix) = match rule.metric.strip_suffix('*') {
+        Some(prefix) => (prefix, true),
+        None => (rule.metric.as_str(), false),
+    };
+
+    let mut pattern = String::with_capacity(name.len() + 1);
+    for c in name.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    if prefix {
+        pattern.push('%');
+    }
+    pattern
+}
+
+pub(crate) fn matches(rule: &MetricRetentionRule, name: &str) -> bool {
+    match rule.metric.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == rule.metric,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(metric: &str) -> MetricRetentionRule {
+        MetricRetentionRule {
+            metric: metric.to_string(),
+            older_than_days: 7,
+            resolution_secs: 300,
+        }
+    }
+
+    #[test]
+    fn test_metric_patterns() {
+        assert_eq!(like_pattern(&rule("cpu_usage")), "cpu\\_usage");
+        assert_eq!(like_pattern(&rule("http_*")), "http\\_%");
+        assert!(matches(&rule("http_*"), "http_requests"));
+        assert!(!matches(&rule("cpu_usage"), "cpu_usage_total"));
+    }
+}
@@ -0,0 +1,734 @@
+use super::{
+    AlertDelivery, ArchivedSegment, AuditEntry, AuditFilter, CompactionStats, ErasureDataset, ErasureMode,
+    ErasureSelector, ExportDataset, JobLease, LogRecord, MetricRecord, Page, PoolStatus, ServiceStatusRecord, Silence,
+    StorageBackend, Trace, TraceSpan, TraceSummary,
+};
+use crate::config::{EncryptionConfig, MetricRetentionRule};
+use crate::pipeline::PipelineDefinition;
+use crate::processor::ProcessedMessage;
+use crate::rbac::RoleBinding;
+use crate::schemas::SchemaVersion;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Prefix of an encrypted value, followed by `<key_id>:<base64 nonce and
+/// ciphertext>`. Stored, so it must never change; a new format gets `v2`.
+const ENVELOPE: &str = "sfenc:v1:";
+
+const NONCE_BYTES: usize = 12;
+
+/// Shown in place of encrypted fields to callers not allowed to read them.
+const REDACTED: &str = "***";
+
+/// Decodes a base64 AES-256 key from `database.encryption`.
+pub(crate) fn decode_key(key: &str) -> Option<[u8; 32]> {
+    BASE64.decode(key.trim()).ok()?.try_into().ok()
+}
+
+/// Encrypts the values at `database.encryption.fields` with AES-256-GCM.
+/// Each value is serialized to JSON and replaced with an envelope string
+/// naming the key, so any JSON type round-trips. The field path is bound as
+/// associated data: a value copied to another field does not decrypt.
+pub struct FieldCipher {
+    key_id: String,
+    keys: HashMap<String, Aes256Gcm>,
+    fields: Vec<Vec<String>>,
+}
+
+impl FieldCipher {
+    pub fn new(config: &EncryptionConfig) -> Result<Self> {
+        let cipher = |id: &str, key: &str| {
+            decode_key(key)
+                .and_then(|key| Aes256Gcm::new_from_slice(&key).ok())
+                .ok_or_else(|| anyhow::anyhow!("Encryption key {} is not a base64-encoded 32-byte key", id))
+        };
+        let key = config
+            .key
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("database.encryption.key is required"))?;
+
+        let mut keys = HashMap::new();
+        for (id, key) in &config.previous_keys {
+            keys.insert(id.clone(), cipher(id, key)?);
+        }
+        keys.insert(config.key_id.clone(), cipher(&config.key_id, key)?);
+
+        Ok(Self {
+            key_id: config.key_id.clone(),
+            keys,
+            fields: config
+                .fields
+                .iter()
+                .map(|field| field.split('.').map(str::to_string).collect())
+                .collect(),
+        })
+    }
+
+    /// Encrypts the configured fields of `value` that are not encrypted yet.
+    pub fn encrypt(&self, value: &mut Value) -> Result<()> {
+        for field in &self.fields {
+            self.seal_at(value, field, &field.join("."))?;
+        }
+        Ok(())
+    }
+
+    /// Decrypts every encrypted value in `value`, wherever it is, so values
+    /// stay readable after their field is dropped from the configuration.
+    pub fn decrypt(&self, value: &mut Value) -> Result<()> {
+        self.open_all(value, &mut Vec::new())
+    }
+
+    /// Decrypts a JSON object or array held as text, such as the attributes
+    /// column of an archived segment. `None` when there is nothing to decrypt.
+    pub fn decrypt_text(&self, text: &str) -> Result<Option<String>> {
+        if !text.contains(ENVELOPE) || !text.starts_with(['{', '[']) {
+            return Ok(None);
+        }
+        let Ok(mut value) = serde_json::from_str::<Value>(text) else {
+            return Ok(None);
+        };
+        self.decrypt(&mut value)?;
+        Ok(Some(value.to_string()))
+    }
+
+    /// Replaces the configured fields of `value` with [`REDACTED`].
+    pub fn redact(&self, value: &mut Value) {
+        for field in &self.fields {
+            redact_at(value, field);
+        }
+    }
+
+    /// Whether values at `path` are stored encrypted: `path` is a
+    /// configured field or lies inside one.
+    pub fn encrypts(&self, path: &[String]) -> bool {
+        self.fields.iter().any(|field| path.starts_with(field))
+    }
+
+    pub fn encrypt_message(&self, message: &mut ProcessedMessage) -> Result<()> {
+        self.encrypt(&mut message.original_message)?;
+        self.encrypt(&mut message.processed_message)
+    }
+
+    pub fn decrypt_message(&self, message: &mut ProcessedMessage) -> Result<()> {
+        self.decrypt(&mut message.original_message)?;
+        self.decrypt(&mut message.processed_message)
+    }
+
+    pub fn encrypt_attributes(&self, attributes: &mut Option<Value>) -> Result<()> {
+        attributes.as_mut().map_or(Ok(()), |attributes| self.encrypt(attributes))
+    }
+
+    pub fn decrypt_attributes(&self, attributes: &mut Option<Value>) -> Result<()> {
+        attributes.as_mut().map_or(Ok(()), |attributes| self.decrypt(attributes))
+    }
+
+    pub fn decrypt_trace(&self, trace: &mut Trace) -> Result<()> {
+        fn decrypt_span(cipher: &FieldCipher, span: &mut TraceSpan) -> Result<()> {
+            cipher.decrypt_attributes(&mut span.attributes)?;
+            span.children.iter_mut().try_for_each(|child| decrypt_span(cipher, child))
+        }
+        trace.roots.iter_mut().try_for_each(|span| decrypt_span(self, span))
+    }
+
+    pub fn redact_trace(&self, trace: &mut Trace) {
+        fn redact_span(cipher: &FieldCipher, span: &mut TraceSpan) {
+            if let Some(attributes) = &mut span.attributes {
+                cipher.redact(attributes);
+            }
+            span.children.iter_mut().for_each(|child| redact_span(cipher, child));
+        }
+        trace.roots.iter_mut().for_each(|span| redact_span(self, span));
+    }
+
+    fn seal_at(&self, value: &mut Value, path: &[String], field: &str) -> Result<()> {
+        let Some((head, rest)) = path.split_first() else {
+            if !matches!(value, Value::String(text) if text.starts_with(ENVELOPE)) {
+                *value = Value::String(self.seal(value, field)?);
+            }
+            return Ok(());
+        };
+        match value {
+            Value::Array(items) => items.iter_mut().try_for_each(|item| self.seal_at(item, path, field)),
+            Value::Object(fields) => match fields.get_mut(head) {
+                Some(child) => self.seal_at(child, rest, field),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn open_all(&self, value: &mut Value, path: &mut Vec<String>) -> Result<()> {
+        match value {
+            Value::String(text) if text.starts_with(ENVELOPE) => {
+                *value = self.open(text, &path.join("."))?;
+                Ok(())
+            }
+            Value::Array(items) => items.iter_mut().try_for_each(|item| self.open_all(item, path)),
+            Value::Object(fields) => {
+                for (key, child) in fields.iter_mut() {
+                    path.push(key.clone());
+                    let result = self.open_all(child, path);
+                    path.pop();
+                    result?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn seal(&self, value: &Value, field: &str) -> Result<String> {
+        let plaintext = serde_json::to_vec(value)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.keys[&self.key_id]
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: field.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt field {}", field))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!("{}{}:{}", ENVELOPE, self.key_id, BASE64.encode(sealed)))
+    }
+
+    fn open(&self, envelope: &str, field: &str) -> Result<Value> {
+        let (key_id, sealed) = envelope[ENVELOPE.len()..]
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Malformed encrypted value in field {}", field))?;
+        let cipher = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| anyhow::anyhow!("Field {} is encrypted with unknown key {}", field, key_id))?;
+        let sealed = BASE64
+            .decode(sealed)
+            .ok()
+            .filter(|sealed| sealed.len() > NONCE_BYTES)
+            .ok_or_else(|| anyhow::anyhow!("Malformed encrypted value in field {}", field))?;
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_BYTES);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: field.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt field {}: wrong key or tampered value", field))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+fn redact_at(value: &mut Value, path: &[String]) {
+    let Some((head, rest)) = path.split_first() else {
+        *value = Value::String(REDACTED.to_string());
+        return;
+    };
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_at(item, path)),
+        Value::Object(fields) => {
+            if let Some(child) = fields.get_mut(head) {
+                redact_at(child, rest);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Backend that encrypts sensitive fields of messages and of log and span
+/// attributes before they reach another one, when
+/// `database.encryption.enabled` is set, and decrypts them on reads. Metric
+/// tags are left alone, since queries group by them.
+pub struct EncryptedStorage {
+    inner: Arc<dyn StorageBackend>,
+    cipher: FieldCipher,
+}
+
+impl EncryptedStorage {
+    pub fn new(inner: Arc<dyn StorageBackend>, cipher: FieldCipher) -> Self {
+        Self { inner, cipher }
+    }
+
+    fn decrypt_page(&self, mut page: Page<ProcessedMessage>) -> Result<Page<ProcessedMessage>> {
+        for message in &mut page.items {
+            self.cipher.decrypt_message(message)?;
+        }
+        Ok(page)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for EncryptedStorage {
+    async fn store_processed_message(&self, message: &ProcessedMessage) -> Result<()> {
+        let mut message = message.clone();
+        self.cipher.encrypt_message(&mut message)?;
+        self.inner.store_processed_message(&message).await
+    }
+
+    async fn store_processed_messages(&self, messages: &[ProcessedMessage]) -> Result<u64> {
+        let mut messages = messages.to_vec();
+        for message in &mut messages {
+            self.cipher.encrypt_message(message)?;
+        }
+        self.inner.store_processed_messages(&messages).await
+    }
+
+    async fn get_processed_message(&self, tenant_id: &str, id: &str) -> Result<Option<ProcessedMessage>> {
+        let mut message = self.inner.get_processed_message(tenant_id, id).await?;
+        if let Some(message) = &mut message {
+            self.cipher.decrypt_message(message)?;
+        }
+        Ok(message)
+    }
+
+    async fn get_processed_messages_by_topic(
+        &self,
+        tenant_id: &str,
+        topic: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        let page = self.inner.get_processed_messages_by_topic(tenant_id, topic, limit, cursor).await?;
+        self.decrypt_page(page)
+    }
+
+    async fn get_processed_messages_by_time_range(
+        &self,
+        tenant_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        let page = self
+            .inner
+            .get_processed_messages_by_time_range(tenant_id, start_time, end_time, limit, cursor)
+            .await?;
+        self.decrypt_page(page)
+    }
+
+    async fn store_metric(
+        &self,
+        tenant_id: &str,
+        name: &str,
+        value: f64,
+        metric_type: &str,
+        tags: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        self.inner.store_metric(tenant_id, name, value, metric_type, tags, timestamp).await
+    }
+
+    async fn store_metrics(&self, tenant_id: &str, metrics: &[MetricRecord]) -> Result<()> {
+        self.inner.store_metrics(tenant_id, metrics).await
+    }
+
+    async fn store_log(
+        &self,
+        tenant_id: &str,
+        level: &str,
+        message: &str,
+        service_name: Option<&str>,
+        host_name: Option<&str>,
+        trace_id: Option<&str>,
+        span_id: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut attributes = attributes;
+        self.cipher.encrypt_attributes(&mut attributes)?;
+        self.inner
+            .store_log(
+                tenant_id,
+                level,
+                message,
+                service_name,
+                host_name,
+                trace_id,
+                span_id,
+                attributes,
+                timestamp,
+            )
+            .await
+    }
+
+    async fn store_logs(&self, tenant_id: &str, logs: &[LogRecord]) -> Result<()> {
+        let mut logs = logs.to_vec();
+        for log in &mut logs {
+            self.cipher.encrypt_attributes(&mut log.attributes)?;
+        }
+        self.inner.store_logs(tenant_id, &logs).await
+    }
+
+    async fn store_trace(
+        &self,
+        tenant_id: &str,
+        trace_id: &str,
+        span_id: &str,
+        parent_span_id: Option<&str>,
+        name: &str,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: Option<DateTime<Utc>>,
+        duration_ms: Option<i64>,
+        status: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        events: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let mut attributes = attributes;
+        self.cipher.encrypt_attributes(&mut attributes)?;
+        self.inner
+            .store_trace(
+                tenant_id,
+                trace_id,
+                span_id,
+                parent_span_id,
+                name,
+                service_name,
+                start_time,
+                end_time,
+                duration_ms,
+                status,
+                attributes,
+                events,
+            )
+            .await
+    }
+
+    async fn get_metrics(
+        &self,
+        tenant_id: &str,
+        name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<MetricRecord>> {
+        self.inner.get_metrics(tenant_id, name, start_time, end_time, limit).await
+    }
+
+    async fn list_metric_names(&self, tenant_id: &str, limit: i64) -> Result<Vec<String>> {
+        self.inner.list_metric_names(tenant_id, limit).await
+    }
+
+    async fn get_logs(
+        &self,
+        tenant_id: &str,
+        level: Option<&str>,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<LogRecord>> {
+        let mut logs = self
+            .inner
+            .get_logs(tenant_id, level, service_name, start_time, end_time, limit)
+            .await?;
+        for log in &mut logs {
+            self.cipher.decrypt_attributes(&mut log.attributes)?;
+        }
+        Ok(logs)
+    }
+
+    async fn get_trace(&self, tenant_id: &str, trace_id: &str) -> Result<Option<Trace>> {
+        let mut trace = self.inner.get_trace(tenant_id, trace_id).await?;
+        if let Some(trace) = &mut trace {
+            self.cipher.decrypt_trace(trace)?;
+        }
+        Ok(trace)
+    }
+
+    async fn find_traces(
+        &self,
+        tenant_id: &str,
+        service: Option<&str>,
+        min_duration: Option<Duration>,
+        status: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<TraceSummary>> {
+        self.inner
+            .find_traces(tenant_id, service, min_duration, status, start_time, end_time, limit)
+            .await
+    }
+
+    async fn compact_metrics(&self, rule: &MetricRetentionRule, cutoff: DateTime<Utc>) -> Result<CompactionStats> {
+        self.inner.compact_metrics(rule, cutoff).await
+    }
+
+    /// Encrypted values are never equal to the plaintext a selector gives,
+    /// so matching on them is refused rather than silently erasing nothing.
+    async fn erase_records(
+        &self,
+        dataset: ErasureDataset,
+        selector: &ErasureSelector,
+        mode: ErasureMode,
+        limit: i64,
+    ) -> Result<u64> {
+        if let Some(attribute) = selector.attribute.as_ref().filter(|a| self.cipher.encrypts(&a.path)) {
+            return Err(anyhow::anyhow!(
+                "Cannot match encrypted field {}; erase by time range instead",
+                attribute.path.join(".")
+            ));
+        }
+        self.inner.erase_records(dataset, selector, mode, limit).await
+    }
+
+    async fn oldest_rows(&self, dataset: ExportDataset, before: DateTime<Utc>) -> Result<Vec<(String, DateTime<Utc>)>> {
+        self.inner.oldest_rows(dataset, before).await
+    }
+
+    async fn record_archived_segment(&self, segment: &ArchivedSegment) -> Result<bool> {
+        self.inner.record_archived_segment(segment).await
+    }
+
+    async fn list_archived_segments(
+        &self,
+        tenant_id: &str,
+        dataset: ExportDataset,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<ArchivedSegment>> {
+        self.inner
+            .list_archived_segments(tenant_id, dataset, start_time, end_time)
+            .await
+    }
+
+    async fn create_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        self.inner.create_pipeline(pipeline).await
+    }
+
+    async fn update_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        self.inner.update_pipeline(pipeline).await
+    }
+
+    async fn delete_pipeline(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        self.inner.delete_pipeline(tenant_id, id).await
+    }
+
+    async fn get_pipeline(&self, tenant_id: &str, id: &str) -> Result<Option<PipelineDefinition>> {
+        self.inner.get_pipeline(tenant_id, id).await
+    }
+
+    async fn list_pipelines(
+        &self,
+        tenant_id: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<PipelineDefinition>> {
+        self.inner.list_pipelines(tenant_id, limit, cursor).await
+    }
+
+    async fn list_scheduled_pipelines(&self) -> Result<Vec<PipelineDefinition>> {
+        self.inner.list_scheduled_pipelines().await
+    }
+
+    async fn acquire_job_lease(&self, lease: &JobLease) -> Result<bool> {
+        self.inner.acquire_job_lease(lease).await
+    }
+
+    async fn renew_job_lease(
+        &self,
+        pipeline_id: &str,
+        owner: &str,
+        job_id: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.inner.renew_job_lease(pipeline_id, owner, job_id, expires_at).await
+    }
+
+    async fn release_job_lease(&self, pipeline_id: &str, owner: &str) -> Result<bool> {
+        self.inner.release_job_lease(pipeline_id, owner).await
+    }
+
+    async fn get_job_lease(&self, job_id: &str) -> Result<Option<JobLease>> {
+        self.inner.get_job_lease(job_id).await
+    }
+
+    async fn list_expired_job_leases(&self, now: DateTime<Utc>) -> Result<Vec<JobLease>> {
+        self.inner.list_expired_job_leases(now).await
+    }
+
+    async fn store_service_statuses(&self, statuses: &[ServiceStatusRecord]) -> Result<()> {
+        self.inner.store_service_statuses(statuses).await
+    }
+
+    async fn list_service_statuses(&self, tenant_id: &str) -> Result<Vec<ServiceStatusRecord>> {
+        self.inner.list_service_statuses(tenant_id).await
+    }
+
+    async fn record_alert_delivery(&self, delivery: &AlertDelivery) -> Result<()> {
+        self.inner.record_alert_delivery(delivery).await
+    }
+
+    async fn list_alert_deliveries(
+        &self,
+        tenant_id: &str,
+        alert_id: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Vec<AlertDelivery>> {
+        self.inner.list_alert_deliveries(tenant_id, alert_id, limit).await
+    }
+
+    async fn create_silence(&self, silence: &Silence) -> Result<bool> {
+        self.inner.create_silence(silence).await
+    }
+
+    async fn delete_silence(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        self.inner.delete_silence(tenant_id, id).await
+    }
+
+    async fn list_silences(&self, tenant_id: &str, active_at: Option<DateTime<Utc>>) -> Result<Vec<Silence>> {
+        self.inner.list_silences(tenant_id, active_at).await
+    }
+
+    async fn list_unexpired_silences(&self, now: DateTime<Utc>) -> Result<Vec<Silence>> {
+        self.inner.list_unexpired_silences(now).await
+    }
+
+    async fn append_audit_entry(&self, entry: &AuditEntry) -> Result<()> {
+        self.inner.append_audit_entry(entry).await
+    }
+
+    async fn query_audit_log(
+        &self,
+        filter: &AuditFilter,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<AuditEntry>> {
+        self.inner.query_audit_log(filter, limit, cursor).await
+    }
+
+    async fn create_schema_version(&self, version: &SchemaVersion) -> Result<bool> {
+        self.inner.create_schema_version(version).await
+    }
+
+    async fn list_schema_versions(&self, tenant_id: &str, topic: &str) -> Result<Vec<SchemaVersion>> {
+        self.inner.list_schema_versions(tenant_id, topic).await
+    }
+
+    async fn list_latest_schema_versions(&self) -> Result<Vec<SchemaVersion>> {
+        self.inner.list_latest_schema_versions().await
+    }
+
+    async fn create_role_binding(&self, binding: &RoleBinding) -> Result<bool> {
+        self.inner.create_role_binding(binding).await
+    }
+
+    async fn delete_role_binding(&self, subject: &str, role: &str) -> Result<bool> {
+        self.inner.delete_role_binding(subject, role).await
+    }
+
+    async fn list_role_bindings(&self, subject: Option<&str>) -> Result<Vec<RoleBinding>> {
+        self.inner.list_role_bindings(subject).await
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        self.inner.health_check().await
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        self.inner.pool_status()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use serde_json::json;
+
+    fn config(key: [u8; 32]) -> EncryptionConfig {
+        EncryptionConfig {
+            enabled: true,
+            key: Some(BASE64.encode(key)),
+            fields: vec!["user.email".to_string(), "tokens".to_string(), "sessions.id".to_string()],
+            ..EncryptionConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_fields_round_trip_and_bind_their_path() {
+        let cipher = FieldCipher::new(&config([7; 32])).unwrap();
+        let original = json!({
+            "user": {"email": "ada@example.com", "name": "Ada"},
+            "tokens": ["t-1", "t-2"],
+            "sessions": [{"id": 1}, {"id": 2}],
+        });
+
+        let mut value = original.clone();
+        cipher.encrypt(&mut value).unwrap();
+        assert!(value["user"]["email"].as_str().unwrap().starts_with("sfenc:v1:primary:"));
+        assert!(value["tokens"].as_str().unwrap().starts_with(ENVELOPE));
+        assert!(value["sessions"][1]["id"].as_str().unwrap().starts_with(ENVELOPE));
+        assert_eq!(value["user"]["name"], "Ada");
+
+        // Encrypting twice leaves encrypted values as they are
+        let once = value.clone();
+        cipher.encrypt(&mut value).unwrap();
+        assert_eq!(value, once);
+
+        let mut decrypted = value.clone();
+        cipher.decrypt(&mut decrypted).unwrap();
+        assert_eq!(decrypted, original);
+
+        let mut moved = json!({ "user": { "name": value["user"]["email"].clone() } });
+        assert!(cipher.decrypt(&mut moved).is_err());
+
+        let mut redacted = original.clone();
+        cipher.redact(&mut redacted);
+        assert_eq!(redacted["user"]["email"], REDACTED);
+        assert_eq!(redacted["sessions"][0]["id"], REDACTED);
+    }
+
+    #[test]
+    fn test_rotated_keys_still_decrypt() {
+        let old = FieldCipher::new(&EncryptionConfig {
+            key_id: "2025".to_string(),
+            ..config([1; 32])
+        })
+        .unwrap();
+        let mut value = json!({"user": {"email": "ada@example.com"}});
+        old.encrypt(&mut value).unwrap();
+
+        let mut rotated = config([2; 32]);
+        rotated.previous_keys.insert("2025".to_string(), BASE64.encode([1; 32]));
+        let mut decrypted = value.clone();
+        FieldCipher::new(&rotated).unwrap().decrypt(&mut decrypted).unwrap();
+        assert_eq!(decrypted["user"]["email"], "ada@example.com");
+
+        assert!(FieldCipher::new(&config([2; 32])).unwrap().decrypt(&mut value).is_err());
+        assert!(FieldCipher::new(&EncryptionConfig { key: Some("c2hvcnQ=".to_string()), ..config([0; 32]) }).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_storage_keeps_only_ciphertext() {
+        let inner = Arc::new(MemoryStorage::with_capacity(10));
+        let storage = EncryptedStorage::new(inner.clone(), FieldCipher::new(&config([9; 32])).unwrap());
+        let now = Utc::now();
+
+        storage
+            .store_log(
+                "tenant-a",
+                "info",
+                "signed in",
+                None,
+                None,
+                None,
+                None,
+                Some(json!({"user": {"email": "ada@example.com"}})),
+                now,
+            )
+            .await
+            .unwrap();
+
+        let range = (now - chrono::Duration::seconds(1), now + chrono::Duration::seconds(1));
+        let stored = inner.get_logs("tenant-a", None, None, range.0, range.1, None).await.unwrap();
+        let email = stored[0].attributes.as_ref().unwrap()["user"]["email"].clone();
+        assert!(email.as_str().unwrap().starts_with(ENVELOPE));
+
+        let read = storage.get_logs("tenant-a", None, None, range.0, range.1, None).await.unwrap();
+        assert_eq!(read[0].attributes.as_ref().unwrap()["user"]["email"], "ada@example.com");
+    }
+}
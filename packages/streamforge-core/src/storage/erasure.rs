@@ -0,0 +1,495 @@
+use super::archive::replace_segment;
+use super::export::{self, ExportDataset};
+use super::lifecycle::{self, ArchivedSegment, SEGMENT_BATCH_SIZE};
+use super::{SegmentReader, StorageBackend};
+use anyhow::Result;
+use arrow::array::{Array, ArrayRef, BooleanArray, StringArray, TimestampMicrosecondArray};
+use arrow::compute::filter_record_batch;
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::info;
+
+/// Records erased per backend call, so no single statement holds locks on
+/// a large part of a table.
+pub const ERASURE_BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErasureDataset {
+    Logs,
+    /// Deleting removes every span of a trace that has a matching span;
+    /// scrubbing only touches the matching spans.
+    Traces,
+    ProcessedMessages,
+}
+
+impl ErasureDataset {
+    pub const ALL: [ErasureDataset; 3] = [
+        ErasureDataset::Logs,
+        ErasureDataset::Traces,
+        ErasureDataset::ProcessedMessages,
+    ];
+
+    /// The warm-tier dataset the records are archived as, if they are.
+    pub fn archived(self) -> Option<ExportDataset> {
+        match self {
+            ErasureDataset::Logs => Some(ExportDataset::Logs),
+            ErasureDataset::Traces => Some(ExportDataset::Traces),
+            ErasureDataset::ProcessedMessages => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErasureDataset::Logs => "logs",
+            ErasureDataset::Traces => "traces",
+            ErasureDataset::ProcessedMessages => "processed_messages",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErasureMode {
+    /// Removes matching records.
+    Delete,
+    /// Keeps matching records but removes the selector's attribute from
+    /// them: from log and span attributes, and from both the original and
+    /// processed payload of messages.
+    Scrub,
+}
+
+/// Matches records whose JSON attribute at `path` equals `value`. Strings
+/// compare as they are and numbers by their JSON text, so `42` matches
+/// `{"user": {"id": 42}}` at `user.id`; other values never match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttributeMatch {
+    pub path: Vec<String>,
+    pub value: String,
+}
+
+impl AttributeMatch {
+    /// Parses a dotted path such as `user.email`.
+    pub fn new(path: &str, value: impl Into<String>) -> Self {
+        Self {
+            path: path.split('.').map(str::to_string).collect(),
+            value: value.into(),
+        }
+    }
+
+    pub fn matches(&self, value: &Value) -> bool {
+        let found = self.path.iter().try_fold(value, |value, key| value.get(key));
+        match found {
+            Some(Value::String(text)) => *text == self.value,
+            Some(Value::Number(number)) => number.to_string() == self.value,
+            _ => false,
+        }
+    }
+
+    /// Removes the attribute from `value`, returning whether it was there.
+    pub fn remove(&self, value: &mut Value) -> bool {
+        let Some((last, parents)) = self.path.split_last() else {
+            return false;
+        };
+        parents
+            .iter()
+            .try_fold(value, |value, key| value.get_mut(key))
+            .and_then(Value::as_object_mut)
+            .map_or(false, |object| object.remove(last).is_some())
+    }
+
+    /// The path as a SQLite JSON path, with every key quoted.
+    pub(crate) fn json_path(&self) -> String {
+        self.path.iter().fold("$".to_string(), |path, key| {
+            format!("{}.\"{}\"", path, key.replace('\\', "\\\\").replace('"', "\\\""))
+        })
+    }
+}
+
+/// Records of one tenant to erase. Unset fields match every record; the
+/// time range is inclusive of `start_time` and exclusive of `end_time`, and
+/// applies to log timestamps, span start times and message processing times.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErasureSelector {
+    pub tenant_id: String,
+    pub attribute: Option<AttributeMatch>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+impl ErasureSelector {
+    pub fn in_range(&self, at: DateTime<Utc>) -> bool {
+        self.start_time.map_or(true, |start| at >= start) && self.end_time.map_or(true, |end| at < end)
+    }
+
+    /// The attribute scrubbing removes, which validated requests always have.
+    pub(crate) fn scrubbed(&self) -> Result<&AttributeMatch> {
+        self.attribute
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Scrubbing needs an attribute to remove"))
+    }
+
+    /// Whether the attribute, when the selector has one, matches any of
+    /// `values`.
+    pub fn matches_any<'a>(&self, values: impl IntoIterator<Item = Option<&'a Value>>) -> bool {
+        match &self.attribute {
+            Some(attribute) => values.into_iter().flatten().any(|value| attribute.matches(value)),
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErasureRequest {
+    pub selector: ErasureSelector,
+    pub mode: ErasureMode,
+    pub datasets: Vec<ErasureDataset>,
+}
+
+impl ErasureRequest {
+    pub fn validate(&self) -> Result<()> {
+        if self.selector.tenant_id.is_empty() {
+            return Err(anyhow::anyhow!("Erasure tenant_id is required"));
+        }
+        if self.datasets.is_empty() {
+            return Err(anyhow::anyhow!("Erasure needs at least one dataset"));
+        }
+        if let Some(attribute) = &self.selector.attribute {
+            if attribute.path.iter().any(|key| key.is_empty()) {
+                return Err(anyhow::anyhow!("Erasure attribute path must not have empty keys"));
+            }
+        }
+        if self.mode == ErasureMode::Scrub && self.selector.attribute.is_none() {
+            return Err(anyhow::anyhow!("Scrubbing needs an attribute to remove"));
+        }
+        if let (Some(start), Some(end)) = (self.selector.start_time, self.selector.end_time) {
+            if end <= start {
+                return Err(anyhow::anyhow!("Erasure end_time must be after start_time"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Erases the requested datasets one after another, in batches of
+/// [`ERASURE_BATCH_SIZE`] until a batch comes back short, then from the
+/// archived segments of the dataset. `progress` is called after every batch
+/// with the dataset and the records erased from it so far. Erased records
+/// no longer match, so an erasure that fails part-way can simply be run
+/// again.
+pub async fn erase<F>(storage: &dyn StorageBackend, request: &ErasureRequest, mut progress: F) -> Result<u64>
+where
+    F: FnMut(ErasureDataset, u64) + Send,
+{
+    request.validate()?;
+
+    let mut total = 0;
+    for &dataset in &request.datasets {
+        let mut erased = 0;
+        loop {
+            let batch = storage
+                .erase_records(dataset, &request.selector, request.mode, ERASURE_BATCH_SIZE)
+                .await?;
+            erased += batch;
+            progress(dataset, erased);
+            if batch < ERASURE_BATCH_SIZE as u64 {
+                break;
+            }
+        }
+        if let Some(archived) = dataset.archived() {
+            erased += erase_archived(storage, archived, request).await?;
+            progress(dataset, erased);
+        }
+        info!(
+            "Erased {} {} records of tenant {}",
+            erased,
+            dataset.as_str(),
+            request.selector.tenant_id
+        );
+        total += erased;
+    }
+    Ok(total)
+}
+
+/// Erases matching rows from the tenant's archived segments of `dataset`
+/// and rewrites each segment that held any. Deleting spans first gathers
+/// the matching traces from every segment, since a trace may cross them.
+async fn erase_archived(
+    storage: &dyn StorageBackend,
+    dataset: ExportDataset,
+    request: &ErasureRequest,
+) -> Result<u64> {
+    let selector = &request.selector;
+    let whole_traces = dataset == ExportDataset::Traces && request.mode == ErasureMode::Delete;
+    let (start_time, end_time) = match (selector.start_time, selector.end_time) {
+        (Some(start), Some(end)) if !whole_traces => (start, end),
+        _ => (DateTime::default(), Utc::now()),
+    };
+    let segments = storage
+        .list_archived_segments(&selector.tenant_id, dataset, start_time, end_time)
+        .await?;
+
+    let mut traces = HashSet::new();
+    if whole_traces {
+        for segment in &segments {
+            for batch in read_segment(segment).await? {
+                let matched = matching_rows(selector, dataset, &batch)?;
+                let trace_ids = text_column(&batch, "trace_id")?;
+                let rows = (0..batch.num_rows()).filter(|&row| matched[row]);
+                traces.extend(rows.map(|row| trace_ids.value(row).to_string()));
+            }
+        }
+    }
+
+    let mut erased = 0;
+    for segment in &segments {
+        let mut changed = false;
+        let mut batches = Vec::new();
+        for batch in read_segment(segment).await? {
+            let matched = match whole_traces {
+                true => {
+                    let trace_ids = text_column(&batch, "trace_id")?;
+                    (0..batch.num_rows()).map(|row| traces.contains(trace_ids.value(row))).collect()
+                }
+                false => matching_rows(selector, dataset, &batch)?,
+            };
+            let (batch, count) = match request.mode {
+                ErasureMode::Delete => {
+                    let keep: BooleanArray = matched.iter().map(|matched| Some(!matched)).collect();
+                    let kept = filter_record_batch(&batch, &keep)?;
+                    let count = batch.num_rows() - kept.num_rows();
+                    (kept, count)
+                }
+                ErasureMode::Scrub => scrub(selector, &batch, &matched)?,
+            };
+            changed |= count > 0;
+            erased += count as u64;
+            batches.push(batch);
+        }
+        if changed {
+            replace_segment(&segment.location, export::schema(dataset), &batches).await?;
+        }
+    }
+    Ok(erased)
+}
+
+async fn read_segment(segment: &ArchivedSegment) -> Result<Vec<RecordBatch>> {
+    let mut reader = SegmentReader::open(&segment.location, SEGMENT_BATCH_SIZE).await?;
+    let mut batches = Vec::new();
+    while let Some(batch) = reader.next_record_batch() {
+        batches.push(batch?);
+    }
+    Ok(batches)
+}
+
+/// Which rows of a segment batch the selector matches, by their time and
+/// attributes.
+fn matching_rows(selector: &ErasureSelector, dataset: ExportDataset, batch: &RecordBatch) -> Result<Vec<bool>> {
+    let (_, time) = lifecycle::table(dataset);
+    let times = batch
+        .column_by_name(time)
+        .and_then(|column| column.as_any().downcast_ref::<TimestampMicrosecondArray>())
+        .ok_or_else(|| anyhow::anyhow!("Segment has no {} column", time))?;
+    let attributes = text_column(batch, "attributes")?;
+    Ok((0..batch.num_rows())
+        .map(|row| {
+            let at = times.is_valid(row).then(|| times.value(row)).and_then(DateTime::from_timestamp_micros);
+            let attributes = attributes.is_valid(row).then(|| attributes.value(row));
+            let attributes: Option<Value> = attributes.and_then(|text| serde_json::from_str(text).ok());
+            at.map_or(false, |at| selector.in_range(at)) && selector.matches_any([attributes.as_ref()])
+        })
+        .collect())
+}
+
+/// `batch` with the selector's attribute removed from the `matched` rows,
+/// and how many rows it was removed from.
+fn scrub(selector: &ErasureSelector, batch: &RecordBatch, matched: &[bool]) -> Result<(RecordBatch, usize)> {
+    let attribute = selector.scrubbed()?;
+    let mut scrubbed = 0;
+    let attributes: StringArray = text_column(batch, "attributes")?
+        .iter()
+        .zip(matched)
+        .map(|(text, &matched)| {
+            let mut value: Value = match text.filter(|_| matched).map(serde_json::from_str) {
+                Some(Ok(value)) => value,
+                _ => return text.map(str::to_string),
+            };
+            if attribute.remove(&mut value) {
+                scrubbed += 1;
+            }
+            Some(value.to_string())
+        })
+        .collect();
+
+    let index = batch.schema().index_of("attributes")?;
+    let mut columns: Vec<ArrayRef> = batch.columns().to_vec();
+    columns[index] = Arc::new(attributes);
+    Ok((RecordBatch::try_new(batch.schema(), columns)?, scrubbed))
+}
+
+fn text_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a StringArray> {
+    batch
+        .column_by_name(name)
+        .and_then(|column| column.as_any().downcast_ref::<StringArray>())
+        .ok_or_else(|| anyhow::anyhow!("Segment has no {} column", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LifecycleConfig, LifecycleRule};
+    use crate::processor::{ProcessedMessage, ProcessingMetadata};
+    use crate::storage::MemoryStorage;
+    use serde_json::json;
+
+    fn selector(attribute: Option<AttributeMatch>) -> ErasureSelector {
+        ErasureSelector {
+            tenant_id: "acme".to_string(),
+            attribute,
+            start_time: None,
+            end_time: None,
+        }
+    }
+
+    fn message(id: &str, offset: i64, user: &str) -> ProcessedMessage {
+        ProcessedMessage {
+            id: id.to_string(),
+            original_message: json!({"user": {"email": user}, "offset": offset}),
+            processed_message: json!({"offset": offset}),
+            processing_metadata: ProcessingMetadata {
+                tenant_id: "acme".to_string(),
+                processed_at: Utc::now(),
+                processor_version: "1.0.0".to_string(),
+                source_topic: "events".to_string(),
+                partition: 0,
+                offset,
+                traceparent: None,
+            },
+        }
+    }
+
+    async fn store_log(storage: &MemoryStorage, tenant_id: &str, user: &str) {
+        storage
+            .store_log(
+                tenant_id,
+                "info",
+                "signed in",
+                None,
+                None,
+                None,
+                None,
+                Some(json!({"user": {"email": user}})),
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_erase_deletes_matching_records_of_the_tenant() {
+        let storage = MemoryStorage::with_capacity(100);
+        store_log(&storage, "acme", "alice@example.com").await;
+        store_log(&storage, "acme", "bob@example.com").await;
+        store_log(&storage, "globex", "alice@example.com").await;
+        let messages = [message("m-1", 1, "alice@example.com"), message("m-2", 2, "bob@example.com")];
+        storage.store_processed_messages(&messages).await.unwrap();
+
+        let request = ErasureRequest {
+            selector: selector(Some(AttributeMatch::new("user.email", "alice@example.com"))),
+            mode: ErasureMode::Delete,
+            datasets: vec![ErasureDataset::Logs, ErasureDataset::ProcessedMessages],
+        };
+        let mut reported = Vec::new();
+        let erased = erase(&storage, &request, |dataset, records| reported.push((dataset, records)))
+            .await
+            .unwrap();
+
+        assert_eq!(erased, 2);
+        assert_eq!(reported, vec![(ErasureDataset::Logs, 1), (ErasureDataset::ProcessedMessages, 1)]);
+        let tenants: Vec<_> = storage.logs().into_iter().map(|log| log.tenant_id).collect();
+        assert_eq!(tenants, vec!["acme", "globex"]);
+        assert!(storage.get_processed_message("acme", "m-1").await.unwrap().is_none());
+        assert!(storage.get_processed_message("acme", "m-2").await.unwrap().is_some());
+
+        // Nothing matches any more, so running it again erases nothing
+        assert_eq!(erase(&storage, &request, |_, _| {}).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_scrub_removes_the_attribute_and_keeps_the_record() {
+        let storage = MemoryStorage::with_capacity(100);
+        store_log(&storage, "acme", "alice@example.com").await;
+
+        let request = ErasureRequest {
+            selector: selector(Some(AttributeMatch::new("user.email", "alice@example.com"))),
+            mode: ErasureMode::Scrub,
+            datasets: ErasureDataset::ALL.to_vec(),
+        };
+        assert_eq!(erase(&storage, &request, |_, _| {}).await.unwrap(), 1);
+        assert_eq!(storage.logs()[0].attributes, Some(json!({"user": {}})));
+
+        let unscoped = ErasureRequest {
+            selector: selector(None),
+            ..request
+        };
+        assert!(erase(&storage, &unscoped, |_, _| {}).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_erase_rewrites_archived_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LifecycleConfig {
+            enabled: true,
+            destination: dir.path().display().to_string(),
+            ..LifecycleConfig::default()
+        };
+        let rule = LifecycleRule {
+            dataset: ExportDataset::Logs,
+            archive_after_days: 7,
+        };
+        let storage = MemoryStorage::with_capacity(100);
+        let at = Utc::now() - chrono::Duration::days(30);
+        for user in ["alice@example.com", "bob@example.com"] {
+            let attributes = json!({"user": {"email": user}});
+            storage
+                .store_log("acme", "info", "signed in", None, None, None, None, Some(attributes), at)
+                .await
+                .unwrap();
+        }
+        let segments = lifecycle::archive(&storage, &config, &rule, None, Utc::now()).await.unwrap();
+        assert!(storage.logs().is_empty());
+
+        let request = ErasureRequest {
+            selector: selector(Some(AttributeMatch::new("user.email", "alice@example.com"))),
+            mode: ErasureMode::Delete,
+            datasets: vec![ErasureDataset::Logs],
+        };
+        assert_eq!(erase(&storage, &request, |_, _| {}).await.unwrap(), 1);
+        let mut reader = SegmentReader::open(&segments[0].location, 10).await.unwrap();
+        let emails: Vec<_> = reader
+            .next_batch()
+            .unwrap()
+            .unwrap()
+            .iter()
+            .map(|log| log["fields"]["user"]["email"].clone())
+            .collect();
+        assert_eq!(emails, vec![json!("bob@example.com")]);
+        assert_eq!(erase(&storage, &request, |_, _| {}).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_attribute_matching() {
+        let attribute = AttributeMatch::new("user.id", "42");
+        assert!(attribute.matches(&json!({"user": {"id": 42}})));
+        assert!(attribute.matches(&json!({"user": {"id": "42"}})));
+        assert!(!attribute.matches(&json!({"user": {"id": [42]}})));
+        assert!(!AttributeMatch::new("admin", "true").matches(&json!({"admin": true})));
+        assert!(!attribute.matches(&json!({"user": 42})));
+
+        assert_eq!(AttributeMatch::new("a.b\"c", "").json_path(), r#"$."a"."b\"c""#);
+    }
+}
@@ -0,0 +1,711 @@
+use super::{FieldCipher, StorageBackend, TraceSpan};
+use anyhow::Result;
+use arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray, TimestampMicrosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Utc};
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+/// Exports are read in windows of this many hours so memory stays bounded no
+/// matter how large the requested time range is.
+const EXPORT_WINDOW_HOURS: i64 = 1;
+
+/// Upper bound on traces fetched per window; effectively unbounded.
+const TRACES_PER_WINDOW: i64 = i64::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportDataset {
+    Metrics,
+    Logs,
+    /// One row per span.
+    Traces,
+}
+
+impl ExportDataset {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportDataset::Metrics => "metrics",
+            ExportDataset::Logs => "logs",
+            ExportDataset::Traces => "traces",
+        }
+    }
+}
+
+impl FromStr for ExportDataset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "metrics" => Ok(ExportDataset::Metrics),
+            "logs" => Ok(ExportDataset::Logs),
+            "traces" => Ok(ExportDataset::Traces),
+            other => Err(anyhow::anyhow!("Unknown dataset: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// What to export and where. `destination` is a local file path or an
+/// `s3://bucket/key` URL; S3 credentials and region come from the standard
+/// AWS environment variables.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportRequest {
+    pub tenant_id: String,
+    pub dataset: ExportDataset,
+    pub format: ExportFormat,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub destination: String,
+    /// Metrics only: restrict to one metric name.
+    pub metric_name: Option<String>,
+    /// Logs only: restrict to one level.
+    pub log_level: Option<String>,
+    /// Logs and traces: restrict to one service.
+    pub service_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub location: String,
+    pub rows: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ColumnType {
+    Text,
+    Float,
+    Integer,
+    Timestamp,
+}
+
+struct Column {
+    name: &'static str,
+    column_type: ColumnType,
+}
+
+const fn column(name: &'static str, column_type: ColumnType) -> Column {
+    Column { name, column_type }
+}
+
+const METRIC_COLUMNS: &[Column] = &[
+    column("timestamp", ColumnType::Timestamp),
+    column("metric_name", ColumnType::Text),
+    column("metric_value", ColumnType::Float),
+    column("metric_type", ColumnType::Text),
+    column("tags", ColumnType::Text),
+    column("resolution_seconds", ColumnType::Integer),
+];
+
+const LOG_COLUMNS: &[Column] = &[
+    column("timestamp", ColumnType::Timestamp),
+    column("log_level", ColumnType::Text),
+    column("message", ColumnType::Text),
+    column("service_name", ColumnType::Text),
+    column("host_name", ColumnType::Text),
+    column("trace_id", ColumnType::Text),
+    column("span_id", ColumnType::Text),
+    column("attributes", ColumnType::Text),
+];
+
+const SPAN_COLUMNS: &[Column] = &[
+    column("trace_id", ColumnType::Text),
+    column("span_id", ColumnType::Text),
+    column("parent_span_id", ColumnType::Text),
+    column("name", ColumnType::Text),
+    column("service_name", ColumnType::Text),
+    column("start_time", ColumnType::Timestamp),
+    column("end_time", ColumnType::Timestamp),
+    column("duration_ms", ColumnType::Integer),
+    column("status", ColumnType::Text),
+    column("attributes", ColumnType::Text),
+    column("events", ColumnType::Text),
+];
+
+/// A single cell; the variant always matches the column's [`ColumnType`].
+#[derive(Debug, Clone)]
+enum Value {
+    Text(Option<String>),
+    Float(Option<f64>),
+    Integer(Option<i64>),
+    Timestamp(Option<DateTime<Utc>>),
+}
+
+fn json_text(value: &Option<serde_json::Value>) -> Value {
+    Value::Text(value.as_ref().map(|v| v.to_string()))
+}
+
+type Row = Vec<Value>;
+
+trait RowWriter: Send {
+    fn write_rows(&mut self, rows: &[Row]) -> Result<()>;
+
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+struct CsvWriter {
+    writer: csv::Writer<File>,
+}
+
+impl CsvWriter {
+    fn create(path: &Path, columns: &[Column]) -> Result<Self> {
+        let mut writer = csv::Writer::from_path(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create export file {}: {}", path.display(), e))?;
+        writer.write_record(columns.iter().map(|c| c.name))?;
+        Ok(Self { writer })
+    }
+}
+
+impl RowWriter for CsvWriter {
+    fn write_rows(&mut self, rows: &[Row]) -> Result<()> {
+        for row in rows {
+            let record = row.iter().map(|value| match value {
+                Value::Text(v) => v.clone().unwrap_or_default(),
+                Value::Float(v) => v.map(|v| v.to_string()).unwrap_or_default(),
+                Value::Integer(v) => v.map(|v| v.to_string()).unwrap_or_default(),
+                Value::Timestamp(v) => v.map(|v| v.to_rfc3339()).unwrap_or_default(),
+            });
+            self.writer.write_record(record)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+struct ParquetWriter {
+    schema: Arc<Schema>,
+    writer: ArrowWriter<File>,
+}
+
+impl ParquetWriter {
+    fn create(path: &Path, columns: &[Column]) -> Result<Self> {
+        let schema = arrow_schema(columns);
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create export file {}: {}", path.display(), e))?;
+        let writer = ArrowWriter::try_new(file, schema.clone(), None)?;
+
+        Ok(Self { schema, writer })
+    }
+}
+
+impl RowWriter for ParquetWriter {
+    fn write_rows(&mut self, rows: &[Row]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.writer.write(&record_batch(&self.schema, rows)?)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+fn arrow_schema(columns: &[Column]) -> Arc<Schema> {
+    let fields: Vec<Field> = columns
+        .iter()
+        .map(|c| {
+            let data_type = match c.column_type {
+                ColumnType::Text => DataType::Utf8,
+                ColumnType::Float => DataType::Float64,
+                ColumnType::Integer => DataType::Int64,
+                ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            };
+            Field::new(c.name, data_type, true)
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+/// The Arrow schema of exported `dataset` rows, which Parquet exports and
+/// archived segments are written with.
+pub(super) fn schema(dataset: ExportDataset) -> Arc<Schema> {
+    arrow_schema(columns(dataset))
+}
+
+fn columns(dataset: ExportDataset) -> &'static [Column] {
+    match dataset {
+        ExportDataset::Metrics => METRIC_COLUMNS,
+        ExportDataset::Logs => LOG_COLUMNS,
+        ExportDataset::Traces => SPAN_COLUMNS,
+    }
+}
+
+/// `rows`, which must not be empty, as one batch of `schema`.
+fn record_batch(schema: &Arc<Schema>, rows: &[Row]) -> Result<RecordBatch> {
+    let arrays: Vec<ArrayRef> = (0..schema.fields().len())
+        .map(|i| -> ArrayRef {
+            match &rows[0][i] {
+                Value::Text(_) => Arc::new(StringArray::from(
+                    rows.iter()
+                        .map(|r| match &r[i] {
+                            Value::Text(v) => v.clone(),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+                Value::Float(_) => Arc::new(Float64Array::from(
+                    rows.iter()
+                        .map(|r| match &r[i] {
+                            Value::Float(v) => *v,
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+                Value::Integer(_) => Arc::new(Int64Array::from(
+                    rows.iter()
+                        .map(|r| match &r[i] {
+                            Value::Integer(v) => *v,
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+                Value::Timestamp(_) => Arc::new(
+                    TimestampMicrosecondArray::from(
+                        rows.iter()
+                            .map(|r| match &r[i] {
+                                Value::Timestamp(v) => v.map(|t| t.timestamp_micros()),
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                    .with_timezone("UTC"),
+                ),
+            }
+        })
+        .collect();
+
+    Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+}
+
+/// A local path or an object in S3, as given by `destination` strings.
+pub(super) enum Destination {
+    Local(PathBuf),
+    S3 { bucket: String, key: String },
+}
+
+impl Destination {
+    pub(super) fn parse(destination: &str) -> Result<Self> {
+        match destination.strip_prefix("s3://") {
+            Some(rest) => {
+                let (bucket, key) = rest
+                    .split_once('/')
+                    .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+                    .ok_or_else(|| anyhow::anyhow!("Invalid S3 destination (expected s3://bucket/key): {}", destination))?;
+                Ok(Destination::S3 {
+                    bucket: bucket.to_string(),
+                    key: key.to_string(),
+                })
+            }
+            None if destination.is_empty() => Err(anyhow::anyhow!("Export destination is required")),
+            None => Ok(Destination::Local(PathBuf::from(destination))),
+        }
+    }
+}
+
+/// Streams the requested dataset into a CSV or Parquet file at
+/// `request.destination`, reading storage one window at a time.
+pub async fn export(storage: &dyn StorageBackend, request: &ExportRequest) -> Result<ExportSummary> {
+    write_export(storage, request, None).await
+}
+
+/// Like [`export`], but encrypts the fields `cipher` covers in log and span
+/// attributes again, so the file holds them as the database does.
+pub(super) async fn export_sealed(
+    storage: &dyn StorageBackend,
+    request: &ExportRequest,
+    cipher: &FieldCipher,
+) -> Result<ExportSummary> {
+    write_export(storage, request, Some(cipher)).await
+}
+
+async fn write_export(
+    storage: &dyn StorageBackend,
+    request: &ExportRequest,
+    seal: Option<&FieldCipher>,
+) -> Result<ExportSummary> {
+    if request.end_time <= request.start_time {
+        return Err(anyhow::anyhow!("Export end_time must be after start_time"));
+    }
+
+    let destination = Destination::parse(&request.destination)?;
+    let local_path = match &destination {
+        Destination::Local(path) => {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            path.clone()
+        }
+        Destination::S3 { .. } => std::env::temp_dir().join(format!(
+            "streamforge-export-{}.{}",
+            uuid::Uuid::new_v4(),
+            request.format.extension()
+        )),
+    };
+
+    let columns = columns(request.dataset);
+    let mut writer: Box<dyn RowWriter> = match request.format {
+        ExportFormat::Csv => Box::new(CsvWriter::create(&local_path, columns)?),
+        ExportFormat::Parquet => Box::new(ParquetWriter::create(&local_path, columns)?),
+    };
+
+    let mut rows_exported = 0u64;
+    let mut window_start = request.start_time;
+    while window_start < request.end_time {
+        let window_end = (window_start + chrono::Duration::hours(EXPORT_WINDOW_HOURS)).min(request.end_time);
+        let rows = fetch_rows(storage, request, seal, window_start, window_end).await?;
+        writer.write_rows(&rows)?;
+        rows_exported += rows.len() as u64;
+        window_start = window_end;
+    }
+    writer.finish()?;
+
+    let location = match destination {
+        Destination::Local(path) => path.display().to_string(),
+        Destination::S3 { bucket, key } => {
+            let uploaded = upload_to_s3(&local_path, &bucket, &key).await;
+            let _ = tokio::fs::remove_file(&local_path).await;
+            uploaded?;
+            format!("s3://{}/{}", bucket, key)
+        }
+    };
+
+    info!(
+        "Exported {} {:?} row(s) for tenant {} to {}",
+        rows_exported, request.dataset, request.tenant_id, location
+    );
+
+    Ok(ExportSummary {
+        location,
+        rows: rows_exported,
+    })
+}
+
+/// The requested rows as record batches of [`schema`], read one window at
+/// a time like an export. Fails once more than `max_rows` have been read.
+pub(super) async fn record_batches(
+    storage: &dyn StorageBackend,
+    request: &ExportRequest,
+    max_rows: usize,
+) -> Result<Vec<RecordBatch>> {
+    let schema = schema(request.dataset);
+    let mut batches = Vec::new();
+    let mut rows_read = 0;
+    let mut window_start = request.start_time;
+    while window_start < request.end_time {
+        let window_end = (window_start + chrono::Duration::hours(EXPORT_WINDOW_HOURS)).min(request.end_time);
+        let rows = fetch_rows(storage, request, None, window_start, window_end).await?;
+        rows_read += rows.len();
+        if rows_read > max_rows {
+            return Err(anyhow::anyhow!(
+                "More than {} {} rows to read from the database",
+                max_rows,
+                request.dataset.as_str()
+            ));
+        }
+        if !rows.is_empty() {
+            batches.push(record_batch(&schema, &rows)?);
+        }
+        window_start = window_end;
+    }
+    Ok(batches)
+}
+
+async fn fetch_rows(
+    storage: &dyn StorageBackend,
+    request: &ExportRequest,
+    seal: Option<&FieldCipher>,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<Row>> {
+    let reseal = |attributes: &mut Option<serde_json::Value>| {
+        seal.map_or(Ok(()), |cipher| cipher.encrypt_attributes(attributes))
+    };
+
+    let tenant_id = request.tenant_id.as_str();
+
+    match request.dataset {
+        ExportDataset::Metrics => {
+            let metrics = storage
+                .get_metrics(tenant_id, request.metric_name.as_deref(), start_time, end_time, None)
+                .await?;
+            Ok(metrics
+                .into_iter()
+                .map(|m| {
+                    vec![
+                        Value::Timestamp(Some(m.timestamp)),
+                        Value::Text(Some(m.name)),
+                        Value::Float(Some(m.value)),
+                        Value::Text(Some(m.metric_type)),
+                        json_text(&m.tags),
+                        Value::Integer(m.resolution_secs),
+                    ]
+                })
+                .collect())
+        }
+        ExportDataset::Logs => {
+            let logs = storage
+                .get_logs(
+                    tenant_id,
+                    request.log_level.as_deref(),
+                    request.service_name.as_deref(),
+                    start_time,
+                    end_time,
+                    None,
+                )
+                .await?;
+            logs.into_iter()
+                .map(|mut l| {
+                    reseal(&mut l.attributes)?;
+                    Ok(vec![
+                        Value::Timestamp(Some(l.timestamp)),
+                        Value::Text(Some(l.level)),
+                        Value::Text(Some(l.message)),
+                        Value::Text(l.service_name),
+                        Value::Text(l.host_name),
+                        Value::Text(l.trace_id),
+                        Value::Text(l.span_id),
+                        json_text(&l.attributes),
+                    ])
+                })
+                .collect()
+        }
+        ExportDataset::Traces => {
+            let service = request.service_name.as_deref();
+            let summaries = storage
+                .find_traces(tenant_id, service, None, None, start_time, end_time, Some(TRACES_PER_WINDOW))
+                .await?;
+
+            let mut rows = Vec::new();
+            for summary in summaries {
+                let trace = match storage.get_trace(tenant_id, &summary.trace_id).await? {
+                    Some(trace) => trace,
+                    None => continue,
+                };
+
+                let mut spans = Vec::with_capacity(trace.span_count);
+                flatten_spans(trace.roots, &mut spans);
+
+                // Windows are half-open and assigned by span start time, so
+                // spans of traces crossing a window boundary are exported once.
+                let spans = spans
+                    .into_iter()
+                    .filter(|s| s.start_time >= start_time && s.start_time < end_time)
+                    .filter(|s| service.map_or(true, |svc| s.service_name.as_deref() == Some(svc)));
+                for mut s in spans {
+                    reseal(&mut s.attributes)?;
+                    rows.push(vec![
+                        Value::Text(Some(trace.trace_id.clone())),
+                        Value::Text(Some(s.span_id)),
+                        Value::Text(s.parent_span_id),
+                        Value::Text(Some(s.name)),
+                        Value::Text(s.service_name),
+                        Value::Timestamp(Some(s.start_time)),
+                        Value::Timestamp(s.end_time),
+                        Value::Integer(s.duration_ms),
+                        Value::Text(s.status),
+                        json_text(&s.attributes),
+                        json_text(&s.events),
+                    ]);
+                }
+            }
+            Ok(rows)
+        }
+    }
+}
+
+fn flatten_spans(spans: Vec<TraceSpan>, out: &mut Vec<TraceSpan>) {
+    for mut span in spans {
+        let children = std::mem::take(&mut span.children);
+        out.push(span);
+        flatten_spans(children, out);
+    }
+}
+
+/// S3 client for `bucket`, configured from the standard AWS environment.
+pub(super) fn s3_store(bucket: &str) -> Result<object_store::aws::AmazonS3> {
+    object_store::aws::AmazonS3Builder::from_env()
+        .with_bucket_name(bucket)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to configure S3 client: {}", e))
+}
+
+async fn upload_to_s3(path: &Path, bucket: &str, key: &str) -> Result<()> {
+    use object_store::ObjectStore;
+
+    let store = s3_store(bucket)?;
+    let location = object_store::path::Path::from(key);
+    let (_, mut upload) = store
+        .put_multipart(&location)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to start S3 upload to s3://{}/{}: {}", bucket, key, e))?;
+
+    let mut file = tokio::fs::File::open(path).await?;
+    tokio::io::copy(&mut file, &mut upload)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to upload export to s3://{}/{}: {}", bucket, key, e))?;
+    upload
+        .shutdown()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to complete S3 upload to s3://{}/{}: {}", bucket, key, e))?;
+
+    Ok(())
+}
+
+/// Writes `body` to a local path or an `s3://bucket/key`, replacing any
+/// object already there.
+pub async fn write_object(location: &str, body: Vec<u8>) -> Result<()> {
+    match Destination::parse(location)? {
+        Destination::Local(path) => {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, body)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", location, e))
+        }
+        Destination::S3 { bucket, key } => {
+            use object_store::ObjectStore;
+
+            s3_store(&bucket)?
+                .put(&object_store::path::Path::from(key), body.into())
+                .await
+                .map(|_| ())
+                .map_err(|e| anyhow::anyhow!("Failed to upload {}: {}", location, e))
+        }
+    }
+}
+
+/// Reads an object written by `write_object`.
+pub async fn read_object(location: &str) -> Result<Vec<u8>> {
+    match Destination::parse(location)? {
+        Destination::Local(path) => tokio::fs::read(&path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", location, e)),
+        Destination::S3 { bucket, key } => {
+            use object_store::ObjectStore;
+
+            let body = s3_store(&bucket)?
+                .get(&object_store::path::Path::from(key))
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to download {}: {}", location, e))?
+                .bytes()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to download {}: {}", location, e))?;
+            Ok(body.to_vec())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn request(dataset: ExportDataset, format: ExportFormat, destination: &Path) -> ExportRequest {
+        let start_time = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        ExportRequest {
+            tenant_id: "tenant-a".to_string(),
+            dataset,
+            format,
+            start_time,
+            end_time: start_time + chrono::Duration::hours(3),
+            destination: destination.to_string_lossy().to_string(),
+            metric_name: None,
+            log_level: None,
+            service_name: None,
+        }
+    }
+
+    async fn storage_with_metrics(start: DateTime<Utc>) -> MemoryStorage {
+        let storage = MemoryStorage::with_capacity(100);
+        for minutes in [0, 90, 150] {
+            storage
+                .store_metric(
+                    "tenant-a",
+                    "cpu_usage",
+                    minutes as f64,
+                    "gauge",
+                    Some(serde_json::json!({"host": "a"})),
+                    start + chrono::Duration::minutes(minutes),
+                )
+                .await
+                .unwrap();
+        }
+        storage
+            .store_metric("tenant-b", "cpu_usage", 1.0, "gauge", None, start)
+            .await
+            .unwrap();
+        storage
+    }
+
+    #[tokio::test]
+    async fn test_export_metrics_to_csv_across_windows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out/metrics.csv");
+        let request = request(ExportDataset::Metrics, ExportFormat::Csv, &path);
+        let storage = storage_with_metrics(request.start_time).await;
+
+        let summary = export(&storage, &request).await.unwrap();
+        assert_eq!(summary.rows, 3);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines[0], "timestamp,metric_name,metric_value,metric_type,tags,resolution_seconds");
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].contains(",cpu_usage,90,gauge,"));
+    }
+
+    #[tokio::test]
+    async fn test_export_metrics_to_parquet() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.parquet");
+        let request = request(ExportDataset::Metrics, ExportFormat::Parquet, &path);
+        let storage = storage_with_metrics(request.start_time).await;
+
+        assert_eq!(export(&storage, &request).await.unwrap().rows, 3);
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+    }
+
+    #[test]
+    fn test_destination_parsing() {
+        assert!(matches!(
+            Destination::parse("s3://bucket/exports/a.csv").unwrap(),
+            Destination::S3 { bucket, key } if bucket == "bucket" && key == "exports/a.csv"
+        ));
+        assert!(Destination::parse("s3://bucket").is_err());
+        assert!(matches!(Destination::parse("/tmp/a.csv").unwrap(), Destination::Local(_)));
+    }
+}
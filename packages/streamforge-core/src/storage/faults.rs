@@ -0,0 +1,386 @@
+use super::{
+    AlertDelivery, ArchivedSegment, AuditEntry, AuditFilter, CompactionStats, ErasureDataset, ErasureMode,
+    ErasureSelector, ExportDataset, JobLease, LogRecord, MetricRecord, Page, PoolStatus, ServiceStatusRecord, Silence,
+    StorageBackend, Trace, TraceSummary,
+};
+use crate::config::MetricRetentionRule;
+use crate::faults::FaultInjector;
+use crate::pipeline::PipelineDefinition;
+use crate::processor::ProcessedMessage;
+use crate::rbac::RoleBinding;
+use crate::schemas::SchemaVersion;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Backend that injects faults into the pipeline's writes to another one,
+/// when `faults.enabled` is set. Reads and control-plane writes pass through.
+pub struct FaultyStorage {
+    inner: Arc<dyn StorageBackend>,
+    faults: FaultInjector,
+}
+
+impl FaultyStorage {
+    pub fn new(inner: Arc<dyn StorageBackend>, faults: FaultInjector) -> Self {
+        Self { inner, faults }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for FaultyStorage {
+    async fn store_processed_message(&self, message: &ProcessedMessage) -> Result<()> {
+        self.faults
+            .run("store_processed_message", || self.inner.store_processed_message(message))
+            .await
+    }
+
+    async fn store_processed_messages(&self, messages: &[ProcessedMessage]) -> Result<u64> {
+        self.faults
+            .run("store_processed_messages", || self.inner.store_processed_messages(messages))
+            .await
+    }
+
+    async fn get_processed_message(&self, tenant_id: &str, id: &str) -> Result<Option<ProcessedMessage>> {
+        self.inner.get_processed_message(tenant_id, id).await
+    }
+
+    async fn get_processed_messages_by_topic(
+        &self,
+        tenant_id: &str,
+        topic: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        self.inner.get_processed_messages_by_topic(tenant_id, topic, limit, cursor).await
+    }
+
+    async fn get_processed_messages_by_time_range(
+        &self,
+        tenant_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        self.inner
+            .get_processed_messages_by_time_range(tenant_id, start_time, end_time, limit, cursor)
+            .await
+    }
+
+    async fn store_metric(
+        &self,
+        tenant_id: &str,
+        name: &str,
+        value: f64,
+        metric_type: &str,
+        tags: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        self.faults
+            .run("store_metric", || {
+                self.inner.store_metric(tenant_id, name, value, metric_type, tags.clone(), timestamp)
+            })
+            .await
+    }
+
+    async fn store_metrics(&self, tenant_id: &str, metrics: &[MetricRecord]) -> Result<()> {
+        self.faults
+            .run("store_metrics", || self.inner.store_metrics(tenant_id, metrics))
+            .await
+    }
+
+    async fn store_log(
+        &self,
+        tenant_id: &str,
+        level: &str,
+        message: &str,
+        service_name: Option<&str>,
+        host_name: Option<&str>,
+        trace_id: Option<&str>,
+        span_id: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        self.faults
+            .run("store_log", || {
+                self.inner.store_log(
+                    tenant_id,
+                    level,
+                    message,
+                    service_name,
+                    host_name,
+                    trace_id,
+                    span_id,
+                    attributes.clone(),
+                    timestamp,
+                )
+            })
+            .await
+    }
+
+    async fn store_logs(&self, tenant_id: &str, logs: &[LogRecord]) -> Result<()> {
+        self.faults
+            .run("store_logs", || self.inner.store_logs(tenant_id, logs))
+            .await
+    }
+
+    async fn store_trace(
+        &self,
+        tenant_id: &str,
+        trace_id: &str,
+        span_id: &str,
+        parent_span_id: Option<&str>,
+        name: &str,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: Option<DateTime<Utc>>,
+        duration_ms: Option<i64>,
+        status: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        events: Option<serde_json::Value>,
+    ) -> Result<()> {
+        self.faults
+            .run("store_trace", || {
+                self.inner.store_trace(
+                    tenant_id,
+                    trace_id,
+                    span_id,
+                    parent_span_id,
+                    name,
+                    service_name,
+                    start_time,
+                    end_time,
+                    duration_ms,
+                    status,
+                    attributes.clone(),
+                    events.clone(),
+                )
+            })
+            .await
+    }
+
+    async fn get_metrics(
+        &self,
+        tenant_id: &str,
+        name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<MetricRecord>> {
+        self.inner.get_metrics(tenant_id, name, start_time, end_time, limit).await
+    }
+
+    async fn list_metric_names(&self, tenant_id: &str, limit: i64) -> Result<Vec<String>> {
+        self.inner.list_metric_names(tenant_id, limit).await
+    }
+
+    async fn get_logs(
+        &self,
+        tenant_id: &str,
+        level: Option<&str>,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<LogRecord>> {
+        self.inner
+            .get_logs(tenant_id, level, service_name, start_time, end_time, limit)
+            .await
+    }
+
+    async fn get_trace(&self, tenant_id: &str, trace_id: &str) -> Result<Option<Trace>> {
+        self.inner.get_trace(tenant_id, trace_id).await
+    }
+
+    async fn find_traces(
+        &self,
+        tenant_id: &str,
+        service: Option<&str>,
+        min_duration: Option<Duration>,
+        status: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<TraceSummary>> {
+        self.inner
+            .find_traces(tenant_id, service, min_duration, status, start_time, end_time, limit)
+            .await
+    }
+
+    async fn compact_metrics(&self, rule: &MetricRetentionRule, cutoff: DateTime<Utc>) -> Result<CompactionStats> {
+        self.inner.compact_metrics(rule, cutoff).await
+    }
+
+    async fn erase_records(
+        &self,
+        dataset: ErasureDataset,
+        selector: &ErasureSelector,
+        mode: ErasureMode,
+        limit: i64,
+    ) -> Result<u64> {
+        self.inner.erase_records(dataset, selector, mode, limit).await
+    }
+
+    async fn oldest_rows(&self, dataset: ExportDataset, before: DateTime<Utc>) -> Result<Vec<(String, DateTime<Utc>)>> {
+        self.inner.oldest_rows(dataset, before).await
+    }
+
+    async fn record_archived_segment(&self, segment: &ArchivedSegment) -> Result<bool> {
+        self.inner.record_archived_segment(segment).await
+    }
+
+    async fn list_archived_segments(
+        &self,
+        tenant_id: &str,
+        dataset: ExportDataset,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<ArchivedSegment>> {
+        self.inner
+            .list_archived_segments(tenant_id, dataset, start_time, end_time)
+            .await
+    }
+
+    async fn create_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        self.inner.create_pipeline(pipeline).await
+    }
+
+    async fn update_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        self.inner.update_pipeline(pipeline).await
+    }
+
+    async fn delete_pipeline(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        self.inner.delete_pipeline(tenant_id, id).await
+    }
+
+    async fn get_pipeline(&self, tenant_id: &str, id: &str) -> Result<Option<PipelineDefinition>> {
+        self.inner.get_pipeline(tenant_id, id).await
+    }
+
+    async fn list_pipelines(
+        &self,
+        tenant_id: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<PipelineDefinition>> {
+        self.inner.list_pipelines(tenant_id, limit, cursor).await
+    }
+
+    async fn list_scheduled_pipelines(&self) -> Result<Vec<PipelineDefinition>> {
+        self.inner.list_scheduled_pipelines().await
+    }
+
+    async fn acquire_job_lease(&self, lease: &JobLease) -> Result<bool> {
+        self.inner.acquire_job_lease(lease).await
+    }
+
+    async fn renew_job_lease(
+        &self,
+        pipeline_id: &str,
+        owner: &str,
+        job_id: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        self.inner.renew_job_lease(pipeline_id, owner, job_id, expires_at).await
+    }
+
+    async fn release_job_lease(&self, pipeline_id: &str, owner: &str) -> Result<bool> {
+        self.inner.release_job_lease(pipeline_id, owner).await
+    }
+
+    async fn get_job_lease(&self, job_id: &str) -> Result<Option<JobLease>> {
+        self.inner.get_job_lease(job_id).await
+    }
+
+    async fn list_expired_job_leases(&self, now: DateTime<Utc>) -> Result<Vec<JobLease>> {
+        self.inner.list_expired_job_leases(now).await
+    }
+
+    async fn store_service_statuses(&self, statuses: &[ServiceStatusRecord]) -> Result<()> {
+        self.faults
+            .run("store_service_statuses", || self.inner.store_service_statuses(statuses))
+            .await
+    }
+
+    async fn list_service_statuses(&self, tenant_id: &str) -> Result<Vec<ServiceStatusRecord>> {
+        self.inner.list_service_statuses(tenant_id).await
+    }
+
+    async fn record_alert_delivery(&self, delivery: &AlertDelivery) -> Result<()> {
+        self.faults
+            .run("record_alert_delivery", || self.inner.record_alert_delivery(delivery))
+            .await
+    }
+
+    async fn list_alert_deliveries(
+        &self,
+        tenant_id: &str,
+        alert_id: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Vec<AlertDelivery>> {
+        self.inner.list_alert_deliveries(tenant_id, alert_id, limit).await
+    }
+
+    async fn create_silence(&self, silence: &Silence) -> Result<bool> {
+        self.inner.create_silence(silence).await
+    }
+
+    async fn delete_silence(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        self.inner.delete_silence(tenant_id, id).await
+    }
+
+    async fn list_silences(&self, tenant_id: &str, active_at: Option<DateTime<Utc>>) -> Result<Vec<Silence>> {
+        self.inner.list_silences(tenant_id, active_at).await
+    }
+
+    async fn list_unexpired_silences(&self, now: DateTime<Utc>) -> Result<Vec<Silence>> {
+        self.inner.list_unexpired_silences(now).await
+    }
+
+    async fn append_audit_entry(&self, entry: &AuditEntry) -> Result<()> {
+        self.inner.append_audit_entry(entry).await
+    }
+
+    async fn query_audit_log(
+        &self,
+        filter: &AuditFilter,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<AuditEntry>> {
+        self.inner.query_audit_log(filter, limit, cursor).await
+    }
+
+    async fn create_schema_version(&self, version: &SchemaVersion) -> Result<bool> {
+        self.inner.create_schema_version(version).await
+    }
+
+    async fn list_schema_versions(&self, tenant_id: &str, topic: &str) -> Result<Vec<SchemaVersion>> {
+        self.inner.list_schema_versions(tenant_id, topic).await
+    }
+
+    async fn list_latest_schema_versions(&self) -> Result<Vec<SchemaVersion>> {
+        self.inner.list_latest_schema_versions().await
+    }
+
+    async fn create_role_binding(&self, binding: &RoleBinding) -> Result<bool> {
+        self.inner.create_role_binding(binding).await
+    }
+
+    async fn delete_role_binding(&self, subject: &str, role: &str) -> Result<bool> {
+        self.inner.delete_role_binding(subject, role).await
+    }
+
+    async fn list_role_bindings(&self, subject: Option<&str>) -> Result<Vec<RoleBinding>> {
+        self.inner.list_role_bindings(subject).await
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        self.inner.health_check().await
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        self.inner.pool_status()
+    }
+}
@@ -0,0 +1,108 @@
+use super::StorageBackend;
+use crate::config::RetryConfig;
+use crate::health::HealthState;
+use crate::metrics::Metrics;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Component name under which storage reports readiness.
+pub const STORAGE_COMPONENT: &str = "storage";
+
+/// Exponential backoff with a cap, driven by `database.retry`.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(config: &RetryConfig) -> Self {
+        Self {
+            initial: config.initial_backoff,
+            max: config.max_backoff,
+            multiplier: config.multiplier.max(1.0),
+            current: config.initial_backoff,
+        }
+    }
+
+    /// Returns the delay to wait before the next attempt and advances.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = self.current.mul_f64(self.multiplier).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+/// Periodically health-checks storage and publishes the result to the
+/// readiness registry. While the database is down, checks back off
+/// exponentially; the sqlx pool re-establishes connections on its own once
+/// the database accepts them again.
+pub async fn monitor(
+    storage: Arc<dyn StorageBackend>,
+    config: RetryConfig,
+    health: Arc<HealthState>,
+    metrics: Arc<Metrics>,
+) {
+    let mut backoff = Backoff::new(&config);
+    let mut healthy = true;
+
+    loop {
+        let ok = storage.health_check().await.unwrap_or(false);
+        metrics.set_database_healthy(ok);
+
+        let delay = if ok {
+            if !healthy {
+                info!("Database connection recovered");
+            }
+            health.set(STORAGE_COMPONENT, true, "ok");
+            backoff.reset();
+            config.health_check_interval
+        } else {
+            let delay = backoff.next_delay();
+            if healthy {
+                warn!("Database health check failed; retrying with backoff");
+            }
+            health.set(
+                STORAGE_COMPONENT,
+                false,
+                format!("database unreachable, next check in {:?}", delay),
+            );
+            delay
+        };
+
+        healthy = ok;
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_to_cap_and_resets() {
+        let config = RetryConfig {
+            max_attempts: 0,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            multiplier: 2.0,
+            health_check_interval: Duration::from_secs(5),
+        };
+        let mut backoff = Backoff::new(&config);
+
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(200));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(350));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(350));
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+}
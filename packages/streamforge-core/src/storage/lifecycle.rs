@@ -0,0 +1,281 @@
+use super::export::{export, export_sealed, s3_store, Destination, ExportDataset, ExportFormat, ExportRequest};
+use super::{FieldCipher, MetricRecord, SegmentReader, StorageBackend};
+use crate::config::{LifecycleConfig, LifecycleRule};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Rows read from a segment at a time by federated queries and erasures.
+pub(super) const SEGMENT_BATCH_SIZE: usize = 10_000;
+
+/// A Parquet segment of the warm tier, holding one tenant's rows of a
+/// dataset from `[start_time, end_time)`. Rows arriving late for a window
+/// already archived end up in another segment of the same window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedSegment {
+    pub location: String,
+    pub tenant_id: String,
+    pub dataset: ExportDataset,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// Rows when archived; erasures may have removed some since.
+    pub rows: u64,
+    pub archived_at: DateTime<Utc>,
+}
+
+/// Runs every lifecycle rule once per `lifecycle.interval`. A failing rule is
+/// logged and retried on the next tick; it does not stop the others.
+/// `cipher` is the one of `database.encryption`, when enabled.
+pub async fn run(storage: Arc<dyn StorageBackend>, config: LifecycleConfig, cipher: Option<FieldCipher>) {
+    info!(
+        "Data lifecycle started with {} rule(s), archiving to {}",
+        config.rules.len(),
+        config.destination
+    );
+    let mut interval = tokio::time::interval(config.interval);
+
+    loop {
+        interval.tick().await;
+
+        for rule in &config.rules {
+            match archive(storage.as_ref(), &config, rule, cipher.as_ref(), Utc::now()).await {
+                Ok(segments) if !segments.is_empty() => info!(
+                    "Archived {} row(s) of {} into {} segment(s)",
+                    segments.iter().map(|s| s.rows).sum::<u64>(),
+                    rule.dataset.as_str(),
+                    segments.len()
+                ),
+                Ok(_) => {}
+                Err(e) => warn!("Archiving {} failed: {}", rule.dataset.as_str(), e),
+            }
+        }
+    }
+}
+
+/// Moves every whole segment of `rule.dataset` older than the rule allows
+/// from the database to the warm tier, oldest first, and returns the
+/// segments written. Each segment is exported, then cataloged while its rows
+/// are deleted; when the rows changed in between, the segment is discarded
+/// and the error leaves the window to the next run. With a `cipher`, the
+/// fields it encrypts stay encrypted in the segments.
+pub async fn archive(
+    storage: &dyn StorageBackend,
+    config: &LifecycleConfig,
+    rule: &LifecycleRule,
+    cipher: Option<&FieldCipher>,
+    now: DateTime<Utc>,
+) -> Result<Vec<ArchivedSegment>> {
+    let segment_length = chrono::Duration::hours(i64::from(config.segment_hours));
+    let cutoff = segment_start(
+        now - chrono::Duration::days(i64::from(rule.archive_after_days)),
+        config.segment_hours,
+    );
+
+    let mut archived = Vec::new();
+    loop {
+        let oldest = storage.oldest_rows(rule.dataset, cutoff).await?;
+        if oldest.is_empty() {
+            return Ok(archived);
+        }
+
+        for (tenant_id, oldest_at) in oldest {
+            let start_time = segment_start(oldest_at, config.segment_hours);
+            let location = format!(
+                "{}/{}/{}/{}-{}.parquet",
+                config.destination.trim_end_matches('/'),
+                tenant_id,
+                rule.dataset.as_str(),
+                start_time.format("%Y%m%dT%H%M%SZ"),
+                uuid::Uuid::new_v4().simple()
+            );
+            let request = ExportRequest {
+                tenant_id: tenant_id.clone(),
+                dataset: rule.dataset,
+                format: ExportFormat::Parquet,
+                start_time,
+                end_time: start_time + segment_length,
+                destination: location,
+                metric_name: None,
+                log_level: None,
+                service_name: None,
+            };
+            let summary = match cipher {
+                Some(cipher) => export_sealed(storage, &request, cipher).await?,
+                None => export(storage, &request).await?,
+            };
+
+            let segment = ArchivedSegment {
+                location: summary.location,
+                tenant_id,
+                dataset: rule.dataset,
+                start_time: request.start_time,
+                end_time: request.end_time,
+                rows: summary.rows,
+                archived_at: now,
+            };
+            if !storage.record_archived_segment(&segment).await? {
+                if let Err(e) = remove_segment(&segment.location).await {
+                    warn!("Failed to remove discarded segment {}: {}", segment.location, e);
+                }
+                return Err(anyhow::anyhow!(
+                    "Rows of tenant {} from {} changed while archiving them",
+                    segment.tenant_id,
+                    segment.start_time
+                ));
+            }
+            archived.push(segment);
+        }
+    }
+}
+
+/// Metrics of `tenant_id` from both tiers, with the semantics of
+/// [`StorageBackend::get_metrics`]. Archived rows keep their timestamps to
+/// the millisecond. Metric tags are never encrypted, so archived metrics
+/// read back as they were stored.
+pub async fn get_metrics(
+    storage: &dyn StorageBackend,
+    tenant_id: &str,
+    name: Option<&str>,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    limit: Option<i64>,
+) -> Result<Vec<MetricRecord>> {
+    let limit = limit.map(|limit| limit.max(0) as usize);
+    let segments = storage
+        .list_archived_segments(tenant_id, ExportDataset::Metrics, start_time, end_time)
+        .await?;
+
+    let mut metrics: Vec<MetricRecord> = Vec::new();
+    for segment in segments {
+        // Segments come in start order, so once `limit` rows precede the
+        // next one neither it nor any after it can make the cut.
+        if let Some(limit) = limit {
+            metrics.sort_by_key(|m| m.timestamp);
+            let full = limit == 0 || metrics.get(limit - 1).map_or(false, |m| m.timestamp < segment.start_time);
+            if full {
+                break;
+            }
+        }
+
+        let mut reader = SegmentReader::open(&segment.location, SEGMENT_BATCH_SIZE).await?;
+        while let Some(batch) = reader.next_batch() {
+            metrics.extend(
+                batch?
+                    .iter()
+                    .filter_map(archived_metric)
+                    .filter(|m| name.map_or(true, |n| m.name == n))
+                    .filter(|m| m.timestamp >= start_time && m.timestamp < end_time),
+            );
+        }
+    }
+
+    metrics.extend(
+        storage
+            .get_metrics(tenant_id, name, start_time, end_time, limit.map(|l| l as i64))
+            .await?,
+    );
+    metrics.sort_by_key(|m| m.timestamp);
+    if let Some(limit) = limit {
+        metrics.truncate(limit);
+    }
+    Ok(metrics)
+}
+
+/// The table and time column backing `dataset` in the SQL backends.
+pub(crate) fn table(dataset: ExportDataset) -> (&'static str, &'static str) {
+    match dataset {
+        ExportDataset::Metrics => ("metrics", "timestamp"),
+        ExportDataset::Logs => ("logs", "timestamp"),
+        ExportDataset::Traces => ("traces", "start_time"),
+    }
+}
+
+/// Start of the segment holding `at`, counting whole segments from the epoch.
+fn segment_start(at: DateTime<Utc>, segment_hours: u32) -> DateTime<Utc> {
+    let length = i64::from(segment_hours.max(1)) * 3600;
+    DateTime::from_timestamp(at.timestamp().div_euclid(length) * length, 0).unwrap_or(at)
+}
+
+/// A metric as [`SegmentReader`] reads it back from an exported segment.
+fn archived_metric(record: &Value) -> Option<MetricRecord> {
+    Some(MetricRecord {
+        name: record.get("name")?.as_str()?.to_string(),
+        value: record.get("value")?.as_f64()?,
+        metric_type: record.get("unit").and_then(Value::as_str).unwrap_or_default().to_string(),
+        tags: record.get("labels").filter(|labels| !labels.is_null()).cloned(),
+        timestamp: DateTime::from_timestamp_millis(record.get("timestamp")?.as_i64()?)?,
+        resolution_secs: record.get("resolution_seconds").and_then(Value::as_i64),
+    })
+}
+
+async fn remove_segment(location: &str) -> Result<()> {
+    match Destination::parse(location)? {
+        Destination::Local(path) => tokio::fs::remove_file(&path).await?,
+        Destination::S3 { bucket, key } => {
+            use object_store::ObjectStore;
+
+            s3_store(&bucket)?
+                .delete(&object_store::path::Path::from(key))
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to delete segment {}: {}", location, e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[tokio::test]
+    async fn test_archived_metrics_are_queried_across_tiers() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LifecycleConfig {
+            enabled: true,
+            destination: dir.path().display().to_string(),
+            ..LifecycleConfig::default()
+        };
+        let rule = LifecycleRule {
+            dataset: ExportDataset::Metrics,
+            archive_after_days: 7,
+        };
+        let now = DateTime::parse_from_rfc3339("2024-01-31T12:00:00Z").unwrap().with_timezone(&Utc);
+
+        let storage = MemoryStorage::with_capacity(100);
+        for (tenant_id, days_ago) in [("acme", 10), ("acme", 9), ("acme", 1), ("globex", 10)] {
+            let at = now - chrono::Duration::days(days_ago);
+            storage
+                .store_metric(tenant_id, "cpu_usage", days_ago as f64, "gauge", None, at)
+                .await
+                .unwrap();
+        }
+
+        let segments = archive(&storage, &config, &rule, None, now).await.unwrap();
+        assert_eq!(segments.len(), 3);
+        assert!(segments.iter().all(|s| s.rows == 1 && s.end_time <= now - chrono::Duration::days(7)));
+        assert_eq!(storage.metrics().len(), 1);
+
+        let start = now - chrono::Duration::days(30);
+        let metrics = get_metrics(&storage, "acme", Some("cpu_usage"), start, now, None).await.unwrap();
+        let values: Vec<_> = metrics.iter().map(|m| m.value).collect();
+        assert_eq!(values, vec![10.0, 9.0, 1.0]);
+
+        let first = get_metrics(&storage, "acme", None, start, now, Some(1)).await.unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].value, 10.0);
+
+        // Everything old enough is archived, so another run writes nothing
+        assert!(archive(&storage, &config, &rule, None, now).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_segment_start() {
+        let at = DateTime::parse_from_rfc3339("2024-01-31T13:45:10Z").unwrap().with_timezone(&Utc);
+        assert_eq!(segment_start(at, 24).to_rfc3339(), "2024-01-31T00:00:00+00:00");
+        assert_eq!(segment_start(at, 6).to_rfc3339(), "2024-01-31T12:00:00+00:00");
+    }
+}
@@ -0,0 +1,1251 @@
+use super::compaction::{self, CompactionStats};
+use super::{
+    assemble_trace, page_of_audit_entries, page_of_pipelines, AlertDelivery, ArchivedSegment, AuditEntry,
+    AuditFilter, ErasureDataset, ErasureMode, ErasureSelector, ExportDataset, JobLease, LogRecord, MetricRecord, Page,
+    PageCursor, PoolStatus, ServiceStatusRecord, Silence, StorageBackend, Trace, TraceSpan, TraceSummary,
+};
+use crate::config::{Config, MetricRetentionRule};
+use crate::pipeline::PipelineDefinition;
+use crate::processor::ProcessedMessage;
+use crate::rbac::RoleBinding;
+use crate::schemas::SchemaVersion;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// A stored metric row, kept so benchmarks can assert on what was written.
+#[derive(Debug, Clone)]
+pub struct MemoryMetric {
+    pub tenant_id: String,
+    pub name: String,
+    pub value: f64,
+    pub metric_type: String,
+    pub tags: Option<serde_json::Value>,
+    pub timestamp: DateTime<Utc>,
+    /// Set on rows produced by metric compaction.
+    pub rollup: Option<MetricRollup>,
+}
+
+/// Aggregates kept alongside a rolled-up metric; `value` holds the mean.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricRollup {
+    pub resolution_secs: u64,
+    pub sample_count: u64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// A stored log row.
+#[derive(Debug, Clone)]
+pub struct MemoryLog {
+    pub tenant_id: String,
+    pub level: String,
+    pub message: String,
+    pub service_name: Option<String>,
+    pub host_name: Option<String>,
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub attributes: Option<serde_json::Value>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Fixed-capacity buffer that evicts the oldest entry once full.
+struct Ring<T> {
+    capacity: usize,
+    items: VecDeque<T>,
+}
+
+impl<T> Ring<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            items: VecDeque::new(),
+        }
+    }
+
+    /// Pushes `item`, returning the evicted entry if the buffer was full.
+    fn push(&mut self, item: T) -> Option<T> {
+        let evicted = if self.items.len() >= self.capacity {
+            self.items.pop_front()
+        } else {
+            None
+        };
+        self.items.push_back(item);
+        evicted
+    }
+}
+
+/// Removes up to `limit` items matching `matches`, returning how many.
+fn remove_limited<T>(items: &mut VecDeque<T>, limit: usize, mut matches: impl FnMut(&T) -> bool) -> usize {
+    let mut removed = 0;
+    items.retain(|item| {
+        let remove = removed < limit && matches(item);
+        removed += remove as usize;
+        !remove
+    });
+    removed
+}
+
+type KafkaPosition = (String, i32, i64);
+
+fn kafka_position(message: &ProcessedMessage) -> KafkaPosition {
+    let metadata = &message.processing_metadata;
+    (metadata.source_topic.clone(), metadata.partition, metadata.offset)
+}
+
+/// Processed messages are upserted by Kafka position like the SQL backends,
+/// so the ring is paired with id and position indexes pointing at each entry's
+/// absolute sequence number. As in SQL, a row owned by another tenant is never
+/// overwritten, and an updated row keeps the id it was first stored with.
+struct ProcessedMessages {
+    ring: Ring<ProcessedMessage>,
+    /// Sequence number of `ring.items[0]`.
+    front_seq: u64,
+    index: HashMap<String, u64>,
+    positions: HashMap<KafkaPosition, u64>,
+}
+
+impl ProcessedMessages {
+    /// Returns whether the message was a duplicate of a stored one.
+    fn upsert(&mut self, mut message: ProcessedMessage) -> bool {
+        if let Some(seq) = self.positions.get(&kafka_position(&message)) {
+            let position = (seq - self.front_seq) as usize;
+            let existing = &mut self.ring.items[position];
+            if existing.processing_metadata.tenant_id == message.processing_metadata.tenant_id {
+                message.id = std::mem::take(&mut existing.id);
+                *existing = message;
+            }
+            return true;
+        }
+
+        let seq = self.front_seq + self.ring.items.len() as u64;
+        self.index.insert(message.id.clone(), seq);
+        self.positions.insert(kafka_position(&message), seq);
+        if let Some(evicted) = self.ring.push(message) {
+            self.index.remove(&evicted.id);
+            self.positions.remove(&kafka_position(&evicted));
+            self.front_seq += 1;
+        }
+        false
+    }
+
+    /// Removes up to `limit` messages matching `matches`, renumbering the
+    /// rest so the indexes keep pointing at them.
+    fn remove(&mut self, limit: usize, matches: impl FnMut(&ProcessedMessage) -> bool) -> usize {
+        let removed = remove_limited(&mut self.ring.items, limit, matches);
+        if removed > 0 {
+            self.index.clear();
+            self.positions.clear();
+            for (i, message) in self.ring.items.iter().enumerate() {
+                let seq = self.front_seq + i as u64;
+                self.index.insert(message.id.clone(), seq);
+                self.positions.insert(kafka_position(message), seq);
+            }
+        }
+        removed
+    }
+
+    fn page<F>(&self, tenant_id: &str, limit: i64, cursor: Option<&str>, filter: F) -> Result<Page<ProcessedMessage>>
+    where
+        F: Fn(&ProcessedMessage) -> bool,
+    {
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+
+        let mut matching: Vec<&ProcessedMessage> = self
+            .ring
+            .items
+            .iter()
+            .filter(|m| m.processing_metadata.tenant_id == tenant_id)
+            .filter(|m| filter(m))
+            .filter(|m| match &cursor {
+                Some(c) => (m.processing_metadata.processed_at, m.id.as_str()) < (c.processed_at, c.id.as_str()),
+                None => true,
+            })
+            .collect();
+
+        // Same ordering as the SQL backends: (processed_at, id) descending.
+        matching.sort_by(|a, b| {
+            (b.processing_metadata.processed_at, &b.id).cmp(&(a.processing_metadata.processed_at, &a.id))
+        });
+
+        let has_more = matching.len() as i64 > limit;
+        let items: Vec<ProcessedMessage> = matching.into_iter().take(limit as usize).cloned().collect();
+
+        let next_cursor = match items.last() {
+            Some(last) if has_more => Some(
+                PageCursor {
+                    processed_at: last.processing_metadata.processed_at,
+                    id: last.id.to_string(),
+                }
+                .encode(),
+            ),
+            _ => None,
+        };
+
+        Ok(Page { items, next_cursor })
+    }
+}
+
+struct MemoryTables {
+    processed_messages: ProcessedMessages,
+    metrics: Ring<MemoryMetric>,
+    logs: Ring<MemoryLog>,
+    /// Spans keyed by `(tenant_id, trace_id)`.
+    traces: Ring<((String, String), TraceSpan)>,
+    /// Pipeline definitions keyed by `(tenant_id, id)`; never evicted.
+    pipelines: HashMap<(String, String), PipelineDefinition>,
+    /// Job leases keyed by pipeline id; never evicted.
+    job_leases: HashMap<String, JobLease>,
+    /// Service statuses keyed by `(tenant_id, name)`; never evicted.
+    service_statuses: HashMap<(String, String), ServiceStatusRecord>,
+    /// Alert deliveries, unique by `(tenant_id, id)`.
+    alert_deliveries: Ring<AlertDelivery>,
+    /// Silences keyed by `(tenant_id, id)`; never evicted.
+    silences: HashMap<(String, String), Silence>,
+    /// Audit entries in append order; never evicted.
+    audit_log: Vec<AuditEntry>,
+    /// Schema versions of each `(tenant_id, topic)`, oldest first; never
+    /// evicted.
+    schema_versions: HashMap<(String, String), Vec<SchemaVersion>>,
+    /// Role bindings keyed by `(subject, role)`; never evicted.
+    role_bindings: BTreeMap<(String, String), RoleBinding>,
+    /// Catalog of archived segments in archive order; never evicted.
+    archived_segments: Vec<ArchivedSegment>,
+}
+
+/// In-memory implementation of [`StorageBackend`] backed by per-table ring
+/// buffers of `database.memory_capacity` entries. Nothing is persisted.
+#[derive(Clone)]
+pub struct MemoryStorage {
+    tables: Arc<RwLock<MemoryTables>>,
+}
+
+impl MemoryStorage {
+    pub fn new(config: &Config) -> Self {
+        Self::with_capacity(config.database.memory_capacity)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let tables = MemoryTables {
+            processed_messages: ProcessedMessages {
+                ring: Ring::new(capacity),
+                front_seq: 0,
+                index: HashMap::new(),
+                positions: HashMap::new(),
+            },
+            metrics: Ring::new(capacity),
+            logs: Ring::new(capacity),
+            traces: Ring::new(capacity),
+            pipelines: HashMap::new(),
+            job_leases: HashMap::new(),
+            service_statuses: HashMap::new(),
+            alert_deliveries: Ring::new(capacity),
+            silences: HashMap::new(),
+            audit_log: Vec::new(),
+            schema_versions: HashMap::new(),
+            role_bindings: BTreeMap::new(),
+            archived_segments: Vec::new(),
+        };
+
+        Self {
+            tables: Arc::new(RwLock::new(tables)),
+        }
+    }
+
+    /// Snapshot of the stored metrics, oldest first.
+    pub fn metrics(&self) -> Vec<MemoryMetric> {
+        self.read().metrics.items.iter().cloned().collect()
+    }
+
+    /// Snapshot of the stored logs, oldest first.
+    pub fn logs(&self) -> Vec<MemoryLog> {
+        self.read().logs.items.iter().cloned().collect()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, MemoryTables> {
+        self.tables.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, MemoryTables> {
+        self.tables.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn store_processed_message(&self, message: &ProcessedMessage) -> Result<()> {
+        self.write().processed_messages.upsert(message.clone());
+        Ok(())
+    }
+
+    async fn store_processed_messages(&self, messages: &[ProcessedMessage]) -> Result<u64> {
+        let mut tables = self.write();
+        let mut duplicates = 0;
+        for message in messages {
+            if tables.processed_messages.upsert(message.clone()) {
+                duplicates += 1;
+            }
+        }
+        Ok(duplicates)
+    }
+
+    async fn get_processed_message(&self, tenant_id: &str, id: &str) -> Result<Option<ProcessedMessage>> {
+        let tables = self.read();
+        let messages = &tables.processed_messages;
+        Ok(messages
+            .index
+            .get(id)
+            .map(|seq| &messages.ring.items[(seq - messages.front_seq) as usize])
+            .filter(|m| m.processing_metadata.tenant_id == tenant_id)
+            .cloned())
+    }
+
+    async fn get_processed_messages_by_topic(
+        &self,
+        tenant_id: &str,
+        topic: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        self.read()
+            .processed_messages
+            .page(tenant_id, limit.unwrap_or(100), cursor, |m| m.processing_metadata.source_topic == topic)
+    }
+
+    async fn get_processed_messages_by_time_range(
+        &self,
+        tenant_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        self.read().processed_messages.page(tenant_id, limit.unwrap_or(100), cursor, |m| {
+            let processed_at = m.processing_metadata.processed_at;
+            processed_at >= start_time && processed_at <= end_time
+        })
+    }
+
+    async fn store_metric(
+        &self,
+        tenant_id: &str,
+        name: &str,
+        value: f64,
+        metric_type: &str,
+        tags: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        self.write().metrics.push(MemoryMetric {
+            tenant_id: tenant_id.to_string(),
+            name: name.to_string(),
+            value,
+            metric_type: metric_type.to_string(),
+            tags,
+            timestamp,
+            rollup: None,
+        });
+        Ok(())
+    }
+
+    async fn store_log(
+        &self,
+        tenant_id: &str,
+        level: &str,
+        message: &str,
+        service_name: Option<&str>,
+        host_name: Option<&str>,
+        trace_id: Option<&str>,
+        span_id: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        self.write().logs.push(MemoryLog {
+            tenant_id: tenant_id.to_string(),
+            level: level.to_string(),
+            message: message.to_string(),
+            service_name: service_name.map(str::to_string),
+            host_name: host_name.map(str::to_string),
+            trace_id: trace_id.map(str::to_string),
+            span_id: span_id.map(str::to_string),
+            attributes,
+            timestamp,
+        });
+        Ok(())
+    }
+
+    async fn store_trace(
+        &self,
+        tenant_id: &str,
+        trace_id: &str,
+        span_id: &str,
+        parent_span_id: Option<&str>,
+        name: &str,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: Option<DateTime<Utc>>,
+        duration_ms: Option<i64>,
+        status: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        events: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let span = TraceSpan {
+            span_id: span_id.to_string(),
+            parent_span_id: parent_span_id.map(str::to_string),
+            name: name.to_string(),
+            service_name: service_name.map(str::to_string),
+            start_time,
+            end_time,
+            duration_ms,
+            status: status.map(str::to_string),
+            attributes,
+            events,
+            children: Vec::new(),
+        };
+
+        self.write()
+            .traces
+            .push(((tenant_id.to_string(), trace_id.to_string()), span));
+        Ok(())
+    }
+
+    async fn get_metrics(
+        &self,
+        tenant_id: &str,
+        name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<MetricRecord>> {
+        let mut metrics: Vec<MetricRecord> = self
+            .read()
+            .metrics
+            .items
+            .iter()
+            .filter(|m| m.tenant_id == tenant_id)
+            .filter(|m| name.map_or(true, |n| m.name == n))
+            .filter(|m| m.timestamp >= start_time && m.timestamp < end_time)
+            .map(|m| MetricRecord {
+                name: m.name.clone(),
+                value: m.value,
+                metric_type: m.metric_type.clone(),
+                tags: m.tags.clone(),
+                timestamp: m.timestamp,
+                resolution_secs: m.rollup.as_ref().map(|r| r.resolution_secs as i64),
+            })
+            .collect();
+
+        metrics.sort_by_key(|m| m.timestamp);
+        if let Some(limit) = limit {
+            metrics.truncate(limit.max(0) as usize);
+        }
+        Ok(metrics)
+    }
+
+    async fn list_metric_names(&self, tenant_id: &str, limit: i64) -> Result<Vec<String>> {
+        let names: BTreeSet<String> = self
+            .read()
+            .metrics
+            .items
+            .iter()
+            .filter(|m| m.tenant_id == tenant_id)
+            .map(|m| m.name.clone())
+            .collect();
+        Ok(names.into_iter().take(limit.max(0) as usize).collect())
+    }
+
+    async fn get_logs(
+        &self,
+        tenant_id: &str,
+        level: Option<&str>,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<LogRecord>> {
+        let mut logs: Vec<LogRecord> = self
+            .read()
+            .logs
+            .items
+            .iter()
+            .filter(|l| l.tenant_id == tenant_id)
+            .filter(|l| level.map_or(true, |lv| l.level == lv))
+            .filter(|l| service_name.map_or(true, |svc| l.service_name.as_deref() == Some(svc)))
+            .filter(|l| l.timestamp >= start_time && l.timestamp < end_time)
+            .map(|l| LogRecord {
+                level: l.level.clone(),
+                message: l.message.clone(),
+                service_name: l.service_name.clone(),
+                host_name: l.host_name.clone(),
+                trace_id: l.trace_id.clone(),
+                span_id: l.span_id.clone(),
+                attributes: l.attributes.clone(),
+                timestamp: l.timestamp,
+            })
+            .collect();
+
+        logs.sort_by_key(|l| l.timestamp);
+        if let Some(limit) = limit {
+            logs.truncate(limit.max(0) as usize);
+        }
+        Ok(logs)
+    }
+
+    async fn get_trace(&self, tenant_id: &str, trace_id: &str) -> Result<Option<Trace>> {
+        let mut spans: Vec<TraceSpan> = self
+            .read()
+            .traces
+            .items
+            .iter()
+            .filter(|((tenant, id), _)| tenant == tenant_id && id == trace_id)
+            .map(|(_, span)| span.clone())
+            .collect();
+
+        if spans.is_empty() {
+            return Ok(None);
+        }
+
+        spans.sort_by_key(|s| s.start_time);
+        Ok(Some(assemble_trace(trace_id, spans)))
+    }
+
+    async fn find_traces(
+        &self,
+        tenant_id: &str,
+        service: Option<&str>,
+        min_duration: Option<Duration>,
+        status: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<TraceSummary>> {
+        let min_duration_ms = min_duration.map(|d| d.as_millis() as i64);
+        let tables = self.read();
+
+        let matching: HashSet<&str> = tables
+            .traces
+            .items
+            .iter()
+            .filter(|((tenant, _), _)| tenant == tenant_id)
+            .filter(|(_, s)| s.start_time >= start_time && s.start_time <= end_time)
+            .filter(|(_, s)| service.map_or(true, |svc| s.service_name.as_deref() == Some(svc)))
+            .filter(|(_, s)| min_duration_ms.map_or(true, |min| s.duration_ms.map_or(false, |d| d >= min)))
+            .filter(|(_, s)| status.map_or(true, |st| s.status.as_deref() == Some(st)))
+            .map(|((_, id), _)| id.as_str())
+            .collect();
+
+        let mut by_trace: HashMap<&str, Vec<&TraceSpan>> = HashMap::new();
+        for ((tenant, id), span) in tables.traces.items.iter() {
+            if tenant == tenant_id && matching.contains(id.as_str()) {
+                by_trace.entry(id.as_str()).or_default().push(span);
+            }
+        }
+
+        let mut summaries: Vec<TraceSummary> = by_trace
+            .into_iter()
+            .map(|(trace_id, spans)| {
+                let root = spans
+                    .iter()
+                    .min_by_key(|s| (s.parent_span_id.is_some(), s.start_time))
+                    .expect("trace has at least one span");
+                TraceSummary {
+                    trace_id: trace_id.to_string(),
+                    root_service_name: root.service_name.clone(),
+                    root_span_name: root.name.clone(),
+                    start_time: spans.iter().map(|s| s.start_time).min().unwrap_or(root.start_time),
+                    duration_ms: spans.iter().filter_map(|s| s.duration_ms).max(),
+                    span_count: spans.len() as i64,
+                    has_error: spans.iter().any(|s| s.status.as_deref() == Some("error")),
+                }
+            })
+            .collect();
+
+        summaries.sort_by(|a, b| {
+            (b.duration_ms.is_some(), b.duration_ms, b.start_time).cmp(&(a.duration_ms.is_some(), a.duration_ms, a.start_time))
+        });
+        summaries.truncate(limit.unwrap_or(20).max(0) as usize);
+
+        Ok(summaries)
+    }
+
+    async fn compact_metrics(&self, rule: &MetricRetentionRule, cutoff: DateTime<Utc>) -> Result<CompactionStats> {
+        let resolution = rule.resolution_secs.max(1);
+        let mut tables = self.write();
+        let (source, kept): (Vec<MemoryMetric>, Vec<MemoryMetric>) = tables.metrics.items.drain(..).partition(|m| {
+            compaction::matches(rule, &m.name)
+                && m.timestamp < cutoff
+                && m.rollup.as_ref().map_or(true, |r| r.resolution_secs < resolution)
+        });
+
+        // Keyed like the SQL GROUP BY; tags are compared by their JSON text.
+        let mut groups: HashMap<(String, String, String, Option<String>, i64), Vec<MemoryMetric>> = HashMap::new();
+        let rows_deleted = source.len() as u64;
+        for metric in source {
+            let bucket = metric.timestamp.timestamp().div_euclid(resolution as i64) * resolution as i64;
+            let key = (
+                metric.tenant_id.clone(),
+                metric.name.clone(),
+                metric.metric_type.clone(),
+                metric.tags.as_ref().map(|t| t.to_string()),
+                bucket,
+            );
+            groups.entry(key).or_default().push(metric);
+        }
+
+        let rollups_written = groups.len() as u64;
+        let mut metrics = kept;
+        for ((tenant_id, name, metric_type, _, bucket), rows) in groups {
+            let mut sample_count = 0;
+            let mut sum = 0.0;
+            let mut min = f64::INFINITY;
+            let mut max = f64::NEG_INFINITY;
+            for row in &rows {
+                let (count, row_min, row_max) = match &row.rollup {
+                    Some(r) => (r.sample_count, r.min, r.max),
+                    None => (1, row.value, row.value),
+                };
+                sample_count += count;
+                sum += row.value * count as f64;
+                min = min.min(row_min);
+                max = max.max(row_max);
+            }
+
+            metrics.push(MemoryMetric {
+                tenant_id,
+                name,
+                value: sum / sample_count as f64,
+                metric_type,
+                tags: rows[0].tags.clone(),
+                timestamp: DateTime::from_timestamp(bucket, 0).unwrap_or(cutoff),
+                rollup: Some(MetricRollup {
+                    resolution_secs: resolution,
+                    sample_count,
+                    min,
+                    max,
+                }),
+            });
+        }
+
+        metrics.sort_by_key(|m| m.timestamp);
+        tables.metrics.items.extend(metrics);
+
+        Ok(CompactionStats {
+            rows_deleted,
+            rollups_written,
+        })
+    }
+
+    async fn erase_records(
+        &self,
+        dataset: ErasureDataset,
+        selector: &ErasureSelector,
+        mode: ErasureMode,
+        limit: i64,
+    ) -> Result<u64> {
+        let limit = limit.max(0) as usize;
+        let tenant_id = selector.tenant_id.as_str();
+        let mut tables = self.write();
+
+        let erased = match (dataset, mode) {
+            (ErasureDataset::Logs, ErasureMode::Delete) => remove_limited(&mut tables.logs.items, limit, |log| {
+                log.tenant_id == tenant_id
+                    && selector.in_range(log.timestamp)
+                    && selector.matches_any([log.attributes.as_ref()])
+            }),
+            (ErasureDataset::Logs, ErasureMode::Scrub) => {
+                let attribute = selector.scrubbed()?;
+                tables
+                    .logs
+                    .items
+                    .iter_mut()
+                    .filter(|log| log.tenant_id == tenant_id && selector.in_range(log.timestamp))
+                    .filter_map(|log| log.attributes.as_mut())
+                    .filter(|attributes| attribute.matches(attributes))
+                    .take(limit)
+                    .fold(0, |erased, attributes| erased + attribute.remove(attributes) as usize)
+            }
+            (ErasureDataset::Traces, ErasureMode::Delete) => {
+                let mut traces = HashSet::new();
+                for ((tenant, trace_id), span) in tables.traces.items.iter() {
+                    if traces.len() == limit {
+                        break;
+                    }
+                    if tenant == tenant_id
+                        && selector.in_range(span.start_time)
+                        && selector.matches_any([span.attributes.as_ref()])
+                    {
+                        traces.insert(trace_id.clone());
+                    }
+                }
+                remove_limited(&mut tables.traces.items, usize::MAX, |((tenant, trace_id), _)| {
+                    tenant == tenant_id && traces.contains(trace_id)
+                })
+            }
+            (ErasureDataset::Traces, ErasureMode::Scrub) => {
+                let attribute = selector.scrubbed()?;
+                tables
+                    .traces
+                    .items
+                    .iter_mut()
+                    .filter(|((tenant, _), span)| tenant == tenant_id && selector.in_range(span.start_time))
+                    .filter_map(|(_, span)| span.attributes.as_mut())
+                    .filter(|attributes| attribute.matches(attributes))
+                    .take(limit)
+                    .fold(0, |erased, attributes| erased + attribute.remove(attributes) as usize)
+            }
+            (ErasureDataset::ProcessedMessages, ErasureMode::Delete) => tables.processed_messages.remove(limit, |m| {
+                m.processing_metadata.tenant_id == tenant_id
+                    && selector.in_range(m.processing_metadata.processed_at)
+                    && selector.matches_any([Some(&m.original_message), Some(&m.processed_message)])
+            }),
+            (ErasureDataset::ProcessedMessages, ErasureMode::Scrub) => {
+                let attribute = selector.scrubbed()?;
+                tables
+                    .processed_messages
+                    .ring
+                    .items
+                    .iter_mut()
+                    .filter(|m| {
+                        m.processing_metadata.tenant_id == tenant_id
+                            && selector.in_range(m.processing_metadata.processed_at)
+                            && selector.matches_any([Some(&m.original_message), Some(&m.processed_message)])
+                    })
+                    .take(limit)
+                    .fold(0, |erased, m| {
+                        let removed = attribute.remove(&mut m.original_message);
+                        erased + (attribute.remove(&mut m.processed_message) || removed) as usize
+                    })
+            }
+        };
+
+        Ok(erased as u64)
+    }
+
+    async fn oldest_rows(&self, dataset: ExportDataset, before: DateTime<Utc>) -> Result<Vec<(String, DateTime<Utc>)>> {
+        let tables = self.read();
+        let rows: Vec<(&str, DateTime<Utc>)> = match dataset {
+            ExportDataset::Metrics => tables
+                .metrics
+                .items
+                .iter()
+                .map(|m| (m.tenant_id.as_str(), m.timestamp))
+                .collect(),
+            ExportDataset::Logs => tables.logs.items.iter().map(|l| (l.tenant_id.as_str(), l.timestamp)).collect(),
+            ExportDataset::Traces => tables
+                .traces
+                .items
+                .iter()
+                .map(|((tenant_id, _), span)| (tenant_id.as_str(), span.start_time))
+                .collect(),
+        };
+
+        let mut oldest: BTreeMap<String, DateTime<Utc>> = BTreeMap::new();
+        for (tenant_id, at) in rows.into_iter().filter(|(_, at)| *at < before) {
+            let entry = oldest.entry(tenant_id.to_string()).or_insert(at);
+            *entry = (*entry).min(at);
+        }
+        Ok(oldest.into_iter().collect())
+    }
+
+    async fn record_archived_segment(&self, segment: &ArchivedSegment) -> Result<bool> {
+        let in_segment = |tenant_id: &str, at: DateTime<Utc>| {
+            tenant_id == segment.tenant_id && at >= segment.start_time && at < segment.end_time
+        };
+        let mut tables = self.write();
+        let rows = match segment.dataset {
+            ExportDataset::Metrics => tables
+                .metrics
+                .items
+                .iter()
+                .filter(|m| in_segment(&m.tenant_id, m.timestamp))
+                .count(),
+            ExportDataset::Logs => tables.logs.items.iter().filter(|l| in_segment(&l.tenant_id, l.timestamp)).count(),
+            ExportDataset::Traces => tables
+                .traces
+                .items
+                .iter()
+                .filter(|((tenant_id, _), span)| in_segment(tenant_id, span.start_time))
+                .count(),
+        };
+        if rows as u64 != segment.rows {
+            return Ok(false);
+        }
+
+        match segment.dataset {
+            ExportDataset::Metrics => tables.metrics.items.retain(|m| !in_segment(&m.tenant_id, m.timestamp)),
+            ExportDataset::Logs => tables.logs.items.retain(|l| !in_segment(&l.tenant_id, l.timestamp)),
+            ExportDataset::Traces => tables
+                .traces
+                .items
+                .retain(|((tenant_id, _), span)| !in_segment(tenant_id, span.start_time)),
+        }
+        tables.archived_segments.push(segment.clone());
+        Ok(true)
+    }
+
+    async fn list_archived_segments(
+        &self,
+        tenant_id: &str,
+        dataset: ExportDataset,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<ArchivedSegment>> {
+        let mut segments: Vec<ArchivedSegment> = self
+            .read()
+            .archived_segments
+            .iter()
+            .filter(|s| s.tenant_id == tenant_id && s.dataset == dataset)
+            .filter(|s| s.start_time < end_time && s.end_time > start_time)
+            .cloned()
+            .collect();
+        segments.sort_by(|a, b| (a.start_time, &a.location).cmp(&(b.start_time, &b.location)));
+        Ok(segments)
+    }
+
+    async fn create_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        let key = (pipeline.tenant_id.clone(), pipeline.id.clone());
+        let mut tables = self.write();
+        if tables.pipelines.contains_key(&key) {
+            return Ok(false);
+        }
+        tables.pipelines.insert(key, pipeline.clone());
+        Ok(true)
+    }
+
+    async fn update_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        let key = (pipeline.tenant_id.clone(), pipeline.id.clone());
+        let mut tables = self.write();
+        match tables.pipelines.get_mut(&key) {
+            Some(stored) if stored.version == pipeline.version - 1 => {
+                // Like the SQL backends, an update never changes the creation time.
+                let created_at = stored.created_at;
+                *stored = pipeline.clone();
+                stored.created_at = created_at;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_pipeline(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        let key = (tenant_id.to_string(), id.to_string());
+        Ok(self.write().pipelines.remove(&key).is_some())
+    }
+
+    async fn get_pipeline(&self, tenant_id: &str, id: &str) -> Result<Option<PipelineDefinition>> {
+        let key = (tenant_id.to_string(), id.to_string());
+        Ok(self.read().pipelines.get(&key).cloned())
+    }
+
+    async fn list_pipelines(
+        &self,
+        tenant_id: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<PipelineDefinition>> {
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+
+        let tables = self.read();
+        let mut pipelines: Vec<PipelineDefinition> = tables
+            .pipelines
+            .values()
+            .filter(|p| p.tenant_id == tenant_id)
+            .filter(|p| match &cursor {
+                Some(c) => (p.created_at, p.id.as_str()) < (c.processed_at, c.id.as_str()),
+                None => true,
+            })
+            .cloned()
+            .collect();
+        pipelines.sort_by(|a, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+        pipelines.truncate(limit.max(0) as usize + 1);
+
+        Ok(page_of_pipelines(pipelines, limit))
+    }
+
+    async fn list_scheduled_pipelines(&self) -> Result<Vec<PipelineDefinition>> {
+        let mut pipelines: Vec<PipelineDefinition> = self
+            .read()
+            .pipelines
+            .values()
+            .filter(|p| p.spec.schedule.is_some())
+            .cloned()
+            .collect();
+        pipelines.sort_by(|a, b| (&a.tenant_id, &a.id).cmp(&(&b.tenant_id, &b.id)));
+        Ok(pipelines)
+    }
+
+    async fn acquire_job_lease(&self, lease: &JobLease) -> Result<bool> {
+        let mut tables = self.write();
+        match tables.job_leases.get(&lease.pipeline_id) {
+            Some(held) if held.expires_at > lease.acquired_at => Ok(false),
+            _ => {
+                tables.job_leases.insert(lease.pipeline_id.clone(), lease.clone());
+                Ok(true)
+            }
+        }
+    }
+
+    async fn renew_job_lease(
+        &self,
+        pipeline_id: &str,
+        owner: &str,
+        job_id: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let mut tables = self.write();
+        match tables.job_leases.get_mut(pipeline_id) {
+            Some(lease) if lease.owner == owner => {
+                if let Some(job_id) = job_id {
+                    lease.job_id = Some(job_id.to_string());
+                }
+                lease.expires_at = expires_at;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn release_job_lease(&self, pipeline_id: &str, owner: &str) -> Result<bool> {
+        let mut tables = self.write();
+        if tables.job_leases.get(pipeline_id).map_or(false, |lease| lease.owner == owner) {
+            tables.job_leases.remove(pipeline_id);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn get_job_lease(&self, job_id: &str) -> Result<Option<JobLease>> {
+        Ok(self
+            .read()
+            .job_leases
+            .values()
+            .find(|lease| lease.job_id.as_deref() == Some(job_id))
+            .cloned())
+    }
+
+    async fn list_expired_job_leases(&self, now: DateTime<Utc>) -> Result<Vec<JobLease>> {
+        let mut leases: Vec<JobLease> = self
+            .read()
+            .job_leases
+            .values()
+            .filter(|lease| lease.expires_at <= now)
+            .cloned()
+            .collect();
+        leases.sort_by(|a, b| (a.expires_at, &a.pipeline_id).cmp(&(b.expires_at, &b.pipeline_id)));
+        Ok(leases)
+    }
+
+    async fn store_service_statuses(&self, statuses: &[ServiceStatusRecord]) -> Result<()> {
+        let mut tables = self.write();
+        for status in statuses {
+            tables
+                .service_statuses
+                .insert((status.tenant_id.clone(), status.name.clone()), status.clone());
+        }
+        Ok(())
+    }
+
+    async fn list_service_statuses(&self, tenant_id: &str) -> Result<Vec<ServiceStatusRecord>> {
+        let mut statuses: Vec<ServiceStatusRecord> = self
+            .read()
+            .service_statuses
+            .values()
+            .filter(|status| status.tenant_id == tenant_id)
+            .cloned()
+            .collect();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(statuses)
+    }
+
+    async fn record_alert_delivery(&self, delivery: &AlertDelivery) -> Result<()> {
+        let mut tables = self.write();
+        let stored = tables
+            .alert_deliveries
+            .items
+            .iter_mut()
+            .find(|d| d.tenant_id == delivery.tenant_id && d.id == delivery.id);
+        match stored {
+            Some(stored) => {
+                stored.status = delivery.status.clone();
+                stored.attempts = delivery.attempts;
+                stored.last_error = delivery.last_error.clone();
+                stored.updated_at = delivery.updated_at;
+            }
+            None => {
+                tables.alert_deliveries.push(delivery.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_alert_deliveries(
+        &self,
+        tenant_id: &str,
+        alert_id: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Vec<AlertDelivery>> {
+        let mut deliveries: Vec<AlertDelivery> = self
+            .read()
+            .alert_deliveries
+            .items
+            .iter()
+            .filter(|d| d.tenant_id == tenant_id)
+            .filter(|d| alert_id.map_or(true, |alert_id| d.alert_id == alert_id))
+            .cloned()
+            .collect();
+        deliveries.sort_by(|a, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+        deliveries.truncate(limit.unwrap_or(100).max(0) as usize);
+        Ok(deliveries)
+    }
+
+    async fn create_silence(&self, silence: &Silence) -> Result<bool> {
+        let key = (silence.tenant_id.clone(), silence.id.clone());
+        let mut tables = self.write();
+        if tables.silences.contains_key(&key) {
+            return Ok(false);
+        }
+        tables.silences.insert(key, silence.clone());
+        Ok(true)
+    }
+
+    async fn delete_silence(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        let key = (tenant_id.to_string(), id.to_string());
+        Ok(self.write().silences.remove(&key).is_some())
+    }
+
+    async fn list_silences(&self, tenant_id: &str, active_at: Option<DateTime<Utc>>) -> Result<Vec<Silence>> {
+        let mut silences: Vec<Silence> = self
+            .read()
+            .silences
+            .values()
+            .filter(|s| s.tenant_id == tenant_id)
+            .filter(|s| active_at.map_or(true, |at| s.is_active(at)))
+            .cloned()
+            .collect();
+        silences.sort_by(|a, b| (a.starts_at, &a.id).cmp(&(b.starts_at, &b.id)));
+        Ok(silences)
+    }
+
+    async fn list_unexpired_silences(&self, now: DateTime<Utc>) -> Result<Vec<Silence>> {
+        let mut silences: Vec<Silence> = self
+            .read()
+            .silences
+            .values()
+            .filter(|s| s.ends_at > now)
+            .cloned()
+            .collect();
+        silences.sort_by(|a, b| (&a.tenant_id, a.starts_at, &a.id).cmp(&(&b.tenant_id, b.starts_at, &b.id)));
+        Ok(silences)
+    }
+
+    async fn append_audit_entry(&self, entry: &AuditEntry) -> Result<()> {
+        self.write().audit_log.push(entry.clone());
+        Ok(())
+    }
+
+    async fn query_audit_log(
+        &self,
+        filter: &AuditFilter,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<AuditEntry>> {
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+
+        let mut entries: Vec<AuditEntry> = self
+            .read()
+            .audit_log
+            .iter()
+            .filter(|e| filter.matches(e))
+            .filter(|e| match &cursor {
+                Some(c) => (e.occurred_at, e.id.as_str()) < (c.processed_at, c.id.as_str()),
+                None => true,
+            })
+            .cloned()
+            .collect();
+        entries.sort_by(|a, b| (b.occurred_at, &b.id).cmp(&(a.occurred_at, &a.id)));
+        entries.truncate(limit.max(0) as usize + 1);
+
+        Ok(page_of_audit_entries(entries, limit))
+    }
+
+    async fn create_schema_version(&self, version: &SchemaVersion) -> Result<bool> {
+        let key = (version.tenant_id.clone(), version.topic.clone());
+        let mut tables = self.write();
+        let versions = tables.schema_versions.entry(key).or_default();
+        if versions.iter().any(|v| v.version == version.version) {
+            return Ok(false);
+        }
+        versions.push(version.clone());
+        versions.sort_by_key(|v| v.version);
+        Ok(true)
+    }
+
+    async fn list_schema_versions(&self, tenant_id: &str, topic: &str) -> Result<Vec<SchemaVersion>> {
+        let key = (tenant_id.to_string(), topic.to_string());
+        Ok(self.read().schema_versions.get(&key).cloned().unwrap_or_default())
+    }
+
+    async fn list_latest_schema_versions(&self) -> Result<Vec<SchemaVersion>> {
+        let mut latest: Vec<SchemaVersion> = self
+            .read()
+            .schema_versions
+            .values()
+            .filter_map(|versions| versions.last().cloned())
+            .collect();
+        latest.sort_by(|a, b| (&a.tenant_id, &a.topic).cmp(&(&b.tenant_id, &b.topic)));
+        Ok(latest)
+    }
+
+    async fn create_role_binding(&self, binding: &RoleBinding) -> Result<bool> {
+        let key = (binding.subject.clone(), binding.role.clone());
+        let mut tables = self.write();
+        if tables.role_bindings.contains_key(&key) {
+            return Ok(false);
+        }
+        tables.role_bindings.insert(key, binding.clone());
+        Ok(true)
+    }
+
+    async fn delete_role_binding(&self, subject: &str, role: &str) -> Result<bool> {
+        let key = (subject.to_string(), role.to_string());
+        Ok(self.write().role_bindings.remove(&key).is_some())
+    }
+
+    async fn list_role_bindings(&self, subject: Option<&str>) -> Result<Vec<RoleBinding>> {
+        Ok(self
+            .read()
+            .role_bindings
+            .values()
+            .filter(|binding| subject.map_or(true, |subject| binding.subject == subject))
+            .cloned()
+            .collect())
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        PoolStatus::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::ProcessingMetadata;
+    use serde_json::json;
+
+    fn message(id: &str, offset: i64) -> ProcessedMessage {
+        tenant_message("tenant-a", id, offset)
+    }
+
+    fn tenant_message(tenant_id: &str, id: &str, offset: i64) -> ProcessedMessage {
+        ProcessedMessage {
+            id: id.to_string(),
+            original_message: json!({"offset": offset}),
+            processed_message: json!({"offset": offset}),
+            processing_metadata: ProcessingMetadata {
+                tenant_id: tenant_id.to_string(),
+                processed_at: Utc::now() + chrono::Duration::milliseconds(offset),
+                processor_version: "1.0.0".to_string(),
+                source_topic: "metrics".to_string(),
+                partition: 0,
+                offset,
+                traceparent: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ring_buffer_evicts_oldest_and_upserts_by_id() {
+        let storage = MemoryStorage::with_capacity(3);
+        for i in 0..5 {
+            storage.store_processed_message(&message(&format!("id-{}", i), i)).await.unwrap();
+        }
+
+        assert!(storage.get_processed_message("tenant-a", "id-1").await.unwrap().is_none());
+        assert!(storage.get_processed_message("tenant-a", "id-4").await.unwrap().is_some());
+
+        let mut updated = message("id-3", 3);
+        updated.processed_message = json!({"updated": true});
+        storage.store_processed_message(&updated).await.unwrap();
+
+        let page = storage
+            .get_processed_messages_by_topic("tenant-a", "metrics", None, None)
+            .await
+            .unwrap();
+        assert_eq!(page.items.len(), 3);
+        let fetched = storage.get_processed_message("tenant-a", "id-3").await.unwrap().unwrap();
+        assert_eq!(fetched.processed_message, json!({"updated": true}));
+    }
+
+    #[tokio::test]
+    async fn test_cursor_pagination_matches_sql_ordering() {
+        let storage = MemoryStorage::with_capacity(100);
+        let messages: Vec<_> = (0..5).map(|i| message(&format!("id-{}", i), i)).collect();
+        storage.store_processed_messages(&messages).await.unwrap();
+
+        let first = storage
+            .get_processed_messages_by_topic("tenant-a", "metrics", Some(2), None)
+            .await
+            .unwrap();
+        assert_eq!(first.items[0].id, "id-4");
+        assert_eq!(first.items[1].id, "id-3");
+
+        let second = storage
+            .get_processed_messages_by_topic("tenant-a", "metrics", Some(3), first.next_cursor.as_deref())
+            .await
+            .unwrap();
+        let ids: Vec<_> = second.items.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["id-2", "id-1", "id-0"]);
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redelivered_offsets_are_counted_and_keep_first_id() {
+        let storage = MemoryStorage::with_capacity(100);
+        let first: Vec<_> = (0..3).map(|i| message(&format!("id-{}", i), i)).collect();
+        assert_eq!(storage.store_processed_messages(&first).await.unwrap(), 0);
+
+        let mut redelivered = message("id-new", 1);
+        redelivered.processed_message = json!({"retried": true});
+        assert_eq!(
+            storage.store_processed_messages(&[redelivered, message("id-3", 3)]).await.unwrap(),
+            1
+        );
+
+        let page = storage
+            .get_processed_messages_by_topic("tenant-a", "metrics", None, None)
+            .await
+            .unwrap();
+        assert_eq!(page.items.len(), 4);
+        assert!(storage.get_processed_message("tenant-a", "id-new").await.unwrap().is_none());
+        let updated = storage.get_processed_message("tenant-a", "id-1").await.unwrap().unwrap();
+        assert_eq!(updated.processed_message, json!({"retried": true}));
+    }
+
+    #[tokio::test]
+    async fn test_tenants_are_isolated() {
+        let storage = MemoryStorage::with_capacity(100);
+        storage.store_processed_message(&tenant_message("tenant-a", "id-1", 1)).await.unwrap();
+
+        let mut hijack = tenant_message("tenant-b", "id-1", 1);
+        hijack.processed_message = json!({"hijacked": true});
+        storage.store_processed_message(&hijack).await.unwrap();
+
+        assert!(storage.get_processed_message("tenant-b", "id-1").await.unwrap().is_none());
+        let owned = storage.get_processed_message("tenant-a", "id-1").await.unwrap().unwrap();
+        assert_eq!(owned.processed_message, json!({"offset": 1}));
+
+        let page = storage
+            .get_processed_messages_by_topic("tenant-b", "metrics", None, None)
+            .await
+            .unwrap();
+        assert!(page.items.is_empty());
+    }
+}
@@ -0,0 +1,861 @@
+mod archive;
+mod codec;
+mod compaction;
+mod encryption;
+mod erasure;
+mod export;
+mod faults;
+mod health;
+mod lifecycle;
+mod memory;
+mod postgres;
+mod snapshot;
+mod sqlite;
+mod spill;
+mod sql;
+
+pub use archive::{archive_segments, SegmentReader};
+pub use compaction::{run as run_compaction, CompactionStats};
+pub(crate) use encryption::decode_key;
+pub use encryption::{EncryptedStorage, FieldCipher};
+pub use erasure::{
+    erase, AttributeMatch, ErasureDataset, ErasureMode, ErasureRequest, ErasureSelector, ERASURE_BATCH_SIZE,
+};
+pub use export::{export, read_object, write_object, ExportDataset, ExportFormat, ExportRequest, ExportSummary};
+pub use faults::FaultyStorage;
+pub use health::{monitor as monitor_health, Backoff, STORAGE_COMPONENT};
+pub use lifecycle::{get_metrics as get_tiered_metrics, run as run_lifecycle, ArchivedSegment};
+pub use memory::{MemoryLog, MemoryMetric, MemoryStorage, MetricRollup};
+pub use postgres::StorageManager;
+pub use snapshot::{load_snapshot, save_snapshot, JobSnapshot, PartitionOffset};
+pub use spill::{BufferedWriter, SpillBuffer};
+pub use sql::{to_arrow_ipc, to_json_rows, RecordBatchStream, SqlScope, SqlSession};
+pub use sqlite::SqliteStorage;
+
+use crate::config::{Config, MetricRetentionRule, ResourceLimits, StorageBackendKind};
+use crate::faults::FaultInjector;
+use crate::pipeline::PipelineDefinition;
+use crate::processor::ProcessedMessage;
+use crate::rbac::RoleBinding;
+use crate::schemas::SchemaVersion;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Persistence operations used by the pipeline and the query layer. Every
+/// backend must provide the same query semantics (ordering, cursors, trace
+/// assembly) so callers can swap them through `database.backend`.
+///
+/// All reads and writes are scoped to a tenant: processed messages carry it
+/// in their metadata, every other call takes it explicitly, and no query ever
+/// returns rows belonging to another tenant.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn store_processed_message(&self, message: &ProcessedMessage) -> Result<()>;
+
+    /// Upserts a batch keyed by its Kafka position `(source_topic, partition,
+    /// offset)`, so messages redelivered after a crash update the row stored
+    /// the first time instead of adding another. Returns how many messages
+    /// in the batch were such duplicates.
+    async fn store_processed_messages(&self, messages: &[ProcessedMessage]) -> Result<u64>;
+
+    async fn get_processed_message(&self, tenant_id: &str, id: &str) -> Result<Option<ProcessedMessage>>;
+
+    async fn get_processed_messages_by_topic(
+        &self,
+        tenant_id: &str,
+        topic: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>>;
+
+    async fn get_processed_messages_by_time_range(
+        &self,
+        tenant_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>>;
+
+    async fn store_metric(
+        &self,
+        tenant_id: &str,
+        name: &str,
+        value: f64,
+        metric_type: &str,
+        tags: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Stores many metric rows at once; `resolution_secs` is ignored, as
+    /// for `store_metric`. Backends that can write them in one round trip
+    /// override this.
+    async fn store_metrics(&self, tenant_id: &str, metrics: &[MetricRecord]) -> Result<()> {
+        for metric in metrics {
+            self.store_metric(
+                tenant_id,
+                &metric.name,
+                metric.value,
+                &metric.metric_type,
+                metric.tags.clone(),
+                metric.timestamp,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn store_log(
+        &self,
+        tenant_id: &str,
+        level: &str,
+        message: &str,
+        service_name: Option<&str>,
+        host_name: Option<&str>,
+        trace_id: Option<&str>,
+        span_id: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Stores many log rows at once, like `store_metrics`.
+    async fn store_logs(&self, tenant_id: &str, logs: &[LogRecord]) -> Result<()> {
+        for log in logs {
+            self.store_log(
+                tenant_id,
+                &log.level,
+                &log.message,
+                log.service_name.as_deref(),
+                log.host_name.as_deref(),
+                log.trace_id.as_deref(),
+                log.span_id.as_deref(),
+                log.attributes.clone(),
+                log.timestamp,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn store_trace(
+        &self,
+        tenant_id: &str,
+        trace_id: &str,
+        span_id: &str,
+        parent_span_id: Option<&str>,
+        name: &str,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: Option<DateTime<Utc>>,
+        duration_ms: Option<i64>,
+        status: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        events: Option<serde_json::Value>,
+    ) -> Result<()>;
+
+    /// Metric rows with `start_time <= timestamp < end_time`, oldest first;
+    /// rows with equal timestamps come back in a stable order.
+    async fn get_metrics(
+        &self,
+        tenant_id: &str,
+        name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<MetricRecord>>;
+
+    /// Distinct names of the tenant's metrics still in the database, in
+    /// order, up to `limit`.
+    async fn list_metric_names(&self, tenant_id: &str, limit: i64) -> Result<Vec<String>>;
+
+    /// Log rows with `start_time <= timestamp < end_time`, oldest first;
+    /// rows with equal timestamps come back in a stable order.
+    #[allow(clippy::too_many_arguments)]
+    async fn get_logs(
+        &self,
+        tenant_id: &str,
+        level: Option<&str>,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<LogRecord>>;
+
+    async fn get_trace(&self, tenant_id: &str, trace_id: &str) -> Result<Option<Trace>>;
+
+    #[allow(clippy::too_many_arguments)]
+    async fn find_traces(
+        &self,
+        tenant_id: &str,
+        service: Option<&str>,
+        min_duration: Option<Duration>,
+        status: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<TraceSummary>>;
+
+    /// Replaces rows of metrics matching `rule` that are older than
+    /// `cutoff` with rollups at the rule's resolution, across all tenants.
+    /// Rows already rolled up at that resolution or coarser are left alone,
+    /// so repeated runs are idempotent.
+    async fn compact_metrics(&self, rule: &MetricRetentionRule, cutoff: DateTime<Utc>) -> Result<CompactionStats>;
+
+    /// Deletes, or scrubs the selector's attribute from, up to `limit`
+    /// records of `dataset` matching `selector`, and returns how many were
+    /// changed. Traces count their deleted spans. Attributes of messages
+    /// stored compressed cannot be matched, so backends refuse attribute
+    /// selectors for processed messages while compression is enabled.
+    async fn erase_records(
+        &self,
+        dataset: ErasureDataset,
+        selector: &ErasureSelector,
+        mode: ErasureMode,
+        limit: i64,
+    ) -> Result<u64>;
+
+    /// The oldest timestamp of `dataset` rows before `before`, for every
+    /// tenant that has such rows.
+    async fn oldest_rows(&self, dataset: ExportDataset, before: DateTime<Utc>) -> Result<Vec<(String, DateTime<Utc>)>>;
+
+    /// Catalogs `segment` and deletes the rows of its window from the
+    /// database in one transaction. When the window no longer holds exactly
+    /// `segment.rows` rows, nothing changes and `false` is returned.
+    async fn record_archived_segment(&self, segment: &ArchivedSegment) -> Result<bool>;
+
+    /// Segments of `dataset` overlapping `[start_time, end_time)`, in start
+    /// order.
+    async fn list_archived_segments(
+        &self,
+        tenant_id: &str,
+        dataset: ExportDataset,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<ArchivedSegment>>;
+
+    /// Inserts a new pipeline definition. Returns `false` without writing if
+    /// the tenant already has a pipeline with that id.
+    async fn create_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool>;
+
+    /// Replaces a stored definition, provided the stored version is the one
+    /// `pipeline.version` succeeds (`pipeline.version - 1`). Returns `false`
+    /// if the pipeline does not exist or was changed concurrently.
+    async fn update_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool>;
+
+    /// Returns whether a pipeline was deleted.
+    async fn delete_pipeline(&self, tenant_id: &str, id: &str) -> Result<bool>;
+
+    async fn get_pipeline(&self, tenant_id: &str, id: &str) -> Result<Option<PipelineDefinition>>;
+
+    /// Pipelines ordered by `(created_at, id)` descending, newest first.
+    async fn list_pipelines(
+        &self,
+        tenant_id: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<PipelineDefinition>>;
+
+    /// Pipelines of every tenant that declare a schedule, for the scheduler.
+    async fn list_scheduled_pipelines(&self) -> Result<Vec<PipelineDefinition>>;
+
+    /// Takes the lease on `lease.pipeline_id` unless another lease on it is
+    /// still live at `lease.acquired_at`. Returns whether it was taken.
+    async fn acquire_job_lease(&self, lease: &JobLease) -> Result<bool>;
+
+    /// Extends a lease held by `owner` to `expires_at`, recording `job_id`
+    /// when given. Returns `false` if `owner` no longer holds the lease.
+    async fn renew_job_lease(
+        &self,
+        pipeline_id: &str,
+        owner: &str,
+        job_id: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool>;
+
+    /// Returns whether `owner` held the lease that was released.
+    async fn release_job_lease(&self, pipeline_id: &str, owner: &str) -> Result<bool>;
+
+    /// The lease of the pipeline running job `job_id`, live or not.
+    async fn get_job_lease(&self, job_id: &str) -> Result<Option<JobLease>>;
+
+    /// Leases that expired before `now`, oldest first, for failover.
+    async fn list_expired_job_leases(&self, now: DateTime<Utc>) -> Result<Vec<JobLease>>;
+
+    /// Replaces the stored status of each service, keyed by tenant and name.
+    async fn store_service_statuses(&self, statuses: &[ServiceStatusRecord]) -> Result<()>;
+
+    /// The tenant's latest service statuses, ordered by name.
+    async fn list_service_statuses(&self, tenant_id: &str) -> Result<Vec<ServiceStatusRecord>>;
+
+    /// Inserts a delivery or replaces the stored one with the same id.
+    async fn record_alert_delivery(&self, delivery: &AlertDelivery) -> Result<()>;
+
+    /// The tenant's deliveries, of one alert when `alert_id` is given,
+    /// newest first.
+    async fn list_alert_deliveries(
+        &self,
+        tenant_id: &str,
+        alert_id: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Vec<AlertDelivery>>;
+
+    /// Returns `false` if the tenant already has a silence with this id.
+    async fn create_silence(&self, silence: &Silence) -> Result<bool>;
+
+    /// Returns whether the silence existed.
+    async fn delete_silence(&self, tenant_id: &str, id: &str) -> Result<bool>;
+
+    /// The tenant's silences, ordered by start time. With `active_at`, only
+    /// those in effect at that time.
+    async fn list_silences(&self, tenant_id: &str, active_at: Option<DateTime<Utc>>) -> Result<Vec<Silence>>;
+
+    /// Silences of every tenant that have not ended by `now`, including
+    /// upcoming ones, for the notifier.
+    async fn list_unexpired_silences(&self, now: DateTime<Utc>) -> Result<Vec<Silence>>;
+
+    /// Appends to the audit log. Backends offer no way to change or remove
+    /// an entry once written.
+    async fn append_audit_entry(&self, entry: &AuditEntry) -> Result<()>;
+
+    /// Audit entries matching `filter`, ordered by `(occurred_at, id)`
+    /// descending, newest first.
+    async fn query_audit_log(
+        &self,
+        filter: &AuditFilter,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<AuditEntry>>;
+
+    /// Stores a new version of a topic's schema unless the topic already
+    /// has a version of that number; returns whether it was stored.
+    async fn create_schema_version(&self, version: &SchemaVersion) -> Result<bool>;
+
+    /// Versions of the tenant's topic schema, oldest first.
+    async fn list_schema_versions(&self, tenant_id: &str, topic: &str) -> Result<Vec<SchemaVersion>>;
+
+    /// The latest schema version of every tenant's topics, for the schema
+    /// registry to start from.
+    async fn list_latest_schema_versions(&self) -> Result<Vec<SchemaVersion>>;
+
+    /// Stores a role binding unless the subject already has that role;
+    /// returns whether it was stored.
+    async fn create_role_binding(&self, binding: &RoleBinding) -> Result<bool>;
+
+    /// Removes the subject's binding to the role; returns whether there
+    /// was one.
+    async fn delete_role_binding(&self, subject: &str, role: &str) -> Result<bool>;
+
+    /// Stored role bindings, of `subject` only when given, ordered by
+    /// `(subject, role)`.
+    async fn list_role_bindings(&self, subject: Option<&str>) -> Result<Vec<RoleBinding>>;
+
+    async fn health_check(&self) -> Result<bool>;
+
+    fn pool_status(&self) -> PoolStatus;
+}
+
+/// Connection pool occupancy reported to the metrics registry.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolStatus {
+    pub size: u32,
+    pub idle: u32,
+}
+
+/// Opens the backend selected by `database.backend`, retrying with backoff
+/// per `database.retry` so the processor can start before the database does.
+pub async fn connect(config: &Config) -> Result<Arc<dyn StorageBackend>> {
+    let retry = &config.database.retry;
+    let mut backoff = Backoff::new(retry);
+    let mut attempt = 1;
+
+    loop {
+        match open(config).await {
+            Ok(storage) => return Ok(storage),
+            Err(e) if retry.max_attempts == 0 || attempt < retry.max_attempts => {
+                let delay = backoff.next_delay();
+                warn!(
+                    "Storage connection attempt {} failed: {}; retrying in {:?}",
+                    attempt, e, delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "Failed to connect to storage after {} attempts: {}",
+                    attempt,
+                    e
+                ))
+            }
+        }
+    }
+}
+
+async fn open(config: &Config) -> Result<Arc<dyn StorageBackend>> {
+    info!("Connecting to {:?} storage backend", config.database.backend);
+
+    let mut storage: Arc<dyn StorageBackend> = match config.database.backend {
+        StorageBackendKind::Postgres => Arc::new(StorageManager::new(config).await?),
+        StorageBackendKind::Sqlite => Arc::new(SqliteStorage::new(config).await?),
+        StorageBackendKind::Memory => Arc::new(MemoryStorage::new(config)),
+    };
+
+    if config.database.encryption.enabled {
+        info!("Encrypting {} configured field(s) at rest", config.database.encryption.fields.len());
+        let cipher = FieldCipher::new(&config.database.encryption)?;
+        storage = Arc::new(EncryptedStorage::new(storage, cipher));
+    }
+
+    if config.faults.enabled {
+        warn!("Fault injection is enabled for storage writes");
+        return Ok(Arc::new(FaultyStorage::new(storage, FaultInjector::storage(config))));
+    }
+    Ok(storage)
+}
+
+/// One page of a list query. `next_cursor` is an opaque continuation token to
+/// pass back as `cursor`; it is `None` on the last page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// Keyset position `(processed_at, id)` of the last row returned. Also used
+/// by callers paging their own in-memory listings the same way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageCursor {
+    pub processed_at: DateTime<Utc>,
+    pub id: String,
+}
+
+impl PageCursor {
+    pub fn encode(&self) -> String {
+        use base64::Engine;
+        let raw = format!("{}|{}", self.processed_at.timestamp_micros(), self.id);
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(token: &str) -> Result<Self> {
+        use base64::Engine;
+        let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|e| anyhow::anyhow!("Invalid page cursor: {}", e))?;
+        let raw = String::from_utf8(raw).map_err(|e| anyhow::anyhow!("Invalid page cursor: {}", e))?;
+
+        let (micros, id) = raw
+            .split_once('|')
+            .ok_or_else(|| anyhow::anyhow!("Invalid page cursor: missing separator"))?;
+        let micros: i64 = micros
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid page cursor: {}", e))?;
+        let processed_at = DateTime::<Utc>::from_timestamp_micros(micros)
+            .ok_or_else(|| anyhow::anyhow!("Invalid page cursor: timestamp out of range"))?;
+
+        Ok(Self {
+            processed_at,
+            id: id.to_string(),
+        })
+    }
+
+    pub(crate) fn split(cursor: Option<Self>) -> (Option<DateTime<Utc>>, Option<String>) {
+        match cursor {
+            Some(c) => (Some(c.processed_at), Some(c.id)),
+            None => (None, None),
+        }
+    }
+}
+
+/// Builds a page from up to `limit + 1` pipelines fetched in list order; the
+/// extra row only signals that another page exists. Pipelines page by
+/// `(created_at, id)`, carried in the cursor's timestamp slot.
+pub(crate) fn page_of_pipelines(mut pipelines: Vec<PipelineDefinition>, limit: i64) -> Page<PipelineDefinition> {
+    let has_more = pipelines.len() as i64 > limit;
+    pipelines.truncate(limit.max(0) as usize);
+
+    let next_cursor = match pipelines.last() {
+        Some(last) if has_more => Some(
+            PageCursor {
+                processed_at: last.created_at,
+                id: last.id.clone(),
+            }
+            .encode(),
+        ),
+        _ => None,
+    };
+
+    Page {
+        items: pipelines,
+        next_cursor,
+    }
+}
+
+/// Builds a page from up to `limit + 1` audit entries fetched newest first;
+/// entries page by `(occurred_at, id)` like pipelines.
+pub(crate) fn page_of_audit_entries(mut entries: Vec<AuditEntry>, limit: i64) -> Page<AuditEntry> {
+    let has_more = entries.len() as i64 > limit;
+    entries.truncate(limit.max(0) as usize);
+
+    let next_cursor = match entries.last() {
+        Some(last) if has_more => Some(
+            PageCursor {
+                processed_at: last.occurred_at,
+                id: last.id.clone(),
+            }
+            .encode(),
+        ),
+        _ => None,
+    };
+
+    Page {
+        items: entries,
+        next_cursor,
+    }
+}
+
+/// A stored metric row. `resolution_secs` is set on compaction rollups.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricRecord {
+    pub name: String,
+    pub value: f64,
+    pub metric_type: String,
+    pub tags: Option<serde_json::Value>,
+    pub timestamp: DateTime<Utc>,
+    pub resolution_secs: Option<i64>,
+}
+
+/// A stored log row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogRecord {
+    pub level: String,
+    pub message: String,
+    pub service_name: Option<String>,
+    pub host_name: Option<String>,
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub attributes: Option<serde_json::Value>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A single span of a stored trace, with its child spans attached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceSpan {
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub service_name: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub duration_ms: Option<i64>,
+    pub status: Option<String>,
+    pub attributes: Option<serde_json::Value>,
+    pub events: Option<serde_json::Value>,
+    pub children: Vec<TraceSpan>,
+}
+
+/// A fully assembled trace. `roots` holds spans without a (known) parent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trace {
+    pub trace_id: String,
+    pub span_count: usize,
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub duration_ms: Option<i64>,
+    pub roots: Vec<TraceSpan>,
+}
+
+/// Claim of one stream-processor instance on running a pipeline. Leases
+/// are not tenant-scoped: they coordinate instances, not tenants' data.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobLease {
+    pub pipeline_id: String,
+    /// Set once the owner has started the job.
+    pub job_id: Option<String>,
+    /// Instance id of the holder.
+    pub owner: String,
+    /// gRPC endpoint of the holder, for forwarding requests to it.
+    pub endpoint: String,
+    /// Start parameters, so another instance can take the job over.
+    pub config: String,
+    pub limits: ResourceLimits,
+    pub acquired_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Latest computed status of one service of a tenant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceStatusRecord {
+    pub tenant_id: String,
+    pub name: String,
+    /// `healthy`, `degraded` or `down`.
+    pub status: String,
+    /// Percentage of checks in the window that found the service up.
+    pub uptime: f64,
+    /// Average response time over the window, in milliseconds.
+    pub response_time: f64,
+    pub requests_per_second: f64,
+    pub last_seen: DateTime<Utc>,
+    pub last_check: DateTime<Utc>,
+}
+
+/// Delivery of one alert to one notification channel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertDelivery {
+    pub id: String,
+    pub tenant_id: String,
+    pub alert_id: String,
+    pub channel: String,
+    /// `pending`, `delivered`, `failed` or `rate_limited`.
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Suppresses notifications of the tenant's alerts that match every matcher
+/// from `starts_at` until `ends_at`, e.g. during a deploy or maintenance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Silence {
+    pub id: String,
+    pub tenant_id: String,
+    pub matchers: Vec<SilenceMatcher>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub comment: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Silence {
+    pub fn is_active(&self, at: DateTime<Utc>) -> bool {
+        self.starts_at <= at && at < self.ends_at
+    }
+}
+
+/// Compares one alert field with a value. Fields are named as in channel
+/// templates: `id`, `severity`, `message`, `service` or `metadata.<key>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SilenceMatcher {
+    pub field: String,
+    pub value: String,
+    /// Matches alerts whose field differs from `value` instead.
+    #[serde(default)]
+    pub negate: bool,
+}
+
+/// One control-plane action, recorded whether or not it succeeded. Like job
+/// leases, the audit log is not tenant-scoped: it records what operators did
+/// to the deployment, and `tenant_id` is only set on actions addressing one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: String,
+    pub occurred_at: DateTime<Utc>,
+    /// Who acted, e.g. the user a gateway forwarded the request for.
+    pub actor: String,
+    /// Where the action came from, e.g. the peer address of the request.
+    pub source: String,
+    /// What was done, e.g. `StartProcessing` or `ConfigReload`.
+    pub action: String,
+    pub tenant_id: Option<String>,
+    /// What it was done to, e.g. a job or pipeline id.
+    pub target: String,
+    /// Parameters of the action.
+    pub details: serde_json::Value,
+    /// `succeeded` or `failed`.
+    pub outcome: String,
+    pub error: Option<String>,
+}
+
+/// Selects audit entries; unset fields match every entry.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// Inclusive.
+    pub start_time: Option<DateTime<Utc>>,
+    /// Exclusive.
+    pub end_time: Option<DateTime<Utc>>,
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.start_time.map_or(true, |start| entry.occurred_at >= start)
+            && self.end_time.map_or(true, |end| entry.occurred_at < end)
+            && self.actor.as_ref().map_or(true, |actor| entry.actor == *actor)
+            && self.action.as_ref().map_or(true, |action| entry.action == *action)
+            && self.target.as_ref().map_or(true, |target| entry.target == *target)
+    }
+}
+
+/// Summary row returned by trace search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceSummary {
+    pub trace_id: String,
+    pub root_service_name: Option<String>,
+    pub root_span_name: String,
+    pub start_time: DateTime<Utc>,
+    pub duration_ms: Option<i64>,
+    pub span_count: i64,
+    pub has_error: bool,
+}
+
+/// Builds the span tree for a trace from its flat span list. Spans whose
+/// parent is not part of the trace (e.g. not yet ingested) become roots.
+pub(crate) fn assemble_trace(trace_id: &str, spans: Vec<TraceSpan>) -> Trace {
+    let span_count = spans.len();
+    let start_time = spans.iter().map(|s| s.start_time).min().unwrap_or_else(Utc::now);
+    let end_time = spans.iter().filter_map(|s| s.end_time).max();
+    let duration_ms = end_time.map(|end| (end - start_time).num_milliseconds());
+
+    let known: std::collections::HashSet<String> =
+        spans.iter().map(|s| s.span_id.clone()).collect();
+
+    let mut children: HashMap<String, Vec<TraceSpan>> = HashMap::new();
+    let mut roots = Vec::new();
+    for span in spans {
+        match span.parent_span_id.clone() {
+            Some(parent) if parent != span.span_id && known.contains(&parent) => {
+                children.entry(parent).or_default().push(span);
+            }
+            _ => roots.push(span),
+        }
+    }
+
+    fn attach(span: &mut TraceSpan, children: &mut HashMap<String, Vec<TraceSpan>>) {
+        if let Some(mut kids) = children.remove(&span.span_id) {
+            for kid in kids.iter_mut() {
+                attach(kid, children);
+            }
+            span.children = kids;
+        }
+    }
+
+    for root in roots.iter_mut() {
+        attach(root, &mut children);
+    }
+    // Spans whose parent links form a cycle are not reached from any root;
+    // each cycle becomes a root where it is first met.
+    while let Some(parent) = children.keys().min().cloned() {
+        for mut span in children.remove(&parent).unwrap_or_default() {
+            attach(&mut span, &mut children);
+            roots.push(span);
+        }
+    }
+
+    Trace {
+        trace_id: trace_id.to_string(),
+        span_count,
+        start_time,
+        end_time,
+        duration_ms,
+        roots,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn span(id: &str, parent: Option<&str>, offset_ms: i64) -> TraceSpan {
+        let start = Utc::now() + chrono::Duration::milliseconds(offset_ms);
+        TraceSpan {
+            span_id: id.to_string(),
+            parent_span_id: parent.map(|p| p.to_string()),
+            name: format!("op-{}", id),
+            service_name: Some("svc".to_string()),
+            start_time: start,
+            end_time: Some(start + chrono::Duration::milliseconds(10)),
+            duration_ms: Some(10),
+            status: None,
+            attributes: None,
+            events: None,
+            children: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_page_cursor_round_trip() {
+        let cursor = PageCursor {
+            processed_at: DateTime::<Utc>::from_timestamp_micros(1_700_000_000_123_456).unwrap(),
+            id: "5f0c6a52-3d0c-4a47-9f0e-2b1d6b1f7c11".to_string(),
+        };
+
+        let token = cursor.encode();
+        assert_eq!(PageCursor::decode(&token).unwrap(), cursor);
+        assert!(PageCursor::decode("not a cursor").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_page_cursor_round_trips(micros in -8_000_000_000_000_000i64..8_000_000_000_000_000, id in ".*") {
+            let cursor = PageCursor {
+                processed_at: DateTime::<Utc>::from_timestamp_micros(micros).unwrap(),
+                id,
+            };
+            prop_assert_eq!(PageCursor::decode(&cursor.encode()).unwrap(), cursor);
+        }
+
+        /// Cursors come back from clients, so any string must be rejected
+        /// cleanly rather than panic.
+        #[test]
+        fn prop_page_cursor_decode_never_panics(token in ".*", raw in ".*") {
+            use base64::Engine;
+            let _ = PageCursor::decode(&token);
+            let _ = PageCursor::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw));
+        }
+
+        /// Every span ends up in the tree exactly once, whatever its parent
+        /// links, cycles included.
+        #[test]
+        fn prop_trace_keeps_every_span(parents in prop::collection::vec(prop::option::of(0usize..12), 1..12)) {
+            let ids: Vec<String> = (0..parents.len()).map(|i| format!("span-{}", i)).collect();
+            let spans = parents
+                .iter()
+                .enumerate()
+                .map(|(i, parent)| span(&ids[i], parent.map(|p| format!("span-{}", p)).as_deref(), i as i64))
+                .collect();
+
+            fn count(spans: &[TraceSpan]) -> usize {
+                spans.iter().map(|s| 1 + count(&s.children)).sum()
+            }
+            let trace = assemble_trace("trace-1", spans);
+            prop_assert_eq!(count(&trace.roots), trace.span_count);
+        }
+    }
+
+    #[test]
+    fn test_assemble_trace_builds_span_tree() {
+        let spans = vec![
+            span("root", None, 0),
+            span("child-a", Some("root"), 1),
+            span("child-b", Some("root"), 2),
+            span("grandchild", Some("child-a"), 3),
+            span("orphan", Some("missing"), 4),
+        ];
+
+        let trace = assemble_trace("trace-1", spans);
+
+        assert_eq!(trace.span_count, 5);
+        assert_eq!(trace.roots.len(), 2);
+        let root = trace.roots.iter().find(|s| s.span_id == "root").unwrap();
+        assert_eq!(root.children.len(), 2);
+        let child_a = root.children.iter().find(|s| s.span_id == "child-a").unwrap();
+        assert_eq!(child_a.children[0].span_id, "grandchild");
+        assert!(trace.roots.iter().any(|s| s.span_id == "orphan"));
+
+        let cycle = assemble_trace("trace-2", vec![span("a", Some("b"), 0), span("b", Some("a"), 1)]);
+        assert_eq!(cycle.roots.len(), 1);
+        assert_eq!(cycle.roots[0].children.len(), 1);
+    }
+}
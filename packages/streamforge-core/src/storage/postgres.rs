@@ -0,0 +1,2379 @@
+use super::codec;
+use super::compaction::{self, CompactionStats};
+use super::lifecycle;
+use super::{
+    assemble_trace, page_of_audit_entries, page_of_pipelines, AlertDelivery, ArchivedSegment, AuditEntry,
+    AuditFilter, ErasureDataset, ErasureMode, ErasureSelector, ExportDataset, JobLease, LogRecord, MetricRecord, Page,
+    PageCursor, PoolStatus, ServiceStatusRecord, Silence, StorageBackend, Trace, TraceSpan, TraceSummary,
+};
+use crate::config::{CompressionConfig, Config, MetricRetentionRule, PayloadCodec};
+use crate::pipeline::PipelineDefinition;
+use crate::processor::{ProcessedMessage, ProcessingMetadata};
+use crate::rbac::RoleBinding;
+use crate::schemas::SchemaVersion;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgRow};
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Tables carrying a `tenant_id` column and, optionally, an RLS policy.
+const TENANT_TABLES: [&str; 10] = [
+    "processed_messages",
+    "metrics",
+    "logs",
+    "traces",
+    "pipelines",
+    "service_statuses",
+    "alert_deliveries",
+    "silences",
+    "schema_versions",
+    "archived_segments",
+];
+
+/// Read replica serving query workloads. While it is marked unhealthy reads
+/// go to the primary; the health check probes it again and restores routing.
+#[derive(Clone)]
+struct Replica {
+    pool: PgPool,
+    healthy: Arc<AtomicBool>,
+}
+
+pub struct StorageManager {
+    pool: PgPool,
+    replica: Option<Replica>,
+    row_level_security: bool,
+    compression: CompressionConfig,
+}
+
+impl StorageManager {
+    pub async fn new(config: &Config) -> Result<Self> {
+        let pool = Self::pool_options(config)
+            .connect_with(Self::connect_options(&config.database.url, config)?)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to database: {}", e))?;
+
+        info!("Database connection pool created successfully");
+
+        // The replica connects lazily so an unavailable replica never blocks
+        // startup; reads simply stay on the primary until it answers.
+        let replica = match &config.database.replica_url {
+            Some(url) => {
+                let pool = Self::pool_options(config)
+                    .connect_lazy_with(Self::connect_options(url, config)?);
+                info!("Read replica pool configured");
+                Some(Replica {
+                    pool,
+                    healthy: Arc::new(AtomicBool::new(true)),
+                })
+            }
+            None => None,
+        };
+
+        // Initialize database schema
+        Self::init_schema(&pool, config.database.row_level_security).await?;
+
+        Ok(Self {
+            pool,
+            replica,
+            row_level_security: config.database.row_level_security,
+            compression: config.database.compression.clone(),
+        })
+    }
+
+    fn connect_options(url: &str, config: &Config) -> Result<PgConnectOptions> {
+        let options = PgConnectOptions::from_str(url)
+            .map_err(|e| anyhow::anyhow!("Invalid database URL: {}", e))?
+            .statement_cache_capacity(config.database.statement_cache_capacity);
+        Ok(match &config.database.password {
+            Some(password) => options.password(password),
+            None => options,
+        })
+    }
+
+    fn pool_options(config: &Config) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(config.database.max_connections)
+            .min_connections(config.database.min_connections)
+            .connect_timeout(std::time::Duration::from_secs(config.database.connect_timeout))
+            .acquire_timeout(std::time::Duration::from_secs(config.database.acquire_timeout))
+            .idle_timeout(std::time::Duration::from_secs(config.database.idle_timeout))
+            .max_lifetime(std::time::Duration::from_secs(config.database.max_lifetime))
+            // Discard connections that died while the database was restarting
+            .test_before_acquire(true)
+    }
+
+    async fn init_schema(pool: &PgPool, row_level_security: bool) -> Result<()> {
+        let schema_sql = r#"
+            -- Create processed_messages table
+            CREATE TABLE IF NOT EXISTS processed_messages (
+                id UUID PRIMARY KEY,
+                tenant_id VARCHAR(255) NOT NULL DEFAULT 'default',
+                original_message JSONB NOT NULL,
+                processed_message JSONB NOT NULL,
+                processed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                processor_version VARCHAR(50) NOT NULL,
+                source_topic VARCHAR(255) NOT NULL,
+                partition INTEGER NOT NULL,
+                offset BIGINT NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+                updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+            );
+
+            -- Create index on processed_at for time-based queries
+            CREATE INDEX IF NOT EXISTS idx_processed_messages_processed_at 
+            ON processed_messages (processed_at);
+
+            -- Create index on source_topic for topic-based queries
+            CREATE INDEX IF NOT EXISTS idx_processed_messages_source_topic 
+            ON processed_messages (source_topic);
+
+            -- Create index on processor_version for version-based queries
+            CREATE INDEX IF NOT EXISTS idx_processed_messages_processor_version 
+            ON processed_messages (processor_version);
+
+            -- Create composite index for efficient filtering
+            CREATE INDEX IF NOT EXISTS idx_processed_messages_topic_partition_offset 
+            ON processed_messages (source_topic, partition, offset);
+
+            -- Create metrics table for storing aggregated metrics
+            CREATE TABLE IF NOT EXISTS metrics (
+                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                tenant_id VARCHAR(255) NOT NULL DEFAULT 'default',
+                metric_name VARCHAR(255) NOT NULL,
+                metric_value DOUBLE PRECISION NOT NULL,
+                metric_type VARCHAR(50) NOT NULL,
+                tags JSONB,
+                timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+            );
+
+            -- Create index on metric_name and timestamp for efficient queries
+            CREATE INDEX IF NOT EXISTS idx_metrics_name_timestamp 
+            ON metrics (metric_name, timestamp);
+
+            -- Create index on tags for tag-based queries
+            CREATE INDEX IF NOT EXISTS idx_metrics_tags 
+            ON metrics USING GIN (tags);
+
+            -- Create logs table for storing log entries
+            CREATE TABLE IF NOT EXISTS logs (
+                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                tenant_id VARCHAR(255) NOT NULL DEFAULT 'default',
+                log_level VARCHAR(20) NOT NULL,
+                message TEXT NOT NULL,
+                service_name VARCHAR(255),
+                host_name VARCHAR(255),
+                trace_id VARCHAR(255),
+                span_id VARCHAR(255),
+                attributes JSONB,
+                timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+            );
+
+            -- Create index on timestamp for time-based queries
+            CREATE INDEX IF NOT EXISTS idx_logs_timestamp 
+            ON logs (timestamp);
+
+            -- Create index on log_level for level-based queries
+            CREATE INDEX IF NOT EXISTS idx_logs_level 
+            ON logs (log_level);
+
+            -- Create index on service_name for service-based queries
+            CREATE INDEX IF NOT EXISTS idx_logs_service_name 
+            ON logs (service_name);
+
+            -- Create index on trace_id for trace-based queries
+            CREATE INDEX IF NOT EXISTS idx_logs_trace_id 
+            ON logs (trace_id);
+
+            -- Create traces table for storing trace spans
+            CREATE TABLE IF NOT EXISTS traces (
+                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                tenant_id VARCHAR(255) NOT NULL DEFAULT 'default',
+                trace_id VARCHAR(255) NOT NULL,
+                span_id VARCHAR(255) NOT NULL,
+                parent_span_id VARCHAR(255),
+                name VARCHAR(255) NOT NULL,
+                service_name VARCHAR(255),
+                start_time TIMESTAMP WITH TIME ZONE NOT NULL,
+                end_time TIMESTAMP WITH TIME ZONE,
+                duration_ms BIGINT,
+                status VARCHAR(50),
+                attributes JSONB,
+                events JSONB,
+                created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+            );
+
+            -- Create index on trace_id for trace-based queries
+            CREATE INDEX IF NOT EXISTS idx_traces_trace_id 
+            ON traces (trace_id);
+
+            -- Create index on span_id for span-based queries
+            CREATE INDEX IF NOT EXISTS idx_traces_span_id 
+            ON traces (span_id);
+
+            -- Create index on start_time for time-based queries
+            CREATE INDEX IF NOT EXISTS idx_traces_start_time 
+            ON traces (start_time);
+
+            -- Create index on service_name for service-based queries
+            CREATE INDEX IF NOT EXISTS idx_traces_service_name 
+            ON traces (service_name);
+
+            -- Create index on duration_ms for slow-trace search
+            CREATE INDEX IF NOT EXISTS idx_traces_duration_ms 
+            ON traces (duration_ms);
+
+            -- Create composite index for status + time range trace search
+            CREATE INDEX IF NOT EXISTS idx_traces_status_start_time 
+            ON traces (status, start_time);
+
+            -- Create function to update updated_at timestamp
+            CREATE OR REPLACE FUNCTION update_updated_at_column()
+            RETURNS TRIGGER AS $$
+            BEGIN
+                NEW.updated_at = NOW();
+                RETURN NEW;
+            END;
+            $$ language 'plpgsql';
+
+            -- Create trigger for processed_messages table
+            DROP TRIGGER IF EXISTS update_processed_messages_updated_at ON processed_messages;
+            CREATE TRIGGER update_processed_messages_updated_at
+                BEFORE UPDATE ON processed_messages
+                FOR EACH ROW
+                EXECUTE FUNCTION update_updated_at_column();
+        "#;
+
+        sqlx::raw_sql(schema_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize database schema: {}", e))?;
+
+        // Tenant columns are added separately so databases created before
+        // multi-tenancy are migrated in place; existing rows land in 'default'.
+        let tenant_sql = r#"
+            ALTER TABLE processed_messages ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+            ALTER TABLE metrics ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+            ALTER TABLE logs ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+            ALTER TABLE traces ADD COLUMN IF NOT EXISTS tenant_id VARCHAR(255) NOT NULL DEFAULT 'default';
+
+            -- Create keyset pagination indexes matching (processed_at, id) cursors
+            CREATE INDEX IF NOT EXISTS idx_processed_messages_tenant_processed_at_id
+            ON processed_messages (tenant_id, processed_at DESC, id DESC);
+
+            CREATE INDEX IF NOT EXISTS idx_processed_messages_tenant_topic_processed_at_id
+            ON processed_messages (tenant_id, source_topic, processed_at DESC, id DESC);
+
+            -- Create tenant-scoped metric and log time-range indexes
+            CREATE INDEX IF NOT EXISTS idx_metrics_tenant_name_timestamp
+            ON metrics (tenant_id, metric_name, timestamp);
+
+            CREATE INDEX IF NOT EXISTS idx_logs_tenant_timestamp
+            ON logs (tenant_id, timestamp);
+
+            -- Create tenant-scoped trace lookup and search indexes
+            CREATE INDEX IF NOT EXISTS idx_traces_tenant_trace_id
+            ON traces (tenant_id, trace_id);
+
+            CREATE INDEX IF NOT EXISTS idx_traces_tenant_service_name_start_time
+            ON traces (tenant_id, service_name, start_time);
+        "#;
+
+        sqlx::raw_sql(tenant_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize tenant columns: {}", e))?;
+
+        // Compressed payloads live in BYTEA columns next to the JSONB ones;
+        // each row populates exactly one of the two.
+        let compression_sql = r#"
+            ALTER TABLE processed_messages ADD COLUMN IF NOT EXISTS original_message_blob BYTEA;
+            ALTER TABLE processed_messages ADD COLUMN IF NOT EXISTS processed_message_blob BYTEA;
+            ALTER TABLE processed_messages ALTER COLUMN original_message DROP NOT NULL;
+            ALTER TABLE processed_messages ALTER COLUMN processed_message DROP NOT NULL;
+        "#;
+
+        sqlx::raw_sql(compression_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize payload columns: {}", e))?;
+
+        // Rollup rows written by metric compaction; all NULL on raw rows.
+        let rollup_sql = r#"
+            ALTER TABLE metrics ADD COLUMN IF NOT EXISTS resolution_seconds BIGINT;
+            ALTER TABLE metrics ADD COLUMN IF NOT EXISTS sample_count BIGINT;
+            ALTER TABLE metrics ADD COLUMN IF NOT EXISTS min_value DOUBLE PRECISION;
+            ALTER TABLE metrics ADD COLUMN IF NOT EXISTS max_value DOUBLE PRECISION;
+        "#;
+
+        // Deduplicate existing rows once before the unique index is created;
+        // the most recently processed copy of each Kafka position wins.
+        let position_sql = r#"
+            DO $$
+            BEGIN
+                IF NOT EXISTS (
+                    SELECT 1 FROM pg_indexes WHERE indexname = 'idx_processed_messages_source_position'
+                ) THEN
+                    PERFORM set_config('app.maintenance', 'on', true);
+
+                    DELETE FROM processed_messages a
+                    USING processed_messages b
+                    WHERE a.source_topic = b.source_topic
+                      AND a.partition = b.partition
+                      AND a.offset = b.offset
+                      AND (a.processed_at, a.id) < (b.processed_at, b.id);
+
+                    CREATE UNIQUE INDEX idx_processed_messages_source_position
+                    ON processed_messages (source_topic, partition, offset);
+                END IF;
+            END
+            $$;
+        "#;
+
+        sqlx::raw_sql(position_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize message position index: {}", e))?;
+
+        sqlx::raw_sql(rollup_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize metric rollup columns: {}", e))?;
+
+        // W3C traceparent of the span that processed each message.
+        let trace_sql = r#"
+            ALTER TABLE processed_messages ADD COLUMN IF NOT EXISTS traceparent VARCHAR(55);
+        "#;
+
+        sqlx::raw_sql(trace_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize trace context column: {}", e))?;
+
+        // Pipeline definitions managed through the pipeline RPCs.
+        let pipeline_sql = r#"
+            CREATE TABLE IF NOT EXISTS pipelines (
+                tenant_id VARCHAR(255) NOT NULL,
+                id VARCHAR(255) NOT NULL,
+                name VARCHAR(255) NOT NULL,
+                description TEXT NOT NULL DEFAULT '',
+                definition JSONB NOT NULL,
+                version BIGINT NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                PRIMARY KEY (tenant_id, id)
+            );
+
+            -- Create keyset pagination index matching (created_at, id) cursors
+            CREATE INDEX IF NOT EXISTS idx_pipelines_tenant_created_at_id
+            ON pipelines (tenant_id, created_at DESC, id DESC);
+        "#;
+
+        sqlx::raw_sql(pipeline_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize pipelines table: {}", e))?;
+
+        // Job ownership leases shared by every stream-processor instance.
+        let lease_sql = r#"
+            CREATE TABLE IF NOT EXISTS job_leases (
+                pipeline_id VARCHAR(255) PRIMARY KEY,
+                job_id VARCHAR(255),
+                owner VARCHAR(255) NOT NULL,
+                endpoint TEXT NOT NULL,
+                config TEXT NOT NULL,
+                limits JSONB NOT NULL,
+                acquired_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_job_leases_job_id ON job_leases (job_id);
+            CREATE INDEX IF NOT EXISTS idx_job_leases_expires_at ON job_leases (expires_at);
+        "#;
+
+        sqlx::raw_sql(lease_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize job leases table: {}", e))?;
+
+        // Latest status of each service, maintained by the status tracker.
+        let status_sql = r#"
+            CREATE TABLE IF NOT EXISTS service_statuses (
+                tenant_id VARCHAR(255) NOT NULL,
+                name VARCHAR(255) NOT NULL,
+                status VARCHAR(16) NOT NULL,
+                uptime DOUBLE PRECISION NOT NULL,
+                response_time DOUBLE PRECISION NOT NULL,
+                requests_per_second DOUBLE PRECISION NOT NULL,
+                last_seen TIMESTAMP WITH TIME ZONE NOT NULL,
+                last_check TIMESTAMP WITH TIME ZONE NOT NULL,
+                PRIMARY KEY (tenant_id, name)
+            );
+        "#;
+
+        sqlx::raw_sql(status_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize service statuses table: {}", e))?;
+
+        // Delivery status of alert notifications.
+        let delivery_sql = r#"
+            CREATE TABLE IF NOT EXISTS alert_deliveries (
+                tenant_id VARCHAR(255) NOT NULL,
+                id VARCHAR(255) NOT NULL,
+                alert_id VARCHAR(255) NOT NULL,
+                channel VARCHAR(255) NOT NULL,
+                status VARCHAR(16) NOT NULL,
+                attempts INTEGER NOT NULL,
+                last_error TEXT,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                updated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                PRIMARY KEY (tenant_id, id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_alert_deliveries_tenant_alert_id
+            ON alert_deliveries (tenant_id, alert_id);
+            CREATE INDEX IF NOT EXISTS idx_alert_deliveries_tenant_created_at
+            ON alert_deliveries (tenant_id, created_at DESC);
+        "#;
+
+        sqlx::raw_sql(delivery_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize alert deliveries table: {}", e))?;
+
+        // Silences suppressing alert notifications.
+        let silence_sql = r#"
+            CREATE TABLE IF NOT EXISTS silences (
+                tenant_id VARCHAR(255) NOT NULL,
+                id VARCHAR(255) NOT NULL,
+                matchers JSONB NOT NULL,
+                starts_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                ends_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                comment TEXT NOT NULL,
+                created_by VARCHAR(255) NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                PRIMARY KEY (tenant_id, id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_silences_ends_at ON silences (ends_at);
+        "#;
+
+        sqlx::raw_sql(silence_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize silences table: {}", e))?;
+
+        // Append-only audit log of control-plane actions; the trigger rejects
+        // changes to recorded entries.
+        let audit_sql = r#"
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id VARCHAR(255) PRIMARY KEY,
+                occurred_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                actor VARCHAR(255) NOT NULL,
+                source VARCHAR(255) NOT NULL,
+                action VARCHAR(64) NOT NULL,
+                tenant_id VARCHAR(255),
+                target VARCHAR(255) NOT NULL,
+                details JSONB NOT NULL,
+                outcome VARCHAR(16) NOT NULL,
+                error TEXT
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_audit_log_occurred_at ON audit_log (occurred_at DESC, id DESC);
+            CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log (actor, occurred_at DESC);
+
+            CREATE OR REPLACE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+            BEGIN
+                RAISE EXCEPTION 'audit_log is append-only';
+            END;
+            $$ LANGUAGE plpgsql;
+
+            DROP TRIGGER IF EXISTS audit_log_append_only ON audit_log;
+            CREATE TRIGGER audit_log_append_only
+            BEFORE UPDATE OR DELETE ON audit_log
+            FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
+        "#;
+
+        sqlx::raw_sql(audit_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize audit log table: {}", e))?;
+
+        // Versions of the observed schema of each tenant's topics.
+        let schema_sql = r#"
+            CREATE TABLE IF NOT EXISTS schema_versions (
+                tenant_id VARCHAR(255) NOT NULL,
+                topic VARCHAR(255) NOT NULL,
+                version BIGINT NOT NULL,
+                fields JSONB NOT NULL,
+                changes JSONB NOT NULL,
+                violations JSONB NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                PRIMARY KEY (tenant_id, topic, version)
+            );
+        "#;
+
+        sqlx::raw_sql(schema_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize schema versions table: {}", e))?;
+
+        // Role bindings apply across tenants, like the audit log, so the
+        // table has no tenant_id and no row level security.
+        let rbac_sql = r#"
+            CREATE TABLE IF NOT EXISTS role_bindings (
+                subject VARCHAR(255) NOT NULL,
+                role VARCHAR(255) NOT NULL,
+                tenants JSONB NOT NULL,
+                created_by VARCHAR(255) NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                PRIMARY KEY (subject, role)
+            );
+        "#;
+
+        sqlx::raw_sql(rbac_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize role bindings table: {}", e))?;
+
+        // Catalog of the Parquet segments rows were archived to; the metrics
+        // index lets the lifecycle manager find aged rows without a scan.
+        let lifecycle_sql = r#"
+            CREATE TABLE IF NOT EXISTS archived_segments (
+                location TEXT PRIMARY KEY,
+                tenant_id VARCHAR(255) NOT NULL,
+                dataset VARCHAR(50) NOT NULL,
+                start_time TIMESTAMP WITH TIME ZONE NOT NULL,
+                end_time TIMESTAMP WITH TIME ZONE NOT NULL,
+                row_count BIGINT NOT NULL,
+                archived_at TIMESTAMP WITH TIME ZONE NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_archived_segments_tenant_dataset_start_time
+            ON archived_segments (tenant_id, dataset, start_time);
+
+            CREATE INDEX IF NOT EXISTS idx_metrics_timestamp
+            ON metrics (timestamp);
+        "#;
+
+        sqlx::raw_sql(lifecycle_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize archived segments table: {}", e))?;
+
+        if row_level_security {
+            for table in TENANT_TABLES {
+                // FORCE makes the policy apply to the table owner as well,
+                // which is usually the role the processor connects with.
+                // `app.maintenance` lets cross-tenant jobs such as metric
+                // compaction see every row.
+                let rls_sql = format!(
+                    r#"
+                    ALTER TABLE {table} ENABLE ROW LEVEL SECURITY;
+                    ALTER TABLE {table} FORCE ROW LEVEL SECURITY;
+                    DROP POLICY IF EXISTS tenant_isolation ON {table};
+                    CREATE POLICY tenant_isolation ON {table}
+                        USING (tenant_id = current_setting('app.tenant_id', true)
+                               OR current_setting('app.maintenance', true) = 'on')
+                        WITH CHECK (tenant_id = current_setting('app.tenant_id', true)
+                                    OR current_setting('app.maintenance', true) = 'on');
+                    "#
+                );
+
+                sqlx::raw_sql(&rls_sql)
+                    .execute(pool)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to enable row-level security on {}: {}", table, e))?;
+            }
+            info!("Row-level security policies enabled");
+        }
+
+        info!("Database schema initialized successfully");
+        Ok(())
+    }
+
+    /// Starts a transaction scoped to `tenant_id`.
+    async fn begin_tenant(&self, tenant_id: &str) -> Result<Transaction<'_, Postgres>> {
+        let mut transaction = self.pool.begin().await?;
+        self.set_tenant(&mut transaction, tenant_id).await?;
+        Ok(transaction)
+    }
+
+    /// Starts a read-only transaction scoped to `tenant_id`, on the replica
+    /// when one is configured and healthy. Failing to reach the replica marks
+    /// it unhealthy and falls back to the primary.
+    async fn begin_read(&self, tenant_id: &str) -> Result<Transaction<'_, Postgres>> {
+        if let Some(replica) = &self.replica {
+            if replica.healthy.load(Ordering::Relaxed) {
+                match replica.pool.begin().await {
+                    Ok(mut transaction) => {
+                        self.set_tenant(&mut transaction, tenant_id).await?;
+                        return Ok(transaction);
+                    }
+                    Err(e) => {
+                        warn!("Read replica unavailable, routing reads to primary: {}", e);
+                        replica.healthy.store(false, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+
+        self.begin_tenant(tenant_id).await
+    }
+
+    /// Publishes the tenant to the RLS policies. `set_config(.., true)` keeps
+    /// the setting local to the current transaction, so pooled connections
+    /// never leak one tenant's scope into the next checkout.
+    async fn set_tenant(&self, transaction: &mut Transaction<'_, Postgres>, tenant_id: &str) -> Result<()> {
+        if self.row_level_security {
+            sqlx::query("SELECT set_config('app.tenant_id', $1, true)")
+                .bind(tenant_id)
+                .execute(&mut **transaction)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to set tenant scope: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Starts a transaction that may touch every tenant's rows.
+    async fn begin_maintenance(&self) -> Result<Transaction<'_, Postgres>> {
+        let mut transaction = self.pool.begin().await?;
+        if self.row_level_security {
+            sqlx::query("SELECT set_config('app.maintenance', 'on', true)")
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to set maintenance scope: {}", e))?;
+        }
+        Ok(transaction)
+    }
+
+    fn processed_message_from_row(row: &PgRow) -> Result<ProcessedMessage> {
+        let processing_metadata = ProcessingMetadata {
+            tenant_id: row.get("tenant_id"),
+            processed_at: row.get("processed_at"),
+            processor_version: row.get("processor_version"),
+            source_topic: row.get("source_topic"),
+            partition: row.get("partition"),
+            offset: row.get("offset"),
+            traceparent: row.get("traceparent"),
+        };
+
+        Ok(ProcessedMessage {
+            id: row.get("id"),
+            original_message: codec::from_columns(row.get("original_message"), row.get("original_message_blob"))?,
+            processed_message: codec::from_columns(row.get("processed_message"), row.get("processed_message_blob"))?,
+            processing_metadata,
+        })
+    }
+
+    fn pipeline_from_row(row: &PgRow) -> Result<PipelineDefinition> {
+        let definition: serde_json::Value = row.get("definition");
+        Ok(PipelineDefinition {
+            id: row.get("id"),
+            tenant_id: row.get("tenant_id"),
+            name: row.get("name"),
+            description: row.get("description"),
+            spec: serde_json::from_value(definition)
+                .map_err(|e| anyhow::anyhow!("Failed to decode pipeline definition: {}", e))?,
+            version: row.get("version"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
+    }
+
+    fn job_lease_from_row(row: &PgRow) -> Result<JobLease> {
+        let limits: serde_json::Value = row.get("limits");
+        Ok(JobLease {
+            pipeline_id: row.get("pipeline_id"),
+            job_id: row.get("job_id"),
+            owner: row.get("owner"),
+            endpoint: row.get("endpoint"),
+            config: row.get("config"),
+            limits: serde_json::from_value(limits)
+                .map_err(|e| anyhow::anyhow!("Failed to decode job lease limits: {}", e))?,
+            acquired_at: row.get("acquired_at"),
+            expires_at: row.get("expires_at"),
+        })
+    }
+
+    fn silence_from_row(row: &PgRow) -> Result<Silence> {
+        let matchers: serde_json::Value = row.get("matchers");
+        Ok(Silence {
+            id: row.get("id"),
+            tenant_id: row.get("tenant_id"),
+            matchers: serde_json::from_value(matchers)
+                .map_err(|e| anyhow::anyhow!("Failed to decode silence matchers: {}", e))?,
+            starts_at: row.get("starts_at"),
+            ends_at: row.get("ends_at"),
+            comment: row.get("comment"),
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+        })
+    }
+
+    fn schema_version_from_row(row: &PgRow) -> Result<SchemaVersion> {
+        let fields: serde_json::Value = row.get("fields");
+        let changes: serde_json::Value = row.get("changes");
+        let violations: serde_json::Value = row.get("violations");
+        Ok(SchemaVersion {
+            tenant_id: row.get("tenant_id"),
+            topic: row.get("topic"),
+            version: row.get("version"),
+            fields: serde_json::from_value(fields)
+                .map_err(|e| anyhow::anyhow!("Failed to decode schema fields: {}", e))?,
+            changes: serde_json::from_value(changes)
+                .map_err(|e| anyhow::anyhow!("Failed to decode schema changes: {}", e))?,
+            violations: serde_json::from_value(violations)
+                .map_err(|e| anyhow::anyhow!("Failed to decode schema violations: {}", e))?,
+            created_at: row.get("created_at"),
+        })
+    }
+
+    fn role_binding_from_row(row: &PgRow) -> Result<RoleBinding> {
+        let tenants: serde_json::Value = row.get("tenants");
+        Ok(RoleBinding {
+            subject: row.get("subject"),
+            role: row.get("role"),
+            tenants: serde_json::from_value(tenants)
+                .map_err(|e| anyhow::anyhow!("Failed to decode role binding tenants: {}", e))?,
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+        })
+    }
+
+    fn archived_segment_from_row(row: &PgRow) -> Result<ArchivedSegment> {
+        let dataset: String = row.get("dataset");
+        let rows: i64 = row.get("row_count");
+        Ok(ArchivedSegment {
+            location: row.get("location"),
+            tenant_id: row.get("tenant_id"),
+            dataset: dataset.parse()?,
+            start_time: row.get("start_time"),
+            end_time: row.get("end_time"),
+            rows: rows as u64,
+            archived_at: row.get("archived_at"),
+        })
+    }
+
+    fn audit_entry_from_row(row: &PgRow) -> AuditEntry {
+        AuditEntry {
+            id: row.get("id"),
+            occurred_at: row.get("occurred_at"),
+            actor: row.get("actor"),
+            source: row.get("source"),
+            action: row.get("action"),
+            tenant_id: row.get("tenant_id"),
+            target: row.get("target"),
+            details: row.get("details"),
+            outcome: row.get("outcome"),
+            error: row.get("error"),
+        }
+    }
+
+    /// Converts a `limit + 1` row fetch into a page, using the extra row only
+    /// to decide whether a continuation token is needed.
+    fn page_of_processed_messages(rows: Vec<PgRow>, limit: i64) -> Result<Page<ProcessedMessage>> {
+        let has_more = rows.len() as i64 > limit;
+
+        let messages = rows
+            .iter()
+            .take(limit as usize)
+            .map(Self::processed_message_from_row)
+            .collect::<Result<Vec<_>>>()?;
+
+        let next_cursor = match messages.last() {
+            Some(last) if has_more => Some(
+                PageCursor {
+                    processed_at: last.processing_metadata.processed_at,
+                    id: last.id.to_string(),
+                }
+                .encode(),
+            ),
+            _ => None,
+        };
+
+        Ok(Page {
+            items: messages,
+            next_cursor,
+        })
+    }
+}
+
+#[async_trait]
+impl StorageBackend for StorageManager {
+    async fn store_processed_message(&self, message: &ProcessedMessage) -> Result<()> {
+        self.store_processed_messages(std::slice::from_ref(message)).await?;
+        Ok(())
+    }
+
+    async fn store_processed_messages(&self, messages: &[ProcessedMessage]) -> Result<u64> {
+        if messages.is_empty() {
+            return Ok(0);
+        }
+
+        // One statement per run of same-tenant messages, with the batch
+        // passed as arrays; prepared once per connection.
+        let sql = r#"
+            INSERT INTO processed_messages (
+                id, tenant_id, original_message, processed_message,
+                original_message_blob, processed_message_blob, processed_at,
+                processor_version, source_topic, partition, offset, traceparent
+            )
+            SELECT id::UUID, $1, original_message::JSONB, processed_message::JSONB,
+                   original_message_blob, processed_message_blob, processed_at,
+                   processor_version, source_topic, partition, offset, traceparent
+            FROM UNNEST(
+                $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::BYTEA[], $6::BYTEA[], $7::TIMESTAMPTZ[],
+                $8::TEXT[], $9::TEXT[], $10::INT4[], $11::INT8[], $12::TEXT[]
+            ) AS batch (
+                id, original_message, processed_message, original_message_blob, processed_message_blob,
+                processed_at, processor_version, source_topic, partition, offset, traceparent
+            )
+            ON CONFLICT (source_topic, partition, offset) DO UPDATE SET
+                original_message = EXCLUDED.original_message,
+                processed_message = EXCLUDED.processed_message,
+                original_message_blob = EXCLUDED.original_message_blob,
+                processed_message_blob = EXCLUDED.processed_message_blob,
+                processed_at = EXCLUDED.processed_at,
+                processor_version = EXCLUDED.processor_version,
+                traceparent = EXCLUDED.traceparent,
+                updated_at = NOW()
+            WHERE processed_messages.tenant_id = EXCLUDED.tenant_id
+            RETURNING (xmax <> 0) AS duplicate
+        "#;
+
+        let mut transaction = self.pool.begin().await?;
+        let mut duplicates = 0;
+
+        let mut rest = messages;
+        while let Some(first) = rest.first() {
+            let tenant_id = first.processing_metadata.tenant_id.as_str();
+            let run_len = rest
+                .iter()
+                .position(|m| m.processing_metadata.tenant_id != tenant_id)
+                .unwrap_or(rest.len());
+            let (run, next) = rest.split_at(run_len);
+            rest = next;
+
+            // A statement cannot update the same row twice, so only the last
+            // delivery of each position in the run is written
+            let mut latest = HashMap::new();
+            for (i, message) in run.iter().enumerate() {
+                let metadata = &message.processing_metadata;
+                latest.insert((metadata.source_topic.as_str(), metadata.partition, metadata.offset), i);
+            }
+            let rows: Vec<&ProcessedMessage> = run
+                .iter()
+                .enumerate()
+                .filter(|(i, message)| {
+                    let metadata = &message.processing_metadata;
+                    latest[&(metadata.source_topic.as_str(), metadata.partition, metadata.offset)] == *i
+                })
+                .map(|(_, message)| message)
+                .collect();
+            duplicates += (run.len() - rows.len()) as u64;
+
+            let mut original_json = Vec::with_capacity(rows.len());
+            let mut processed_json = Vec::with_capacity(rows.len());
+            let mut original_blob = Vec::with_capacity(rows.len());
+            let mut processed_blob = Vec::with_capacity(rows.len());
+            for message in &rows {
+                let original = codec::encode(&message.original_message, &self.compression)?;
+                let processed = codec::encode(&message.processed_message, &self.compression)?;
+                original_json.push(original.is_none().then(|| message.original_message.to_string()));
+                processed_json.push(processed.is_none().then(|| message.processed_message.to_string()));
+                original_blob.push(original);
+                processed_blob.push(processed);
+            }
+            let metadata = || rows.iter().map(|message| &message.processing_metadata);
+
+            self.set_tenant(&mut transaction, tenant_id).await?;
+            let returned = sqlx::query(sql)
+                .bind(tenant_id)
+                .bind(rows.iter().map(|message| message.id.as_str()).collect::<Vec<_>>())
+                .bind(original_json)
+                .bind(processed_json)
+                .bind(original_blob)
+                .bind(processed_blob)
+                .bind(metadata().map(|m| m.processed_at).collect::<Vec<_>>())
+                .bind(metadata().map(|m| m.processor_version.as_str()).collect::<Vec<_>>())
+                .bind(metadata().map(|m| m.source_topic.as_str()).collect::<Vec<_>>())
+                .bind(metadata().map(|m| m.partition).collect::<Vec<_>>())
+                .bind(metadata().map(|m| m.offset).collect::<Vec<_>>())
+                .bind(metadata().map(|m| m.traceparent.as_deref()).collect::<Vec<_>>())
+                .fetch_all(&mut *transaction)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to store processed message batch: {}", e))?;
+
+            // xmax is only set on the conflicting row when it was updated; a
+            // missing row means it belongs to another tenant and was skipped.
+            let inserted = returned.iter().filter(|row| !row.get::<bool, _>("duplicate")).count();
+            duplicates += (rows.len() - inserted) as u64;
+        }
+
+        transaction.commit().await?;
+        info!(
+            "Stored {} processed messages in batch ({} duplicates)",
+            messages.len(),
+            duplicates
+        );
+
+        Ok(duplicates)
+    }
+
+    async fn get_processed_message(&self, tenant_id: &str, id: &str) -> Result<Option<ProcessedMessage>> {
+        let sql = r#"
+            SELECT id, tenant_id, original_message, processed_message,
+                   original_message_blob, processed_message_blob, processed_at,
+                   processor_version, source_topic, partition, offset, traceparent
+            FROM processed_messages
+            WHERE tenant_id = $1 AND id = $2
+        "#;
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let row = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(id)
+            .fetch_optional(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get processed message: {}", e))?;
+        transaction.commit().await?;
+
+        row.as_ref().map(Self::processed_message_from_row).transpose()
+    }
+
+    async fn get_processed_messages_by_topic(
+        &self,
+        tenant_id: &str,
+        topic: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        let sql = r#"
+            SELECT id, tenant_id, original_message, processed_message,
+                   original_message_blob, processed_message_blob, processed_at,
+                   processor_version, source_topic, partition, offset, traceparent
+            FROM processed_messages
+            WHERE tenant_id = $1 AND source_topic = $2
+              AND ($3::TIMESTAMPTZ IS NULL OR (processed_at, id) < ($3, $4::UUID))
+            ORDER BY processed_at DESC, id DESC
+            LIMIT $5
+        "#;
+
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+        let (after_processed_at, after_id) = PageCursor::split(cursor);
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(topic)
+            .bind(after_processed_at)
+            .bind(after_id)
+            .bind(limit + 1)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get processed messages by topic: {}", e))?;
+        transaction.commit().await?;
+
+        Self::page_of_processed_messages(rows, limit)
+    }
+
+    async fn get_processed_messages_by_time_range(
+        &self,
+        tenant_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        let sql = r#"
+            SELECT id, tenant_id, original_message, processed_message,
+                   original_message_blob, processed_message_blob, processed_at,
+                   processor_version, source_topic, partition, offset, traceparent
+            FROM processed_messages
+            WHERE tenant_id = $1 AND processed_at >= $2 AND processed_at <= $3
+              AND ($4::TIMESTAMPTZ IS NULL OR (processed_at, id) < ($4, $5::UUID))
+            ORDER BY processed_at DESC, id DESC
+            LIMIT $6
+        "#;
+
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+        let (after_processed_at, after_id) = PageCursor::split(cursor);
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(after_processed_at)
+            .bind(after_id)
+            .bind(limit + 1)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get processed messages by time range: {}", e))?;
+        transaction.commit().await?;
+
+        Self::page_of_processed_messages(rows, limit)
+    }
+
+    async fn store_metric(
+        &self,
+        tenant_id: &str,
+        name: &str,
+        value: f64,
+        metric_type: &str,
+        tags: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        let sql = r#"
+            INSERT INTO metrics (tenant_id, metric_name, metric_value, metric_type, tags, timestamp)
+            VALUES ($1, $2, $3, $4, $5, $6)
+        "#;
+
+        let mut transaction = self.begin_tenant(tenant_id).await?;
+        sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(name)
+            .bind(value)
+            .bind(metric_type)
+            .bind(tags)
+            .bind(timestamp)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store metric: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn store_metrics(&self, tenant_id: &str, metrics: &[MetricRecord]) -> Result<()> {
+        if metrics.is_empty() {
+            return Ok(());
+        }
+
+        let sql = r#"
+            INSERT INTO metrics (tenant_id, metric_name, metric_value, metric_type, tags, timestamp)
+            SELECT $1, metric_name, metric_value, metric_type, tags::JSONB, timestamp
+            FROM UNNEST($2::TEXT[], $3::FLOAT8[], $4::TEXT[], $5::TEXT[], $6::TIMESTAMPTZ[])
+                AS batch (metric_name, metric_value, metric_type, tags, timestamp)
+        "#;
+
+        let mut transaction = self.begin_tenant(tenant_id).await?;
+        sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(metrics.iter().map(|m| m.name.as_str()).collect::<Vec<_>>())
+            .bind(metrics.iter().map(|m| m.value).collect::<Vec<_>>())
+            .bind(metrics.iter().map(|m| m.metric_type.as_str()).collect::<Vec<_>>())
+            .bind(metrics.iter().map(|m| m.tags.as_ref().map(|t| t.to_string())).collect::<Vec<_>>())
+            .bind(metrics.iter().map(|m| m.timestamp).collect::<Vec<_>>())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store metrics: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn store_log(
+        &self,
+        tenant_id: &str,
+        level: &str,
+        message: &str,
+        service_name: Option<&str>,
+        host_name: Option<&str>,
+        trace_id: Option<&str>,
+        span_id: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        let sql = r#"
+            INSERT INTO logs (tenant_id, log_level, message, service_name, host_name, trace_id, span_id, attributes, timestamp)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#;
+
+        let mut transaction = self.begin_tenant(tenant_id).await?;
+        sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(level)
+            .bind(message)
+            .bind(service_name)
+            .bind(host_name)
+            .bind(trace_id)
+            .bind(span_id)
+            .bind(attributes)
+            .bind(timestamp)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store log: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn store_logs(&self, tenant_id: &str, logs: &[LogRecord]) -> Result<()> {
+        if logs.is_empty() {
+            return Ok(());
+        }
+
+        let sql = r#"
+            INSERT INTO logs (tenant_id, log_level, message, service_name, host_name, trace_id, span_id, attributes, timestamp)
+            SELECT $1, log_level, message, service_name, host_name, trace_id, span_id, attributes::JSONB, timestamp
+            FROM UNNEST($2::TEXT[], $3::TEXT[], $4::TEXT[], $5::TEXT[], $6::TEXT[], $7::TEXT[], $8::TEXT[], $9::TIMESTAMPTZ[])
+                AS batch (log_level, message, service_name, host_name, trace_id, span_id, attributes, timestamp)
+        "#;
+
+        let mut transaction = self.begin_tenant(tenant_id).await?;
+        sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(logs.iter().map(|l| l.level.as_str()).collect::<Vec<_>>())
+            .bind(logs.iter().map(|l| l.message.as_str()).collect::<Vec<_>>())
+            .bind(logs.iter().map(|l| l.service_name.as_deref()).collect::<Vec<_>>())
+            .bind(logs.iter().map(|l| l.host_name.as_deref()).collect::<Vec<_>>())
+            .bind(logs.iter().map(|l| l.trace_id.as_deref()).collect::<Vec<_>>())
+            .bind(logs.iter().map(|l| l.span_id.as_deref()).collect::<Vec<_>>())
+            .bind(logs.iter().map(|l| l.attributes.as_ref().map(|a| a.to_string())).collect::<Vec<_>>())
+            .bind(logs.iter().map(|l| l.timestamp).collect::<Vec<_>>())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store logs: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn store_trace(
+        &self,
+        tenant_id: &str,
+        trace_id: &str,
+        span_id: &str,
+        parent_span_id: Option<&str>,
+        name: &str,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: Option<DateTime<Utc>>,
+        duration_ms: Option<i64>,
+        status: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        events: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let sql = r#"
+            INSERT INTO traces (tenant_id, trace_id, span_id, parent_span_id, name, service_name,
+                               start_time, end_time, duration_ms, status, attributes, events)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        "#;
+
+        let mut transaction = self.begin_tenant(tenant_id).await?;
+        sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(trace_id)
+            .bind(span_id)
+            .bind(parent_span_id)
+            .bind(name)
+            .bind(service_name)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(duration_ms)
+            .bind(status)
+            .bind(attributes)
+            .bind(events)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store trace: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_metrics(
+        &self,
+        tenant_id: &str,
+        name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<MetricRecord>> {
+        let sql = r#"
+            SELECT metric_name, metric_value, metric_type, tags, timestamp, resolution_seconds
+            FROM metrics
+            WHERE tenant_id = $1
+              AND ($2::TEXT IS NULL OR metric_name = $2)
+              AND timestamp >= $3 AND timestamp < $4
+            ORDER BY timestamp ASC, id ASC
+            LIMIT $5
+        "#;
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(name)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(limit)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get metrics: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| MetricRecord {
+                name: row.get("metric_name"),
+                value: row.get("metric_value"),
+                metric_type: row.get("metric_type"),
+                tags: row.get("tags"),
+                timestamp: row.get("timestamp"),
+                resolution_secs: row.get("resolution_seconds"),
+            })
+            .collect())
+    }
+
+    async fn list_metric_names(&self, tenant_id: &str, limit: i64) -> Result<Vec<String>> {
+        let sql = r#"
+            SELECT DISTINCT metric_name
+            FROM metrics
+            WHERE tenant_id = $1
+            ORDER BY metric_name
+            LIMIT $2
+        "#;
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let names = sqlx::query_scalar(sql)
+            .bind(tenant_id)
+            .bind(limit)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list metric names: {}", e))?;
+        transaction.commit().await?;
+        Ok(names)
+    }
+
+    async fn get_logs(
+        &self,
+        tenant_id: &str,
+        level: Option<&str>,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<LogRecord>> {
+        let sql = r#"
+            SELECT log_level, message, service_name, host_name, trace_id, span_id, attributes, timestamp
+            FROM logs
+            WHERE tenant_id = $1
+              AND ($2::TEXT IS NULL OR log_level = $2)
+              AND ($3::TEXT IS NULL OR service_name = $3)
+              AND timestamp >= $4 AND timestamp < $5
+            ORDER BY timestamp ASC, id ASC
+            LIMIT $6
+        "#;
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(level)
+            .bind(service_name)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(limit)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get logs: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| LogRecord {
+                level: row.get("log_level"),
+                message: row.get("message"),
+                service_name: row.get("service_name"),
+                host_name: row.get("host_name"),
+                trace_id: row.get("trace_id"),
+                span_id: row.get("span_id"),
+                attributes: row.get("attributes"),
+                timestamp: row.get("timestamp"),
+            })
+            .collect())
+    }
+
+    async fn get_trace(&self, tenant_id: &str, trace_id: &str) -> Result<Option<Trace>> {
+        let sql = r#"
+            SELECT span_id, parent_span_id, name, service_name, start_time, end_time,
+                   duration_ms, status, attributes, events
+            FROM traces
+            WHERE tenant_id = $1 AND trace_id = $2
+            ORDER BY start_time ASC
+        "#;
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(trace_id)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get trace: {}", e))?;
+        transaction.commit().await?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        let spans = rows
+            .into_iter()
+            .map(|row| TraceSpan {
+                span_id: row.get("span_id"),
+                parent_span_id: row.get("parent_span_id"),
+                name: row.get("name"),
+                service_name: row.get("service_name"),
+                start_time: row.get("start_time"),
+                end_time: row.get("end_time"),
+                duration_ms: row.get("duration_ms"),
+                status: row.get("status"),
+                attributes: row.get("attributes"),
+                events: row.get("events"),
+                children: Vec::new(),
+            })
+            .collect();
+
+        Ok(Some(assemble_trace(trace_id, spans)))
+    }
+
+    async fn find_traces(
+        &self,
+        tenant_id: &str,
+        service: Option<&str>,
+        min_duration: Option<Duration>,
+        status: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<TraceSummary>> {
+        // Spans are matched individually (Jaeger semantics), then the matching
+        // traces are aggregated so the summary covers every span of the trace.
+        let sql = r#"
+            WITH matching AS (
+                SELECT DISTINCT trace_id
+                FROM traces
+                WHERE tenant_id = $1
+                  AND start_time >= $2 AND start_time <= $3
+                  AND ($4::VARCHAR IS NULL OR service_name = $4)
+                  AND ($5::BIGINT IS NULL OR duration_ms >= $5)
+                  AND ($6::VARCHAR IS NULL OR status = $6)
+            )
+            SELECT t.trace_id,
+                   (ARRAY_AGG(t.service_name ORDER BY t.parent_span_id NULLS FIRST, t.start_time))[1] AS root_service_name,
+                   (ARRAY_AGG(t.name ORDER BY t.parent_span_id NULLS FIRST, t.start_time))[1] AS root_span_name,
+                   MIN(t.start_time) AS start_time,
+                   MAX(t.duration_ms) AS duration_ms,
+                   COUNT(*) AS span_count,
+                   COALESCE(BOOL_OR(t.status = 'error'), FALSE) AS has_error
+            FROM traces t
+            JOIN matching m ON m.trace_id = t.trace_id
+            WHERE t.tenant_id = $1
+            GROUP BY t.trace_id
+            ORDER BY duration_ms DESC NULLS LAST, start_time DESC
+            LIMIT $7
+        "#;
+
+        let min_duration_ms = min_duration.map(|d| d.as_millis() as i64);
+        let limit = limit.unwrap_or(20);
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(service)
+            .bind(min_duration_ms)
+            .bind(status)
+            .bind(limit)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to find traces: {}", e))?;
+        transaction.commit().await?;
+
+        let traces = rows
+            .into_iter()
+            .map(|row| TraceSummary {
+                trace_id: row.get("trace_id"),
+                root_service_name: row.get("root_service_name"),
+                root_span_name: row.get("root_span_name"),
+                start_time: row.get("start_time"),
+                duration_ms: row.get("duration_ms"),
+                span_count: row.get("span_count"),
+                has_error: row.get("has_error"),
+            })
+            .collect();
+
+        Ok(traces)
+    }
+
+    async fn erase_records(
+        &self,
+        dataset: ErasureDataset,
+        selector: &ErasureSelector,
+        mode: ErasureMode,
+        limit: i64,
+    ) -> Result<u64> {
+        if mode == ErasureMode::Scrub {
+            selector.scrubbed()?;
+        }
+        if dataset == ErasureDataset::ProcessedMessages
+            && selector.attribute.is_some()
+            && self.compression.codec != PayloadCodec::None
+        {
+            return Err(anyhow::anyhow!(
+                "Cannot match attributes of compressed processed messages; erase them by time range instead"
+            ));
+        }
+
+        let attribute_in = |column: &str| {
+            format!("(jsonb_typeof({column} #> $4) IN ('string', 'number') AND {column} #>> $4 = $5)")
+        };
+        let (table, time_column, attribute_matches) = match dataset {
+            ErasureDataset::Logs => ("logs", "timestamp", attribute_in("attributes")),
+            ErasureDataset::Traces => ("traces", "start_time", attribute_in("attributes")),
+            ErasureDataset::ProcessedMessages => (
+                "processed_messages",
+                "processed_at",
+                format!("({} OR {})", attribute_in("original_message"), attribute_in("processed_message")),
+            ),
+        };
+        let matching = |column: &str| {
+            format!(
+                r#"
+                SELECT {column} FROM {table}
+                WHERE tenant_id = $1
+                  AND ($2::TIMESTAMPTZ IS NULL OR {time_column} >= $2)
+                  AND ($3::TIMESTAMPTZ IS NULL OR {time_column} < $3)
+                  AND ($4::TEXT[] IS NULL OR {attribute_matches})
+                LIMIT $6
+                "#
+            )
+        };
+        // Traces are deleted whole, so no span of them is left behind
+        let sql = match (dataset, mode) {
+            (ErasureDataset::Traces, ErasureMode::Delete) => format!(
+                "DELETE FROM traces WHERE tenant_id = $1 AND trace_id IN ({})",
+                matching("DISTINCT trace_id")
+            ),
+            (_, ErasureMode::Delete) => format!("DELETE FROM {} WHERE id IN ({})", table, matching("id")),
+            (ErasureDataset::ProcessedMessages, ErasureMode::Scrub) => format!(
+                r#"
+                UPDATE processed_messages
+                SET original_message = original_message #- $4, processed_message = processed_message #- $4
+                WHERE id IN ({})
+                "#,
+                matching("id")
+            ),
+            (_, ErasureMode::Scrub) => format!(
+                "UPDATE {} SET attributes = attributes #- $4 WHERE id IN ({})",
+                table,
+                matching("id")
+            ),
+        };
+
+        let attribute = selector.attribute.as_ref();
+        let mut transaction = self.begin_tenant(&selector.tenant_id).await?;
+        let result = sqlx::query(&sql)
+            .bind(&selector.tenant_id)
+            .bind(selector.start_time)
+            .bind(selector.end_time)
+            .bind(attribute.map(|a| a.path.clone()))
+            .bind(attribute.map(|a| a.value.clone()))
+            .bind(limit)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to erase {}: {}", dataset.as_str(), e))?;
+        transaction.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn compact_metrics(&self, rule: &MetricRetentionRule, cutoff: DateTime<Utc>) -> Result<CompactionStats> {
+        // Existing rollups are re-aggregated by sample count, so coarser
+        // tiers stay exact averages of the raw data.
+        let sql = r#"
+            WITH raw AS (
+                DELETE FROM metrics
+                WHERE metric_name LIKE $1 ESCAPE ''
+                  AND timestamp < $2
+                  AND (resolution_seconds IS NULL OR resolution_seconds < $3::BIGINT)
+                RETURNING tenant_id, metric_name, metric_type, tags, timestamp,
+                          metric_value, sample_count, min_value, max_value
+            ),
+            rollups AS (
+                INSERT INTO metrics (tenant_id, metric_name, metric_value, metric_type, tags, timestamp,
+                                     resolution_seconds, sample_count, min_value, max_value)
+                SELECT tenant_id,
+                       metric_name,
+                       SUM(metric_value * COALESCE(sample_count, 1)) / SUM(COALESCE(sample_count, 1)),
+                       metric_type,
+                       tags,
+                       to_timestamp(floor(extract(epoch FROM timestamp) / $3::BIGINT) * $3::BIGINT) AS bucket,
+                       $3::BIGINT,
+                       SUM(COALESCE(sample_count, 1)),
+                       MIN(COALESCE(min_value, metric_value)),
+                       MAX(COALESCE(max_value, metric_value))
+                FROM raw
+                GROUP BY tenant_id, metric_name, metric_type, tags, bucket
+                RETURNING 1
+            )
+            SELECT (SELECT COUNT(*) FROM raw) AS rows_deleted,
+                   (SELECT COUNT(*) FROM rollups) AS rollups_written
+        "#;
+
+        let mut transaction = self.begin_maintenance().await?;
+        let row = sqlx::query(sql)
+            .bind(compaction::like_pattern(rule))
+            .bind(cutoff)
+            .bind(rule.resolution_secs as i64)
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to compact metrics: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(CompactionStats {
+            rows_deleted: row.get::<i64, _>("rows_deleted") as u64,
+            rollups_written: row.get::<i64, _>("rollups_written") as u64,
+        })
+    }
+
+    async fn oldest_rows(&self, dataset: ExportDataset, before: DateTime<Utc>) -> Result<Vec<(String, DateTime<Utc>)>> {
+        let (table, time) = lifecycle::table(dataset);
+        let sql = format!(
+            r#"
+            SELECT tenant_id, MIN({time}) AS oldest FROM {table}
+            WHERE {time} < $1
+            GROUP BY tenant_id
+            ORDER BY tenant_id
+            "#
+        );
+
+        let mut transaction = self.begin_maintenance().await?;
+        let rows = sqlx::query(&sql)
+            .bind(before)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to find aged {}: {}", table, e))?;
+        transaction.commit().await?;
+
+        Ok(rows.iter().map(|row| (row.get("tenant_id"), row.get("oldest"))).collect())
+    }
+
+    async fn record_archived_segment(&self, segment: &ArchivedSegment) -> Result<bool> {
+        let (table, time) = lifecycle::table(segment.dataset);
+        let delete_sql = format!("DELETE FROM {table} WHERE tenant_id = $1 AND {time} >= $2 AND {time} < $3");
+        let insert_sql = r#"
+            INSERT INTO archived_segments (location, tenant_id, dataset, start_time, end_time, row_count, archived_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#;
+
+        let mut transaction = self.begin_tenant(&segment.tenant_id).await?;
+        let deleted = sqlx::query(&delete_sql)
+            .bind(&segment.tenant_id)
+            .bind(segment.start_time)
+            .bind(segment.end_time)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete archived {}: {}", table, e))?;
+        // Dropping the transaction rolls the delete back
+        if deleted.rows_affected() != segment.rows {
+            return Ok(false);
+        }
+        sqlx::query(insert_sql)
+            .bind(&segment.location)
+            .bind(&segment.tenant_id)
+            .bind(segment.dataset.as_str())
+            .bind(segment.start_time)
+            .bind(segment.end_time)
+            .bind(segment.rows as i64)
+            .bind(segment.archived_at)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to record archived segment: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn list_archived_segments(
+        &self,
+        tenant_id: &str,
+        dataset: ExportDataset,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<ArchivedSegment>> {
+        let sql = r#"
+            SELECT location, tenant_id, dataset, start_time, end_time, row_count, archived_at
+            FROM archived_segments
+            WHERE tenant_id = $1 AND dataset = $2 AND start_time < $4 AND end_time > $3
+            ORDER BY start_time ASC, location ASC
+        "#;
+
+        // Read where metric queries read, so a lagging replica shows rows and
+        // the segment they moved to as of the same moment.
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(dataset.as_str())
+            .bind(start_time)
+            .bind(end_time)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list archived segments: {}", e))?;
+        transaction.commit().await?;
+
+        rows.iter().map(Self::archived_segment_from_row).collect()
+    }
+
+    async fn create_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        let sql = r#"
+            INSERT INTO pipelines (tenant_id, id, name, description, definition, version, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (tenant_id, id) DO NOTHING
+        "#;
+
+        let definition = serde_json::to_value(&pipeline.spec)
+            .map_err(|e| anyhow::anyhow!("Failed to encode pipeline definition: {}", e))?;
+
+        let mut transaction = self.begin_tenant(&pipeline.tenant_id).await?;
+        let result = sqlx::query(sql)
+            .bind(&pipeline.tenant_id)
+            .bind(&pipeline.id)
+            .bind(&pipeline.name)
+            .bind(&pipeline.description)
+            .bind(definition)
+            .bind(pipeline.version)
+            .bind(pipeline.created_at)
+            .bind(pipeline.updated_at)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create pipeline: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn update_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        let sql = r#"
+            UPDATE pipelines
+            SET name = $3, description = $4, definition = $5, version = $6, updated_at = $7
+            WHERE tenant_id = $1 AND id = $2 AND version = $6 - 1
+        "#;
+
+        let definition = serde_json::to_value(&pipeline.spec)
+            .map_err(|e| anyhow::anyhow!("Failed to encode pipeline definition: {}", e))?;
+
+        let mut transaction = self.begin_tenant(&pipeline.tenant_id).await?;
+        let result = sqlx::query(sql)
+            .bind(&pipeline.tenant_id)
+            .bind(&pipeline.id)
+            .bind(&pipeline.name)
+            .bind(&pipeline.description)
+            .bind(definition)
+            .bind(pipeline.version)
+            .bind(pipeline.updated_at)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to update pipeline: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_pipeline(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        let mut transaction = self.begin_tenant(tenant_id).await?;
+        let result = sqlx::query("DELETE FROM pipelines WHERE tenant_id = $1 AND id = $2")
+            .bind(tenant_id)
+            .bind(id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete pipeline: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_pipeline(&self, tenant_id: &str, id: &str) -> Result<Option<PipelineDefinition>> {
+        let sql = r#"
+            SELECT tenant_id, id, name, description, definition, version, created_at, updated_at
+            FROM pipelines
+            WHERE tenant_id = $1 AND id = $2
+        "#;
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let row = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(id)
+            .fetch_optional(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get pipeline: {}", e))?;
+        transaction.commit().await?;
+
+        row.as_ref().map(Self::pipeline_from_row).transpose()
+    }
+
+    async fn list_pipelines(
+        &self,
+        tenant_id: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<PipelineDefinition>> {
+        let sql = r#"
+            SELECT tenant_id, id, name, description, definition, version, created_at, updated_at
+            FROM pipelines
+            WHERE tenant_id = $1
+              AND ($2::TIMESTAMPTZ IS NULL OR (created_at, id) < ($2, $3))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $4
+        "#;
+
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+        let (after_created_at, after_id) = PageCursor::split(cursor);
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(after_created_at)
+            .bind(after_id)
+            .bind(limit + 1)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list pipelines: {}", e))?;
+        transaction.commit().await?;
+
+        let pipelines = rows.iter().map(Self::pipeline_from_row).collect::<Result<Vec<_>>>()?;
+        Ok(page_of_pipelines(pipelines, limit))
+    }
+
+    async fn list_scheduled_pipelines(&self) -> Result<Vec<PipelineDefinition>> {
+        let sql = r#"
+            SELECT tenant_id, id, name, description, definition, version, created_at, updated_at
+            FROM pipelines
+            WHERE jsonb_typeof(definition->'schedule') = 'object'
+            ORDER BY tenant_id, id
+        "#;
+
+        let mut transaction = self.begin_maintenance().await?;
+        let rows = sqlx::query(sql)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list scheduled pipelines: {}", e))?;
+        transaction.commit().await?;
+
+        rows.iter().map(Self::pipeline_from_row).collect()
+    }
+
+    async fn acquire_job_lease(&self, lease: &JobLease) -> Result<bool> {
+        // The conflict update only applies to an expired lease, and the row
+        // lock it takes makes concurrent takeovers pick a single winner.
+        let sql = r#"
+            INSERT INTO job_leases (pipeline_id, job_id, owner, endpoint, config, limits, acquired_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (pipeline_id) DO UPDATE
+            SET job_id = EXCLUDED.job_id,
+                owner = EXCLUDED.owner,
+                endpoint = EXCLUDED.endpoint,
+                config = EXCLUDED.config,
+                limits = EXCLUDED.limits,
+                acquired_at = EXCLUDED.acquired_at,
+                expires_at = EXCLUDED.expires_at
+            WHERE job_leases.expires_at <= EXCLUDED.acquired_at
+        "#;
+
+        let limits = serde_json::to_value(&lease.limits)
+            .map_err(|e| anyhow::anyhow!("Failed to encode job lease limits: {}", e))?;
+
+        let result = sqlx::query(sql)
+            .bind(&lease.pipeline_id)
+            .bind(&lease.job_id)
+            .bind(&lease.owner)
+            .bind(&lease.endpoint)
+            .bind(&lease.config)
+            .bind(limits)
+            .bind(lease.acquired_at)
+            .bind(lease.expires_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to acquire job lease: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn renew_job_lease(
+        &self,
+        pipeline_id: &str,
+        owner: &str,
+        job_id: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let sql = r#"
+            UPDATE job_leases
+            SET job_id = COALESCE($3, job_id), expires_at = $4
+            WHERE pipeline_id = $1 AND owner = $2
+        "#;
+
+        let result = sqlx::query(sql)
+            .bind(pipeline_id)
+            .bind(owner)
+            .bind(job_id)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to renew job lease: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn release_job_lease(&self, pipeline_id: &str, owner: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM job_leases WHERE pipeline_id = $1 AND owner = $2")
+            .bind(pipeline_id)
+            .bind(owner)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to release job lease: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_job_lease(&self, job_id: &str) -> Result<Option<JobLease>> {
+        let sql = r#"
+            SELECT pipeline_id, job_id, owner, endpoint, config, limits, acquired_at, expires_at
+            FROM job_leases
+            WHERE job_id = $1
+        "#;
+
+        let row = sqlx::query(sql)
+            .bind(job_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get job lease: {}", e))?;
+
+        row.as_ref().map(Self::job_lease_from_row).transpose()
+    }
+
+    async fn list_expired_job_leases(&self, now: DateTime<Utc>) -> Result<Vec<JobLease>> {
+        let sql = r#"
+            SELECT pipeline_id, job_id, owner, endpoint, config, limits, acquired_at, expires_at
+            FROM job_leases
+            WHERE expires_at <= $1
+            ORDER BY expires_at ASC, pipeline_id ASC
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list expired job leases: {}", e))?;
+
+        rows.iter().map(Self::job_lease_from_row).collect()
+    }
+
+    async fn store_service_statuses(&self, statuses: &[ServiceStatusRecord]) -> Result<()> {
+        if statuses.is_empty() {
+            return Ok(());
+        }
+
+        let sql = r#"
+            INSERT INTO service_statuses (
+                tenant_id, name, status, uptime, response_time, requests_per_second, last_seen, last_check
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (tenant_id, name) DO UPDATE SET
+                status = EXCLUDED.status,
+                uptime = EXCLUDED.uptime,
+                response_time = EXCLUDED.response_time,
+                requests_per_second = EXCLUDED.requests_per_second,
+                last_seen = EXCLUDED.last_seen,
+                last_check = EXCLUDED.last_check
+        "#;
+
+        let mut transaction = self.pool.begin().await?;
+        let mut scoped_tenant: Option<&str> = None;
+        for status in statuses {
+            if scoped_tenant != Some(status.tenant_id.as_str()) {
+                self.set_tenant(&mut transaction, &status.tenant_id).await?;
+                scoped_tenant = Some(status.tenant_id.as_str());
+            }
+            sqlx::query(sql)
+                .bind(&status.tenant_id)
+                .bind(&status.name)
+                .bind(&status.status)
+                .bind(status.uptime)
+                .bind(status.response_time)
+                .bind(status.requests_per_second)
+                .bind(status.last_seen)
+                .bind(status.last_check)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to store service status: {}", e))?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn list_service_statuses(&self, tenant_id: &str) -> Result<Vec<ServiceStatusRecord>> {
+        let sql = r#"
+            SELECT tenant_id, name, status, uptime, response_time, requests_per_second, last_seen, last_check
+            FROM service_statuses
+            WHERE tenant_id = $1
+            ORDER BY name ASC
+        "#;
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list service statuses: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ServiceStatusRecord {
+                tenant_id: row.get("tenant_id"),
+                name: row.get("name"),
+                status: row.get("status"),
+                uptime: row.get("uptime"),
+                response_time: row.get("response_time"),
+                requests_per_second: row.get("requests_per_second"),
+                last_seen: row.get("last_seen"),
+                last_check: row.get("last_check"),
+            })
+            .collect())
+    }
+
+    async fn record_alert_delivery(&self, delivery: &AlertDelivery) -> Result<()> {
+        let sql = r#"
+            INSERT INTO alert_deliveries (
+                tenant_id, id, alert_id, channel, status, attempts, last_error, created_at, updated_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (tenant_id, id) DO UPDATE SET
+                status = EXCLUDED.status,
+                attempts = EXCLUDED.attempts,
+                last_error = EXCLUDED.last_error,
+                updated_at = EXCLUDED.updated_at
+        "#;
+
+        let mut transaction = self.begin_tenant(&delivery.tenant_id).await?;
+        sqlx::query(sql)
+            .bind(&delivery.tenant_id)
+            .bind(&delivery.id)
+            .bind(&delivery.alert_id)
+            .bind(&delivery.channel)
+            .bind(&delivery.status)
+            .bind(delivery.attempts)
+            .bind(&delivery.last_error)
+            .bind(delivery.created_at)
+            .bind(delivery.updated_at)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to record alert delivery: {}", e))?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn list_alert_deliveries(
+        &self,
+        tenant_id: &str,
+        alert_id: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Vec<AlertDelivery>> {
+        let sql = r#"
+            SELECT tenant_id, id, alert_id, channel, status, attempts, last_error, created_at, updated_at
+            FROM alert_deliveries
+            WHERE tenant_id = $1
+              AND ($2::VARCHAR IS NULL OR alert_id = $2)
+            ORDER BY created_at DESC, id DESC
+            LIMIT $3
+        "#;
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(alert_id)
+            .bind(limit.unwrap_or(100))
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list alert deliveries: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| AlertDelivery {
+                id: row.get("id"),
+                tenant_id: row.get("tenant_id"),
+                alert_id: row.get("alert_id"),
+                channel: row.get("channel"),
+                status: row.get("status"),
+                attempts: row.get("attempts"),
+                last_error: row.get("last_error"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+            })
+            .collect())
+    }
+
+    async fn create_silence(&self, silence: &Silence) -> Result<bool> {
+        let sql = r#"
+            INSERT INTO silences (tenant_id, id, matchers, starts_at, ends_at, comment, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (tenant_id, id) DO NOTHING
+        "#;
+
+        let matchers = serde_json::to_value(&silence.matchers)
+            .map_err(|e| anyhow::anyhow!("Failed to encode silence matchers: {}", e))?;
+
+        let mut transaction = self.begin_tenant(&silence.tenant_id).await?;
+        let result = sqlx::query(sql)
+            .bind(&silence.tenant_id)
+            .bind(&silence.id)
+            .bind(matchers)
+            .bind(silence.starts_at)
+            .bind(silence.ends_at)
+            .bind(&silence.comment)
+            .bind(&silence.created_by)
+            .bind(silence.created_at)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create silence: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_silence(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        let mut transaction = self.begin_tenant(tenant_id).await?;
+        let result = sqlx::query("DELETE FROM silences WHERE tenant_id = $1 AND id = $2")
+            .bind(tenant_id)
+            .bind(id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete silence: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn list_silences(&self, tenant_id: &str, active_at: Option<DateTime<Utc>>) -> Result<Vec<Silence>> {
+        let sql = r#"
+            SELECT tenant_id, id, matchers, starts_at, ends_at, comment, created_by, created_at
+            FROM silences
+            WHERE tenant_id = $1
+              AND ($2::TIMESTAMPTZ IS NULL OR (starts_at <= $2 AND ends_at > $2))
+            ORDER BY starts_at ASC, id ASC
+        "#;
+
+        let mut transaction = self.begin_read(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(active_at)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list silences: {}", e))?;
+        transaction.commit().await?;
+
+        rows.iter().map(Self::silence_from_row).collect()
+    }
+
+    async fn list_unexpired_silences(&self, now: DateTime<Utc>) -> Result<Vec<Silence>> {
+        let sql = r#"
+            SELECT tenant_id, id, matchers, starts_at, ends_at, comment, created_by, created_at
+            FROM silences
+            WHERE ends_at > $1
+            ORDER BY tenant_id, starts_at ASC, id ASC
+        "#;
+
+        let mut transaction = self.begin_maintenance().await?;
+        let rows = sqlx::query(sql)
+            .bind(now)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list unexpired silences: {}", e))?;
+        transaction.commit().await?;
+
+        rows.iter().map(Self::silence_from_row).collect()
+    }
+
+    async fn append_audit_entry(&self, entry: &AuditEntry) -> Result<()> {
+        let sql = r#"
+            INSERT INTO audit_log (id, occurred_at, actor, source, action, tenant_id, target, details, outcome, error)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "#;
+
+        sqlx::query(sql)
+            .bind(&entry.id)
+            .bind(entry.occurred_at)
+            .bind(&entry.actor)
+            .bind(&entry.source)
+            .bind(&entry.action)
+            .bind(&entry.tenant_id)
+            .bind(&entry.target)
+            .bind(&entry.details)
+            .bind(&entry.outcome)
+            .bind(&entry.error)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to append audit entry: {}", e))?;
+        Ok(())
+    }
+
+    async fn query_audit_log(
+        &self,
+        filter: &AuditFilter,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<AuditEntry>> {
+        let sql = r#"
+            SELECT id, occurred_at, actor, source, action, tenant_id, target, details, outcome, error
+            FROM audit_log
+            WHERE ($1::TIMESTAMPTZ IS NULL OR occurred_at >= $1)
+              AND ($2::TIMESTAMPTZ IS NULL OR occurred_at < $2)
+              AND ($3::VARCHAR IS NULL OR actor = $3)
+              AND ($4::VARCHAR IS NULL OR action = $4)
+              AND ($5::VARCHAR IS NULL OR target = $5)
+              AND ($6::TIMESTAMPTZ IS NULL OR (occurred_at, id) < ($6, $7))
+            ORDER BY occurred_at DESC, id DESC
+            LIMIT $8
+        "#;
+
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+        let (after_occurred_at, after_id) = PageCursor::split(cursor);
+
+        let rows = sqlx::query(sql)
+            .bind(filter.start_time)
+            .bind(filter.end_time)
+            .bind(&filter.actor)
+            .bind(&filter.action)
+            .bind(&filter.target)
+            .bind(after_occurred_at)
+            .bind(after_id)
+            .bind(limit + 1)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to query audit log: {}", e))?;
+
+        let entries = rows.iter().map(Self::audit_entry_from_row).collect();
+        Ok(page_of_audit_entries(entries, limit))
+    }
+
+    async fn create_schema_version(&self, version: &SchemaVersion) -> Result<bool> {
+        let sql = r#"
+            INSERT INTO schema_versions (tenant_id, topic, version, fields, changes, violations, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (tenant_id, topic, version) DO NOTHING
+        "#;
+
+        let encode = |value: serde_json::Result<serde_json::Value>| {
+            value.map_err(|e| anyhow::anyhow!("Failed to encode schema version: {}", e))
+        };
+        let mut transaction = self.begin_tenant(&version.tenant_id).await?;
+        let result = sqlx::query(sql)
+            .bind(&version.tenant_id)
+            .bind(&version.topic)
+            .bind(version.version)
+            .bind(encode(serde_json::to_value(&version.fields))?)
+            .bind(encode(serde_json::to_value(&version.changes))?)
+            .bind(encode(serde_json::to_value(&version.violations))?)
+            .bind(version.created_at)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create schema version: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn list_schema_versions(&self, tenant_id: &str, topic: &str) -> Result<Vec<SchemaVersion>> {
+        let sql = r#"
+            SELECT tenant_id, topic, version, fields, changes, violations, created_at
+            FROM schema_versions
+            WHERE tenant_id = $1 AND topic = $2
+            ORDER BY version ASC
+        "#;
+
+        // Read from the primary: the registry reloads a topic right after
+        // another instance wrote to it.
+        let mut transaction = self.begin_tenant(tenant_id).await?;
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(topic)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list schema versions: {}", e))?;
+        transaction.commit().await?;
+
+        rows.iter().map(Self::schema_version_from_row).collect()
+    }
+
+    async fn list_latest_schema_versions(&self) -> Result<Vec<SchemaVersion>> {
+        let sql = r#"
+            SELECT DISTINCT ON (tenant_id, topic)
+                tenant_id, topic, version, fields, changes, violations, created_at
+            FROM schema_versions
+            ORDER BY tenant_id, topic, version DESC
+        "#;
+
+        let mut transaction = self.begin_maintenance().await?;
+        let rows = sqlx::query(sql)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list latest schema versions: {}", e))?;
+        transaction.commit().await?;
+
+        rows.iter().map(Self::schema_version_from_row).collect()
+    }
+
+    async fn create_role_binding(&self, binding: &RoleBinding) -> Result<bool> {
+        let sql = r#"
+            INSERT INTO role_bindings (subject, role, tenants, created_by, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (subject, role) DO NOTHING
+        "#;
+
+        let tenants = serde_json::to_value(&binding.tenants)
+            .map_err(|e| anyhow::anyhow!("Failed to encode role binding tenants: {}", e))?;
+        let result = sqlx::query(sql)
+            .bind(&binding.subject)
+            .bind(&binding.role)
+            .bind(tenants)
+            .bind(&binding.created_by)
+            .bind(binding.created_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create role binding: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_role_binding(&self, subject: &str, role: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM role_bindings WHERE subject = $1 AND role = $2")
+            .bind(subject)
+            .bind(role)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete role binding: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn list_role_bindings(&self, subject: Option<&str>) -> Result<Vec<RoleBinding>> {
+        let sql = r#"
+            SELECT subject, role, tenants, created_by, created_at
+            FROM role_bindings
+            WHERE ($1::VARCHAR IS NULL OR subject = $1)
+            ORDER BY subject, role
+        "#;
+
+        // Read from the primary so that a binding applies as soon as the
+        // next policy refresh, wherever it was created.
+        let rows = sqlx::query(sql)
+            .bind(subject)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list role bindings: {}", e))?;
+
+        rows.iter().map(Self::role_binding_from_row).collect()
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        if let Some(replica) = &self.replica {
+            let healthy = sqlx::query("SELECT 1").fetch_one(&replica.pool).await.is_ok();
+            if healthy != replica.healthy.swap(healthy, Ordering::Relaxed) {
+                if healthy {
+                    info!("Read replica recovered, routing reads to replica");
+                } else {
+                    warn!("Read replica health check failed, routing reads to primary");
+                }
+            }
+        }
+
+        match sqlx::query("SELECT 1").fetch_one(&self.pool).await {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                error!("Database health check failed: {}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        PoolStatus {
+            size: self.pool.size(),
+            idle: self.pool.num_idle() as u32,
+        }
+    }
+}
+
+impl Clone for StorageManager {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            replica: self.replica.clone(),
+            row_level_security: self.row_level_security,
+            compression: self.compression.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_storage_manager_creation() {
+        let config = Config::default();
+        let storage = StorageManager::new(&config).await;
+        // This will likely fail in test environment without database running
+        // but we can test that the method signature is correct
+        assert!(storage.is_ok() || storage.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_store_and_get_processed_message() {
+        let config = Config::default();
+        let storage = StorageManager::new(&config).await;
+
+        if let Ok(storage) = storage {
+            let message = ProcessedMessage {
+                id: "test-id".to_string(),
+                original_message: json!({"test": "original"}),
+                processed_message: json!({"test": "processed"}),
+                processing_metadata: ProcessingMetadata {
+                    tenant_id: "test-tenant".to_string(),
+                    processed_at: Utc::now(),
+                    processor_version: "1.0.0".to_string(),
+                    source_topic: "test-topic".to_string(),
+                    partition: 0,
+                    offset: 100,
+                    traceparent: None,
+                },
+            };
+
+            let store_result = storage.store_processed_message(&message).await;
+            assert!(store_result.is_ok() || store_result.is_err());
+
+            if store_result.is_ok() {
+                let get_result = storage.get_processed_message("test-tenant", "test-id").await;
+                assert!(get_result.is_ok());
+
+                let other_tenant = storage.get_processed_message("other-tenant", "test-id").await;
+                assert!(matches!(other_tenant, Ok(None)));
+            }
+        }
+    }
+}
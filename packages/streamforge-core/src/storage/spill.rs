@@ -0,0 +1,479 @@
+use super::StorageBackend;
+use crate::config::SpillConfig;
+use crate::metrics::Metrics;
+use crate::processor::ProcessedMessage;
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+const SEGMENT_EXTENSION: &str = "seg";
+
+/// Segments holding a corrupt record are renamed to this extension, out of
+/// the replay queue but kept for inspection.
+const QUARANTINE_EXTENSION: &str = "corrupt";
+
+/// Every record in a segment is a little-endian `u32` length followed by the
+/// JSON encoding of one batch (`Vec<ProcessedMessage>`).
+const RECORD_HEADER_LEN: u64 = 4;
+
+#[derive(Debug)]
+struct Segment {
+    seq: u64,
+    path: PathBuf,
+    bytes: u64,
+}
+
+#[derive(Debug)]
+struct SpillState {
+    segments: VecDeque<Segment>,
+    next_seq: u64,
+}
+
+/// Append-only, size-capped on-disk queue of batches that could not be
+/// written to the database. Segments are replayed oldest-first and deleted
+/// once every batch in them has been stored.
+pub struct SpillBuffer {
+    directory: PathBuf,
+    max_bytes: u64,
+    segment_bytes: u64,
+    total_bytes: AtomicU64,
+    state: Mutex<SpillState>,
+}
+
+impl SpillBuffer {
+    /// Opens the spill directory, picking up segments left behind by a
+    /// previous run so they are replayed after a restart.
+    pub async fn open(config: &SpillConfig) -> Result<Self> {
+        let directory = PathBuf::from(&config.directory);
+        tokio::fs::create_dir_all(&directory)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create spill directory {}: {}", directory.display(), e))?;
+
+        let mut segments = Vec::new();
+        let mut entries = tokio::fs::read_dir(&directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            let seq = match path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u64>().ok()) {
+                Some(seq) => seq,
+                None => continue,
+            };
+            let bytes = entry.metadata().await?.len();
+            segments.push(Segment { seq, path, bytes });
+        }
+        segments.sort_by_key(|s| s.seq);
+
+        // A crash during append can leave a torn record at the end of the
+        // newest segment. Cut it off so new batches are not appended after it.
+        if let Some(newest) = segments.last_mut() {
+            let data = tokio::fs::read(&newest.path).await?;
+            let framed = framed_len(&data) as u64;
+            if framed < newest.bytes {
+                warn!(
+                    "Discarding {} bytes of a torn record at the end of {}",
+                    newest.bytes - framed,
+                    newest.path.display()
+                );
+                let file = tokio::fs::OpenOptions::new().write(true).open(&newest.path).await?;
+                file.set_len(framed).await?;
+                newest.bytes = framed;
+            }
+        }
+
+        let total_bytes = segments.iter().map(|s| s.bytes).sum();
+        let next_seq = segments.last().map(|s| s.seq + 1).unwrap_or(0);
+
+        if !segments.is_empty() {
+            info!(
+                "Found {} spilled segment(s) ({} bytes) in {}",
+                segments.len(),
+                total_bytes,
+                directory.display()
+            );
+        }
+
+        Ok(Self {
+            directory,
+            max_bytes: config.max_bytes,
+            segment_bytes: config.segment_bytes,
+            total_bytes: AtomicU64::new(total_bytes),
+            state: Mutex::new(SpillState {
+                segments: segments.into(),
+                next_seq,
+            }),
+        })
+    }
+
+    /// Bytes currently held on disk.
+    pub fn bytes(&self) -> u64 {
+        self.total_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes() == 0
+    }
+
+    /// Appends a batch, failing if it would push the buffer past `max_bytes`.
+    pub async fn append(&self, batch: &[ProcessedMessage]) -> Result<()> {
+        let payload = serde_json::to_vec(batch)?;
+        let record_len = RECORD_HEADER_LEN + payload.len() as u64;
+
+        let mut state = self.state.lock().await;
+
+        if self.bytes() + record_len > self.max_bytes {
+            return Err(anyhow::anyhow!(
+                "Spill buffer full ({} of {} bytes used)",
+                self.bytes(),
+                self.max_bytes
+            ));
+        }
+
+        let needs_new_segment = match state.segments.back() {
+            Some(last) => last.bytes + record_len > self.segment_bytes,
+            None => true,
+        };
+        if needs_new_segment {
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.segments.push_back(Segment {
+                seq,
+                path: self.directory.join(format!("{:020}.{}", seq, SEGMENT_EXTENSION)),
+                bytes: 0,
+            });
+        }
+
+        let segment = state.segments.back_mut().expect("segment was just ensured");
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&segment.path)
+            .await?;
+        file.write_all(&(payload.len() as u32).to_le_bytes()).await?;
+        file.write_all(&payload).await?;
+        file.sync_data().await?;
+
+        segment.bytes += record_len;
+        self.total_bytes.fetch_add(record_len, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Replays spilled batches into `storage`, oldest first. Stops at the
+    /// first write failure and returns the number of batches replayed.
+    ///
+    /// A segment is only deleted after all of its batches were written, so a
+    /// failure mid-segment replays the earlier batches again next time; the
+    /// storage upsert on message id keeps that idempotent.
+    ///
+    /// A segment with a corrupt record is moved aside with the
+    /// `QUARANTINE_EXTENSION` and replay fails, so that its batches are never
+    /// dropped silently. The next replay carries on with the following segment.
+    pub async fn replay(&self, storage: &dyn StorageBackend) -> Result<usize> {
+        let mut state = self.state.lock().await;
+        let mut replayed = 0;
+
+        while let Some(segment) = state.segments.front() {
+            let path = segment.path.clone();
+            let newest = state.segments.len() == 1;
+            let data = tokio::fs::read(&path).await?;
+            for (end, batch) in decode_records(&data) {
+                let batch = match batch {
+                    Ok(batch) => batch,
+                    // Only the record being appended when the process died
+                    // can be torn, and that is the last one of the newest segment
+                    Err(e) if newest && end == data.len() => {
+                        warn!("Discarding torn record at the end of {}: {}", path.display(), e);
+                        break;
+                    }
+                    Err(e) => {
+                        let quarantined = path.with_extension(QUARANTINE_EXTENSION);
+                        tokio::fs::rename(&path, &quarantined).await.map_err(|re| {
+                            anyhow::anyhow!("Failed to quarantine spill segment {}: {}", path.display(), re)
+                        })?;
+                        let segment = state.segments.pop_front().expect("front segment exists");
+                        self.total_bytes.fetch_sub(segment.bytes, Ordering::Relaxed);
+                        return Err(anyhow::anyhow!(
+                            "Corrupt record in spill segment {}, moved it to {}: {}",
+                            path.display(),
+                            quarantined.display(),
+                            e
+                        ));
+                    }
+                };
+                storage.store_processed_messages(&batch).await?;
+                replayed += 1;
+            }
+
+            let segment = state.segments.pop_front().expect("front segment exists");
+            if let Err(e) = tokio::fs::remove_file(&segment.path).await {
+                error!("Failed to remove replayed segment {}: {}", segment.path.display(), e);
+            }
+            self.total_bytes.fetch_sub(segment.bytes, Ordering::Relaxed);
+        }
+
+        Ok(replayed)
+    }
+}
+
+/// Decodes each record in `data` along with the offset where it ends.
+fn decode_records(data: &[u8]) -> impl Iterator<Item = (usize, Result<Vec<ProcessedMessage>>)> + '_ {
+    let mut position = 0usize;
+    std::iter::from_fn(move || {
+        if position >= data.len() {
+            return None;
+        }
+        let header_end = position + RECORD_HEADER_LEN as usize;
+        if header_end > data.len() {
+            position = data.len();
+            return Some((position, Err(anyhow::anyhow!("truncated record header"))));
+        }
+        let len = u32::from_le_bytes(data[position..header_end].try_into().expect("4-byte header")) as usize;
+        let record_end = header_end + len;
+        if record_end > data.len() {
+            position = data.len();
+            return Some((position, Err(anyhow::anyhow!("truncated record payload"))));
+        }
+        position = record_end;
+        Some((position, serde_json::from_slice(&data[header_end..record_end]).map_err(Into::into)))
+    })
+}
+
+/// Length of the whole records at the start of `data`, ignoring whether
+/// their payloads decode.
+fn framed_len(data: &[u8]) -> usize {
+    let mut position = 0usize;
+    while let Some(header) = data.get(position..position + RECORD_HEADER_LEN as usize) {
+        let len = u32::from_le_bytes(header.try_into().expect("4-byte header")) as usize;
+        let record_end = position + header.len() + len;
+        if record_end > data.len() {
+            break;
+        }
+        position = record_end;
+    }
+    position
+}
+
+/// Database writer front-end: writes batches straight to storage while it is
+/// healthy and spills them to disk while it is not. Once anything is spilled,
+/// new batches queue behind it until replay catches up, preserving order.
+pub struct BufferedWriter {
+    storage: Arc<dyn StorageBackend>,
+    spill: Option<SpillBuffer>,
+    metrics: Arc<Metrics>,
+}
+
+impl BufferedWriter {
+    pub async fn new(storage: Arc<dyn StorageBackend>, config: &SpillConfig, metrics: Arc<Metrics>) -> Result<Self> {
+        let spill = if config.enabled {
+            let spill = SpillBuffer::open(config).await?;
+            metrics.set_spill_bytes(spill.bytes() as i64);
+            Some(spill)
+        } else {
+            None
+        };
+
+        Ok(Self { storage, spill, metrics })
+    }
+
+    /// Bytes waiting to be replayed. While there are any, new batches are
+    /// spilled rather than written.
+    pub fn spilled_bytes(&self) -> u64 {
+        self.spill.as_ref().map_or(0, SpillBuffer::bytes)
+    }
+
+    /// Returns `true` once the batch is committed to storage and `false` if it
+    /// was spilled to disk instead.
+    pub async fn write(&self, batch: &[ProcessedMessage]) -> Result<bool> {
+        if batch.is_empty() {
+            return Ok(true);
+        }
+
+        let spill = match &self.spill {
+            Some(spill) => spill,
+            None => {
+                let duplicates = self.storage.store_processed_messages(batch).await?;
+                self.metrics.increment_duplicate_writes(duplicates);
+                return Ok(true);
+            }
+        };
+
+        if spill.is_empty() {
+            match self.storage.store_processed_messages(batch).await {
+                Ok(duplicates) => {
+                    self.metrics.increment_duplicate_writes(duplicates);
+                    return Ok(true);
+                }
+                Err(e) => {
+                    warn!("Database write failed, spilling batch of {} to disk: {}", batch.len(), e);
+                    self.metrics.increment_database_errors();
+                }
+            }
+        }
+
+        spill.append(batch).await?;
+        self.metrics.increment_spilled_batches();
+        self.metrics.set_spill_bytes(spill.bytes() as i64);
+        Ok(false)
+    }
+
+    /// Drains the spill buffer if the database is reachable again.
+    pub async fn replay(&self) -> Result<()> {
+        let spill = match &self.spill {
+            Some(spill) if !spill.is_empty() => spill,
+            _ => return Ok(()),
+        };
+
+        if !self.storage.health_check().await? {
+            return Ok(());
+        }
+
+        let result = spill.replay(self.storage.as_ref()).await;
+        self.metrics.set_spill_bytes(spill.bytes() as i64);
+
+        match result {
+            Ok(replayed) => {
+                if replayed > 0 {
+                    info!("Replayed {} spilled batch(es) into the database", replayed);
+                    self.metrics.increment_replayed_batches(replayed as u64);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Spill replay interrupted: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::ProcessingMetadata;
+    use crate::storage::MemoryStorage;
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn spill_config(dir: &std::path::Path, max_bytes: u64) -> SpillConfig {
+        SpillConfig {
+            enabled: true,
+            directory: dir.to_string_lossy().to_string(),
+            max_bytes,
+            segment_bytes: 512,
+        }
+    }
+
+    fn batch(start: i64, len: i64) -> Vec<ProcessedMessage> {
+        (start..start + len)
+            .map(|i| ProcessedMessage {
+                id: format!("id-{}", i),
+                original_message: json!({"i": i}),
+                processed_message: json!({"i": i}),
+                processing_metadata: ProcessingMetadata {
+                    tenant_id: "tenant-a".to_string(),
+                    processed_at: Utc::now(),
+                    processor_version: "1.0.0".to_string(),
+                    source_topic: "metrics".to_string(),
+                    partition: 0,
+                    offset: i,
+                    traceparent: None,
+                },
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_spill_survives_reopen_and_replays_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = spill_config(dir.path(), 1024 * 1024);
+
+        {
+            let spill = SpillBuffer::open(&config).await.unwrap();
+            for i in 0..4 {
+                spill.append(&batch(i * 3, 3)).await.unwrap();
+            }
+            assert!(!spill.is_empty());
+        }
+
+        let spill = SpillBuffer::open(&config).await.unwrap();
+        let storage = MemoryStorage::with_capacity(100);
+        assert_eq!(spill.replay(&storage).await.unwrap(), 4);
+        assert!(spill.is_empty());
+
+        for i in 0..12 {
+            let id = format!("id-{}", i);
+            assert!(storage.get_processed_message("tenant-a", &id).await.unwrap().is_some());
+        }
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_spill_rejects_batches_past_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        let spill = SpillBuffer::open(&spill_config(dir.path(), 300)).await.unwrap();
+
+        spill.append(&batch(0, 1)).await.unwrap();
+        assert!(spill.append(&batch(1, 5)).await.is_err());
+    }
+
+    fn segment_paths(dir: &std::path::Path) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+        paths.sort();
+        paths
+    }
+
+    #[tokio::test]
+    async fn test_spill_cuts_off_a_torn_tail_on_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = spill_config(dir.path(), 1024 * 1024);
+
+        {
+            let spill = SpillBuffer::open(&config).await.unwrap();
+            spill.append(&batch(0, 1)).await.unwrap();
+        }
+        // The process died while writing the next record
+        let mut data = std::fs::read(&segment_paths(dir.path())[0]).unwrap();
+        data.extend_from_slice(&100u32.to_le_bytes());
+        data.extend_from_slice(b"[{\"id\"");
+        std::fs::write(&segment_paths(dir.path())[0], &data).unwrap();
+
+        let spill = SpillBuffer::open(&config).await.unwrap();
+        spill.append(&batch(1, 1)).await.unwrap();
+
+        let storage = MemoryStorage::with_capacity(100);
+        assert_eq!(spill.replay(&storage).await.unwrap(), 2);
+        assert!(spill.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_spill_quarantines_segments_with_corrupt_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let spill = SpillBuffer::open(&spill_config(dir.path(), 1024 * 1024)).await.unwrap();
+        // Each of these batches is larger than a segment, so gets its own
+        for i in 0..3 {
+            spill.append(&batch(i * 3, 3)).await.unwrap();
+        }
+
+        let corrupt = segment_paths(dir.path())[0].clone();
+        let mut data = std::fs::read(&corrupt).unwrap();
+        data[RECORD_HEADER_LEN as usize] = b'#';
+        std::fs::write(&corrupt, &data).unwrap();
+
+        let storage = MemoryStorage::with_capacity(100);
+        assert!(spill.replay(&storage).await.is_err());
+        assert!(corrupt.with_extension(QUARANTINE_EXTENSION).exists());
+        assert!(!corrupt.exists());
+
+        assert_eq!(spill.replay(&storage).await.unwrap(), 2);
+        assert!(spill.is_empty());
+        assert!(storage.get_processed_message("tenant-a", "id-0").await.unwrap().is_none());
+        assert!(storage.get_processed_message("tenant-a", "id-8").await.unwrap().is_some());
+    }
+}
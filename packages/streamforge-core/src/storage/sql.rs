@@ -0,0 +1,326 @@
+use super::export::{self, ExportDataset, ExportFormat, ExportRequest};
+use super::lifecycle;
+use super::{FieldCipher, StorageBackend};
+use crate::config::SqlConfig;
+use anyhow::Result;
+use arrow::array::{Array, ArrayRef, StringArray};
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Utc};
+use datafusion::datasource::file_format::parquet::ParquetFormat;
+use datafusion::datasource::listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl};
+use datafusion::datasource::{MemTable, TableProvider};
+use datafusion::execution::context::SQLOptions;
+use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
+use datafusion::prelude::{col, lit, SessionConfig, SessionContext};
+use datafusion::scalar::ScalarValue;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Datasets a query can read, each as a table of its name with the columns
+/// of its exports.
+const DATASETS: [ExportDataset; 3] = [ExportDataset::Metrics, ExportDataset::Logs, ExportDataset::Traces];
+
+/// What a query can see: one tenant's rows in `[start_time, end_time)`.
+#[derive(Debug, Clone)]
+pub struct SqlScope {
+    pub tenant_id: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// Also read rows that are still in the database. They are loaded into
+    /// memory, up to `sql.max_hot_rows` per table, so this suits short
+    /// ranges; archived segments are scanned in place.
+    pub include_hot: bool,
+}
+
+/// Result batches in order. The stream fails once the query has run for
+/// `sql.timeout`.
+pub type RecordBatchStream = BoxStream<'static, Result<RecordBatch>>;
+
+/// A DataFusion session over the `metrics`, `logs` and `traces` of one
+/// scope. Only the scope's archived segments and rows are registered, so no
+/// query can reach another tenant's data, and statements that would create
+/// tables or write files are refused. Segments keep encrypted fields
+/// encrypted; with the `cipher` of `database.encryption`, results are
+/// decrypted, which only works on attributes selected whole.
+pub struct SqlSession {
+    context: SessionContext,
+    max_rows: usize,
+    timeout: Duration,
+    cipher: Option<Arc<FieldCipher>>,
+}
+
+impl SqlSession {
+    pub async fn open(
+        storage: &dyn StorageBackend,
+        config: &SqlConfig,
+        scope: &SqlScope,
+        cipher: Option<Arc<FieldCipher>>,
+    ) -> Result<Self> {
+        if scope.end_time <= scope.start_time {
+            return Err(anyhow::anyhow!("Query end_time must be after start_time"));
+        }
+
+        let runtime = RuntimeEnv::new(RuntimeConfig::new().with_memory_limit(config.memory_limit_bytes, 1.0))
+            .map_err(|e| anyhow::anyhow!("Failed to start query engine: {}", e))?;
+        let context = SessionContext::new_with_config_rt(SessionConfig::new(), Arc::new(runtime));
+
+        for dataset in DATASETS {
+            let schema = export::schema(dataset);
+            let segments = storage
+                .list_archived_segments(&scope.tenant_id, dataset, scope.start_time, scope.end_time)
+                .await?;
+            let archived: Arc<dyn TableProvider> = if segments.is_empty() {
+                Arc::new(MemTable::try_new(schema.clone(), vec![Vec::new()])?)
+            } else {
+                let mut urls = Vec::with_capacity(segments.len());
+                for segment in &segments {
+                    let url = ListingTableUrl::parse(&segment.location)
+                        .map_err(|e| anyhow::anyhow!("Invalid segment location {}: {}", segment.location, e))?;
+                    let bucket = segment.location.strip_prefix("s3://").and_then(|rest| rest.split('/').next());
+                    if let Some(bucket) = bucket {
+                        let store = export::s3_store(bucket)?;
+                        context.runtime_env().register_object_store(url.object_store().as_ref(), Arc::new(store));
+                    }
+                    urls.push(url);
+                }
+                let options = ListingOptions::new(Arc::new(ParquetFormat::default())).with_file_extension(".parquet");
+                let config = ListingTableConfig::new_with_multi_paths(urls)
+                    .with_listing_options(options)
+                    .with_schema(schema.clone());
+                Arc::new(ListingTable::try_new(config)?)
+            };
+
+            let hot = if scope.include_hot {
+                let request = ExportRequest {
+                    tenant_id: scope.tenant_id.clone(),
+                    dataset,
+                    format: ExportFormat::Parquet,
+                    start_time: scope.start_time,
+                    end_time: scope.end_time,
+                    destination: String::new(),
+                    metric_name: None,
+                    log_level: None,
+                    service_name: None,
+                };
+                export::record_batches(storage, &request, config.max_hot_rows).await?
+            } else {
+                Vec::new()
+            };
+
+            // Segments cover whole windows, so their rows are cut to the scope
+            let (_, time) = lifecycle::table(dataset);
+            let in_scope = col(time)
+                .gt_eq(lit(timestamp(scope.start_time)))
+                .and(col(time).lt(lit(timestamp(scope.end_time))));
+            let table = context
+                .read_table(archived)?
+                .filter(in_scope)?
+                .union(context.read_table(Arc::new(MemTable::try_new(schema, vec![hot])?))?)?;
+            context.register_table(dataset.as_str(), table.into_view())?;
+        }
+
+        Ok(Self {
+            context,
+            max_rows: config.max_rows,
+            timeout: config.timeout,
+            cipher,
+        })
+    }
+
+    /// Plans `sql` and starts running it. Errors here are the query's: it
+    /// does not parse, names unknown columns, or is not a query.
+    pub async fn execute(&self, sql: &str) -> Result<RecordBatchStream> {
+        let options = SQLOptions::new()
+            .with_allow_ddl(false)
+            .with_allow_dml(false)
+            .with_allow_statements(false);
+        let frame = self.context.sql_with_options(sql, options).await?.limit(0, Some(self.max_rows))?;
+        let mut batches = frame.execute_stream().await?;
+
+        let timeout = self.timeout;
+        let deadline = tokio::time::Instant::now() + timeout;
+        let cipher = self.cipher.clone();
+        let stream = async_stream::try_stream! {
+            while let Some(batch) = tokio::time::timeout_at(deadline, batches.next())
+                .await
+                .map_err(|_| anyhow::anyhow!("Query timed out after {:?}", timeout))?
+            {
+                let batch = batch.map_err(|e| anyhow::anyhow!("Query failed: {}", e))?;
+                yield match &cipher {
+                    Some(cipher) => decrypt_batch(cipher, batch)?,
+                    None => batch,
+                };
+            }
+        };
+        Ok(stream.boxed())
+    }
+}
+
+/// `batch` with the encrypted values in its text columns decrypted.
+fn decrypt_batch(cipher: &FieldCipher, batch: RecordBatch) -> Result<RecordBatch> {
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(batch.num_columns());
+    for column in batch.columns() {
+        let Some(text) = column.as_any().downcast_ref::<StringArray>() else {
+            columns.push(column.clone());
+            continue;
+        };
+        let decrypted = text
+            .iter()
+            .map(|value| {
+                value
+                    .map(|value| Ok(cipher.decrypt_text(value)?.unwrap_or_else(|| value.to_string())))
+                    .transpose()
+            })
+            .collect::<Result<StringArray>>()?;
+        columns.push(Arc::new(decrypted));
+    }
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+fn timestamp(at: DateTime<Utc>) -> ScalarValue {
+    ScalarValue::TimestampMicrosecond(Some(at.timestamp_micros()), Some("UTC".into()))
+}
+
+/// `batch` as an Arrow IPC stream of its own, schema included, so every
+/// batch can be decoded without the others.
+pub fn to_arrow_ipc(batch: &RecordBatch) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut writer = arrow::ipc::writer::StreamWriter::try_new(&mut buffer, &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()?;
+    drop(writer);
+    Ok(buffer)
+}
+
+/// Each row of `batch` as a JSON object; null columns are left out.
+pub fn to_json_rows(batch: &RecordBatch) -> Result<Vec<String>> {
+    let rows = arrow::json::writer::record_batches_to_json_rows(&[batch])?;
+    Ok(rows.into_iter().map(|row| serde_json::Value::Object(row).to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EncryptionConfig, LifecycleConfig, LifecycleRule};
+    use crate::storage::{EncryptedStorage, MemoryStorage, SegmentReader};
+    use base64::Engine;
+
+    #[tokio::test]
+    async fn test_query_spans_archived_and_hot_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let lifecycle = LifecycleConfig {
+            enabled: true,
+            destination: dir.path().display().to_string(),
+            ..LifecycleConfig::default()
+        };
+        let rule = LifecycleRule {
+            dataset: ExportDataset::Metrics,
+            archive_after_days: 7,
+        };
+        let now = DateTime::parse_from_rfc3339("2024-01-31T12:00:00Z").unwrap().with_timezone(&Utc);
+
+        let storage = MemoryStorage::with_capacity(100);
+        for (tenant_id, days_ago) in [("acme", 10), ("acme", 1), ("globex", 10)] {
+            let at = now - chrono::Duration::days(days_ago);
+            storage
+                .store_metric(tenant_id, "cpu_usage", days_ago as f64, "gauge", None, at)
+                .await
+                .unwrap();
+        }
+        lifecycle::archive(&storage, &lifecycle, &rule, None, now).await.unwrap();
+
+        let mut scope = SqlScope {
+            tenant_id: "acme".to_string(),
+            start_time: now - chrono::Duration::days(30),
+            end_time: now,
+            include_hot: true,
+        };
+        let sql = "SELECT metric_name, SUM(metric_value) AS total FROM metrics GROUP BY metric_name";
+        let query = |scope: SqlScope| {
+            let storage = storage.clone();
+            async move {
+                let session = SqlSession::open(&storage, &SqlConfig::default(), &scope, None).await.unwrap();
+                let batches: Vec<_> = session.execute(sql).await.unwrap().collect().await;
+                batches
+                    .into_iter()
+                    .flat_map(|batch| to_json_rows(&batch.unwrap()).unwrap())
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(query(scope.clone()).await, vec![r#"{"metric_name":"cpu_usage","total":11.0}"#]);
+        scope.include_hot = false;
+        assert_eq!(query(scope).await, vec![r#"{"metric_name":"cpu_usage","total":10.0}"#]);
+    }
+
+    #[tokio::test]
+    async fn test_archived_fields_stay_encrypted_until_queried() {
+        let dir = tempfile::tempdir().unwrap();
+        let lifecycle = LifecycleConfig {
+            enabled: true,
+            destination: dir.path().display().to_string(),
+            ..LifecycleConfig::default()
+        };
+        let rule = LifecycleRule {
+            dataset: ExportDataset::Logs,
+            archive_after_days: 7,
+        };
+        let encryption = EncryptionConfig {
+            enabled: true,
+            key: Some(base64::engine::general_purpose::STANDARD.encode([3; 32])),
+            fields: vec!["user.email".to_string()],
+            ..EncryptionConfig::default()
+        };
+        let cipher = Arc::new(FieldCipher::new(&encryption).unwrap());
+        let storage = EncryptedStorage::new(
+            Arc::new(MemoryStorage::with_capacity(10)),
+            FieldCipher::new(&encryption).unwrap(),
+        );
+        let now = DateTime::parse_from_rfc3339("2024-01-31T12:00:00Z").unwrap().with_timezone(&Utc);
+        let attributes = serde_json::json!({"user": {"email": "ada@example.com"}});
+        let at = now - chrono::Duration::days(10);
+        storage
+            .store_log("acme", "info", "signed in", None, None, None, None, Some(attributes), at)
+            .await
+            .unwrap();
+
+        let segments = lifecycle::archive(&storage, &lifecycle, &rule, Some(&cipher), now).await.unwrap();
+        let mut reader = SegmentReader::open(&segments[0].location, 10).await.unwrap();
+        let rows = reader.next_batch().unwrap().unwrap();
+        assert!(rows[0]["fields"]["user"]["email"].as_str().unwrap().starts_with("sfenc:v1:"));
+
+        let scope = SqlScope {
+            tenant_id: "acme".to_string(),
+            start_time: now - chrono::Duration::days(30),
+            end_time: now,
+            include_hot: false,
+        };
+        let session = SqlSession::open(&storage, &SqlConfig::default(), &scope, Some(cipher)).await.unwrap();
+        let batches: Vec<_> = session.execute("SELECT attributes FROM logs").await.unwrap().collect().await;
+        let rows = to_json_rows(batches[0].as_ref().unwrap()).unwrap();
+        assert_eq!(rows, vec![r#"{"attributes":"{\"user\":{\"email\":\"ada@example.com\"}}"}"#]);
+    }
+
+    #[tokio::test]
+    async fn test_statements_other_than_queries_are_refused() {
+        let storage = MemoryStorage::with_capacity(10);
+        let now = Utc::now();
+        let scope = SqlScope {
+            tenant_id: "acme".to_string(),
+            start_time: now - chrono::Duration::hours(1),
+            end_time: now,
+            include_hot: false,
+        };
+        let session = SqlSession::open(&storage, &SqlConfig::default(), &scope, None).await.unwrap();
+
+        assert!(session.execute("SELECT COUNT(*) FROM logs").await.is_ok());
+        assert!(session
+            .execute("CREATE EXTERNAL TABLE secrets STORED AS CSV LOCATION '/etc/passwd'")
+            .await
+            .is_err());
+        assert!(session.execute("COPY metrics TO '/tmp/metrics.parquet'").await.is_err());
+        assert!(session.execute("SELECT * FROM missing").await.is_err());
+    }
+}
@@ -0,0 +1,2075 @@
+use super::codec;
+use super::compaction::{self, CompactionStats};
+use super::lifecycle;
+use super::{
+    assemble_trace, page_of_audit_entries, page_of_pipelines, AlertDelivery, ArchivedSegment, AuditEntry,
+    AuditFilter, ErasureDataset, ErasureMode, ErasureSelector, ExportDataset, JobLease, LogRecord, MetricRecord, Page,
+    PageCursor, PoolStatus, ServiceStatusRecord, Silence, StorageBackend, Trace, TraceSpan, TraceSummary,
+};
+use crate::config::{CompressionConfig, Config, MetricRetentionRule, PayloadCodec};
+use crate::pipeline::PipelineDefinition;
+use crate::processor::{ProcessedMessage, ProcessingMetadata};
+use crate::rbac::RoleBinding;
+use crate::schemas::SchemaVersion;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow};
+use sqlx::{Row, SqlitePool};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// SQLite implementation of [`StorageBackend`] for local development and CI.
+///
+/// Uses a compatible subset of the Postgres schema: JSONB columns are stored
+/// as TEXT, UUIDs as TEXT, and GIN indexes/triggers are omitted.
+#[derive(Clone)]
+pub struct SqliteStorage {
+    pool: SqlitePool,
+    compression: CompressionConfig,
+}
+
+impl SqliteStorage {
+    pub async fn new(config: &Config) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(&config.database.url)
+            .map_err(|e| anyhow::anyhow!("Invalid SQLite database URL: {}", e))?
+            .create_if_missing(true);
+
+        // Every connection to `sqlite::memory:` opens its own empty database,
+        // so in-memory databases must be served by a single connection.
+        let max_connections = if config.database.url.contains(":memory:") {
+            1
+        } else {
+            config.database.max_connections
+        };
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to database: {}", e))?;
+
+        info!("SQLite connection pool created successfully");
+
+        Self::init_schema(&pool).await?;
+
+        Ok(Self {
+            pool,
+            compression: config.database.compression.clone(),
+        })
+    }
+
+    async fn init_schema(pool: &SqlitePool) -> Result<()> {
+        let schema_sql = r#"
+            CREATE TABLE IF NOT EXISTS processed_messages (
+                id TEXT PRIMARY KEY,
+                tenant_id TEXT NOT NULL DEFAULT 'default',
+                original_message TEXT,
+                processed_message TEXT,
+                original_message_blob BLOB,
+                processed_message_blob BLOB,
+                processed_at TEXT NOT NULL,
+                processor_version TEXT NOT NULL,
+                source_topic TEXT NOT NULL,
+                partition INTEGER NOT NULL,
+                "offset" INTEGER NOT NULL,
+                traceparent TEXT,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP
+            );
+
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_processed_messages_source_position
+            ON processed_messages (source_topic, partition, "offset");
+
+            CREATE INDEX IF NOT EXISTS idx_processed_messages_tenant_processed_at_id
+            ON processed_messages (tenant_id, processed_at, id);
+
+            CREATE INDEX IF NOT EXISTS idx_processed_messages_tenant_topic_processed_at_id
+            ON processed_messages (tenant_id, source_topic, processed_at, id);
+
+            CREATE TABLE IF NOT EXISTS metrics (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                tenant_id TEXT NOT NULL DEFAULT 'default',
+                metric_name TEXT NOT NULL,
+                metric_value REAL NOT NULL,
+                metric_type TEXT NOT NULL,
+                tags TEXT,
+                timestamp TEXT NOT NULL,
+                resolution_seconds INTEGER,
+                sample_count INTEGER,
+                min_value REAL,
+                max_value REAL,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_metrics_tenant_name_timestamp
+            ON metrics (tenant_id, metric_name, timestamp);
+
+            CREATE TABLE IF NOT EXISTS logs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                tenant_id TEXT NOT NULL DEFAULT 'default',
+                log_level TEXT NOT NULL,
+                message TEXT NOT NULL,
+                service_name TEXT,
+                host_name TEXT,
+                trace_id TEXT,
+                span_id TEXT,
+                attributes TEXT,
+                timestamp TEXT NOT NULL,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_logs_tenant_timestamp
+            ON logs (tenant_id, timestamp);
+
+            CREATE INDEX IF NOT EXISTS idx_logs_trace_id
+            ON logs (trace_id);
+
+            CREATE TABLE IF NOT EXISTS traces (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                tenant_id TEXT NOT NULL DEFAULT 'default',
+                trace_id TEXT NOT NULL,
+                span_id TEXT NOT NULL,
+                parent_span_id TEXT,
+                name TEXT NOT NULL,
+                service_name TEXT,
+                start_time TEXT NOT NULL,
+                end_time TEXT,
+                duration_ms INTEGER,
+                status TEXT,
+                attributes TEXT,
+                events TEXT,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_traces_tenant_trace_id
+            ON traces (tenant_id, trace_id);
+
+            CREATE INDEX IF NOT EXISTS idx_traces_tenant_service_name_start_time
+            ON traces (tenant_id, service_name, start_time);
+
+            CREATE INDEX IF NOT EXISTS idx_traces_duration_ms
+            ON traces (duration_ms);
+
+            CREATE TABLE IF NOT EXISTS pipelines (
+                tenant_id TEXT NOT NULL,
+                id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT NOT NULL DEFAULT '',
+                definition TEXT NOT NULL,
+                version INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                PRIMARY KEY (tenant_id, id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_pipelines_tenant_created_at_id
+            ON pipelines (tenant_id, created_at DESC, id DESC);
+
+            CREATE TABLE IF NOT EXISTS job_leases (
+                pipeline_id TEXT PRIMARY KEY,
+                job_id TEXT,
+                owner TEXT NOT NULL,
+                endpoint TEXT NOT NULL,
+                config TEXT NOT NULL,
+                limits TEXT NOT NULL,
+                acquired_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_job_leases_job_id ON job_leases (job_id);
+            CREATE INDEX IF NOT EXISTS idx_job_leases_expires_at ON job_leases (expires_at);
+
+            CREATE TABLE IF NOT EXISTS service_statuses (
+                tenant_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                status TEXT NOT NULL,
+                uptime REAL NOT NULL,
+                response_time REAL NOT NULL,
+                requests_per_second REAL NOT NULL,
+                last_seen TEXT NOT NULL,
+                last_check TEXT NOT NULL,
+                PRIMARY KEY (tenant_id, name)
+            );
+
+            CREATE TABLE IF NOT EXISTS alert_deliveries (
+                tenant_id TEXT NOT NULL,
+                id TEXT NOT NULL,
+                alert_id TEXT NOT NULL,
+                channel TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                last_error TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                PRIMARY KEY (tenant_id, id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_alert_deliveries_tenant_alert_id
+            ON alert_deliveries (tenant_id, alert_id);
+            CREATE INDEX IF NOT EXISTS idx_alert_deliveries_tenant_created_at
+            ON alert_deliveries (tenant_id, created_at DESC);
+
+            CREATE TABLE IF NOT EXISTS silences (
+                tenant_id TEXT NOT NULL,
+                id TEXT NOT NULL,
+                matchers TEXT NOT NULL,
+                starts_at TEXT NOT NULL,
+                ends_at TEXT NOT NULL,
+                comment TEXT NOT NULL,
+                created_by TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (tenant_id, id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_silences_ends_at ON silences (ends_at);
+
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id TEXT PRIMARY KEY,
+                occurred_at TEXT NOT NULL,
+                actor TEXT NOT NULL,
+                source TEXT NOT NULL,
+                action TEXT NOT NULL,
+                tenant_id TEXT,
+                target TEXT NOT NULL,
+                details TEXT NOT NULL,
+                outcome TEXT NOT NULL,
+                error TEXT
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_audit_log_occurred_at ON audit_log (occurred_at DESC, id DESC);
+            CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log (actor, occurred_at DESC);
+
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_log is append-only');
+            END;
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_log is append-only');
+            END;
+
+            CREATE TABLE IF NOT EXISTS schema_versions (
+                tenant_id TEXT NOT NULL,
+                topic TEXT NOT NULL,
+                version INTEGER NOT NULL,
+                fields TEXT NOT NULL,
+                changes TEXT NOT NULL,
+                violations TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (tenant_id, topic, version)
+            );
+
+            CREATE TABLE IF NOT EXISTS role_bindings (
+                subject TEXT NOT NULL,
+                role TEXT NOT NULL,
+                tenants TEXT NOT NULL,
+                created_by TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (subject, role)
+            );
+
+            CREATE TABLE IF NOT EXISTS archived_segments (
+                location TEXT PRIMARY KEY,
+                tenant_id TEXT NOT NULL,
+                dataset TEXT NOT NULL,
+                start_time TEXT NOT NULL,
+                end_time TEXT NOT NULL,
+                row_count INTEGER NOT NULL,
+                archived_at TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_archived_segments_tenant_dataset_start_time
+            ON archived_segments (tenant_id, dataset, start_time);
+        "#;
+
+        sqlx::raw_sql(schema_sql)
+            .execute(pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize database schema: {}", e))?;
+
+        info!("SQLite schema initialized successfully");
+        Ok(())
+    }
+
+    fn processed_message_from_row(row: &SqliteRow) -> Result<ProcessedMessage> {
+        let original: Option<String> = row.get("original_message");
+        let processed: Option<String> = row.get("processed_message");
+
+        Ok(ProcessedMessage {
+            id: row.get("id"),
+            original_message: codec::from_columns(Self::json_value(original), row.get("original_message_blob"))?,
+            processed_message: codec::from_columns(Self::json_value(processed), row.get("processed_message_blob"))?,
+            processing_metadata: ProcessingMetadata {
+                tenant_id: row.get("tenant_id"),
+                processed_at: row.get("processed_at"),
+                processor_version: row.get("processor_version"),
+                source_topic: row.get("source_topic"),
+                partition: row.get("partition"),
+                offset: row.get("offset"),
+                traceparent: row.get("traceparent"),
+            },
+        })
+    }
+
+    fn page_of_processed_messages(rows: Vec<SqliteRow>, limit: i64) -> Result<Page<ProcessedMessage>> {
+        let has_more = rows.len() as i64 > limit;
+
+        let messages = rows
+            .iter()
+            .take(limit as usize)
+            .map(Self::processed_message_from_row)
+            .collect::<Result<Vec<_>>>()?;
+
+        let next_cursor = match messages.last() {
+            Some(last) if has_more => Some(
+                PageCursor {
+                    processed_at: last.processing_metadata.processed_at,
+                    id: last.id.to_string(),
+                }
+                .encode(),
+            ),
+            _ => None,
+        };
+
+        Ok(Page {
+            items: messages,
+            next_cursor,
+        })
+    }
+
+    fn pipeline_from_row(row: &SqliteRow) -> Result<PipelineDefinition> {
+        let definition: String = row.get("definition");
+        Ok(PipelineDefinition {
+            id: row.get("id"),
+            tenant_id: row.get("tenant_id"),
+            name: row.get("name"),
+            description: row.get("description"),
+            spec: serde_json::from_str(&definition)
+                .map_err(|e| anyhow::anyhow!("Failed to decode pipeline definition: {}", e))?,
+            version: row.get("version"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
+    }
+
+    fn job_lease_from_row(row: &SqliteRow) -> Result<JobLease> {
+        let limits: String = row.get("limits");
+        Ok(JobLease {
+            pipeline_id: row.get("pipeline_id"),
+            job_id: row.get("job_id"),
+            owner: row.get("owner"),
+            endpoint: row.get("endpoint"),
+            config: row.get("config"),
+            limits: serde_json::from_str(&limits)
+                .map_err(|e| anyhow::anyhow!("Failed to decode job lease limits: {}", e))?,
+            acquired_at: row.get("acquired_at"),
+            expires_at: row.get("expires_at"),
+        })
+    }
+
+    fn silence_from_row(row: &SqliteRow) -> Result<Silence> {
+        let matchers: String = row.get("matchers");
+        Ok(Silence {
+            id: row.get("id"),
+            tenant_id: row.get("tenant_id"),
+            matchers: serde_json::from_str(&matchers)
+                .map_err(|e| anyhow::anyhow!("Failed to decode silence matchers: {}", e))?,
+            starts_at: row.get("starts_at"),
+            ends_at: row.get("ends_at"),
+            comment: row.get("comment"),
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+        })
+    }
+
+    fn audit_entry_from_row(row: &SqliteRow) -> Result<AuditEntry> {
+        let details: String = row.get("details");
+        Ok(AuditEntry {
+            id: row.get("id"),
+            occurred_at: row.get("occurred_at"),
+            actor: row.get("actor"),
+            source: row.get("source"),
+            action: row.get("action"),
+            tenant_id: row.get("tenant_id"),
+            target: row.get("target"),
+            details: serde_json::from_str(&details)
+                .map_err(|e| anyhow::anyhow!("Failed to decode audit entry details: {}", e))?,
+            outcome: row.get("outcome"),
+            error: row.get("error"),
+        })
+    }
+
+    fn schema_version_from_row(row: &SqliteRow) -> Result<SchemaVersion> {
+        let fields: String = row.get("fields");
+        let changes: String = row.get("changes");
+        let violations: String = row.get("violations");
+        Ok(SchemaVersion {
+            tenant_id: row.get("tenant_id"),
+            topic: row.get("topic"),
+            version: row.get("version"),
+            fields: serde_json::from_str(&fields)
+                .map_err(|e| anyhow::anyhow!("Failed to decode schema fields: {}", e))?,
+            changes: serde_json::from_str(&changes)
+                .map_err(|e| anyhow::anyhow!("Failed to decode schema changes: {}", e))?,
+            violations: serde_json::from_str(&violations)
+                .map_err(|e| anyhow::anyhow!("Failed to decode schema violations: {}", e))?,
+            created_at: row.get("created_at"),
+        })
+    }
+
+    fn role_binding_from_row(row: &SqliteRow) -> Result<RoleBinding> {
+        let tenants: String = row.get("tenants");
+        Ok(RoleBinding {
+            subject: row.get("subject"),
+            role: row.get("role"),
+            tenants: serde_json::from_str(&tenants)
+                .map_err(|e| anyhow::anyhow!("Failed to decode role binding tenants: {}", e))?,
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+        })
+    }
+
+    fn archived_segment_from_row(row: &SqliteRow) -> Result<ArchivedSegment> {
+        let dataset: String = row.get("dataset");
+        let rows: i64 = row.get("row_count");
+        Ok(ArchivedSegment {
+            location: row.get("location"),
+            tenant_id: row.get("tenant_id"),
+            dataset: dataset.parse()?,
+            start_time: row.get("start_time"),
+            end_time: row.get("end_time"),
+            rows: rows as u64,
+            archived_at: row.get("archived_at"),
+        })
+    }
+
+    fn json_text(value: Option<serde_json::Value>) -> Option<String> {
+        value.map(|v| v.to_string())
+    }
+
+    fn json_value(text: Option<String>) -> Option<serde_json::Value> {
+        text.and_then(|t| serde_json::from_str(&t).ok())
+    }
+}
+
+#[async_trait]
+impl StorageBackend for SqliteStorage {
+    async fn store_processed_message(&self, message: &ProcessedMessage) -> Result<()> {
+        self.store_processed_messages(std::slice::from_ref(message)).await?;
+        Ok(())
+    }
+
+    async fn store_processed_messages(&self, messages: &[ProcessedMessage]) -> Result<u64> {
+        if messages.is_empty() {
+            return Ok(0);
+        }
+
+        let existing_sql = r#"
+            SELECT 1 FROM processed_messages
+            WHERE source_topic = ?1 AND partition = ?2 AND "offset" = ?3
+        "#;
+
+        let sql = r#"
+            INSERT INTO processed_messages (
+                id, tenant_id, original_message, processed_message,
+                original_message_blob, processed_message_blob, processed_at,
+                processor_version, source_topic, partition, "offset", traceparent
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            ON CONFLICT (source_topic, partition, "offset") DO UPDATE SET
+                original_message = excluded.original_message,
+                processed_message = excluded.processed_message,
+                original_message_blob = excluded.original_message_blob,
+                processed_message_blob = excluded.processed_message_blob,
+                processed_at = excluded.processed_at,
+                processor_version = excluded.processor_version,
+                traceparent = excluded.traceparent,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE processed_messages.tenant_id = excluded.tenant_id
+        "#;
+
+        let mut transaction = self.pool.begin().await?;
+        let mut duplicates = 0;
+
+        for message in messages {
+            let existing = sqlx::query(existing_sql)
+                .bind(&message.processing_metadata.source_topic)
+                .bind(message.processing_metadata.partition)
+                .bind(message.processing_metadata.offset)
+                .fetch_optional(&mut *transaction)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to look up processed message: {}", e))?;
+            if existing.is_some() {
+                duplicates += 1;
+            }
+
+            let original_blob = codec::encode(&message.original_message, &self.compression)?;
+            let processed_blob = codec::encode(&message.processed_message, &self.compression)?;
+            let original_json = original_blob.is_none().then(|| message.original_message.to_string());
+            let processed_json = processed_blob.is_none().then(|| message.processed_message.to_string());
+
+            sqlx::query(sql)
+                .bind(message.id.to_string())
+                .bind(&message.processing_metadata.tenant_id)
+                .bind(original_json)
+                .bind(processed_json)
+                .bind(original_blob)
+                .bind(processed_blob)
+                .bind(message.processing_metadata.processed_at)
+                .bind(&message.processing_metadata.processor_version)
+                .bind(&message.processing_metadata.source_topic)
+                .bind(message.processing_metadata.partition)
+                .bind(message.processing_metadata.offset)
+                .bind(&message.processing_metadata.traceparent)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to store processed message: {}", e))?;
+        }
+
+        transaction.commit().await?;
+        Ok(duplicates)
+    }
+
+    async fn get_processed_message(&self, tenant_id: &str, id: &str) -> Result<Option<ProcessedMessage>> {
+        let sql = r#"
+            SELECT id, tenant_id, original_message, processed_message,
+                   original_message_blob, processed_message_blob, processed_at,
+                   processor_version, source_topic, partition, "offset", traceparent
+            FROM processed_messages
+            WHERE tenant_id = ?1 AND id = ?2
+        "#;
+
+        let row = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get processed message: {}", e))?;
+
+        row.as_ref().map(Self::processed_message_from_row).transpose()
+    }
+
+    async fn get_processed_messages_by_topic(
+        &self,
+        tenant_id: &str,
+        topic: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        let sql = r#"
+            SELECT id, tenant_id, original_message, processed_message,
+                   original_message_blob, processed_message_blob, processed_at,
+                   processor_version, source_topic, partition, "offset", traceparent
+            FROM processed_messages
+            WHERE tenant_id = ?1 AND source_topic = ?2
+              AND (?3 IS NULL OR (processed_at, id) < (?3, ?4))
+            ORDER BY processed_at DESC, id DESC
+            LIMIT ?5
+        "#;
+
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+        let (after_processed_at, after_id) = PageCursor::split(cursor);
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(topic)
+            .bind(after_processed_at)
+            .bind(after_id)
+            .bind(limit + 1)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get processed messages by topic: {}", e))?;
+
+        Self::page_of_processed_messages(rows, limit)
+    }
+
+    async fn get_processed_messages_by_time_range(
+        &self,
+        tenant_id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<ProcessedMessage>> {
+        let sql = r#"
+            SELECT id, tenant_id, original_message, processed_message,
+                   original_message_blob, processed_message_blob, processed_at,
+                   processor_version, source_topic, partition, "offset", traceparent
+            FROM processed_messages
+            WHERE tenant_id = ?1 AND processed_at >= ?2 AND processed_at <= ?3
+              AND (?4 IS NULL OR (processed_at, id) < (?4, ?5))
+            ORDER BY processed_at DESC, id DESC
+            LIMIT ?6
+        "#;
+
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+        let (after_processed_at, after_id) = PageCursor::split(cursor);
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(after_processed_at)
+            .bind(after_id)
+            .bind(limit + 1)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get processed messages by time range: {}", e))?;
+
+        Self::page_of_processed_messages(rows, limit)
+    }
+
+    async fn store_metric(
+        &self,
+        tenant_id: &str,
+        name: &str,
+        value: f64,
+        metric_type: &str,
+        tags: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        let sql = r#"
+            INSERT INTO metrics (tenant_id, metric_name, metric_value, metric_type, tags, timestamp)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        "#;
+
+        sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(name)
+            .bind(value)
+            .bind(metric_type)
+            .bind(Self::json_text(tags))
+            .bind(timestamp)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store metric: {}", e))?;
+
+        Ok(())
+    }
+
+    async fn store_log(
+        &self,
+        tenant_id: &str,
+        level: &str,
+        message: &str,
+        service_name: Option<&str>,
+        host_name: Option<&str>,
+        trace_id: Option<&str>,
+        span_id: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<()> {
+        let sql = r#"
+            INSERT INTO logs (tenant_id, log_level, message, service_name, host_name, trace_id, span_id, attributes, timestamp)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+        "#;
+
+        sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(level)
+            .bind(message)
+            .bind(service_name)
+            .bind(host_name)
+            .bind(trace_id)
+            .bind(span_id)
+            .bind(Self::json_text(attributes))
+            .bind(timestamp)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store log: {}", e))?;
+
+        Ok(())
+    }
+
+    async fn store_trace(
+        &self,
+        tenant_id: &str,
+        trace_id: &str,
+        span_id: &str,
+        parent_span_id: Option<&str>,
+        name: &str,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: Option<DateTime<Utc>>,
+        duration_ms: Option<i64>,
+        status: Option<&str>,
+        attributes: Option<serde_json::Value>,
+        events: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let sql = r#"
+            INSERT INTO traces (tenant_id, trace_id, span_id, parent_span_id, name, service_name,
+                               start_time, end_time, duration_ms, status, attributes, events)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+        "#;
+
+        sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(trace_id)
+            .bind(span_id)
+            .bind(parent_span_id)
+            .bind(name)
+            .bind(service_name)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(duration_ms)
+            .bind(status)
+            .bind(Self::json_text(attributes))
+            .bind(Self::json_text(events))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to store trace: {}", e))?;
+
+        Ok(())
+    }
+
+    async fn get_metrics(
+        &self,
+        tenant_id: &str,
+        name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<MetricRecord>> {
+        let sql = r#"
+            SELECT metric_name, metric_value, metric_type, tags, timestamp, resolution_seconds
+            FROM metrics
+            WHERE tenant_id = ?1
+              AND (?2 IS NULL OR metric_name = ?2)
+              AND timestamp >= ?3 AND timestamp < ?4
+            ORDER BY timestamp ASC, id ASC
+            LIMIT COALESCE(?5, -1)
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(name)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get metrics: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| MetricRecord {
+                name: row.get("metric_name"),
+                value: row.get("metric_value"),
+                metric_type: row.get("metric_type"),
+                tags: Self::json_value(row.get("tags")),
+                timestamp: row.get("timestamp"),
+                resolution_secs: row.get("resolution_seconds"),
+            })
+            .collect())
+    }
+
+    async fn list_metric_names(&self, tenant_id: &str, limit: i64) -> Result<Vec<String>> {
+        let sql = "SELECT DISTINCT metric_name FROM metrics WHERE tenant_id = ?1 ORDER BY metric_name LIMIT ?2";
+        sqlx::query_scalar(sql)
+            .bind(tenant_id)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list metric names: {}", e))
+    }
+
+    async fn get_logs(
+        &self,
+        tenant_id: &str,
+        level: Option<&str>,
+        service_name: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<LogRecord>> {
+        let sql = r#"
+            SELECT log_level, message, service_name, host_name, trace_id, span_id, attributes, timestamp
+            FROM logs
+            WHERE tenant_id = ?1
+              AND (?2 IS NULL OR log_level = ?2)
+              AND (?3 IS NULL OR service_name = ?3)
+              AND timestamp >= ?4 AND timestamp < ?5
+            ORDER BY timestamp ASC, id ASC
+            LIMIT COALESCE(?6, -1)
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(level)
+            .bind(service_name)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get logs: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| LogRecord {
+                level: row.get("log_level"),
+                message: row.get("message"),
+                service_name: row.get("service_name"),
+                host_name: row.get("host_name"),
+                trace_id: row.get("trace_id"),
+                span_id: row.get("span_id"),
+                attributes: Self::json_value(row.get("attributes")),
+                timestamp: row.get("timestamp"),
+            })
+            .collect())
+    }
+
+    async fn get_trace(&self, tenant_id: &str, trace_id: &str) -> Result<Option<Trace>> {
+        let sql = r#"
+            SELECT span_id, parent_span_id, name, service_name, start_time, end_time,
+                   duration_ms, status, attributes, events
+            FROM traces
+            WHERE tenant_id = ?1 AND trace_id = ?2
+            ORDER BY start_time ASC
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(trace_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get trace: {}", e))?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        let spans = rows
+            .into_iter()
+            .map(|row| TraceSpan {
+                span_id: row.get("span_id"),
+                parent_span_id: row.get("parent_span_id"),
+                name: row.get("name"),
+                service_name: row.get("service_name"),
+                start_time: row.get("start_time"),
+                end_time: row.get("end_time"),
+                duration_ms: row.get("duration_ms"),
+                status: row.get("status"),
+                attributes: Self::json_value(row.get("attributes")),
+                events: Self::json_value(row.get("events")),
+                children: Vec::new(),
+            })
+            .collect();
+
+        Ok(Some(assemble_trace(trace_id, spans)))
+    }
+
+    async fn find_traces(
+        &self,
+        tenant_id: &str,
+        service: Option<&str>,
+        min_duration: Option<Duration>,
+        status: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        limit: Option<i64>,
+    ) -> Result<Vec<TraceSummary>> {
+        // SQLite has no ARRAY_AGG, so the root span is picked with correlated
+        // subqueries instead.
+        let sql = r#"
+            WITH matching AS (
+                SELECT DISTINCT trace_id
+                FROM traces
+                WHERE tenant_id = ?1
+                  AND start_time >= ?2 AND start_time <= ?3
+                  AND (?4 IS NULL OR service_name = ?4)
+                  AND (?5 IS NULL OR duration_ms >= ?5)
+                  AND (?6 IS NULL OR status = ?6)
+            )
+            SELECT t.trace_id,
+                   (SELECT r.service_name FROM traces r WHERE r.tenant_id = ?1 AND r.trace_id = t.trace_id
+                    ORDER BY r.parent_span_id IS NOT NULL, r.start_time LIMIT 1) AS root_service_name,
+                   (SELECT r.name FROM traces r WHERE r.tenant_id = ?1 AND r.trace_id = t.trace_id
+                    ORDER BY r.parent_span_id IS NOT NULL, r.start_time LIMIT 1) AS root_span_name,
+                   MIN(t.start_time) AS start_time,
+                   MAX(t.duration_ms) AS duration_ms,
+                   COUNT(*) AS span_count,
+                   MAX(COALESCE(t.status = 'error', 0)) AS has_error
+            FROM traces t
+            JOIN matching m ON m.trace_id = t.trace_id
+            WHERE t.tenant_id = ?1
+            GROUP BY t.trace_id
+            ORDER BY duration_ms IS NULL, duration_ms DESC, start_time DESC
+            LIMIT ?7
+        "#;
+
+        let min_duration_ms = min_duration.map(|d| d.as_millis() as i64);
+        let limit = limit.unwrap_or(20);
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(start_time)
+            .bind(end_time)
+            .bind(service)
+            .bind(min_duration_ms)
+            .bind(status)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to find traces: {}", e))?;
+
+        let traces = rows
+            .into_iter()
+            .map(|row| TraceSummary {
+                trace_id: row.get("trace_id"),
+                root_service_name: row.get("root_service_name"),
+                root_span_name: row.get("root_span_name"),
+                start_time: row.get("start_time"),
+                duration_ms: row.get("duration_ms"),
+                span_count: row.get("span_count"),
+                has_error: row.get("has_error"),
+            })
+            .collect();
+
+        Ok(traces)
+    }
+
+    async fn erase_records(
+        &self,
+        dataset: ErasureDataset,
+        selector: &ErasureSelector,
+        mode: ErasureMode,
+        limit: i64,
+    ) -> Result<u64> {
+        if mode == ErasureMode::Scrub {
+            selector.scrubbed()?;
+        }
+        if dataset == ErasureDataset::ProcessedMessages
+            && selector.attribute.is_some()
+            && self.compression.codec != PayloadCodec::None
+        {
+            return Err(anyhow::anyhow!(
+                "Cannot match attributes of compressed processed messages; erase them by time range instead"
+            ));
+        }
+
+        let attribute_in = |column: &str| {
+            format!(
+                "(json_type({column}, ?4) IN ('text', 'integer', 'real') \
+                 AND CAST(json_extract({column}, ?4) AS TEXT) = ?5)"
+            )
+        };
+        let (table, time_column, attribute_matches) = match dataset {
+            ErasureDataset::Logs => ("logs", "timestamp", attribute_in("attributes")),
+            ErasureDataset::Traces => ("traces", "start_time", attribute_in("attributes")),
+            ErasureDataset::ProcessedMessages => (
+                "processed_messages",
+                "processed_at",
+                format!("({} OR {})", attribute_in("original_message"), attribute_in("processed_message")),
+            ),
+        };
+        let matching = |column: &str| {
+            format!(
+                r#"
+                SELECT {column} FROM {table}
+                WHERE tenant_id = ?1
+                  AND (?2 IS NULL OR {time_column} >= ?2)
+                  AND (?3 IS NULL OR {time_column} < ?3)
+                  AND (?4 IS NULL OR {attribute_matches})
+                LIMIT ?6
+                "#
+            )
+        };
+        // Traces are deleted whole, so no span of them is left behind
+        let sql = match (dataset, mode) {
+            (ErasureDataset::Traces, ErasureMode::Delete) => format!(
+                "DELETE FROM traces WHERE tenant_id = ?1 AND trace_id IN ({})",
+                matching("DISTINCT trace_id")
+            ),
+            (_, ErasureMode::Delete) => format!("DELETE FROM {} WHERE id IN ({})", table, matching("id")),
+            (ErasureDataset::ProcessedMessages, ErasureMode::Scrub) => format!(
+                r#"
+                UPDATE processed_messages
+                SET original_message = json_remove(original_message, ?4),
+                    processed_message = json_remove(processed_message, ?4),
+                    updated_at = CURRENT_TIMESTAMP
+                WHERE id IN ({})
+                "#,
+                matching("id")
+            ),
+            (_, ErasureMode::Scrub) => format!(
+                "UPDATE {} SET attributes = json_remove(attributes, ?4) WHERE id IN ({})",
+                table,
+                matching("id")
+            ),
+        };
+
+        let attribute = selector.attribute.as_ref();
+        let result = sqlx::query(&sql)
+            .bind(&selector.tenant_id)
+            .bind(selector.start_time)
+            .bind(selector.end_time)
+            .bind(attribute.map(|a| a.json_path()))
+            .bind(attribute.map(|a| a.value.clone()))
+            .bind(limit)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to erase {}: {}", dataset.as_str(), e))?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn compact_metrics(&self, rule: &MetricRetentionRule, cutoff: DateTime<Utc>) -> Result<CompactionStats> {
+        // SQLite has no data-modifying CTEs, so rollups are inserted first and
+        // the source rows deleted afterwards; the new rollups carry
+        // `resolution_seconds = ?3` and are excluded from the delete.
+        let insert_sql = r#"
+            INSERT INTO metrics (tenant_id, metric_name, metric_value, metric_type, tags, timestamp,
+                                 resolution_seconds, sample_count, min_value, max_value)
+            SELECT tenant_id,
+                   metric_name,
+                   SUM(metric_value * COALESCE(sample_count, 1)) / SUM(COALESCE(sample_count, 1)),
+                   metric_type,
+                   tags,
+                   strftime('%Y-%m-%dT%H:%M:%S+00:00',
+                            (CAST(strftime('%s', timestamp) AS INTEGER) / ?3) * ?3, 'unixepoch') AS bucket,
+                   ?3,
+                   SUM(COALESCE(sample_count, 1)),
+                   MIN(COALESCE(min_value, metric_value)),
+                   MAX(COALESCE(max_value, metric_value))
+            FROM metrics
+            WHERE metric_name LIKE ?1 ESCAPE ''
+              AND timestamp < ?2
+              AND (resolution_seconds IS NULL OR resolution_seconds < ?3)
+            GROUP BY tenant_id, metric_name, metric_type, tags, bucket
+        "#;
+
+        let delete_sql = r#"
+            DELETE FROM metrics
+            WHERE metric_name LIKE ?1 ESCAPE ''
+              AND timestamp < ?2
+              AND (resolution_seconds IS NULL OR resolution_seconds < ?3)
+        "#;
+
+        let pattern = compaction::like_pattern(rule);
+        let resolution = rule.resolution_secs as i64;
+
+        let mut transaction = self.pool.begin().await?;
+        let inserted = sqlx::query(insert_sql)
+            .bind(&pattern)
+            .bind(cutoff)
+            .bind(resolution)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to write metric rollups: {}", e))?;
+        let deleted = sqlx::query(delete_sql)
+            .bind(&pattern)
+            .bind(cutoff)
+            .bind(resolution)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete compacted metrics: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(CompactionStats {
+            rows_deleted: deleted.rows_affected(),
+            rollups_written: inserted.rows_affected(),
+        })
+    }
+
+    async fn oldest_rows(&self, dataset: ExportDataset, before: DateTime<Utc>) -> Result<Vec<(String, DateTime<Utc>)>> {
+        let (table, time) = lifecycle::table(dataset);
+        let sql = format!(
+            r#"
+            SELECT tenant_id, MIN({time}) AS oldest FROM {table}
+            WHERE {time} < ?1
+            GROUP BY tenant_id
+            ORDER BY tenant_id
+            "#
+        );
+
+        let rows = sqlx::query(&sql)
+            .bind(before)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to find aged {}: {}", table, e))?;
+
+        Ok(rows.iter().map(|row| (row.get("tenant_id"), row.get("oldest"))).collect())
+    }
+
+    async fn record_archived_segment(&self, segment: &ArchivedSegment) -> Result<bool> {
+        let (table, time) = lifecycle::table(segment.dataset);
+        let delete_sql = format!("DELETE FROM {table} WHERE tenant_id = ?1 AND {time} >= ?2 AND {time} < ?3");
+        let insert_sql = r#"
+            INSERT INTO archived_segments (location, tenant_id, dataset, start_time, end_time, row_count, archived_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        "#;
+
+        let mut transaction = self.pool.begin().await?;
+        let deleted = sqlx::query(&delete_sql)
+            .bind(&segment.tenant_id)
+            .bind(segment.start_time)
+            .bind(segment.end_time)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete archived {}: {}", table, e))?;
+        // Dropping the transaction rolls the delete back
+        if deleted.rows_affected() != segment.rows {
+            return Ok(false);
+        }
+        sqlx::query(insert_sql)
+            .bind(&segment.location)
+            .bind(&segment.tenant_id)
+            .bind(segment.dataset.as_str())
+            .bind(segment.start_time)
+            .bind(segment.end_time)
+            .bind(segment.rows as i64)
+            .bind(segment.archived_at)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to record archived segment: {}", e))?;
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn list_archived_segments(
+        &self,
+        tenant_id: &str,
+        dataset: ExportDataset,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<ArchivedSegment>> {
+        let sql = r#"
+            SELECT location, tenant_id, dataset, start_time, end_time, row_count, archived_at
+            FROM archived_segments
+            WHERE tenant_id = ?1 AND dataset = ?2 AND start_time < ?4 AND end_time > ?3
+            ORDER BY start_time ASC, location ASC
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(dataset.as_str())
+            .bind(start_time)
+            .bind(end_time)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list archived segments: {}", e))?;
+
+        rows.iter().map(Self::archived_segment_from_row).collect()
+    }
+
+    async fn create_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        let sql = r#"
+            INSERT INTO pipelines (tenant_id, id, name, description, definition, version, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (tenant_id, id) DO NOTHING
+        "#;
+
+        let definition = serde_json::to_string(&pipeline.spec)
+            .map_err(|e| anyhow::anyhow!("Failed to encode pipeline definition: {}", e))?;
+
+        let result = sqlx::query(sql)
+            .bind(&pipeline.tenant_id)
+            .bind(&pipeline.id)
+            .bind(&pipeline.name)
+            .bind(&pipeline.description)
+            .bind(definition)
+            .bind(pipeline.version)
+            .bind(pipeline.created_at)
+            .bind(pipeline.updated_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create pipeline: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn update_pipeline(&self, pipeline: &PipelineDefinition) -> Result<bool> {
+        let sql = r#"
+            UPDATE pipelines
+            SET name = ?3, description = ?4, definition = ?5, version = ?6, updated_at = ?7
+            WHERE tenant_id = ?1 AND id = ?2 AND version = ?6 - 1
+        "#;
+
+        let definition = serde_json::to_string(&pipeline.spec)
+            .map_err(|e| anyhow::anyhow!("Failed to encode pipeline definition: {}", e))?;
+
+        let result = sqlx::query(sql)
+            .bind(&pipeline.tenant_id)
+            .bind(&pipeline.id)
+            .bind(&pipeline.name)
+            .bind(&pipeline.description)
+            .bind(definition)
+            .bind(pipeline.version)
+            .bind(pipeline.updated_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to update pipeline: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_pipeline(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM pipelines WHERE tenant_id = ?1 AND id = ?2")
+            .bind(tenant_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete pipeline: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_pipeline(&self, tenant_id: &str, id: &str) -> Result<Option<PipelineDefinition>> {
+        let sql = r#"
+            SELECT tenant_id, id, name, description, definition, version, created_at, updated_at
+            FROM pipelines
+            WHERE tenant_id = ?1 AND id = ?2
+        "#;
+
+        let row = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get pipeline: {}", e))?;
+
+        row.as_ref().map(Self::pipeline_from_row).transpose()
+    }
+
+    async fn list_pipelines(
+        &self,
+        tenant_id: &str,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<PipelineDefinition>> {
+        let sql = r#"
+            SELECT tenant_id, id, name, description, definition, version, created_at, updated_at
+            FROM pipelines
+            WHERE tenant_id = ?1
+              AND (?2 IS NULL OR (created_at, id) < (?2, ?3))
+            ORDER BY created_at DESC, id DESC
+            LIMIT ?4
+        "#;
+
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+        let (after_created_at, after_id) = PageCursor::split(cursor);
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(after_created_at)
+            .bind(after_id)
+            .bind(limit + 1)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list pipelines: {}", e))?;
+
+        let pipelines = rows.iter().map(Self::pipeline_from_row).collect::<Result<Vec<_>>>()?;
+        Ok(page_of_pipelines(pipelines, limit))
+    }
+
+    async fn list_scheduled_pipelines(&self) -> Result<Vec<PipelineDefinition>> {
+        let sql = r#"
+            SELECT tenant_id, id, name, description, definition, version, created_at, updated_at
+            FROM pipelines
+            WHERE json_type(definition, '$.schedule') = 'object'
+            ORDER BY tenant_id, id
+        "#;
+
+        let rows = sqlx::query(sql)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list scheduled pipelines: {}", e))?;
+
+        rows.iter().map(Self::pipeline_from_row).collect()
+    }
+
+    async fn acquire_job_lease(&self, lease: &JobLease) -> Result<bool> {
+        let sql = r#"
+            INSERT INTO job_leases (pipeline_id, job_id, owner, endpoint, config, limits, acquired_at, expires_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (pipeline_id) DO UPDATE
+            SET job_id = excluded.job_id,
+                owner = excluded.owner,
+                endpoint = excluded.endpoint,
+                config = excluded.config,
+                limits = excluded.limits,
+                acquired_at = excluded.acquired_at,
+                expires_at = excluded.expires_at
+            WHERE job_leases.expires_at <= excluded.acquired_at
+        "#;
+
+        let limits = serde_json::to_string(&lease.limits)
+            .map_err(|e| anyhow::anyhow!("Failed to encode job lease limits: {}", e))?;
+
+        let result = sqlx::query(sql)
+            .bind(&lease.pipeline_id)
+            .bind(&lease.job_id)
+            .bind(&lease.owner)
+            .bind(&lease.endpoint)
+            .bind(&lease.config)
+            .bind(limits)
+            .bind(lease.acquired_at)
+            .bind(lease.expires_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to acquire job lease: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn renew_job_lease(
+        &self,
+        pipeline_id: &str,
+        owner: &str,
+        job_id: Option<&str>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let sql = r#"
+            UPDATE job_leases
+            SET job_id = COALESCE(?3, job_id), expires_at = ?4
+            WHERE pipeline_id = ?1 AND owner = ?2
+        "#;
+
+        let result = sqlx::query(sql)
+            .bind(pipeline_id)
+            .bind(owner)
+            .bind(job_id)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to renew job lease: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn release_job_lease(&self, pipeline_id: &str, owner: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM job_leases WHERE pipeline_id = ?1 AND owner = ?2")
+            .bind(pipeline_id)
+            .bind(owner)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to release job lease: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_job_lease(&self, job_id: &str) -> Result<Option<JobLease>> {
+        let sql = r#"
+            SELECT pipeline_id, job_id, owner, endpoint, config, limits, acquired_at, expires_at
+            FROM job_leases
+            WHERE job_id = ?1
+        "#;
+
+        let row = sqlx::query(sql)
+            .bind(job_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get job lease: {}", e))?;
+
+        row.as_ref().map(Self::job_lease_from_row).transpose()
+    }
+
+    async fn list_expired_job_leases(&self, now: DateTime<Utc>) -> Result<Vec<JobLease>> {
+        let sql = r#"
+            SELECT pipeline_id, job_id, owner, endpoint, config, limits, acquired_at, expires_at
+            FROM job_leases
+            WHERE expires_at <= ?1
+            ORDER BY expires_at ASC, pipeline_id ASC
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list expired job leases: {}", e))?;
+
+        rows.iter().map(Self::job_lease_from_row).collect()
+    }
+
+    async fn store_service_statuses(&self, statuses: &[ServiceStatusRecord]) -> Result<()> {
+        if statuses.is_empty() {
+            return Ok(());
+        }
+
+        let sql = r#"
+            INSERT INTO service_statuses (
+                tenant_id, name, status, uptime, response_time, requests_per_second, last_seen, last_check
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (tenant_id, name) DO UPDATE SET
+                status = excluded.status,
+                uptime = excluded.uptime,
+                response_time = excluded.response_time,
+                requests_per_second = excluded.requests_per_second,
+                last_seen = excluded.last_seen,
+                last_check = excluded.last_check
+        "#;
+
+        let mut transaction = self.pool.begin().await?;
+        for status in statuses {
+            sqlx::query(sql)
+                .bind(&status.tenant_id)
+                .bind(&status.name)
+                .bind(&status.status)
+                .bind(status.uptime)
+                .bind(status.response_time)
+                .bind(status.requests_per_second)
+                .bind(status.last_seen)
+                .bind(status.last_check)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to store service status: {}", e))?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn list_service_statuses(&self, tenant_id: &str) -> Result<Vec<ServiceStatusRecord>> {
+        let sql = r#"
+            SELECT tenant_id, name, status, uptime, response_time, requests_per_second, last_seen, last_check
+            FROM service_statuses
+            WHERE tenant_id = ?1
+            ORDER BY name ASC
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list service statuses: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ServiceStatusRecord {
+                tenant_id: row.get("tenant_id"),
+                name: row.get("name"),
+                status: row.get("status"),
+                uptime: row.get("uptime"),
+                response_time: row.get("response_time"),
+                requests_per_second: row.get("requests_per_second"),
+                last_seen: row.get("last_seen"),
+                last_check: row.get("last_check"),
+            })
+            .collect())
+    }
+
+    async fn record_alert_delivery(&self, delivery: &AlertDelivery) -> Result<()> {
+        let sql = r#"
+            INSERT INTO alert_deliveries (
+                tenant_id, id, alert_id, channel, status, attempts, last_error, created_at, updated_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT (tenant_id, id) DO UPDATE SET
+                status = excluded.status,
+                attempts = excluded.attempts,
+                last_error = excluded.last_error,
+                updated_at = excluded.updated_at
+        "#;
+
+        sqlx::query(sql)
+            .bind(&delivery.tenant_id)
+            .bind(&delivery.id)
+            .bind(&delivery.alert_id)
+            .bind(&delivery.channel)
+            .bind(&delivery.status)
+            .bind(delivery.attempts)
+            .bind(&delivery.last_error)
+            .bind(delivery.created_at)
+            .bind(delivery.updated_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to record alert delivery: {}", e))?;
+        Ok(())
+    }
+
+    async fn list_alert_deliveries(
+        &self,
+        tenant_id: &str,
+        alert_id: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Vec<AlertDelivery>> {
+        let sql = r#"
+            SELECT tenant_id, id, alert_id, channel, status, attempts, last_error, created_at, updated_at
+            FROM alert_deliveries
+            WHERE tenant_id = ?1
+              AND (?2 IS NULL OR alert_id = ?2)
+            ORDER BY created_at DESC, id DESC
+            LIMIT ?3
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(alert_id)
+            .bind(limit.unwrap_or(100))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list alert deliveries: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| AlertDelivery {
+                id: row.get("id"),
+                tenant_id: row.get("tenant_id"),
+                alert_id: row.get("alert_id"),
+                channel: row.get("channel"),
+                status: row.get("status"),
+                attempts: row.get("attempts"),
+                last_error: row.get("last_error"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+            })
+            .collect())
+    }
+
+    async fn create_silence(&self, silence: &Silence) -> Result<bool> {
+        let sql = r#"
+            INSERT INTO silences (tenant_id, id, matchers, starts_at, ends_at, comment, created_by, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (tenant_id, id) DO NOTHING
+        "#;
+
+        let matchers = serde_json::to_string(&silence.matchers)
+            .map_err(|e| anyhow::anyhow!("Failed to encode silence matchers: {}", e))?;
+
+        let result = sqlx::query(sql)
+            .bind(&silence.tenant_id)
+            .bind(&silence.id)
+            .bind(matchers)
+            .bind(silence.starts_at)
+            .bind(silence.ends_at)
+            .bind(&silence.comment)
+            .bind(&silence.created_by)
+            .bind(silence.created_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create silence: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_silence(&self, tenant_id: &str, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM silences WHERE tenant_id = ?1 AND id = ?2")
+            .bind(tenant_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete silence: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn list_silences(&self, tenant_id: &str, active_at: Option<DateTime<Utc>>) -> Result<Vec<Silence>> {
+        let sql = r#"
+            SELECT tenant_id, id, matchers, starts_at, ends_at, comment, created_by, created_at
+            FROM silences
+            WHERE tenant_id = ?1
+              AND (?2 IS NULL OR (starts_at <= ?2 AND ends_at > ?2))
+            ORDER BY starts_at ASC, id ASC
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(active_at)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list silences: {}", e))?;
+
+        rows.iter().map(Self::silence_from_row).collect()
+    }
+
+    async fn list_unexpired_silences(&self, now: DateTime<Utc>) -> Result<Vec<Silence>> {
+        let sql = r#"
+            SELECT tenant_id, id, matchers, starts_at, ends_at, comment, created_by, created_at
+            FROM silences
+            WHERE ends_at > ?1
+            ORDER BY tenant_id, starts_at ASC, id ASC
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list unexpired silences: {}", e))?;
+
+        rows.iter().map(Self::silence_from_row).collect()
+    }
+
+    async fn append_audit_entry(&self, entry: &AuditEntry) -> Result<()> {
+        let sql = r#"
+            INSERT INTO audit_log (id, occurred_at, actor, source, action, tenant_id, target, details, outcome, error)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+        "#;
+
+        sqlx::query(sql)
+            .bind(&entry.id)
+            .bind(entry.occurred_at)
+            .bind(&entry.actor)
+            .bind(&entry.source)
+            .bind(&entry.action)
+            .bind(&entry.tenant_id)
+            .bind(&entry.target)
+            .bind(entry.details.to_string())
+            .bind(&entry.outcome)
+            .bind(&entry.error)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to append audit entry: {}", e))?;
+        Ok(())
+    }
+
+    async fn query_audit_log(
+        &self,
+        filter: &AuditFilter,
+        limit: Option<i64>,
+        cursor: Option<&str>,
+    ) -> Result<Page<AuditEntry>> {
+        let sql = r#"
+            SELECT id, occurred_at, actor, source, action, tenant_id, target, details, outcome, error
+            FROM audit_log
+            WHERE (?1 IS NULL OR occurred_at >= ?1)
+              AND (?2 IS NULL OR occurred_at < ?2)
+              AND (?3 IS NULL OR actor = ?3)
+              AND (?4 IS NULL OR action = ?4)
+              AND (?5 IS NULL OR target = ?5)
+              AND (?6 IS NULL OR (occurred_at, id) < (?6, ?7))
+            ORDER BY occurred_at DESC, id DESC
+            LIMIT ?8
+        "#;
+
+        let limit = limit.unwrap_or(100);
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+        let (after_occurred_at, after_id) = PageCursor::split(cursor);
+
+        let rows = sqlx::query(sql)
+            .bind(filter.start_time)
+            .bind(filter.end_time)
+            .bind(&filter.actor)
+            .bind(&filter.action)
+            .bind(&filter.target)
+            .bind(after_occurred_at)
+            .bind(after_id)
+            .bind(limit + 1)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to query audit log: {}", e))?;
+
+        let entries = rows.iter().map(Self::audit_entry_from_row).collect::<Result<Vec<_>>>()?;
+        Ok(page_of_audit_entries(entries, limit))
+    }
+
+    async fn create_schema_version(&self, version: &SchemaVersion) -> Result<bool> {
+        let sql = r#"
+            INSERT INTO schema_versions (tenant_id, topic, version, fields, changes, violations, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT (tenant_id, topic, version) DO NOTHING
+        "#;
+
+        let encode = |value: serde_json::Result<String>| {
+            value.map_err(|e| anyhow::anyhow!("Failed to encode schema version: {}", e))
+        };
+        let result = sqlx::query(sql)
+            .bind(&version.tenant_id)
+            .bind(&version.topic)
+            .bind(version.version)
+            .bind(encode(serde_json::to_string(&version.fields))?)
+            .bind(encode(serde_json::to_string(&version.changes))?)
+            .bind(encode(serde_json::to_string(&version.violations))?)
+            .bind(version.created_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create schema version: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn list_schema_versions(&self, tenant_id: &str, topic: &str) -> Result<Vec<SchemaVersion>> {
+        let sql = r#"
+            SELECT tenant_id, topic, version, fields, changes, violations, created_at
+            FROM schema_versions
+            WHERE tenant_id = ?1 AND topic = ?2
+            ORDER BY version ASC
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(tenant_id)
+            .bind(topic)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list schema versions: {}", e))?;
+
+        rows.iter().map(Self::schema_version_from_row).collect()
+    }
+
+    async fn list_latest_schema_versions(&self) -> Result<Vec<SchemaVersion>> {
+        let sql = r#"
+            SELECT tenant_id, topic, version, fields, changes, violations, created_at
+            FROM schema_versions s
+            WHERE version = (
+                SELECT MAX(version) FROM schema_versions l
+                WHERE l.tenant_id = s.tenant_id AND l.topic = s.topic
+            )
+            ORDER BY tenant_id, topic
+        "#;
+
+        let rows = sqlx::query(sql)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list latest schema versions: {}", e))?;
+
+        rows.iter().map(Self::schema_version_from_row).collect()
+    }
+
+    async fn create_role_binding(&self, binding: &RoleBinding) -> Result<bool> {
+        let sql = r#"
+            INSERT INTO role_bindings (subject, role, tenants, created_by, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (subject, role) DO NOTHING
+        "#;
+
+        let tenants = serde_json::to_string(&binding.tenants)
+            .map_err(|e| anyhow::anyhow!("Failed to encode role binding tenants: {}", e))?;
+        let result = sqlx::query(sql)
+            .bind(&binding.subject)
+            .bind(&binding.role)
+            .bind(tenants)
+            .bind(&binding.created_by)
+            .bind(binding.created_at)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create role binding: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_role_binding(&self, subject: &str, role: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM role_bindings WHERE subject = ?1 AND role = ?2")
+            .bind(subject)
+            .bind(role)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete role binding: {}", e))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn list_role_bindings(&self, subject: Option<&str>) -> Result<Vec<RoleBinding>> {
+        let sql = r#"
+            SELECT subject, role, tenants, created_by, created_at
+            FROM role_bindings
+            WHERE (?1 IS NULL OR subject = ?1)
+            ORDER BY subject, role
+        "#;
+
+        let rows = sqlx::query(sql)
+            .bind(subject)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list role bindings: {}", e))?;
+
+        rows.iter().map(Self::role_binding_from_row).collect()
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        match sqlx::query("SELECT 1").fetch_one(&self.pool).await {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                error!("Database health check failed: {}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    fn pool_status(&self) -> PoolStatus {
+        PoolStatus {
+            size: self.pool.size(),
+            idle: self.pool.num_idle() as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StorageBackendKind;
+    use crate::pipeline::{PipelineSpec, ScheduleSpec, SourceSpec};
+    use crate::storage::AttributeMatch;
+    use serde_json::json;
+
+    async fn memory_storage() -> SqliteStorage {
+        let mut config = Config::default();
+        config.database.backend = StorageBackendKind::Sqlite;
+        config.database.url = "sqlite::memory:".to_string();
+        SqliteStorage::new(&config).await.unwrap()
+    }
+
+    fn message(id: &str, topic: &str, offset: i64) -> ProcessedMessage {
+        ProcessedMessage {
+            id: id.to_string(),
+            original_message: json!({"test": "original"}),
+            processed_message: json!({"test": "processed"}),
+            processing_metadata: ProcessingMetadata {
+                tenant_id: "tenant-a".to_string(),
+                processed_at: Utc::now() + chrono::Duration::milliseconds(offset),
+                processor_version: "1.0.0".to_string(),
+                source_topic: topic.to_string(),
+                partition: 0,
+                offset,
+                traceparent: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compact_metrics_rolls_up_old_rows() {
+        let storage = memory_storage().await;
+        let old = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+
+        for (offset, value) in [(0, 1.0), (60, 3.0), (400, 10.0)] {
+            let timestamp = old + chrono::Duration::seconds(offset);
+            storage
+                .store_metric("tenant-a", "cpu_usage", value, "gauge", None, timestamp)
+                .await
+                .unwrap();
+        }
+        storage
+            .store_metric("tenant-a", "cpu_usage", 5.0, "gauge", None, Utc::now())
+            .await
+            .unwrap();
+
+        let rule = MetricRetentionRule {
+            metric: "cpu_*".to_string(),
+            older_than_days: 7,
+            resolution_secs: 300,
+        };
+        let cutoff = Utc::now() - chrono::Duration::days(7);
+
+        let stats = storage.compact_metrics(&rule, cutoff).await.unwrap();
+        assert_eq!(stats, CompactionStats { rows_deleted: 3, rollups_written: 2 });
+
+        let rows: Vec<(f64, i64, f64)> = sqlx::query_as(
+            "SELECT metric_value, sample_count, max_value FROM metrics WHERE resolution_seconds = 300 ORDER BY timestamp",
+        )
+        .fetch_all(&storage.pool)
+        .await
+        .unwrap();
+        assert_eq!(rows, vec![(2.0, 2, 3.0), (10.0, 1, 10.0)]);
+
+        let again = storage.compact_metrics(&rule, cutoff).await.unwrap();
+        assert_eq!(again, CompactionStats::default());
+    }
+
+    #[tokio::test]
+    async fn test_compressed_payloads_round_trip() {
+        let mut config = Config::default();
+        config.database.backend = StorageBackendKind::Sqlite;
+        config.database.url = "sqlite::memory:".to_string();
+        config.database.compression.codec = crate::config::PayloadCodec::Zstd;
+        let storage = SqliteStorage::new(&config).await.unwrap();
+
+        storage.store_processed_message(&message("id-z", "metrics", 0)).await.unwrap();
+
+        let blob: Option<Vec<u8>> = sqlx::query_scalar("SELECT original_message_blob FROM processed_messages")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap();
+        assert!(blob.is_some());
+
+        let fetched = storage.get_processed_message("tenant-a", "id-z").await.unwrap().unwrap();
+        assert_eq!(fetched.original_message, json!({"test": "original"}));
+        assert_eq!(fetched.processed_message, json!({"test": "processed"}));
+    }
+
+    #[tokio::test]
+    async fn test_store_and_page_processed_messages() {
+        let storage = memory_storage().await;
+        let messages: Vec<_> = (0..5).map(|i| message(&format!("id-{}", i), "metrics", i)).collect();
+        storage.store_processed_messages(&messages).await.unwrap();
+
+        let first = storage
+            .get_processed_messages_by_topic("tenant-a", "metrics", Some(3), None)
+            .await
+            .unwrap();
+        assert_eq!(first.items.len(), 3);
+        assert_eq!(first.items[0].id, "id-4");
+
+        let cursor = first.next_cursor.expect("expected a second page");
+        let second = storage
+            .get_processed_messages_by_topic("tenant-a", "metrics", Some(3), Some(&cursor))
+            .await
+            .unwrap();
+        assert_eq!(second.items.len(), 2);
+        assert!(second.next_cursor.is_none());
+
+        let fetched = storage.get_processed_message("tenant-a", "id-2").await.unwrap().unwrap();
+        assert_eq!(fetched.processing_metadata.offset, 2);
+
+        let other = storage
+            .get_processed_messages_by_topic("tenant-b", "metrics", None, None)
+            .await
+            .unwrap();
+        assert!(other.items.is_empty());
+        assert!(storage.get_processed_message("tenant-b", "id-2").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_store_and_get_trace() {
+        let storage = memory_storage().await;
+        let start = Utc::now();
+
+        storage
+            .store_trace("tenant-a", "t1", "root", None, "GET /", Some("api"), start, None, Some(120), None, None, None)
+            .await
+            .unwrap();
+        storage
+            .store_trace("tenant-a", "t1", "db", Some("root"), "SELECT", Some("db"), start, None, Some(80), Some("error"), None, None)
+            .await
+            .unwrap();
+
+        let trace = storage.get_trace("tenant-a", "t1").await.unwrap().unwrap();
+        assert!(storage.get_trace("tenant-b", "t1").await.unwrap().is_none());
+        assert_eq!(trace.span_count, 2);
+        assert_eq!(trace.roots[0].children[0].span_id, "db");
+
+        let found = storage
+            .find_traces("tenant-a", Some("db"), Some(Duration::from_millis(50)), None, start - chrono::Duration::minutes(1), start + chrono::Duration::minutes(1), None)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].root_span_name, "GET /");
+        assert!(found[0].has_error);
+    }
+
+    #[tokio::test]
+    async fn test_erase_traces_and_scrub_messages() {
+        let storage = memory_storage().await;
+        let start = Utc::now();
+        let user = Some(json!({"user": {"id": 42}}));
+
+        storage
+            .store_trace("tenant-a", "t1", "root", None, "GET /", Some("api"), start, None, None, None, None, None)
+            .await
+            .unwrap();
+        storage
+            .store_trace("tenant-a", "t1", "db", Some("root"), "SELECT", Some("db"), start, None, None, None, user.clone(), None)
+            .await
+            .unwrap();
+        storage
+            .store_trace("tenant-b", "t1", "root", None, "GET /", Some("api"), start, None, None, None, user, None)
+            .await
+            .unwrap();
+
+        let selector = ErasureSelector {
+            tenant_id: "tenant-a".to_string(),
+            attribute: Some(AttributeMatch::new("user.id", "42")),
+            start_time: None,
+            end_time: None,
+        };
+        let erased = storage
+            .erase_records(ErasureDataset::Traces, &selector, ErasureMode::Delete, 100)
+            .await
+            .unwrap();
+        assert_eq!(erased, 2);
+        assert!(storage.get_trace("tenant-a", "t1").await.unwrap().is_none());
+        assert!(storage.get_trace("tenant-b", "t1").await.unwrap().is_some());
+
+        let mut stored = message("id-1", "events", 1);
+        stored.original_message = json!({"user": {"id": 42, "name": "alice"}});
+        storage.store_processed_message(&stored).await.unwrap();
+        let erased = storage
+            .erase_records(ErasureDataset::ProcessedMessages, &selector, ErasureMode::Scrub, 100)
+            .await
+            .unwrap();
+        assert_eq!(erased, 1);
+        let scrubbed = storage.get_processed_message("tenant-a", "id-1").await.unwrap().unwrap();
+        assert_eq!(scrubbed.original_message, json!({"user": {"name": "alice"}}));
+        assert_eq!(scrubbed.processed_message, json!({"test": "processed"}));
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_crud_and_paging() {
+        let storage = memory_storage().await;
+        let now = Utc::now();
+        let pipeline = |id: &str, offset: i64| PipelineDefinition {
+            id: id.to_string(),
+            tenant_id: "tenant-a".to_string(),
+            name: format!("pipeline {}", id),
+            description: String::new(),
+            spec: PipelineSpec {
+                sources: vec![SourceSpec {
+                    name: "events".to_string(),
+                    topics: vec!["events".to_string()],
+                }],
+                ..PipelineSpec::default()
+            },
+            version: 1,
+            created_at: now + chrono::Duration::milliseconds(offset),
+            updated_at: now,
+        };
+
+        for (i, id) in ["p1", "p2", "p3"].iter().enumerate() {
+            assert!(storage.create_pipeline(&pipeline(id, i as i64)).await.unwrap());
+        }
+        assert!(!storage.create_pipeline(&pipeline("p1", 0)).await.unwrap());
+
+        let mut updated = pipeline("p1", 0);
+        updated.version = 2;
+        updated.name = "renamed".to_string();
+        updated.spec.schedule = Some(ScheduleSpec {
+            interval_seconds: Some(3600),
+            window_seconds: 3600,
+            ..ScheduleSpec::default()
+        });
+        assert!(storage.update_pipeline(&updated).await.unwrap());
+        // A second writer still holding version 1 loses.
+        assert!(!storage.update_pipeline(&updated).await.unwrap());
+        let stored = storage.get_pipeline("tenant-a", "p1").await.unwrap().unwrap();
+        assert_eq!(stored.name, "renamed");
+        assert_eq!(stored.spec, updated.spec);
+        assert!(storage.get_pipeline("tenant-b", "p1").await.unwrap().is_none());
+        let scheduled = storage.list_scheduled_pipelines().await.unwrap();
+        assert_eq!(scheduled.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), vec!["p1"]);
+
+        let first = storage.list_pipelines("tenant-a", Some(2), None).await.unwrap();
+        assert_eq!(first.items.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), vec!["p3", "p2"]);
+        let second = storage
+            .list_pipelines("tenant-a", Some(2), first.next_cursor.as_deref())
+            .await
+            .unwrap();
+        assert_eq!(second.items[0].id, "p1");
+        assert!(second.next_cursor.is_none());
+
+        assert!(storage.delete_pipeline("tenant-a", "p2").await.unwrap());
+        assert!(!storage.delete_pipeline("tenant-a", "p2").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_job_lease_fails_over_only_after_expiry() {
+        let storage = memory_storage().await;
+        let now = Utc::now();
+        let lease = |owner: &str, at: DateTime<Utc>| JobLease {
+            pipeline_id: "pipeline-1".to_string(),
+            job_id: None,
+            owner: owner.to_string(),
+            endpoint: format!("http://{}:50051", owner),
+            config: "{}".to_string(),
+            limits: Default::default(),
+            acquired_at: at,
+            expires_at: at + chrono::Duration::seconds(15),
+        };
+
+        assert!(storage.acquire_job_lease(&lease("a", now)).await.unwrap());
+        assert!(!storage.acquire_job_lease(&lease("b", now)).await.unwrap());
+        assert!(storage
+            .renew_job_lease("pipeline-1", "a", Some("job-1"), now + chrono::Duration::seconds(15))
+            .await
+            .unwrap());
+        assert_eq!(storage.get_job_lease("job-1").await.unwrap().unwrap().owner, "a");
+
+        let later = now + chrono::Duration::seconds(20);
+        assert_eq!(storage.list_expired_job_leases(later).await.unwrap().len(), 1);
+        assert!(storage.acquire_job_lease(&lease("b", later)).await.unwrap());
+        assert!(!storage.renew_job_lease("pipeline-1", "a", None, later).await.unwrap());
+        assert!(!storage.release_job_lease("pipeline-1", "a").await.unwrap());
+        assert!(storage.release_job_lease("pipeline-1", "b").await.unwrap());
+        assert!(storage.get_job_lease("job-1").await.unwrap().is_none());
+    }
+}
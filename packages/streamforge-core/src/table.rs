@@ -0,0 +1,303 @@
+//! Tables materialized from compacted topics and kept current, for
+//! `enrich` transforms to join records against (stream-table joins).
+
+use anyhow::Result;
+use futures::StreamExt;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::{Message, Offset, TopicPartitionList};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::columnar::lookup;
+use crate::config::{Config, TableConfig};
+use crate::metrics::Metrics;
+
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Latest JSON value per record key of one compacted topic.
+pub struct Table {
+    name: String,
+    max_entries: Option<usize>,
+    entries: RwLock<HashMap<Vec<u8>, Arc<Value>>>,
+    ready: watch::Sender<bool>,
+    metrics: Arc<Metrics>,
+}
+
+impl Table {
+    pub fn new(name: &str, config: &TableConfig, metrics: Arc<Metrics>) -> Self {
+        Self {
+            name: name.to_string(),
+            max_entries: config.max_entries,
+            entries: RwLock::new(HashMap::new()),
+            ready: watch::channel(false).0,
+            metrics,
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Arc<Value>> {
+        self.entries.read().unwrap().get(key).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the table has caught up with everything the topic held when
+    /// it started loading. Joins before then may miss keys.
+    pub fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /// Waits until [`Table::is_ready`].
+    pub async fn wait_ready(&self) {
+        let mut ready = self.ready.subscribe();
+        let _ = ready.wait_for(|ready| *ready).await;
+    }
+
+    /// Applies one record of the topic. An empty payload is a tombstone and
+    /// removes the key.
+    pub fn apply(&self, key: &[u8], payload: Option<&[u8]>) {
+        let mut entries = self.entries.write().unwrap();
+        match payload.filter(|p| !p.is_empty()) {
+            None => {
+                entries.remove(key);
+            }
+            Some(payload) => match serde_json::from_slice::<Value>(payload) {
+                Ok(value) => {
+                    let full = self.max_entries.map_or(false, |max| entries.len() >= max);
+                    if full && !entries.contains_key(key) {
+                        self.metrics.increment_table_updates_dropped(&self.name);
+                    } else {
+                        entries.insert(key.to_vec(), Arc::new(value));
+                    }
+                }
+                Err(e) => {
+                    warn!("Dropping update to table {}: invalid JSON payload: {}", self.name, e);
+                    self.metrics.increment_table_updates_dropped(&self.name);
+                }
+            },
+        }
+        self.metrics.set_table_entries(&self.name, entries.len());
+    }
+}
+
+/// Every table configured under `kafka.tables`, by name.
+#[derive(Default)]
+pub struct Tables(HashMap<String, Arc<Table>>);
+
+impl Tables {
+    pub fn new(config: &Config, metrics: Arc<Metrics>) -> Self {
+        Self(
+            config
+                .kafka
+                .tables
+                .iter()
+                .map(|(name, table)| (name.clone(), Arc::new(Table::new(name, table, metrics.clone()))))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Table>> {
+        self.0.get(name)
+    }
+
+    /// Starts one loader per table. Each restarts from the beginning of its
+    /// topic if its consumer fails.
+    pub fn spawn(&self, config: &Config) -> Vec<JoinHandle<()>> {
+        self.0
+            .values()
+            .map(|table| {
+                let table = table.clone();
+                let topic = config.kafka.tables[&table.name].topic.clone();
+                let config = config.clone();
+                tokio::spawn(async move {
+                    loop {
+                        if let Err(e) = load(&table, &topic, &config).await {
+                            error!("Table {} stopped following {}: {}", table.name, topic, e);
+                        }
+                        tokio::time::sleep(RETRY_DELAY).await;
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads every partition of `topic` from the beginning, outside any
+/// consumer group so each instance holds the whole table, and keeps
+/// following it.
+async fn load(table: &Table, topic: &str, config: &Config) -> Result<()> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &config.kafka.bootstrap_servers)
+        .set("group.id", format!("{}-table-{}", config.kafka.group_id, table.name))
+        .set("enable.auto.commit", "false")
+        .set("enable.partition.eof", "true")
+        .create()
+        .map_err(|e| anyhow::anyhow!("Failed to create Kafka consumer: {}", e))?;
+
+    let metadata = consumer
+        .fetch_metadata(Some(topic), METADATA_TIMEOUT)
+        .map_err(|e| anyhow::anyhow!("Failed to fetch metadata for topic {}: {}", topic, e))?;
+    let partitions: Vec<i32> = metadata
+        .topics()
+        .iter()
+        .flat_map(|t| t.partitions().iter().map(|p| p.id()))
+        .collect();
+    if partitions.is_empty() {
+        return Err(anyhow::anyhow!("Topic not found: {}", topic));
+    }
+
+    // Ready once every partition reaches the end it had at startup
+    let mut pending = BTreeMap::new();
+    let mut assignment = TopicPartitionList::new();
+    for partition in partitions {
+        let (low, high) = consumer
+            .fetch_watermarks(topic, partition, METADATA_TIMEOUT)
+            .map_err(|e| anyhow::anyhow!("Failed to fetch watermarks for {}/{}: {}", topic, partition, e))?;
+        if high > low {
+            pending.insert(partition, high);
+        }
+        assignment.add_partition_offset(topic, partition, Offset::Beginning)?;
+    }
+    consumer
+        .assign(&assignment)
+        .map_err(|e| anyhow::anyhow!("Failed to assign {}: {}", topic, e))?;
+    info!("Loading table {} from {}", table.name, topic);
+
+    let mut stream = consumer.stream();
+    loop {
+        if pending.is_empty() && !table.is_ready() {
+            info!("Table {} loaded with {} keys", table.name, table.len());
+            table.ready.send_replace(true);
+        }
+        // Compaction leaves gaps, so the end of a partition is only known
+        // for sure from its EOF event
+        let message = match stream.next().await {
+            Some(Ok(message)) => message,
+            Some(Err(KafkaError::PartitionEOF(partition))) => {
+                pending.remove(&partition);
+                continue;
+            }
+            Some(Err(e)) => return Err(anyhow::anyhow!("Failed to read {}: {}", topic, e)),
+            None => return Ok(()),
+        };
+        match message.key() {
+            Some(key) => table.apply(key, message.payload()),
+            None => table.metrics.increment_table_updates_dropped(&table.name),
+        }
+        if pending.get(&message.partition()).map_or(false, |high| message.offset() + 1 >= *high) {
+            pending.remove(&message.partition());
+        }
+    }
+}
+
+/// An `enrich` transform that joins a table:
+/// `{"table": "services", "key": "labels.service", "into": "service"}`
+/// looks up the record's `labels.service` and sets the matching value
+/// under `service`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TableJoin {
+    pub table: String,
+    /// Dotted path to the field holding the lookup key.
+    pub key: String,
+    /// Dotted path the joined value is written to.
+    pub into: String,
+}
+
+impl TableJoin {
+    /// `None` when the transform does not join a table.
+    pub fn from_config(config: &Value) -> Result<Option<Self>, String> {
+        if config.get("table").is_none() {
+            return Ok(None);
+        }
+        let join = Self::deserialize(config).map_err(|e| e.to_string())?;
+        if join.key.is_empty() || join.into.is_empty() {
+            return Err("key and into must not be empty".to_string());
+        }
+        Ok(Some(join))
+    }
+
+    /// Joins `record` with its table entry, returning whether one matched.
+    /// Records without the key field, or whose key is not in the table,
+    /// pass through unchanged.
+    pub fn apply(&self, table: &Table, record: &mut Value) -> bool {
+        let key = match lookup(record, &self.key) {
+            Some(Value::String(s)) => s.as_bytes().to_vec(),
+            Some(Value::Null) | None => return false,
+            Some(other) => other.to_string().into_bytes(),
+        };
+        let Some(entry) = table.get(&key) else {
+            return false;
+        };
+        set_path(record, &self.into, entry.as_ref().clone());
+        true
+    }
+}
+
+/// Writes `value` at the dotted `path`, creating objects along the way.
+pub(crate) fn set_path(record: &mut Value, path: &str, value: Value) {
+    let mut target = record;
+    for segment in path.split('.') {
+        if !target.is_object() {
+            *target = Value::Object(Default::default());
+        }
+        target = target
+            .as_object_mut()
+            .expect("target was just made an object")
+            .entry(segment)
+            .or_insert(Value::Null);
+    }
+    *target = value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsConfig;
+    use serde_json::json;
+
+    #[test]
+    fn test_table_updates_and_joins() {
+        let metrics = Arc::new(Metrics::new(&MetricsConfig::default()).unwrap());
+        let config = TableConfig {
+            topic: "service-metadata".to_string(),
+            max_entries: Some(2),
+        };
+        let table = Table::new("services", &config, metrics);
+        table.apply(b"api", Some(br#"{"team": "core"}"#));
+        table.apply(b"web", Some(br#"{"team": "edge"}"#));
+        table.apply(b"db", Some(br#"{"team": "data"}"#));
+        table.apply(b"web", Some(b"not json"));
+        assert_eq!(table.len(), 2);
+        table.apply(b"api", Some(br#"{"team": "platform"}"#));
+        assert_eq!(table.get(b"api").as_deref(), Some(&json!({"team": "platform"})));
+        table.apply(b"web", None);
+        assert_eq!(table.get(b"web"), None);
+
+        let join = TableJoin::from_config(&json!({"table": "services", "key": "labels.service", "into": "meta.service"}))
+            .unwrap()
+            .unwrap();
+        let mut record = json!({"labels": {"service": "api"}, "meta": 1});
+        assert!(join.apply(&table, &mut record));
+        assert_eq!(record["meta"], json!({"service": {"team": "platform"}}));
+        let mut unknown = json!({"labels": {"service": "web"}});
+        assert!(!join.apply(&table, &mut unknown));
+        assert_eq!(unknown, json!({"labels": {"service": "web"}}));
+
+        assert_eq!(TableJoin::from_config(&json!({"fields": ["region"]})), Ok(None));
+        assert!(TableJoin::from_config(&json!({"table": "services", "key": "labels.service"})).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use crate::config::TelemetryConfig;
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Span fields promoted to top-level keys on every JSON log line, as
+/// `(span field, log key)`. The innermost span that recorded a field wins.
+const CORRELATION_FIELDS: &[(&str, &str)] = &[
+    ("messaging.destination.name", "topic"),
+    ("messaging.kafka.partition", "partition"),
+    ("messaging.kafka.offset", "offset"),
+    ("worker_id", "worker_id"),
+];
+
+/// Correlation fields recorded on a span, stored in its extensions.
+#[derive(Debug, Default)]
+struct SpanCorrelation(Map<String, Value>);
+
+/// Captures the correlation fields of each span so [`JsonFormat`] can attach
+/// them to events emitted inside it.
+pub struct CorrelationLayer;
+
+impl<S> Layer<S> for CorrelationLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut fields = SpanCorrelation::default();
+        attrs.record(&mut CorrelationVisitor(&mut fields.0));
+        span.extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        if let Some(fields) = extensions.get_mut::<SpanCorrelation>() {
+            values.record(&mut CorrelationVisitor(&mut fields.0));
+        }
+    }
+}
+
+struct CorrelationVisitor<'a>(&'a mut Map<String, Value>);
+
+impl CorrelationVisitor<'_> {
+    fn insert(&mut self, field: &Field, value: Value) {
+        if let Some((_, key)) = CORRELATION_FIELDS.iter().find(|(name, _)| *name == field.name()) {
+            self.0.insert(key.to_string(), value);
+        }
+    }
+}
+
+impl Visit for CorrelationVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
+
+/// Collects every event field; `message` is pulled out separately.
+#[derive(Default)]
+struct EventVisitor {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl Visit for EventVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.fields.insert(field.name().to_string(), value.into());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{:?}", value));
+        } else {
+            self.fields.insert(field.name().to_string(), format!("{:?}", value).into());
+        }
+    }
+}
+
+/// One JSON object per line with the event's level, target, message and
+/// fields, plus `trace_id`/`span_id` of the current span, the Kafka position
+/// it is processing and the configured pipeline and job IDs.
+pub struct JsonFormat {
+    static_fields: Map<String, Value>,
+}
+
+impl JsonFormat {
+    pub fn new(config: &TelemetryConfig) -> Self {
+        let mut static_fields = Map::new();
+        static_fields.insert("service".to_string(), config.service_name.clone().into());
+        if let Some(pipeline_id) = &config.pipeline_id {
+            static_fields.insert("pipeline_id".to_string(), pipeline_id.clone().into());
+        }
+        if let Some(job_id) = &config.job_id {
+            static_fields.insert("job_id".to_string(), job_id.clone().into());
+        }
+        Self { static_fields }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut visitor = EventVisitor::default();
+        event.record(&mut visitor);
+
+        let mut line = self.static_fields.clone();
+        line.insert(
+            "timestamp".to_string(),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into(),
+        );
+        line.insert("level".to_string(), metadata.level().as_str().into());
+        line.insert("target".to_string(), metadata.target().into());
+        line.insert("message".to_string(), visitor.message.unwrap_or_default().into());
+        if !visitor.fields.is_empty() {
+            line.insert("fields".to_string(), Value::Object(visitor.fields));
+        }
+
+        if let Some(scope) = ctx.event_scope() {
+            // Root first, so inner spans override outer ones.
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                if let Some(correlation) = extensions.get::<SpanCorrelation>() {
+                    for (key, value) in &correlation.0 {
+                        line.insert(key.clone(), value.clone());
+                    }
+                }
+                if let Some(otel) = extensions.get::<OtelData>() {
+                    if let Some(trace_id) = otel.builder.trace_id {
+                        line.insert("trace_id".to_string(), trace_id.to_string().into());
+                    }
+                    if let Some(span_id) = otel.builder.span_id {
+                        line.insert("span_id".to_string(), span_id.to_string().into());
+                    }
+                }
+            }
+        }
+
+        let encoded = serde_json::to_string(&line).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{}", encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines_carry_correlation_fields() {
+        let config = TelemetryConfig {
+            pipeline_id: Some("pipeline-1".to_string()),
+            job_id: Some("job-7".to_string()),
+            ..TelemetryConfig::default()
+        };
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(CorrelationLayer).with(
+            tracing_subscriber::fmt::layer()
+                .event_format(JsonFormat::new(&config))
+                .with_writer(move || writer.clone()),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            let consume = tracing::info_span!(
+                "kafka.consume",
+                messaging.destination.name = "events",
+                messaging.kafka.partition = 2,
+                messaging.kafka.offset = 41,
+            );
+            let _consume = consume.enter();
+            let transform = tracing::info_span!("transform", worker_id = 3);
+            let _transform = transform.enter();
+            tracing::warn!(batch = 10, "slow transform");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["message"], "slow transform");
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["topic"], "events");
+        assert_eq!(line["partition"], 2);
+        assert_eq!(line["offset"], 41);
+        assert_eq!(line["worker_id"], 3);
+        assert_eq!(line["pipeline_id"], "pipeline-1");
+        assert_eq!(line["job_id"], "job-7");
+        assert_eq!(line["fields"]["batch"], 10);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::config::{LogFormat, SamplingConfig, TelemetryConfig, TraceExporterKind};
+use anyhow::Result;
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::{BaggagePropagator, TextMapCompositePropagator, TraceContextPropagator};
+use opentelemetry_sdk::trace::{self, Sampler, Tracer};
+use opentelemetry_sdk::Resource;
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+use std::collections::HashMap;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+mod logging;
+
+pub use logging::{CorrelationLayer, JsonFormat};
+
+/// Keeps the span exporter alive; call [`TelemetryGuard::shutdown`] before
+/// exiting so batched spans are flushed.
+#[must_use = "dropping the guard without calling shutdown loses buffered spans"]
+pub struct TelemetryGuard {
+    tracing_enabled: bool,
+    log_level: LogLevelHandle,
+}
+
+impl TelemetryGuard {
+    pub fn log_level(&self) -> LogLevelHandle {
+        self.log_level.clone()
+    }
+
+    pub fn shutdown(self) {
+        if self.tracing_enabled {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// Changes the active log filter at runtime.
+#[derive(Clone)]
+pub struct LogLevelHandle(reload::Handle<EnvFilter, Registry>);
+
+impl LogLevelHandle {
+    pub fn set(&self, directive: &str) -> Result<()> {
+        let filter = EnvFilter::try_new(directive)
+            .map_err(|e| anyhow::anyhow!("Invalid log level {}: {}", directive, e))?;
+        self.0
+            .reload(filter)
+            .map_err(|e| anyhow::anyhow!("Failed to change log level: {}", e))
+    }
+}
+
+/// Installs the global tracing subscriber: an env filter seeded from
+/// `telemetry.log_level`, falling back to `log_level` (both overridable
+/// through `RUST_LOG`), text or JSON log output per
+/// `log_format`, and, unless the exporter is `none`, an OpenTelemetry layer
+/// exporting spans to the configured collector.
+pub fn init(config: &TelemetryConfig, log_level: &str) -> Result<TelemetryGuard> {
+    let log_level = config.log_level.as_deref().unwrap_or(log_level);
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(log_level))
+        .map_err(|e| anyhow::anyhow!("Invalid log level {}: {}", log_level, e))?;
+    let (filter, filter_handle) = reload::Layer::new(filter);
+
+    let tracer = match config.exporter {
+        TraceExporterKind::None => None,
+        exporter => Some(build_tracer(config, exporter)?),
+    };
+    let tracing_enabled = tracer.is_some();
+
+    opentelemetry::global::set_text_map_propagator(propagator());
+
+    let (text_layer, json_layer) = match config.log_format {
+        LogFormat::Text => (Some(tracing_subscriber::fmt::layer()), None),
+        LogFormat::Json => (
+            None,
+            Some(tracing_subscriber::fmt::layer().event_format(JsonFormat::new(config))),
+        ),
+    };
+    let correlation_layer = json_layer.is_some().then_some(CorrelationLayer);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+        .with(correlation_layer)
+        .with(text_layer)
+        .with(json_layer)
+        .try_init()
+        .map_err(|e| anyhow::anyhow!("Failed to install tracing subscriber: {}", e))?;
+
+    Ok(TelemetryGuard {
+        tracing_enabled,
+        log_level: LogLevelHandle(filter_handle),
+    })
+}
+
+/// Resource attributes identifying this processor in traces and metrics.
+pub fn resource(config: &TelemetryConfig) -> Resource {
+    Resource::new(vec![
+        KeyValue::new("service.name", config.service_name.clone()),
+        KeyValue::new("service.version", config.service_version.clone()),
+        KeyValue::new("deployment.environment", config.environment.clone()),
+    ])
+}
+
+fn build_tracer(config: &TelemetryConfig, exporter: TraceExporterKind) -> Result<Tracer> {
+    let trace_config = trace::config()
+        .with_sampler(sampler(&config.sampling))
+        .with_resource(resource(config));
+
+    match exporter {
+        TraceExporterKind::Otlp => opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&config.otlp_endpoint),
+            )
+            .with_trace_config(trace_config)
+            .install_batch(opentelemetry_sdk::runtime::Tokio)
+            .map_err(|e| anyhow::anyhow!("Failed to install OTLP tracer: {}", e)),
+        TraceExporterKind::Jaeger => opentelemetry_jaeger::new_collector_pipeline()
+            .with_endpoint(&config.jaeger_endpoint)
+            .with_service_name(&config.service_name)
+            .with_reqwest()
+            .with_trace_config(trace_config)
+            .install_batch(opentelemetry_sdk::runtime::Tokio)
+            .map_err(|e| anyhow::anyhow!("Failed to install Jaeger tracer: {}", e)),
+        TraceExporterKind::None => Err(anyhow::anyhow!("Tracing exporter is disabled")),
+    }
+}
+
+/// W3C `traceparent`/`tracestate` plus `baggage`, the headers exchanged
+/// with producers and downstream consumers.
+fn propagator() -> TextMapCompositePropagator {
+    TextMapCompositePropagator::new(vec![
+        Box::new(TraceContextPropagator::new()),
+        Box::new(BaggagePropagator::new()),
+    ])
+}
+
+struct HeaderExtractor<'a, H>(&'a H);
+
+impl<H: Headers> Extractor for HeaderExtractor<'_, H> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|header| header.key.eq_ignore_ascii_case(key))
+            .and_then(|header| header.value)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.iter().map(|header| header.key).collect()
+    }
+}
+
+/// Trace context carried by a consumed message, or an empty context when
+/// the producer sent none.
+pub fn extract_context<H: Headers>(headers: Option<&H>) -> Context {
+    match headers {
+        Some(headers) => propagator().extract(&HeaderExtractor(headers)),
+        None => Context::new(),
+    }
+}
+
+/// Appends the propagation headers for `context` to `headers`.
+pub fn inject_context(context: &Context, headers: OwnedHeaders) -> OwnedHeaders {
+    let mut fields: HashMap<String, String> = HashMap::new();
+    propagator().inject_context(context, &mut fields);
+    fields.into_iter().fold(headers, |headers, (key, value)| {
+        headers.insert(Header {
+            key: &key,
+            value: Some(value.as_str()),
+        })
+    })
+}
+
+/// Whether `key` is one of the headers [`inject_context`] writes.
+pub fn is_propagation_header(key: &str) -> bool {
+    propagator().fields().any(|field| field.eq_ignore_ascii_case(key))
+}
+
+/// The W3C `traceparent` for `context`, or `None` without a valid span.
+pub fn traceparent(context: &Context) -> Option<String> {
+    let mut fields: HashMap<String, String> = HashMap::new();
+    TraceContextPropagator::new().inject_context(context, &mut fields);
+    fields.remove("traceparent")
+}
+
+fn sampler(config: &SamplingConfig) -> Sampler {
+    let ratio = Sampler::TraceIdRatioBased(config.ratio.clamp(0.0, 1.0));
+    if config.parent_based {
+        Sampler::ParentBased(Box::new(ratio))
+    } else {
+        ratio
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+
+    #[test]
+    fn test_context_round_trips_through_kafka_headers() {
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let context = Context::new().with_remote_span_context(span_context.clone());
+
+        let headers = inject_context(&context, OwnedHeaders::new());
+        let extracted = extract_context(Some(&headers));
+
+        assert_eq!(extracted.span().span_context().trace_id(), span_context.trace_id());
+        assert_eq!(extracted.span().span_context().span_id(), span_context.span_id());
+        assert_eq!(
+            traceparent(&extracted).as_deref(),
+            Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+        );
+        assert!(traceparent(&Context::new()).is_none());
+    }
+
+    #[test]
+    fn test_sampler_from_config() {
+        let parent_based = sampler(&SamplingConfig {
+            ratio: 0.25,
+            parent_based: true,
+        });
+        assert!(matches!(parent_based, Sampler::ParentBased(_)));
+
+        let ratio_only = sampler(&SamplingConfig {
+            ratio: 1.5,
+            parent_based: false,
+        });
+        assert!(matches!(ratio_only, Sampler::TraceIdRatioBased(r) if r == 1.0));
+    }
+}
@@ -0,0 +1,11 @@
+//! End-to-end tests against real Kafka and Postgres, started in Docker by
+//! testcontainers. Behind the `integration` feature so a plain `cargo test`
+//! does not need Docker:
+//!
+//! ```text
+//! cargo test -p streamforge-core --features integration --test it
+//! ```
+
+mod harness;
+mod pipeline;
+mod storage;
@@ -0,0 +1,133 @@
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+use streamforge_core::canary::{CanaryMode, CanarySettings, CANARY_KEY};
+use streamforge_core::config::MetricsConfig;
+use streamforge_core::dlq::{self, DeadLetterQuery, ErrorClass};
+use streamforge_core::metrics::Metrics;
+use streamforge_core::processor::StreamProcessor;
+
+use crate::harness::{eventually, Harness, DEAD_LETTER_TOPIC, INPUT_TOPIC, TENANT};
+
+/// Valid messages are stored for their tenant; one that cannot be processed
+/// ends up on the dead letter topic with its error envelope. The dead letter
+/// topic is the only topic the processor writes to.
+#[tokio::test]
+async fn test_produce_process_store_and_dead_letter() {
+    let harness = Harness::start().await.unwrap();
+    let metrics = Arc::new(Metrics::new(&MetricsConfig::default()).unwrap());
+    let processor = Arc::new(StreamProcessor::new(harness.config.clone(), metrics).await.unwrap());
+    let storage = processor.storage();
+    let running = {
+        let processor = processor.clone();
+        tokio::spawn(async move { processor.run().await })
+    };
+
+    let producer = harness.producer().unwrap();
+    for i in 0..20 {
+        let payload = json!({
+            "name": "cpu_usage",
+            "value": i as f64,
+            "unit": "percent",
+            "labels": { "service": "api" },
+        });
+        harness
+            .produce(&producer, &format!("metric-{}", i), payload.to_string().as_bytes())
+            .await
+            .unwrap();
+    }
+    harness.produce(&producer, "poison", b"not json").await.unwrap();
+
+    let stored = eventually("20 stored messages", || async {
+        let page = storage
+            .get_processed_messages_by_topic(TENANT, INPUT_TOPIC, Some(100), None)
+            .await?;
+        Ok((page.items.len() >= 20).then_some(page.items))
+    })
+    .await
+    .unwrap();
+    assert_eq!(stored.len(), 20);
+    assert!(stored.iter().all(|message| message.processing_metadata.tenant_id == TENANT));
+    let other = storage
+        .get_processed_messages_by_topic("other-tenant", INPUT_TOPIC, Some(100), None)
+        .await
+        .unwrap();
+    assert!(other.items.is_empty());
+
+    let query = DeadLetterQuery {
+        topic: DEAD_LETTER_TOPIC.to_string(),
+        limit: 10,
+        ..Default::default()
+    };
+    let letters = eventually("the dead letter", || async {
+        let page = dlq::read(&harness.config, &query, Duration::from_secs(5))?;
+        Ok((!page.letters.is_empty()).then_some(page.letters))
+    })
+    .await
+    .unwrap();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].payload, b"not json");
+    assert_eq!(letters[0].key.as_deref(), Some(&b"poison"[..]));
+    assert_eq!(letters[0].envelope.original_topic, INPUT_TOPIC);
+    assert_eq!(letters[0].envelope.error_class, ErrorClass::Processing);
+    assert_eq!(letters[0].envelope.attempts, 1);
+
+    running.abort();
+}
+
+/// A deployment's candidate in canary mode stores only the records its
+/// sample takes, the same ones `CanarySettings::selects` picks.
+#[tokio::test]
+async fn test_canary_candidate_processes_only_its_sample() {
+    let harness = Harness::start().await.unwrap();
+    let canary = CanarySettings {
+        deployment_id: "it-deployment".to_string(),
+        mode: CanaryMode::Canary,
+        sample_percent: 50.0,
+        namespace: "canary-it".to_string(),
+    };
+    let job_config = json!({ CANARY_KEY: canary });
+    let metrics = Arc::new(Metrics::new(&MetricsConfig::default()).unwrap());
+    let processor = StreamProcessor::new(harness.config.clone(), metrics).await.unwrap();
+    let processor = Arc::new(processor.for_job(&job_config).unwrap());
+    let storage = processor.storage();
+    let running = {
+        let processor = processor.clone();
+        tokio::spawn(async move { processor.run().await })
+    };
+
+    let producer = harness.producer().unwrap();
+    let mut sampled = Vec::new();
+    for i in 0..40 {
+        let key = format!("metric-{}", i);
+        if canary.selects(Some(key.as_bytes()), 0, i) {
+            sampled.push(i as f64);
+        }
+        let payload = json!({
+            "name": "cpu_usage",
+            "value": i as f64,
+            "unit": "percent",
+            "labels": { "service": "api" },
+        });
+        harness.produce(&producer, &key, payload.to_string().as_bytes()).await.unwrap();
+    }
+    assert!(!sampled.is_empty() && sampled.len() < 40, "{} sampled", sampled.len());
+
+    let stored = eventually("the sampled messages", || async {
+        let page = storage
+            .get_processed_messages_by_topic(TENANT, INPUT_TOPIC, Some(100), None)
+            .await?;
+        Ok((page.items.len() >= sampled.len()).then_some(page.items))
+    })
+    .await
+    .unwrap();
+    let mut values: Vec<f64> = stored
+        .iter()
+        .filter_map(|message| message.original_message["value"].as_f64())
+        .collect();
+    values.sort_by(f64::total_cmp);
+    assert_eq!(values, sampled);
+
+    running.abort();
+}
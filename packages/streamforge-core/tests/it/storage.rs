@@ -0,0 +1,124 @@
+use chrono::Utc;
+use serde_json::json;
+
+use streamforge_core::processor::{ProcessedMessage, ProcessingMetadata};
+use streamforge_core::storage::{LogRecord, MetricRecord, StorageBackend, StorageManager};
+
+use crate::harness::{Harness, INPUT_TOPIC, TENANT};
+
+fn message(id: &str, offset: i64) -> ProcessedMessage {
+    ProcessedMessage {
+        id: id.to_string(),
+        original_message: json!({"offset": offset}),
+        processed_message: json!({"offset": offset}),
+        processing_metadata: ProcessingMetadata {
+            tenant_id: TENANT.to_string(),
+            processed_at: Utc::now() + chrono::Duration::milliseconds(offset),
+            processor_version: "1.0.0".to_string(),
+            source_topic: INPUT_TOPIC.to_string(),
+            partition: 0,
+            offset,
+            traceparent: None,
+        },
+    }
+}
+
+/// The upsert on `(source_topic, partition, offset)` that the in-memory
+/// backend emulates, against the real schema.
+#[tokio::test]
+async fn test_postgres_upserts_redelivered_offsets() {
+    let harness = Harness::start().await.unwrap();
+    let storage = StorageManager::new(&harness.config).await.unwrap();
+
+    let first: Vec<_> = (0..3).map(|i| message(&format!("id-{}", i), i)).collect();
+    assert_eq!(storage.store_processed_messages(&first).await.unwrap(), 0);
+
+    let mut redelivered = message("id-new", 1);
+    redelivered.processed_message = json!({"retried": true});
+    assert_eq!(
+        storage.store_processed_messages(&[redelivered, message("id-3", 3)]).await.unwrap(),
+        1
+    );
+
+    let page = storage
+        .get_processed_messages_by_topic(TENANT, INPUT_TOPIC, Some(2), None)
+        .await
+        .unwrap();
+    assert_eq!(page.items.len(), 2);
+    let rest = storage
+        .get_processed_messages_by_topic(TENANT, INPUT_TOPIC, Some(2), page.next_cursor.as_deref())
+        .await
+        .unwrap();
+    assert_eq!(rest.items.len(), 2);
+    assert!(rest.next_cursor.is_none());
+
+    assert!(storage.get_processed_message(TENANT, "id-new").await.unwrap().is_none());
+    let updated = storage.get_processed_message(TENANT, "id-1").await.unwrap().unwrap();
+    assert_eq!(updated.processed_message, json!({"retried": true}));
+}
+
+/// One statement cannot update a row twice, so a position repeated within a
+/// batch is written once, from its later delivery.
+#[tokio::test]
+async fn test_postgres_keeps_the_last_delivery_within_a_batch() {
+    let harness = Harness::start().await.unwrap();
+    let storage = StorageManager::new(&harness.config).await.unwrap();
+
+    let mut retried = message("id-retried", 0);
+    retried.processed_message = json!({"retried": true});
+    assert_eq!(
+        storage
+            .store_processed_messages(&[message("id-first", 0), retried, message("id-1", 1)])
+            .await
+            .unwrap(),
+        1
+    );
+
+    assert!(storage.get_processed_message(TENANT, "id-first").await.unwrap().is_none());
+    let stored = storage.get_processed_message(TENANT, "id-retried").await.unwrap().unwrap();
+    assert_eq!(stored.processed_message, json!({"retried": true}));
+    assert!(storage.get_processed_message(TENANT, "id-1").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_postgres_stores_metric_and_log_batches() {
+    let harness = Harness::start().await.unwrap();
+    let storage = StorageManager::new(&harness.config).await.unwrap();
+    let start = Utc::now();
+
+    let metrics: Vec<MetricRecord> = (0..50)
+        .map(|i| MetricRecord {
+            name: "cpu_usage".to_string(),
+            value: i as f64,
+            metric_type: "gauge".to_string(),
+            tags: (i % 2 == 0).then(|| json!({"host": format!("host-{}", i)})),
+            timestamp: start + chrono::Duration::milliseconds(i),
+            resolution_secs: None,
+        })
+        .collect();
+    storage.store_metrics(TENANT, &metrics).await.unwrap();
+
+    let logs = vec![LogRecord {
+        level: "error".to_string(),
+        message: "boom".to_string(),
+        service_name: Some("api".to_string()),
+        host_name: None,
+        trace_id: None,
+        span_id: None,
+        attributes: Some(json!({"code": 500})),
+        timestamp: start,
+    }];
+    storage.store_logs(TENANT, &logs).await.unwrap();
+
+    let end = start + chrono::Duration::seconds(1);
+    let stored = storage
+        .get_metrics(TENANT, Some("cpu_usage"), start, end, None)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 50);
+    assert_eq!(stored[2].tags, Some(json!({"host": "host-2"})));
+    assert_eq!(stored[3].tags, None);
+    let stored = storage.get_logs(TENANT, None, None, start, end, None).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].attributes, Some(json!({"code": 500})));
+}
@@ -3,6 +3,7 @@ package config
 import (
 	"os"
 	"strconv"
+	"strings"
 	"time"
 )
 
@@ -14,6 +15,7 @@ type Config struct {
 	Redis       RedisConfig
 	JWT         JWTConfig
 	Logging     LoggingConfig
+	Ingest      IngestConfig
 }
 
 // ServerConfig represents server configuration
@@ -55,6 +57,22 @@ type LoggingConfig struct {
 	File  string
 }
 
+// IngestConfig represents ingest endpoint configuration
+type IngestConfig struct {
+	// IdempotencyTTL is how long a response is replayed for retries carrying its Idempotency-Key
+	IdempotencyTTL time.Duration
+	// IdempotencyMaxEntries caps the responses kept for replay, evicting the oldest; 0 leaves it unbounded
+	IdempotencyMaxEntries int
+	// APIKeys are the keys that identify ingest clients; callers without one are limited per IP
+	APIKeys []string
+	// RateLimit is the requests per second allowed per API key; 0 disables rate limiting
+	RateLimit int
+	// RateBurst is the requests an API key may send at once before being limited
+	RateBurst int
+	// MaxBodyBytes is the largest request body accepted; 0 disables the limit
+	MaxBodyBytes int
+}
+
 // Load loads configuration from environment variables
 func Load() *Config {
 	return &Config{
@@ -88,6 +106,14 @@ func Load() *Config {
 			Level: getEnv("LOG_LEVEL", "info"),
 			File:  getEnv("LOG_FILE", ""),
 		},
+		Ingest: IngestConfig{
+			IdempotencyTTL:        getDurationEnv("INGEST_IDEMPOTENCY_TTL", 24*time.Hour),
+			IdempotencyMaxEntries: getIntEnv("INGEST_IDEMPOTENCY_MAX_ENTRIES", 100000),
+			APIKeys:               getListEnv("INGEST_API_KEYS"),
+			RateLimit:             getIntEnv("INGEST_RATE_LIMIT", 100),
+			RateBurst:             getIntEnv("INGEST_RATE_BURST", 200),
+			MaxBodyBytes:          getIntEnv("INGEST_MAX_BODY_BYTES", 5<<20),
+		},
 	}
 }
 
@@ -109,6 +135,17 @@ func getIntEnv(key string, defaultValue int) int {
 	return defaultValue
 }
 
+// getListEnv gets a comma-separated environment variable, empty when unset
+func getListEnv(key string) []string {
+	var values []string
+	for _, value := range strings.Split(os.Getenv(key), ",") {
+		if value = strings.TrimSpace(value); value != "" {
+			values = append(values, value)
+		}
+	}
+	return values
+}
+
 // getDurationEnv gets a duration environment variable with a default value
 func getDurationEnv(key string, defaultValue time.Duration) time.Duration {
 	if value := os.Getenv(key); value != "" {
@@ -9,6 +9,7 @@ import (
 	"time"
 
 	"github.com/gin-gonic/gin"
+	"github.com/prometheus/client_golang/prometheus/promhttp"
 	"github.com/streamforge/streamforge/services/api-gateway/config"
 	"github.com/streamforge/streamforge/services/api-gateway/handlers"
 	"github.com/streamforge/streamforge/services/api-gateway/middleware"
@@ -40,13 +41,29 @@ func main() {
 	// ヘルスチェック
 	router.GET("/health", handlers.HealthCheck)
 
+	// Prometheusメトリクス
+	router.GET("/metrics", gin.WrapH(promhttp.Handler()))
+
+	// 取り込みエンドポイントのレート制限とサイズ制限
+	var limiter *middleware.RateLimiter
+	if cfg.Ingest.RateLimit > 0 {
+		limiter = middleware.NewRateLimiter(float64(cfg.Ingest.RateLimit), cfg.Ingest.RateBurst)
+	}
+	clients := middleware.NewClients(cfg.Ingest.APIKeys)
+	rateLimit := middleware.RateLimit(limiter, clients)
+	bodyLimit := middleware.BodyLimit(int64(cfg.Ingest.MaxBodyBytes), clients)
+
+	// 再送されたバッチの重複取り込みを防ぐ
+	idempotencyStore := middleware.NewIdempotencyStore(cfg.Ingest.IdempotencyTTL, cfg.Ingest.IdempotencyMaxEntries)
+	idempotency := middleware.Idempotency(idempotencyStore)
+
 	// API v1 ルート
 	v1 := router.Group("/api/v1")
 	{
 		// メトリクス関連
 		metrics := v1.Group("/metrics")
 		{
-			metrics.POST("/", handlers.SendMetrics)
+			metrics.POST("/", rateLimit, bodyLimit, idempotency, handlers.SendMetrics)
 			metrics.GET("/", handlers.GetMetrics)
 			metrics.GET("/timeseries", handlers.GetTimeSeries)
 			metrics.GET("/stream", handlers.StreamMetrics)
@@ -55,7 +72,7 @@ func main() {
 		// ログ関連
 		logs := v1.Group("/logs")
 		{
-			logs.POST("/", handlers.SendLogs)
+			logs.POST("/", rateLimit, bodyLimit, idempotency, handlers.SendLogs)
 			logs.GET("/", handlers.GetLogs)
 			logs.GET("/stream", handlers.StreamLogs)
 		}
@@ -63,7 +80,7 @@ func main() {
 		// トレース関連
 		traces := v1.Group("/traces")
 		{
-			traces.POST("/", handlers.SendTrace)
+			traces.POST("/", rateLimit, bodyLimit, idempotency, handlers.SendTrace)
 			traces.GET("/:trace_id", handlers.GetTrace)
 			traces.GET("/stream", handlers.StreamTraces)
 		}
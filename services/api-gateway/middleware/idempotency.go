@@ -0,0 +1,208 @@
+package middleware
+
+import (
+	"bytes"
+	"container/list"
+	"crypto/sha256"
+	"encoding/hex"
+	"io"
+	"net/http"
+	"sync"
+	"time"
+
+	"github.com/gin-gonic/gin"
+)
+
+// IdempotencyKeyHeader carries the client's token for a request that may be retried
+const IdempotencyKeyHeader = "Idempotency-Key"
+
+// IdempotentReplayHeader marks a response replayed from an earlier request
+const IdempotentReplayHeader = "Idempotent-Replayed"
+
+// IdempotencyStore remembers responses by idempotency key until their TTL
+// expires, or until it holds more than its maximum and they are the oldest
+type IdempotencyStore struct {
+	mu         sync.Mutex
+	ttl        time.Duration
+	maxEntries int
+	entries    map[string]*idempotencyEntry
+	order      *list.List
+	nextSweep  time.Time
+	now        func() time.Time
+}
+
+type idempotencyEntry struct {
+	fingerprint [sha256.Size]byte
+	done        bool
+	status      int
+	contentType string
+	body        []byte
+	expires     time.Time
+	// element is the key's place in the store's claim order
+	element *list.Element
+}
+
+// NewIdempotencyStore creates an in-memory store keeping each response for
+// ttl and at most maxEntries keys, evicting the oldest first. Zero or less
+// leaves the store unbounded.
+func NewIdempotencyStore(ttl time.Duration, maxEntries int) *IdempotencyStore {
+	return &IdempotencyStore{
+		ttl:        ttl,
+		maxEntries: maxEntries,
+		entries:    make(map[string]*idempotencyEntry),
+		order:      list.New(),
+		now:        time.Now,
+	}
+}
+
+// remove drops key; the caller holds the lock
+func (s *IdempotencyStore) remove(key string) {
+	if entry, ok := s.entries[key]; ok {
+		s.order.Remove(entry.element)
+		delete(s.entries, key)
+	}
+}
+
+// begin claims key for a request with the given fingerprint. It returns the
+// earlier entry when the key is already claimed and not expired.
+func (s *IdempotencyStore) begin(key string, fingerprint [sha256.Size]byte) (*idempotencyEntry, bool) {
+	s.mu.Lock()
+	defer s.mu.Unlock()
+
+	now := s.now()
+	if now.After(s.nextSweep) {
+		for k, entry := range s.entries {
+			if now.After(entry.expires) {
+				s.remove(k)
+			}
+		}
+		s.nextSweep = now.Add(s.ttl)
+	}
+
+	if entry, ok := s.entries[key]; ok && !now.After(entry.expires) {
+		copied := *entry
+		return &copied, true
+	}
+	s.remove(key)
+	s.entries[key] = &idempotencyEntry{
+		fingerprint: fingerprint,
+		expires:     now.Add(s.ttl),
+		element:     s.order.PushBack(key),
+	}
+	// A retry after an eviction is ingested again, which beats running out of memory
+	for s.maxEntries > 0 && len(s.entries) > s.maxEntries {
+		s.remove(s.order.Front().Value.(string))
+	}
+	return nil, false
+}
+
+// complete records the response for key, or forgets key so that the client
+// can retry when the request failed on the server side
+func (s *IdempotencyStore) complete(key string, status int, contentType string, body []byte) {
+	s.mu.Lock()
+	defer s.mu.Unlock()
+
+	entry, ok := s.entries[key]
+	if !ok {
+		return
+	}
+	if status >= http.StatusInternalServerError {
+		s.remove(key)
+		return
+	}
+	entry.done = true
+	entry.status = status
+	entry.contentType = contentType
+	entry.body = body
+	entry.expires = s.now().Add(s.ttl)
+}
+
+// forget drops key, as for a request that never completed
+func (s *IdempotencyStore) forget(key string) {
+	s.mu.Lock()
+	defer s.mu.Unlock()
+	s.remove(key)
+}
+
+// responseRecorder keeps a copy of the response body as it is written
+type responseRecorder struct {
+	gin.ResponseWriter
+	body bytes.Buffer
+}
+
+func (w *responseRecorder) Write(data []byte) (int, error) {
+	w.body.Write(data)
+	return w.ResponseWriter.Write(data)
+}
+
+func (w *responseRecorder) WriteString(data string) (int, error) {
+	w.body.WriteString(data)
+	return w.ResponseWriter.WriteString(data)
+}
+
+// Idempotency middleware deduplicates requests carrying an Idempotency-Key
+// header. A retry with the same key and body gets the first response again
+// instead of ingesting the batch twice; the same key with a different body
+// is rejected. Keys are scoped to the caller's credential, or its IP without
+// one, and to the method and route; requests without the header pass through
+// unchanged.
+func Idempotency(store *IdempotencyStore) gin.HandlerFunc {
+	return gin.HandlerFunc(func(c *gin.Context) {
+		token := c.GetHeader(IdempotencyKeyHeader)
+		if token == "" {
+			c.Next()
+			return
+		}
+
+		body, err := io.ReadAll(c.Request.Body)
+		if err != nil {
+			c.AbortWithStatusJSON(http.StatusBadRequest, gin.H{
+				"error":   "Failed to read request body",
+				"details": err.Error(),
+			})
+			return
+		}
+		c.Request.Body = io.NopCloser(bytes.NewReader(body))
+
+		// Clients choose keys independently, so one caller's key must never
+		// reach another caller's response
+		caller := "ip:" + c.ClientIP()
+		if sum, ok := credentialHash(c); ok {
+			caller = "key:" + hex.EncodeToString(sum[:])
+		}
+		key := caller + " " + c.Request.Method + " " + c.FullPath() + " " + token
+		fingerprint := sha256.Sum256(body)
+		if earlier, ok := store.begin(key, fingerprint); ok {
+			switch {
+			case earlier.fingerprint != fingerprint:
+				c.AbortWithStatusJSON(http.StatusUnprocessableEntity, gin.H{
+					"error": "Idempotency key was already used for a different request",
+				})
+			case !earlier.done:
+				c.AbortWithStatusJSON(http.StatusConflict, gin.H{
+					"error": "A request with this idempotency key is still in progress",
+				})
+			default:
+				c.Header(IdempotentReplayHeader, "true")
+				c.Data(earlier.status, earlier.contentType, earlier.body)
+				c.Abort()
+			}
+			return
+		}
+
+		// A handler that panics leaves no response, so the key is released
+		completed := false
+		defer func() {
+			if !completed {
+				store.forget(key)
+			}
+		}()
+
+		recorder := &responseRecorder{ResponseWriter: c.Writer}
+		c.Writer = recorder
+		c.Next()
+
+		store.complete(key, recorder.Status(), recorder.Header().Get("Content-Type"), recorder.body.Bytes())
+		completed = true
+	})
+}
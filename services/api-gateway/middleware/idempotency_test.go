@@ -0,0 +1,135 @@
+package middleware
+
+import (
+	"net/http"
+	"net/http/httptest"
+	"strings"
+	"testing"
+	"time"
+
+	"github.com/gin-gonic/gin"
+	"github.com/stretchr/testify/assert"
+)
+
+func setupIdempotentRouter(store *IdempotencyStore, ingested *int) *gin.Engine {
+	router := setupTestRouter()
+	router.POST("/ingest", Idempotency(store), func(c *gin.Context) {
+		*ingested++
+		c.JSON(http.StatusCreated, gin.H{"batch": *ingested})
+	})
+	return router
+}
+
+func postBatch(router *gin.Engine, key, body string) *httptest.ResponseRecorder {
+	return postBatchAs(router, "", key, body)
+}
+
+func postBatchAs(router *gin.Engine, apiKey, key, body string) *httptest.ResponseRecorder {
+	w := httptest.NewRecorder()
+	req, _ := http.NewRequest("POST", "/ingest", strings.NewReader(body))
+	if apiKey != "" {
+		req.Header.Set(APIKeyHeader, apiKey)
+	}
+	if key != "" {
+		req.Header.Set(IdempotencyKeyHeader, key)
+	}
+	router.ServeHTTP(w, req)
+	return w
+}
+
+func TestIdempotency(t *testing.T) {
+	t.Run("retry with the same key replays the first response", func(t *testing.T) {
+		ingested := 0
+		router := setupIdempotentRouter(NewIdempotencyStore(time.Hour, 0), &ingested)
+
+		first := postBatch(router, "batch-1", `{"data":[1,2]}`)
+		retry := postBatch(router, "batch-1", `{"data":[1,2]}`)
+
+		assert.Equal(t, 1, ingested)
+		assert.Equal(t, http.StatusCreated, retry.Code)
+		assert.Equal(t, first.Body.String(), retry.Body.String())
+		assert.Equal(t, "true", retry.Header().Get(IdempotentReplayHeader))
+		assert.Empty(t, first.Header().Get(IdempotentReplayHeader))
+	})
+
+	t.Run("clients with different API keys do not share idempotency keys", func(t *testing.T) {
+		ingested := 0
+		router := setupIdempotentRouter(NewIdempotencyStore(time.Hour, 0), &ingested)
+
+		first := postBatchAs(router, "key-a", "1", `{"data":[1]}`)
+		same := postBatchAs(router, "key-b", "1", `{"data":[1]}`)
+		different := postBatchAs(router, "key-c", "1", `{"data":[2]}`)
+
+		assert.Equal(t, 3, ingested)
+		assert.Equal(t, http.StatusCreated, same.Code)
+		assert.Equal(t, http.StatusCreated, different.Code)
+		assert.NotEqual(t, first.Body.String(), same.Body.String())
+		assert.Empty(t, same.Header().Get(IdempotentReplayHeader))
+
+		retry := postBatchAs(router, "key-a", "1", `{"data":[1]}`)
+		assert.Equal(t, first.Body.String(), retry.Body.String())
+		assert.Equal(t, 3, ingested)
+	})
+
+	t.Run("reusing a key for a different body is rejected", func(t *testing.T) {
+		ingested := 0
+		router := setupIdempotentRouter(NewIdempotencyStore(time.Hour, 0), &ingested)
+
+		postBatch(router, "batch-1", `{"data":[1,2]}`)
+		w := postBatch(router, "batch-1", `{"data":[3]}`)
+
+		assert.Equal(t, http.StatusUnprocessableEntity, w.Code)
+		assert.Equal(t, 1, ingested)
+	})
+
+	t.Run("requests without a key or after the TTL are ingested again", func(t *testing.T) {
+		ingested := 0
+		store := NewIdempotencyStore(time.Minute, 0)
+		now := time.Now()
+		store.now = func() time.Time { return now }
+		router := setupIdempotentRouter(store, &ingested)
+
+		postBatch(router, "", `{"data":[1]}`)
+		postBatch(router, "", `{"data":[1]}`)
+		assert.Equal(t, 2, ingested)
+
+		postBatch(router, "batch-1", `{"data":[1]}`)
+		now = now.Add(2 * time.Minute)
+		w := postBatch(router, "batch-1", `{"data":[1]}`)
+		assert.Equal(t, 4, ingested)
+		assert.Empty(t, w.Header().Get(IdempotentReplayHeader))
+	})
+
+	t.Run("server errors release the key", func(t *testing.T) {
+		attempts := 0
+		router := setupTestRouter()
+		router.POST("/ingest", Idempotency(NewIdempotencyStore(time.Hour, 0)), func(c *gin.Context) {
+			attempts++
+			if attempts == 1 {
+				c.JSON(http.StatusServiceUnavailable, gin.H{"error": "unavailable"})
+				return
+			}
+			c.JSON(http.StatusCreated, gin.H{"status": "accepted"})
+		})
+
+		assert.Equal(t, http.StatusServiceUnavailable, postBatch(router, "batch-1", `{}`).Code)
+		assert.Equal(t, http.StatusCreated, postBatch(router, "batch-1", `{}`).Code)
+		assert.Equal(t, 2, attempts)
+	})
+
+	t.Run("the oldest keys are evicted past the maximum", func(t *testing.T) {
+		ingested := 0
+		store := NewIdempotencyStore(time.Hour, 2)
+		router := setupIdempotentRouter(store, &ingested)
+
+		postBatch(router, "batch-1", `{}`)
+		postBatch(router, "batch-2", `{}`)
+		postBatch(router, "batch-3", `{}`)
+		assert.Len(t, store.entries, 2)
+
+		assert.Equal(t, "true", postBatch(router, "batch-3", `{}`).Header().Get(IdempotentReplayHeader))
+		assert.Equal(t, "true", postBatch(router, "batch-2", `{}`).Header().Get(IdempotentReplayHeader))
+		assert.Empty(t, postBatch(router, "batch-1", `{}`).Header().Get(IdempotentReplayHeader))
+		assert.Equal(t, 4, ingested)
+	})
+}
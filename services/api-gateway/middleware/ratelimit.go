@@ -0,0 +1,213 @@
+package middleware
+
+import (
+	"bytes"
+	"crypto/sha256"
+	"encoding/hex"
+	"io"
+	"math"
+	"net/http"
+	"strconv"
+	"strings"
+	"sync"
+	"time"
+
+	"github.com/gin-gonic/gin"
+	"github.com/prometheus/client_golang/prometheus"
+	"github.com/prometheus/client_golang/prometheus/promauto"
+)
+
+// APIKeyHeader identifies the client that ingest limits are applied to
+const APIKeyHeader = "X-API-Key"
+
+var (
+	ingestRequests = promauto.NewCounterVec(prometheus.CounterOpts{
+		Name: "streamforge_gateway_ingest_requests_total",
+		Help: "Ingest requests by API key and outcome (allowed, rate_limited, too_large)",
+	}, []string{"api_key", "outcome"})
+
+	ingestBytes = promauto.NewCounterVec(prometheus.CounterOpts{
+		Name: "streamforge_gateway_ingest_bytes_total",
+		Help: "Bytes of ingest request bodies accepted, by API key",
+	}, []string{"api_key"})
+)
+
+// Clients tells ingest callers apart for limits and metrics. Only the API
+// keys it was given identify a caller; anyone can make up other credentials,
+// so callers presenting them are told apart by IP instead.
+type Clients struct {
+	labels map[[sha256.Size]byte]string
+}
+
+// NewClients creates Clients accepting apiKeys as caller identities
+func NewClients(apiKeys []string) *Clients {
+	labels := make(map[[sha256.Size]byte]string, len(apiKeys))
+	for _, apiKey := range apiKeys {
+		if apiKey == "" {
+			continue
+		}
+		sum := sha256.Sum256([]byte(apiKey))
+		labels[sum] = hex.EncodeToString(sum[:4])
+	}
+	return &Clients{labels: labels}
+}
+
+// identify returns the caller's bucket key and metric label. A known API key
+// is keyed on its full hash and labelled with a short one so that metrics
+// never carry it. Every other caller is keyed on its IP and labelled "other"
+// when it presented an unknown credential, else "anonymous".
+func (cl *Clients) identify(c *gin.Context) (key string, label string) {
+	sum, ok := credentialHash(c)
+	if !ok {
+		return "ip:" + c.ClientIP(), "anonymous"
+	}
+	if cl == nil {
+		return "ip:" + c.ClientIP(), "other"
+	}
+	if label, known := cl.labels[sum]; known {
+		return "key:" + hex.EncodeToString(sum[:]), label
+	}
+	return "ip:" + c.ClientIP(), "other"
+}
+
+// credentialHash is the SHA-256 of the caller's API key, else of its bearer
+// token; ok is false for callers presenting neither.
+func credentialHash(c *gin.Context) (sum [sha256.Size]byte, ok bool) {
+	credential := c.GetHeader(APIKeyHeader)
+	if credential == "" {
+		credential = strings.TrimPrefix(c.GetHeader("Authorization"), "Bearer ")
+	}
+	if credential == "" {
+		return sum, false
+	}
+	return sha256.Sum256([]byte(credential)), true
+}
+
+// RateLimiter is a token bucket per client: each holds up to burst requests
+// and refills at rate requests per second
+type RateLimiter struct {
+	mu        sync.Mutex
+	rate      float64
+	burst     float64
+	buckets   map[string]*bucket
+	nextSweep time.Time
+	now       func() time.Time
+}
+
+type bucket struct {
+	tokens float64
+	last   time.Time
+}
+
+// NewRateLimiter creates a limiter allowing rate requests per second per client, in bursts of up to burst
+func NewRateLimiter(rate float64, burst int) *RateLimiter {
+	return &RateLimiter{
+		rate:    rate,
+		burst:   math.Max(float64(burst), 1),
+		buckets: make(map[string]*bucket),
+		now:     time.Now,
+	}
+}
+
+// allow takes a token from key's bucket, or tells how long until one is available
+func (l *RateLimiter) allow(key string) (bool, time.Duration) {
+	l.mu.Lock()
+	defer l.mu.Unlock()
+
+	now := l.now()
+	if now.After(l.nextSweep) {
+		// Buckets that have refilled completely are the same as new ones
+		full := time.Duration(l.burst / l.rate * float64(time.Second))
+		for k, b := range l.buckets {
+			if now.Sub(b.last) > full {
+				delete(l.buckets, k)
+			}
+		}
+		l.nextSweep = now.Add(time.Minute)
+	}
+
+	b, ok := l.buckets[key]
+	if !ok {
+		b = &bucket{tokens: l.burst, last: now}
+		l.buckets[key] = b
+	}
+	b.tokens = math.Min(l.burst, b.tokens+now.Sub(b.last).Seconds()*l.rate)
+	b.last = now
+
+	if b.tokens >= 1 {
+		b.tokens--
+		return true, 0
+	}
+	return false, time.Duration((1 - b.tokens) / l.rate * float64(time.Second))
+}
+
+// RateLimit middleware answers 429 with Retry-After once a client, as told
+// apart by clients, has used up its requests. A nil limiter lets every
+// request through.
+func RateLimit(limiter *RateLimiter, clients *Clients) gin.HandlerFunc {
+	return gin.HandlerFunc(func(c *gin.Context) {
+		if limiter == nil {
+			c.Next()
+			return
+		}
+
+		key, label := clients.identify(c)
+		allowed, wait := limiter.allow(key)
+		if !allowed {
+			ingestRequests.WithLabelValues(label, "rate_limited").Inc()
+			retryAfter := int(math.Ceil(wait.Seconds()))
+			c.Header("Retry-After", strconv.Itoa(retryAfter))
+			c.AbortWithStatusJSON(http.StatusTooManyRequests, gin.H{
+				"error":               "Rate limit exceeded",
+				"retry_after_seconds": retryAfter,
+			})
+			return
+		}
+
+		ingestRequests.WithLabelValues(label, "allowed").Inc()
+		c.Next()
+	})
+}
+
+// BodyLimit middleware answers 413 for request bodies over maxBytes, before
+// handlers start decoding them. Zero or less disables the limit.
+func BodyLimit(maxBytes int64, clients *Clients) gin.HandlerFunc {
+	return gin.HandlerFunc(func(c *gin.Context) {
+		if maxBytes <= 0 {
+			c.Next()
+			return
+		}
+
+		_, label := clients.identify(c)
+		tooLarge := func() {
+			ingestRequests.WithLabelValues(label, "too_large").Inc()
+			c.AbortWithStatusJSON(http.StatusRequestEntityTooLarge, gin.H{
+				"error":       "Request body too large",
+				"limit_bytes": maxBytes,
+			})
+		}
+
+		// Bodies announced as too large are refused without reading them
+		if c.Request.ContentLength > maxBytes {
+			tooLarge()
+			return
+		}
+
+		body, err := io.ReadAll(io.LimitReader(c.Request.Body, maxBytes+1))
+		if err != nil {
+			c.AbortWithStatusJSON(http.StatusBadRequest, gin.H{
+				"error":   "Failed to read request body",
+				"details": err.Error(),
+			})
+			return
+		}
+		if int64(len(body)) > maxBytes {
+			tooLarge()
+			return
+		}
+		c.Request.Body = io.NopCloser(bytes.NewReader(body))
+		ingestBytes.WithLabelValues(label).Add(float64(len(body)))
+
+		c.Next()
+	})
+}
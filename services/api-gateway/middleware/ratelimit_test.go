@@ -0,0 +1,101 @@
+package middleware
+
+import (
+	"net/http"
+	"net/http/httptest"
+	"strings"
+	"testing"
+	"time"
+
+	"github.com/gin-gonic/gin"
+	"github.com/stretchr/testify/assert"
+)
+
+func TestRateLimit(t *testing.T) {
+	t.Run("clients get 429 with Retry-After once their burst is used", func(t *testing.T) {
+		limiter := NewRateLimiter(0.5, 2)
+		now := time.Now()
+		limiter.now = func() time.Time { return now }
+
+		router := setupTestRouter()
+		router.POST("/ingest", RateLimit(limiter, NewClients([]string{"edge-1", "edge-2"})), func(c *gin.Context) {
+			c.Status(http.StatusAccepted)
+		})
+		send := func(apiKey string) *httptest.ResponseRecorder {
+			w := httptest.NewRecorder()
+			req, _ := http.NewRequest("POST", "/ingest", nil)
+			req.Header.Set(APIKeyHeader, apiKey)
+			router.ServeHTTP(w, req)
+			return w
+		}
+
+		assert.Equal(t, http.StatusAccepted, send("edge-1").Code)
+		assert.Equal(t, http.StatusAccepted, send("edge-1").Code)
+		limited := send("edge-1")
+		assert.Equal(t, http.StatusTooManyRequests, limited.Code)
+		assert.Equal(t, "2", limited.Header().Get("Retry-After"))
+
+		// Other keys have buckets of their own
+		assert.Equal(t, http.StatusAccepted, send("edge-2").Code)
+
+		now = now.Add(2 * time.Second)
+		assert.Equal(t, http.StatusAccepted, send("edge-1").Code)
+	})
+
+	t.Run("unknown keys share their client's IP bucket", func(t *testing.T) {
+		limiter := NewRateLimiter(0.5, 2)
+		now := time.Now()
+		limiter.now = func() time.Time { return now }
+
+		router := setupTestRouter()
+		router.POST("/ingest", RateLimit(limiter, NewClients([]string{"edge-1"})), func(c *gin.Context) {
+			c.Status(http.StatusAccepted)
+		})
+		send := func(apiKey string) *httptest.ResponseRecorder {
+			w := httptest.NewRecorder()
+			req, _ := http.NewRequest("POST", "/ingest", nil)
+			req.Header.Set(APIKeyHeader, apiKey)
+			router.ServeHTTP(w, req)
+			return w
+		}
+
+		// Making up a new key per request does not get a fresh bucket
+		assert.Equal(t, http.StatusAccepted, send("made-up-1").Code)
+		assert.Equal(t, http.StatusAccepted, send("made-up-2").Code)
+		assert.Equal(t, http.StatusTooManyRequests, send("made-up-3").Code)
+		assert.Equal(t, http.StatusTooManyRequests, send("").Code)
+
+		assert.Equal(t, http.StatusAccepted, send("edge-1").Code)
+	})
+}
+
+func TestBodyLimit(t *testing.T) {
+	router := setupTestRouter()
+	router.POST("/ingest", BodyLimit(8, nil), func(c *gin.Context) {
+		body, _ := c.GetRawData()
+		c.String(http.StatusAccepted, string(body))
+	})
+	send := func(body string, chunked bool) *httptest.ResponseRecorder {
+		w := httptest.NewRecorder()
+		req, _ := http.NewRequest("POST", "/ingest", strings.NewReader(body))
+		if chunked {
+			req.ContentLength = -1
+		}
+		router.ServeHTTP(w, req)
+		return w
+	}
+
+	t.Run("bodies within the limit reach the handler", func(t *testing.T) {
+		w := send(`{"a":1}`, false)
+		assert.Equal(t, http.StatusAccepted, w.Code)
+		assert.Equal(t, `{"a":1}`, w.Body.String())
+	})
+
+	t.Run("bodies over the limit get 413", func(t *testing.T) {
+		assert.Equal(t, http.StatusRequestEntityTooLarge, send(`{"a":12345}`, false).Code)
+		// Without a Content-Length the body is cut off at the limit
+		w := send(`{"a":12345}`, true)
+		assert.Equal(t, http.StatusRequestEntityTooLarge, w.Code)
+		assert.Contains(t, w.Body.String(), `"limit_bytes":8`)
+	})
+}
@@ -0,0 +1,13 @@
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+
+    // The descriptor set backs the gRPC reflection service.
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("streamforge_descriptor.bin"))
+        .compile(&["../../proto/streamforge/v1/grpc.proto"], &["../../proto"])?;
+
+    println!("cargo:rerun-if-changed=../../proto/streamforge/v1");
+    Ok(())
+}
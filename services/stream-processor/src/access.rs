@@ -0,0 +1,172 @@
+use crate::audit::ACTOR;
+use crate::errors;
+use crate::pipeline::timestamp;
+use crate::streamforge_v1::RoleBinding as RoleBindingProto;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, RwLock};
+use streamforge_core::config::RbacConfig;
+use streamforge_core::rbac::{self, Policy, RoleBinding};
+use streamforge_core::storage::StorageBackend;
+use tonic::metadata::MetadataValue;
+use tonic::{Request, Status};
+use tracing::warn;
+
+/// Metadata carrying the caller's API key.
+pub const API_KEY: &str = "x-api-key";
+
+/// Permission, granted in roles like an RPC, to read fields encrypted at
+/// rest in the clear.
+pub const DECRYPT_FIELDS: &str = "DecryptFields";
+
+/// Authorizes requests against the roles and bindings of `grpc.rbac`,
+/// together with the bindings managed over the API.
+pub struct Access {
+    config: RbacConfig,
+    storage: Arc<dyn StorageBackend>,
+    policy: RwLock<Arc<Policy>>,
+}
+
+impl Access {
+    /// Starts from the configured bindings alone until the first refresh.
+    pub fn new(config: RbacConfig, storage: Arc<dyn StorageBackend>) -> Self {
+        let policy = Policy::new(&config.roles, configured(&config));
+        Self {
+            config,
+            storage,
+            policy: RwLock::new(Arc::new(policy)),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn policy(&self) -> Arc<Policy> {
+        Arc::clone(&self.policy.read().unwrap())
+    }
+
+    /// Reloads the stored bindings, keeping the configured ones.
+    pub async fn refresh(&self) -> Result<()> {
+        let mut bindings = configured(&self.config);
+        bindings.extend(self.storage.list_role_bindings(None).await?);
+        *self.policy.write().unwrap() = Arc::new(Policy::new(&self.config.roles, bindings));
+        Ok(())
+    }
+
+    /// Refreshes the policy every `refresh_interval`, picking up bindings
+    /// changed through other instances.
+    pub async fn run(self: Arc<Self>) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut refresh = tokio::time::interval(self.config.refresh_interval);
+        loop {
+            refresh.tick().await;
+            if let Err(e) = self.refresh().await {
+                warn!("Failed to refresh role bindings: {}", e);
+            }
+        }
+    }
+
+    /// Checks that the caller may call `rpc` for `tenant`, or for every
+    /// tenant when the RPC is about none in particular. The caller is the
+    /// subject of their API key, or the user a gateway key forwards; the
+    /// actor metadata is replaced with that subject so the audit log records
+    /// who was authorized rather than who a client claimed to be.
+    pub fn authorize<T>(&self, request: &mut Request<T>, rpc: &str, tenant: Option<&str>) -> Result<(), Status> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let metadata = |key: &str| {
+            request
+                .metadata()
+                .get(key)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let key = metadata(API_KEY)
+            .and_then(|presented| rbac::api_key(&self.config, presented))
+            .ok_or_else(|| Status::unauthenticated(format!("A valid {} is required", API_KEY)))?;
+        let subject = match metadata(ACTOR) {
+            Some(actor) if key.forwards_identity => actor.to_string(),
+            _ => key.subject.clone(),
+        };
+
+        if !self.policy().permits(&subject, rpc, tenant) {
+            return Err(Status::permission_denied(match tenant {
+                Some(tenant) => format!("{} may not call {} in tenant {}", subject, rpc, tenant),
+                None => format!("{} may not call {} across tenants", subject, rpc),
+            }));
+        }
+
+        let actor = MetadataValue::try_from(subject.as_str())
+            .map_err(|_| Status::invalid_argument(format!("{} is not valid metadata", ACTOR)))?;
+        request.metadata_mut().insert(ACTOR, actor);
+        Ok(())
+    }
+
+    /// Whether the caller [`Access::authorize`] let through also holds
+    /// `permission` for `tenant`. Everyone does while RBAC is disabled.
+    pub fn allows<T>(&self, request: &Request<T>, permission: &str, tenant: Option<&str>) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        request
+            .metadata()
+            .get(ACTOR)
+            .and_then(|subject| subject.to_str().ok())
+            .map_or(false, |subject| self.policy().permits(subject, permission, tenant))
+    }
+}
+
+fn configured(config: &RbacConfig) -> Vec<RoleBinding> {
+    config.bindings.iter().map(RoleBinding::configured).collect()
+}
+
+/// Converts a request binding into a stored one. Every problem is reported
+/// at once in a `BadRequest`.
+pub fn from_proto(
+    binding: RoleBindingProto,
+    policy: &Policy,
+    created_by: impl Into<String>,
+    now: DateTime<Utc>,
+) -> Result<RoleBinding, Status> {
+    let mut violations = Vec::new();
+
+    if binding.subject.trim().is_empty() {
+        violations.push(("binding.subject", "is required".to_string()));
+    }
+    if !policy.is_role(&binding.role) {
+        violations.push(("binding.role", format!("no role is named {:?}", binding.role)));
+    }
+    if binding.tenants.is_empty() {
+        violations.push(("binding.tenants", "at least one tenant, or \"*\", is required".to_string()));
+    }
+    if binding.tenants.iter().any(|tenant| tenant.trim().is_empty()) {
+        violations.push(("binding.tenants", "must not contain empty tenants".to_string()));
+    }
+    if !violations.is_empty() {
+        return Err(errors::bad_request(violations));
+    }
+
+    Ok(RoleBinding {
+        subject: binding.subject,
+        role: binding.role,
+        tenants: binding.tenants,
+        created_by: created_by.into(),
+        created_at: now,
+    })
+}
+
+pub fn to_proto(binding: RoleBinding) -> RoleBindingProto {
+    RoleBindingProto {
+        subject: binding.subject,
+        role: binding.role,
+        tenants: binding.tenants,
+        created_by: binding.created_by,
+        created_at: Some(timestamp(binding.created_at)),
+    }
+}
@@ -0,0 +1,69 @@
+use crate::coordination::FORWARDED_BY;
+use crate::pipeline::timestamp;
+use crate::streamforge_v1::AuditEntry as AuditEntryProto;
+use streamforge_core::storage::AuditEntry;
+use tonic::Request;
+
+/// Metadata naming who a request is made on behalf of, set by the API
+/// gateway or CLI from the authenticated user.
+pub const ACTOR: &str = "x-streamforge-actor";
+
+/// Recorded as the actor of requests that do not name one.
+const ANONYMOUS: &str = "anonymous";
+
+/// Who made a request and where it came from, for the audit log.
+pub struct Caller {
+    actor: String,
+    source: String,
+}
+
+impl Caller {
+    /// Takes the source from the first `x-forwarded-for` hop when a proxy
+    /// set one, then the instance that forwarded the request, then the peer
+    /// address. Requests over the unix socket have no peer address.
+    pub fn of<T>(request: &Request<T>) -> Self {
+        let metadata = |key: &str| {
+            request
+                .metadata()
+                .get(key)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        let source = metadata("x-forwarded-for")
+            .and_then(|hops| hops.split(',').next().map(|hop| hop.trim().to_string()))
+            .or_else(|| metadata(FORWARDED_BY).map(|instance| format!("instance:{}", instance)))
+            .or_else(|| request.remote_addr().map(|addr| addr.to_string()))
+            .unwrap_or_else(|| "unix".to_string());
+
+        Self {
+            actor: metadata(ACTOR).unwrap_or_else(|| ANONYMOUS.to_string()),
+            source,
+        }
+    }
+
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    pub fn entry(&self, action: &str, target: impl Into<String>) -> AuditEntry {
+        AuditEntry::new(self.actor.clone(), self.source.clone(), action, target)
+    }
+}
+
+pub fn to_proto(entry: AuditEntry) -> AuditEntryProto {
+    AuditEntryProto {
+        id: entry.id,
+        occurred_at: Some(timestamp(entry.occurred_at)),
+        actor: entry.actor,
+        source: entry.source,
+        action: entry.action,
+        tenant_id: entry.tenant_id.unwrap_or_default(),
+        target: entry.target,
+        details: entry.details.to_string(),
+        outcome: entry.outcome,
+        error: entry.error.unwrap_or_default(),
+    }
+}
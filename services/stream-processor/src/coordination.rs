@@ -0,0 +1,309 @@
+use crate::access::API_KEY;
+use crate::audit::ACTOR;
+use crate::errors;
+use crate::jobs::{JobRegistry, JobState};
+use crate::processor::StreamProcessor;
+use crate::streamforge_v1::stream_processor_service_client::StreamProcessorServiceClient;
+use crate::streamforge_v1::{GetProcessingStatusRequest, GetProcessingStatusResponse};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use streamforge_core::config::{CoordinationConfig, ResourceLimits};
+use streamforge_core::storage::{JobLease, JobSnapshot, StorageBackend};
+use tokio::sync::Mutex;
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request, Response, Status};
+use tracing::{error, info, warn};
+
+/// Metadata naming the instance that forwarded a request. Forwarded requests
+/// are answered locally, so a stale lease cannot bounce them around.
+pub const FORWARDED_BY: &str = "x-streamforge-forwarded-by";
+
+/// Assigns each pipeline to one instance through leases in the shared
+/// database, and takes over the jobs of instances that stop renewing them.
+/// With coordination disabled, jobs simply run where they are started.
+pub struct Coordinator {
+    config: CoordinationConfig,
+    storage: Arc<dyn StorageBackend>,
+    processor: Arc<Mutex<StreamProcessor>>,
+    jobs: Arc<JobRegistry>,
+    /// Job ids of the pipelines this instance holds leases on.
+    held: Mutex<HashMap<String, String>>,
+}
+
+impl Coordinator {
+    pub fn new(
+        config: CoordinationConfig,
+        storage: Arc<dyn StorageBackend>,
+        processor: Arc<Mutex<StreamProcessor>>,
+        jobs: Arc<JobRegistry>,
+    ) -> Self {
+        Self {
+            config,
+            storage,
+            processor,
+            jobs,
+            held: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Starts a job for `pipeline_id` on this instance, provided no other
+    /// instance holds the pipeline's lease.
+    pub async fn start(&self, pipeline_id: &str, config: &str, limits: &ResourceLimits) -> Result<String, Status> {
+        self.acquire(pipeline_id, config, limits).await?;
+        self.launch(pipeline_id, config, limits).await.map_err(|e| {
+            error!("Failed to start processing: {}", e);
+            errors::error(
+                Code::Unavailable,
+                errors::PIPELINE_START_FAILED,
+                format!("Failed to start processing: {}", e),
+                true,
+            )
+        })
+    }
+
+    /// Restores a job from `snapshot` on this instance under the pipeline's
+    /// lease, like `start`. An instance taking the lease over later starts
+    /// the pipeline from its configuration and committed offsets.
+    pub async fn restore(&self, snapshot: &JobSnapshot, limits: &ResourceLimits) -> Result<String, Status> {
+        self.acquire(&snapshot.pipeline_id, &snapshot.config, limits).await?;
+        let restored = self.processor.lock().await.restore_pipeline(snapshot).await;
+        self.register(&snapshot.pipeline_id, &snapshot.config, restored).await.map_err(|e| {
+            error!("Failed to restore job: {}", e);
+            errors::error(
+                Code::Unavailable,
+                errors::RESTORE_FAILED,
+                format!("Failed to restore job: {}", e),
+                true,
+            )
+        })
+    }
+
+    /// Gives up the lease of a job stopped on this instance.
+    pub async fn stopped(&self, job_id: &str) {
+        let mut held = self.held.lock().await;
+        let Some(pipeline_id) = held.iter().find(|(_, id)| *id == job_id).map(|(p, _)| p.clone()) else {
+            return;
+        };
+        held.remove(&pipeline_id);
+        if let Err(e) = self.storage.release_job_lease(&pipeline_id, &self.config.instance_id).await {
+            warn!("Failed to release lease on pipeline {}: {}", pipeline_id, e);
+        }
+    }
+
+    /// Expires this instance's leases on shutdown so other instances take
+    /// its jobs over right away instead of after the lease TTL.
+    pub async fn hand_off(&self) {
+        let held: Vec<String> = self.held.lock().await.drain().map(|(pipeline_id, _)| pipeline_id).collect();
+        for pipeline_id in held {
+            if let Err(e) = self
+                .storage
+                .renew_job_lease(&pipeline_id, &self.config.instance_id, None, Utc::now())
+                .await
+            {
+                warn!("Failed to hand off pipeline {}: {}", pipeline_id, e);
+            }
+        }
+    }
+
+    /// The live lease of another instance running `job_id`, if any.
+    pub async fn owner(&self, job_id: &str) -> Result<Option<JobLease>, Status> {
+        if !self.config.enabled || self.jobs.get(job_id).is_some() {
+            return Ok(None);
+        }
+        let lease = self
+            .storage
+            .get_job_lease(job_id)
+            .await
+            .map_err(|e| Status::unavailable(format!("Failed to look up job owner: {}", e)))?;
+        Ok(lease.filter(|lease| lease.owner != self.config.instance_id && lease.expires_at > Utc::now()))
+    }
+
+    /// Asks the instance holding `lease` for the status of its job, passing
+    /// on the caller's credentials for the owner to authorize them again.
+    pub async fn forward_status(
+        &self,
+        lease: &JobLease,
+        request: GetProcessingStatusRequest,
+        metadata: &MetadataMap,
+    ) -> Result<Response<GetProcessingStatusResponse>, Status> {
+        let mut client = StreamProcessorServiceClient::connect(lease.endpoint.clone())
+            .await
+            .map_err(|e| Status::unavailable(format!("Failed to reach instance {}: {}", lease.owner, e)))?;
+
+        let mut request = Request::new(request);
+        let forwarded_by = self
+            .config
+            .instance_id
+            .parse()
+            .map_err(|_| Status::internal("Instance id is not valid metadata"))?;
+        request.metadata_mut().insert(FORWARDED_BY, forwarded_by);
+        for key in [API_KEY, ACTOR] {
+            if let Some(value) = metadata.get(key) {
+                request.metadata_mut().insert(key, value.clone());
+            }
+        }
+        client.get_processing_status(request).await
+    }
+
+    /// Renews this instance's leases and takes over expired ones until the
+    /// task is aborted.
+    pub async fn run(self: Arc<Self>) {
+        if !self.config.enabled {
+            return;
+        }
+        info!(
+            "Coordinating jobs as instance {} (lease TTL {:?})",
+            self.config.instance_id, self.config.lease_ttl
+        );
+
+        let mut interval = tokio::time::interval(self.config.renew_interval);
+        loop {
+            interval.tick().await;
+            self.renew().await;
+            if let Err(e) = self.take_over().await {
+                warn!("Failed to check for expired job leases: {}", e);
+            }
+        }
+    }
+
+    async fn renew(&self) {
+        let held: Vec<(String, String)> = self.held.lock().await.clone().into_iter().collect();
+        let expires_at = Utc::now() + self.ttl();
+
+        for (pipeline_id, job_id) in held {
+            // Jobs that ended here, e.g. at their scheduled maximum runtime
+            if self.jobs.get(&job_id).map_or(true, |job| job.state == JobState::Stopped) {
+                self.stopped(&job_id).await;
+                continue;
+            }
+
+            match self
+                .storage
+                .renew_job_lease(&pipeline_id, &self.config.instance_id, Some(&job_id), expires_at)
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => {
+                    // Another instance took over while this one was unreachable
+                    warn!("Lost lease on pipeline {}; stopping job {}", pipeline_id, job_id);
+                    self.held.lock().await.remove(&pipeline_id);
+                    match self.processor.lock().await.stop_pipeline(&job_id).await {
+                        Ok(_) => self.jobs.stopped(&job_id),
+                        Err(e) => error!("Failed to stop job {} after losing its lease: {}", job_id, e),
+                    }
+                }
+                Err(e) => warn!("Failed to renew lease on pipeline {}: {}", pipeline_id, e),
+            }
+        }
+    }
+
+    async fn take_over(&self) -> anyhow::Result<()> {
+        let now = Utc::now();
+        for expired in self.storage.list_expired_job_leases(now).await? {
+            // Still running here; the next renewal revives the lease
+            if self.held.lock().await.contains_key(&expired.pipeline_id) {
+                continue;
+            }
+            let lease = self.lease(&expired.pipeline_id, &expired.config, &expired.limits, now);
+            if !self.storage.acquire_job_lease(&lease).await? {
+                // Another instance got there first
+                continue;
+            }
+
+            info!(
+                "Taking over pipeline {} from instance {} (job {})",
+                expired.pipeline_id,
+                expired.owner,
+                expired.job_id.as_deref().unwrap_or("not started")
+            );
+            if let Err(e) = self.launch(&expired.pipeline_id, &expired.config, &expired.limits).await {
+                error!("Failed to take over pipeline {}: {}", expired.pipeline_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the lease on `pipeline_id` for this instance; nothing to take
+    /// when coordination is disabled.
+    async fn acquire(&self, pipeline_id: &str, config: &str, limits: &ResourceLimits) -> Result<(), Status> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let lease = self.lease(pipeline_id, config, limits, Utc::now());
+        let acquired = self.storage.acquire_job_lease(&lease).await.map_err(|e| {
+            error!("Failed to acquire lease on pipeline {}: {}", pipeline_id, e);
+            Status::unavailable(format!("Failed to acquire job lease: {}", e))
+        })?;
+        if !acquired {
+            return Err(errors::error(
+                Code::AlreadyExists,
+                errors::PIPELINE_OWNED,
+                format!("Pipeline {} is already running on another instance", pipeline_id),
+                false,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Starts a job under a lease this instance holds, or under none when
+    /// coordination is disabled.
+    async fn launch(&self, pipeline_id: &str, config: &str, limits: &ResourceLimits) -> anyhow::Result<String> {
+        let started = self.processor.lock().await.start_pipeline(pipeline_id, config, limits).await;
+        self.register(pipeline_id, config, started).await
+    }
+
+    /// Records a job started or restored under the lease on `pipeline_id`.
+    /// A job that failed to start gives the lease up so another instance can
+    /// try.
+    async fn register(
+        &self,
+        pipeline_id: &str,
+        config: &str,
+        started: anyhow::Result<String>,
+    ) -> anyhow::Result<String> {
+        let job_id = match started {
+            Ok(job_id) => job_id,
+            Err(e) => {
+                if self.config.enabled {
+                    if let Err(e) = self.storage.release_job_lease(pipeline_id, &self.config.instance_id).await {
+                        warn!("Failed to release lease on pipeline {}: {}", pipeline_id, e);
+                    }
+                }
+                return Err(e);
+            }
+        };
+        self.jobs.started(&job_id, pipeline_id, config);
+
+        if self.config.enabled {
+            // The next renewal retries recording the job id if this fails
+            self.held.lock().await.insert(pipeline_id.to_string(), job_id.clone());
+            let expires_at = Utc::now() + self.ttl();
+            if let Err(e) = self
+                .storage
+                .renew_job_lease(pipeline_id, &self.config.instance_id, Some(&job_id), expires_at)
+                .await
+            {
+                warn!("Failed to record job {} on its lease: {}", job_id, e);
+            }
+        }
+        Ok(job_id)
+    }
+
+    fn lease(&self, pipeline_id: &str, config: &str, limits: &ResourceLimits, now: DateTime<Utc>) -> JobLease {
+        JobLease {
+            pipeline_id: pipeline_id.to_string(),
+            job_id: None,
+            owner: self.config.instance_id.clone(),
+            endpoint: self.config.advertise_url.clone().unwrap_or_default(),
+            config: config.to_string(),
+            limits: limits.clone(),
+            acquired_at: now,
+            expires_at: now + self.ttl(),
+        }
+    }
+
+    fn ttl(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.config.lease_ttl).unwrap_or_else(|_| chrono::Duration::seconds(15))
+    }
+}
@@ -0,0 +1,226 @@
+use crate::errors;
+use crate::pipeline::timestamp;
+use crate::streamforge_v1::{
+    DeadLetter as DeadLetterProto, DeadLetterEnvelope, DeadLetterFailure, DeadLetterRef, DeadLetterSelector,
+};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use streamforge_core::config::Config;
+use streamforge_core::dlq::{self, DeadLetter, DeadLetterPage, DeadLetterQuery};
+use streamforge_core::faults::FaultInjector;
+use tonic::Status;
+use tracing::error;
+
+/// How long one read of the dead letter topic may take.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Dead letters examined by one redrive request; larger selections continue
+/// from the returned page token.
+pub const MAX_REDRIVE_BATCH: usize = 1000;
+
+/// Dead letter topic a request addresses; empty means the configured one.
+pub fn topic(topic: String, config: &Config) -> String {
+    if topic.is_empty() {
+        config.processing.dead_letter_queue_topic.clone()
+    } else {
+        topic
+    }
+}
+
+/// Validates an optional `[start_time, end_time)` range.
+pub fn time_range(
+    start_time: Option<prost_types::Timestamp>,
+    end_time: Option<prost_types::Timestamp>,
+) -> Result<(Option<DateTime<Utc>>, Option<DateTime<Utc>>), Status> {
+    let time = |ts: Option<prost_types::Timestamp>, field: &str| {
+        ts.map(|ts| {
+            DateTime::from_timestamp(ts.seconds, ts.nanos as u32)
+                .ok_or_else(|| Status::invalid_argument(format!("{} is out of range", field)))
+        })
+        .transpose()
+    };
+    let (start, end) = (time(start_time, "start_time")?, time(end_time, "end_time")?);
+    if let (Some(start), Some(end)) = (start, end) {
+        if end <= start {
+            return Err(Status::invalid_argument("end_time must be after start_time"));
+        }
+    }
+    Ok((start, end))
+}
+
+/// What a redrive selected, for the audit log.
+pub fn selector_details(selector: Option<&DeadLetterSelector>) -> serde_json::Value {
+    let Some(selector) = selector else {
+        return serde_json::json!({});
+    };
+    let time = |ts: &Option<prost_types::Timestamp>| {
+        ts.as_ref()
+            .and_then(|ts| DateTime::from_timestamp(ts.seconds, ts.nanos as u32))
+            .map(|time| time.to_rfc3339())
+    };
+    serde_json::json!({
+        "refs": selector.refs.iter().map(|r| format!("{}@{}", r.partition, r.offset)).collect::<Vec<_>>(),
+        "start_time": time(&selector.start_time),
+        "end_time": time(&selector.end_time),
+        "original_topic": selector.original_topic,
+    })
+}
+
+pub fn page_positions(page_token: &str) -> Result<BTreeMap<i32, i64>, Status> {
+    dlq::parse_page_token(page_token).map_err(|e| Status::invalid_argument(format!("Invalid page_token: {}", e)))
+}
+
+/// Reads from the dead letter topic on a blocking task.
+pub async fn read(config: Config, query: DeadLetterQuery) -> Result<DeadLetterPage, Status> {
+    tokio::task::spawn_blocking(move || dlq::read(&config, &query, READ_TIMEOUT))
+        .await
+        .map_err(|e| Status::internal(format!("Failed to read dead letters: {}", e)))?
+        .map_err(|e| {
+            error!("Failed to read dead letters: {}", e);
+            Status::unavailable(format!("Failed to read dead letters: {}", e))
+        })
+}
+
+/// The dead letters a redrive selector picks out of its topic.
+pub struct Selection {
+    pub query: DeadLetterQuery,
+    /// Explicitly selected letters not yet reached; empty when the selector
+    /// picks by range instead.
+    refs: BTreeSet<(i32, i64)>,
+}
+
+impl Selection {
+    pub fn new(topic: String, selector: Option<DeadLetterSelector>, page_token: &str) -> Result<Self, Status> {
+        let selector = selector.unwrap_or_default();
+        let (start, end) = time_range(selector.start_time, selector.end_time)?;
+        let original_topic = Some(selector.original_topic).filter(|topic| !topic.is_empty());
+        let refs: BTreeSet<(i32, i64)> = selector.refs.iter().map(|r| (r.partition, r.offset)).collect();
+        if refs.is_empty() && start.is_none() && original_topic.is_none() {
+            return Err(errors::bad_request(vec![(
+                "selector",
+                "must list refs or set start_time or original_topic".to_string(),
+            )]));
+        }
+
+        let mut positions = page_positions(page_token)?;
+        let refs = if page_token.is_empty() {
+            // Start each partition at its first selected letter
+            for &(partition, offset) in &refs {
+                let position = positions.entry(partition).or_insert(offset);
+                *position = (*position).min(offset);
+            }
+            refs
+        } else {
+            // Letters before a partition's position were handled by earlier pages
+            refs.into_iter()
+                .filter(|(partition, offset)| positions.get(partition).map_or(false, |p| offset >= p))
+                .collect()
+        };
+
+        Ok(Self {
+            query: DeadLetterQuery {
+                topic,
+                start,
+                end,
+                original_topic,
+                positions,
+                limit: MAX_REDRIVE_BATCH,
+            },
+            refs,
+        })
+    }
+
+    /// Keeps the selected letters of a page read with `query`. Returns them
+    /// with the refs that can no longer be found and the token continuing
+    /// the selection.
+    pub fn select(&self, page: DeadLetterPage) -> (Vec<DeadLetter>, Vec<DeadLetterFailure>, String) {
+        if self.refs.is_empty() {
+            let next_page_token = page.next_page_token();
+            return (page.letters, Vec::new(), next_page_token);
+        }
+
+        let letters: Vec<DeadLetter> = page
+            .letters
+            .into_iter()
+            .filter(|letter| self.refs.contains(&(letter.partition, letter.offset)))
+            .collect();
+        let found: BTreeSet<(i32, i64)> = letters.iter().map(|l| (l.partition, l.offset)).collect();
+
+        let (pending, missing): (Vec<_>, Vec<_>) = self
+            .refs
+            .difference(&found)
+            .partition(|(partition, offset)| page.positions.get(partition).map_or(false, |p| offset >= p));
+        let failures = missing
+            .into_iter()
+            .map(|&(partition, offset)| DeadLetterFailure {
+                r#ref: Some(DeadLetterRef { partition, offset }),
+                message: format!("No dead letter at {}@{} in {}", partition, offset, self.query.topic),
+            })
+            .collect();
+
+        let partitions: BTreeSet<i32> = pending.iter().map(|(partition, _)| *partition).collect();
+        let next = DeadLetterPage {
+            letters: Vec::new(),
+            positions: page
+                .positions
+                .into_iter()
+                .filter(|(partition, _)| partitions.contains(partition))
+                .collect(),
+        };
+        (letters, failures, next.next_page_token())
+    }
+}
+
+/// Republishes `letters`, returning how many were sent and the failures.
+pub async fn redrive(config: &Config, letters: &[DeadLetter]) -> Result<(i64, Vec<DeadLetterFailure>), Status> {
+    if letters.is_empty() {
+        return Ok((0, Vec::new()));
+    }
+    let producer = dlq::producer(config).map_err(|e| Status::unavailable(e.to_string()))?;
+    let faults = FaultInjector::kafka(config);
+
+    let mut redriven = 0;
+    let mut failures = Vec::new();
+    for letter in letters {
+        match faults.run("dlq.redrive", || dlq::redrive(&producer, letter)).await {
+            Ok(()) => redriven += 1,
+            Err(e) => {
+                error!("Failed to redrive dead letter {}@{}: {}", letter.partition, letter.offset, e);
+                failures.push(DeadLetterFailure {
+                    r#ref: Some(DeadLetterRef {
+                        partition: letter.partition,
+                        offset: letter.offset,
+                    }),
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
+    Ok((redriven, failures))
+}
+
+pub fn to_proto(letter: DeadLetter) -> DeadLetterProto {
+    DeadLetterProto {
+        partition: letter.partition,
+        offset: letter.offset,
+        key: letter.key.unwrap_or_default(),
+        payload: letter.payload,
+        headers: letter.headers.into_iter().collect(),
+        envelope: Some(DeadLetterEnvelope {
+            original_topic: letter.envelope.original_topic,
+            original_partition: letter.envelope.original_partition,
+            original_offset: letter.envelope.original_offset,
+            error: letter.envelope.error,
+            failed_at: Some(timestamp(letter.envelope.failed_at)),
+            version: letter.envelope.version,
+            pipeline_id: letter.envelope.pipeline_id.unwrap_or_default(),
+            stage: letter.envelope.stage,
+            error_class: letter.envelope.error_class.as_str().to_string(),
+            causes: letter.envelope.causes,
+            context: letter.envelope.context.into_iter().collect(),
+            attempts: letter.envelope.attempts,
+            received_at: letter.envelope.received_at.map(timestamp),
+        }),
+    }
+}
@@ -0,0 +1,221 @@
+use crate::errors;
+use crate::jobs::{Job, JobRegistry};
+use crate::pipeline::timestamp;
+use crate::streamforge_v1::{
+    Deployment as DeploymentProto, DeploymentComparison, DeploymentMode, StartDeploymentRequest, VersionMetrics,
+};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use streamforge_core::canary::{candidate_config, CanaryMode, CanarySettings, Comparison, VersionStats};
+use streamforge_core::config::ResourceLimits;
+use tonic::Status;
+
+/// Finished deployments kept for GetDeployment; the oldest are forgotten
+/// beyond this. The audit log keeps the lasting record of each.
+const MAX_FINISHED_DEPLOYMENTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeploymentState {
+    Running,
+    Promoted,
+    RolledBack,
+}
+
+impl DeploymentState {
+    fn as_str(self) -> &'static str {
+        match self {
+            DeploymentState::Running => "running",
+            DeploymentState::Promoted => "promoted",
+            DeploymentState::RolledBack => "rolled_back",
+        }
+    }
+}
+
+/// A candidate version running beside a baseline job.
+#[derive(Debug, Clone)]
+pub struct Deployment {
+    pub deployment_id: String,
+    pub pipeline_id: String,
+    pub baseline_job_id: String,
+    pub candidate_job_id: String,
+    pub settings: CanarySettings,
+    /// Configuration of the new version as requested, which promotion starts.
+    pub config: String,
+    /// Limits of the candidate job, which the promoted job keeps.
+    pub limits: ResourceLimits,
+    pub baseline_fingerprint: String,
+    pub candidate_fingerprint: String,
+    pub state: DeploymentState,
+    pub promoted_job_id: Option<String>,
+    /// Comparison when the deployment finished; running ones are compared
+    /// on every read.
+    pub comparison: Option<Comparison>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Pipeline id the candidate job of a deployment runs and is leased under,
+/// so it does not contend with the baseline for the pipeline's lease.
+pub fn candidate_pipeline_id(pipeline_id: &str, settings: &CanarySettings) -> String {
+    format!("{}@{}", pipeline_id, settings.namespace)
+}
+
+/// The settings of a StartDeployment request for a new deployment, and the
+/// candidate's job configuration. Every problem is reported at once in a
+/// `BadRequest`.
+pub fn request_from_proto(
+    deployment_id: &str,
+    req: &StartDeploymentRequest,
+    default_group: &str,
+) -> Result<(CanarySettings, String), Status> {
+    let mut violations = Vec::new();
+    if req.baseline_job_id.is_empty() {
+        violations.push(("baseline_job_id", "is required".to_string()));
+    }
+    let mode = match DeploymentMode::try_from(req.mode) {
+        Ok(DeploymentMode::Unspecified | DeploymentMode::Shadow) => CanaryMode::Shadow,
+        Ok(DeploymentMode::Canary) => CanaryMode::Canary,
+        Err(_) => {
+            violations.push(("mode", format!("unknown deployment mode {}", req.mode)));
+            CanaryMode::Shadow
+        }
+    };
+    let settings = CanarySettings {
+        deployment_id: deployment_id.to_string(),
+        mode,
+        sample_percent: if mode == CanaryMode::Shadow { 100.0 } else { req.sample_percent },
+        namespace: Some(req.namespace.clone())
+            .filter(|namespace| !namespace.is_empty())
+            .unwrap_or_else(|| format!("canary-{}", deployment_id)),
+    };
+
+    let config = match serde_json::from_str::<serde_json::Value>(&req.config) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            violations.push(("config", format!("is not valid JSON: {}", e)));
+            None
+        }
+    };
+    let candidate = config.map(|config| candidate_config(&config, &settings, default_group));
+    match candidate {
+        Some(Ok(candidate)) if violations.is_empty() => Ok((settings, candidate.to_string())),
+        Some(Err(e)) => {
+            violations.push(("config", e));
+            Err(errors::bad_request(violations))
+        }
+        _ => Err(errors::bad_request(violations)),
+    }
+}
+
+/// Deployments started through this instance.
+#[derive(Debug, Default)]
+pub struct Deployments {
+    deployments: RwLock<HashMap<String, Deployment>>,
+}
+
+impl Deployments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The running deployment of `pipeline_id`, if there is one; a
+    /// pipeline runs one candidate at a time.
+    pub fn running(&self, pipeline_id: &str) -> Option<Deployment> {
+        self.deployments
+            .read()
+            .unwrap()
+            .values()
+            .find(|deployment| deployment.pipeline_id == pipeline_id && deployment.state == DeploymentState::Running)
+            .cloned()
+    }
+
+    pub fn started(&self, deployment: Deployment) {
+        self.deployments.write().unwrap().insert(deployment.deployment_id.clone(), deployment);
+    }
+
+    pub fn get(&self, deployment_id: &str) -> Option<Deployment> {
+        self.deployments.read().unwrap().get(deployment_id).cloned()
+    }
+
+    /// Ends a running deployment, fixing its comparison as of now.
+    pub fn finished(
+        &self,
+        deployment_id: &str,
+        state: DeploymentState,
+        promoted_job_id: Option<String>,
+        jobs: &JobRegistry,
+    ) -> Option<Deployment> {
+        let mut deployments = self.deployments.write().unwrap();
+        let deployment = deployments.get_mut(deployment_id).map(|deployment| {
+            deployment.comparison = Some(compare(deployment, jobs));
+            deployment.state = state;
+            deployment.promoted_job_id = promoted_job_id;
+            deployment.finished_at = Some(Utc::now());
+            deployment.clone()
+        });
+
+        let mut finished: Vec<(DateTime<Utc>, String)> = deployments
+            .values()
+            .filter_map(|deployment| deployment.finished_at.map(|at| (at, deployment.deployment_id.clone())))
+            .collect();
+        if finished.len() > MAX_FINISHED_DEPLOYMENTS {
+            finished.sort();
+            for (_, deployment_id) in finished.drain(..finished.len() - MAX_FINISHED_DEPLOYMENTS) {
+                deployments.remove(&deployment_id);
+            }
+        }
+        deployment
+    }
+}
+
+/// The baseline and candidate jobs' counts so far.
+pub fn compare(deployment: &Deployment, jobs: &JobRegistry) -> Comparison {
+    let stats = |job: Option<Job>| {
+        job.map(|job| VersionStats {
+            messages_processed: job.messages_processed,
+            errors: job.errors,
+            throughput: job.throughput(),
+        })
+        .unwrap_or_default()
+    };
+    Comparison::new(
+        stats(jobs.get(&deployment.baseline_job_id)),
+        stats(jobs.get(&deployment.candidate_job_id)),
+        deployment.settings.share(),
+    )
+}
+
+pub fn to_proto(deployment: Deployment, jobs: &JobRegistry) -> DeploymentProto {
+    let comparison = deployment.comparison.unwrap_or_else(|| compare(&deployment, jobs));
+    let metrics = |stats: VersionStats| VersionMetrics {
+        messages_processed: stats.messages_processed as i64,
+        errors: stats.errors as i64,
+        error_rate: stats.error_rate(),
+        throughput: stats.throughput,
+    };
+    DeploymentProto {
+        deployment_id: deployment.deployment_id,
+        pipeline_id: deployment.pipeline_id,
+        baseline_job_id: deployment.baseline_job_id,
+        candidate_job_id: deployment.candidate_job_id,
+        mode: match deployment.settings.mode {
+            CanaryMode::Shadow => DeploymentMode::Shadow,
+            CanaryMode::Canary => DeploymentMode::Canary,
+        } as i32,
+        sample_percent: deployment.settings.sample_percent,
+        namespace: deployment.settings.namespace,
+        state: deployment.state.as_str().to_string(),
+        promoted_job_id: deployment.promoted_job_id.unwrap_or_default(),
+        baseline_fingerprint: deployment.baseline_fingerprint,
+        candidate_fingerprint: deployment.candidate_fingerprint,
+        comparison: Some(DeploymentComparison {
+            baseline: Some(metrics(comparison.baseline)),
+            candidate: Some(metrics(comparison.candidate)),
+            error_rate_delta: comparison.error_rate_delta,
+            throughput_ratio: comparison.throughput_ratio,
+        }),
+        started_at: Some(timestamp(deployment.started_at)),
+        finished_at: deployment.finished_at.map(timestamp),
+    }
+}
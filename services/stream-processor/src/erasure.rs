@@ -0,0 +1,213 @@
+use crate::dead_letters::time_range;
+use crate::errors;
+use crate::pipeline::timestamp;
+use crate::streamforge_v1::{DataDeletion as DataDeletionProto, DeleteDataRequest};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use streamforge_core::storage::{AttributeMatch, ErasureDataset, ErasureMode, ErasureRequest, ErasureSelector};
+use tonic::Status;
+
+/// Finished deletions kept for GetDataDeletion; the oldest are forgotten
+/// beyond this. The audit log keeps the lasting record of each.
+const MAX_FINISHED_DELETIONS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeletionState {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl DeletionState {
+    fn as_str(self) -> &'static str {
+        match self {
+            DeletionState::Running => "running",
+            DeletionState::Succeeded => "succeeded",
+            DeletionState::Failed => "failed",
+        }
+    }
+}
+
+/// Progress of one DeleteData request.
+#[derive(Debug, Clone)]
+pub struct Deletion {
+    pub deletion_id: String,
+    pub tenant_id: String,
+    pub requested_by: String,
+    pub state: DeletionState,
+    /// Records erased so far from each dataset started on.
+    pub records: BTreeMap<ErasureDataset, u64>,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Deletions started through this instance, so clients can follow one
+/// after DeleteData has returned.
+#[derive(Debug, Default)]
+pub struct Deletions {
+    deletions: RwLock<HashMap<String, Deletion>>,
+}
+
+impl Deletions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn started(&self, deletion_id: &str, tenant_id: &str, requested_by: &str) -> Deletion {
+        let deletion = Deletion {
+            deletion_id: deletion_id.to_string(),
+            tenant_id: tenant_id.to_string(),
+            requested_by: requested_by.to_string(),
+            state: DeletionState::Running,
+            records: BTreeMap::new(),
+            error: None,
+            started_at: Utc::now(),
+            finished_at: None,
+        };
+        self.deletions.write().unwrap().insert(deletion_id.to_string(), deletion.clone());
+        deletion
+    }
+
+    pub fn progress(&self, deletion_id: &str, dataset: ErasureDataset, records: u64) {
+        if let Some(deletion) = self.deletions.write().unwrap().get_mut(deletion_id) {
+            deletion.records.insert(dataset, records);
+        }
+    }
+
+    pub fn finished(&self, deletion_id: &str, result: &anyhow::Result<u64>) -> Option<Deletion> {
+        let mut deletions = self.deletions.write().unwrap();
+        let deletion = deletions.get_mut(deletion_id).map(|deletion| {
+            deletion.finished_at = Some(Utc::now());
+            match result {
+                Ok(_) => deletion.state = DeletionState::Succeeded,
+                Err(e) => {
+                    deletion.state = DeletionState::Failed;
+                    deletion.error = Some(e.to_string());
+                }
+            }
+            deletion.clone()
+        });
+
+        let mut finished: Vec<(DateTime<Utc>, String)> = deletions
+            .values()
+            .filter_map(|deletion| deletion.finished_at.map(|at| (at, deletion.deletion_id.clone())))
+            .collect();
+        if finished.len() > MAX_FINISHED_DELETIONS {
+            finished.sort();
+            for (_, deletion_id) in finished.drain(..finished.len() - MAX_FINISHED_DELETIONS) {
+                deletions.remove(&deletion_id);
+            }
+        }
+        deletion
+    }
+
+    /// The deletion, only when it belongs to `tenant_id`.
+    pub fn get(&self, tenant_id: &str, deletion_id: &str) -> Option<Deletion> {
+        self.deletions
+            .read()
+            .unwrap()
+            .get(deletion_id)
+            .filter(|deletion| deletion.tenant_id == tenant_id)
+            .cloned()
+    }
+}
+
+/// Converts a DeleteData request for `tenant_id`. A request must narrow the
+/// records by attribute or by time, so that a mistyped request cannot erase
+/// all of a tenant's data; offboarding a tenant takes an explicit time range.
+/// Every problem is reported at once in a `BadRequest`.
+pub fn request_from_proto(tenant_id: String, req: DeleteDataRequest) -> Result<ErasureRequest, Status> {
+    let mut violations = Vec::new();
+
+    let mut datasets = Vec::new();
+    for dataset in &req.datasets {
+        match ErasureDataset::ALL.into_iter().find(|d| d.as_str() == dataset) {
+            Some(dataset) if !datasets.contains(&dataset) => datasets.push(dataset),
+            Some(_) => {}
+            None => violations.push(("datasets", format!("unknown dataset {:?}", dataset))),
+        }
+    }
+    if req.datasets.is_empty() {
+        datasets = ErasureDataset::ALL.to_vec();
+    }
+
+    let mode = match req.mode.as_str() {
+        "" | "delete" => Some(ErasureMode::Delete),
+        "scrub" => Some(ErasureMode::Scrub),
+        other => {
+            violations.push(("mode", format!("must be \"delete\" or \"scrub\", not {:?}", other)));
+            None
+        }
+    };
+
+    let attribute = Some(req.attribute)
+        .filter(|path| !path.is_empty())
+        .map(|path| AttributeMatch::new(&path, req.value));
+    if let Some(attribute) = &attribute {
+        if attribute.path.iter().any(|key| key.is_empty()) {
+            violations.push(("attribute", "must be a dotted path without empty keys".to_string()));
+        }
+        if attribute.value.is_empty() {
+            violations.push(("value", "is required with an attribute".to_string()));
+        }
+    }
+    if mode == Some(ErasureMode::Scrub) && attribute.is_none() {
+        violations.push(("attribute", "is required to scrub".to_string()));
+    }
+
+    let (start_time, end_time) = match time_range(req.start_time, req.end_time) {
+        Ok(range) => range,
+        Err(status) => {
+            violations.push(("end_time", status.message().to_string()));
+            (None, None)
+        }
+    };
+    if attribute.is_none() && start_time.is_none() && end_time.is_none() {
+        violations.push(("attribute", "or a time range is required".to_string()));
+    }
+
+    match mode {
+        Some(mode) if violations.is_empty() => Ok(ErasureRequest {
+            selector: ErasureSelector {
+                tenant_id,
+                attribute,
+                start_time,
+                end_time,
+            },
+            mode,
+            datasets,
+        }),
+        _ => Err(errors::bad_request(violations)),
+    }
+}
+
+/// What a deletion selected, for the audit log. The attribute value is left
+/// out: it is usually the personal data being erased.
+pub fn request_details(request: &ErasureRequest) -> serde_json::Value {
+    serde_json::json!({
+        "datasets": request.datasets.iter().map(|d| d.as_str()).collect::<Vec<_>>(),
+        "mode": request.mode,
+        "attribute": request.selector.attribute.as_ref().map(|a| a.path.join(".")),
+        "start_time": request.selector.start_time,
+        "end_time": request.selector.end_time,
+    })
+}
+
+pub fn to_proto(deletion: Deletion) -> DataDeletionProto {
+    DataDeletionProto {
+        deletion_id: deletion.deletion_id,
+        tenant_id: deletion.tenant_id,
+        requested_by: deletion.requested_by,
+        state: deletion.state.as_str().to_string(),
+        records_erased: deletion
+            .records
+            .into_iter()
+            .map(|(dataset, records)| (dataset.as_str().to_string(), records as i64))
+            .collect(),
+        error: deletion.error.unwrap_or_default(),
+        started_at: Some(timestamp(deletion.started_at)),
+        finished_at: deletion.finished_at.map(timestamp),
+    }
+}
@@ -0,0 +1,79 @@
+use crate::jobs::DuplicateJob;
+use std::collections::HashMap;
+use std::time::Duration;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, FieldViolation, StatusExt};
+
+/// `google.rpc.ErrorInfo.domain` of errors raised by this service.
+pub const ERROR_DOMAIN: &str = "streamforge.dev";
+
+/// How long clients should wait before retrying a retryable error.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Machine-readable `google.rpc.ErrorInfo.reason` values.
+pub const JOB_NOT_FOUND: &str = "JOB_NOT_FOUND";
+pub const JOB_NOT_RUNNING: &str = "JOB_NOT_RUNNING";
+pub const JOB_NOT_PAUSED: &str = "JOB_NOT_PAUSED";
+pub const PIPELINE_START_FAILED: &str = "PIPELINE_START_FAILED";
+pub const PIPELINE_OWNED: &str = "PIPELINE_OWNED";
+pub const DUPLICATE_JOB: &str = "DUPLICATE_JOB";
+pub const JOB_STOP_FAILED: &str = "JOB_STOP_FAILED";
+pub const JOB_PAUSE_FAILED: &str = "JOB_PAUSE_FAILED";
+pub const JOB_RESUME_FAILED: &str = "JOB_RESUME_FAILED";
+pub const SNAPSHOT_FAILED: &str = "SNAPSHOT_FAILED";
+pub const RESTORE_FAILED: &str = "RESTORE_FAILED";
+pub const DEPLOYMENT_NOT_FOUND: &str = "DEPLOYMENT_NOT_FOUND";
+pub const DEPLOYMENT_RUNNING: &str = "DEPLOYMENT_RUNNING";
+pub const DEPLOYMENT_NOT_RUNNING: &str = "DEPLOYMENT_NOT_RUNNING";
+
+/// A status carrying `ErrorInfo` with `reason`, its retryability in the
+/// `retryable` metadata entry and, when retryable, a `RetryInfo` delay.
+pub fn error(code: Code, reason: &str, message: impl Into<String>, retryable: bool) -> Status {
+    let metadata = HashMap::from([("retryable".to_string(), retryable.to_string())]);
+    let mut details = ErrorDetails::with_error_info(reason, ERROR_DOMAIN, metadata);
+    if retryable {
+        details.set_retry_info(Some(RETRY_DELAY));
+    }
+    Status::with_error_details(code, message, details)
+}
+
+/// INVALID_ARGUMENT with a `BadRequest` listing each `(field, description)`.
+pub fn bad_request(violations: Vec<(&str, String)>) -> Status {
+    let message = violations
+        .iter()
+        .map(|(field, description)| format!("{}: {}", field, description))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let violations = violations
+        .into_iter()
+        .map(|(field, description)| FieldViolation::new(field, description))
+        .collect();
+    Status::with_error_details(Code::InvalidArgument, message, ErrorDetails::with_bad_request(violations))
+}
+
+pub fn job_not_found(job_id: &str) -> Status {
+    error(Code::NotFound, JOB_NOT_FOUND, format!("Job {} not found", job_id), false)
+}
+
+pub fn deployment_not_found(deployment_id: &str) -> Status {
+    error(
+        Code::NotFound,
+        DEPLOYMENT_NOT_FOUND,
+        format!("Deployment {} not found on this instance", deployment_id),
+        false,
+    )
+}
+
+pub fn duplicate_job(duplicate: DuplicateJob) -> Status {
+    let message = match duplicate {
+        DuplicateJob::Running(job) => format!(
+            "Job {} of pipeline {} already runs this configuration ({}) in consumer group {}",
+            job.job_id, job.pipeline_id, job.fingerprint, job.consumer_group
+        ),
+        DuplicateJob::Starting { fingerprint, consumer_group } => format!(
+            "A job with this configuration ({}) is already starting in consumer group {}",
+            fingerprint, consumer_group
+        ),
+    };
+    error(Code::AlreadyExists, DUPLICATE_JOB, message, false)
+}
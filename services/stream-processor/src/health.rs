@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use streamforge_core::health::HealthState;
+use tonic::server::NamedService;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+/// How often subsystem readiness is mirrored into the gRPC health service.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Mirrors the readiness registry into `grpc.health.v1.Health`. Every
+/// component is reported under its own name (e.g. `storage`); the empty
+/// service name and `S` report overall readiness, so a plain health check
+/// from a load balancer fails as soon as any subsystem is down.
+pub async fn report<S: NamedService>(health: Arc<HealthState>, mut reporter: HealthReporter) {
+    let mut reported: BTreeMap<String, ServingStatus> = BTreeMap::new();
+    let mut interval = tokio::time::interval(REPORT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let overall = serving_status(health.is_ready());
+        let mut statuses: Vec<(String, ServingStatus)> = health
+            .snapshot()
+            .into_iter()
+            .map(|(component, state)| (component.to_string(), serving_status(state.ready)))
+            .collect();
+        statuses.push((String::new(), overall));
+        statuses.push((S::NAME.to_string(), overall));
+
+        for (service, status) in statuses {
+            if reported.get(&service) != Some(&status) {
+                reporter.set_service_status(&service, status).await;
+                reported.insert(service, status);
+            }
+        }
+    }
+}
+
+fn serving_status(ready: bool) -> ServingStatus {
+    if ready {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    }
+}
@@ -0,0 +1,261 @@
+use crate::jobs::JobRegistry;
+use crate::streamforge_v1::{RecordError, StreamData, StreamDataAck, StreamDataResponse};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Arc;
+use streamforge_core::config::GrpcConfig;
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+use tonic::Status;
+use tracing::warn;
+
+/// Records processed since the last acknowledgement.
+#[derive(Default)]
+struct PendingAck {
+    first_sequence: Option<u64>,
+    last_sequence: u64,
+    records: u32,
+    errors: Vec<RecordError>,
+}
+
+impl PendingAck {
+    fn push(&mut self, sequence: u64, error: Option<RecordError>) {
+        self.first_sequence.get_or_insert(sequence);
+        self.last_sequence = sequence;
+        self.records += 1;
+        self.errors.extend(error);
+    }
+
+    /// The acknowledgement for everything pending, which also hands the
+    /// acknowledged records' credits back to the client.
+    fn take(&mut self) -> StreamDataResponse {
+        let pending = std::mem::take(self);
+        let message = match pending.errors.len() {
+            0 => format!("{} record(s) processed", pending.records),
+            failed => format!("{} of {} record(s) failed", failed, pending.records),
+        };
+        StreamDataResponse {
+            success: pending.errors.is_empty(),
+            message,
+            credits: pending.records,
+            ack: Some(StreamDataAck {
+                first_sequence: pending.first_sequence.unwrap_or_default(),
+                last_sequence: pending.last_sequence,
+                errors: pending.errors,
+            }),
+        }
+    }
+}
+
+/// Serves one SendStreamData stream with credit-based flow control. The
+/// first response grants `stream_credits` records; each acknowledgement
+/// returns the credits of the records it covers. A client sending without
+/// credit gets RESOURCE_EXHAUSTED and the stream ends. Each record is
+/// handed to `process` in order.
+pub async fn serve<S, P, F, E>(
+    mut stream: S,
+    tx: mpsc::Sender<Result<StreamDataResponse, Status>>,
+    jobs: Arc<JobRegistry>,
+    settings: GrpcConfig,
+    mut process: P,
+) where
+    S: Stream<Item = Result<StreamData, Status>> + Unpin,
+    P: FnMut(StreamData) -> F,
+    F: Future<Output = Result<(), E>>,
+    E: Display,
+{
+    let grant = StreamDataResponse {
+        success: true,
+        message: String::new(),
+        credits: settings.stream_credits,
+        ack: None,
+    };
+    if tx.send(Ok(grant)).await.is_err() {
+        return;
+    }
+
+    let mut credits = settings.stream_credits;
+    let mut pending = PendingAck::default();
+    // The first tick waits a full interval, not acknowledging the first record on sight
+    let start = tokio::time::Instant::now() + settings.stream_ack_interval;
+    let mut ack_timer = tokio::time::interval_at(start, settings.stream_ack_interval);
+
+    loop {
+        tokio::select! {
+            message = stream.next() => {
+                let data = match message {
+                    Some(Ok(data)) => data,
+                    None => break,
+                    Some(Err(status)) => {
+                        warn!("SendStreamData stream failed: {}", status);
+                        return;
+                    }
+                };
+                if credits == 0 {
+                    let _ = tx
+                        .send(Err(Status::resource_exhausted(format!(
+                            "Record {} was sent without credit; wait for an acknowledgement",
+                            data.sequence
+                        ))))
+                        .await;
+                    return;
+                }
+                credits -= 1;
+
+                let sequence = data.sequence;
+                let job_id = data.job_id.clone();
+                let event_time = data
+                    .timestamp
+                    .as_ref()
+                    .and_then(|ts| chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32));
+                let result = process(data).await;
+                jobs.record(&job_id, result.is_ok(), event_time);
+                pending.push(
+                    sequence,
+                    result.err().map(|e| RecordError {
+                        sequence,
+                        message: format!("Failed to process data: {}", e),
+                    }),
+                );
+
+                if pending.records >= settings.stream_ack_batch_size {
+                    credits += pending.records;
+                    if tx.send(Ok(pending.take())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            _ = ack_timer.tick(), if pending.records > 0 => {
+                credits += pending.records;
+                if tx.send(Ok(pending.take())).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    if pending.records > 0 {
+        let _ = tx.send(Ok(pending.take())).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio_stream::wrappers::ReceiverStream;
+    use tonic::Code;
+
+    fn settings(credits: u32, batch_size: u32, interval: Duration) -> GrpcConfig {
+        GrpcConfig {
+            stream_credits: credits,
+            stream_ack_batch_size: batch_size,
+            stream_ack_interval: interval,
+            ..GrpcConfig::default()
+        }
+    }
+
+    fn record(sequence: u64) -> Result<StreamData, Status> {
+        Ok(StreamData {
+            job_id: "job-1".to_string(),
+            sequence,
+            ..StreamData::default()
+        })
+    }
+
+    /// Serves a client whose side stays open until the returned sender is
+    /// dropped, failing the records numbered in `failing`.
+    fn start(
+        settings: GrpcConfig,
+        failing: &'static [u64],
+    ) -> (mpsc::Sender<Result<StreamData, Status>>, mpsc::Receiver<Result<StreamDataResponse, Status>>) {
+        let (client, requests) = mpsc::channel(16);
+        let (tx, responses) = mpsc::channel(16);
+        let jobs = Arc::new(JobRegistry::new("processors"));
+        tokio::spawn(serve(ReceiverStream::new(requests), tx, jobs, settings, move |data: StreamData| async move {
+            if failing.contains(&data.sequence) {
+                Err("bad record")
+            } else {
+                Ok(())
+            }
+        }));
+        (client, responses)
+    }
+
+    async fn ack(responses: &mut mpsc::Receiver<Result<StreamDataResponse, Status>>) -> StreamDataResponse {
+        responses.recv().await.expect("stream ended").expect("stream failed")
+    }
+
+    #[test]
+    fn test_take_acknowledges_and_clears_pending_records() {
+        let mut pending = PendingAck::default();
+        pending.push(4, None);
+        pending.push(
+            5,
+            Some(RecordError {
+                sequence: 5,
+                message: "bad".to_string(),
+            }),
+        );
+        pending.push(6, None);
+
+        let response = pending.take();
+        assert!(!response.success);
+        assert_eq!(response.message, "1 of 3 record(s) failed");
+        assert_eq!(response.credits, 3);
+        let ack = response.ack.unwrap();
+        assert_eq!((ack.first_sequence, ack.last_sequence), (4, 6));
+        assert_eq!(ack.errors.len(), 1);
+
+        assert_eq!(pending.records, 0);
+        assert_eq!(pending.take().credits, 0);
+    }
+
+    #[tokio::test]
+    async fn test_acks_every_batch_and_returns_its_credits() {
+        let (client, mut responses) = start(settings(4, 2, Duration::from_secs(60)), &[3]);
+        assert_eq!(ack(&mut responses).await.credits, 4);
+        for sequence in 1..=5 {
+            client.send(record(sequence)).await.unwrap();
+        }
+
+        let first = ack(&mut responses).await;
+        assert!(first.success);
+        assert_eq!(first.credits, 2);
+        assert_eq!(first.ack.map(|ack| (ack.first_sequence, ack.last_sequence)), Some((1, 2)));
+        let second = ack(&mut responses).await;
+        assert!(!second.success);
+        assert_eq!(second.ack.unwrap().errors[0].sequence, 3);
+
+        // The last record is acknowledged when the client closes its side
+        drop(client);
+        let last = ack(&mut responses).await;
+        assert_eq!(last.ack.map(|ack| (ack.first_sequence, ack.last_sequence)), Some((5, 5)));
+        assert!(responses.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_acks_a_partial_batch_once_the_interval_passes() {
+        let (client, mut responses) = start(settings(4, 4, Duration::from_millis(50)), &[]);
+        ack(&mut responses).await;
+        client.send(record(1)).await.unwrap();
+
+        let response = tokio::time::timeout(Duration::from_secs(5), ack(&mut responses)).await.unwrap();
+        assert_eq!(response.credits, 1);
+        assert_eq!(response.ack.map(|ack| ack.last_sequence), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_sending_without_credit_ends_the_stream() {
+        // A batch larger than the credits is never acknowledged before they run out
+        let (client, mut responses) = start(settings(2, 10, Duration::from_secs(60)), &[]);
+        ack(&mut responses).await;
+        for sequence in 1..=3 {
+            client.send(record(sequence)).await.unwrap();
+        }
+
+        let status = responses.recv().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert!(responses.recv().await.is_none());
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, RwLock};
+use streamforge_core::pipeline::fingerprint;
+use streamforge_core::storage::{Page, PageCursor};
+
+/// Stopped jobs kept for listing; the oldest are forgotten beyond this.
+const MAX_STOPPED_JOBS: usize = 1000;
+
+/// WatchJobMetrics push interval when the request does not set one.
+pub const DEFAULT_METRICS_INTERVAL_SECONDS: u32 = 5;
+pub const MAX_METRICS_INTERVAL_SECONDS: u32 = 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Paused,
+    Stopped,
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub job_id: String,
+    pub pipeline_id: String,
+    /// Content hash of the job's configuration, from `pipeline::fingerprint`.
+    pub fingerprint: String,
+    pub consumer_group: String,
+    pub state: JobState,
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub messages_processed: u64,
+    pub errors: u64,
+    /// Latest event time seen in the job's input.
+    pub watermark: Option<DateTime<Utc>>,
+}
+
+impl Job {
+    /// Messages per second over the job's lifetime so far.
+    pub fn throughput(&self) -> f64 {
+        let end = self.stopped_at.unwrap_or_else(Utc::now);
+        let seconds = (end - self.started_at).num_milliseconds() as f64 / 1000.0;
+        if seconds > 0.0 {
+            self.messages_processed as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JobFilter {
+    pub pipeline_id: Option<String>,
+    pub state: Option<JobState>,
+    pub started_after: Option<DateTime<Utc>>,
+}
+
+impl JobFilter {
+    fn matches(&self, job: &Job) -> bool {
+        self.pipeline_id.as_ref().map_or(true, |id| &job.pipeline_id == id)
+            && self.state.map_or(true, |state| job.state == state)
+            && self.started_after.map_or(true, |after| job.started_at > after)
+    }
+}
+
+/// The fingerprint of a job configuration and the consumer group the job
+/// reads as: its `group_id`, or `kafka.group_id` when it sets none.
+pub fn identity(config: &str, default_group: &str) -> (String, String) {
+    let config = match config.trim() {
+        "" => serde_json::Value::Object(Default::default()),
+        config => serde_json::from_str(config).unwrap_or_else(|_| serde_json::Value::String(config.to_string())),
+    };
+    let group = config.get("group_id").and_then(|group| group.as_str()).unwrap_or(default_group);
+    (fingerprint(&config), group.to_string())
+}
+
+/// Why a job may not start: another with its identity runs or is starting.
+#[derive(Debug)]
+pub enum DuplicateJob {
+    Running(Job),
+    Starting { fingerprint: String, consumer_group: String },
+}
+
+/// A job identity claimed by [`JobRegistry::reserve`] until dropped.
+pub struct Reservation<'a> {
+    registry: &'a JobRegistry,
+    identity: (String, String),
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.registry.reserved.lock().unwrap().remove(&self.identity);
+    }
+}
+
+/// Jobs started through this service, so clients can discover them without
+/// keeping StartProcessing responses around.
+#[derive(Debug)]
+pub struct JobRegistry {
+    jobs: RwLock<HashMap<String, Job>>,
+    /// Fingerprints and consumer groups of jobs being started.
+    reserved: Mutex<HashSet<(String, String)>>,
+    /// Consumer group of jobs whose configuration does not set one.
+    default_group: String,
+}
+
+impl JobRegistry {
+    pub fn new(default_group: &str) -> Self {
+        Self {
+            jobs: RwLock::new(HashMap::new()),
+            reserved: Mutex::new(HashSet::new()),
+            default_group: default_group.to_string(),
+        }
+    }
+
+    /// Claims the identity of a job about to be started with `config`, or
+    /// fails with the running or paused job that already has it: same
+    /// fingerprint, same consumer group. Two such jobs split the group's
+    /// partitions and each processes only part of the input. The claim is
+    /// given up when the returned guard is dropped, once the job is
+    /// registered or has failed to start.
+    pub fn reserve(&self, config: &str) -> Result<Reservation<'_>, DuplicateJob> {
+        let identity = identity(config, &self.default_group);
+        let mut reserved = self.reserved.lock().unwrap();
+        let running = self
+            .jobs
+            .read()
+            .unwrap()
+            .values()
+            .find(|job| {
+                job.state != JobState::Stopped && job.fingerprint == identity.0 && job.consumer_group == identity.1
+            })
+            .cloned();
+        if let Some(job) = running {
+            return Err(DuplicateJob::Running(job));
+        }
+        if !reserved.insert(identity.clone()) {
+            return Err(DuplicateJob::Starting {
+                fingerprint: identity.0,
+                consumer_group: identity.1,
+            });
+        }
+        Ok(Reservation { registry: self, identity })
+    }
+
+    pub fn started(&self, job_id: &str, pipeline_id: &str, config: &str) {
+        let (fingerprint, consumer_group) = identity(config, &self.default_group);
+        let job = Job {
+            job_id: job_id.to_string(),
+            pipeline_id: pipeline_id.to_string(),
+            fingerprint,
+            consumer_group,
+            state: JobState::Running,
+            started_at: Utc::now(),
+            stopped_at: None,
+            messages_processed: 0,
+            errors: 0,
+            watermark: None,
+        };
+        self.jobs.write().unwrap().insert(job.job_id.clone(), job);
+    }
+
+    pub fn stopped(&self, job_id: &str) {
+        let mut jobs = self.jobs.write().unwrap();
+        if let Some(job) = jobs.get_mut(job_id) {
+            job.state = JobState::Stopped;
+            job.stopped_at = Some(Utc::now());
+        }
+
+        let mut stopped: Vec<(DateTime<Utc>, String)> = jobs
+            .values()
+            .filter_map(|job| job.stopped_at.map(|at| (at, job.job_id.clone())))
+            .collect();
+        if stopped.len() > MAX_STOPPED_JOBS {
+            stopped.sort();
+            for (_, job_id) in stopped.drain(..stopped.len() - MAX_STOPPED_JOBS) {
+                jobs.remove(&job_id);
+            }
+        }
+    }
+
+    /// Moves a running job to paused or back; stopped jobs are left alone.
+    pub fn set_paused(&self, job_id: &str, paused: bool) {
+        if let Some(job) = self.jobs.write().unwrap().get_mut(job_id) {
+            if job.state != JobState::Stopped {
+                job.state = if paused { JobState::Paused } else { JobState::Running };
+            }
+        }
+    }
+
+    /// Counts one message handled for `job_id` and advances its watermark to
+    /// `event_time`; unknown jobs are ignored.
+    pub fn record(&self, job_id: &str, success: bool, event_time: Option<DateTime<Utc>>) {
+        if let Some(job) = self.jobs.write().unwrap().get_mut(job_id) {
+            job.messages_processed += 1;
+            if !success {
+                job.errors += 1;
+            }
+            if event_time > job.watermark {
+                job.watermark = event_time;
+            }
+        }
+    }
+
+    /// Raises the counts of `job_id` to the totals its pipeline reports,
+    /// which cover the records it consumes from Kafka; `record` only sees
+    /// those sent over SendStreamData.
+    pub fn counted(&self, job_id: &str, messages_processed: u64, errors: u64) {
+        if let Some(job) = self.jobs.write().unwrap().get_mut(job_id) {
+            job.messages_processed = job.messages_processed.max(messages_processed);
+            job.errors = job.errors.max(errors);
+        }
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<Job> {
+        self.jobs.read().unwrap().get(job_id).cloned()
+    }
+
+    /// Matching jobs, most recently started first, paged like the storage
+    /// list queries.
+    pub fn list(&self, filter: &JobFilter, limit: i64, cursor: Option<&str>) -> Result<Page<Job>> {
+        let cursor = cursor.map(PageCursor::decode).transpose()?;
+
+        let mut jobs: Vec<Job> = self
+            .jobs
+            .read()
+            .unwrap()
+            .values()
+            .filter(|job| filter.matches(job))
+            .filter(|job| {
+                cursor.as_ref().map_or(true, |c| {
+                    (job.started_at, job.job_id.as_str()) < (c.processed_at, c.id.as_str())
+                })
+            })
+            .cloned()
+            .collect();
+        jobs.sort_by(|a, b| (b.started_at, &b.job_id).cmp(&(a.started_at, &a.job_id)));
+
+        let has_more = jobs.len() as i64 > limit;
+        jobs.truncate(limit.max(0) as usize);
+        let next_cursor = match jobs.last() {
+            Some(last) if has_more => Some(
+                PageCursor {
+                    processed_at: last.started_at,
+                    id: last.job_id.clone(),
+                }
+                .encode(),
+            ),
+            _ => None,
+        };
+
+        Ok(Page {
+            items: jobs,
+            next_cursor,
+        })
+    }
+}
@@ -1,100 +1,481 @@
+use clap::Parser;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::Mutex;
-use tonic::{transport::Server, Request, Response, Status};
+use tonic::codec::CompressionEncoding;
+use tonic::{transport::Server, Code, Request, Response, Status};
+use tokio_stream::StreamExt;
+use tonic_health::ServingStatus;
+use serde_json::json;
 use tracing::{info, warn, error};
 use tracing_subscriber;
 
-mod config;
+mod access;
+mod audit;
+mod coordination;
+mod dead_letters;
+mod deployments;
+mod erasure;
+mod errors;
+mod health;
+mod ingest;
+mod jobs;
+mod pipeline;
 mod processor;
-mod storage;
-mod metrics;
-mod error;
+mod query;
+mod scheduler;
+mod schemas;
+mod silences;
 
-use config::Config;
+use access::Access;
+use coordination::Coordinator;
+use deployments::{Deployment, DeploymentState, Deployments};
+use erasure::Deletions;
+use jobs::{JobFilter, JobRegistry, JobState};
 use processor::StreamProcessor;
-use storage::StorageBackend;
+use scheduler::Scheduler;
+use streamforge_core::audit::Auditor;
+use streamforge_core::config::{self as app_config, Config, GrpcCompression, ResourceLimits};
+use streamforge_core::dlq::DeadLetterQuery;
+use streamforge_core::health::HealthState;
+use streamforge_core::kafka;
+use streamforge_core::pipeline::{Diagnostic, Severity};
+use streamforge_core::metrics::Metrics;
+use streamforge_core::storage::{
+    self, AuditFilter, ExportDataset, ExportFormat, ExportRequest, FieldCipher, SqlScope, SqlSession, StorageBackend,
+};
 use streamforge_v1::stream_processor_service_server::{StreamProcessorService, StreamProcessorServiceServer};
 use streamforge_v1::{StartProcessingRequest, StartProcessingResponse, StopProcessingRequest, StopProcessingResponse, 
+                     PauseProcessingRequest, PauseProcessingResponse, ResumeProcessingRequest, ResumeProcessingResponse,
+                     SnapshotJobRequest, SnapshotJobResponse, RestoreJobRequest, RestoreJobResponse,
                      GetProcessingStatusRequest, GetProcessingStatusResponse, StreamData, StreamDataResponse, 
-                     StreamResultsRequest, ProcessingResult};
+                     StreamResultsRequest, ProcessingResult, ExportDataRequest, ExportDataResponse,
+                     CreatePipelineRequest, CreatePipelineResponse, UpdatePipelineRequest, UpdatePipelineResponse,
+                     DeletePipelineRequest, DeletePipelineResponse, GetPipelineRequest, GetPipelineResponse,
+                     ListPipelinesRequest, ListPipelinesResponse, ValidatePipelineRequest, ValidatePipelineResponse,
+                     ListJobsRequest, ListJobsResponse, JobSummary, WatchJobMetricsRequest, JobMetrics,
+                     ListSchedulesRequest, ListSchedulesResponse, Schedule, TriggerNowRequest, TriggerNowResponse,
+                     QueryMetricsRequest, QueryMetricsResponse, QueryLogsRequest, QueryLogsResponse,
+                     GetStoredTraceRequest, GetStoredTraceResponse, ListDeadLettersRequest, ListDeadLettersResponse,
+                     RedriveDeadLettersRequest, RedriveDeadLettersResponse, CreateSilenceRequest,
+                     CreateSilenceResponse, ListSilencesRequest, ListSilencesResponse, DeleteSilenceRequest,
+                     DeleteSilenceResponse, QueryAuditLogRequest, QueryAuditLogResponse,
+                     ListSchemaVersionsRequest, ListSchemaVersionsResponse, CreateRoleBindingRequest,
+                     CreateRoleBindingResponse, ListRoleBindingsRequest, ListRoleBindingsResponse,
+                     DeleteRoleBindingRequest, DeleteRoleBindingResponse, DeleteDataRequest, DeleteDataResponse,
+                     GetDataDeletionRequest, GetDataDeletionResponse, ExecuteQueryRequest, ExecuteQueryResponse,
+                     StartDeploymentRequest, StartDeploymentResponse, GetDeploymentRequest, GetDeploymentResponse,
+                     PromoteDeploymentRequest, PromoteDeploymentResponse, RollbackDeploymentRequest,
+                     RollbackDeploymentResponse};
 
 pub mod streamforge_v1 {
     tonic::include_proto!("streamforge.v1");
+
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("streamforge_descriptor");
+}
+
+/// How long ValidatePipeline waits for Kafka before skipping topic checks.
+const TOPIC_METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Configuration file path
+    #[arg(short, long, default_value = "config/config.toml")]
+    config: String,
+
+    #[command(flatten)]
+    profile: app_config::ProfileArgs,
+
+    /// gRPC listen address, overriding grpc.host and grpc.port
+    #[arg(long)]
+    listen: Option<SocketAddr>,
+
+    /// Serve gRPC on this unix domain socket, overriding grpc.unix_socket
+    #[arg(long)]
+    unix_socket: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct StreamProcessorService {
     processor: Arc<Mutex<StreamProcessor>>,
+    jobs: Arc<JobRegistry>,
+    coordinator: Arc<Coordinator>,
+    scheduler: Arc<Scheduler>,
+    storage: Arc<dyn StorageBackend>,
+    auditor: Arc<Auditor>,
+    access: Arc<Access>,
+    cipher: Option<Arc<FieldCipher>>,
+    deletions: Arc<Deletions>,
+    deployments: Arc<Deployments>,
     config: Config,
 }
 
+impl StreamProcessorService {
+    /// The cipher to redact encrypted fields with in results for a caller
+    /// who may not read them in the clear.
+    fn redaction<T>(&self, request: &Request<T>, tenant_id: &str) -> Option<&FieldCipher> {
+        self.cipher
+            .as_deref()
+            .filter(|_| !self.access.allows(request, access::DECRYPT_FIELDS, Some(tenant_id)))
+    }
+
+    fn running_deployment(&self, deployment_id: &str) -> Result<Deployment, Status> {
+        let deployment = self
+            .deployments
+            .get(deployment_id)
+            .ok_or_else(|| errors::deployment_not_found(deployment_id))?;
+        if deployment.state != DeploymentState::Running {
+            return Err(errors::error(
+                Code::FailedPrecondition,
+                errors::DEPLOYMENT_NOT_RUNNING,
+                format!("Deployment {} has already finished", deployment_id),
+                false,
+            ));
+        }
+        Ok(deployment)
+    }
+
+    /// Brings the counts of a deployment's jobs up to the totals their
+    /// pipelines report, so the comparison covers their Kafka input.
+    async fn count_deployment(&self, deployment: &Deployment) {
+        let processor = self.processor.lock().await;
+        for job_id in [&deployment.baseline_job_id, &deployment.candidate_job_id] {
+            let Ok(status) = processor.get_pipeline_status(job_id).await else { continue };
+            let count = |name: &str| status.metrics.get(name).and_then(|value| value.parse().ok()).unwrap_or_default();
+            self.jobs.counted(job_id, count("messages_processed"), count("errors"));
+        }
+    }
+
+    /// Stops a job that may already have stopped on its own, and gives up
+    /// its lease.
+    async fn stop_job(&self, job_id: &str) -> Result<(), Status> {
+        if self.jobs.get(job_id).map_or(true, |job| job.state == JobState::Stopped) {
+            return Ok(());
+        }
+        let mut processor = self.processor.lock().await;
+        processor.stop_pipeline(job_id).await.map_err(|e| {
+            error!("Failed to stop job {}: {}", job_id, e);
+            errors::error(
+                Code::Internal,
+                errors::JOB_STOP_FAILED,
+                format!("Failed to stop job {}: {}", job_id, e),
+                true,
+            )
+        })?;
+        drop(processor);
+        self.jobs.stopped(job_id);
+        self.coordinator.stopped(job_id).await;
+        Ok(())
+    }
+}
+
 #[tonic::async_trait]
 impl StreamProcessorService for StreamProcessorService {
     async fn start_processing(
         &self,
-        request: Request<StartProcessingRequest>,
+        mut request: Request<StartProcessingRequest>,
     ) -> Result<Response<StartProcessingResponse>, Status> {
+        self.access.authorize(&mut request, "StartProcessing", None)?;
+        let caller = audit::Caller::of(&request);
         let req = request.into_inner();
-        info!("Starting processing for pipeline: {}", req.pipeline_id);
+        let mut entry = caller
+            .entry("StartProcessing", req.pipeline_id.clone())
+            .details(json!({ "config": req.config }));
 
-        let mut processor = self.processor.lock().await;
-        match processor.start_pipeline(&req.pipeline_id, &req.config).await {
-            Ok(job_id) => {
-                info!("Processing started successfully with job_id: {}", job_id);
-                Ok(Response::new(StartProcessingResponse {
-                    success: true,
-                    job_id,
-                    message: "Processing started successfully".to_string(),
-                }))
+        let result: Result<Response<StartProcessingResponse>, Status> = async {
+            info!("Starting processing for pipeline: {}", req.pipeline_id);
+
+            let mut violations = Vec::new();
+            if req.pipeline_id.is_empty() {
+                violations.push(("pipeline_id", "is required".to_string()));
             }
-            Err(e) => {
-                error!("Failed to start processing: {}", e);
-                Ok(Response::new(StartProcessingResponse {
-                    success: false,
-                    job_id: "".to_string(),
-                    message: format!("Failed to start processing: {}", e),
-                }))
+            if !req.config.is_empty() {
+                if let Err(e) = serde_json::from_str::<serde_json::Value>(&req.config) {
+                    violations.push(("config", format!("is not valid JSON: {}", e)));
+                }
             }
+            if !violations.is_empty() {
+                return Err(errors::bad_request(violations));
+            }
+            // 同一定義・同一コンシューマーグループのジョブはパーティションを分け合ってしまう
+            let allow_duplicate = req.parameters.get("allow_duplicate").map_or(false, |v| v == "true");
+            // 登録が済むまで予約を保持し、同時に起動された同一ジョブを弾く
+            let _reservation = match allow_duplicate {
+                true => None,
+                false => Some(self.jobs.reserve(&req.config).map_err(errors::duplicate_job)?),
+            };
+
+            // 複数インスタンス構成ではリースを取得したインスタンスのみがジョブを実行する
+            let limits = resource_limits(req.limits, &self.config.processing.limits);
+            let job_id = self.coordinator.start(&req.pipeline_id, &req.config, &limits).await?;
+            info!("Processing started successfully with job_id: {}", job_id);
+            Ok(Response::new(StartProcessingResponse { job_id }))
+        }
+        .await;
+
+        if let Ok(response) = &result {
+            entry.details["job_id"] = json!(response.get_ref().job_id);
         }
+        self.auditor.record(entry.outcome(&result)).await;
+        result
     }
 
     async fn stop_processing(
         &self,
-        request: Request<StopProcessingRequest>,
+        mut request: Request<StopProcessingRequest>,
     ) -> Result<Response<StopProcessingResponse>, Status> {
+        self.access.authorize(&mut request, "StopProcessing", None)?;
+        let caller = audit::Caller::of(&request);
         let req = request.into_inner();
-        info!("Stopping processing for job: {}", req.job_id);
+        let entry = caller.entry("StopProcessing", req.job_id.clone());
 
-        let mut processor = self.processor.lock().await;
-        match processor.stop_pipeline(&req.job_id).await {
-            Ok(_) => {
-                info!("Processing stopped successfully for job: {}", req.job_id);
-                Ok(Response::new(StopProcessingResponse {
-                    success: true,
-                    message: "Processing stopped successfully".to_string(),
-                }))
+        let result: Result<Response<StopProcessingResponse>, Status> = async {
+            info!("Stopping processing for job: {}", req.job_id);
+
+            let job = self.jobs.get(&req.job_id).ok_or_else(|| errors::job_not_found(&req.job_id))?;
+            if job.state == JobState::Stopped {
+                return Err(errors::error(
+                    Code::FailedPrecondition,
+                    errors::JOB_NOT_RUNNING,
+                    format!("Job {} is already stopped", req.job_id),
+                    false,
+                ));
             }
-            Err(e) => {
-                error!("Failed to stop processing: {}", e);
-                Ok(Response::new(StopProcessingResponse {
-                    success: false,
-                    message: format!("Failed to stop processing: {}", e),
-                }))
+
+            let mut processor = self.processor.lock().await;
+            match processor.stop_pipeline(&req.job_id).await {
+                Ok(_) => {
+                    info!("Processing stopped successfully for job: {}", req.job_id);
+                    self.jobs.stopped(&req.job_id);
+                    self.coordinator.stopped(&req.job_id).await;
+                    Ok(Response::new(StopProcessingResponse {}))
+                }
+                Err(e) => {
+                    error!("Failed to stop processing: {}", e);
+                    Err(errors::error(
+                        Code::Internal,
+                        errors::JOB_STOP_FAILED,
+                        format!("Failed to stop processing: {}", e),
+                        true,
+                    ))
+                }
+            }
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn pause_processing(
+        &self,
+        mut request: Request<PauseProcessingRequest>,
+    ) -> Result<Response<PauseProcessingResponse>, Status> {
+        self.access.authorize(&mut request, "PauseProcessing", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let entry = caller.entry("PauseProcessing", req.job_id.clone());
+
+        let result: Result<Response<PauseProcessingResponse>, Status> = async {
+            info!("Pausing processing for job: {}", req.job_id);
+
+            let job = self.jobs.get(&req.job_id).ok_or_else(|| errors::job_not_found(&req.job_id))?;
+            if job.state != JobState::Running {
+                return Err(errors::error(
+                    Code::FailedPrecondition,
+                    errors::JOB_NOT_RUNNING,
+                    format!("Job {} is not running", req.job_id),
+                    false,
+                ));
+            }
+
+            let mut processor = self.processor.lock().await;
+            match processor.pause_pipeline(&req.job_id).await {
+                Ok(_) => {
+                    info!("Processing paused for job: {}", req.job_id);
+                    self.jobs.set_paused(&req.job_id, true);
+                    Ok(Response::new(PauseProcessingResponse {}))
+                }
+                Err(e) => {
+                    error!("Failed to pause processing: {}", e);
+                    Err(errors::error(
+                        Code::Internal,
+                        errors::JOB_PAUSE_FAILED,
+                        format!("Failed to pause processing: {}", e),
+                        true,
+                    ))
+                }
+            }
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn resume_processing(
+        &self,
+        mut request: Request<ResumeProcessingRequest>,
+    ) -> Result<Response<ResumeProcessingResponse>, Status> {
+        self.access.authorize(&mut request, "ResumeProcessing", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let entry = caller.entry("ResumeProcessing", req.job_id.clone());
+
+        let result: Result<Response<ResumeProcessingResponse>, Status> = async {
+            info!("Resuming processing for job: {}", req.job_id);
+
+            let job = self.jobs.get(&req.job_id).ok_or_else(|| errors::job_not_found(&req.job_id))?;
+            if job.state != JobState::Paused {
+                return Err(errors::error(
+                    Code::FailedPrecondition,
+                    errors::JOB_NOT_PAUSED,
+                    format!("Job {} is not paused", req.job_id),
+                    false,
+                ));
+            }
+
+            let mut processor = self.processor.lock().await;
+            match processor.resume_pipeline(&req.job_id).await {
+                Ok(_) => {
+                    info!("Processing resumed for job: {}", req.job_id);
+                    self.jobs.set_paused(&req.job_id, false);
+                    Ok(Response::new(ResumeProcessingResponse {}))
+                }
+                Err(e) => {
+                    error!("Failed to resume processing: {}", e);
+                    Err(errors::error(
+                        Code::Internal,
+                        errors::JOB_RESUME_FAILED,
+                        format!("Failed to resume processing: {}", e),
+                        true,
+                    ))
+                }
             }
         }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn snapshot_job(
+        &self,
+        mut request: Request<SnapshotJobRequest>,
+    ) -> Result<Response<SnapshotJobResponse>, Status> {
+        self.access.authorize(&mut request, "SnapshotJob", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let entry = caller
+            .entry("SnapshotJob", req.job_id.clone())
+            .details(json!({ "destination": req.destination }));
+
+        let result: Result<Response<SnapshotJobResponse>, Status> = async {
+            info!("Snapshotting job {} to {}", req.job_id, req.destination);
+
+            if req.destination.is_empty() {
+                return Err(errors::bad_request(vec![("destination", "is required".to_string())]));
+            }
+            self.jobs.get(&req.job_id).ok_or_else(|| errors::job_not_found(&req.job_id))?;
+
+            // 状態の取得中のみプロセッサをロックし、アップロードは解放後に行う
+            let snapshot = {
+                let processor = self.processor.lock().await;
+                processor.snapshot_pipeline(&req.job_id).await
+            };
+            let saved = match snapshot {
+                Ok(snapshot) => storage::save_snapshot(&snapshot, &req.destination)
+                    .await
+                    .map(|location| (snapshot.snapshot_id, location)),
+                Err(e) => Err(e),
+            };
+
+            match saved {
+                Ok((snapshot_id, location)) => Ok(Response::new(SnapshotJobResponse { snapshot_id, location })),
+                Err(e) => {
+                    error!("Failed to snapshot job {}: {}", req.job_id, e);
+                    Err(errors::error(
+                        Code::Unavailable,
+                        errors::SNAPSHOT_FAILED,
+                        format!("Failed to snapshot job: {}", e),
+                        true,
+                    ))
+                }
+            }
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn restore_job(
+        &self,
+        mut request: Request<RestoreJobRequest>,
+    ) -> Result<Response<RestoreJobResponse>, Status> {
+        self.access.authorize(&mut request, "RestoreJob", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let mut entry = caller.entry("RestoreJob", req.location.clone());
+
+        let result: Result<Response<RestoreJobResponse>, Status> = async {
+            info!("Restoring job from snapshot: {}", req.location);
+
+            if req.location.is_empty() {
+                return Err(errors::bad_request(vec![("location", "is required".to_string())]));
+            }
+            let snapshot = storage::load_snapshot(&req.location).await.map_err(|e| {
+                error!("Failed to load snapshot: {}", e);
+                errors::error(
+                    Code::FailedPrecondition,
+                    errors::RESTORE_FAILED,
+                    format!("Failed to load snapshot: {}", e),
+                    false,
+                )
+            })?;
+
+            // 復元したジョブも起動したジョブと同様に重複を確認し、リースを保持する
+            let _reservation = self.jobs.reserve(&snapshot.config).map_err(errors::duplicate_job)?;
+            let job_id = self.coordinator.restore(&snapshot, &self.config.processing.limits).await?;
+            info!("Restored snapshot {} as job {}", snapshot.snapshot_id, job_id);
+            Ok(Response::new(RestoreJobResponse { job_id }))
+        }
+        .await;
+
+        if let Ok(response) = &result {
+            entry.details = json!({ "job_id": response.get_ref().job_id });
+        }
+        self.auditor.record(entry.outcome(&result)).await;
+        result
     }
 
     async fn get_processing_status(
         &self,
-        request: Request<GetProcessingStatusRequest>,
+        mut request: Request<GetProcessingStatusRequest>,
     ) -> Result<Response<GetProcessingStatusResponse>, Status> {
+        self.access.authorize(&mut request, "GetProcessingStatus", None)?;
+        let forwarded = request.metadata().contains_key(coordination::FORWARDED_BY);
+        let metadata = request.metadata().clone();
         let req = request.into_inner();
-        
+
+        // 他インスタンスが所有するジョブは所有者に問い合わせる
+        if !forwarded {
+            if let Some(lease) = self.coordinator.owner(&req.job_id).await? {
+                return self.coordinator.forward_status(&lease, req, &metadata).await;
+            }
+        }
+
         let processor = self.processor.lock().await;
         match processor.get_pipeline_status(&req.job_id).await {
             Ok(status) => {
+                let job = self.jobs.get(&req.job_id);
                 Ok(Response::new(GetProcessingStatusResponse {
                     status: status.status,
+                    fingerprint: job.as_ref().map(|job| job.fingerprint.clone()).unwrap_or_default(),
+                    consumer_group: job.map(|job| job.consumer_group).unwrap_or_default(),
                     job_id: req.job_id,
                     metrics: status.metrics,
                     start_time: Some(status.start_time.into()),
@@ -108,33 +489,151 @@ impl StreamProcessorService for StreamProcessorService {
         }
     }
 
+    async fn list_jobs(
+        &self,
+        mut request: Request<ListJobsRequest>,
+    ) -> Result<Response<ListJobsResponse>, Status> {
+        self.access.authorize(&mut request, "ListJobs", None)?;
+        let req = request.into_inner();
+        let page_size = pipeline::page_size(req.page_size)?;
+        let state = match streamforge_v1::JobState::try_from(req.state) {
+            Ok(streamforge_v1::JobState::Unspecified) => None,
+            Ok(streamforge_v1::JobState::Running) => Some(JobState::Running),
+            Ok(streamforge_v1::JobState::Paused) => Some(JobState::Paused),
+            Ok(streamforge_v1::JobState::Stopped) => Some(JobState::Stopped),
+            Err(_) => return Err(Status::invalid_argument(format!("Unknown job state: {}", req.state))),
+        };
+        let filter = JobFilter {
+            pipeline_id: Some(req.pipeline_id).filter(|id| !id.is_empty()),
+            state,
+            started_after: req
+                .started_after
+                .map(|ts| {
+                    chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32)
+                        .ok_or_else(|| Status::invalid_argument("started_after is out of range"))
+                })
+                .transpose()?,
+        };
+        let cursor = Some(req.page_token.as_str()).filter(|token| !token.is_empty());
+
+        let page = self
+            .jobs
+            .list(&filter, page_size, cursor)
+            .map_err(|e| Status::invalid_argument(format!("Invalid page_token: {}", e)))?;
+
+        Ok(Response::new(ListJobsResponse {
+            jobs: page
+                .items
+                .into_iter()
+                .map(|job| JobSummary {
+                    throughput: job.throughput(),
+                    state: match job.state {
+                        JobState::Running => streamforge_v1::JobState::Running,
+                        JobState::Paused => streamforge_v1::JobState::Paused,
+                        JobState::Stopped => streamforge_v1::JobState::Stopped,
+                    } as i32,
+                    started_at: Some(pipeline::timestamp(job.started_at)),
+                    stopped_at: job.stopped_at.map(pipeline::timestamp),
+                    messages_processed: job.messages_processed as i64,
+                    errors: job.errors as i64,
+                    job_id: job.job_id,
+                    pipeline_id: job.pipeline_id,
+                    fingerprint: job.fingerprint,
+                    consumer_group: job.consumer_group,
+                })
+                .collect(),
+            next_page_token: page.next_cursor.unwrap_or_default(),
+        }))
+    }
+
     type SendStreamDataStream = tokio_stream::wrappers::ReceiverStream<Result<StreamDataResponse, Status>>;
 
     async fn send_stream_data(
         &self,
-        request: Request<tonic::Streaming<StreamData>>,
+        mut request: Request<tonic::Streaming<StreamData>>,
     ) -> Result<Response<Self::SendStreamDataStream>, Status> {
-        let mut stream = request.into_inner();
-        let (tx, rx) = tokio::sync::mpsc::channel(128);
+        self.access.authorize(&mut request, "SendStreamData", None)?;
+        let stream = request.into_inner();
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+
+        let processor = Arc::clone(&self.processor);
+        tokio::spawn(ingest::serve(
+            stream,
+            tx,
+            Arc::clone(&self.jobs),
+            self.config.grpc.clone(),
+            move |data| {
+                let processor = Arc::clone(&processor);
+                async move { processor.lock().await.process_stream_data(data).await.map(|_| ()) }
+            },
+        ));
+
+        Ok(Response::new(tokio_stream::wrappers::ReceiverStream::new(rx)))
+    }
+
+    type WatchJobMetricsStream = tokio_stream::wrappers::ReceiverStream<Result<JobMetrics, Status>>;
+
+    async fn watch_job_metrics(
+        &self,
+        mut request: Request<WatchJobMetricsRequest>,
+    ) -> Result<Response<Self::WatchJobMetricsStream>, Status> {
+        self.access.authorize(&mut request, "WatchJobMetrics", None)?;
+        let req = request.into_inner();
+        let interval_seconds = match req.interval_seconds {
+            0 => jobs::DEFAULT_METRICS_INTERVAL_SECONDS,
+            seconds if seconds <= jobs::MAX_METRICS_INTERVAL_SECONDS => seconds,
+            seconds => {
+                return Err(Status::invalid_argument(format!(
+                    "interval_seconds must be at most {}, got {}",
+                    jobs::MAX_METRICS_INTERVAL_SECONDS,
+                    seconds
+                )))
+            }
+        };
+        let mut previous = self
+            .jobs
+            .get(&req.job_id)
+            .ok_or_else(|| Status::not_found(format!("Job {} not found", req.job_id)))?;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        let jobs = Arc::clone(&self.jobs);
         let processor = Arc::clone(&self.processor);
 
         tokio::spawn(async move {
-            while let Some(data) = stream.message().await.unwrap_or(None) {
-                let mut proc = processor.lock().await;
-                match proc.process_stream_data(data).await {
-                    Ok(_) => {
-                        let _ = tx.send(Ok(StreamDataResponse {
-                            success: true,
-                            message: "Data processed successfully".to_string(),
-                        })).await;
-                    }
-                    Err(e) => {
-                        let _ = tx.send(Ok(StreamDataResponse {
-                            success: false,
-                            message: format!("Failed to process data: {}", e),
-                        })).await;
-                    }
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds as u64));
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+                let Some(job) = jobs.get(&req.job_id) else { break };
+
+                // ラグはプロセッサが報告している場合のみ設定する
+                let lag = processor
+                    .lock()
+                    .await
+                    .get_pipeline_status(&req.job_id)
+                    .await
+                    .ok()
+                    .and_then(|status| status.metrics.get("consumer_lag").and_then(|lag| lag.parse().ok()))
+                    .unwrap_or_default();
+
+                let messages = job.messages_processed - previous.messages_processed;
+                let errors = job.errors - previous.errors;
+                let metrics = JobMetrics {
+                    job_id: job.job_id.clone(),
+                    timestamp: Some(pipeline::timestamp(chrono::Utc::now())),
+                    throughput: messages as f64 / interval_seconds as f64,
+                    error_rate: if messages > 0 { errors as f64 / messages as f64 } else { 0.0 },
+                    lag,
+                    watermark: job.watermark.map(pipeline::timestamp),
+                };
+                if tx.send(Ok(metrics)).await.is_err() {
+                    break;
                 }
+                if job.state == JobState::Stopped {
+                    break;
+                }
+                previous = job;
             }
         });
 
@@ -145,23 +644,1190 @@ impl StreamProcessorService for StreamProcessorService {
 
     async fn stream_results(
         &self,
-        request: Request<StreamResultsRequest>,
+        mut request: Request<StreamResultsRequest>,
     ) -> Result<Response<Self::StreamResultsStream>, Status> {
+        self.access.authorize(&mut request, "StreamResults", None)?;
         let req = request.into_inner();
         let (tx, rx) = tokio::sync::mpsc::channel(128);
         let processor = Arc::clone(&self.processor);
 
         tokio::spawn(async move {
-            let mut proc = processor.lock().await;
-            if let Ok(mut result_stream) = proc.stream_results(&req.job_id).await {
-                while let Some(result) = result_stream.next().await {
-                    let _ = tx.send(Ok(result)).await;
+            // ストリームの取得後はロックを解放し、他のRPCを妨げない
+            let result_stream = processor.lock().await.stream_results(&req.job_id).await;
+            let mut result_stream = match result_stream {
+                Ok(result_stream) => result_stream,
+                Err(e) => {
+                    let _ = tx
+                        .send(Err(Status::internal(format!("Failed to stream results: {}", e))))
+                        .await;
+                    return;
+                }
+            };
+
+            // クライアントが切断したら結果の取得を止め、ストリームを破棄する
+            loop {
+                tokio::select! {
+                    result = result_stream.next() => match result {
+                        Some(result) => {
+                            if tx.send(Ok(result)).await.is_err() {
+                                break;
+                            }
+                        }
+                        None => break,
+                    },
+                    _ = tx.closed() => break,
                 }
             }
+            info!("Stopped streaming results for job: {}", req.job_id);
         });
 
         Ok(Response::new(tokio_stream::wrappers::ReceiverStream::new(rx)))
     }
+
+    async fn export_data(
+        &self,
+        mut request: Request<ExportDataRequest>,
+    ) -> Result<Response<ExportDataResponse>, Status> {
+        let tenant_id = request.get_ref().tenant_id.clone();
+        self.access.authorize(&mut request, "ExportData", Some(&tenant_id))?;
+        // エクスポート先には復号済みの値が書き出される
+        if self.redaction(&request, &tenant_id).is_some() {
+            return Err(Status::permission_denied(format!(
+                "Exporting data with encrypted fields requires {}",
+                access::DECRYPT_FIELDS
+            )));
+        }
+        let req = request.into_inner();
+        info!("Exporting {} as {} to {}", req.dataset, req.format, req.destination);
+
+        let export_request = export_request_from_proto(req)?;
+        match storage::export(self.storage.as_ref(), &export_request).await {
+            Ok(summary) => {
+                info!("Exported {} rows to {}", summary.rows, summary.location);
+                Ok(Response::new(ExportDataResponse {
+                    success: true,
+                    location: summary.location,
+                    rows_exported: summary.rows as i64,
+                    message: "Export completed successfully".to_string(),
+                }))
+            }
+            Err(e) => {
+                error!("Failed to export data: {}", e);
+                Ok(Response::new(ExportDataResponse {
+                    success: false,
+                    location: "".to_string(),
+                    rows_exported: 0,
+                    message: format!("Failed to export data: {}", e),
+                }))
+            }
+        }
+    }
+
+    async fn query_metrics(
+        &self,
+        mut request: Request<QueryMetricsRequest>,
+    ) -> Result<Response<QueryMetricsResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "QueryMetrics", Some(&tenant_id))?;
+        let req = request.into_inner();
+        let tenant_id = pipeline::tenant(req.tenant_id);
+        let (start_time, end_time) = query::time_range(req.start_time, req.end_time)?;
+        let page_size = pipeline::page_size(req.page_size)?;
+        let cursor = query::TimeCursor::decode(&req.page_token)?;
+        let name = Some(req.metric_name.as_str()).filter(|name| !name.is_empty());
+
+        // アーカイブ済みのセグメントも含めて、両方の階層から読み出す
+        let metrics = storage::get_tiered_metrics(
+            self.storage.as_ref(),
+            &tenant_id,
+            name,
+            cursor.as_ref().map_or(start_time, |c| c.after),
+            end_time,
+            Some(query::TimeCursor::fetch_limit(cursor.as_ref(), page_size)),
+        )
+        .await
+            .map_err(|e| {
+                error!("Failed to query metrics: {}", e);
+                Status::internal(format!("Failed to query metrics: {}", e))
+            })?;
+
+        let (metrics, next_page_token) = query::page(metrics, cursor, page_size, |m| m.timestamp);
+        Ok(Response::new(QueryMetricsResponse {
+            metrics: metrics.into_iter().map(query::metric_to_proto).collect(),
+            next_page_token,
+        }))
+    }
+
+    async fn query_logs(
+        &self,
+        mut request: Request<QueryLogsRequest>,
+    ) -> Result<Response<QueryLogsResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "QueryLogs", Some(&tenant_id))?;
+        let redaction = self.redaction(&request, &tenant_id);
+        let req = request.into_inner();
+        let tenant_id = pipeline::tenant(req.tenant_id);
+        let (start_time, end_time) = query::time_range(req.start_time, req.end_time)?;
+        let page_size = pipeline::page_size(req.page_size)?;
+        let cursor = query::TimeCursor::decode(&req.page_token)?;
+        let level = Some(req.level.as_str()).filter(|level| !level.is_empty());
+        let service_name = Some(req.service_name.as_str()).filter(|name| !name.is_empty());
+
+        let logs = self
+            .storage
+            .get_logs(
+                &tenant_id,
+                level,
+                service_name,
+                cursor.as_ref().map_or(start_time, |c| c.after),
+                end_time,
+                Some(query::TimeCursor::fetch_limit(cursor.as_ref(), page_size)),
+            )
+            .await
+            .map_err(|e| {
+                error!("Failed to query logs: {}", e);
+                Status::internal(format!("Failed to query logs: {}", e))
+            })?;
+
+        let (mut logs, next_page_token) = query::page(logs, cursor, page_size, |l| l.timestamp);
+        if let Some(cipher) = redaction {
+            for attributes in logs.iter_mut().filter_map(|log| log.attributes.as_mut()) {
+                cipher.redact(attributes);
+            }
+        }
+        Ok(Response::new(QueryLogsResponse {
+            logs: logs.into_iter().map(query::log_to_proto).collect(),
+            next_page_token,
+        }))
+    }
+
+    async fn get_trace(
+        &self,
+        mut request: Request<GetStoredTraceRequest>,
+    ) -> Result<Response<GetStoredTraceResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "GetTrace", Some(&tenant_id))?;
+        let redaction = self.redaction(&request, &tenant_id);
+        let req = request.into_inner();
+        let tenant_id = pipeline::tenant(req.tenant_id);
+
+        match self.storage.get_trace(&tenant_id, &req.trace_id).await {
+            Ok(Some(mut trace)) => {
+                if let Some(cipher) = redaction {
+                    cipher.redact_trace(&mut trace);
+                }
+                Ok(Response::new(GetStoredTraceResponse {
+                    trace: Some(query::trace_to_proto(trace)),
+                }))
+            }
+            Ok(None) => Err(Status::not_found(format!("Trace {} not found", req.trace_id))),
+            Err(e) => {
+                error!("Failed to get trace: {}", e);
+                Err(Status::internal(format!("Failed to get trace: {}", e)))
+            }
+        }
+    }
+
+    type ExecuteQueryStream = tokio_stream::wrappers::ReceiverStream<Result<ExecuteQueryResponse, Status>>;
+
+    async fn execute_query(
+        &self,
+        mut request: Request<ExecuteQueryRequest>,
+    ) -> Result<Response<Self::ExecuteQueryStream>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "ExecuteQuery", Some(&tenant_id))?;
+        // アーカイブ済みセグメントには復号済みの値が書き出されている
+        if self.redaction(&request, &tenant_id).is_some() {
+            return Err(Status::permission_denied(format!(
+                "Querying data with encrypted fields requires {}",
+                access::DECRYPT_FIELDS
+            )));
+        }
+        let req = request.into_inner();
+        let arrow = match req.format.as_str() {
+            "" | "json" => false,
+            "arrow" => true,
+            other => {
+                return Err(Status::invalid_argument(format!(
+                    "format must be \"json\" or \"arrow\", not {:?}",
+                    other
+                )))
+            }
+        };
+        let (start_time, end_time) = query::time_range(req.start_time, req.end_time)?;
+        let scope = SqlScope {
+            tenant_id,
+            start_time,
+            end_time,
+            include_hot: req.include_hot,
+        };
+
+        let session = SqlSession::open(self.storage.as_ref(), &self.config.database.sql, &scope, self.cipher.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to open query session: {}", e);
+                Status::internal(format!("Failed to open query session: {}", e))
+            })?;
+        let mut batches = session
+            .execute(&req.sql)
+            .await
+            .map_err(|e| Status::invalid_argument(format!("Invalid query: {}", e)))?;
+        info!("Executing query for tenant {}", scope.tenant_id);
+
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        tokio::spawn(async move {
+            // クライアントが切断したらクエリを打ち切る
+            loop {
+                tokio::select! {
+                    batch = batches.next() => {
+                        let response = match batch {
+                            Some(batch) => batch.and_then(|batch| {
+                                let mut response = ExecuteQueryResponse {
+                                    rows: batch.num_rows() as i64,
+                                    ..Default::default()
+                                };
+                                if arrow {
+                                    response.arrow_ipc = storage::to_arrow_ipc(&batch)?;
+                                } else {
+                                    response.json_rows = storage::to_json_rows(&batch)?;
+                                }
+                                Ok(response)
+                            }),
+                            None => break,
+                        };
+                        let failed = response.is_err();
+                        let response = response.map_err(|e| Status::internal(format!("Query failed: {}", e)));
+                        if tx.send(response).await.is_err() || failed {
+                            break;
+                        }
+                    }
+                    _ = tx.closed() => break,
+                }
+            }
+        });
+
+        Ok(Response::new(tokio_stream::wrappers::ReceiverStream::new(rx)))
+    }
+
+    async fn list_dead_letters(
+        &self,
+        mut request: Request<ListDeadLettersRequest>,
+    ) -> Result<Response<ListDeadLettersResponse>, Status> {
+        self.access.authorize(&mut request, "ListDeadLetters", None)?;
+        let req = request.into_inner();
+        let (start, end) = dead_letters::time_range(req.start_time, req.end_time)?;
+        let page_size = pipeline::page_size(req.page_size)?;
+
+        let query = DeadLetterQuery {
+            topic: dead_letters::topic(req.topic, &self.config),
+            start,
+            end,
+            original_topic: Some(req.original_topic).filter(|topic| !topic.is_empty()),
+            positions: dead_letters::page_positions(&req.page_token)?,
+            limit: page_size as usize,
+        };
+        let page = dead_letters::read(self.config.clone(), query).await?;
+
+        Ok(Response::new(ListDeadLettersResponse {
+            next_page_token: page.next_page_token(),
+            dead_letters: page.letters.into_iter().map(dead_letters::to_proto).collect(),
+        }))
+    }
+
+    async fn redrive_dead_letters(
+        &self,
+        mut request: Request<RedriveDeadLettersRequest>,
+    ) -> Result<Response<RedriveDeadLettersResponse>, Status> {
+        self.access.authorize(&mut request, "RedriveDeadLetters", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let mut entry = caller
+            .entry("RedriveDeadLetters", dead_letters::topic(req.topic.clone(), &self.config))
+            .details(dead_letters::selector_details(req.selector.as_ref()));
+
+        let result: Result<Response<RedriveDeadLettersResponse>, Status> = async {
+            let topic = dead_letters::topic(req.topic, &self.config);
+            let selection = dead_letters::Selection::new(topic, req.selector, &req.page_token)?;
+
+            // 対象を読み出してから元トピックへ再投入する（DLQ側のメッセージは残る）
+            let page = dead_letters::read(self.config.clone(), selection.query.clone()).await?;
+            let (letters, mut failures, next_page_token) = selection.select(page);
+            let (redriven, redrive_failures) = dead_letters::redrive(&self.config, &letters).await?;
+            failures.extend(redrive_failures);
+
+            info!(
+                "Redrove {} dead letters from {} ({} failed)",
+                redriven,
+                selection.query.topic,
+                failures.len()
+            );
+            Ok(Response::new(RedriveDeadLettersResponse {
+                redriven,
+                failures,
+                next_page_token,
+            }))
+        }
+        .await;
+
+        if let Ok(response) = &result {
+            entry.details["redriven"] = json!(response.get_ref().redriven);
+        }
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn create_pipeline(
+        &self,
+        mut request: Request<CreatePipelineRequest>,
+    ) -> Result<Response<CreatePipelineResponse>, Status> {
+        let tenant_id = request.get_ref().pipeline.as_ref().map(|p| pipeline::tenant(p.tenant_id.clone()));
+        self.access.authorize(&mut request, "CreatePipeline", tenant_id.as_deref())?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let mut entry = match &req.pipeline {
+            Some(p) => caller
+                .entry("CreatePipeline", p.id.clone())
+                .tenant(pipeline::tenant(p.tenant_id.clone()))
+                .details(json!({ "name": p.name })),
+            None => caller.entry("CreatePipeline", ""),
+        };
+
+        let result: Result<Response<CreatePipelineResponse>, Status> = async {
+            let mut definition = pipeline::from_proto(
+                req.pipeline.ok_or_else(|| Status::invalid_argument("pipeline is required"))?,
+            )?;
+            if definition.id.is_empty() {
+                definition.id = uuid::Uuid::new_v4().to_string();
+            }
+            definition.version = 1;
+
+            match self.storage.create_pipeline(&definition).await {
+                Ok(true) => {
+                    info!("Created pipeline {} for tenant {}", definition.id, definition.tenant_id);
+                    Ok(Response::new(CreatePipelineResponse {
+                        pipeline: Some(pipeline::to_proto(definition)),
+                    }))
+                }
+                Ok(false) => Err(Status::already_exists(format!("Pipeline {} already exists", definition.id))),
+                Err(e) => {
+                    error!("Failed to create pipeline: {}", e);
+                    Err(Status::internal(format!("Failed to create pipeline: {}", e)))
+                }
+            }
+        }
+        .await;
+
+        if let Some(pipeline) = result.as_ref().ok().and_then(|response| response.get_ref().pipeline.as_ref()) {
+            entry.target = pipeline.id.clone();
+        }
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn update_pipeline(
+        &self,
+        mut request: Request<UpdatePipelineRequest>,
+    ) -> Result<Response<UpdatePipelineResponse>, Status> {
+        let tenant_id = request.get_ref().pipeline.as_ref().map(|p| pipeline::tenant(p.tenant_id.clone()));
+        self.access.authorize(&mut request, "UpdatePipeline", tenant_id.as_deref())?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let entry = match &req.pipeline {
+            Some(p) => caller
+                .entry("UpdatePipeline", p.id.clone())
+                .tenant(pipeline::tenant(p.tenant_id.clone()))
+                .details(json!({ "name": p.name, "version": p.version })),
+            None => caller.entry("UpdatePipeline", ""),
+        };
+
+        let result: Result<Response<UpdatePipelineResponse>, Status> = async {
+            let mut definition = pipeline::from_proto(
+                req.pipeline.ok_or_else(|| Status::invalid_argument("pipeline is required"))?,
+            )?;
+
+            let stored = self
+                .storage
+                .get_pipeline(&definition.tenant_id, &definition.id)
+                .await
+                .map_err(|e| Status::internal(format!("Failed to get pipeline: {}", e)))?
+                .ok_or_else(|| Status::not_found(format!("Pipeline {} not found", definition.id)))?;
+            if definition.version != stored.version {
+                return Err(Status::aborted(format!(
+                    "Pipeline {} is at version {}, not {}",
+                    definition.id, stored.version, definition.version
+                )));
+            }
+            definition.version = stored.version + 1;
+            definition.created_at = stored.created_at;
+
+            match self.storage.update_pipeline(&definition).await {
+                Ok(true) => {
+                    info!("Updated pipeline {} to version {}", definition.id, definition.version);
+                    Ok(Response::new(UpdatePipelineResponse {
+                        pipeline: Some(pipeline::to_proto(definition)),
+                    }))
+                }
+                Ok(false) => Err(Status::aborted(format!(
+                    "Pipeline {} was modified concurrently; retry with the current version",
+                    definition.id
+                ))),
+                Err(e) => {
+                    error!("Failed to update pipeline: {}", e);
+                    Err(Status::internal(format!("Failed to update pipeline: {}", e)))
+                }
+            }
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn delete_pipeline(
+        &self,
+        mut request: Request<DeletePipelineRequest>,
+    ) -> Result<Response<DeletePipelineResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "DeletePipeline", Some(&tenant_id))?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let entry = caller
+            .entry("DeletePipeline", req.pipeline_id.clone())
+            .tenant(pipeline::tenant(req.tenant_id.clone()));
+
+        let result: Result<Response<DeletePipelineResponse>, Status> = async {
+            let tenant_id = pipeline::tenant(req.tenant_id);
+
+            match self.storage.delete_pipeline(&tenant_id, &req.pipeline_id).await {
+                Ok(true) => {
+                    info!("Deleted pipeline {} for tenant {}", req.pipeline_id, tenant_id);
+                    Ok(Response::new(DeletePipelineResponse {}))
+                }
+                Ok(false) => Err(Status::not_found(format!("Pipeline {} not found", req.pipeline_id))),
+                Err(e) => {
+                    error!("Failed to delete pipeline: {}", e);
+                    Err(Status::internal(format!("Failed to delete pipeline: {}", e)))
+                }
+            }
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn get_pipeline(
+        &self,
+        mut request: Request<GetPipelineRequest>,
+    ) -> Result<Response<GetPipelineResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "GetPipeline", Some(&tenant_id))?;
+        let req = request.into_inner();
+        let tenant_id = pipeline::tenant(req.tenant_id);
+
+        match self.storage.get_pipeline(&tenant_id, &req.pipeline_id).await {
+            Ok(Some(definition)) => Ok(Response::new(GetPipelineResponse {
+                pipeline: Some(pipeline::to_proto(definition)),
+            })),
+            Ok(None) => Err(Status::not_found(format!("Pipeline {} not found", req.pipeline_id))),
+            Err(e) => {
+                error!("Failed to get pipeline: {}", e);
+                Err(Status::internal(format!("Failed to get pipeline: {}", e)))
+            }
+        }
+    }
+
+    async fn list_pipelines(
+        &self,
+        mut request: Request<ListPipelinesRequest>,
+    ) -> Result<Response<ListPipelinesResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "ListPipelines", Some(&tenant_id))?;
+        let req = request.into_inner();
+        let tenant_id = pipeline::tenant(req.tenant_id);
+        let page_size = pipeline::page_size(req.page_size)?;
+        let cursor = Some(req.page_token.as_str()).filter(|token| !token.is_empty());
+
+        match self.storage.list_pipelines(&tenant_id, Some(page_size), cursor).await {
+            Ok(page) => Ok(Response::new(ListPipelinesResponse {
+                pipelines: page.items.into_iter().map(pipeline::to_proto).collect(),
+                next_page_token: page.next_cursor.unwrap_or_default(),
+            })),
+            Err(e) => {
+                error!("Failed to list pipelines: {}", e);
+                Err(Status::internal(format!("Failed to list pipelines: {}", e)))
+            }
+        }
+    }
+
+    async fn list_schedules(
+        &self,
+        mut request: Request<ListSchedulesRequest>,
+    ) -> Result<Response<ListSchedulesResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "ListSchedules", Some(&tenant_id))?;
+        let req = request.into_inner();
+        let tenant_id = pipeline::tenant(req.tenant_id);
+
+        let schedules = self
+            .scheduler
+            .list(&tenant_id)
+            .await
+            .into_iter()
+            .map(|entry| Schedule {
+                next_run: entry.next_run.map(pipeline::timestamp),
+                last_run: entry.last_run.map(pipeline::timestamp),
+                last_job_id: entry.last_job_id.unwrap_or_default(),
+                schedule: entry.pipeline.spec.schedule.map(pipeline::schedule_to_proto),
+                pipeline_id: entry.pipeline.id,
+            })
+            .collect();
+
+        Ok(Response::new(ListSchedulesResponse { schedules }))
+    }
+
+    async fn trigger_now(
+        &self,
+        mut request: Request<TriggerNowRequest>,
+    ) -> Result<Response<TriggerNowResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "TriggerNow", Some(&tenant_id))?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let mut entry = caller
+            .entry("TriggerNow", req.pipeline_id.clone())
+            .tenant(pipeline::tenant(req.tenant_id.clone()));
+
+        let result: Result<Response<TriggerNowResponse>, Status> = async {
+            let tenant_id = pipeline::tenant(req.tenant_id);
+
+            match self.scheduler.trigger_now(&tenant_id, &req.pipeline_id).await {
+                Ok(Some(job_id)) => Ok(Response::new(TriggerNowResponse { job_id })),
+                Ok(None) => Err(Status::not_found(format!(
+                    "Pipeline {} has no schedule",
+                    req.pipeline_id
+                ))),
+                Err(e) => match e.downcast::<Status>() {
+                    Ok(status) => Err(status),
+                    Err(e) => {
+                        error!("Failed to trigger pipeline {}: {}", req.pipeline_id, e);
+                        Err(Status::internal(format!("Failed to trigger pipeline: {}", e)))
+                    }
+                },
+            }
+        }
+        .await;
+
+        if let Ok(response) = &result {
+            entry.details = json!({ "job_id": response.get_ref().job_id });
+        }
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn create_silence(
+        &self,
+        mut request: Request<CreateSilenceRequest>,
+    ) -> Result<Response<CreateSilenceResponse>, Status> {
+        let tenant_id = request.get_ref().silence.as_ref().map(|s| pipeline::tenant(s.tenant_id.clone()));
+        self.access.authorize(&mut request, "CreateSilence", tenant_id.as_deref())?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let mut entry = match &req.silence {
+            Some(s) => caller
+                .entry("CreateSilence", s.id.clone())
+                .tenant(pipeline::tenant(s.tenant_id.clone()))
+                .details(silences::details(s)),
+            None => caller.entry("CreateSilence", ""),
+        };
+
+        let result: Result<Response<CreateSilenceResponse>, Status> = async {
+            let silence = silences::from_proto(
+                req.silence.ok_or_else(|| Status::invalid_argument("silence is required"))?,
+                chrono::Utc::now(),
+            )?;
+
+            match self.storage.create_silence(&silence).await {
+                Ok(true) => {
+                    info!(
+                        "Created silence {} for tenant {} until {}",
+                        silence.id, silence.tenant_id, silence.ends_at
+                    );
+                    Ok(Response::new(CreateSilenceResponse {
+                        silence: Some(silences::to_proto(silence)),
+                    }))
+                }
+                Ok(false) => Err(Status::already_exists(format!("Silence {} already exists", silence.id))),
+                Err(e) => {
+                    error!("Failed to create silence: {}", e);
+                    Err(Status::internal(format!("Failed to create silence: {}", e)))
+                }
+            }
+        }
+        .await;
+
+        if let Some(silence) = result.as_ref().ok().and_then(|response| response.get_ref().silence.as_ref()) {
+            entry.target = silence.id.clone();
+        }
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn list_silences(
+        &self,
+        mut request: Request<ListSilencesRequest>,
+    ) -> Result<Response<ListSilencesResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "ListSilences", Some(&tenant_id))?;
+        let req = request.into_inner();
+        let tenant_id = pipeline::tenant(req.tenant_id);
+        let active_at = req.active_only.then(chrono::Utc::now);
+
+        match self.storage.list_silences(&tenant_id, active_at).await {
+            Ok(stored) => Ok(Response::new(ListSilencesResponse {
+                silences: stored.into_iter().map(silences::to_proto).collect(),
+            })),
+            Err(e) => {
+                error!("Failed to list silences: {}", e);
+                Err(Status::internal(format!("Failed to list silences: {}", e)))
+            }
+        }
+    }
+
+    async fn delete_silence(
+        &self,
+        mut request: Request<DeleteSilenceRequest>,
+    ) -> Result<Response<DeleteSilenceResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "DeleteSilence", Some(&tenant_id))?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let entry = caller
+            .entry("DeleteSilence", req.silence_id.clone())
+            .tenant(pipeline::tenant(req.tenant_id.clone()));
+
+        let result: Result<Response<DeleteSilenceResponse>, Status> = async {
+            let tenant_id = pipeline::tenant(req.tenant_id);
+
+            match self.storage.delete_silence(&tenant_id, &req.silence_id).await {
+                Ok(true) => {
+                    info!("Deleted silence {} for tenant {}", req.silence_id, tenant_id);
+                    Ok(Response::new(DeleteSilenceResponse {}))
+                }
+                Ok(false) => Err(Status::not_found(format!("Silence {} not found", req.silence_id))),
+                Err(e) => {
+                    error!("Failed to delete silence: {}", e);
+                    Err(Status::internal(format!("Failed to delete silence: {}", e)))
+                }
+            }
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn query_audit_log(
+        &self,
+        mut request: Request<QueryAuditLogRequest>,
+    ) -> Result<Response<QueryAuditLogResponse>, Status> {
+        self.access.authorize(&mut request, "QueryAuditLog", None)?;
+        let req = request.into_inner();
+        let (start_time, end_time) = dead_letters::time_range(req.start_time, req.end_time)?;
+        let page_size = pipeline::page_size(req.page_size)?;
+        let cursor = Some(req.page_token.as_str()).filter(|token| !token.is_empty());
+        let non_empty = |value: String| Some(value).filter(|v| !v.is_empty());
+        let filter = AuditFilter {
+            start_time,
+            end_time,
+            actor: non_empty(req.actor),
+            action: non_empty(req.action),
+            target: non_empty(req.target),
+        };
+
+        match self.storage.query_audit_log(&filter, Some(page_size), cursor).await {
+            Ok(page) => Ok(Response::new(QueryAuditLogResponse {
+                entries: page.items.into_iter().map(audit::to_proto).collect(),
+                next_page_token: page.next_cursor.unwrap_or_default(),
+            })),
+            Err(e) => {
+                error!("Failed to query audit log: {}", e);
+                Err(Status::internal(format!("Failed to query audit log: {}", e)))
+            }
+        }
+    }
+
+    async fn list_schema_versions(
+        &self,
+        mut request: Request<ListSchemaVersionsRequest>,
+    ) -> Result<Response<ListSchemaVersionsResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "ListSchemaVersions", Some(&tenant_id))?;
+        let req = request.into_inner();
+        if req.topic.is_empty() {
+            return Err(Status::invalid_argument("topic is required"));
+        }
+        let tenant_id = pipeline::tenant(req.tenant_id);
+
+        match self.storage.list_schema_versions(&tenant_id, &req.topic).await {
+            Ok(versions) => Ok(Response::new(ListSchemaVersionsResponse {
+                versions: versions.into_iter().map(schemas::to_proto).collect(),
+            })),
+            Err(e) => {
+                error!("Failed to list schema versions: {}", e);
+                Err(Status::internal(format!("Failed to list schema versions: {}", e)))
+            }
+        }
+    }
+
+    async fn create_role_binding(
+        &self,
+        mut request: Request<CreateRoleBindingRequest>,
+    ) -> Result<Response<CreateRoleBindingResponse>, Status> {
+        self.access.authorize(&mut request, "CreateRoleBinding", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let entry = match &req.binding {
+            Some(b) => caller
+                .entry("CreateRoleBinding", b.subject.clone())
+                .details(json!({ "role": b.role, "tenants": b.tenants })),
+            None => caller.entry("CreateRoleBinding", ""),
+        };
+
+        let result: Result<Response<CreateRoleBindingResponse>, Status> = async {
+            let binding = access::from_proto(
+                req.binding.ok_or_else(|| Status::invalid_argument("binding is required"))?,
+                &self.access.policy(),
+                caller.actor(),
+                chrono::Utc::now(),
+            )?;
+
+            match self.storage.create_role_binding(&binding).await {
+                Ok(true) => {
+                    info!("Bound {} to role {} in {:?}", binding.subject, binding.role, binding.tenants);
+                    // このインスタンスでは即座に反映し、他のインスタンスは次の更新で反映する
+                    if let Err(e) = self.access.refresh().await {
+                        warn!("Failed to refresh role bindings: {}", e);
+                    }
+                    Ok(Response::new(CreateRoleBindingResponse {
+                        binding: Some(access::to_proto(binding)),
+                    }))
+                }
+                Ok(false) => Err(Status::already_exists(format!(
+                    "{} is already bound to role {}",
+                    binding.subject, binding.role
+                ))),
+                Err(e) => {
+                    error!("Failed to create role binding: {}", e);
+                    Err(Status::internal(format!("Failed to create role binding: {}", e)))
+                }
+            }
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn list_role_bindings(
+        &self,
+        mut request: Request<ListRoleBindingsRequest>,
+    ) -> Result<Response<ListRoleBindingsResponse>, Status> {
+        self.access.authorize(&mut request, "ListRoleBindings", None)?;
+        let req = request.into_inner();
+        let subject = Some(req.subject.as_str()).filter(|subject| !subject.is_empty());
+
+        let mut bindings: Vec<_> = self
+            .config
+            .grpc
+            .rbac
+            .bindings
+            .iter()
+            .filter(|binding| subject.map_or(true, |subject| binding.subject == subject))
+            .map(streamforge_core::rbac::RoleBinding::configured)
+            .collect();
+        match self.storage.list_role_bindings(subject).await {
+            Ok(stored) => {
+                bindings.extend(stored);
+                Ok(Response::new(ListRoleBindingsResponse {
+                    bindings: bindings.into_iter().map(access::to_proto).collect(),
+                }))
+            }
+            Err(e) => {
+                error!("Failed to list role bindings: {}", e);
+                Err(Status::internal(format!("Failed to list role bindings: {}", e)))
+            }
+        }
+    }
+
+    async fn delete_role_binding(
+        &self,
+        mut request: Request<DeleteRoleBindingRequest>,
+    ) -> Result<Response<DeleteRoleBindingResponse>, Status> {
+        self.access.authorize(&mut request, "DeleteRoleBinding", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let entry = caller
+            .entry("DeleteRoleBinding", req.subject.clone())
+            .details(json!({ "role": req.role }));
+
+        let result: Result<Response<DeleteRoleBindingResponse>, Status> = async {
+            match self.storage.delete_role_binding(&req.subject, &req.role).await {
+                Ok(true) => {
+                    info!("Unbound {} from role {}", req.subject, req.role);
+                    if let Err(e) = self.access.refresh().await {
+                        warn!("Failed to refresh role bindings: {}", e);
+                    }
+                    Ok(Response::new(DeleteRoleBindingResponse {}))
+                }
+                // 設定ファイルのバインディングはAPIからは削除できない
+                Ok(false) => Err(Status::not_found(format!(
+                    "{} has no stored binding to role {}",
+                    req.subject, req.role
+                ))),
+                Err(e) => {
+                    error!("Failed to delete role binding: {}", e);
+                    Err(Status::internal(format!("Failed to delete role binding: {}", e)))
+                }
+            }
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn delete_data(
+        &self,
+        mut request: Request<DeleteDataRequest>,
+    ) -> Result<Response<DeleteDataResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "DeleteData", Some(&tenant_id))?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let deletion_id = uuid::Uuid::new_v4().to_string();
+        let mut entry = caller.entry("DeleteData", deletion_id.clone()).tenant(tenant_id.clone());
+
+        let result: Result<Response<DeleteDataResponse>, Status> = async {
+            let erasure_request = erasure::request_from_proto(tenant_id.clone(), req)?;
+            entry = entry.clone().details(erasure::request_details(&erasure_request));
+            let deletion = self.deletions.started(&deletion_id, &tenant_id, caller.actor());
+            info!("Started deletion {} of tenant {} data", deletion_id, tenant_id);
+
+            // 完了時に消去件数を監査ログに記録する
+            let completed = caller
+                .entry("DeleteDataCompleted", deletion_id.clone())
+                .tenant(tenant_id.clone())
+                .details(erasure::request_details(&erasure_request));
+            let backend = Arc::clone(&self.storage);
+            let deletions = Arc::clone(&self.deletions);
+            let auditor = Arc::clone(&self.auditor);
+            let id = deletion_id.clone();
+            tokio::spawn(async move {
+                let result = storage::erase(backend.as_ref(), &erasure_request, |dataset, records| {
+                    deletions.progress(&id, dataset, records)
+                })
+                .await;
+                if let Err(e) = &result {
+                    error!("Deletion {} failed: {}", id, e);
+                }
+                let records = deletions.finished(&id, &result).map(|deletion| deletion.records).unwrap_or_default();
+                let mut details = completed.details.clone();
+                details["records_erased"] = json!(records
+                    .into_iter()
+                    .map(|(dataset, records)| (dataset.as_str(), records))
+                    .collect::<std::collections::BTreeMap<_, _>>());
+                auditor.record(completed.details(details).outcome(&result)).await;
+            });
+
+            Ok(Response::new(DeleteDataResponse {
+                deletion: Some(erasure::to_proto(deletion)),
+            }))
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn get_data_deletion(
+        &self,
+        mut request: Request<GetDataDeletionRequest>,
+    ) -> Result<Response<GetDataDeletionResponse>, Status> {
+        let tenant_id = pipeline::tenant(request.get_ref().tenant_id.clone());
+        self.access.authorize(&mut request, "GetDataDeletion", Some(&tenant_id))?;
+        let req = request.into_inner();
+
+        match self.deletions.get(&tenant_id, &req.deletion_id) {
+            Some(deletion) => Ok(Response::new(GetDataDeletionResponse {
+                deletion: Some(erasure::to_proto(deletion)),
+            })),
+            // 削除ジョブは開始したインスタンスのメモリにのみ保持される
+            None => Err(Status::not_found(format!(
+                "Deletion {} not found on this instance",
+                req.deletion_id
+            ))),
+        }
+    }
+
+    async fn start_deployment(
+        &self,
+        mut request: Request<StartDeploymentRequest>,
+    ) -> Result<Response<StartDeploymentResponse>, Status> {
+        self.access.authorize(&mut request, "StartDeployment", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let deployment_id = uuid::Uuid::new_v4().to_string();
+        let mut entry = caller.entry("StartDeployment", deployment_id.clone()).details(json!({
+            "baseline_job_id": req.baseline_job_id,
+            "config": req.config,
+        }));
+
+        let result: Result<Response<StartDeploymentResponse>, Status> = async {
+            let group = &self.config.kafka.group_id;
+            let (settings, candidate_config) = deployments::request_from_proto(&deployment_id, &req, group)?;
+            let baseline = self
+                .jobs
+                .get(&req.baseline_job_id)
+                .ok_or_else(|| errors::job_not_found(&req.baseline_job_id))?;
+            if baseline.state == JobState::Stopped {
+                return Err(errors::error(
+                    Code::FailedPrecondition,
+                    errors::JOB_NOT_RUNNING,
+                    format!("Job {} is stopped", req.baseline_job_id),
+                    false,
+                ));
+            }
+            if let Some(running) = self.deployments.running(&baseline.pipeline_id) {
+                return Err(errors::error(
+                    Code::FailedPrecondition,
+                    errors::DEPLOYMENT_RUNNING,
+                    format!(
+                        "Pipeline {} already has deployment {} running",
+                        baseline.pipeline_id, running.deployment_id
+                    ),
+                    false,
+                ));
+            }
+
+            // 候補バージョンは別のパイプラインIDでリースを取り、ベースラインと競合しない
+            let limits = resource_limits(req.limits, &self.config.processing.limits);
+            let candidate_pipeline = deployments::candidate_pipeline_id(&baseline.pipeline_id, &settings);
+            let candidate_job_id = self.coordinator.start(&candidate_pipeline, &candidate_config, &limits).await?;
+            info!(
+                "Started deployment {} of pipeline {} as job {} beside job {}",
+                deployment_id, baseline.pipeline_id, candidate_job_id, baseline.job_id
+            );
+
+            let deployment = Deployment {
+                deployment_id: deployment_id.clone(),
+                pipeline_id: baseline.pipeline_id.clone(),
+                baseline_job_id: baseline.job_id.clone(),
+                candidate_job_id,
+                settings,
+                config: req.config.clone(),
+                limits,
+                baseline_fingerprint: baseline.fingerprint.clone(),
+                candidate_fingerprint: jobs::identity(&req.config, group).0,
+                state: DeploymentState::Running,
+                promoted_job_id: None,
+                comparison: None,
+                started_at: chrono::Utc::now(),
+                finished_at: None,
+            };
+            self.deployments.started(deployment.clone());
+            Ok(Response::new(StartDeploymentResponse {
+                deployment: Some(deployments::to_proto(deployment, &self.jobs)),
+            }))
+        }
+        .await;
+
+        if let Ok(response) = &result {
+            if let Some(deployment) = &response.get_ref().deployment {
+                entry.details["candidate_job_id"] = json!(deployment.candidate_job_id);
+            }
+        }
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn get_deployment(
+        &self,
+        mut request: Request<GetDeploymentRequest>,
+    ) -> Result<Response<GetDeploymentResponse>, Status> {
+        self.access.authorize(&mut request, "GetDeployment", None)?;
+        let req = request.into_inner();
+
+        // デプロイメントは開始したインスタンスのメモリにのみ保持される
+        let deployment = self
+            .deployments
+            .get(&req.deployment_id)
+            .ok_or_else(|| errors::deployment_not_found(&req.deployment_id))?;
+        if deployment.comparison.is_none() {
+            self.count_deployment(&deployment).await;
+        }
+        Ok(Response::new(GetDeploymentResponse {
+            deployment: Some(deployments::to_proto(deployment, &self.jobs)),
+        }))
+    }
+
+    async fn promote_deployment(
+        &self,
+        mut request: Request<PromoteDeploymentRequest>,
+    ) -> Result<Response<PromoteDeploymentResponse>, Status> {
+        self.access.authorize(&mut request, "PromoteDeployment", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let mut entry = caller.entry("PromoteDeployment", req.deployment_id.clone());
+
+        let result: Result<Response<PromoteDeploymentResponse>, Status> = async {
+            let deployment = self.running_deployment(&req.deployment_id)?;
+            // 停止前の件数で比較結果を確定させる
+            self.count_deployment(&deployment).await;
+
+            // 新バージョンは名前空間を外した元の設定で、ベースラインのリースを引き継いで起動する
+            self.stop_job(&deployment.candidate_job_id).await?;
+            self.stop_job(&deployment.baseline_job_id).await?;
+            let job_id = self
+                .coordinator
+                .start(&deployment.pipeline_id, &deployment.config, &deployment.limits)
+                .await?;
+            info!(
+                "Promoted deployment {} of pipeline {} as job {}",
+                deployment.deployment_id, deployment.pipeline_id, job_id
+            );
+
+            let deployment = self
+                .deployments
+                .finished(&deployment.deployment_id, DeploymentState::Promoted, Some(job_id), &self.jobs)
+                .ok_or_else(|| errors::deployment_not_found(&req.deployment_id))?;
+            Ok(Response::new(PromoteDeploymentResponse {
+                deployment: Some(deployments::to_proto(deployment, &self.jobs)),
+            }))
+        }
+        .await;
+
+        if let Ok(response) = &result {
+            if let Some(deployment) = &response.get_ref().deployment {
+                entry.details["job_id"] = json!(deployment.promoted_job_id);
+            }
+        }
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn rollback_deployment(
+        &self,
+        mut request: Request<RollbackDeploymentRequest>,
+    ) -> Result<Response<RollbackDeploymentResponse>, Status> {
+        self.access.authorize(&mut request, "RollbackDeployment", None)?;
+        let caller = audit::Caller::of(&request);
+        let req = request.into_inner();
+        let entry = caller.entry("RollbackDeployment", req.deployment_id.clone());
+
+        let result: Result<Response<RollbackDeploymentResponse>, Status> = async {
+            let deployment = self.running_deployment(&req.deployment_id)?;
+            self.count_deployment(&deployment).await;
+            self.stop_job(&deployment.candidate_job_id).await?;
+            info!(
+                "Rolled back deployment {} of pipeline {}",
+                deployment.deployment_id, deployment.pipeline_id
+            );
+
+            let deployment = self
+                .deployments
+                .finished(&deployment.deployment_id, DeploymentState::RolledBack, None, &self.jobs)
+                .ok_or_else(|| errors::deployment_not_found(&req.deployment_id))?;
+            Ok(Response::new(RollbackDeploymentResponse {
+                deployment: Some(deployments::to_proto(deployment, &self.jobs)),
+            }))
+        }
+        .await;
+
+        self.auditor.record(entry.outcome(&result)).await;
+        result
+    }
+
+    async fn validate_pipeline(
+        &self,
+        mut request: Request<ValidatePipelineRequest>,
+    ) -> Result<Response<ValidatePipelineResponse>, Status> {
+        let tenant_id = request.get_ref().pipeline.as_ref().map(|p| pipeline::tenant(p.tenant_id.clone()));
+        self.access.authorize(&mut request, "ValidatePipeline", tenant_id.as_deref())?;
+        let req = request.into_inner();
+        let definition = req.pipeline.ok_or_else(|| Status::invalid_argument("pipeline is required"))?;
+
+        // トピックの存在確認はKafkaに到達できる場合のみ行う
+        let config = self.config.clone();
+        let topics = tokio::task::spawn_blocking(move || {
+            kafka::fetch_topic_names(&config, TOPIC_METADATA_TIMEOUT)
+        })
+        .await
+        .map_err(|e| Status::internal(format!("Failed to fetch topics: {}", e)))?;
+
+        let mut diagnostics = pipeline::validate(definition, topics.as_ref().ok());
+        if let Err(e) = topics {
+            warn!("Validating pipeline without topic checks: {}", e);
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                field: "pipeline.sources".to_string(),
+                message: format!("topics could not be checked: {}", e),
+            });
+        }
+
+        Ok(Response::new(ValidatePipelineResponse {
+            valid: !diagnostics.iter().any(|d| d.severity == Severity::Error),
+            diagnostics: diagnostics.into_iter().map(pipeline::diagnostic_to_proto).collect(),
+        }))
+    }
+}
+
+/// Request limits over the configured defaults; zero leaves a default in
+/// place.
+fn resource_limits(requested: Option<streamforge_v1::ResourceLimits>, defaults: &ResourceLimits) -> ResourceLimits {
+    let Some(requested) = requested else {
+        return defaults.clone();
+    };
+    ResourceLimits {
+        max_workers: Some(requested.max_workers as usize).filter(|v| *v > 0).or(defaults.max_workers),
+        max_memory_bytes: Some(requested.max_memory_bytes).filter(|v| *v > 0).or(defaults.max_memory_bytes),
+        max_inflight_bytes: Some(requested.max_inflight_bytes).filter(|v| *v > 0).or(defaults.max_inflight_bytes),
+        max_messages_per_second: Some(requested.max_messages_per_second)
+            .filter(|v| *v > 0)
+            .or(defaults.max_messages_per_second),
+    }
+}
+
+fn export_request_from_proto(req: ExportDataRequest) -> Result<ExportRequest, Status> {
+    let dataset = match req.dataset.as_str() {
+        "metrics" => ExportDataset::Metrics,
+        "logs" => ExportDataset::Logs,
+        "traces" => ExportDataset::Traces,
+        other => return Err(Status::invalid_argument(format!("Unknown dataset: {}", other))),
+    };
+    let format = match req.format.as_str() {
+        "csv" => ExportFormat::Csv,
+        "parquet" => ExportFormat::Parquet,
+        other => return Err(Status::invalid_argument(format!("Unknown export format: {}", other))),
+    };
+
+    let timestamp = |ts: Option<prost_types::Timestamp>, field: &str| {
+        ts.and_then(|ts| chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32))
+            .ok_or_else(|| Status::invalid_argument(format!("{} is required", field)))
+    };
+    let non_empty = |value: String| if value.is_empty() { None } else { Some(value) };
+
+    Ok(ExportRequest {
+        tenant_id: req.tenant_id,
+        dataset,
+        format,
+        start_time: timestamp(req.start_time, "start_time")?,
+        end_time: timestamp(req.end_time, "end_time")?,
+        destination: req.destination,
+        metric_name: non_empty(req.metric_name),
+        log_level: non_empty(req.log_level),
+        service_name: non_empty(req.service_name),
+    })
 }
 
 #[tokio::main]
@@ -170,31 +1836,193 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
 
     // 設定の読み込み
-    let config = Config::load()?;
+    let args = Args::parse();
+    let mut config = Config::load(&args.config, args.profile.active().as_deref())?;
+    if let Some(listen) = args.listen {
+        config.grpc.host = listen.ip().to_string();
+        config.grpc.port = listen.port();
+    }
+    if args.unix_socket.is_some() {
+        config.grpc.unix_socket = args.unix_socket;
+    }
     info!("StreamForge Stream Processor starting with config: {:?}", config);
 
     // ストレージバックエンドの初期化
-    let storage = StorageBackend::new(&config.storage).await?;
+    let storage = storage::connect(&config).await?;
     info!("Storage backend initialized");
 
+    // サブシステムのヘルス監視
+    let metrics = Arc::new(Metrics::new(&config.metrics)?);
+    let health = Arc::new(HealthState::new());
+    tokio::spawn(storage::monitor_health(
+        storage.clone(),
+        config.database.retry.clone(),
+        health.clone(),
+        metrics.clone(),
+    ));
+
     // ストリームプロセッサーの初期化
-    let processor = Arc::new(Mutex::new(StreamProcessor::new(config.clone(), storage).await?));
+    let processor = Arc::new(Mutex::new(StreamProcessor::new(config.clone(), storage.clone()).await?));
     info!("Stream processor initialized");
 
+    // インスタンス間のジョブ所有権の調整
+    let jobs = Arc::new(JobRegistry::new(&config.kafka.group_id));
+    let coordinator = Arc::new(Coordinator::new(
+        config.coordination.clone(),
+        storage.clone(),
+        Arc::clone(&processor),
+        Arc::clone(&jobs),
+    ));
+    let coordinator_handle = tokio::spawn(Arc::clone(&coordinator).run());
+
+    // スケジュール実行の開始
+    let scheduler = Arc::new(Scheduler::new(
+        storage.clone(),
+        Arc::clone(&processor),
+        Arc::clone(&coordinator),
+        Arc::clone(&jobs),
+        config.processing.limits.clone(),
+    ));
+    let scheduler_handle = tokio::spawn(Arc::clone(&scheduler).run());
+
+    // ロールバインディングの読み込み（grpc.rbac が無効な場合はすべて許可）
+    let access = Arc::new(Access::new(config.grpc.rbac.clone(), storage.clone()));
+    if access.enabled() {
+        access.refresh().await?;
+    }
+    let access_handle = tokio::spawn(Arc::clone(&access).run());
+
+    // 暗号化フィールドの秘匿（復号はストレージ層で行われる）
+    let cipher = if config.database.encryption.enabled {
+        Some(Arc::new(FieldCipher::new(&config.database.encryption)?))
+    } else {
+        None
+    };
+
     // gRPCサービスの作成
+    let grpc = config.grpc.clone();
     let service = StreamProcessorService {
-        processor,
+        processor: Arc::clone(&processor),
+        jobs,
+        coordinator: Arc::clone(&coordinator),
+        scheduler,
+        auditor: Arc::new(Auditor::new(storage.clone(), metrics)),
+        access,
+        cipher,
+        deletions: Arc::new(Deletions::new()),
+        deployments: Arc::new(Deployments::new()),
+        storage,
         config,
     };
 
-    let addr = "[::1]:50051".parse()?;
-    info!("Stream Processor server listening on {}", addr);
+    // gRPCヘルスチェックとリフレクション
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    let health_report_handle = tokio::spawn(health::report::<StreamProcessorServiceServer<StreamProcessorService>>(
+        health,
+        health_reporter.clone(),
+    ));
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(streamforge_v1::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build()?;
+
+    let mut service = StreamProcessorServiceServer::new(service)
+        .max_decoding_message_size(grpc.max_receive_message_size)
+        .max_encoding_message_size(grpc.max_send_message_size);
+    for compression in &grpc.compression {
+        let encoding = match compression {
+            GrpcCompression::Gzip => CompressionEncoding::Gzip,
+            GrpcCompression::Zstd => CompressionEncoding::Zstd,
+        };
+        service = service.accept_compressed(encoding).send_compressed(encoding);
+    }
+
+    let router = Server::builder()
+        .add_service(health_service)
+        .add_service(reflection_service)
+        .add_service(service);
 
     // サーバーの起動
-    Server::builder()
-        .add_service(StreamProcessorServiceServer::new(service))
-        .serve(addr)
-        .await?;
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let shutdown = async move {
+        let _ = shutdown_rx.await;
+    };
+    let mut server = match &grpc.unix_socket {
+        Some(path) => {
+            // 前回の起動で残ったソケットファイルを削除
+            let _ = std::fs::remove_file(path);
+            let listener = tokio::net::UnixListener::bind(path)?;
+            info!("Stream Processor server listening on unix:{}", path);
+            tokio::spawn(router.serve_with_incoming_shutdown(
+                tokio_stream::wrappers::UnixListenerStream::new(listener),
+                shutdown,
+            ))
+        }
+        None => {
+            let addr = grpc.address()?;
+            info!("Stream Processor server listening on {}", addr);
+            tokio::spawn(router.serve_with_shutdown(addr, shutdown))
+        }
+    };
+
+    // シャットダウンシグナルの待機
+    tokio::select! {
+        result = &mut server => {
+            result??;
+            return Ok(());
+        }
+        _ = shutdown_signal() => info!("Received shutdown signal"),
+    }
+
+    // ロードバランサーから外し、新規RPCの受付を停止
+    health_report_handle.abort();
+    health_reporter.set_service_status("", ServingStatus::NotServing).await;
+    health_reporter
+        .set_not_serving::<StreamProcessorServiceServer<StreamProcessorService>>()
+        .await;
+    let _ = shutdown_tx.send(());
+
+    // 実行中のRPCとストリームを期限付きで完了させる
+    match tokio::time::timeout(grpc.shutdown_timeout, &mut server).await {
+        Ok(result) => result??,
+        Err(_) => {
+            warn!(
+                "In-flight RPCs did not finish within {:?}; cancelling them",
+                grpc.shutdown_timeout
+            );
+            server.abort();
+        }
+    }
+
+    // 新たなスケジュール実行を止めてから実行中のパイプラインを停止
+    scheduler_handle.abort();
+    coordinator_handle.abort();
+    access_handle.abort();
+    if let Err(e) = processor.lock().await.stop_all().await {
+        error!("Failed to stop running pipelines: {}", e);
+    }
+    coordinator.hand_off().await;
+    if let Some(path) = &grpc.unix_socket {
+        let _ = std::fs::remove_file(path);
+    }
 
+    info!("Stream Processor server shutdown complete");
     Ok(())
+}
+
+/// Resolves on SIGTERM (sent by Kubernetes and docker stop) or Ctrl-C.
+async fn shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            error!("Unable to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,294 @@
+use crate::streamforge_v1::{
+    DiagnosticSeverity, Pipeline, PipelineDiagnostic, PipelineExecutionMode, PipelineSchedule, PipelineSink,
+    PipelineSource, PipelineTransform,
+};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeSet;
+use streamforge_core::pipeline::{
+    Diagnostic, ExecutionMode, PipelineDefinition, PipelineSpec, ScheduleSpec, Severity, SinkSpec, SourceSpec,
+    TransformSpec,
+};
+use streamforge_core::processor::DEFAULT_TENANT_ID;
+use tonic::Status;
+
+/// Page size used when a list request does not set one.
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Tenant a request addresses; requests without one use the default tenant.
+pub fn tenant(tenant_id: String) -> String {
+    if tenant_id.is_empty() {
+        DEFAULT_TENANT_ID.to_string()
+    } else {
+        tenant_id
+    }
+}
+
+pub fn page_size(page_size: i32) -> Result<i64, Status> {
+    match page_size as i64 {
+        0 => Ok(DEFAULT_PAGE_SIZE),
+        size if (1..=MAX_PAGE_SIZE).contains(&size) => Ok(size),
+        size => Err(Status::invalid_argument(format!(
+            "page_size must be between 1 and {}, got {}",
+            MAX_PAGE_SIZE, size
+        ))),
+    }
+}
+
+/// Converts a request pipeline into a definition. Timestamps are left to the
+/// caller, which knows whether it is creating or updating.
+pub fn from_proto(pipeline: Pipeline) -> Result<PipelineDefinition, Status> {
+    if pipeline.name.is_empty() {
+        return Err(Status::invalid_argument("pipeline.name is required"));
+    }
+
+    let config = |value: String, field: String| -> Result<serde_json::Value, Status> {
+        parse_config(&value).map_err(|e| Status::invalid_argument(format!("{} is not valid JSON: {}", field, e)))
+    };
+
+    let transforms = pipeline
+        .transforms
+        .into_iter()
+        .enumerate()
+        .map(|(i, t)| {
+            Ok(TransformSpec {
+                config: config(t.config, format!("pipeline.transforms[{}].config", i))?,
+                name: t.name,
+                kind: t.r#type,
+                inputs: t.inputs,
+                buffer: buffer(t.buffer),
+            })
+        })
+        .collect::<Result<Vec<_>, Status>>()?;
+    let sinks = pipeline
+        .sinks
+        .into_iter()
+        .enumerate()
+        .map(|(i, s)| {
+            Ok(SinkSpec {
+                config: config(s.config, format!("pipeline.sinks[{}].config", i))?,
+                name: s.name,
+                kind: s.r#type,
+                inputs: s.inputs,
+                buffer: buffer(s.buffer),
+            })
+        })
+        .collect::<Result<Vec<_>, Status>>()?;
+
+    let now = Utc::now();
+    Ok(PipelineDefinition {
+        id: pipeline.id,
+        tenant_id: tenant(pipeline.tenant_id),
+        name: pipeline.name,
+        description: pipeline.description,
+        spec: PipelineSpec {
+            sources: pipeline
+                .sources
+                .into_iter()
+                .map(|s| SourceSpec {
+                    name: s.name,
+                    topics: s.topics,
+                })
+                .collect(),
+            transforms,
+            sinks,
+            schedule: pipeline.schedule.map(schedule_from_proto),
+            execution: execution_from_proto(pipeline.execution)?,
+        },
+        version: pipeline.version,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+/// Validates a request pipeline. Unlike `from_proto`, problems converting it
+/// are reported as diagnostics alongside those of the spec itself.
+pub fn validate(pipeline: Pipeline, topics: Option<&BTreeSet<String>>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if pipeline.name.is_empty() {
+        diagnostics.push(error("pipeline.name", "name is required".to_string()));
+    }
+
+    let mut config = |value: &str, field: String| {
+        parse_config(value).unwrap_or_else(|e| {
+            diagnostics.push(error(&field, format!("not valid JSON: {}", e)));
+            serde_json::Value::Null
+        })
+    };
+    let spec = PipelineSpec {
+        sources: pipeline
+            .sources
+            .into_iter()
+            .map(|s| SourceSpec {
+                name: s.name,
+                topics: s.topics,
+            })
+            .collect(),
+        transforms: pipeline
+            .transforms
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| TransformSpec {
+                config: config(&t.config, format!("pipeline.transforms[{}].config", i)),
+                name: t.name,
+                kind: t.r#type,
+                inputs: t.inputs,
+                buffer: buffer(t.buffer),
+            })
+            .collect(),
+        sinks: pipeline
+            .sinks
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| SinkSpec {
+                config: config(&s.config, format!("pipeline.sinks[{}].config", i)),
+                name: s.name,
+                kind: s.r#type,
+                inputs: s.inputs,
+                buffer: buffer(s.buffer),
+            })
+            .collect(),
+        schedule: pipeline.schedule.map(schedule_from_proto),
+        execution: execution_from_proto(pipeline.execution).unwrap_or_else(|e| {
+            diagnostics.push(error("pipeline.execution", e.message().to_string()));
+            ExecutionMode::Row
+        }),
+    };
+
+    diagnostics.extend(spec.validate(topics).into_iter().map(|d| Diagnostic {
+        field: format!("pipeline.{}", d.field),
+        ..d
+    }));
+    diagnostics
+}
+
+pub fn diagnostic_to_proto(diagnostic: Diagnostic) -> PipelineDiagnostic {
+    let severity = match diagnostic.severity {
+        Severity::Error => DiagnosticSeverity::Error,
+        Severity::Warning => DiagnosticSeverity::Warning,
+    };
+    PipelineDiagnostic {
+        severity: severity as i32,
+        field: diagnostic.field,
+        message: diagnostic.message,
+    }
+}
+
+fn error(field: &str, message: String) -> Diagnostic {
+    Diagnostic {
+        severity: Severity::Error,
+        field: field.to_string(),
+        message,
+    }
+}
+
+/// Transform and sink configs are JSON strings on the wire; empty means none.
+fn parse_config(value: &str) -> serde_json::Result<serde_json::Value> {
+    if value.is_empty() {
+        return Ok(serde_json::Value::Null);
+    }
+    serde_json::from_str(value)
+}
+
+/// Edge buffer of a transform or sink; zero means the default.
+fn buffer(buffer: u32) -> Option<usize> {
+    Some(buffer as usize).filter(|b| *b > 0)
+}
+
+pub fn to_proto(pipeline: PipelineDefinition) -> Pipeline {
+    let fingerprint = pipeline.spec.fingerprint();
+    let config = |value: serde_json::Value| {
+        if value.is_null() {
+            String::new()
+        } else {
+            value.to_string()
+        }
+    };
+
+    Pipeline {
+        id: pipeline.id,
+        tenant_id: pipeline.tenant_id,
+        name: pipeline.name,
+        description: pipeline.description,
+        sources: pipeline
+            .spec
+            .sources
+            .into_iter()
+            .map(|s| PipelineSource {
+                name: s.name,
+                topics: s.topics,
+            })
+            .collect(),
+        transforms: pipeline
+            .spec
+            .transforms
+            .into_iter()
+            .map(|t| PipelineTransform {
+                name: t.name,
+                r#type: t.kind,
+                config: config(t.config),
+                inputs: t.inputs,
+                buffer: t.buffer.map_or(0, |b| b as u32),
+            })
+            .collect(),
+        sinks: pipeline
+            .spec
+            .sinks
+            .into_iter()
+            .map(|s| PipelineSink {
+                name: s.name,
+                r#type: s.kind,
+                config: config(s.config),
+                inputs: s.inputs,
+                buffer: s.buffer.map_or(0, |b| b as u32),
+            })
+            .collect(),
+        schedule: pipeline.spec.schedule.map(schedule_to_proto),
+        execution: execution_to_proto(pipeline.spec.execution) as i32,
+        version: pipeline.version,
+        created_at: Some(timestamp(pipeline.created_at)),
+        updated_at: Some(timestamp(pipeline.updated_at)),
+        fingerprint,
+    }
+}
+
+/// Zero and empty fields mean unset, as everywhere on the wire.
+fn schedule_from_proto(schedule: PipelineSchedule) -> ScheduleSpec {
+    ScheduleSpec {
+        cron: Some(schedule.cron).filter(|cron| !cron.is_empty()),
+        interval_seconds: Some(schedule.interval_seconds).filter(|s| *s > 0),
+        window_seconds: schedule.window_seconds,
+        max_runtime_seconds: Some(schedule.max_runtime_seconds).filter(|s| *s > 0),
+    }
+}
+
+pub fn schedule_to_proto(schedule: ScheduleSpec) -> PipelineSchedule {
+    PipelineSchedule {
+        cron: schedule.cron.unwrap_or_default(),
+        interval_seconds: schedule.interval_seconds.unwrap_or_default(),
+        window_seconds: schedule.window_seconds,
+        max_runtime_seconds: schedule.max_runtime_seconds.unwrap_or_default(),
+    }
+}
+
+fn execution_from_proto(execution: i32) -> Result<ExecutionMode, Status> {
+    match PipelineExecutionMode::try_from(execution) {
+        Ok(PipelineExecutionMode::Unspecified | PipelineExecutionMode::Row) => Ok(ExecutionMode::Row),
+        Ok(PipelineExecutionMode::Columnar) => Ok(ExecutionMode::Columnar),
+        Err(_) => Err(Status::invalid_argument(format!("Unknown execution mode: {}", execution))),
+    }
+}
+
+fn execution_to_proto(execution: ExecutionMode) -> PipelineExecutionMode {
+    match execution {
+        ExecutionMode::Row => PipelineExecutionMode::Row,
+        ExecutionMode::Columnar => PipelineExecutionMode::Columnar,
+    }
+}
+
+pub fn timestamp(time: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
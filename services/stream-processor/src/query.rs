@@ -0,0 +1,145 @@
+use crate::pipeline::timestamp;
+use crate::streamforge_v1::{
+    LogRecord as LogRecordProto, MetricPoint, Trace as TraceProto, TraceSpan as TraceSpanProto,
+};
+use chrono::{DateTime, Utc};
+use streamforge_core::storage::{LogRecord, MetricRecord, PageCursor, Trace, TraceSpan};
+use tonic::Status;
+
+/// Where a time-ordered listing resumes: at `after`, skipping the `skip`
+/// rows with exactly that timestamp that earlier pages already returned.
+/// Encoded as a storage page cursor with the skip count in its id slot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeCursor {
+    pub after: DateTime<Utc>,
+    pub skip: usize,
+}
+
+impl TimeCursor {
+    pub fn decode(token: &str) -> Result<Option<Self>, Status> {
+        if token.is_empty() {
+            return Ok(None);
+        }
+        let invalid = |e: String| Status::invalid_argument(format!("Invalid page_token: {}", e));
+        let cursor = PageCursor::decode(token).map_err(|e| invalid(e.to_string()))?;
+        let skip = cursor.id.parse().map_err(|e: std::num::ParseIntError| invalid(e.to_string()))?;
+        Ok(Some(Self {
+            after: cursor.processed_at,
+            skip,
+        }))
+    }
+
+    fn encode(&self) -> String {
+        PageCursor {
+            processed_at: self.after,
+            id: self.skip.to_string(),
+        }
+        .encode()
+    }
+
+    /// Rows to fetch from `after` so one page remains after skipping.
+    pub fn fetch_limit(cursor: Option<&Self>, page_size: i64) -> i64 {
+        cursor.map_or(0, |c| c.skip as i64) + page_size + 1
+    }
+}
+
+/// Cuts one page out of rows fetched from the cursor position with
+/// [`TimeCursor::fetch_limit`], returning it with the next page's token.
+pub fn page<T>(
+    mut rows: Vec<T>,
+    cursor: Option<TimeCursor>,
+    page_size: i64,
+    time: impl Fn(&T) -> DateTime<Utc>,
+) -> (Vec<T>, String) {
+    if let Some(cursor) = &cursor {
+        let skip = rows.iter().take(cursor.skip).take_while(|row| time(row) == cursor.after).count();
+        rows.drain(..skip);
+    }
+    let has_more = rows.len() as i64 > page_size;
+    rows.truncate(page_size.max(0) as usize);
+
+    let next = match rows.last() {
+        Some(last) if has_more => {
+            let after = time(last);
+            let mut skip = rows.iter().rev().take_while(|row| time(row) == after).count();
+            // A page entirely within one timestamp continues the previous skip
+            if skip == rows.len() {
+                skip += cursor.filter(|c| c.after == after).map_or(0, |c| c.skip);
+            }
+            TimeCursor { after, skip }.encode()
+        }
+        _ => String::new(),
+    };
+    (rows, next)
+}
+
+/// Validates a required `[start_time, end_time)` range.
+pub fn time_range(
+    start_time: Option<prost_types::Timestamp>,
+    end_time: Option<prost_types::Timestamp>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), Status> {
+    let time = |ts: Option<prost_types::Timestamp>, field: &str| {
+        ts.and_then(|ts| DateTime::from_timestamp(ts.seconds, ts.nanos as u32))
+            .ok_or_else(|| Status::invalid_argument(format!("{} is required", field)))
+    };
+    let (start, end) = (time(start_time, "start_time")?, time(end_time, "end_time")?);
+    if end <= start {
+        return Err(Status::invalid_argument("end_time must be after start_time"));
+    }
+    Ok((start, end))
+}
+
+fn json(value: Option<serde_json::Value>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+pub fn metric_to_proto(metric: MetricRecord) -> MetricPoint {
+    MetricPoint {
+        name: metric.name,
+        value: metric.value,
+        metric_type: metric.metric_type,
+        tags: json(metric.tags),
+        timestamp: Some(timestamp(metric.timestamp)),
+        resolution_seconds: metric.resolution_secs.unwrap_or_default(),
+    }
+}
+
+pub fn log_to_proto(log: LogRecord) -> LogRecordProto {
+    LogRecordProto {
+        level: log.level,
+        message: log.message,
+        service_name: log.service_name.unwrap_or_default(),
+        host_name: log.host_name.unwrap_or_default(),
+        trace_id: log.trace_id.unwrap_or_default(),
+        span_id: log.span_id.unwrap_or_default(),
+        attributes: json(log.attributes),
+        timestamp: Some(timestamp(log.timestamp)),
+    }
+}
+
+pub fn trace_to_proto(trace: Trace) -> TraceProto {
+    TraceProto {
+        trace_id: trace.trace_id,
+        span_count: trace.span_count as i64,
+        start_time: Some(timestamp(trace.start_time)),
+        end_time: trace.end_time.map(timestamp),
+        duration_ms: trace.duration_ms.unwrap_or_default(),
+        roots: trace.roots.into_iter().map(span_to_proto).collect(),
+    }
+}
+
+fn span_to_proto(span: TraceSpan) -> TraceSpanProto {
+    TraceSpanProto {
+        span_id: span.span_id,
+        parent_span_id: span.parent_span_id.unwrap_or_default(),
+        name: span.name,
+        service_name: span.service_name.unwrap_or_default(),
+        start_time: Some(timestamp(span.start_time)),
+        end_time: span.end_time.map(timestamp),
+        duration_ms: span.duration_ms.unwrap_or_default(),
+        status: span.status.unwrap_or_default(),
+        attributes: json(span.attributes),
+        events: json(span.events),
+        children: span.children.into_iter().map(span_to_proto).collect(),
+    }
+}
@@ -0,0 +1,213 @@
+use crate::coordination::Coordinator;
+use crate::jobs::JobRegistry;
+use crate::processor::StreamProcessor;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use streamforge_core::config::ResourceLimits;
+use streamforge_core::pipeline::PipelineDefinition;
+use streamforge_core::scheduler::Trigger;
+use streamforge_core::storage::StorageBackend;
+use tokio::sync::Mutex;
+use tonic::{Code, Status};
+use tracing::{error, info, warn};
+
+/// How often scheduled pipelines are re-read from storage.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often due schedules are checked.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct ScheduleEntry {
+    pub pipeline: PipelineDefinition,
+    pub next_run: Option<DateTime<Utc>>,
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_job_id: Option<String>,
+    trigger: Trigger,
+}
+
+/// Starts bounded jobs for pipelines that declare a schedule and stops them
+/// once their maximum runtime has passed.
+pub struct Scheduler {
+    storage: Arc<dyn StorageBackend>,
+    processor: Arc<Mutex<StreamProcessor>>,
+    coordinator: Arc<Coordinator>,
+    jobs: Arc<JobRegistry>,
+    limits: ResourceLimits,
+    entries: Mutex<BTreeMap<(String, String), ScheduleEntry>>,
+}
+
+impl Scheduler {
+    pub fn new(
+        storage: Arc<dyn StorageBackend>,
+        processor: Arc<Mutex<StreamProcessor>>,
+        coordinator: Arc<Coordinator>,
+        jobs: Arc<JobRegistry>,
+        limits: ResourceLimits,
+    ) -> Self {
+        Self {
+            storage,
+            processor,
+            coordinator,
+            jobs,
+            limits,
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+        let mut tick = tokio::time::interval(TICK_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = refresh.tick() => {
+                    if let Err(e) = self.refresh().await {
+                        warn!("Failed to refresh pipeline schedules: {}", e);
+                    }
+                }
+                _ = tick.tick() => self.start_due(Utc::now()).await,
+            }
+        }
+    }
+
+    /// Schedules of `tenant_id`, ordered by pipeline id.
+    pub async fn list(&self, tenant_id: &str) -> Vec<ScheduleEntry> {
+        self.entries
+            .lock()
+            .await
+            .values()
+            .filter(|entry| entry.pipeline.tenant_id == tenant_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Starts a run right away without moving the next scheduled one.
+    pub async fn trigger_now(&self, tenant_id: &str, pipeline_id: &str) -> Result<Option<String>> {
+        let pipeline = match self.entries.lock().await.get(&key(tenant_id, pipeline_id)) {
+            Some(entry) => entry.pipeline.clone(),
+            None => return Ok(None),
+        };
+        let job_id = self.start(&pipeline, Utc::now()).await?;
+        Ok(Some(job_id))
+    }
+
+    async fn refresh(&self) -> Result<()> {
+        let pipelines = self.storage.list_scheduled_pipelines().await?;
+        let now = Utc::now();
+
+        let mut entries = self.entries.lock().await;
+        let mut refreshed = BTreeMap::new();
+        for pipeline in pipelines {
+            let key = key(&pipeline.tenant_id, &pipeline.id);
+            match entries.remove(&key) {
+                Some(entry) if entry.pipeline.version == pipeline.version => {
+                    refreshed.insert(key, entry);
+                }
+                previous => {
+                    let Some(schedule) = &pipeline.spec.schedule else { continue };
+                    let trigger = match Trigger::parse(schedule) {
+                        Ok(trigger) => trigger,
+                        Err(e) => {
+                            warn!("Ignoring schedule of pipeline {}: {}", pipeline.id, e);
+                            continue;
+                        }
+                    };
+                    refreshed.insert(
+                        key,
+                        ScheduleEntry {
+                            next_run: trigger.next_after(now),
+                            last_run: previous.as_ref().and_then(|p| p.last_run),
+                            last_job_id: previous.and_then(|p| p.last_job_id),
+                            pipeline,
+                            trigger,
+                        },
+                    );
+                }
+            }
+        }
+        *entries = refreshed;
+        Ok(())
+    }
+
+    async fn start_due(&self, now: DateTime<Utc>) {
+        let due: Vec<(PipelineDefinition, DateTime<Utc>)> = {
+            let mut entries = self.entries.lock().await;
+            entries
+                .values_mut()
+                .filter_map(|entry| {
+                    let run_at = entry.next_run.filter(|next| *next <= now)?;
+                    entry.next_run = entry.trigger.next_after(now);
+                    Some((entry.pipeline.clone(), run_at))
+                })
+                .collect()
+        };
+
+        for (pipeline, run_at) in due {
+            match self.start(&pipeline, run_at).await {
+                Ok(_) => {}
+                // Every instance schedules the run; the one holding the lease runs it
+                Err(e) if e.downcast_ref::<Status>().map_or(false, |s| s.code() == Code::AlreadyExists) => {
+                    info!("Scheduled run of pipeline {} is running on another instance", pipeline.id);
+                }
+                Err(e) => error!("Failed to start scheduled run of pipeline {}: {}", pipeline.id, e),
+            }
+        }
+    }
+
+    /// Starts a job replaying the schedule's window before `run_at`.
+    async fn start(&self, pipeline: &PipelineDefinition, run_at: DateTime<Utc>) -> Result<String> {
+        let schedule = pipeline
+            .spec
+            .schedule
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Pipeline {} has no schedule", pipeline.id))?;
+        let config = serde_json::json!({
+            "tenant_id": pipeline.tenant_id,
+            "spec": pipeline.spec,
+            "replay": {
+                "start": run_at - chrono::Duration::seconds(schedule.window_seconds as i64),
+                "end": run_at,
+            },
+        });
+
+        let job_id = self
+            .coordinator
+            .start(&pipeline.id, &config.to_string(), &self.limits)
+            .await?;
+        info!("Started scheduled run {} of pipeline {}", job_id, pipeline.id);
+
+        if let Some(entry) = self.entries.lock().await.get_mut(&key(&pipeline.tenant_id, &pipeline.id)) {
+            entry.last_run = Some(run_at);
+            entry.last_job_id = Some(job_id.clone());
+        }
+
+        if let Some(seconds) = schedule.max_runtime_seconds {
+            let processor = Arc::clone(&self.processor);
+            let jobs = Arc::clone(&self.jobs);
+            let job_id = job_id.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(seconds)).await;
+                if jobs.get(&job_id).map_or(true, |job| job.state == crate::jobs::JobState::Stopped) {
+                    return;
+                }
+                match processor.lock().await.stop_pipeline(&job_id).await {
+                    Ok(_) => {
+                        info!("Stopped scheduled run {} after {}s", job_id, seconds);
+                        jobs.stopped(&job_id);
+                    }
+                    Err(e) => error!("Failed to stop scheduled run {}: {}", job_id, e),
+                }
+            });
+        }
+
+        Ok(job_id)
+    }
+}
+
+fn key(tenant_id: &str, pipeline_id: &str) -> (String, String) {
+    (tenant_id.to_string(), pipeline_id.to_string())
+}
@@ -0,0 +1,15 @@
+use crate::pipeline::timestamp;
+use crate::streamforge_v1::SchemaVersion as SchemaVersionProto;
+use streamforge_core::schemas::SchemaVersion;
+
+pub fn to_proto(version: SchemaVersion) -> SchemaVersionProto {
+    SchemaVersionProto {
+        tenant_id: version.tenant_id,
+        topic: version.topic,
+        version: version.version,
+        fields: serde_json::to_string(&version.fields).unwrap_or_default(),
+        changes: serde_json::to_string(&version.changes).unwrap_or_default(),
+        violations: version.violations,
+        created_at: Some(timestamp(version.created_at)),
+    }
+}
@@ -0,0 +1,128 @@
+use crate::errors;
+use crate::pipeline::{tenant, timestamp};
+use crate::streamforge_v1::{Silence as SilenceProto, SilenceMatcher as SilenceMatcherProto};
+use chrono::{DateTime, Utc};
+use streamforge_core::storage::{Silence, SilenceMatcher};
+use tonic::Status;
+
+/// Alert fields a matcher may compare, besides `metadata.<key>`.
+const MATCHER_FIELDS: [&str; 4] = ["id", "severity", "message", "service"];
+
+/// Converts a request silence into a stored one, starting now unless it says
+/// otherwise. Every problem is reported at once in a `BadRequest`.
+pub fn from_proto(silence: SilenceProto, now: DateTime<Utc>) -> Result<Silence, Status> {
+    let mut violations = Vec::new();
+
+    if silence.matchers.is_empty() {
+        violations.push(("silence.matchers", "at least one matcher is required".to_string()));
+    }
+    for (i, matcher) in silence.matchers.iter().enumerate() {
+        if !valid_field(&matcher.field) {
+            violations.push((
+                "silence.matchers",
+                format!("matchers[{}] compares unknown alert field {:?}", i, matcher.field),
+            ));
+        }
+    }
+
+    let time = |ts: Option<prost_types::Timestamp>| {
+        ts.map(|ts| DateTime::from_timestamp(ts.seconds, ts.nanos as u32))
+    };
+    let starts_at = match time(silence.starts_at) {
+        None => Some(now),
+        Some(None) => {
+            violations.push(("silence.starts_at", "is out of range".to_string()));
+            None
+        }
+        Some(starts_at) => starts_at,
+    };
+    let ends_at = match time(silence.ends_at) {
+        None => {
+            violations.push(("silence.ends_at", "is required".to_string()));
+            None
+        }
+        Some(None) => {
+            violations.push(("silence.ends_at", "is out of range".to_string()));
+            None
+        }
+        Some(Some(ends_at)) if ends_at <= now => {
+            violations.push(("silence.ends_at", "must be in the future".to_string()));
+            None
+        }
+        Some(ends_at) => ends_at,
+    };
+    if let (Some(starts_at), Some(ends_at)) = (starts_at, ends_at) {
+        if ends_at <= starts_at {
+            violations.push(("silence.ends_at", "must be after starts_at".to_string()));
+        }
+    }
+
+    match (starts_at, ends_at) {
+        (Some(starts_at), Some(ends_at)) if violations.is_empty() => Ok(Silence {
+            id: if silence.id.is_empty() {
+                uuid::Uuid::new_v4().to_string()
+            } else {
+                silence.id
+            },
+            tenant_id: tenant(silence.tenant_id),
+            matchers: silence
+                .matchers
+                .into_iter()
+                .map(|m| SilenceMatcher {
+                    field: m.field,
+                    value: m.value,
+                    negate: m.negate,
+                })
+                .collect(),
+            starts_at,
+            ends_at,
+            comment: silence.comment,
+            created_by: silence.created_by,
+            created_at: now,
+        }),
+        _ => Err(errors::bad_request(violations)),
+    }
+}
+
+pub fn to_proto(silence: Silence) -> SilenceProto {
+    SilenceProto {
+        id: silence.id,
+        tenant_id: silence.tenant_id,
+        matchers: silence
+            .matchers
+            .into_iter()
+            .map(|m| SilenceMatcherProto {
+                field: m.field,
+                value: m.value,
+                negate: m.negate,
+            })
+            .collect(),
+        starts_at: Some(timestamp(silence.starts_at)),
+        ends_at: Some(timestamp(silence.ends_at)),
+        comment: silence.comment,
+        created_by: silence.created_by,
+        created_at: Some(timestamp(silence.created_at)),
+    }
+}
+
+/// What a silence suppresses and until when, for the audit log.
+pub fn details(silence: &SilenceProto) -> serde_json::Value {
+    let matchers: Vec<serde_json::Value> = silence
+        .matchers
+        .iter()
+        .map(|m| serde_json::json!({ "field": m.field, "value": m.value, "negate": m.negate }))
+        .collect();
+    serde_json::json!({
+        "matchers": matchers,
+        "ends_at": silence
+            .ends_at
+            .as_ref()
+            .and_then(|ts| DateTime::from_timestamp(ts.seconds, ts.nanos as u32))
+            .map(|time| time.to_rfc3339()),
+        "comment": silence.comment,
+    })
+}
+
+fn valid_field(field: &str) -> bool {
+    MATCHER_FIELDS.contains(&field) || field.strip_prefix("metadata.").map_or(false, |key| !key.is_empty())
+}